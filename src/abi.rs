@@ -406,6 +406,26 @@ pub fn write_next_arg<T: GuestArg>(
     }
 }
 
+/// Read `count` words of arguments from registers and the stack, as if each
+/// were a separate [u32] argument. This is useful when the types of the
+/// arguments are only known at runtime, e.g. when forwarding a message.
+pub fn read_arg_words(regs: &[u32], mem: &Mem, count: usize) -> Vec<u32> {
+    let stack_ptr = Ptr::from_bits(regs[Cpu::SP]);
+    let mut reg_offset = 0;
+    (0..count)
+        .map(|_| read_next_arg::<u32>(&mut reg_offset, regs, stack_ptr, mem))
+        .collect()
+}
+
+/// Counterpart of [read_arg_words]. The stack pointer **must** be
+/// appropriately decremented in advance! See [extend_stack_for_args].
+pub fn write_arg_words(regs: &mut [u32], mem: &mut Mem, words: &[u32]) {
+    let mut reg_offset = 0;
+    for &word in words {
+        write_next_arg::<u32>(&mut reg_offset, regs, mem, word);
+    }
+}
+
 /// Represents variable arguments in a [CallFromGuest] function signature,
 /// like C `...`, e.g. in the signature of `printf()`. See also [VaList].
 #[derive(Debug)]
@@ -675,6 +695,15 @@ impl<T, const MUT: bool> GuestRet for Ptr<T, MUT> {
     }
 }
 
+impl GuestRet for GuestFunction {
+    fn from_regs(regs: &[u32]) -> Self {
+        GuestFunction(<ConstVoidPtr as GuestRet>::from_regs(regs))
+    }
+    fn to_regs(self, regs: &mut [u32]) {
+        <ConstVoidPtr as GuestRet>::to_regs(self.0, regs)
+    }
+}
+
 // GuestRet implementations for u64-like types
 
 impl GuestRet for u64 {
@@ -79,7 +79,16 @@ impl Bundle {
     }
 
     pub fn bundle_localizations(&self) -> &[Value] {
-        self.plist["CFBundleLocalizations"].as_array().unwrap()
+        self.plist
+            .get("CFBundleLocalizations")
+            .and_then(Value::as_array)
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn development_region(&self) -> Option<&str> {
+        self.plist
+            .get("CFBundleDevelopmentRegion")
+            .and_then(Value::as_string)
     }
 
     /// Canonical name for the bundle according to Info.plist
@@ -105,6 +114,19 @@ impl Bundle {
             .map(|v| v.as_string().unwrap())
     }
 
+    pub fn status_bar_hidden(&self) -> bool {
+        self.plist
+            .get("UIStatusBarHidden")
+            .and_then(|hidden| hidden.as_boolean())
+            .unwrap_or(false)
+    }
+
+    pub fn status_bar_style(&self) -> Option<&str> {
+        self.plist
+            .get("UIStatusBarStyle")
+            .and_then(Value::as_string)
+    }
+
     pub fn executable_path(&self) -> GuestPathBuf {
         // FIXME: Is this key optional? All iPhone apps seem to have it.
         self.path
@@ -11,7 +11,11 @@
 //! For the moment, only ARMv6 has been tested.
 
 use crate::abi::GuestFunction;
-use crate::mem::{ConstPtr, GuestUSize, Mem, MutPtr, Ptr, SafeRead, SafeWrite};
+use crate::mem::{
+    guest_size_of, ConstPtr, GuestUSize, Mem, MutPtr, Protection, Ptr, SafeRead, SafeWrite,
+    PAGE_SIZE,
+};
+use std::ops::Range;
 
 // Import functions from C++
 use touchHLE_dynarmic_wrapper::*;
@@ -22,9 +26,11 @@ fn touchHLE_cpu_read_impl<T: SafeRead + Default>(
     mem: *mut touchHLE_Mem,
     addr: VAddr,
     error: *mut bool,
+    needed: Protection,
 ) -> T {
-    // If a panic occurs (probably due to a null-pointer access), we can't let
-    // it keep unwinding as it will hit non-Rust stack frames (dynarmic).
+    // If a panic occurs (probably due to a null-pointer access or a page
+    // protection violation), we can't let it keep unwinding as it will hit
+    // non-Rust stack frames (dynarmic).
     // Instead we catch the unwind and then tell the C++ code a problem occurred
     // so it can immediately halt CPU execution and then panic itself, now
     // with only Rust stack frames to worry about and with CPU state information
@@ -38,6 +44,7 @@ fn touchHLE_cpu_read_impl<T: SafeRead + Default>(
     // the emulator will crash anyway, maybe this is okay.
     let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         let mem = unsafe { &mut *mem.cast::<Mem>() };
+        mem.check_guest_access(addr, guest_size_of::<T>(), needed);
         let ptr: ConstPtr<T> = Ptr::from_bits(addr);
         mem.read(ptr)
     }));
@@ -51,6 +58,7 @@ fn touchHLE_cpu_write_impl<T: SafeWrite>(mem: *mut touchHLE_Mem, addr: VAddr, va
     // See comments above about catch_unwind
     let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         let mem = unsafe { &mut *mem.cast::<Mem>() };
+        mem.check_guest_access(addr, guest_size_of::<T>(), Protection::WRITE);
         let ptr: MutPtr<T> = Ptr::from_bits(addr);
         mem.write(ptr, value)
     }));
@@ -60,19 +68,32 @@ fn touchHLE_cpu_write_impl<T: SafeWrite>(mem: *mut touchHLE_Mem, addr: VAddr, va
 // Export functions for use by C++
 #[no_mangle]
 extern "C" fn touchHLE_cpu_read_u8(mem: *mut touchHLE_Mem, addr: VAddr, error: *mut bool) -> u8 {
-    touchHLE_cpu_read_impl(mem, addr, error)
+    touchHLE_cpu_read_impl(mem, addr, error, Protection::READ)
 }
 #[no_mangle]
 extern "C" fn touchHLE_cpu_read_u16(mem: *mut touchHLE_Mem, addr: VAddr, error: *mut bool) -> u16 {
-    touchHLE_cpu_read_impl(mem, addr, error)
+    touchHLE_cpu_read_impl(mem, addr, error, Protection::READ)
 }
 #[no_mangle]
 extern "C" fn touchHLE_cpu_read_u32(mem: *mut touchHLE_Mem, addr: VAddr, error: *mut bool) -> u32 {
-    touchHLE_cpu_read_impl(mem, addr, error)
+    touchHLE_cpu_read_impl(mem, addr, error, Protection::READ)
 }
 #[no_mangle]
 extern "C" fn touchHLE_cpu_read_u64(mem: *mut touchHLE_Mem, addr: VAddr, error: *mut bool) -> u64 {
-    touchHLE_cpu_read_impl(mem, addr, error)
+    touchHLE_cpu_read_impl(mem, addr, error, Protection::READ)
+}
+#[no_mangle]
+extern "C" fn touchHLE_cpu_read_code_u32(
+    mem: *mut touchHLE_Mem,
+    addr: VAddr,
+    error: *mut bool,
+) -> u32 {
+    touchHLE_cpu_read_impl(mem, addr, error, Protection::EXECUTE)
+}
+#[no_mangle]
+extern "C" fn touchHLE_cpu_is_read_only_memory(mem: *mut touchHLE_Mem, addr: VAddr) -> bool {
+    let mem = unsafe { &*mem.cast::<Mem>() };
+    mem.is_read_only_for_guest(addr)
 }
 #[no_mangle]
 extern "C" fn touchHLE_cpu_write_u8(mem: *mut touchHLE_Mem, addr: VAddr, value: u8) -> bool {
@@ -96,6 +117,9 @@ pub struct Cpu {
     /// Copy of the direct memory access pointer used to check it has not
     /// changed. If this is null, direct memory access is not in use.
     direct_memory_access_ptr: *const std::ffi::c_void,
+    /// Whether read-only pages can also be accessed directly, see
+    /// [Self::update_page_table].
+    direct_read_only_access: bool,
 }
 
 impl Drop for Cpu {
@@ -136,7 +160,8 @@ pub enum CpuState {
 /// A reason that can cause CPU execution to be interrupted.
 #[derive(Debug)]
 pub enum CpuError {
-    /// Memory error during execution (probably a null page access).
+    /// Memory error during execution (probably a null page access, or some
+    /// other access not allowed by page protection).
     MemoryError,
     /// Undefined instruction (perhaps from a GDB software breakpoint).
     UndefinedInstruction,
@@ -163,27 +188,83 @@ impl Cpu {
     /// is provided, direct memory access is enabled, and the CPU instance
     /// becomes bound to that [Mem] instance (subsequent calls must use the same
     /// one).
-    pub fn new(direct_memory_access: Option<&mut Mem>) -> Cpu {
-        // Null page count is in pages rather than bytes. Mem ensures it is
-        // page aligned.
-        let null_page_count: usize = direct_memory_access
-            .as_ref()
-            .map_or(0, |mem| mem.null_segment_size() / 0x1000)
-            .try_into()
-            .unwrap();
+    ///
+    /// `direct_read_only_access` trades catching writes to read-only pages for
+    /// faster reads from them, see [Self::update_page_table].
+    pub fn new(mut direct_memory_access: Option<&mut Mem>, direct_read_only_access: bool) -> Cpu {
         // Safety: the direct memory access pointer will be retained directly by
         // the dynarmic wrapper and indirectly by cached JIT code, so we must
         // ensure we only execute the CPU while holding a &mut on the Mem object
         // to which that pointer belongs.
         let direct_memory_access_ptr = direct_memory_access
+            .as_mut()
             .map_or(std::ptr::null_mut(), |mem| unsafe {
                 mem.direct_memory_access_ptr()
             });
-        let dynarmic_wrapper =
-            unsafe { touchHLE_DynarmicWrapper_new(direct_memory_access_ptr, null_page_count) };
-        Cpu {
+        let dynarmic_wrapper = unsafe { touchHLE_DynarmicWrapper_new(direct_memory_access_ptr) };
+        let mut cpu = Cpu {
             dynarmic_wrapper,
             direct_memory_access_ptr,
+            direct_read_only_access,
+        };
+        if let Some(mem) = direct_memory_access {
+            // Nothing has been executed yet, so there's nothing to invalidate,
+            // but the page table needs to match the memory's protection.
+            let _ = mem.take_protection_changes();
+            let page_count = ((1u64 << 32) / u64::from(PAGE_SIZE)) as u32;
+            cpu.update_page_table(mem, 0..page_count);
+        }
+        cpu
+    }
+
+    /// Make dynarmic's page table agree with the protection of some range of
+    /// pages. Only pages that are both readable and writable can be accessed
+    /// directly, all others use the memory callbacks, which check protection.
+    ///
+    /// dynarmic's page table is used for both reads and writes, so this is the
+    /// only way to catch writes to read-only pages (e.g. `__TEXT`). The cost is
+    /// that reads from them are slower, but it's smaller than it sounds:
+    /// instructions are fetched when they're compiled, loads from literal pools
+    /// are turned into constants (see `IsReadOnlyMemory` in `lib.cpp`), and
+    /// string and memory functions are host code that doesn't use dynarmic.
+    /// What's left is mostly guest code reading tables in `__const`. If that
+    /// matters more than catching bugs, `direct_read_only_access` lets
+    /// read-only pages be accessed directly too, so writes to them aren't
+    /// caught.
+    ///
+    /// TODO: Use dynarmic's fastmem mode and host memory protection, so reads
+    /// are fast and writes are still caught.
+    fn update_page_table(&mut self, mem: &Mem, pages: Range<u32>) {
+        if self.direct_memory_access_ptr.is_null() {
+            return;
+        }
+        for (run, protection) in mem.protection_runs(pages) {
+            let direct = if self.direct_read_only_access {
+                protection.contains(Protection::READ)
+            } else {
+                protection.contains(Protection::READ | Protection::WRITE)
+            };
+            unsafe {
+                touchHLE_DynarmicWrapper_set_page_direct_access(
+                    self.dynarmic_wrapper,
+                    run.start,
+                    run.end - run.start,
+                    direct,
+                )
+            }
+        }
+    }
+
+    /// Respond to changes in memory protection since the last time this was
+    /// called.
+    fn apply_protection_changes(&mut self, mem: &mut Mem) {
+        for pages in mem.take_protection_changes() {
+            self.update_page_table(mem, pages.clone());
+            // Code from pages that are no longer executable, or constants
+            // loaded from pages that are no longer read-only, might be cached.
+            let base = pages.start * PAGE_SIZE;
+            let size = u64::from(pages.end - pages.start) * u64::from(PAGE_SIZE);
+            self.invalidate_cache_range(base, size.min(u32::MAX.into()) as u32);
         }
     }
 
@@ -200,6 +281,22 @@ impl Cpu {
         }
     }
 
+    /// Get the VFP registers as 32 single-precision registers (S0-S31), which
+    /// overlap the 16 double-precision registers (D0-D15). Only the first 32
+    /// words are meaningful for the VFPv2 found in iPhone OS devices.
+    pub fn extregs(&self) -> &[u32; 64] {
+        unsafe {
+            let ptr = touchHLE_DynarmicWrapper_extregs_const(self.dynarmic_wrapper);
+            &*(ptr as *const [u32; 64])
+        }
+    }
+    pub fn extregs_mut(&mut self) -> &mut [u32; 64] {
+        unsafe {
+            let ptr = touchHLE_DynarmicWrapper_extregs_mut(self.dynarmic_wrapper);
+            &mut *(ptr as *mut [u32; 64])
+        }
+    }
+
     pub fn dump_regs(&self) {
         let regs = self.regs();
         for row in 0..4 {
@@ -289,6 +386,8 @@ impl Cpu {
             assert!(self.direct_memory_access_ptr == unsafe { mem.direct_memory_access_ptr() });
         }
 
+        self.apply_protection_changes(mem);
+
         let res = unsafe {
             touchHLE_DynarmicWrapper_run_or_step(
                 self.dynarmic_wrapper,
@@ -306,3 +405,108 @@ impl Cpu {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `strb r1, [r0]` followed by `svc #0`.
+    const STORE_BYTE: [u32; 2] = [0xe5c01000, 0xef000000];
+    /// `ldrb r1, [r0]` followed by `svc #0`.
+    const LOAD_BYTE: [u32; 2] = [0xe5d01000, 0xef000000];
+    const CODE_ADDR: VAddr = 0x10000;
+    const DATA_ADDR: VAddr = 0x20000;
+
+    /// Run `code` with `r0` pointing to a page with the given protection and
+    /// `r1` set to 1. Returns the CPU state, the value of `r1` and the byte at
+    /// the start of the page.
+    fn run_with_data_page(
+        code: [u32; 2],
+        direct_memory_access: bool,
+        direct_read_only_access: bool,
+        protection: Protection,
+    ) -> (CpuState, u32, u8) {
+        let mut mem = Mem::new();
+        for (i, &instruction) in code.iter().enumerate() {
+            mem.write(Ptr::from_bits(CODE_ADDR + i as VAddr * 4), instruction);
+        }
+        mem.write(Ptr::from_bits(DATA_ADDR), 2u8);
+        mem.set_protection(DATA_ADDR, PAGE_SIZE, protection);
+
+        let mut cpu = Cpu::new(
+            if direct_memory_access {
+                Some(&mut mem)
+            } else {
+                None
+            },
+            direct_read_only_access,
+        );
+        cpu.set_cpsr(Cpu::CPSR_USER_MODE);
+        cpu.regs_mut()[0] = DATA_ADDR;
+        cpu.regs_mut()[1] = 1;
+        cpu.regs_mut()[Cpu::PC] = CODE_ADDR;
+        let mut ticks = 100;
+        let state = cpu.run_or_step(&mut mem, Some(&mut ticks));
+        (state, cpu.regs()[1], mem.read(Ptr::from_bits(DATA_ADDR)))
+    }
+
+    #[test]
+    fn write_to_writable_page() {
+        for direct_memory_access in [false, true] {
+            let (state, _, byte) = run_with_data_page(
+                STORE_BYTE,
+                direct_memory_access,
+                false,
+                Protection::READ | Protection::WRITE,
+            );
+            assert!(matches!(state, CpuState::Svc(0)));
+            assert_eq!(byte, 1);
+        }
+    }
+
+    #[test]
+    fn write_to_read_only_page_is_caught() {
+        for direct_memory_access in [false, true] {
+            let (state, _, byte) =
+                run_with_data_page(STORE_BYTE, direct_memory_access, false, Protection::READ);
+            assert!(matches!(state, CpuState::Error(CpuError::MemoryError)));
+            assert_eq!(byte, 2);
+        }
+    }
+
+    #[test]
+    fn write_to_inaccessible_page_is_caught() {
+        for direct_memory_access in [false, true] {
+            let (state, _, byte) =
+                run_with_data_page(STORE_BYTE, direct_memory_access, false, Protection::NONE);
+            assert!(matches!(state, CpuState::Error(CpuError::MemoryError)));
+            assert_eq!(byte, 2);
+        }
+    }
+
+    #[test]
+    fn read_from_read_only_page() {
+        for direct_memory_access in [false, true] {
+            let (state, r1, _) =
+                run_with_data_page(LOAD_BYTE, direct_memory_access, false, Protection::READ);
+            assert!(matches!(state, CpuState::Svc(0)));
+            assert_eq!(r1, 2);
+        }
+    }
+
+    #[test]
+    fn direct_read_only_access() {
+        let (state, r1, _) = run_with_data_page(LOAD_BYTE, true, true, Protection::READ);
+        assert!(matches!(state, CpuState::Svc(0)));
+        assert_eq!(r1, 2);
+
+        // This is the price of that option.
+        let (state, _, byte) = run_with_data_page(STORE_BYTE, true, true, Protection::READ);
+        assert!(matches!(state, CpuState::Svc(0)));
+        assert_eq!(byte, 1);
+
+        let (state, _, byte) = run_with_data_page(STORE_BYTE, true, true, Protection::NONE);
+        assert!(matches!(state, CpuState::Error(CpuError::MemoryError)));
+        assert_eq!(byte, 2);
+    }
+}
@@ -3,6 +3,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+#include <algorithm>
 #include <cstdint>
 #include <cstdio>
 
@@ -21,6 +22,9 @@ std::uint8_t touchHLE_cpu_read_u8(touchHLE_Mem *mem, VAddr addr, bool *error);
 std::uint16_t touchHLE_cpu_read_u16(touchHLE_Mem *mem, VAddr addr, bool *error);
 std::uint32_t touchHLE_cpu_read_u32(touchHLE_Mem *mem, VAddr addr, bool *error);
 std::uint64_t touchHLE_cpu_read_u64(touchHLE_Mem *mem, VAddr addr, bool *error);
+std::uint32_t touchHLE_cpu_read_code_u32(touchHLE_Mem *mem, VAddr addr,
+                                         bool *error);
+bool touchHLE_cpu_is_read_only_memory(touchHLE_Mem *mem, VAddr addr);
 bool touchHLE_cpu_write_u8(touchHLE_Mem *mem, VAddr addr, std::uint8_t value);
 bool touchHLE_cpu_write_u16(touchHLE_Mem *mem, VAddr addr, std::uint16_t value);
 bool touchHLE_cpu_write_u32(touchHLE_Mem *mem, VAddr addr, std::uint32_t value);
@@ -74,7 +78,7 @@ private:
 
   std::optional<std::uint32_t> MemoryReadCode(VAddr vaddr) override {
     bool error;
-    auto value = touchHLE_cpu_read_u32(mem, vaddr, &error);
+    auto value = touchHLE_cpu_read_code_u32(mem, vaddr, &error);
     if (error) {
       return std::nullopt;
     } else {
@@ -82,6 +86,14 @@ private:
     }
   }
 
+  // Lets dynarmic treat loads from these addresses as constants, which is
+  // important for literal pools, since pages that aren't writable have to
+  // use the slow memory callbacks. If the page is later made writable, Rust
+  // code invalidates the cache.
+  bool IsReadOnlyMemory(VAddr vaddr) override {
+    return mem && touchHLE_cpu_is_read_only_memory(mem, vaddr);
+  }
+
   void MemoryWrite8(VAddr vaddr, std::uint8_t value) override {
     if (touchHLE_cpu_write_u8(mem, vaddr, value)) {
       cpu->HaltExecution(Dynarmic::HaltReason::MemoryAbort);
@@ -139,31 +151,27 @@ class DynarmicWrapper {
   std::unique_ptr<Dynarmic::A32::Jit> cpu;
   std::array<std::uint8_t *, Dynarmic::A32::UserConfig::NUM_PAGE_TABLE_ENTRIES>
       page_table;
+  std::uint8_t *direct_memory_access_ptr;
 
 public:
-  DynarmicWrapper(void *direct_memory_access_ptr, size_t null_page_count) {
+  DynarmicWrapper(void *direct_memory_access_ptr)
+      : direct_memory_access_ptr((std::uint8_t *)direct_memory_access_ptr) {
     Dynarmic::A32::UserConfig user_config;
     user_config.callbacks = &env;
     // TODO: only do this in debug builds? it's probably expensive
     user_config.check_halt_on_memory_access = true;
     if (direct_memory_access_ptr) {
-      // Allow fast accesses to all pages other than the null page, which will
-      // fall back to a memory callback, which will then abort execution.
+      // Allow fast accesses to all pages to begin with. Rust code then uses
+      // set_page_direct_access() so that pages which aren't both readable and
+      // writable (or, optionally, just readable) fall back to a memory
+      // callback, which checks the access and aborts execution if it isn't
+      // allowed.
       // TODO: Eventually we should use dynarmic's true fastmem mode, but that
       // requires using mmap/mprotect/etc on the host OS so we can still catch
-      // null pointer accesses.
+      // disallowed accesses.
       page_table.fill((std::uint8_t *)direct_memory_access_ptr);
-      // Note that the null page size is also defined in src/mem.rs.
+      // Note that the page size is also defined in src/mem.rs.
       static_assert(1 << Dynarmic::A32::UserConfig::PAGE_BITS == 0x1000);
-
-      if (null_page_count > page_table.size()) {
-        printf("Too many null pages, %zu requested but maximum is %zu.",
-               null_page_count, page_table.size());
-        abort();
-      }
-      for (size_t i = 0; i < null_page_count; i++) {
-        page_table[i] = nullptr;
-      }
       user_config.page_table = &page_table;
       user_config.absolute_offset_page_table = true;
     }
@@ -174,9 +182,26 @@ public:
   const std::uint32_t *regs() const { return &cpu->Regs().front(); }
   std::uint32_t *regs() { return &cpu->Regs().front(); }
 
+  const std::uint32_t *extregs() const { return &cpu->ExtRegs().front(); }
+  std::uint32_t *extregs() { return &cpu->ExtRegs().front(); }
+
   std::uint32_t cpsr() const { return cpu->Cpsr(); }
   void set_cpsr(std::uint32_t cpsr) { cpu->SetCpsr(cpsr); }
 
+  void set_page_direct_access(std::uint32_t first_page,
+                              std::uint32_t page_count, bool direct) {
+    if (!direct_memory_access_ptr) {
+      return;
+    }
+    if (first_page > page_table.size() ||
+        page_count > page_table.size() - first_page) {
+      printf("Page range %u+%u is out of bounds.", first_page, page_count);
+      abort();
+    }
+    std::fill_n(page_table.begin() + first_page, page_count,
+                direct ? direct_memory_access_ptr : nullptr);
+  }
+
   void invalidate_cache_range(VAddr start, std::uint32_t size) {
     cpu->InvalidateCacheRange(start, size);
   }
@@ -221,9 +246,8 @@ public:
 
 extern "C" {
 
-DynarmicWrapper *touchHLE_DynarmicWrapper_new(void *direct_memory_access_ptr,
-                                              size_t null_page_count) {
-  return new DynarmicWrapper(direct_memory_access_ptr, null_page_count);
+DynarmicWrapper *touchHLE_DynarmicWrapper_new(void *direct_memory_access_ptr) {
+  return new DynarmicWrapper(direct_memory_access_ptr);
 }
 void touchHLE_DynarmicWrapper_delete(DynarmicWrapper *cpu) { delete cpu; }
 
@@ -235,6 +259,14 @@ std::uint32_t *touchHLE_DynarmicWrapper_regs_mut(DynarmicWrapper *cpu) {
   return cpu->regs();
 }
 
+const std::uint32_t *
+touchHLE_DynarmicWrapper_extregs_const(const DynarmicWrapper *cpu) {
+  return cpu->extregs();
+}
+std::uint32_t *touchHLE_DynarmicWrapper_extregs_mut(DynarmicWrapper *cpu) {
+  return cpu->extregs();
+}
+
 std::uint32_t touchHLE_DynarmicWrapper_cpsr(const DynarmicWrapper *cpu) {
   return cpu->cpsr();
 }
@@ -248,6 +280,13 @@ void touchHLE_DynarmicWrapper_swap_context(DynarmicWrapper *cpu,
   cpu->swap_context(context);
 }
 
+void touchHLE_DynarmicWrapper_set_page_direct_access(DynarmicWrapper *cpu,
+                                                     std::uint32_t first_page,
+                                                     std::uint32_t page_count,
+                                                     bool direct) {
+  cpu->set_page_direct_access(first_page, page_count, direct);
+}
+
 void touchHLE_DynarmicWrapper_invalidate_cache_range(DynarmicWrapper *cpu,
                                                      VAddr start,
                                                      std::uint32_t size) {
@@ -28,17 +28,26 @@ type VAddr = u32;
 extern "C" {
     pub fn touchHLE_DynarmicWrapper_new(
         dynamic_memory_access_ptr: *mut std::ffi::c_void,
-        null_page_count: usize,
     ) -> *mut touchHLE_DynarmicWrapper;
     pub fn touchHLE_DynarmicWrapper_delete(cpu: *mut touchHLE_DynarmicWrapper);
     pub fn touchHLE_DynarmicWrapper_regs_const(cpu: *const touchHLE_DynarmicWrapper) -> *const u32;
     pub fn touchHLE_DynarmicWrapper_regs_mut(cpu: *mut touchHLE_DynarmicWrapper) -> *mut u32;
+    pub fn touchHLE_DynarmicWrapper_extregs_const(
+        cpu: *const touchHLE_DynarmicWrapper,
+    ) -> *const u32;
+    pub fn touchHLE_DynarmicWrapper_extregs_mut(cpu: *mut touchHLE_DynarmicWrapper) -> *mut u32;
     pub fn touchHLE_DynarmicWrapper_cpsr(cpu: *const touchHLE_DynarmicWrapper) -> u32;
     pub fn touchHLE_DynarmicWrapper_set_cpsr(cpu: *mut touchHLE_DynarmicWrapper, cpsr: u32);
     pub fn touchHLE_DynarmicWrapper_swap_context(
         cpu: *mut touchHLE_DynarmicWrapper,
         context: *mut Dynarmic_A32_Context,
     );
+    pub fn touchHLE_DynarmicWrapper_set_page_direct_access(
+        cpu: *mut touchHLE_DynarmicWrapper,
+        first_page: u32,
+        page_count: u32,
+        direct: bool,
+    );
     pub fn touchHLE_DynarmicWrapper_invalidate_cache_range(
         cpu: *mut touchHLE_DynarmicWrapper,
         start: VAddr,
@@ -15,8 +15,9 @@
 //! touchHLE's own implementations of the frameworks, which are "host code"
 //! (i.e. not themselves running under emulation).
 //!
-//! This also does normal dynamic linking for libgcc and libstdc++. It might
-//! eventually support linking other things too.
+//! This also does normal dynamic linking for libraries that are loaded from the
+//! guest filesystem: libgcc and libstdc++ (which are bundled with touchHLE),
+//! and any libraries or plugins shipped with the app.
 //!
 //! See [crate::mach_o] for resources.
 
@@ -26,8 +27,9 @@ mod function_lists;
 use crate::abi::{CallFromGuest, GuestFunction};
 use crate::cpu::Cpu;
 use crate::frameworks::foundation::ns_string;
+use crate::fs::{Fs, GuestPath, GuestPathBuf};
 use crate::mach_o::{MachO, SectionType};
-use crate::mem::{ConstVoidPtr, GuestUSize, Mem, MutPtr, Ptr};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, Ptr};
 use crate::objc::{nil, ObjC};
 use crate::Environment;
 use std::collections::HashMap;
@@ -167,6 +169,18 @@ fn write_return_to_host_routine(mem: &mut Mem, svc: u32) -> GuestFunction {
     assert!(!ptr.is_thumb());
     ptr
 }
+
+/// Information about a binary loaded by [Dyld::load_bin_and_dependencies].
+/// The index of one of these matches the index of the binary in
+/// [crate::Environment::bins].
+struct LoadedBin {
+    /// Path the binary was loaded from.
+    path: GuestPathBuf,
+    /// Indices of the binaries it depends on. Libraries with host
+    /// implementations aren't included.
+    dependencies: Vec<usize>,
+}
+
 pub struct Dyld {
     /// List of host functions that have been "linked" and had SVCs assigned.
     ///
@@ -177,6 +191,10 @@ pub struct Dyld {
     thread_exit_routine: Option<GuestFunction>,
     constants_to_link_later: Vec<(MutPtr<ConstVoidPtr>, &'static HostConstant)>,
     non_lazy_host_functions: HashMap<&'static str, GuestFunction>,
+    loaded_bins: Vec<LoadedBin>,
+    /// Trampolines for calling the original implementations of guest functions
+    /// that have been hooked (see [function_lists::HOOK_LISTS]).
+    hooked_originals: HashMap<&'static str, GuestFunction>,
 }
 
 impl Dyld {
@@ -200,6 +218,8 @@ impl Dyld {
             thread_exit_routine: None,
             constants_to_link_later: Vec::new(),
             non_lazy_host_functions: HashMap::new(),
+            loaded_bins: Vec::new(),
+            hooked_originals: HashMap::new(),
         }
     }
 
@@ -211,31 +231,331 @@ impl Dyld {
         self.thread_exit_routine.unwrap()
     }
 
+    /// Load a binary from the guest filesystem into `bins`, followed by any
+    /// libraries it depends on (recursively) that aren't loaded yet and don't
+    /// have host implementations. Returns the index of the binary in `bins`.
+    ///
+    /// The indices of the newly-loaded binaries are appended to `new_bins`, in
+    /// the order their static initializers should be run, i.e. dependencies
+    /// come before the binaries that depend on them.
+    ///
+    /// If loading fails, `bins` is left as it was, though the memory used by
+    /// any binaries that were loaded is not reclaimed.
+    pub fn load_bin_and_dependencies(
+        &mut self,
+        path: &GuestPath,
+        fs: &Fs,
+        mem: &mut Mem,
+        bins: &mut Vec<MachO>,
+        new_bins: &mut Vec<usize>,
+    ) -> Result<usize, String> {
+        assert!(self.loaded_bins.len() == bins.len());
+        let old_len = bins.len();
+        let old_new_bins_len = new_bins.len();
+        let res = self.load_bin_and_dependencies_inner(path, fs, mem, bins, new_bins);
+        if res.is_err() {
+            bins.truncate(old_len);
+            self.loaded_bins.truncate(old_len);
+            new_bins.truncate(old_new_bins_len);
+        }
+        res
+    }
+
+    fn load_bin_and_dependencies_inner(
+        &mut self,
+        path: &GuestPath,
+        fs: &Fs,
+        mem: &mut Mem,
+        bins: &mut Vec<MachO>,
+        new_bins: &mut Vec<usize>,
+    ) -> Result<usize, String> {
+        // This check also stops infinite recursion if there's a cycle.
+        if let Some(idx) = self.find_loaded_bin(path) {
+            return Ok(idx);
+        }
+
+        let bin = MachO::load_from_file(path, fs, mem)
+            .map_err(|e| format!("Could not load {:?}: {}", path.as_str(), e))?;
+        let dynamic_libraries = bin.dynamic_libraries.clone();
+        let idx = bins.len();
+        bins.push(bin);
+        self.loaded_bins.push(LoadedBin {
+            path: path.to_owned(),
+            dependencies: Vec::new(),
+        });
+
+        for library in dynamic_libraries {
+            let Some(library_path) = self.resolve_library_path(&library, path, fs) else {
+                continue;
+            };
+            let dependency_idx =
+                self.load_bin_and_dependencies_inner(&library_path, fs, mem, bins, new_bins)?;
+            self.loaded_bins[idx].dependencies.push(dependency_idx);
+        }
+
+        new_bins.push(idx);
+        Ok(idx)
+    }
+
+    /// Find the index of a binary that has already been loaded from `path`.
+    pub fn find_loaded_bin(&self, path: &GuestPath) -> Option<usize> {
+        self.loaded_bins
+            .iter()
+            .position(|loaded_bin| loaded_bin.path.as_str() == path.as_str())
+    }
+
+    /// Get the indices of the binaries a binary depends on.
+    pub fn bin_dependencies(&self, idx: usize) -> &[usize] {
+        &self.loaded_bins[idx].dependencies
+    }
+
+    /// Whether a library path refers to one of the libraries outside the system
+    /// frameworks that touchHLE has host implementations of.
+    pub fn is_host_library(library: &str) -> bool {
+        library == "/usr/lib/libSystem.B.dylib"
+            || library == "/usr/lib/libobjc.A.dylib"
+            || library.starts_with("/usr/lib/libsqlite3")
+    }
+
+    /// Expand `@executable_path` and `@loader_path` in a library path.
+    pub fn expand_library_path(&self, library: &str, loader_path: &GuestPath) -> GuestPathBuf {
+        if let Some(relative) = library.strip_prefix("@executable_path/") {
+            let executable_path = match self.loaded_bins.first() {
+                Some(executable) => &*executable.path,
+                None => loader_path,
+            };
+            executable_path.parent().unwrap().join(relative)
+        } else if let Some(relative) = library.strip_prefix("@loader_path/") {
+            loader_path.parent().unwrap().join(relative)
+        } else {
+            GuestPathBuf::from(library.to_string())
+        }
+    }
+
+    /// Work out the path in the guest filesystem of a library referenced by
+    /// `loader_path` (the path of a binary or of the binary calling `dlopen()`),
+    /// expanding `@executable_path` and `@loader_path`. Returns [None] if the
+    /// library has a host implementation or can't be found, in which case it's
+    /// assumed the host implementation will be used.
+    pub fn resolve_library_path(
+        &self,
+        library: &str,
+        loader_path: &GuestPath,
+        fs: &Fs,
+    ) -> Option<GuestPathBuf> {
+        if Self::is_host_library(library) {
+            return None;
+        }
+
+        let path = self.expand_library_path(library, loader_path);
+
+        // There are some Free Software libraries bundled with touchHLE and
+        // exposed via the guest file system (see Fs::new()), and apps can
+        // contain their own libraries.
+        if fs.is_file(&path) {
+            Some(path)
+        } else {
+            // System frameworks will have host implementations.
+            // TODO: warn about unimplemented frameworks?
+            if !library.starts_with("/System/Library/Frameworks/") {
+                log!(
+                    "Warning: {:?} depends on unexpected dylib {:?}",
+                    loader_path.as_str(),
+                    library
+                );
+            }
+            None
+        }
+    }
+
     /// Do linking-related tasks that need doing right after loading the
-    /// binaries.
-    pub fn do_initial_linking(&mut self, bins: &[MachO], mem: &mut Mem, objc: &mut ObjC) {
+    /// binaries. `bins` are the binaries loaded so far and `new_bins` are the
+    /// indices returned by [Self::load_bin_and_dependencies].
+    pub fn do_initial_linking(
+        &mut self,
+        bins: &[MachO],
+        new_bins: &[usize],
+        mem: &mut Mem,
+        objc: &mut ObjC,
+    ) {
         assert!(self.return_to_host_routine.is_none());
         assert!(self.thread_exit_routine.is_none());
         self.return_to_host_routine =
             Some(write_return_to_host_routine(mem, Self::SVC_RETURN_TO_HOST));
         self.thread_exit_routine = Some(write_return_to_host_routine(mem, Self::SVC_THREAD_EXIT));
 
-        // Currently assuming only the app binary contains Objective-C things.
-
-        objc.register_bin_selectors(&bins[0], mem);
+        for &idx in new_bins {
+            objc.register_bin_selectors(&bins[idx], mem);
+        }
         objc.register_host_selectors(mem);
 
-        for bin in bins {
+        self.link_bins(bins, new_bins, mem, objc);
+    }
+
+    /// Link binaries loaded after [Self::do_initial_linking], e.g. by
+    /// `dlopen()`. See [Self::load_bin_and_dependencies].
+    ///
+    /// This must be followed by [Self::do_late_linking] and
+    /// [Self::run_initializers].
+    pub fn do_additional_linking(
+        &mut self,
+        bins: &[MachO],
+        new_bins: &[usize],
+        mem: &mut Mem,
+        cpu: &mut Cpu,
+        objc: &mut ObjC,
+    ) {
+        for &idx in new_bins {
+            objc.register_bin_selectors(&bins[idx], mem);
+        }
+
+        self.link_bins(bins, new_bins, mem, objc);
+
+        // The CPU may have cached code from memory that was previously used
+        // for something else.
+        for &idx in new_bins {
+            for section in &bins[idx].sections {
+                cpu.invalidate_cache_range(section.addr, section.size);
+            }
+        }
+    }
+
+    /// Shared part of [Self::do_initial_linking] and
+    /// [Self::do_additional_linking].
+    fn link_bins(&mut self, bins: &[MachO], new_bins: &[usize], mem: &mut Mem, objc: &mut ObjC) {
+        for &idx in new_bins {
+            let bin = &bins[idx];
             self.setup_lazy_linking(bin, mem);
             // Must happen before `register_bin_classes`, else superclass
             // pointers will be wrong.
             self.do_non_lazy_linking(bin, bins, mem, objc);
+            self.install_hooks(bin, mem);
+        }
+
+        // A new binary might have the first definition of a symbol that a
+        // binary loaded earlier has a weak reference to, so this covers all of
+        // them, not just the new ones.
+        Self::do_weak_binding(bins, mem);
+
+        // Dependencies come first, so superclasses in other binaries should be
+        // registered before their subclasses.
+        for &idx in new_bins {
+            objc.register_bin_classes(&bins[idx], mem);
+            objc.register_bin_categories(&bins[idx], mem);
+            ns_string::register_constant_strings(&bins[idx], mem, objc);
+        }
+    }
+
+    /// Patch guest functions that have host hooks (see
+    /// [function_lists::HOOK_LISTS]) so that the hook is called instead. The
+    /// hook can call the original function via [Self::hooked_original].
+    ///
+    /// The first two instructions of the function are overwritten, so they are
+    /// moved to a trampoline that then jumps to the rest of the function. This
+    /// only works if they don't depend on the PC, so only the usual `push`
+    /// followed by `add r7, sp, #...` prologue is accepted.
+    fn install_hooks(&mut self, bin: &MachO, mem: &mut Mem) {
+        for &(symbol, f) in function_lists::HOOK_LISTS.iter().flat_map(|&n| n) {
+            let Some(&addr) = bin.exported_symbols.get(symbol) else {
+                continue;
+            };
+            let function = GuestFunction::from_addr_with_thumb_bit(addr);
+            let entry: MutPtr<u32> = Ptr::from_bits(function.addr_without_thumb_bit());
+            let prologue = [mem.read(entry), mem.read(entry + 1)];
+            // push {..., r7, lr} (without pc), add r7, sp, #imm
+            if function.is_thumb()
+                || prologue[0] & 0xffff8000 != 0xe92d0000
+                || prologue[1] & 0xfffff000 != 0xe28d7000
+            {
+                log!(
+                    "Warning: Can't hook {} in {}, unexpected prologue {:08x?}",
+                    symbol,
+                    bin.name,
+                    prologue
+                );
+                continue;
+            }
+
+            let trampoline: MutPtr<u32> = mem.alloc(4 * 4).cast();
+            mem.write(trampoline + 0, prologue[0]);
+            mem.write(trampoline + 1, prologue[1]);
+            mem.write(trampoline + 2, 0xe51ff004); // ldr pc, [pc, #-4]
+            mem.write(trampoline + 3, entry.to_bits() + 8);
+
+            let svc = self.allocate_svc(symbol, f);
+            mem.write(entry + 0, encode_a32_svc(svc));
+            mem.write(entry + 1, encode_a32_ret());
+
+            log_dbg!("Hooked {} at {:?} in {}", symbol, entry, bin.name);
+            self.hooked_originals.insert(
+                symbol,
+                GuestFunction::from_addr_with_thumb_bit(trampoline.to_bits()),
+            );
+        }
+    }
+
+    /// Get a guest function that behaves like the original implementation of
+    /// a hooked function (see [Self::install_hooks]), if it was hooked.
+    pub fn hooked_original(&self, symbol: &str) -> Option<GuestFunction> {
+        self.hooked_originals.get(symbol).copied()
+    }
+
+    /// Run the static initializers (`__mod_init_func`) of newly-loaded
+    /// binaries, in the order given by [Self::load_bin_and_dependencies].
+    pub fn run_initializers(env: &mut Environment, new_bins: &[usize]) {
+        for &idx in new_bins {
+            let bin = &env.bins[idx];
+            let Some(section) = bin.get_section(SectionType::ModInitFuncPointers) else {
+                continue;
+            };
+
+            log_dbg!("Calling static initializers for {:?}", bin.name);
+            assert!(section.size % 4 == 0);
+            let base: ConstPtr<GuestFunction> = Ptr::from_bits(section.addr);
+            let count = section.size / 4;
+            for i in 0..count {
+                let func = env.mem.read(base + i);
+                func.call(env);
+            }
+            log_dbg!("Static initialization done");
         }
+    }
 
-        objc.register_bin_classes(&bins[0], mem);
-        objc.register_bin_categories(&bins[0], mem);
+    /// Load, link and initialize a library (and its dependencies) at runtime,
+    /// e.g. for `dlopen()`. Returns the index of the library in
+    /// [Environment::bins]. If the library was already loaded, this just
+    /// returns its index.
+    pub fn load_library_at_runtime(
+        env: &mut Environment,
+        path: &GuestPath,
+    ) -> Result<usize, String> {
+        let mut new_bins = Vec::new();
+        let idx = env.dyld.load_bin_and_dependencies(
+            path,
+            &env.fs,
+            &mut env.mem,
+            &mut env.bins,
+            &mut new_bins,
+        )?;
+        if new_bins.is_empty() {
+            return Ok(idx);
+        }
 
-        ns_string::register_constant_strings(&bins[0], mem, objc);
+        log!(
+            "Loaded {:?} and {} dependencies at runtime",
+            path.as_str(),
+            new_bins.len() - 1
+        );
+        env.dyld.do_additional_linking(
+            &env.bins,
+            &new_bins,
+            &mut env.mem,
+            &mut env.cpu,
+            &mut env.objc,
+        );
+        Self::do_late_linking(env);
+        Self::run_initializers(env, &new_bins);
+        Ok(idx)
     }
 
     /// [Self::do_initial_linking] but for when this is the app picker's special
@@ -427,6 +747,23 @@ impl Dyld {
         // FIXME: check for internal relocations?
     }
 
+    /// Point references to weak definitions (e.g. C++ template instantiations)
+    /// in all loaded binaries at the first definition of the symbol in any
+    /// loaded binary, so that all binaries agree on a single definition.
+    fn do_weak_binding(bins: &[MachO], mem: &mut Mem) {
+        for &(ptr_ptr, ref symbol, addend) in bins.iter().flat_map(|bin| &bin.weak_bindings) {
+            let Some(&addr) = bins
+                .iter()
+                .find_map(|other_bin| other_bin.exported_symbols.get(symbol))
+            else {
+                continue;
+            };
+            let ptr_ptr: MutPtr<u32> = Ptr::from_bits(ptr_ptr);
+            mem.write(ptr_ptr, addr.wrapping_add(addend as u32));
+            log_dbg!("Weak-bound {} at {:?} to {:#x}", symbol, ptr_ptr, addr);
+        }
+    }
+
     /// Do linking that can only be done once there is a full [Environment].
     /// Not to be confused with lazy linking.
     pub fn do_late_linking(env: &mut Environment) {
@@ -640,16 +977,20 @@ impl Dyld {
         Ok(function_ptr)
     }
 
+    /// Allocate an SVC ID for a host function.
+    fn allocate_svc(&mut self, symbol: &'static str, f: HostFunction) -> u32 {
+        let idx: u32 = self.linked_host_functions.len().try_into().unwrap();
+        self.linked_host_functions.push((symbol, f));
+        idx + Self::SVC_LINKED_FUNCTIONS_BASE
+    }
+
     pub fn create_guest_function(
         &mut self,
         mem: &mut Mem,
         symbol: &'static str,
         f: HostFunction,
     ) -> GuestFunction {
-        // Allocate an SVC ID for this host function
-        let idx: u32 = self.linked_host_functions.len().try_into().unwrap();
-        let svc = idx + Self::SVC_LINKED_FUNCTIONS_BASE;
-        self.linked_host_functions.push((symbol, f));
+        let svc = self.allocate_svc(symbol, f);
 
         // Create guest function to call this host function
         let function_ptr = mem.alloc(8);
@@ -660,3 +1001,65 @@ impl Dyld {
         GuestFunction::from_addr_with_thumb_bit(function_ptr.to_bits())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bin(
+        name: &str,
+        exported_symbols: &[(&str, u32)],
+        weak_bindings: &[(MutPtr<u32>, &str)],
+    ) -> MachO {
+        MachO {
+            name: name.to_string(),
+            dynamic_libraries: Vec::new(),
+            sections: Vec::new(),
+            exported_symbols: exported_symbols
+                .iter()
+                .map(|&(symbol, addr)| (symbol.to_string(), addr))
+                .collect(),
+            external_relocations: Vec::new(),
+            weak_bindings: weak_bindings
+                .iter()
+                .map(|&(ptr_ptr, symbol)| (ptr_ptr.to_bits(), symbol.to_string(), 0))
+                .collect(),
+            function_symbols: Vec::new(),
+            entry_point_pc: None,
+        }
+    }
+
+    #[test]
+    fn weak_binding_across_two_images() {
+        let mut mem = Mem::new();
+        let app_ptr: MutPtr<u32> = mem.alloc(4).cast();
+        let lib_ptr: MutPtr<u32> = mem.alloc(4).cast();
+        let other_ptr: MutPtr<u32> = mem.alloc(4).cast();
+
+        // The app has its own copy of _shared, but it refers to _lib_only
+        // without defining it.
+        let app = bin(
+            "app",
+            &[("_shared", 0x1000)],
+            &[(app_ptr, "_shared"), (other_ptr, "_lib_only")],
+        );
+        let mut bins = vec![app];
+        Dyld::do_weak_binding(&bins, &mut mem);
+        assert_eq!(mem.read(app_ptr), 0x1000);
+        assert_eq!(mem.read(other_ptr), 0);
+
+        // Now a library is loaded (e.g. by dlopen()) which defines both.
+        let lib = bin(
+            "lib",
+            &[("_shared", 0x2000), ("_lib_only", 0x2004)],
+            &[(lib_ptr, "_shared")],
+        );
+        bins.push(lib);
+        Dyld::do_weak_binding(&bins, &mut mem);
+        // The app's definition of _shared was loaded first, so it wins in
+        // both images, but the app now uses the library's _lib_only.
+        assert_eq!(mem.read(app_ptr), 0x1000);
+        assert_eq!(mem.read(lib_ptr), 0x1000);
+        assert_eq!(mem.read(other_ptr), 0x2004);
+    }
+}
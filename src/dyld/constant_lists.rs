@@ -7,7 +7,8 @@
 //! very long and frequently-updated list.
 
 use crate::frameworks::{
-    core_foundation, core_graphics, foundation, media_player, opengles, uikit,
+    core_animation, core_foundation, core_graphics, foundation, game_kit, media_player, opengles,
+    uikit,
 };
 use crate::libc;
 
@@ -15,6 +16,10 @@ use crate::libc;
 pub const CONSTANT_LISTS: &[super::ConstantExports] = &[
     libc::ctype::CONSTANTS,
     libc::stdio::CONSTANTS,
+    core_animation::ca_animation::CONSTANTS,
+    core_animation::ca_media_timing_function::CONSTANTS,
+    core_animation::ca_transaction::CONSTANTS,
+    core_animation::ca_transform_3d::CONSTANTS,
     core_foundation::cf_allocator::CONSTANTS,
     core_foundation::cf_run_loop::CONSTANTS,
     core_graphics::cg_affine_transform::CONSTANTS,
@@ -25,7 +30,10 @@ pub const CONSTANT_LISTS: &[super::ConstantExports] = &[
     foundation::ns_keyed_unarchiver::CONSTANTS,
     foundation::ns_locale::CONSTANTS,
     foundation::ns_run_loop::CONSTANTS,
+    game_kit::gk_local_player::CONSTANTS,
     media_player::movie_player::CONSTANTS,
     opengles::eagl::CONSTANTS,
+    uikit::ui_application::CONSTANTS,
     uikit::ui_device::CONSTANTS,
+    uikit::ui_view::ui_control::ui_text_field::CONSTANTS,
 ];
@@ -7,12 +7,18 @@
 //! very long and frequently-updated list.
 
 use crate::frameworks::{
-    audio_toolbox, core_foundation, core_graphics, dnssd, foundation, openal, opengles, uikit,
+    audio_toolbox, core_animation, core_foundation, core_graphics, dnssd, foundation, openal,
+    opengles, uikit,
 };
-use crate::libc;
+use crate::{libc, libsqlite3};
+
+/// Lists of host functions that replace functions in guest binaries, which
+/// they can still call into. See [super::Dyld::hooked_original].
+pub const HOOK_LISTS: &[super::FunctionExports] = &[libc::cxxabi::HOOKS];
 
 /// All the lists of functions that the linker should search through.
 pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
+    libc::blocks::FUNCTIONS,
     libc::clocale::FUNCTIONS,
     libc::ctype::FUNCTIONS,
     libc::cxxabi::FUNCTIONS,
@@ -46,11 +52,14 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     libc::time::FUNCTIONS,
     libc::unistd::FUNCTIONS,
     libc::wchar::FUNCTIONS,
+    libsqlite3::FUNCTIONS,
     crate::objc::FUNCTIONS,
     audio_toolbox::audio_file::FUNCTIONS,
     audio_toolbox::audio_queue::FUNCTIONS,
     audio_toolbox::audio_services::FUNCTIONS,
     audio_toolbox::audio_session::FUNCTIONS,
+    core_animation::ca_animation::FUNCTIONS,
+    core_animation::ca_transform_3d::FUNCTIONS,
     core_foundation::cf_array::FUNCTIONS,
     core_foundation::cf_bundle::FUNCTIONS,
     core_foundation::cf_data::FUNCTIONS,
@@ -67,6 +76,7 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     core_graphics::cg_data_provider::FUNCTIONS,
     core_graphics::cg_geometry::FUNCTIONS,
     core_graphics::cg_image::FUNCTIONS,
+    core_graphics::cg_path::FUNCTIONS,
     dnssd::FUNCTIONS,
     foundation::ns_file_manager::FUNCTIONS,
     foundation::ns_log::FUNCTIONS,
@@ -64,6 +64,32 @@ pub struct Thread {
     /// Address range of this thread's stack, used to check if addresses are in
     /// range while producing a stack trace.
     stack: Option<std::ops::RangeInclusive<u32>>,
+    /// Base of the region reserved for a secondary thread's stack, which
+    /// starts with the guard page. The main thread's stack is not freed.
+    stack_guard_base: Option<u32>,
+    /// One entry for each host-to-guest call currently in progress on this
+    /// thread, innermost last. See [Environment::unwind_guest_to].
+    guest_call_boundaries: Vec<GuestCallBoundary>,
+}
+
+/// Bookkeeping for a host-to-guest call ([Environment::run_call]) that is in
+/// progress. Guest stack frames below the stack pointer recorded here were
+/// created during the call, while those above it belong to guest code that is
+/// waiting for a host function to return.
+pub struct GuestCallBoundary {
+    /// Guest stack pointer at the time the call was made.
+    pub stack_pointer: u32,
+    /// C++ unwinding context registered by [crate::libc::cxxabi] so that C++
+    /// exceptions can propagate through the host stack frames, if any.
+    pub cxx_unwind_sentinel: Option<MutVoidPtr>,
+}
+
+/// Panic payload used by [Environment::unwind_guest_to] to unwind the host
+/// stack until the segment of the guest stack containing the target is found.
+struct GuestUnwind {
+    thread: ThreadId,
+    target_sp: u32,
+    landing: Box<dyn FnOnce(&mut Environment) + Send>,
 }
 
 impl Thread {
@@ -91,6 +117,7 @@ pub struct Environment {
     pub current_thread: ThreadId,
     pub threads: Vec<Thread>,
     pub libc_state: libc::State,
+    pub libsqlite3_state: libsqlite3::State,
     pub framework_state: frameworks::State,
     pub mutex_state: mutex::MutexState,
     pub options: options::Options,
@@ -192,35 +219,24 @@ impl Environment {
         } else {
             mem::Mem::new()
         };
+        if options.debug_allocator {
+            mem.enable_allocator_debugging();
+        }
 
-        let executable = mach_o::MachO::load_from_file(bundle.executable_path(), &fs, &mut mem)
-            .map_err(|e| format!("Could not load executable: {}", e))?;
-
-        let mut dylibs = Vec::new();
-        for dylib in &executable.dynamic_libraries {
-            if dylib == "/usr/lib/libSystem.B.dylib" || dylib == "/usr/lib/libobjc.A.dylib" {
-                // We have host implementations of these
-                continue;
-            }
+        let mut dyld = dyld::Dyld::new();
 
-            // There are some Free Software libraries bundled with touchHLE and
-            // exposed via the guest file system (see Fs::new()).
-            if fs.is_file(fs::GuestPath::new(dylib)) {
-                let dylib = mach_o::MachO::load_from_file(fs::GuestPath::new(dylib), &fs, &mut mem)
-                    .map_err(|e| format!("Could not load bundled dylib: {}", e))?;
-                dylibs.push(dylib);
-            } else {
-                // System frameworks will have host implementations.
-                // TODO: warn about unimplemented frameworks?
-                if !dylib.starts_with("/System/Library/Frameworks/") {
-                    log!(
-                        "Warning: app binary depends on unexpected dylib \"{}\"",
-                        dylib
-                    );
-                }
-                continue;
-            };
-        }
+        // The app binary is always loaded first, so it has index 0. Its
+        // dependencies come before it in the initialization order.
+        let mut bins = Vec::new();
+        let mut init_order = Vec::new();
+        dyld.load_bin_and_dependencies(
+            &bundle.executable_path(),
+            &fs,
+            &mut mem,
+            &mut bins,
+            &mut init_order,
+        )?;
+        let executable = &bins[0];
 
         let entry_point_addr = executable.entry_point_pc.ok_or_else(|| {
             "Mach-O file does not specify an entry point PC, perhaps it is not an executable?"
@@ -230,18 +246,17 @@ impl Environment {
 
         log_dbg!("Address of start function: {:?}", entry_point_addr);
 
-        let mut bins = dylibs;
-        bins.insert(0, executable);
-
         let mut objc = objc::ObjC::new();
 
-        let mut dyld = dyld::Dyld::new();
-        dyld.do_initial_linking(&bins, &mut mem, &mut objc);
+        dyld.do_initial_linking(&bins, &init_order, &mut mem, &mut objc);
 
-        let cpu = cpu::Cpu::new(match options.direct_memory_access {
-            true => Some(&mut mem),
-            false => None,
-        });
+        let cpu = cpu::Cpu::new(
+            match options.direct_memory_access {
+                true => Some(&mut mem),
+                false => None,
+            },
+            options.direct_read_only_memory_access,
+        );
 
         let main_thread = Thread {
             active: true,
@@ -251,6 +266,8 @@ impl Environment {
             in_host_function: false,
             context: None,
             stack: Some(mem::Mem::MAIN_THREAD_STACK_LOW_END..=0u32.wrapping_sub(1)),
+            stack_guard_base: None,
+            guest_call_boundaries: Vec::new(),
         };
 
         let mut env = Environment {
@@ -266,6 +283,7 @@ impl Environment {
             current_thread: 0,
             threads: vec![main_thread],
             libc_state: Default::default(),
+            libsqlite3_state: Default::default(),
             mutex_state: Default::default(),
             framework_state: Default::default(),
             options,
@@ -311,27 +329,7 @@ impl Environment {
 
         // Static initializers for libraries must be run before the initializer
         // in the app binary.
-        // TODO: once we support more libraries, replace this hard-coded order
-        //       with e.g. a topological sort.
-        assert!(env.bins.len() <= 3);
-        for bin_idx in [1, 2, 0] {
-            let Some(bin) = env.bins.get(bin_idx) else {
-                continue;
-            };
-            let Some(section) = bin.get_section(mach_o::SectionType::ModInitFuncPointers) else {
-                continue;
-            };
-
-            log_dbg!("Calling static initializers for {:?}", bin.name);
-            assert!(section.size % 4 == 0);
-            let base: mem::ConstPtr<abi::GuestFunction> = mem::Ptr::from_bits(section.addr);
-            let count = section.size / 4;
-            for i in 0..count {
-                let func = env.mem.read(base + i);
-                func.call(&mut env);
-            }
-            log_dbg!("Static initialization done");
-        }
+        dyld::Dyld::run_initializers(&mut env, &init_order);
 
         env.cpu.branch(entry_point_addr);
 
@@ -363,6 +361,9 @@ impl Environment {
         ));
 
         let mut mem = mem::Mem::new();
+        if options.debug_allocator {
+            mem.enable_allocator_debugging();
+        }
 
         let bins = Vec::new();
 
@@ -371,10 +372,13 @@ impl Environment {
         let mut dyld = dyld::Dyld::new();
         dyld.do_initial_linking_with_no_bins(&mut mem, &mut objc);
 
-        let cpu = cpu::Cpu::new(match options.direct_memory_access {
-            true => Some(&mut mem),
-            false => None,
-        });
+        let cpu = cpu::Cpu::new(
+            match options.direct_memory_access {
+                true => Some(&mut mem),
+                false => None,
+            },
+            options.direct_read_only_memory_access,
+        );
 
         let main_thread = Thread {
             active: true,
@@ -384,6 +388,8 @@ impl Environment {
             in_host_function: false,
             context: None,
             stack: Some(mem::Mem::MAIN_THREAD_STACK_LOW_END..=0u32.wrapping_sub(1)),
+            stack_guard_base: None,
+            guest_call_boundaries: Vec::new(),
         };
 
         let mut env = Environment {
@@ -399,6 +405,7 @@ impl Environment {
             current_thread: 0,
             threads: vec![main_thread],
             libc_state: Default::default(),
+            libsqlite3_state: Default::default(),
             mutex_state: Default::default(),
             framework_state: Default::default(),
             options,
@@ -441,6 +448,18 @@ impl Environment {
         )
     }
 
+    /// Describe the location of a code address for a stack trace, e.g.
+    /// `" (AppName: _main + 0x10)"`, or an empty string if it's unknown.
+    fn symbolicate(&self, addr: u32) -> String {
+        let addr = addr & !abi::GuestFunction::THUMB_BIT;
+        for bin in &self.bins {
+            if let Some((symbol, offset)) = bin.symbolicate(addr) {
+                return format!(" ({}: {} + {:#x})", bin.name, symbol, offset);
+            }
+        }
+        String::new()
+    }
+
     fn stack_trace(&self) {
         if self.current_thread == 0 {
             echo!("Attempting to produce stack trace for main thread:");
@@ -451,10 +470,8 @@ impl Environment {
             );
         }
         let stack_range = self.threads[self.current_thread].stack.clone().unwrap();
-        echo!(
-            " 0. {:#x} (PC)",
-            self.cpu.pc_with_thumb_bit().addr_with_thumb_bit()
-        );
+        let pc = self.cpu.pc_with_thumb_bit().addr_with_thumb_bit();
+        echo!(" 0. {:#x} (PC){}", pc, self.symbolicate(pc));
         let regs = self.cpu.regs();
         let mut lr = regs[cpu::Cpu::LR];
         let return_to_host_routine_addr = self.dyld.return_to_host_routine().addr_with_thumb_bit();
@@ -465,7 +482,7 @@ impl Environment {
             echo!(" 1. [thread exit] (LR)");
             return;
         } else {
-            echo!(" 1. {:#x} (LR)", lr);
+            echo!(" 1. {:#x} (LR){}", lr, self.symbolicate(lr));
         }
         let mut i = 2;
         let mut fp: mem::ConstPtr<u8> = mem::Ptr::from_bits(regs[abi::FRAME_POINTER]);
@@ -482,12 +499,88 @@ impl Environment {
                 echo!("{:2}. [thread exit]", i);
                 return;
             } else {
-                echo!("{:2}. {:#x}", i, lr);
+                echo!("{:2}. {:#x}{}", i, lr, self.symbolicate(lr));
             }
             i += 1;
         }
     }
 
+    /// Get what the allocator debugging mode needs to find the guest backtrace
+    /// of a host function call (see [mem::BacktraceContext]).
+    fn backtrace_context(&self) -> mem::BacktraceContext {
+        let regs = self.cpu.regs();
+        mem::BacktraceContext {
+            lr: regs[cpu::Cpu::LR],
+            fp: regs[abi::FRAME_POINTER],
+            stack: self.threads[self.current_thread].stack.clone().unwrap(),
+            end_addrs: [
+                self.dyld.return_to_host_routine().addr_with_thumb_bit(),
+                self.dyld.thread_exit_routine().addr_with_thumb_bit(),
+            ],
+        }
+    }
+
+    fn print_backtrace(&self, backtrace: Option<&[u32]>) {
+        let Some(backtrace) = backtrace else {
+            echo!("    [host]");
+            return;
+        };
+        for (i, &addr) in backtrace.iter().enumerate() {
+            echo!("    {:2}. {:#x}{}", i, addr, self.symbolicate(addr));
+        }
+    }
+
+    /// Print any problems found by the allocator debugging mode
+    /// (`--debug-allocator`).
+    fn print_allocator_reports(&mut self) {
+        for mem::AllocatorReport {
+            message,
+            backtraces,
+        } in self.mem.take_allocator_reports()
+        {
+            echo!("Allocator debugging: {}", message);
+            for (label, backtrace) in backtraces {
+                echo!("  {}:", label);
+                self.print_backtrace(backtrace.as_deref());
+            }
+        }
+    }
+
+    /// If the allocator debugging mode (`--debug-allocator`) is enabled, print
+    /// a summary of allocations that were never freed. This should be called
+    /// when the app exits.
+    pub fn print_allocator_leak_report(&mut self) {
+        self.print_allocator_reports();
+        let Some(mem::LeakSummary { sites, untraced }) = self.mem.allocator_leak_summary() else {
+            return;
+        };
+        // Most apps never free some things, so only the biggest are shown.
+        const MAX_SITES: usize = 20;
+        let (count, bytes) = sites.iter().fold(untraced, |(count, bytes), &(_, c, b)| {
+            (count + c, bytes + b)
+        });
+        echo!(
+            "Allocator debugging: {} allocations ({} bytes) were not freed before exit, from {} guest call sites.",
+            count,
+            bytes,
+            sites.len(),
+        );
+        for (backtrace, count, bytes) in sites.iter().take(MAX_SITES) {
+            echo!("  {} bytes in {} allocations from:", bytes, count);
+            self.print_backtrace(Some(&backtrace[..]));
+        }
+        if sites.len() > MAX_SITES {
+            echo!("  ...and {} more call sites.", sites.len() - MAX_SITES);
+        }
+        if untraced.0 != 0 {
+            echo!(
+                "  {} bytes in {} allocations made by the host.",
+                untraced.1,
+                untraced.0
+            );
+        }
+    }
+
     /// Create a new thread and return its ID. The `start_routine` and
     /// `user_data` arguments have the same meaning as the last two arguments to
     /// `pthread_create`.
@@ -497,7 +590,13 @@ impl Environment {
         user_data: mem::MutVoidPtr,
     ) -> ThreadId {
         let stack_size = mem::Mem::SECONDARY_THREAD_STACK_SIZE;
-        let stack_alloc = self.mem.alloc(stack_size);
+        let guard_size = mem::Mem::STACK_GUARD_SIZE;
+        let guard_base = self
+            .mem
+            .reserve_anywhere(guard_size + stack_size, mem::PAGE_SIZE)
+            .expect("Not enough free memory for thread stack");
+        self.mem.set_stack_guard(guard_base, guard_size);
+        let stack_alloc: mem::MutVoidPtr = mem::Ptr::from_bits(guard_base + guard_size);
         let stack_high_addr = stack_alloc.to_bits() + stack_size;
         assert!(stack_high_addr % 4 == 0);
 
@@ -509,6 +608,8 @@ impl Environment {
             in_host_function: false,
             context: Some(cpu::CpuContext::new()),
             stack: Some(stack_alloc.to_bits()..=(stack_high_addr - 1)),
+            stack_guard_base: Some(guard_base),
+            guest_call_boundaries: Vec::new(),
         });
         let new_thread_id = self.threads.len() - 1;
 
@@ -693,10 +794,122 @@ impl Environment {
     pub fn run_call(&mut self) {
         let was_in_host_function = self.threads[self.current_thread].in_host_function;
         let old_thread = self.current_thread;
-        self.threads[self.current_thread].in_host_function = false;
-        self.run_inner(false);
+        self.threads[old_thread].in_host_function = false;
+        self.threads[old_thread]
+            .guest_call_boundaries
+            .push(GuestCallBoundary {
+                stack_pointer: self.cpu.regs()[cpu::Cpu::SP],
+                cxx_unwind_sentinel: None,
+            });
+
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.run_inner(false)));
+
+        let boundary = self.threads[old_thread]
+            .guest_call_boundaries
+            .pop()
+            .unwrap();
+        self.threads[old_thread].in_host_function = was_in_host_function;
+        if let Some(sentinel) = boundary.cxx_unwind_sentinel {
+            // If another thread's panic is propagating, the sentinel can't
+            // be removed, but the emulator is going to crash anyway.
+            if self.current_thread == old_thread {
+                libc::cxxabi::remove_unwind_sentinel(self, sentinel);
+            }
+        }
+
+        if let Err(e) = res {
+            if let Some(unwind) = e.downcast_ref::<GuestUnwind>() {
+                if self.current_thread != old_thread || unwind.thread != old_thread {
+                    panic!(
+                        "Thread {} tried to unwind past a host-to-guest call made by thread {}, this is not supported!",
+                        unwind.thread,
+                        old_thread
+                    );
+                }
+            }
+            std::panic::resume_unwind(e);
+        }
         assert!(self.current_thread == old_thread);
-        self.threads[self.current_thread].in_host_function = was_in_host_function;
+    }
+
+    /// Get the host-to-guest calls in progress on the current thread, innermost
+    /// last.
+    pub fn guest_call_boundaries_mut(&mut self) -> &mut Vec<GuestCallBoundary> {
+        &mut self.threads[self.current_thread].guest_call_boundaries
+    }
+
+    /// Abandon the guest stack frames of the current thread below `target_sp`
+    /// and then call `landing`, which is responsible for setting up the CPU
+    /// state needed to resume guest execution (e.g. restoring registers saved
+    /// by `setjmp`). This is how `longjmp` and exceptions are implemented.
+    ///
+    /// Guest code can't simply jump to an older stack frame if there are host
+    /// functions in between, because their state lives on the host stack. So
+    /// this uses a panic to unwind the host stack, which is caught once the
+    /// host-to-guest call (if any) that created the target stack frame is
+    /// reached. The landing function is then run as if it were the host
+    /// function originally called by the guest.
+    pub fn unwind_guest_to<F>(&mut self, target_sp: u32, landing: F) -> !
+    where
+        F: FnOnce(&mut Environment) + Send + 'static,
+    {
+        let stack_range = self.threads[self.current_thread].stack.clone().unwrap();
+        assert!(
+            stack_range.contains(&target_sp),
+            "Unwind target {:#x} is outside the stack of thread {}",
+            target_sp,
+            self.current_thread
+        );
+        log_dbg!(
+            "Thread {} unwinding guest stack to SP {:#x}",
+            self.current_thread,
+            target_sp
+        );
+        std::panic::resume_unwind(Box::new(GuestUnwind {
+            thread: self.current_thread,
+            target_sp,
+            landing: Box::new(landing),
+        }))
+    }
+
+    /// Run a host function called by the guest, landing any unwinds (see
+    /// [Self::unwind_guest_to]) that target the current guest stack segment.
+    fn call_host_function_from_guest(&mut self, f: &dyn abi::CallFromGuest) {
+        let thread = self.current_thread;
+        let was_in_host_function = self.threads[thread].in_host_function;
+        self.threads[thread].in_host_function = true;
+        let old_backtrace_context = self.mem.is_allocator_debugging_enabled().then(|| {
+            let context = self.backtrace_context();
+            self.mem.set_backtrace_context(Some(context))
+        });
+        let res =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f.call_from_guest(self)));
+        self.threads[thread].in_host_function = was_in_host_function;
+        if let Some(old_backtrace_context) = old_backtrace_context {
+            self.mem.set_backtrace_context(old_backtrace_context);
+            self.print_allocator_reports();
+        }
+
+        let Err(e) = res else {
+            return;
+        };
+        let unwind = match e.downcast::<GuestUnwind>() {
+            Ok(unwind) => unwind,
+            Err(e) => std::panic::resume_unwind(e),
+        };
+        let segment_top = self.threads[thread]
+            .guest_call_boundaries
+            .last()
+            .map_or(u32::MAX, |boundary| boundary.stack_pointer);
+        if unwind.thread != thread || unwind.target_sp >= segment_top {
+            std::panic::resume_unwind(unwind);
+        }
+        log_dbg!(
+            "Thread {} landed unwind to SP {:#x}",
+            thread,
+            unwind.target_sp
+        );
+        (unwind.landing)(self);
     }
 
     fn switch_thread(&mut self, new_thread: ThreadId) {
@@ -796,9 +1009,16 @@ impl Environment {
                             curr_thread.return_value = Some(GuestRet::from_regs(self.cpu.regs()));
                             curr_thread.active = false;
                             let stack = curr_thread.stack.take().unwrap();
-                            let stack: mem::MutVoidPtr = mem::Ptr::from_bits(*stack.start());
-                            log_dbg!("Freeing thread {} stack {:?}", self.current_thread, stack);
-                            self.mem.free(stack);
+                            let guard_base = curr_thread.stack_guard_base.take().unwrap();
+                            log_dbg!(
+                                "Freeing thread {} stack {:#x}",
+                                self.current_thread,
+                                stack.start()
+                            );
+                            self.mem.unreserve(
+                                guard_base,
+                                mem::Mem::STACK_GUARD_SIZE + mem::Mem::SECONDARY_THREAD_STACK_SIZE,
+                            );
                             ThreadNextAction::Yield
                         }
                     }
@@ -839,12 +1059,7 @@ impl Environment {
                             svc_pc,
                             svc,
                         ) {
-                            let was_in_host_function =
-                                self.threads[self.current_thread].in_host_function;
-                            self.threads[self.current_thread].in_host_function = true;
-                            f.call_from_guest(self);
-                            self.threads[self.current_thread].in_host_function =
-                                was_in_host_function;
+                            self.call_host_function_from_guest(f);
                             // Host function might have put the thread to sleep.
                             if let ThreadBlock::NotBlocked =
                                 self.threads[self.current_thread].blocked_by
@@ -905,6 +1120,12 @@ impl Environment {
                     }
                     ThreadNextAction::Yield => break,
                     ThreadNextAction::ReturnToHost => return,
+                    ThreadNextAction::DebugCpuError(cpu::CpuError::MemoryError)
+                        if libc::signal::prepare_memory_fault(self) =>
+                    {
+                        let f: fn(&mut Environment) = libc::signal::call_memory_fault_handler;
+                        self.call_host_function_from_guest(&f);
+                    }
                     ThreadNextAction::DebugCpuError(e) => {
                         step_and_debug = self.debug_cpu_error(e);
                     }
@@ -29,6 +29,7 @@ pub mod core_foundation;
 pub mod core_graphics;
 pub mod dnssd;
 pub mod foundation;
+pub mod game_kit;
 pub mod media_player;
 pub mod openal;
 pub mod opengles;
@@ -41,6 +42,7 @@ pub struct State {
     audio_toolbox: audio_toolbox::State,
     core_animation: core_animation::State,
     foundation: foundation::State,
+    game_kit: game_kit::State,
     media_player: media_player::State,
     openal: openal::State,
     opengles: opengles::State,
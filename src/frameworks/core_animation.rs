@@ -8,13 +8,22 @@
 //! Useful resources:
 //! - Apple's [Core Animation Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/CoreAnimation_guide/Introduction/Introduction.html)
 
+pub mod ca_animation;
+pub mod ca_display_link;
 pub mod ca_eagl_layer;
 pub mod ca_layer;
+pub mod ca_media_timing_function;
+pub mod ca_transaction;
+pub mod ca_transform_3d;
 
 mod composition;
-pub use composition::recomposite_if_necessary;
+pub use composition::{
+    draw_status_bar_overlay, recomposite_if_necessary, status_bar_overlay, StatusBarOverlay,
+};
 
 #[derive(Default)]
 pub struct State {
+    ca_animation: ca_animation::State,
+    ca_transaction: ca_transaction::State,
     composition: composition::State,
 }
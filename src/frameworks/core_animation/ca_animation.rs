@@ -0,0 +1,1261 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CAAnimation` and its subclasses, and the engine that evaluates them.
+//!
+//! Layers keep their "model" property values as-is while animations are
+//! attached to them. Each frame, [update_animations] evaluates the animations
+//! and stores the result as the layer's presentation values, which are what
+//! the compositor draws.
+//!
+//! All the `CAAnimation` classes share one host object type, since they are
+//! all collections of parameters. Animations added to layers are meant to be
+//! copied, but here they are retained instead.
+//!
+//! Useful resources:
+//! - Apple's [Core Animation Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/CoreAnimation_guide/Introduction/Introduction.html)
+//! - Apple's [Animation Types and Timing Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/Animation_Types_Timing/Introduction/Introduction.html)
+
+use super::ca_layer::{CALayerHostObject, PresentationValues};
+use super::ca_transform_3d::CATransform3D;
+use super::{ca_media_timing_function, ca_transaction};
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::frameworks::core_foundation::time::CFTimeInterval;
+use crate::frameworks::core_graphics::cg_path::{self, CGPathRef};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_value::NSValueHostObject;
+use crate::frameworks::foundation::{ns_string, NSUInteger};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports,
+    HostObject, NSZonePtr,
+};
+use crate::Environment;
+use std::time::{Duration, Instant};
+
+pub const kCAFillModeForwards: &str = "forwards";
+pub const kCAFillModeBackwards: &str = "backwards";
+pub const kCAFillModeBoth: &str = "both";
+pub const kCAFillModeRemoved: &str = "removed";
+
+pub const kCAAnimationLinear: &str = "linear";
+pub const kCAAnimationDiscrete: &str = "discrete";
+pub const kCAAnimationPaced: &str = "paced";
+
+pub const kCATransitionFade: &str = "fade";
+pub const kCATransitionMoveIn: &str = "moveIn";
+pub const kCATransitionPush: &str = "push";
+pub const kCATransitionReveal: &str = "reveal";
+pub const kCATransitionFromRight: &str = "fromRight";
+pub const kCATransitionFromLeft: &str = "fromLeft";
+pub const kCATransitionFromTop: &str = "fromTop";
+pub const kCATransitionFromBottom: &str = "fromBottom";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_kCAFillModeForwards",
+        HostConstant::NSString(kCAFillModeForwards),
+    ),
+    (
+        "_kCAFillModeBackwards",
+        HostConstant::NSString(kCAFillModeBackwards),
+    ),
+    ("_kCAFillModeBoth", HostConstant::NSString(kCAFillModeBoth)),
+    (
+        "_kCAFillModeRemoved",
+        HostConstant::NSString(kCAFillModeRemoved),
+    ),
+    (
+        "_kCAAnimationLinear",
+        HostConstant::NSString(kCAAnimationLinear),
+    ),
+    (
+        "_kCAAnimationDiscrete",
+        HostConstant::NSString(kCAAnimationDiscrete),
+    ),
+    (
+        "_kCAAnimationPaced",
+        HostConstant::NSString(kCAAnimationPaced),
+    ),
+    (
+        "_kCATransitionFade",
+        HostConstant::NSString(kCATransitionFade),
+    ),
+    (
+        "_kCATransitionMoveIn",
+        HostConstant::NSString(kCATransitionMoveIn),
+    ),
+    (
+        "_kCATransitionPush",
+        HostConstant::NSString(kCATransitionPush),
+    ),
+    (
+        "_kCATransitionReveal",
+        HostConstant::NSString(kCATransitionReveal),
+    ),
+    (
+        "_kCATransitionFromRight",
+        HostConstant::NSString(kCATransitionFromRight),
+    ),
+    (
+        "_kCATransitionFromLeft",
+        HostConstant::NSString(kCATransitionFromLeft),
+    ),
+    (
+        "_kCATransitionFromTop",
+        HostConstant::NSString(kCATransitionFromTop),
+    ),
+    (
+        "_kCATransitionFromBottom",
+        HostConstant::NSString(kCATransitionFromBottom),
+    ),
+];
+
+/// Used when an animation's duration is 0.
+const DEFAULT_DURATION: CFTimeInterval = 0.25;
+
+#[derive(Default)]
+pub struct State {
+    /// Layers that have animations attached. Non-retaining, layers remove
+    /// themselves when deallocated.
+    pub(super) animated_layers: Vec<id>,
+    /// Delegate messages waiting to be sent at the end of the next update.
+    /// The animations are strong references.
+    pending_delegate_messages: Vec<DelegateMessage>,
+}
+
+#[derive(Copy, Clone)]
+enum DelegateMessage {
+    DidStart(id),
+    DidStop(id, bool),
+}
+
+/// An animation attached to a layer with `addAnimation:forKey:`.
+pub(super) struct LayerAnimation {
+    /// `NSString*`, strong reference. May be [nil].
+    pub(super) key: id,
+    /// `CAAnimation*`, strong reference.
+    pub(super) animation: id,
+    /// The media time at which the animation begins.
+    start_time: CFTimeInterval,
+    started: bool,
+    stopped: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum AnimationKind {
+    Abstract,
+    Property,
+    Basic,
+    Keyframe,
+    Group,
+    Transition,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FillMode {
+    Removed,
+    Forwards,
+    Backwards,
+    Both,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum CalculationMode {
+    Linear,
+    Discrete,
+    Paced,
+}
+
+struct CAAnimationHostObject {
+    kind: AnimationKind,
+    // CAMediaTiming
+    begin_time: CFTimeInterval,
+    duration: CFTimeInterval,
+    speed: f32,
+    time_offset: CFTimeInterval,
+    repeat_count: f32,
+    autoreverses: bool,
+    fill_mode: FillMode,
+    // CAAnimation
+    /// `CAMediaTimingFunction*`, strong reference
+    timing_function: id,
+    /// Strong reference (unusually!)
+    delegate: id,
+    removed_on_completion: bool,
+    // CAPropertyAnimation
+    /// `NSString*`, strong reference
+    key_path: id,
+    // CABasicAnimation
+    from_value: id,
+    to_value: id,
+    by_value: id,
+    // CAKeyframeAnimation
+    /// `NSArray*` of values, strong reference
+    values: id,
+    /// `NSArray*` of `NSNumber*`, strong reference
+    key_times: id,
+    /// `NSArray*` of `CAMediaTimingFunction*`, strong reference
+    timing_functions: id,
+    calculation_mode: CalculationMode,
+    /// Strong reference. Overrides `values` if set.
+    path: CGPathRef,
+    // CAAnimationGroup
+    /// `NSArray*` of `CAAnimation*`, strong reference
+    animations: id,
+    // CATransition
+    /// `NSString*`, strong reference
+    transition_type: id,
+    /// `NSString*`, strong reference
+    transition_subtype: id,
+}
+impl HostObject for CAAnimationHostObject {}
+
+/// An animatable layer property, identified by a key path.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Property {
+    Opacity,
+    Position,
+    PositionX,
+    PositionY,
+    AnchorPoint,
+    Bounds,
+    BoundsOrigin,
+    BoundsSize,
+    BoundsSizeWidth,
+    BoundsSizeHeight,
+    Transform,
+}
+impl Property {
+    fn from_key_path(key_path: &str) -> Option<Property> {
+        Some(match key_path {
+            "opacity" => Property::Opacity,
+            "position" => Property::Position,
+            "position.x" => Property::PositionX,
+            "position.y" => Property::PositionY,
+            "anchorPoint" => Property::AnchorPoint,
+            "bounds" => Property::Bounds,
+            "bounds.origin" => Property::BoundsOrigin,
+            "bounds.size" => Property::BoundsSize,
+            "bounds.size.width" => Property::BoundsSizeWidth,
+            "bounds.size.height" => Property::BoundsSizeHeight,
+            // TODO: "transform.rotation.z" and similar
+            "transform" => Property::Transform,
+            _ => return None,
+        })
+    }
+
+    fn get(self, values: &PresentationValues) -> Value {
+        match self {
+            Property::Opacity => Value::Float(values.opacity),
+            Property::Position => Value::Point(values.position),
+            Property::PositionX => Value::Float(values.position.x),
+            Property::PositionY => Value::Float(values.position.y),
+            Property::AnchorPoint => Value::Point(values.anchor_point),
+            Property::Bounds => Value::Rect(values.bounds),
+            Property::BoundsOrigin => Value::Point(values.bounds.origin),
+            Property::BoundsSize => Value::Size(values.bounds.size),
+            Property::BoundsSizeWidth => Value::Float(values.bounds.size.width),
+            Property::BoundsSizeHeight => Value::Float(values.bounds.size.height),
+            Property::Transform => Value::Transform(values.transform),
+        }
+    }
+
+    fn set(self, values: &mut PresentationValues, value: Value) {
+        match (self, value) {
+            (Property::Opacity, Value::Float(v)) => values.opacity = v,
+            (Property::Position, Value::Point(v)) => values.position = v,
+            (Property::PositionX, Value::Float(v)) => values.position.x = v,
+            (Property::PositionY, Value::Float(v)) => values.position.y = v,
+            (Property::AnchorPoint, Value::Point(v)) => values.anchor_point = v,
+            (Property::Bounds, Value::Rect(v)) => values.bounds = v,
+            (Property::BoundsOrigin, Value::Point(v)) => values.bounds.origin = v,
+            (Property::BoundsSize, Value::Size(v)) => values.bounds.size = v,
+            (Property::BoundsSizeWidth, Value::Float(v)) => values.bounds.size.width = v,
+            (Property::BoundsSizeHeight, Value::Float(v)) => values.bounds.size.height = v,
+            (Property::Transform, Value::Transform(v)) => values.transform = v,
+            _ => log!("Warning: Can't set {:?} to {:?}, ignoring", self, value),
+        }
+    }
+
+    /// Unbox an `NSNumber*` or `NSValue*` of the type this property uses.
+    /// Returns [None] if the object is `nil` or of the wrong type, since it
+    /// comes straight from the app.
+    fn unbox(self, env: &mut Environment, object: id) -> Option<Value> {
+        if object == nil {
+            return None;
+        }
+        let expected = self.get(&PresentationValues::default());
+        let value = if let Value::Float(_) = expected {
+            let number_class: Class = env.objc.get_known_class("NSNumber", &mut env.mem);
+            let is_number: bool = msg![env; object isKindOfClass:number_class];
+            is_number.then(|| Value::Float(msg![env; object floatValue]))
+        } else {
+            match (expected, env.objc.try_borrow::<NSValueHostObject>(object)) {
+                (Value::Point(_), Some(&NSValueHostObject::CGPoint(v))) => Some(Value::Point(v)),
+                (Value::Size(_), Some(&NSValueHostObject::CGSize(v))) => Some(Value::Size(v)),
+                (Value::Rect(_), Some(&NSValueHostObject::CGRect(v))) => Some(Value::Rect(v)),
+                (Value::Transform(_), Some(&NSValueHostObject::CATransform3D(v))) => {
+                    Some(Value::Transform(v))
+                }
+                _ => None,
+            }
+        };
+        if value.is_none() {
+            log!(
+                "Warning: {:?} is not a valid value for {:?}, ignoring",
+                object,
+                self
+            );
+        }
+        value
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Value {
+    Float(CGFloat),
+    Point(CGPoint),
+    Size(CGSize),
+    Rect(CGRect),
+    /// TODO: Real Core Animation interpolates the decomposed transform, not
+    /// the individual matrix components.
+    Transform(CATransform3D),
+}
+impl Value {
+    fn to_floats(self) -> ([CGFloat; 16], usize) {
+        let mut floats = [0.0; 16];
+        let count = match self {
+            Value::Float(v) => {
+                floats[0] = v;
+                1
+            }
+            Value::Point(p) => {
+                floats[..2].copy_from_slice(&[p.x, p.y]);
+                2
+            }
+            Value::Size(s) => {
+                floats[..2].copy_from_slice(&[s.width, s.height]);
+                2
+            }
+            Value::Rect(r) => {
+                floats[..4].copy_from_slice(&[r.origin.x, r.origin.y, r.size.width, r.size.height]);
+                4
+            }
+            Value::Transform(t) => {
+                floats = t.rows().concat().try_into().unwrap();
+                16
+            }
+        };
+        (floats, count)
+    }
+
+    fn with_floats(self, f: [CGFloat; 16]) -> Value {
+        match self {
+            Value::Float(_) => Value::Float(f[0]),
+            Value::Point(_) => Value::Point(CGPoint { x: f[0], y: f[1] }),
+            Value::Size(_) => Value::Size(CGSize {
+                width: f[0],
+                height: f[1],
+            }),
+            Value::Rect(_) => Value::Rect(CGRect {
+                origin: CGPoint { x: f[0], y: f[1] },
+                size: CGSize {
+                    width: f[2],
+                    height: f[3],
+                },
+            }),
+            Value::Transform(_) => Value::Transform(CATransform3D::from_rows([
+                [f[0], f[1], f[2], f[3]],
+                [f[4], f[5], f[6], f[7]],
+                [f[8], f[9], f[10], f[11]],
+                [f[12], f[13], f[14], f[15]],
+            ])),
+        }
+    }
+
+    fn combine(a: Value, b: Value, f: impl Fn(CGFloat, CGFloat) -> CGFloat) -> Value {
+        let (a_floats, count) = a.to_floats();
+        let (b_floats, _) = b.to_floats();
+        let mut result = [0.0; 16];
+        for (result, (a, b)) in result
+            .iter_mut()
+            .zip(a_floats.into_iter().zip(b_floats))
+            .take(count)
+        {
+            *result = f(a, b);
+        }
+        a.with_floats(result)
+    }
+
+    fn lerp(a: Value, b: Value, t: CGFloat) -> Value {
+        Value::combine(a, b, |a, b| a + (b - a) * t)
+    }
+    fn add(a: Value, b: Value) -> Value {
+        Value::combine(a, b, |a, b| a + b)
+    }
+    fn sub(a: Value, b: Value) -> Value {
+        Value::combine(a, b, |a, b| a - b)
+    }
+}
+
+/// The current media time, as returned by `CACurrentMediaTime()`.
+pub fn current_media_time(env: &Environment) -> CFTimeInterval {
+    env.startup_time.elapsed().as_secs_f64()
+}
+
+fn state(env: &mut Environment) -> &mut State {
+    &mut env.framework_state.core_animation.ca_animation
+}
+
+fn fill_mode_from_string(env: &mut Environment, string: id) -> FillMode {
+    let string = ns_string::to_rust_string(env, string);
+    match &*string {
+        kCAFillModeRemoved => FillMode::Removed,
+        kCAFillModeForwards => FillMode::Forwards,
+        kCAFillModeBackwards => FillMode::Backwards,
+        kCAFillModeBoth => FillMode::Both,
+        _ => {
+            log!("Warning: Unknown fill mode {:?}, using removed", string);
+            FillMode::Removed
+        }
+    }
+}
+
+fn calculation_mode_from_string(env: &mut Environment, string: id) -> CalculationMode {
+    let string = ns_string::to_rust_string(env, string);
+    match &*string {
+        kCAAnimationLinear => CalculationMode::Linear,
+        kCAAnimationDiscrete => CalculationMode::Discrete,
+        kCAAnimationPaced => CalculationMode::Paced,
+        _ => {
+            log!("TODO: calculation mode {:?}, using linear", string);
+            CalculationMode::Linear
+        }
+    }
+}
+
+/// Replace a retained object field of an animation.
+fn set_object_field(
+    env: &mut Environment,
+    this: id,
+    new: id,
+    field: fn(&mut CAAnimationHostObject) -> &mut id,
+) {
+    retain(env, new);
+    let old = std::mem::replace(field(env.objc.borrow_mut(this)), new);
+    release(env, old);
+}
+
+/// Work out how far through its timeline an animation is, given the time since
+/// it began in its parent's time space (which should already account for the
+/// animation's `beginTime`). The result is the time in seconds within the
+/// current repetition, after applying the timing function, or [None] if the
+/// animation has no effect at this time.
+fn local_time(
+    env: &mut Environment,
+    animation: id,
+    time: CFTimeInterval,
+) -> Option<CFTimeInterval> {
+    let &CAAnimationHostObject {
+        duration,
+        speed,
+        time_offset,
+        repeat_count,
+        autoreverses,
+        fill_mode,
+        timing_function,
+        ..
+    } = env.objc.borrow(animation);
+
+    let duration = if duration > 0.0 {
+        duration
+    } else {
+        DEFAULT_DURATION
+    };
+    let cycle_duration = if autoreverses {
+        duration * 2.0
+    } else {
+        duration
+    };
+    let active_duration = cycle_duration * (repeat_count as f64).max(1.0);
+
+    let mut time = time * speed as f64 + time_offset;
+    if time < 0.0 {
+        if !matches!(fill_mode, FillMode::Backwards | FillMode::Both) {
+            return None;
+        }
+        time = 0.0;
+    }
+    let at_end = time >= active_duration;
+    if at_end {
+        if !matches!(fill_mode, FillMode::Forwards | FillMode::Both) {
+            return None;
+        }
+        time = active_duration;
+    }
+
+    let mut cycle_time = if at_end {
+        // The end of the last cycle, not the start of the next one.
+        time - cycle_duration * ((active_duration / cycle_duration).ceil() - 1.0)
+    } else {
+        time % cycle_duration
+    };
+    if cycle_time > duration {
+        cycle_time = cycle_duration - cycle_time;
+    }
+
+    let progress = (cycle_time / duration) as f32;
+    let progress = ca_media_timing_function::evaluate(&env.objc, timing_function, progress);
+    Some(progress as f64 * duration)
+}
+
+/// Whether an animation has run its course, given the time since it began.
+fn is_finished(env: &mut Environment, animation: id, time: CFTimeInterval) -> bool {
+    let &CAAnimationHostObject {
+        duration,
+        speed,
+        time_offset,
+        repeat_count,
+        autoreverses,
+        ..
+    } = env.objc.borrow(animation);
+    if speed == 0.0 {
+        return false;
+    }
+    let duration = if duration > 0.0 {
+        duration
+    } else {
+        DEFAULT_DURATION
+    };
+    let cycle_duration = if autoreverses {
+        duration * 2.0
+    } else {
+        duration
+    };
+    let active_duration = cycle_duration * (repeat_count as f64).max(1.0);
+    time * speed as f64 + time_offset >= active_duration
+}
+
+/// Apply an animation to a layer's presentation values, given the time since
+/// it began. `model` is used for animations without an explicit end value.
+fn apply_animation(
+    env: &mut Environment,
+    animation: id,
+    time: CFTimeInterval,
+    model: &PresentationValues,
+    values: &mut PresentationValues,
+) {
+    let Some(time) = local_time(env, animation, time) else {
+        return;
+    };
+    let &CAAnimationHostObject {
+        kind,
+        duration,
+        key_path,
+        from_value,
+        to_value,
+        by_value,
+        values: keyframe_values,
+        key_times,
+        timing_functions,
+        calculation_mode,
+        path,
+        animations,
+        ..
+    } = env.objc.borrow(animation);
+    let duration = if duration > 0.0 {
+        duration
+    } else {
+        DEFAULT_DURATION
+    };
+    let progress = (time / duration) as f32;
+
+    if kind == AnimationKind::Group {
+        if animations == nil {
+            return;
+        }
+        let count: NSUInteger = msg![env; animations count];
+        for i in 0..count {
+            let child: id = msg![env; animations objectAtIndex:i];
+            let child_begin_time = env.objc.borrow::<CAAnimationHostObject>(child).begin_time;
+            apply_animation(env, child, time - child_begin_time, model, values);
+        }
+        return;
+    }
+
+    if !matches!(kind, AnimationKind::Basic | AnimationKind::Keyframe) {
+        return;
+    }
+
+    if key_path == nil {
+        return;
+    }
+    let key_path_str = ns_string::to_rust_string(env, key_path);
+    let Some(property) = Property::from_key_path(&key_path_str) else {
+        // The unsupported key path is logged when the animation is added.
+        return;
+    };
+
+    let current = property.get(values);
+    let new_value = if kind == AnimationKind::Basic {
+        let from = property.unbox(env, from_value);
+        let to = property.unbox(env, to_value);
+        let by = property.unbox(env, by_value);
+        let (from, to) = match (from, to, by) {
+            (Some(from), Some(to), _) => (from, to),
+            (Some(from), None, Some(by)) => (from, Value::add(from, by)),
+            (None, Some(to), Some(by)) => (Value::sub(to, by), to),
+            (Some(from), None, None) => (from, property.get(model)),
+            (None, Some(to), None) => (current, to),
+            (None, None, Some(by)) => (current, Value::add(current, by)),
+            (None, None, None) => (current, current),
+        };
+        Value::lerp(from, to, progress)
+    } else {
+        // A path is followed segment by segment, with each segment's start
+        // and end being keyframes. Only point properties can follow a path,
+        // which is logged when the animation is added.
+        let path_segments = if path != nil && matches!(current, Value::Point(_)) {
+            Some(cg_path::segments(&env.objc, path))
+        } else {
+            None
+        };
+        let keyframes = if let Some(ref segments) = path_segments {
+            let Some(&last) = segments.last() else {
+                return;
+            };
+            let mut keyframes: Vec<Value> = segments
+                .iter()
+                .map(|segment| Value::Point(segment.point_at(0.0)))
+                .collect();
+            keyframes.push(Value::Point(last.point_at(1.0)));
+            keyframes
+        } else {
+            if keyframe_values == nil {
+                return;
+            }
+            let count: NSUInteger = msg![env; keyframe_values count];
+            if count == 0 {
+                return;
+            }
+            let mut keyframes = Vec::with_capacity(count as usize);
+            for i in 0..count {
+                let value: id = msg![env; keyframe_values objectAtIndex:i];
+                keyframes.push(property.unbox(env, value).unwrap_or(current));
+            }
+            keyframes
+        };
+        let count = keyframes.len() as NSUInteger;
+        // keyTimes is ignored unless it has a time for every keyframe.
+        let key_times_count: NSUInteger = if key_times != nil {
+            msg![env; key_times count]
+        } else {
+            0
+        };
+        let times: Vec<f32> = if key_times_count == count {
+            (0..count)
+                .map(|i| {
+                    let time: id = msg![env; key_times objectAtIndex:i];
+                    msg![env; time floatValue]
+                })
+                .collect()
+        } else if calculation_mode == CalculationMode::Discrete {
+            (0..count).map(|i| i as f32 / count as f32).collect()
+        } else {
+            // TODO: paced mode should space the keyframes by distance
+            (0..count)
+                .map(|i| i as f32 / (count - 1).max(1) as f32)
+                .collect()
+        };
+
+        // Index of the last keyframe at or before the current time.
+        let i = times
+            .iter()
+            .take(keyframes.len())
+            .rposition(|&t| t <= progress)
+            .unwrap_or(0);
+        if calculation_mode == CalculationMode::Discrete || i + 1 >= keyframes.len() {
+            keyframes[i]
+        } else {
+            let (start, end) = (times[i], times.get(i + 1).copied().unwrap_or(1.0));
+            let mut segment_progress = if end > start {
+                ((progress - start) / (end - start)).clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+            if timing_functions != nil {
+                let count: NSUInteger = msg![env; timing_functions count];
+                if (i as NSUInteger) < count {
+                    let function: id = msg![env; timing_functions objectAtIndex:(i as NSUInteger)];
+                    segment_progress =
+                        ca_media_timing_function::evaluate(&env.objc, function, segment_progress);
+                }
+            }
+            if let Some(segments) = path_segments {
+                Value::Point(segments[i].point_at(segment_progress))
+            } else {
+                Value::lerp(keyframes[i], keyframes[i + 1], segment_progress)
+            }
+        }
+    };
+    property.set(values, new_value);
+}
+
+/// For use by `addAnimation:forKey:`.
+pub(super) fn add_animation(env: &mut Environment, layer: id, animation: id, key: id) {
+    let &CAAnimationHostObject {
+        kind,
+        begin_time,
+        key_path,
+        path,
+        ..
+    } = env.objc.borrow(animation);
+
+    match kind {
+        AnimationKind::Basic | AnimationKind::Keyframe => {
+            if key_path == nil {
+                log!(
+                    "Warning: Animation {:?} added to layer {:?} has no key path, ignoring",
+                    animation,
+                    layer
+                );
+                return;
+            }
+            let key_path_str = ns_string::to_rust_string(env, key_path);
+            match Property::from_key_path(&key_path_str) {
+                None => log!(
+                    "TODO: Animating key path {:?} of layer {:?} is not supported",
+                    key_path_str,
+                    layer
+                ),
+                Some(property)
+                    if path != nil
+                        && !matches!(
+                            property.get(&PresentationValues::default()),
+                            Value::Point(_)
+                        ) =>
+                {
+                    log!(
+                        "Warning: Key path {:?} can't follow a path, using values instead",
+                        key_path_str
+                    )
+                }
+                Some(_) => (),
+            }
+        }
+        AnimationKind::Transition => {
+            log!(
+                "TODO: CATransition on layer {:?} has no visual effect yet",
+                layer
+            );
+        }
+        AnimationKind::Group => (),
+        AnimationKind::Abstract | AnimationKind::Property => {
+            log!(
+                "Warning: Animation {:?} of abstract class can't be added to layer {:?}, ignoring",
+                animation,
+                layer
+            );
+            return;
+        }
+    }
+
+    // A beginTime of 0 means "now".
+    let start_time = if begin_time == 0.0 {
+        current_media_time(env)
+    } else {
+        begin_time
+    };
+
+    let key: id = msg![env; key copy];
+    retain(env, animation);
+
+    // An animation replaces any existing one for the same key.
+    if key != nil {
+        remove_animation(env, layer, key);
+    }
+
+    let new = LayerAnimation {
+        key,
+        animation,
+        start_time,
+        started: false,
+        stopped: false,
+    };
+    env.objc
+        .borrow_mut::<CALayerHostObject>(layer)
+        .animations
+        .push(new);
+    if !state(env).animated_layers.contains(&layer) {
+        state(env).animated_layers.push(layer);
+    }
+}
+
+/// For use by `removeAnimationForKey:` and `removeAllAnimations`. If `key` is
+/// [nil], all animations are removed.
+pub(super) fn remove_animation(env: &mut Environment, layer: id, key: id) {
+    let animations =
+        std::mem::take(&mut env.objc.borrow_mut::<CALayerHostObject>(layer).animations);
+    let mut kept = Vec::new();
+    for entry in animations {
+        let matches =
+            key == nil || (entry.key != nil && msg![env; key isEqualToString:(entry.key)]);
+        if matches {
+            finish_layer_animation(env, entry, false);
+        } else {
+            kept.push(entry);
+        }
+    }
+    env.objc.borrow_mut::<CALayerHostObject>(layer).animations = kept;
+}
+
+/// Release an animation that has been removed from its layer, queueing the
+/// delegate message if needed.
+fn finish_layer_animation(env: &mut Environment, entry: LayerAnimation, finished: bool) {
+    if entry.stopped {
+        release(env, entry.animation);
+    } else {
+        // Ownership passes to the message queue.
+        state(env)
+            .pending_delegate_messages
+            .push(DelegateMessage::DidStop(entry.animation, finished));
+    }
+    release(env, entry.key);
+}
+
+/// For use by `CALayer`'s `dealloc`.
+pub(super) fn layer_deallocated(env: &mut Environment, layer: id) {
+    remove_animation(env, layer, nil);
+    state(env).animated_layers.retain(|&other| other != layer);
+}
+
+/// For use by the compositor: evaluate all the animations attached to layers
+/// and update their presentation values, then send any delegate messages.
+///
+/// Returns the time the next update is due, if there are animations running.
+pub fn update_animations(env: &mut Environment) -> Option<Instant> {
+    // This is the end of the run loop iteration as far as Core Animation is
+    // concerned.
+    ca_transaction::commit_implicit_transaction(env);
+
+    let now = current_media_time(env);
+
+    let layers = state(env).animated_layers.clone();
+    for layer in layers {
+        let host_obj = env.objc.borrow_mut::<CALayerHostObject>(layer);
+        let entries = std::mem::take(&mut host_obj.animations);
+        let model = PresentationValues::from_model(host_obj);
+        let mut values = model;
+
+        let mut kept = Vec::new();
+        for mut entry in entries {
+            let time = now - entry.start_time;
+            if time >= 0.0 && !entry.started {
+                entry.started = true;
+                retain(env, entry.animation);
+                state(env)
+                    .pending_delegate_messages
+                    .push(DelegateMessage::DidStart(entry.animation));
+            }
+
+            let finished = !entry.stopped && is_finished(env, entry.animation, time);
+            let removed_on_completion = env
+                .objc
+                .borrow::<CAAnimationHostObject>(entry.animation)
+                .removed_on_completion;
+            if finished && removed_on_completion {
+                finish_layer_animation(env, entry, true);
+                continue;
+            }
+
+            apply_animation(env, entry.animation, time, &model, &mut values);
+
+            if finished {
+                // Kept around to fill forwards, but it still counts as
+                // finished.
+                entry.stopped = true;
+                retain(env, entry.animation);
+                state(env)
+                    .pending_delegate_messages
+                    .push(DelegateMessage::DidStop(entry.animation, true));
+            }
+            kept.push(entry);
+        }
+
+        let no_animations_left = kept.is_empty();
+        let host_obj = env.objc.borrow_mut::<CALayerHostObject>(layer);
+        host_obj.presentation = (!no_animations_left).then_some(values);
+        host_obj.animations = kept;
+        if no_animations_left {
+            state(env).animated_layers.retain(|&other| other != layer);
+        }
+    }
+
+    let messages = std::mem::take(&mut state(env).pending_delegate_messages);
+    for message in messages {
+        let (DelegateMessage::DidStart(animation) | DelegateMessage::DidStop(animation, _)) =
+            message;
+        let delegate = env.objc.borrow::<CAAnimationHostObject>(animation).delegate;
+        if delegate != nil {
+            match message {
+                DelegateMessage::DidStart(_) => {
+                    if env
+                        .objc
+                        .object_has_method_named(&env.mem, delegate, "animationDidStart:")
+                    {
+                        () = msg![env; delegate animationDidStart:animation];
+                    }
+                }
+                DelegateMessage::DidStop(_, finished) => {
+                    if env.objc.object_has_method_named(
+                        &env.mem,
+                        delegate,
+                        "animationDidStop:finished:",
+                    ) {
+                        () = msg![env; delegate animationDidStop:animation finished:finished];
+                    }
+                }
+            }
+        }
+        release(env, animation);
+    }
+
+    if state(env).animated_layers.is_empty() {
+        None
+    } else {
+        Some(Instant::now() + Duration::from_secs_f64(1.0 / 60.0))
+    }
+}
+
+/// Box a presentation value for use as an animation's `fromValue`.
+pub(super) fn box_value_for_key(
+    env: &mut Environment,
+    key: &str,
+    values: &PresentationValues,
+) -> id {
+    match Property::from_key_path(key).map(|property| property.get(values)) {
+        Some(Value::Float(v)) => msg_class![env; NSNumber numberWithFloat:v],
+        Some(Value::Point(v)) => msg_class![env; NSValue valueWithCGPoint:v],
+        Some(Value::Size(v)) => msg_class![env; NSValue valueWithCGSize:v],
+        Some(Value::Rect(v)) => msg_class![env; NSValue valueWithCGRect:v],
+        Some(Value::Transform(v)) => msg_class![env; NSValue valueWithCATransform3D:v],
+        None => nil,
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// Abstract class, but the host object is shared by all subclasses.
+@implementation CAAnimation: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let kind = [
+        ("CATransition", AnimationKind::Transition),
+        ("CAAnimationGroup", AnimationKind::Group),
+        ("CAKeyframeAnimation", AnimationKind::Keyframe),
+        ("CABasicAnimation", AnimationKind::Basic),
+        ("CAPropertyAnimation", AnimationKind::Property),
+    ]
+    .into_iter()
+    .find(|&(name, _)| {
+        let class: Class = env.objc.get_known_class(name, &mut env.mem);
+        env.objc.class_is_subclass_of(this, class)
+    })
+    .map_or(AnimationKind::Abstract, |(_, kind)| kind);
+
+    let host_object = Box::new(CAAnimationHostObject {
+        kind,
+        begin_time: 0.0,
+        duration: 0.0,
+        speed: 1.0,
+        time_offset: 0.0,
+        repeat_count: 0.0,
+        autoreverses: false,
+        fill_mode: FillMode::Removed,
+        timing_function: nil,
+        delegate: nil,
+        removed_on_completion: true,
+        key_path: nil,
+        from_value: nil,
+        to_value: nil,
+        by_value: nil,
+        values: nil,
+        key_times: nil,
+        timing_functions: nil,
+        calculation_mode: CalculationMode::Linear,
+        path: nil,
+        animations: nil,
+        transition_type: nil,
+        transition_subtype: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)animation {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new init];
+    autorelease(env, new)
+}
+
+- (())dealloc {
+    let &CAAnimationHostObject {
+        timing_function,
+        delegate,
+        key_path,
+        from_value,
+        to_value,
+        by_value,
+        values,
+        key_times,
+        timing_functions,
+        path,
+        animations,
+        transition_type,
+        transition_subtype,
+        ..
+    } = env.objc.borrow(this);
+    for object in [
+        timing_function,
+        delegate,
+        key_path,
+        from_value,
+        to_value,
+        by_value,
+        values,
+        key_times,
+        timing_functions,
+        path,
+        animations,
+        transition_type,
+        transition_subtype,
+    ] {
+        release(env, object);
+    }
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // TODO: actually copy, once something needs it
+    retain(env, this)
+}
+
+// CAAction implementation
+- (())runActionForKey:(id)key // NSString*
+               object:(id)object
+            arguments:(id)_arguments { // NSDictionary*
+    () = msg![env; object addAnimation:this forKey:key];
+}
+
+// CAMediaTiming implementation
+- (CFTimeInterval)beginTime {
+    env.objc.borrow::<CAAnimationHostObject>(this).begin_time
+}
+- (())setBeginTime:(CFTimeInterval)begin_time {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).begin_time = begin_time;
+}
+- (CFTimeInterval)duration {
+    env.objc.borrow::<CAAnimationHostObject>(this).duration
+}
+- (())setDuration:(CFTimeInterval)duration {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).duration = duration;
+}
+- (f32)speed {
+    env.objc.borrow::<CAAnimationHostObject>(this).speed
+}
+- (())setSpeed:(f32)speed {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).speed = speed;
+}
+- (CFTimeInterval)timeOffset {
+    env.objc.borrow::<CAAnimationHostObject>(this).time_offset
+}
+- (())setTimeOffset:(CFTimeInterval)time_offset {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).time_offset = time_offset;
+}
+- (f32)repeatCount {
+    env.objc.borrow::<CAAnimationHostObject>(this).repeat_count
+}
+- (())setRepeatCount:(f32)repeat_count {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).repeat_count = repeat_count;
+}
+- (bool)autoreverses {
+    env.objc.borrow::<CAAnimationHostObject>(this).autoreverses
+}
+- (())setAutoreverses:(bool)autoreverses {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).autoreverses = autoreverses;
+}
+- (id)fillMode {
+    let name = match env.objc.borrow::<CAAnimationHostObject>(this).fill_mode {
+        FillMode::Removed => kCAFillModeRemoved,
+        FillMode::Forwards => kCAFillModeForwards,
+        FillMode::Backwards => kCAFillModeBackwards,
+        FillMode::Both => kCAFillModeBoth,
+    };
+    ns_string::get_static_str(env, name)
+}
+- (())setFillMode:(id)fill_mode { // NSString*
+    let fill_mode = fill_mode_from_string(env, fill_mode);
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).fill_mode = fill_mode;
+}
+
+- (id)timingFunction {
+    env.objc.borrow::<CAAnimationHostObject>(this).timing_function
+}
+- (())setTimingFunction:(id)function { // CAMediaTimingFunction*
+    set_object_field(env, this, function, |host| &mut host.timing_function);
+}
+- (id)delegate {
+    env.objc.borrow::<CAAnimationHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate {
+    set_object_field(env, this, delegate, |host| &mut host.delegate);
+}
+- (bool)isRemovedOnCompletion {
+    env.objc.borrow::<CAAnimationHostObject>(this).removed_on_completion
+}
+- (())setRemovedOnCompletion:(bool)removed {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).removed_on_completion = removed;
+}
+
+@end
+
+@implementation CAPropertyAnimation: CAAnimation
+
++ (id)animationWithKeyPath:(id)key_path { // NSString*
+    let new: id = msg![env; this animation];
+    () = msg![env; new setKeyPath:key_path];
+    new
+}
+
+- (id)keyPath {
+    env.objc.borrow::<CAAnimationHostObject>(this).key_path
+}
+- (())setKeyPath:(id)key_path { // NSString*
+    let key_path: id = msg![env; key_path copy];
+    let old = std::mem::replace(
+        &mut env.objc.borrow_mut::<CAAnimationHostObject>(this).key_path,
+        key_path,
+    );
+    release(env, old);
+}
+
+@end
+
+@implementation CABasicAnimation: CAPropertyAnimation
+
+- (id)fromValue {
+    env.objc.borrow::<CAAnimationHostObject>(this).from_value
+}
+- (())setFromValue:(id)value {
+    set_object_field(env, this, value, |host| &mut host.from_value);
+}
+- (id)toValue {
+    env.objc.borrow::<CAAnimationHostObject>(this).to_value
+}
+- (())setToValue:(id)value {
+    set_object_field(env, this, value, |host| &mut host.to_value);
+}
+- (id)byValue {
+    env.objc.borrow::<CAAnimationHostObject>(this).by_value
+}
+- (())setByValue:(id)value {
+    set_object_field(env, this, value, |host| &mut host.by_value);
+}
+
+@end
+
+@implementation CAKeyframeAnimation: CAPropertyAnimation
+
+- (id)values {
+    env.objc.borrow::<CAAnimationHostObject>(this).values
+}
+- (())setValues:(id)values { // NSArray*
+    set_object_field(env, this, values, |host| &mut host.values);
+}
+- (id)keyTimes {
+    env.objc.borrow::<CAAnimationHostObject>(this).key_times
+}
+- (())setKeyTimes:(id)key_times { // NSArray* of NSNumber*
+    set_object_field(env, this, key_times, |host| &mut host.key_times);
+}
+- (id)timingFunctions {
+    env.objc.borrow::<CAAnimationHostObject>(this).timing_functions
+}
+- (())setTimingFunctions:(id)functions { // NSArray* of CAMediaTimingFunction*
+    set_object_field(env, this, functions, |host| &mut host.timing_functions);
+}
+- (id)calculationMode {
+    let name = match env.objc.borrow::<CAAnimationHostObject>(this).calculation_mode {
+        CalculationMode::Linear => kCAAnimationLinear,
+        CalculationMode::Discrete => kCAAnimationDiscrete,
+        CalculationMode::Paced => kCAAnimationPaced,
+    };
+    ns_string::get_static_str(env, name)
+}
+- (())setCalculationMode:(id)mode { // NSString*
+    let mode = calculation_mode_from_string(env, mode);
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).calculation_mode = mode;
+}
+- (CGPathRef)path {
+    env.objc.borrow::<CAAnimationHostObject>(this).path
+}
+- (())setPath:(CGPathRef)path {
+    set_object_field(env, this, path, |host| &mut host.path);
+}
+
+@end
+
+@implementation CAAnimationGroup: CAAnimation
+
+- (id)animations {
+    env.objc.borrow::<CAAnimationHostObject>(this).animations
+}
+- (())setAnimations:(id)animations { // NSArray* of CAAnimation*
+    set_object_field(env, this, animations, |host| &mut host.animations);
+}
+
+@end
+
+@implementation CATransition: CAAnimation
+
+- (id)type {
+    let transition_type = env.objc.borrow::<CAAnimationHostObject>(this).transition_type;
+    if transition_type == nil {
+        ns_string::get_static_str(env, kCATransitionFade)
+    } else {
+        transition_type
+    }
+}
+- (())setType:(id)transition_type { // NSString*
+    let transition_type: id = msg![env; transition_type copy];
+    let old = std::mem::replace(
+        &mut env.objc.borrow_mut::<CAAnimationHostObject>(this).transition_type,
+        transition_type,
+    );
+    release(env, old);
+}
+- (id)subtype {
+    env.objc.borrow::<CAAnimationHostObject>(this).transition_subtype
+}
+- (())setSubtype:(id)subtype { // NSString*
+    let subtype: id = msg![env; subtype copy];
+    let old = std::mem::replace(
+        &mut env.objc.borrow_mut::<CAAnimationHostObject>(this).transition_subtype,
+        subtype,
+    );
+    release(env, old);
+}
+
+@end
+
+};
+
+fn CACurrentMediaTime(env: &mut Environment) -> CFTimeInterval {
+    current_media_time(env)
+}
+
+pub const FUNCTIONS: FunctionExports = &[export_c_func!(CACurrentMediaTime())];
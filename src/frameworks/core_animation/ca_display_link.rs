@@ -0,0 +1,237 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CADisplayLink`.
+//!
+//! touchHLE has no real display to synchronize with, so display links follow
+//! the simulated refresh timing used to limit the framerate (see
+//! [crate::gles::present::refresh_interval]). Refreshes happen at regular
+//! intervals since startup, so all display links fire together.
+
+use crate::frameworks::core_foundation::time::CFTimeInterval;
+use crate::frameworks::foundation::ns_run_loop::{
+    add_display_link, remove_display_link, NSDefaultRunLoopMode, NSRunLoopCommonModes,
+};
+use crate::frameworks::foundation::{ns_string, NSInteger};
+use crate::gles::present::refresh_interval;
+use crate::objc::{
+    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports,
+    HostObject, SEL,
+};
+use crate::Environment;
+use std::time::{Duration, Instant};
+
+/// Refresh interval reported if there's no framerate limit.
+const DEFAULT_REFRESH_INTERVAL: CFTimeInterval = 1.0 / 60.0;
+
+struct CADisplayLinkHostObject {
+    /// Strong reference. [nil] once invalidated.
+    target: id,
+    selector: SEL,
+    frame_interval: NSInteger,
+    paused: bool,
+    /// The time of the refresh the most recent callback was for, in the same
+    /// time base as `CACurrentMediaTime()`.
+    timestamp: CFTimeInterval,
+    /// When the next callback is due. [None] if the display link is paused,
+    /// not in a run loop, or invalidated.
+    due_by: Option<Instant>,
+    /// Weak reference. [nil] if not in a run loop.
+    run_loop: id,
+}
+impl HostObject for CADisplayLinkHostObject {}
+
+/// Find the time of the `count`th refresh after `after`. If there's no
+/// framerate limit, refreshes happen continuously, so this returns `after`.
+fn refresh_after(env: &Environment, after: Instant, count: NSInteger) -> Instant {
+    let Some(interval) = refresh_interval(&env.options) else {
+        return after;
+    };
+    let since_startup = after.duration_since(env.startup_time).as_secs_f64();
+    let refreshes = (since_startup / interval).floor() + count as f64;
+    env.startup_time + Duration::from_secs_f64(refreshes * interval)
+}
+
+/// Schedule the next callback if the display link should be running.
+fn update_schedule(env: &mut Environment, display_link: id) {
+    let host_object = env.objc.borrow::<CADisplayLinkHostObject>(display_link);
+    let due_by = if host_object.paused || host_object.run_loop == nil {
+        None
+    } else {
+        Some(refresh_after(env, Instant::now(), 1))
+    };
+    env.objc
+        .borrow_mut::<CADisplayLinkHostObject>(display_link)
+        .due_by = due_by;
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation CADisplayLink: NSObject
+
++ (id)displayLinkWithTarget:(id)target
+                   selector:(SEL)selector {
+    retain(env, target);
+
+    let host_object = Box::new(CADisplayLinkHostObject {
+        target,
+        selector,
+        frame_interval: 1,
+        paused: false,
+        timestamp: 0.0,
+        due_by: None,
+        run_loop: nil,
+    });
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+
+    log_dbg!(
+        "New display link {:?}, target [{:?} {}]",
+        new,
+        target,
+        selector.as_str(&env.mem),
+    );
+
+    autorelease(env, new)
+}
+
+- (())dealloc {
+    let target = env.objc.borrow::<CADisplayLinkHostObject>(this).target;
+    release(env, target);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (())addToRunLoop:(id)run_loop // NSRunLoop*
+           forMode:(id)mode { // NSRunLoopMode
+    let default_mode = ns_string::get_static_str(env, NSDefaultRunLoopMode);
+    let common_modes = ns_string::get_static_str(env, NSRunLoopCommonModes);
+    if !(msg![env; mode isEqualToString:default_mode] || msg![env; mode isEqualToString:common_modes]) {
+        log!(
+            "TODO: [(CADisplayLink*){:?} addToRunLoop:{:?} forMode:{:?}] (treating as common modes)",
+            this,
+            run_loop,
+            ns_string::to_rust_string(env, mode),
+        );
+    }
+
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(this);
+    if host_object.target == nil {
+        log!("Warning: Display link {:?} is invalidated, not adding it to a run loop", this);
+        return;
+    }
+    if host_object.run_loop != nil {
+        // TODO: multiple modes or run loops
+        log!("TODO: Display link {:?} is already in a run loop, ignoring", this);
+        return;
+    }
+    host_object.run_loop = run_loop;
+    add_display_link(env, run_loop, this);
+    update_schedule(env, this);
+}
+- (())removeFromRunLoop:(id)run_loop // NSRunLoop*
+                forMode:(id)_mode { // NSRunLoopMode
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(this);
+    if host_object.run_loop != run_loop {
+        return;
+    }
+    host_object.run_loop = nil;
+    host_object.due_by = None;
+    // The run loop might hold the last reference.
+    retain(env, this);
+    remove_display_link(env, run_loop, this);
+    autorelease(env, this);
+}
+
+- (())invalidate {
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(this);
+    let run_loop = std::mem::replace(&mut host_object.run_loop, nil);
+    let target = std::mem::replace(&mut host_object.target, nil);
+    host_object.due_by = None;
+    release(env, target);
+    if run_loop != nil {
+        // The run loop might hold the last reference.
+        retain(env, this);
+        remove_display_link(env, run_loop, this);
+        autorelease(env, this);
+    }
+}
+
+- (CFTimeInterval)timestamp {
+    env.objc.borrow::<CADisplayLinkHostObject>(this).timestamp
+}
+- (CFTimeInterval)duration {
+    refresh_interval(&env.options).unwrap_or(DEFAULT_REFRESH_INTERVAL)
+}
+
+- (NSInteger)frameInterval {
+    env.objc.borrow::<CADisplayLinkHostObject>(this).frame_interval
+}
+- (())setFrameInterval:(NSInteger)frame_interval {
+    // Values less than 1 are documented to be ignored.
+    if frame_interval >= 1 {
+        env.objc.borrow_mut::<CADisplayLinkHostObject>(this).frame_interval = frame_interval;
+    }
+}
+
+- (bool)isPaused {
+    env.objc.borrow::<CADisplayLinkHostObject>(this).paused
+}
+- (())setPaused:(bool)paused {
+    env.objc.borrow_mut::<CADisplayLinkHostObject>(this).paused = paused;
+    update_schedule(env, this);
+}
+
+@end
+
+};
+
+/// For use by `NSRunLoop`: check if a display link is due to fire and fire it
+/// if necessary.
+///
+/// Returns the next firing time, if any.
+pub fn handle_display_link(env: &mut Environment, display_link: id) -> Option<Instant> {
+    let &CADisplayLinkHostObject {
+        target,
+        selector,
+        frame_interval,
+        due_by,
+        ..
+    } = env.objc.borrow(display_link);
+
+    // Paused, or removed from the run loop by an earlier callback.
+    let due_by = due_by?;
+
+    let now = Instant::now();
+    if due_by > now {
+        return Some(due_by);
+    }
+
+    // As with NSTimer, if the callback is late, this doesn't try to catch up
+    // by firing repeatedly.
+    let new_due_by = refresh_after(env, now, frame_interval);
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(display_link);
+    host_object.due_by = Some(new_due_by);
+    host_object.timestamp = due_by.duration_since(env.startup_time).as_secs_f64();
+
+    log_dbg!(
+        "Display link {:?} fired, sending {:?} message to {:?}",
+        display_link,
+        selector.as_str(&env.mem),
+        target
+    );
+
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    // Signature should be `- (void)displayLinkDidFire:(CADisplayLink *)sender`.
+    let _: () = msg_send(env, (target, selector, display_link));
+
+    release(env, pool);
+
+    // The callback may have paused or invalidated the display link.
+    env.objc
+        .borrow::<CADisplayLinkHostObject>(display_link)
+        .due_by
+}
@@ -45,6 +45,9 @@ pub const CLASSES: ClassExports = objc_classes! {
 /// there's a single full-screen layer, we skip transferring between contexts
 /// and present it directly from the app's context. This function is used to
 /// determine when that will happen.
+///
+/// The status bar doesn't prevent this: when it's visible, `EAGLContext` draws
+/// it on top of the presented frame itself.
 pub fn find_fullscreen_eagl_layer(env: &mut Environment) -> id {
     // Assumes the last window in the list is the one on top.
     // TODO: this is not correct once we support zPosition.
@@ -5,7 +5,13 @@
  */
 //! `CALayer`.
 
+use super::ca_animation::{self, LayerAnimation};
+use super::ca_transaction;
+use super::ca_transform_3d::{CATransform3D, CATransform3DIdentity};
 use crate::frameworks::core_foundation::{CFRelease, CFRetain};
+use crate::frameworks::core_graphics::cg_affine_transform::{
+    CGAffineTransform, CGAffineTransformIdentity,
+};
 use crate::frameworks::core_graphics::cg_bitmap_context::{
     CGBitmapContextCreate, CGBitmapContextGetHeight, CGBitmapContextGetWidth,
 };
@@ -16,9 +22,14 @@ use crate::frameworks::core_graphics::cg_context::{
 use crate::frameworks::core_graphics::cg_image::{
     kCGImageAlphaPremultipliedLast, kCGImageByteOrder32Big,
 };
-use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::{ns_array, ns_string};
 use crate::mem::{GuestUSize, Ptr};
-use crate::objc::{id, msg, nil, objc_classes, release, retain, ClassExports, HostObject, ObjC};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports,
+    HostObject, ObjC,
+};
+use crate::Environment;
 use std::collections::HashMap;
 
 pub(super) struct CALayerHostObject {
@@ -31,6 +42,10 @@ pub(super) struct CALayerHostObject {
     pub(super) bounds: CGRect,
     pub(super) position: CGPoint,
     pub(super) anchor_point: CGPoint,
+    pub(super) transform: CATransform3D,
+    pub(super) sublayer_transform: CATransform3D,
+    pub(super) z_position: CGFloat,
+    pub(super) masks_to_bounds: bool,
     pub(super) hidden: bool,
     pub(super) opaque: bool,
     pub(super) opacity: f32,
@@ -48,8 +63,188 @@ pub(super) struct CALayerHostObject {
     pub(super) gles_texture: Option<crate::gles::gles11_raw::types::GLuint>,
     /// Internal state for compositor
     pub(super) gles_texture_is_up_to_date: bool,
+    /// Animations attached with `addAnimation:forKey:`, in the order they were
+    /// added.
+    pub(super) animations: Vec<LayerAnimation>,
+    /// Values of the animated properties, if there are animations attached.
+    /// See [ca_animation].
+    pub(super) presentation: Option<PresentationValues>,
 }
 impl HostObject for CALayerHostObject {}
+impl CALayerHostObject {
+    /// Get the values of the animatable properties that should be displayed.
+    pub(super) fn presentation_values(&self) -> PresentationValues {
+        self.presentation
+            .unwrap_or_else(|| PresentationValues::from_model(self))
+    }
+}
+
+/// The animatable properties of a layer.
+#[derive(Copy, Clone, Debug)]
+pub(super) struct PresentationValues {
+    pub(super) bounds: CGRect,
+    pub(super) position: CGPoint,
+    pub(super) anchor_point: CGPoint,
+    pub(super) transform: CATransform3D,
+    pub(super) opacity: f32,
+}
+impl Default for PresentationValues {
+    fn default() -> PresentationValues {
+        PresentationValues {
+            bounds: CGRect::default(),
+            position: CGPoint::default(),
+            anchor_point: CGPoint::default(),
+            transform: CATransform3DIdentity,
+            opacity: 0.0,
+        }
+    }
+}
+impl PresentationValues {
+    pub(super) fn from_model(host_obj: &CALayerHostObject) -> PresentationValues {
+        PresentationValues {
+            bounds: host_obj.bounds,
+            position: host_obj.position,
+            anchor_point: host_obj.anchor_point,
+            transform: host_obj.transform,
+            opacity: host_obj.opacity,
+        }
+    }
+}
+
+/// Keys for which `defaultActionForKey:` provides an implicit animation.
+const IMPLICITLY_ANIMATED_KEYS: &[&str] =
+    &["bounds", "position", "anchorPoint", "transform", "opacity"];
+
+/// Calculate the frame of a layer (or view) from its geometry: the bounding
+/// box of its bounds, after applying the transform around the anchor point.
+pub fn frame_from_geometry(
+    bounds: CGRect,
+    position: CGPoint,
+    anchor_point: CGPoint,
+    transform: CGAffineTransform,
+) -> CGRect {
+    let relative_to_anchor = CGRect {
+        origin: CGPoint {
+            x: -bounds.size.width * anchor_point.x,
+            y: -bounds.size.height * anchor_point.y,
+        },
+        size: bounds.size,
+    };
+    let transformed = transform.apply_to_rect(relative_to_anchor);
+    CGRect {
+        origin: CGPoint {
+            x: position.x + transformed.origin.x,
+            y: position.y + transformed.origin.y,
+        },
+        size: transformed.size,
+    }
+}
+
+/// Like [frame_from_geometry], but for a 3D transform, which is projected onto
+/// the superlayer's plane like the compositor does.
+fn projected_frame_from_geometry(
+    bounds: CGRect,
+    position: CGPoint,
+    anchor_point: CGPoint,
+    transform: CATransform3D,
+) -> CGRect {
+    if transform.is_affine() {
+        return frame_from_geometry(bounds, position, anchor_point, transform.to_affine());
+    }
+
+    let (x1, y1) = (
+        -bounds.size.width * anchor_point.x,
+        -bounds.size.height * anchor_point.y,
+    );
+    let (x2, y2) = (x1 + bounds.size.width, y1 + bounds.size.height);
+    let mut min = CGPoint {
+        x: f32::INFINITY,
+        y: f32::INFINITY,
+    };
+    let mut max = CGPoint {
+        x: f32::NEG_INFINITY,
+        y: f32::NEG_INFINITY,
+    };
+    for (x, y) in [(x1, y1), (x2, y1), (x1, y2), (x2, y2)] {
+        let [x, y, _, w] = transform.apply_to_vector([x, y, 0.0, 1.0]);
+        if w <= 0.0 {
+            log!("TODO: frame of a layer that is partly behind the viewer");
+            return frame_from_geometry(bounds, position, anchor_point, transform.to_affine());
+        }
+        let (x, y) = (x / w, y / w);
+        min = CGPoint {
+            x: min.x.min(x),
+            y: min.y.min(y),
+        };
+        max = CGPoint {
+            x: max.x.max(x),
+            y: max.y.max(y),
+        };
+    }
+    CGRect {
+        origin: CGPoint {
+            x: position.x + min.x,
+            y: position.y + min.y,
+        },
+        size: CGSize {
+            width: max.x - min.x,
+            height: max.y - min.y,
+        },
+    }
+}
+
+/// Run the action for a property change, which usually means an implicit
+/// animation from the old value to the new one. `old_values` is the
+/// presentation state before the change.
+fn run_action_for_key(
+    env: &mut Environment,
+    this: id,
+    key: &'static str,
+    old_values: PresentationValues,
+) {
+    let (_, _, disable_actions) = ca_transaction::implicit_animation_parameters(env);
+    // Layers not in a layer tree aren't visible, so there's nothing to
+    // animate.
+    if disable_actions || env.objc.borrow::<CALayerHostObject>(this).superlayer == nil {
+        return;
+    }
+
+    let key_string = ns_string::get_static_str(env, key);
+    let action: id = msg![env; this actionForKey:key_string];
+    if action == nil {
+        return;
+    }
+
+    // An implicit animation starts from the value before the change.
+    let basic_animation_class: Class = msg_class![env; CABasicAnimation class];
+    if msg![env; action isKindOfClass:basic_animation_class] {
+        let from_value: id = msg![env; action fromValue];
+        let to_value: id = msg![env; action toValue];
+        let by_value: id = msg![env; action byValue];
+        if from_value == nil && to_value == nil && by_value == nil {
+            let from_value = ca_animation::box_value_for_key(env, key, &old_values);
+            () = msg![env; action setFromValue:from_value];
+        }
+    }
+
+    () = msg![env; action runActionForKey:key_string object:this arguments:nil];
+}
+
+/// Get the transform from a layer's co-ordinate space to its superlayer's
+/// co-ordinate space.
+fn transform_to_superlayer(env: &mut Environment, layer: id) -> CGAffineTransform {
+    let bounds: CGRect = msg![env; layer bounds];
+    let position: CGPoint = msg![env; layer position];
+    let anchor_point: CGPoint = msg![env; layer anchorPoint];
+    // TODO: 3D transforms
+    let transform: CGAffineTransform = msg![env; layer affineTransform];
+    CGAffineTransform::make_translation(
+        -(bounds.origin.x + bounds.size.width * anchor_point.x),
+        -(bounds.origin.y + bounds.size.height * anchor_point.y),
+    )
+    .concat(transform)
+    .concat(CGAffineTransform::make_translation(position.x, position.y))
+}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -68,6 +263,10 @@ pub const CLASSES: ClassExports = objc_classes! {
         },
         position: CGPoint { x: 0.0, y: 0.0 },
         anchor_point: CGPoint { x: 0.5, y: 0.5 },
+        transform: CATransform3DIdentity,
+        sublayer_transform: CATransform3DIdentity,
+        z_position: 0.0,
+        masks_to_bounds: false,
         hidden: false,
         opaque: false,
         opacity: 1.0,
@@ -79,6 +278,8 @@ pub const CLASSES: ClassExports = objc_classes! {
         cg_context: None,
         gles_texture: None,
         gles_texture_is_up_to_date: false,
+        animations: Vec::new(),
+        presentation: None,
     });
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
@@ -88,6 +289,24 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; new_layer init]
 }
 
++ (id)defaultActionForKey:(id)key { // NSString*
+    let key_str = ns_string::to_rust_string(env, key);
+    if !IMPLICITLY_ANIMATED_KEYS.contains(&&*key_str) {
+        return nil;
+    }
+    let (duration, timing_function, _) = ca_transaction::implicit_animation_parameters(env);
+    let timing_function = if timing_function == nil {
+        let name = ns_string::get_static_str(env, "default");
+        msg_class![env; CAMediaTimingFunction functionWithName:name]
+    } else {
+        timing_function
+    };
+    let animation: id = msg_class![env; CABasicAnimation animationWithKeyPath:key];
+    () = msg![env; animation setDuration:duration];
+    () = msg![env; animation setTimingFunction:timing_function];
+    animation
+}
+
 - (())dealloc {
     let &mut CALayerHostObject {
         drawable_properties,
@@ -100,6 +319,8 @@ pub const CLASSES: ClassExports = objc_classes! {
     } = env.objc.borrow_mut(this);
     let sublayers = std::mem::take(sublayers);
 
+    ca_animation::layer_deallocated(env, this);
+
     if drawable_properties != nil {
         release(env, drawable_properties);
     }
@@ -166,19 +387,28 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.borrow::<CALayerHostObject>(this).bounds
 }
 - (())setBounds:(CGRect)bounds {
-    env.objc.borrow_mut::<CALayerHostObject>(this).bounds = bounds;
+    let host_obj = env.objc.borrow_mut::<CALayerHostObject>(this);
+    let old_values = host_obj.presentation_values();
+    host_obj.bounds = bounds;
+    run_action_for_key(env, this, "bounds", old_values);
 }
 - (CGPoint)position {
     env.objc.borrow::<CALayerHostObject>(this).position
 }
 - (())setPosition:(CGPoint)position {
-    env.objc.borrow_mut::<CALayerHostObject>(this).position = position;
+    let host_obj = env.objc.borrow_mut::<CALayerHostObject>(this);
+    let old_values = host_obj.presentation_values();
+    host_obj.position = position;
+    run_action_for_key(env, this, "position", old_values);
 }
 - (CGPoint)anchorPoint {
     env.objc.borrow::<CALayerHostObject>(this).anchor_point
 }
 - (())setAnchorPoint:(CGPoint)anchor_point {
-    env.objc.borrow_mut::<CALayerHostObject>(this).anchor_point = anchor_point;
+    let host_obj = env.objc.borrow_mut::<CALayerHostObject>(this);
+    let old_values = host_obj.presentation_values();
+    host_obj.anchor_point = anchor_point;
+    run_action_for_key(env, this, "anchorPoint", old_values);
 }
 
 - (CGRect)frame {
@@ -186,23 +416,21 @@ pub const CLASSES: ClassExports = objc_classes! {
         bounds,
         position,
         anchor_point,
+        transform,
         ..
     } = env.objc.borrow(this);
-    CGRect {
-        origin: CGPoint {
-            x: position.x - bounds.size.width * anchor_point.x,
-            y: position.y - bounds.size.height * anchor_point.y,
-        },
-        size: bounds.size,
-    }
+    projected_frame_from_geometry(bounds, position, anchor_point, transform)
 }
+// TODO: setting the frame of a layer with a non-identity transform
 - (())setFrame:(CGRect)frame {
+    let host_obj = env.objc.borrow_mut::<CALayerHostObject>(this);
+    let old_values = host_obj.presentation_values();
     let CALayerHostObject {
         bounds,
         position,
         anchor_point,
         ..
-    } = env.objc.borrow_mut(this);
+    } = host_obj;
     *position = CGPoint {
         x: frame.origin.x + frame.size.width * anchor_point.x,
         y: frame.origin.y + frame.size.height * anchor_point.y,
@@ -211,6 +439,44 @@ pub const CLASSES: ClassExports = objc_classes! {
         origin: CGPoint { x: 0.0, y: 0.0 },
         size: frame.size,
     };
+    run_action_for_key(env, this, "position", old_values);
+    run_action_for_key(env, this, "bounds", old_values);
+}
+
+- (CATransform3D)transform {
+    env.objc.borrow::<CALayerHostObject>(this).transform
+}
+- (())setTransform:(CATransform3D)transform {
+    let host_obj = env.objc.borrow_mut::<CALayerHostObject>(this);
+    let old_values = host_obj.presentation_values();
+    host_obj.transform = transform;
+    run_action_for_key(env, this, "transform", old_values);
+}
+- (CGAffineTransform)affineTransform {
+    env.objc.borrow::<CALayerHostObject>(this).transform.to_affine()
+}
+- (())setAffineTransform:(CGAffineTransform)transform {
+    let transform = CATransform3D::from_affine(transform);
+    () = msg![env; this setTransform:transform];
+}
+- (CATransform3D)sublayerTransform {
+    env.objc.borrow::<CALayerHostObject>(this).sublayer_transform
+}
+- (())setSublayerTransform:(CATransform3D)transform {
+    env.objc.borrow_mut::<CALayerHostObject>(this).sublayer_transform = transform;
+}
+- (CGFloat)zPosition {
+    env.objc.borrow::<CALayerHostObject>(this).z_position
+}
+- (())setZPosition:(CGFloat)z_position {
+    env.objc.borrow_mut::<CALayerHostObject>(this).z_position = z_position;
+}
+
+- (bool)masksToBounds {
+    env.objc.borrow::<CALayerHostObject>(this).masks_to_bounds
+}
+- (())setMasksToBounds:(bool)masks_to_bounds {
+    env.objc.borrow_mut::<CALayerHostObject>(this).masks_to_bounds = masks_to_bounds;
 }
 
 - (bool)isHidden {
@@ -231,7 +497,10 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.borrow::<CALayerHostObject>(this).opacity
 }
 - (())setOpacity:(f32)opacity {
-    env.objc.borrow_mut::<CALayerHostObject>(this).opacity = opacity;
+    let host_obj = env.objc.borrow_mut::<CALayerHostObject>(this);
+    let old_values = host_obj.presentation_values();
+    host_obj.opacity = opacity;
+    run_action_for_key(env, this, "opacity", old_values);
 }
 
 // See remarks in ui_view.rs about the type of this property
@@ -393,32 +662,29 @@ pub const CLASSES: ClassExports = objc_classes! {
     // The idea is to walk up each layer's superlayer chain, one at a time,
     // alternating between layers until we find a match.
 
-    // Maps of layer pointers to origins in that layer's co-ordinate space.
-    let mut this_map = HashMap::from([(this, CGPoint { x: 0.0, y: 0.0 })]);
-    let mut other_map = HashMap::from([(other, CGPoint { x: 0.0, y: 0.0 })]);
+    // Maps of layer pointers to transforms from this/other layer's
+    // co-ordinate space to that layer's co-ordinate space.
+    let mut this_map = HashMap::from([(this, CGAffineTransformIdentity)]);
+    let mut other_map = HashMap::from([(other, CGAffineTransformIdentity)]);
     // Current iteration state.
     let mut this_superlayer = this;
-    let mut this_origin = CGPoint { x: 0.0, y: 0.0 };
+    let mut this_transform = CGAffineTransformIdentity;
     let mut other_superlayer = other;
-    let mut other_origin = CGPoint { x: 0.0, y: 0.0 };
-    let (common_ancestor, this_origin, other_origin) = loop {
+    let mut other_transform = CGAffineTransformIdentity;
+    let (common_ancestor, this_transform, other_transform) = loop {
         if this_superlayer != nil {
             let next: id = msg![env; this_superlayer superlayer];
             if next == nil {
                 this_superlayer = nil;
             } else {
-                let bounds: CGRect = msg![env; this_superlayer bounds];
-                let frame: CGRect = msg![env; this_superlayer frame];
-                let next_origin = CGPoint {
-                    x: this_origin.x + frame.origin.x - bounds.origin.x,
-                    y: this_origin.y + frame.origin.y - bounds.origin.y,
-                };
-                if let Some(&other_origin) = other_map.get(&next) {
-                    break (next, next_origin, other_origin);
+                let next_transform =
+                    this_transform.concat(transform_to_superlayer(env, this_superlayer));
+                if let Some(&other_transform) = other_map.get(&next) {
+                    break (next, next_transform, other_transform);
                 }
-                this_map.insert(next, next_origin);
+                this_map.insert(next, next_transform);
                 this_superlayer = next;
-                this_origin = next_origin;
+                this_transform = next_transform;
             }
         }
 
@@ -427,18 +693,14 @@ pub const CLASSES: ClassExports = objc_classes! {
             if next == nil {
                 other_superlayer = nil;
             } else {
-                let bounds: CGRect = msg![env; other_superlayer bounds];
-                let frame: CGRect = msg![env; other_superlayer frame];
-                let next_origin = CGPoint {
-                    x: other_origin.x + frame.origin.x - bounds.origin.x,
-                    y: other_origin.y + frame.origin.y - bounds.origin.y,
-                };
-                if let Some(&this_origin) = this_map.get(&next) {
-                    break (next, this_origin, next_origin);
+                let next_transform =
+                    other_transform.concat(transform_to_superlayer(env, other_superlayer));
+                if let Some(&this_transform) = this_map.get(&next) {
+                    break (next, this_transform, next_transform);
                 }
-                other_map.insert(next, next_origin);
+                other_map.insert(next, next_transform);
                 other_superlayer = next;
-                other_origin = next_origin;
+                other_transform = next_transform;
             }
         }
 
@@ -449,12 +711,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     };
 
     log_dbg!("{:?} and {:?}'s common ancestor: {:?}", this, other, common_ancestor);
-    log_dbg!("{:?}'s origin in common ancestor: {:?}", this, this_origin);
-    log_dbg!("{:?}'s origin in common ancestor: {:?}", other, other_origin);
-    let res = CGPoint {
-        x: point.x + other_origin.x - this_origin.x,
-        y: point.y + other_origin.y - this_origin.y,
-    };
+    log_dbg!("{:?}'s transform to common ancestor: {:?}", this, this_transform);
+    log_dbg!("{:?}'s transform to common ancestor: {:?}", other, other_transform);
+    let res = this_transform
+        .invert()
+        .apply_to_point(other_transform.apply_to_point(point));
     log_dbg!("Converted {:?} from {:?} to {:?}: {:?}", point, other, this, res);
     res
 }
@@ -466,6 +727,105 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; other convertPoint:point fromLayer:this]
 }
 
+// Actions and animations (see ca_animation.rs)
+
+- (id)actionForKey:(id)key { // NSString*
+    // TODO: actions dictionary and style
+    let delegate = env.objc.borrow::<CALayerHostObject>(this).delegate;
+    if delegate != nil && env.objc.object_has_method_named(
+        &env.mem,
+        delegate,
+        "actionForLayer:forKey:"
+    ) {
+        let action: id = msg![env; delegate actionForLayer:this forKey:key];
+        if action != nil {
+            // NSNull means "no action, and stop searching".
+            let null: id = msg_class![env; NSNull null];
+            return if action == null { nil } else { action };
+        }
+    }
+    let class: Class = msg![env; this class];
+    msg![env; class defaultActionForKey:key]
+}
+
+- (())addAnimation:(id)animation // CAAnimation*
+            forKey:(id)key { // NSString*
+    ca_animation::add_animation(env, this, animation, key);
+}
+- (())removeAnimationForKey:(id)key { // NSString*
+    if key != nil {
+        ca_animation::remove_animation(env, this, key);
+    }
+}
+- (())removeAllAnimations {
+    ca_animation::remove_animation(env, this, nil);
+}
+- (id)animationForKey:(id)key { // NSString*
+    let entries: Vec<(id, id)> = env.objc.borrow::<CALayerHostObject>(this)
+        .animations
+        .iter()
+        .map(|entry| (entry.key, entry.animation))
+        .collect();
+    for (entry_key, animation) in entries {
+        if entry_key != nil && msg![env; key isEqualToString:entry_key] {
+            return animation;
+        }
+    }
+    nil
+}
+- (id)animationKeys {
+    let keys: Vec<id> = env.objc.borrow::<CALayerHostObject>(this)
+        .animations
+        .iter()
+        .map(|entry| entry.key)
+        .filter(|&key| key != nil)
+        .collect();
+    if keys.is_empty() {
+        return nil;
+    }
+    for &key in &keys {
+        retain(env, key);
+    }
+    let array = ns_array::from_vec(env, keys);
+    autorelease(env, array)
+}
+
+- (id)presentationLayer {
+    let &CALayerHostObject {
+        sublayer_transform,
+        z_position,
+        masks_to_bounds,
+        hidden,
+        opaque,
+        ..
+    } = env.objc.borrow(this);
+    let PresentationValues {
+        bounds,
+        position,
+        anchor_point,
+        transform,
+        opacity,
+    } = env.objc.borrow::<CALayerHostObject>(this).presentation_values();
+    // TODO: This is only a snapshot of the geometry, it has no contents.
+    let new: id = msg_class![env; CALayer alloc];
+    let new: id = msg![env; new init];
+    let host_obj = env.objc.borrow_mut::<CALayerHostObject>(new);
+    host_obj.bounds = bounds;
+    host_obj.position = position;
+    host_obj.anchor_point = anchor_point;
+    host_obj.transform = transform;
+    host_obj.sublayer_transform = sublayer_transform;
+    host_obj.z_position = z_position;
+    host_obj.masks_to_bounds = masks_to_bounds;
+    host_obj.opacity = opacity;
+    host_obj.hidden = hidden;
+    host_obj.opaque = opaque;
+    autorelease(env, new)
+}
+- (id)modelLayer {
+    this
+}
+
 // TODO: more
 
 @end
@@ -0,0 +1,218 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CAMediaTimingFunction`.
+
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::foundation::{ns_string, NSUInteger};
+use crate::mem::MutPtr;
+use crate::objc::{
+    autorelease, id, msg, msg_send, nil, ClassExports, ClassTemplate, HostObject, NSZonePtr, ObjC,
+    SEL,
+};
+use crate::Environment;
+
+pub const kCAMediaTimingFunctionLinear: &str = "linear";
+pub const kCAMediaTimingFunctionEaseIn: &str = "easeIn";
+pub const kCAMediaTimingFunctionEaseOut: &str = "easeOut";
+pub const kCAMediaTimingFunctionEaseInEaseOut: &str = "easeInEaseOut";
+pub const kCAMediaTimingFunctionDefault: &str = "default";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_kCAMediaTimingFunctionLinear",
+        HostConstant::NSString(kCAMediaTimingFunctionLinear),
+    ),
+    (
+        "_kCAMediaTimingFunctionEaseIn",
+        HostConstant::NSString(kCAMediaTimingFunctionEaseIn),
+    ),
+    (
+        "_kCAMediaTimingFunctionEaseOut",
+        HostConstant::NSString(kCAMediaTimingFunctionEaseOut),
+    ),
+    (
+        "_kCAMediaTimingFunctionEaseInEaseOut",
+        HostConstant::NSString(kCAMediaTimingFunctionEaseInEaseOut),
+    ),
+    (
+        "_kCAMediaTimingFunctionDefault",
+        HostConstant::NSString(kCAMediaTimingFunctionDefault),
+    ),
+];
+
+/// The two inner control points of a cubic Bézier curve from (0, 0) to (1, 1).
+pub type ControlPoints = [f32; 4];
+
+/// Look up the control points for one of the named timing functions.
+pub fn control_points_for_name(name: &str) -> Option<ControlPoints> {
+    match name {
+        kCAMediaTimingFunctionLinear => Some([0.0, 0.0, 1.0, 1.0]),
+        kCAMediaTimingFunctionEaseIn => Some([0.42, 0.0, 1.0, 1.0]),
+        kCAMediaTimingFunctionEaseOut => Some([0.0, 0.0, 0.58, 1.0]),
+        kCAMediaTimingFunctionEaseInEaseOut => Some([0.42, 0.0, 0.58, 1.0]),
+        kCAMediaTimingFunctionDefault => Some([0.25, 0.1, 0.25, 1.0]),
+        _ => None,
+    }
+}
+
+/// Evaluate a cubic Bézier timing curve at `x`, which should be in the range
+/// 0 to 1.
+pub fn cubic_bezier(points: ControlPoints, x: f32) -> f32 {
+    fn bezier(p1: f32, p2: f32, t: f32) -> f32 {
+        let u = 1.0 - t;
+        3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t
+    }
+    let [x1, y1, x2, y2] = points;
+    // The x coordinate is monotonic in t, so bisection finds the t for x.
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..20 {
+        let mid = (low + high) / 2.0;
+        if bezier(x1, x2, mid) < x {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    bezier(y1, y2, (low + high) / 2.0)
+}
+
+struct CAMediaTimingFunctionHostObject {
+    control_points: ControlPoints,
+}
+impl HostObject for CAMediaTimingFunctionHostObject {}
+
+/// Apply a `CAMediaTimingFunction*` to a progress value. [nil] means linear.
+pub fn evaluate(objc: &ObjC, function: id, x: f32) -> f32 {
+    if function == nil {
+        return x;
+    }
+    let points = objc
+        .borrow::<CAMediaTimingFunctionHostObject>(function)
+        .control_points;
+    cubic_bezier(points, x)
+}
+
+fn alloc_with_zone(env: &mut Environment, this: id, _cmd: SEL, _zone: NSZonePtr) -> id {
+    let host_object = Box::new(CAMediaTimingFunctionHostObject {
+        control_points: control_points_for_name(kCAMediaTimingFunctionLinear).unwrap(),
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+fn function_with_name(env: &mut Environment, this: id, _cmd: SEL, name: id) -> id {
+    let name_str = ns_string::to_rust_string(env, name);
+    let control_points = control_points_for_name(&name_str).unwrap_or_else(|| {
+        log!(
+            "Warning: Unknown CAMediaTimingFunction name {:?}, using linear",
+            name_str
+        );
+        control_points_for_name(kCAMediaTimingFunctionLinear).unwrap()
+    });
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new init];
+    env.objc
+        .borrow_mut::<CAMediaTimingFunctionHostObject>(new)
+        .control_points = control_points;
+    autorelease(env, new)
+}
+
+fn function_with_control_points(
+    env: &mut Environment,
+    this: id,
+    _cmd: SEL,
+    x1: f32,
+    y1: f32,
+    x2: f32,
+    y2: f32,
+) -> id {
+    let new: id = msg![env; this alloc];
+    let sel = env
+        .objc
+        .lookup_selector("initWithControlPoints::::")
+        .unwrap();
+    let new: id = msg_send(env, (new, sel, x1, y1, x2, y2));
+    autorelease(env, new)
+}
+
+fn init_with_control_points(
+    env: &mut Environment,
+    this: id,
+    _cmd: SEL,
+    x1: f32,
+    y1: f32,
+    x2: f32,
+    y2: f32,
+) -> id {
+    env.objc
+        .borrow_mut::<CAMediaTimingFunctionHostObject>(this)
+        .control_points = [x1, y1, x2, y2];
+    this
+}
+
+fn get_control_point_at_index_values(
+    env: &mut Environment,
+    this: id,
+    _cmd: SEL,
+    index: NSUInteger,
+    values: MutPtr<f32>,
+) {
+    let [x1, y1, x2, y2] = env
+        .objc
+        .borrow::<CAMediaTimingFunctionHostObject>(this)
+        .control_points;
+    let (x, y) = match index {
+        0 => (0.0, 0.0),
+        1 => (x1, y1),
+        2 => (x2, y2),
+        3 => (1.0, 1.0),
+        _ => {
+            log!(
+                "Warning: Invalid CAMediaTimingFunction control point index {}, ignoring",
+                index
+            );
+            return;
+        }
+    };
+    env.mem.write(values, x);
+    env.mem.write(values + 1, y);
+}
+
+// This is written out by hand rather than with objc_classes!, because that
+// can't express selectors with empty labels like initWithControlPoints::::.
+pub const CLASSES: ClassExports = &[(
+    "CAMediaTimingFunction",
+    ClassTemplate {
+        name: "CAMediaTimingFunction",
+        superclass: Some("NSObject"),
+        class_methods: &[
+            (
+                "allocWithZone:",
+                &(alloc_with_zone as fn(&mut Environment, id, SEL, NSZonePtr) -> id),
+            ),
+            (
+                "functionWithName:",
+                &(function_with_name as fn(&mut Environment, id, SEL, id) -> id),
+            ),
+            (
+                "functionWithControlPoints::::",
+                &(function_with_control_points
+                    as fn(&mut Environment, id, SEL, f32, f32, f32, f32) -> id),
+            ),
+        ],
+        instance_methods: &[
+            (
+                "initWithControlPoints::::",
+                &(init_with_control_points
+                    as fn(&mut Environment, id, SEL, f32, f32, f32, f32) -> id),
+            ),
+            (
+                "getControlPointAtIndex:values:",
+                &(get_control_point_at_index_values
+                    as fn(&mut Environment, id, SEL, NSUInteger, MutPtr<f32>)),
+            ),
+        ],
+    },
+)];
@@ -0,0 +1,203 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CATransaction`.
+//!
+//! Transactions don't batch anything here, since layer changes take effect
+//! immediately anyway. They only scope the parameters used for implicit
+//! animations (see `actionForKey:` in `ca_layer.rs`).
+
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::core_foundation::time::CFTimeInterval;
+use crate::frameworks::foundation::ns_string;
+use crate::objc::{id, msg, msg_class, nil, objc_classes, release, retain, ClassExports};
+use crate::Environment;
+
+pub const kCATransactionAnimationDuration: &str = "animationDuration";
+pub const kCATransactionDisableActions: &str = "disableActions";
+pub const kCATransactionAnimationTimingFunction: &str = "animationTimingFunction";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_kCATransactionAnimationDuration",
+        HostConstant::NSString(kCATransactionAnimationDuration),
+    ),
+    (
+        "_kCATransactionDisableActions",
+        HostConstant::NSString(kCATransactionDisableActions),
+    ),
+    (
+        "_kCATransactionAnimationTimingFunction",
+        HostConstant::NSString(kCATransactionAnimationTimingFunction),
+    ),
+];
+
+#[derive(Default)]
+pub struct State {
+    /// Open transactions, innermost last.
+    stack: Vec<Transaction>,
+    /// Whether the outermost transaction was created implicitly, by changing
+    /// a parameter outside `begin`/`commit`. It's committed at the end of the
+    /// current run loop iteration.
+    implicit: bool,
+}
+
+struct Transaction {
+    animation_duration: CFTimeInterval,
+    /// `CAMediaTimingFunction*`, strong reference. May be [nil].
+    animation_timing_function: id,
+    disable_actions: bool,
+}
+
+fn state(env: &mut Environment) -> &mut State {
+    &mut env.framework_state.core_animation.ca_transaction
+}
+
+fn begin(env: &mut Environment) {
+    // Nested transactions inherit the parameters of the enclosing one.
+    let new = match state(env).stack.last() {
+        Some(outer) => Transaction {
+            animation_duration: outer.animation_duration,
+            animation_timing_function: outer.animation_timing_function,
+            disable_actions: outer.disable_actions,
+        },
+        None => Transaction {
+            animation_duration: 0.25,
+            animation_timing_function: nil,
+            disable_actions: false,
+        },
+    };
+    retain(env, new.animation_timing_function);
+    state(env).stack.push(new);
+}
+
+fn commit(env: &mut Environment) {
+    let Some(transaction) = state(env).stack.pop() else {
+        log!("Warning: [CATransaction commit] without matching begin, ignoring");
+        return;
+    };
+    if state(env).stack.is_empty() {
+        state(env).implicit = false;
+    }
+    release(env, transaction.animation_timing_function);
+}
+
+/// Get the innermost transaction, creating an implicit one if there is none.
+fn current(env: &mut Environment) -> &mut Transaction {
+    if state(env).stack.is_empty() {
+        begin(env);
+        state(env).implicit = true;
+    }
+    state(env).stack.last_mut().unwrap()
+}
+
+/// For use by the animation engine: commit the implicit transaction, if any.
+/// This should happen at the end of each run loop iteration.
+///
+/// Apps can leave explicit transactions open across run loop iterations, in
+/// which case they stay on the stack and only the implicit one is committed.
+pub fn commit_implicit_transaction(env: &mut Environment) {
+    if !state(env).implicit {
+        return;
+    }
+    let open = state(env).stack.len() - 1;
+    if open != 0 {
+        log!(
+            "Warning: {} explicit CATransaction(s) still open at end of run loop iteration",
+            open
+        );
+    }
+    let transaction = state(env).stack.remove(0);
+    state(env).implicit = false;
+    release(env, transaction.animation_timing_function);
+}
+
+/// Parameters for implicit animations: duration, timing function and whether
+/// they are disabled.
+pub fn implicit_animation_parameters(env: &mut Environment) -> (CFTimeInterval, id, bool) {
+    match state(env).stack.last() {
+        Some(transaction) => (
+            transaction.animation_duration,
+            transaction.animation_timing_function,
+            transaction.disable_actions,
+        ),
+        None => (0.25, nil, false),
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation CATransaction: NSObject
+
++ (())begin {
+    begin(env);
+}
++ (())commit {
+    commit(env);
+}
++ (())flush {
+    // Nothing is batched, so there's nothing to flush.
+}
+
++ (CFTimeInterval)animationDuration {
+    implicit_animation_parameters(env).0
+}
++ (())setAnimationDuration:(CFTimeInterval)duration {
+    current(env).animation_duration = duration;
+}
+
++ (id)animationTimingFunction {
+    implicit_animation_parameters(env).1
+}
++ (())setAnimationTimingFunction:(id)function { // CAMediaTimingFunction*
+    retain(env, function);
+    let old = std::mem::replace(&mut current(env).animation_timing_function, function);
+    release(env, old);
+}
+
++ (bool)disableActions {
+    implicit_animation_parameters(env).2
+}
++ (())setDisableActions:(bool)disable {
+    current(env).disable_actions = disable;
+}
+
++ (id)valueForKey:(id)key { // NSString*
+    let key_str = ns_string::to_rust_string(env, key);
+    let (duration, function, disable) = implicit_animation_parameters(env);
+    match &*key_str {
+        kCATransactionAnimationDuration => msg_class![env; NSNumber numberWithDouble:duration],
+        kCATransactionAnimationTimingFunction => function,
+        kCATransactionDisableActions => msg_class![env; NSNumber numberWithBool:disable],
+        _ => {
+            log!("TODO: [CATransaction valueForKey:{:?}]", key_str);
+            nil
+        }
+    }
+}
++ (())setValue:(id)value
+        forKey:(id)key { // NSString*
+    let key_str = ns_string::to_rust_string(env, key);
+    match &*key_str {
+        kCATransactionAnimationDuration => {
+            let duration: CFTimeInterval = msg![env; value doubleValue];
+            current(env).animation_duration = duration;
+        }
+        kCATransactionAnimationTimingFunction => {
+            () = msg![env; this setAnimationTimingFunction:value];
+        }
+        kCATransactionDisableActions => {
+            let disable: bool = msg![env; value boolValue];
+            current(env).disable_actions = disable;
+        }
+        _ => log!("TODO: [CATransaction setValue:{:?} forKey:{:?}]", value, key_str),
+    }
+}
+
+@end
+
+};
@@ -0,0 +1,413 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CATransform3D.h`
+
+use crate::abi::{impl_GuestRet_for_large_struct, GuestArg};
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
+use crate::frameworks::core_graphics::CGFloat;
+use crate::mem::SafeRead;
+use crate::Environment;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C, packed)]
+/// 4-by-4 matrix type. Points are treated as row vectors, so the translation
+/// is in `m41`, `m42` and `m43`. This is the same memory layout OpenGL ES uses
+/// for its column-major matrices with column vectors.
+pub struct CATransform3D {
+    pub m11: CGFloat,
+    pub m12: CGFloat,
+    pub m13: CGFloat,
+    pub m14: CGFloat,
+    pub m21: CGFloat,
+    pub m22: CGFloat,
+    pub m23: CGFloat,
+    pub m24: CGFloat,
+    pub m31: CGFloat,
+    pub m32: CGFloat,
+    pub m33: CGFloat,
+    pub m34: CGFloat,
+    pub m41: CGFloat,
+    pub m42: CGFloat,
+    pub m43: CGFloat,
+    pub m44: CGFloat,
+}
+unsafe impl SafeRead for CATransform3D {}
+impl GuestArg for CATransform3D {
+    const REG_COUNT: usize = 16;
+
+    fn from_regs(regs: &[u32]) -> Self {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                let reg = i * 4 + j;
+                *value = GuestArg::from_regs(&regs[reg..reg + 1]);
+            }
+        }
+        CATransform3D::from_rows(rows)
+    }
+    fn to_regs(self, regs: &mut [u32]) {
+        for (i, row) in self.rows().into_iter().enumerate() {
+            for (j, value) in row.into_iter().enumerate() {
+                let reg = i * 4 + j;
+                value.to_regs(&mut regs[reg..reg + 1]);
+            }
+        }
+    }
+}
+impl_GuestRet_for_large_struct!(CATransform3D);
+
+#[rustfmt::skip]
+pub const CATransform3DIdentity: CATransform3D = CATransform3D {
+    m11: 1.0, m12: 0.0, m13: 0.0, m14: 0.0,
+    m21: 0.0, m22: 1.0, m23: 0.0, m24: 0.0,
+    m31: 0.0, m32: 0.0, m33: 1.0, m34: 0.0,
+    m41: 0.0, m42: 0.0, m43: 0.0, m44: 1.0,
+};
+
+pub const CONSTANTS: ConstantExports = &[(
+    "_CATransform3DIdentity",
+    HostConstant::Custom(|mem| {
+        mem.alloc_and_write(CATransform3DIdentity)
+            .cast()
+            .cast_const()
+    }),
+)];
+
+// As with CGAffineTransform, the CATransform3D* functions are wrappers around
+// these methods, so the compositor and other host code can use them directly.
+impl CATransform3D {
+    pub fn from_rows(rows: [[CGFloat; 4]; 4]) -> Self {
+        let [[m11, m12, m13, m14], [m21, m22, m23, m24], [m31, m32, m33, m34], [m41, m42, m43, m44]] =
+            rows;
+        CATransform3D {
+            m11,
+            m12,
+            m13,
+            m14,
+            m21,
+            m22,
+            m23,
+            m24,
+            m31,
+            m32,
+            m33,
+            m34,
+            m41,
+            m42,
+            m43,
+            m44,
+        }
+    }
+    pub fn rows(self) -> [[CGFloat; 4]; 4] {
+        let CATransform3D {
+            m11,
+            m12,
+            m13,
+            m14,
+            m21,
+            m22,
+            m23,
+            m24,
+            m31,
+            m32,
+            m33,
+            m34,
+            m41,
+            m42,
+            m43,
+            m44,
+        } = self;
+        [
+            [m11, m12, m13, m14],
+            [m21, m22, m23, m24],
+            [m31, m32, m33, m34],
+            [m41, m42, m43, m44],
+        ]
+    }
+
+    pub fn is_identity(self) -> bool {
+        self == CATransform3DIdentity
+    }
+    pub fn is_affine(self) -> bool {
+        let CATransform3D {
+            m13,
+            m14,
+            m23,
+            m24,
+            m31,
+            m32,
+            m33,
+            m34,
+            m43,
+            m44,
+            ..
+        } = self;
+        [m13, m14, m23, m24, m31, m32, m34, m43] == [0.0; 8] && m33 == 1.0 && m44 == 1.0
+    }
+    pub fn from_affine(transform: CGAffineTransform) -> Self {
+        let CGAffineTransform { a, b, c, d, tx, ty } = transform;
+        CATransform3D {
+            m11: a,
+            m12: b,
+            m21: c,
+            m22: d,
+            m41: tx,
+            m42: ty,
+            ..CATransform3DIdentity
+        }
+    }
+    /// Get the 2D affine part of the transform. This is only meaningful if
+    /// [Self::is_affine] is true, otherwise the other components are lost.
+    pub fn to_affine(self) -> CGAffineTransform {
+        CGAffineTransform {
+            a: self.m11,
+            b: self.m12,
+            c: self.m21,
+            d: self.m22,
+            tx: self.m41,
+            ty: self.m42,
+        }
+    }
+
+    pub fn make_translation(x: CGFloat, y: CGFloat, z: CGFloat) -> Self {
+        CATransform3D {
+            m41: x,
+            m42: y,
+            m43: z,
+            ..CATransform3DIdentity
+        }
+    }
+    pub fn make_scale(x: CGFloat, y: CGFloat, z: CGFloat) -> Self {
+        CATransform3D {
+            m11: x,
+            m22: y,
+            m33: z,
+            ..CATransform3DIdentity
+        }
+    }
+    /// Rotation by `angle` radians around the vector `(x, y, z)`. A rotation
+    /// around `(0, 0, 1)` matches `CGAffineTransformMakeRotation`.
+    pub fn make_rotation(angle: CGFloat, x: CGFloat, y: CGFloat, z: CGFloat) -> Self {
+        let length = (x * x + y * y + z * z).sqrt();
+        if length == 0.0 {
+            return CATransform3DIdentity;
+        }
+        let (x, y, z) = (x / length, y / length, z / length);
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+        CATransform3D::from_rows([
+            [t * x * x + c, t * x * y + s * z, t * x * z - s * y, 0.0],
+            [t * x * y - s * z, t * y * y + c, t * y * z + s * x, 0.0],
+            [t * x * z + s * y, t * y * z - s * x, t * z * z + c, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    /// The result applies `self` first and `other` second.
+    pub fn concat(self, other: Self) -> Self {
+        let a = self.rows();
+        let b = other.rows();
+        let mut res = [[0.0; 4]; 4];
+        for (i, row) in res.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        CATransform3D::from_rows(res)
+    }
+    pub fn translate(self, x: CGFloat, y: CGFloat, z: CGFloat) -> Self {
+        Self::make_translation(x, y, z).concat(self)
+    }
+    pub fn scale(self, x: CGFloat, y: CGFloat, z: CGFloat) -> Self {
+        Self::make_scale(x, y, z).concat(self)
+    }
+    pub fn rotate(self, angle: CGFloat, x: CGFloat, y: CGFloat, z: CGFloat) -> Self {
+        Self::make_rotation(angle, x, y, z).concat(self)
+    }
+    /// Returns the original matrix if it is not invertible, like the real
+    /// `CATransform3DInvert`.
+    pub fn invert(self) -> Self {
+        // Gauss-Jordan elimination with partial pivoting.
+        let mut m = self.rows();
+        let mut inv = CATransform3DIdentity.rows();
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))
+                .unwrap();
+            if m[pivot][col] == 0.0 {
+                return self;
+            }
+            m.swap(col, pivot);
+            inv.swap(col, pivot);
+            let divisor = m[col][col];
+            #[allow(clippy::needless_range_loop)]
+            for j in 0..4 {
+                m[col][j] /= divisor;
+                inv[col][j] /= divisor;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = m[row][col];
+                #[allow(clippy::needless_range_loop)]
+                for j in 0..4 {
+                    m[row][j] -= factor * m[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+        CATransform3D::from_rows(inv)
+    }
+
+    /// Transform a point in homogeneous co-ordinates.
+    pub fn apply_to_vector(self, vector: [CGFloat; 4]) -> [CGFloat; 4] {
+        let rows = self.rows();
+        let mut res = [0.0; 4];
+        for (j, value) in res.iter_mut().enumerate() {
+            *value = (0..4).map(|k| vector[k] * rows[k][j]).sum();
+        }
+        res
+    }
+}
+
+fn CATransform3DIsIdentity(_env: &mut Environment, t: CATransform3D) -> bool {
+    t.is_identity()
+}
+fn CATransform3DEqualToTransform(
+    _env: &mut Environment,
+    a: CATransform3D,
+    b: CATransform3D,
+) -> bool {
+    a == b
+}
+
+fn CATransform3DMakeTranslation(
+    _env: &mut Environment,
+    x: CGFloat,
+    y: CGFloat,
+    z: CGFloat,
+) -> CATransform3D {
+    CATransform3D::make_translation(x, y, z)
+}
+fn CATransform3DMakeScale(
+    _env: &mut Environment,
+    x: CGFloat,
+    y: CGFloat,
+    z: CGFloat,
+) -> CATransform3D {
+    CATransform3D::make_scale(x, y, z)
+}
+fn CATransform3DMakeRotation(
+    _env: &mut Environment,
+    angle: CGFloat,
+    x: CGFloat,
+    y: CGFloat,
+    z: CGFloat,
+) -> CATransform3D {
+    CATransform3D::make_rotation(angle, x, y, z)
+}
+
+fn CATransform3DTranslate(
+    _env: &mut Environment,
+    t: CATransform3D,
+    x: CGFloat,
+    y: CGFloat,
+    z: CGFloat,
+) -> CATransform3D {
+    t.translate(x, y, z)
+}
+fn CATransform3DScale(
+    _env: &mut Environment,
+    t: CATransform3D,
+    x: CGFloat,
+    y: CGFloat,
+    z: CGFloat,
+) -> CATransform3D {
+    t.scale(x, y, z)
+}
+fn CATransform3DRotate(
+    _env: &mut Environment,
+    t: CATransform3D,
+    angle: CGFloat,
+    x: CGFloat,
+    y: CGFloat,
+    z: CGFloat,
+) -> CATransform3D {
+    t.rotate(angle, x, y, z)
+}
+fn CATransform3DConcat(
+    _env: &mut Environment,
+    a: CATransform3D,
+    b: CATransform3D,
+) -> CATransform3D {
+    a.concat(b)
+}
+fn CATransform3DInvert(_env: &mut Environment, t: CATransform3D) -> CATransform3D {
+    t.invert()
+}
+
+fn CATransform3DMakeAffineTransform(_env: &mut Environment, m: CGAffineTransform) -> CATransform3D {
+    CATransform3D::from_affine(m)
+}
+fn CATransform3DIsAffine(_env: &mut Environment, t: CATransform3D) -> bool {
+    t.is_affine()
+}
+fn CATransform3DGetAffineTransform(_env: &mut Environment, t: CATransform3D) -> CGAffineTransform {
+    t.to_affine()
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CATransform3DIsIdentity(_)),
+    export_c_func!(CATransform3DEqualToTransform(_, _)),
+    export_c_func!(CATransform3DMakeTranslation(_, _, _)),
+    export_c_func!(CATransform3DMakeScale(_, _, _)),
+    export_c_func!(CATransform3DMakeRotation(_, _, _, _)),
+    export_c_func!(CATransform3DTranslate(_, _, _, _)),
+    export_c_func!(CATransform3DScale(_, _, _, _)),
+    export_c_func!(CATransform3DRotate(_, _, _, _, _)),
+    export_c_func!(CATransform3DConcat(_, _)),
+    export_c_func!(CATransform3DInvert(_)),
+    export_c_func!(CATransform3DMakeAffineTransform(_)),
+    export_c_func!(CATransform3DIsAffine(_)),
+    export_c_func!(CATransform3DGetAffineTransform(_)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx_eq(a: CATransform3D, b: CATransform3D) -> bool {
+        a.rows()
+            .iter()
+            .flatten()
+            .zip(b.rows().iter().flatten())
+            .all(|(a, b)| (a - b).abs() < 1e-5)
+    }
+
+    #[test]
+    fn rotation_matches_affine() {
+        let angle = 0.7;
+        assert!(approx_eq(
+            CATransform3D::make_rotation(angle, 0.0, 0.0, 1.0),
+            CATransform3D::from_affine(CGAffineTransform::make_rotation(angle))
+        ));
+    }
+
+    #[test]
+    fn concat_and_invert() {
+        let t = CATransform3D::make_translation(10.0, 20.0, 0.0)
+            .scale(2.0, 3.0, 1.0)
+            .rotate(0.5, 1.0, 0.0, 0.0);
+        assert!(approx_eq(t.concat(t.invert()), CATransform3DIdentity));
+        // Translate applies the translation first, then the existing transform.
+        let t = CATransform3D::make_scale(2.0, 2.0, 1.0).translate(1.0, 1.0, 0.0);
+        assert_eq!(
+            t.apply_to_vector([0.0, 0.0, 0.0, 1.0]),
+            [2.0, 2.0, 0.0, 1.0]
+        );
+    }
+}
@@ -9,12 +9,16 @@
 //! I haven't attempted to reverse-engineer the details. As such, it probably
 //! diverges wildly from what the real iPhone OS does.
 
+use super::ca_animation::update_animations;
 use super::ca_eagl_layer::find_fullscreen_eagl_layer;
-use super::ca_layer::CALayerHostObject;
+use super::ca_layer::{CALayerHostObject, PresentationValues};
+use super::ca_transform_3d::{CATransform3D, CATransform3DIdentity};
 use crate::frameworks::core_graphics::{
     cg_bitmap_context, cg_image, CGFloat, CGPoint, CGRect, CGSize,
 };
 use crate::frameworks::uikit::ui_color;
+use crate::frameworks::uikit::ui_view::animation::advance_animations;
+use crate::frameworks::uikit::ui_view::ui_status_bar::status_bar_layer;
 use crate::gles::gles11_raw as gles11; // constants only
 use crate::gles::gles11_raw::types::*;
 use crate::gles::present::{present_frame, FpsCounter};
@@ -37,6 +41,13 @@ pub(super) struct State {
 ///
 /// Returns the time a recomposite is due, if any.
 pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
+    // Animations need to progress even if nothing is being composited, so
+    // that their delegates get called.
+    let animations_due = match (advance_animations(env), update_animations(env)) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
     // Assumes the last window in the list is the one on top.
     // TODO: this is not correct once we support zPosition.
     // TODO: can there be windows smaller than the screen? If so we need to draw
@@ -50,13 +61,13 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
         .last()
     else {
         log_dbg!("No visible window, skipping composition");
-        return None;
+        return animations_due;
     };
 
     if find_fullscreen_eagl_layer(env) != nil {
         // No composition done, EAGLContext will present directly.
         log_dbg!("Using CAEAGLLayer fast path, skipping composition");
-        return None;
+        return animations_due;
     }
 
     if env.options.print_fps {
@@ -105,8 +116,13 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
 
     let root_layer: id = msg![env; top_window layer];
 
+    let status_bar_layer = status_bar_layer(env);
+
     // Ensure layer bitmaps are up to date.
     display_layers(env, root_layer);
+    if let Some(status_bar_layer) = status_bar_layer {
+        display_layers(env, status_bar_layer);
+    }
 
     let screen_bounds: CGRect = {
         let screen: id = msg_class![env; UIScreen mainScreen];
@@ -121,14 +137,18 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
         env.window().virtual_cursor_visible_at(),
     );
 
-    // TODO: draw status bar if it's not hidden
-
     // Initial state for layer tree traversal (see composite_layer_recursive)
-    let origin = CGPoint { x: 0.0, y: 0.0 };
-    let clip_to = CGRect {
-        origin,
-        size: screen_bounds.size,
-    };
+    let parent_matrix = CATransform3DIdentity;
+    let (width, height) = (screen_bounds.size.width, screen_bounds.size.height);
+    let clip_to = [
+        CGPoint { x: 0.0, y: 0.0 },
+        CGPoint { x: width, y: 0.0 },
+        CGPoint {
+            x: width,
+            y: height,
+        },
+        CGPoint { x: 0.0, y: height },
+    ];
     let opacity = 1.0;
 
     let window = env.window.as_mut().unwrap();
@@ -203,9 +223,13 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
         gles.Viewport(0, 0, fb_width as _, fb_height as _);
         gles.ClearColor(0.0, 0.0, 0.0, 1.0);
         gles.Clear(gles11::COLOR_BUFFER_BIT);
-        gles.Enable(gles11::SCISSOR_TEST);
-        gles.Scissor(0, 0, fb_width as _, fb_height as _);
         gles.Color4f(1.0, 1.0, 1.0, 1.0);
+
+        gles.MatrixMode(gles11::PROJECTION);
+        load_matrix(gles, screen_projection(screen_bounds.size));
+        gles.MatrixMode(gles11::MODELVIEW);
+        gles.BindBuffer(gles11::ARRAY_BUFFER, 0);
+        gles.EnableClientState(gles11::VERTEX_ARRAY);
     }
 
     // Here's where the actual drawing happens
@@ -215,20 +239,33 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
             &mut env.objc,
             &env.mem,
             root_layer,
-            origin,
-            clip_to,
+            parent_matrix,
+            &clip_to,
             opacity,
-            scale_hack,
-            fb_height,
         );
+        // The status bar is drawn over the app.
+        if let Some(status_bar_layer) = status_bar_layer {
+            composite_layer_recursive(
+                gles,
+                &mut env.objc,
+                &env.mem,
+                status_bar_layer,
+                parent_matrix,
+                &clip_to,
+                opacity,
+            );
+        }
     }
 
     // Clean up some GL state
     unsafe {
         gles.Viewport(0, 0, fb_width as _, fb_height as _);
-        gles.Disable(gles11::SCISSOR_TEST);
         gles.Color4f(1.0, 1.0, 1.0, 1.0);
         gles.Disable(gles11::BLEND);
+        gles.LoadIdentity();
+        gles.MatrixMode(gles11::PROJECTION);
+        gles.LoadIdentity();
+        gles.MatrixMode(gles11::MODELVIEW);
         assert_eq!(gles.GetError(), 0);
     }
 
@@ -249,6 +286,140 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
     new_recomposite_next
 }
 
+/// A copy of the status bar's appearance, for drawing it over frames that
+/// `EAGLContext` presents directly, bypassing the compositor (see
+/// [find_fullscreen_eagl_layer]). This has to be prepared before the app's
+/// OpenGL ES context is made current for presenting, because drawing the
+/// status bar's layer sends messages.
+pub struct StatusBarOverlay {
+    /// RGBA pixels with premultiplied alpha, in bottom-to-top row order.
+    pixels: Vec<u8>,
+    dimensions: (u32, u32),
+    bounds: CGRect,
+    /// Maps the layer's bounds co-ordinate space to screen points.
+    matrix: CATransform3D,
+    opaque: bool,
+    screen_size: CGSize,
+}
+
+/// For use by `EAGLContext`: get a [StatusBarOverlay], or [None] if the status
+/// bar shouldn't be drawn.
+pub fn status_bar_overlay(env: &mut Environment) -> Option<StatusBarOverlay> {
+    let layer = status_bar_layer(env)?;
+    display_layers(env, layer);
+
+    let screen_size = {
+        let screen: id = msg_class![env; UIScreen mainScreen];
+        let bounds: CGRect = msg![env; screen bounds];
+        bounds.size
+    };
+
+    let host_obj = env.objc.borrow::<CALayerHostObject>(layer);
+    let cg_context = host_obj.cg_context?;
+    let PresentationValues {
+        bounds,
+        position,
+        anchor_point,
+        transform,
+        ..
+    } = host_obj.presentation_values();
+    let anchor = CGPoint {
+        x: bounds.origin.x + bounds.size.width * anchor_point.x,
+        y: bounds.origin.y + bounds.size.height * anchor_point.y,
+    };
+    let matrix = CATransform3D::make_translation(-anchor.x, -anchor.y, 0.0)
+        .concat(transform)
+        .concat(CATransform3D::make_translation(position.x, position.y, 0.0));
+    let opaque = host_obj.opaque;
+
+    // Make sure this is in sync with the code in ca_layer.rs that sets up the
+    // context!
+    let (width, height, data) = cg_bitmap_context::get_data(&env.objc, cg_context);
+    let pixels = env.mem.bytes_at(data.cast(), width * height * 4).to_vec();
+
+    Some(StatusBarOverlay {
+        pixels,
+        dimensions: (width, height),
+        bounds,
+        matrix,
+        opaque,
+        screen_size,
+    })
+}
+
+/// For use by `EAGLContext`: draw a [StatusBarOverlay] over a frame that is in
+/// `texture`, which must have the size of the screen (in any units) and be
+/// bound to `GL_TEXTURE_2D`. It is still bound afterwards.
+///
+/// This changes the framebuffer binding to 0, the projection and modelview
+/// matrices to identity, and disables blending. It also changes the viewport,
+/// texturing and the vertex and texture co-ordinate arrays, so the caller must
+/// back these up.
+pub unsafe fn draw_status_bar_overlay(
+    gles: &mut dyn GLES,
+    overlay: &StatusBarOverlay,
+    texture: GLuint,
+    texture_size: (GLsizei, GLsizei),
+) {
+    let mut framebuffer = 0;
+    gles.GenFramebuffersOES(1, &mut framebuffer);
+    gles.BindFramebufferOES(gles11::FRAMEBUFFER_OES, framebuffer);
+    gles.FramebufferTexture2DOES(
+        gles11::FRAMEBUFFER_OES,
+        gles11::COLOR_ATTACHMENT0_OES,
+        gles11::TEXTURE_2D,
+        texture,
+        0,
+    );
+    if gles.CheckFramebufferStatusOES(gles11::FRAMEBUFFER_OES) != gles11::FRAMEBUFFER_COMPLETE_OES {
+        log!("Warning: Can't render to presented frame, status bar will not be drawn");
+        gles.DeleteFramebuffersOES(1, &framebuffer);
+        return;
+    }
+
+    let mut overlay_texture = 0;
+    gles.GenTextures(1, &mut overlay_texture);
+    gles.BindTexture(gles11::TEXTURE_2D, overlay_texture);
+    upload_rgba8_pixels(gles, &overlay.pixels, overlay.dimensions);
+
+    gles.Viewport(0, 0, texture_size.0, texture_size.1);
+    gles.MatrixMode(gles11::PROJECTION);
+    load_matrix(gles, screen_projection(overlay.screen_size));
+    gles.MatrixMode(gles11::MODELVIEW);
+    load_matrix(gles, overlay.matrix);
+    if overlay.opaque {
+        gles.Disable(gles11::BLEND);
+    } else {
+        gles.Enable(gles11::BLEND);
+        gles.BlendFunc(gles11::ONE, gles11::ONE_MINUS_SRC_ALPHA);
+    }
+
+    // Same as for a layer with a CGContextRef in composite_layer_recursive().
+    let bounds = overlay.bounds;
+    let (x1, y1) = (bounds.origin.x, bounds.origin.y);
+    let (x2, y2) = (x1 + bounds.size.width, y1 + bounds.size.height);
+    let vertices: [f32; 12] = [x1, y2, x1, y1, x2, y2, x2, y2, x1, y1, x2, y1];
+    let tex_coords: [f32; 12] = [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+    gles.BindBuffer(gles11::ARRAY_BUFFER, 0);
+    gles.EnableClientState(gles11::VERTEX_ARRAY);
+    gles.VertexPointer(2, gles11::FLOAT, 0, vertices.as_ptr() as *const GLvoid);
+    gles.EnableClientState(gles11::TEXTURE_COORD_ARRAY);
+    gles.TexCoordPointer(2, gles11::FLOAT, 0, tex_coords.as_ptr() as *const GLvoid);
+    gles.Enable(gles11::TEXTURE_2D);
+    gles.DrawArrays(gles11::TRIANGLES, 0, 6);
+
+    gles.Disable(gles11::BLEND);
+    gles.LoadIdentity();
+    gles.MatrixMode(gles11::PROJECTION);
+    gles.LoadIdentity();
+    gles.MatrixMode(gles11::MODELVIEW);
+
+    gles.DeleteTextures(1, &overlay_texture);
+    gles.BindTexture(gles11::TEXTURE_2D, texture);
+    // This also sets the framebuffer binding back to zero.
+    gles.DeleteFramebuffersOES(1, &framebuffer);
+}
+
 /// Call `displayIfNeeded` on all relevant layers in the tree, so their bitmaps
 /// are up to date before compositing.
 fn display_layers(env: &mut Environment, root_layer: id) {
@@ -276,19 +447,21 @@ fn display_layers(env: &mut Environment, root_layer: id) {
 }
 
 /// Traverses the layer tree and draws each layer.
+///
+/// `parent_matrix` maps the superlayer's bounds co-ordinate space (after its
+/// `sublayerTransform` has been applied) to screen points, and `clip_to` is a
+/// convex polygon in screen points.
 unsafe fn composite_layer_recursive(
     gles: &mut dyn GLES,
     objc: &mut ObjC,
     mem: &Mem,
     layer: id,
-    origin: CGPoint,
-    clip_to: CGRect,
+    parent_matrix: CATransform3D,
+    clip_to: &[CGPoint],
     opacity: CGFloat,
-    scale_hack: u32,
-    fb_height: u32,
 ) {
-    // TODO: this can't handle zPosition, non-AABB layer transforms, rounded
-    // corners, and many other things, but none of these are supported yet :)
+    // TODO: this can't handle rounded corners, masks, and many other things,
+    // but none of these are supported yet :)
     // TODO: back-to-front drawing is not efficient, could we use front-to-back?
 
     let host_obj = objc.borrow::<CALayerHostObject>(layer);
@@ -297,34 +470,87 @@ unsafe fn composite_layer_recursive(
         return;
     }
 
-    let opacity = opacity * host_obj.opacity;
-    let bounds = host_obj.bounds;
-    let absolute_frame = {
-        let position = host_obj.position;
-        let anchor_point = host_obj.anchor_point;
-        CGRect {
-            origin: CGPoint {
-                x: origin.x + position.x - bounds.size.width * anchor_point.x,
-                y: origin.y + position.y - bounds.size.height * anchor_point.y,
-            },
-            size: bounds.size,
+    let PresentationValues {
+        bounds,
+        position,
+        anchor_point,
+        transform,
+        opacity: layer_opacity,
+    } = host_obj.presentation_values();
+    let opacity = opacity * layer_opacity;
+
+    // The transform is applied around the anchor point, which is placed at
+    // the layer's position in the superlayer.
+    let anchor = CGPoint {
+        x: bounds.origin.x + bounds.size.width * anchor_point.x,
+        y: bounds.origin.y + bounds.size.height * anchor_point.y,
+    };
+    let matrix = CATransform3D::make_translation(-anchor.x, -anchor.y, 0.0)
+        .concat(transform)
+        .concat(CATransform3D::make_translation(
+            position.x,
+            position.y,
+            host_obj.z_position,
+        ))
+        .concat(parent_matrix);
+
+    // The layer covers its bounds, but only the part of that within the clip
+    // polygon is drawn. Clipping the geometry rather than using the scissor
+    // test means this also works for rotated and 3D-transformed layers.
+    let (x1, y1) = (bounds.origin.x, bounds.origin.y);
+    let (x2, y2) = (x1 + bounds.size.width, y1 + bounds.size.height);
+    let visible = clip_polygon(
+        matrix,
+        &[
+            CGPoint { x: x1, y: y1 },
+            CGPoint { x: x2, y: y1 },
+            CGPoint { x: x2, y: y2 },
+            CGPoint { x: x1, y: y2 },
+        ],
+        clip_to,
+    );
+    let visible = if signed_area(&visible) == 0.0 {
+        Vec::new()
+    } else {
+        visible
+    };
+
+    let sublayer_clip_to = if host_obj.masks_to_bounds {
+        if visible.is_empty() {
+            // Neither this layer nor its sublayers can be visible.
+            return;
         }
+        project_to_screen(matrix, &visible)
+    } else {
+        clip_to.to_vec()
     };
-    let absolute_frame_clipped = clip_rects(clip_to, absolute_frame);
+
+    load_matrix(gles, matrix);
+
+    // The visible part is convex, so it can be drawn as a triangle fan.
+    let vertices: Vec<f32> = visible.iter().flat_map(|p| [p.x, p.y]).collect();
+    let vertex_count = visible.len() as GLsizei;
+    gles.VertexPointer(2, gles11::FLOAT, 0, vertices.as_ptr() as *const GLvoid);
 
     // Draw background color, if any
-    let have_background = if host_obj.background_color == nil {
+    let have_background = if host_obj.background_color == nil || visible.is_empty() {
         false
     } else {
         let (r, g, b, a) = ui_color::get_rgba(objc, host_obj.background_color);
-        // TODO: fully support alpha transparency for backgrounds
-        if a == 0.0 || opacity == 0.0 {
+        let a = a * opacity;
+        if a == 0.0 {
             false
         } else {
-            gles.ClearColor(r * opacity, g * opacity, b * opacity, a * opacity);
-            let (x, y, w, h) = gl_rect_from_cg_rect(absolute_frame_clipped, scale_hack, fb_height);
-            gles.Scissor(x, y, w, h);
-            gles.Clear(gles11::COLOR_BUFFER_BIT);
+            if a == 1.0 {
+                gles.Disable(gles11::BLEND);
+            } else {
+                gles.Enable(gles11::BLEND);
+                gles.BlendFunc(gles11::ONE, gles11::ONE_MINUS_SRC_ALPHA);
+            }
+            gles.Color4f(r * a, g * a, b * a, a);
+            gles.Disable(gles11::TEXTURE_2D);
+            gles.DisableClientState(gles11::TEXTURE_COORD_ARRAY);
+            gles.DrawArrays(gles11::TRIANGLE_FAN, 0, vertex_count);
             true
         }
     };
@@ -396,7 +622,7 @@ unsafe fn composite_layer_recursive(
     }
 
     // Draw texture, if any
-    if need_texture {
+    if need_texture && !visible.is_empty() {
         gles.Color4f(opacity, opacity, opacity, opacity);
         if opacity == 1.0 && host_obj.opaque && !have_background {
             gles.Disable(gles11::BLEND);
@@ -405,51 +631,171 @@ unsafe fn composite_layer_recursive(
             gles.BlendFunc(gles11::ONE, gles11::ONE_MINUS_SRC_ALPHA);
         }
 
-        let (x, y, w, h) = gl_rect_from_cg_rect(absolute_frame_clipped, scale_hack, fb_height);
-        gles.Scissor(x, y, w, h);
-        gles.Viewport(x, y, w, h);
-
-        gles.BindBuffer(gles11::ARRAY_BUFFER, 0);
-        let vertices: [f32; 12] = [
-            -1.0, -1.0, -1.0, 1.0, 1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0,
-        ];
-        gles.EnableClientState(gles11::VERTEX_ARRAY);
-        gles.VertexPointer(2, gles11::FLOAT, 0, vertices.as_ptr() as *const GLvoid);
-
         // Normal images will have top-to-bottom row order, but OpenGL ES
         // expects bottom-to-top, so flip the UVs in that case.
-        let tex_coords: [f32; 12] = if host_obj.contents != nil {
-            [0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0]
-        } else {
-            [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0]
-        };
+        let flip = host_obj.contents == nil;
+        let tex_coords: Vec<f32> = visible
+            .iter()
+            .flat_map(|p| {
+                let u = (p.x - x1) / (x2 - x1);
+                let v = (p.y - y1) / (y2 - y1);
+                [u, if flip { 1.0 - v } else { v }]
+            })
+            .collect();
         gles.EnableClientState(gles11::TEXTURE_COORD_ARRAY);
         gles.TexCoordPointer(2, gles11::FLOAT, 0, tex_coords.as_ptr() as *const GLvoid);
         gles.Enable(gles11::TEXTURE_2D);
-        gles.DrawArrays(gles11::TRIANGLES, 0, 6);
+        gles.DrawArrays(gles11::TRIANGLE_FAN, 0, vertex_count);
     }
 
-    // avoid holding mutable borrow while recursing
-    let sublayers = std::mem::take(&mut host_obj.sublayers);
-    for &child_layer in &sublayers {
+    // Sublayers are positioned relative to the bounds origin, with the
+    // sublayer transform applied around the anchor point. Layers that aren't
+    // CATransformLayers flatten their sublayers into their own plane, which
+    // is what discarding z does.
+    let sublayer_matrix = CATransform3D::make_translation(-anchor.x, -anchor.y, 0.0)
+        .concat(host_obj.sublayer_transform)
+        .concat(CATransform3D::make_translation(anchor.x, anchor.y, 0.0))
+        .concat(CATransform3D::make_scale(1.0, 1.0, 0.0))
+        .concat(matrix);
+
+    // Sublayers are drawn in order of zPosition. The sort is stable, so
+    // sublayers with the same zPosition are still drawn back-to-front.
+    // TODO: Real Core Animation also depth-sorts by transformed z.
+    let mut sublayers = host_obj.sublayers.clone();
+    sublayers.sort_by(|&a, &b| {
+        let a_z = objc.borrow::<CALayerHostObject>(a).z_position;
+        let b_z = objc.borrow::<CALayerHostObject>(b).z_position;
+        a_z.total_cmp(&b_z)
+    });
+    for child_layer in sublayers {
         composite_layer_recursive(
             gles,
             objc,
             mem,
             child_layer,
-            /* origin: */
-            CGPoint {
-                x: absolute_frame.origin.x - bounds.origin.x,
-                y: absolute_frame.origin.y - bounds.origin.y,
-            },
-            // TODO: clipping goes here (when masksToBounds is implemented)
-            clip_to,
+            sublayer_matrix,
+            &sublayer_clip_to,
             opacity,
-            scale_hack,
-            fb_height,
         )
     }
-    objc.borrow_mut::<CALayerHostObject>(layer).sublayers = sublayers;
+}
+
+/// Map screen points to normalized device co-ordinates. Core Animation
+/// flattens everything into the plane of the screen, so z is discarded (and
+/// can't be clipped against).
+fn screen_projection(screen_size: CGSize) -> CATransform3D {
+    CATransform3D::from_rows([
+        [2.0 / screen_size.width, 0.0, 0.0, 0.0],
+        [0.0, -2.0 / screen_size.height, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 0.0],
+        [-1.0, 1.0, 0.0, 1.0],
+    ])
+}
+
+/// Load a [CATransform3D] as the current OpenGL ES matrix.
+unsafe fn load_matrix(gles: &mut dyn GLES, matrix: CATransform3D) {
+    // Core Animation's row vector convention means the matrix has the same
+    // layout as OpenGL ES's column-major matrices for column vectors.
+    let rows = matrix.rows();
+    gles.LoadMatrixf(rows.as_ptr() as *const GLfloat);
+}
+
+/// Clip a convex polygon in a layer's bounds co-ordinate space to the part
+/// that is within `clip_to`, a convex polygon in screen points. `matrix` maps
+/// the layer's bounds co-ordinate space to screen points.
+///
+/// The clipping is done in homogeneous co-ordinates, so it works for
+/// perspective transforms and for layers that are partly behind the viewer.
+/// The result is in the layer's bounds co-ordinate space, so OpenGL ES can
+/// still do perspective-correct texturing when drawing it.
+fn clip_polygon(matrix: CATransform3D, polygon: &[CGPoint], clip_to: &[CGPoint]) -> Vec<CGPoint> {
+    let orientation = signed_area(clip_to).signum();
+    if orientation == 0.0 {
+        return Vec::new();
+    }
+
+    // Each half-plane is (a, b, c, d), containing the homogeneous points where
+    // a*x + b*y + c*w + d >= 0. Points with w <= 0 are behind the viewer or at
+    // infinity, so the first half-plane excludes them.
+    const MIN_W: CGFloat = 1e-5;
+    let mut half_planes = vec![[0.0, 0.0, 1.0, -MIN_W]];
+    for (i, &p) in clip_to.iter().enumerate() {
+        let q = clip_to[(i + 1) % clip_to.len()];
+        // The cross product of the edge and the point, multiplied by w.
+        let (dx, dy) = (q.x - p.x, q.y - p.y);
+        half_planes.push([
+            -orientation * dy,
+            orientation * dx,
+            orientation * (dy * p.x - dx * p.y),
+            0.0,
+        ]);
+    }
+
+    // Each vertex is paired with its homogeneous screen co-ordinates. Both
+    // are affine in the layer's plane, so they can be interpolated together.
+    let mut vertices: Vec<(CGPoint, [CGFloat; 3])> = polygon
+        .iter()
+        .map(|&point| {
+            let [x, y, _, w] = matrix.apply_to_vector([point.x, point.y, 0.0, 1.0]);
+            (point, [x, y, w])
+        })
+        .collect();
+    for [a, b, c, d] in half_planes {
+        if vertices.is_empty() {
+            break;
+        }
+        let distance = |(_, [x, y, w]): (CGPoint, [CGFloat; 3])| a * x + b * y + c * w + d;
+        let mut clipped = Vec::with_capacity(vertices.len() + 1);
+        for (i, &start) in vertices.iter().enumerate() {
+            let end = vertices[(i + 1) % vertices.len()];
+            let (start_distance, end_distance) = (distance(start), distance(end));
+            if start_distance >= 0.0 {
+                clipped.push(start);
+            }
+            if (start_distance >= 0.0) != (end_distance >= 0.0) {
+                let t = start_distance / (start_distance - end_distance);
+                let lerp = |a: CGFloat, b: CGFloat| a + (b - a) * t;
+                let ((start_point, start_h), (end_point, end_h)) = (start, end);
+                clipped.push((
+                    CGPoint {
+                        x: lerp(start_point.x, end_point.x),
+                        y: lerp(start_point.y, end_point.y),
+                    },
+                    [
+                        lerp(start_h[0], end_h[0]),
+                        lerp(start_h[1], end_h[1]),
+                        lerp(start_h[2], end_h[2]),
+                    ],
+                ));
+            }
+        }
+        vertices = clipped;
+    }
+    if vertices.len() < 3 {
+        return Vec::new();
+    }
+    vertices.into_iter().map(|(point, _)| point).collect()
+}
+
+/// Map a polygon from [clip_polygon] to screen points.
+fn project_to_screen(matrix: CATransform3D, polygon: &[CGPoint]) -> Vec<CGPoint> {
+    polygon
+        .iter()
+        .map(|&point| {
+            let [x, y, _, w] = matrix.apply_to_vector([point.x, point.y, 0.0, 1.0]);
+            CGPoint { x: x / w, y: y / w }
+        })
+        .collect()
+}
+
+/// Twice the signed area of a polygon. The sign gives its winding order.
+fn signed_area(polygon: &[CGPoint]) -> CGFloat {
+    let mut area = 0.0;
+    for (i, &p) in polygon.iter().enumerate() {
+        let q = polygon[(i + 1) % polygon.len()];
+        area += p.x * q.y - q.x * p.y;
+    }
+    area
 }
 
 unsafe fn upload_rgba8_pixels(gles: &mut dyn GLES, pixels: &[u8], dimensions: (u32, u32)) {
@@ -475,40 +821,3 @@ unsafe fn upload_rgba8_pixels(gles: &mut dyn GLES, pixels: &[u8], dimensions: (u
         gles11::LINEAR as _,
     );
 }
-
-fn clip_rects(a_clip: CGRect, b_clip: CGRect) -> CGRect {
-    let a_x1 = a_clip.origin.x;
-    let a_y1 = a_clip.origin.y;
-    let a_x2 = a_x1 + a_clip.size.width;
-    let a_y2 = a_y1 + a_clip.size.height;
-
-    let b_x1 = b_clip.origin.x;
-    let b_y1 = b_clip.origin.y;
-    let b_x2 = b_x1 + b_clip.size.width;
-    let b_y2 = b_y1 + b_clip.size.height;
-
-    let x1 = b_x1.max(a_x1);
-    let y1 = b_y1.max(a_y1);
-    let x2 = b_x2.min(a_x2);
-    let y2 = b_y2.min(a_y2);
-    CGRect {
-        origin: CGPoint { x: x1, y: y1 },
-        size: CGSize {
-            width: (x2 - x1).max(0.0),
-            height: (y2 - y1).max(0.0),
-        },
-    }
-}
-
-fn gl_rect_from_cg_rect(
-    rect: CGRect,
-    scale_hack: u32,
-    fb_height: u32,
-) -> (GLint, GLint, GLint, GLint) {
-    let x = (rect.origin.x * scale_hack as f32).round() as GLint;
-    let y = (rect.origin.y * scale_hack as f32).round() as GLint;
-    let w = (rect.size.width * scale_hack as f32).round() as GLint;
-    let h = (rect.size.height * scale_hack as f32).round() as GLint;
-    // y points up in OpenGL ES, but down in UIKit and Core Animation
-    (x, fb_height as GLint - h - y, w, h)
-}
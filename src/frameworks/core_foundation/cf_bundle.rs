@@ -83,7 +83,8 @@ pub fn CFBundleCopyBundleLocalizations(env: &mut Environment, bundle: CFBundleRe
         .unwrap_or(&env.bundle)
         .bundle_localizations()
         .iter()
-        .map(|value| value.as_string().unwrap().to_string())
+        .filter_map(|value| value.as_string())
+        .map(str::to_owned)
         .collect::<Vec<String>>();
     let guest_bundle_localizations = bundle_localizations
         .iter()
@@ -138,6 +139,20 @@ pub fn CFBundleCopyPreferredLocalizationsFromArray(
     result
 }
 
+/// This is also what the `CFCopyLocalizedString` family of macros expand to.
+fn CFBundleCopyLocalizedString(
+    env: &mut Environment,
+    bundle: CFBundleRef,
+    key: CFStringRef,
+    value: CFStringRef,
+    table_name: CFStringRef,
+) -> CFStringRef {
+    let string: CFStringRef = msg![env; bundle localizedStringForKey:key
+                                                              value:value
+                                                              table:table_name];
+    retain(env, string)
+}
+
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CFBundleGetMainBundle()),
     export_c_func!(CFBundleGetVersionNumber(_)),
@@ -146,4 +161,5 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CFBundleCopyResourceURL(_, _, _, _)),
     export_c_func!(CFBundleCopyBundleLocalizations(_)),
     export_c_func!(CFBundleCopyPreferredLocalizationsFromArray(_)),
+    export_c_func!(CFBundleCopyLocalizedString(_, _, _, _)),
 ];
@@ -15,6 +15,7 @@ pub mod cg_context;
 pub mod cg_data_provider;
 pub mod cg_geometry;
 pub mod cg_image;
+pub mod cg_path;
 
 pub type CGFloat = f32;
 
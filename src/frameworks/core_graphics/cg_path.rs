@@ -0,0 +1,233 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CGPath.h`
+//!
+//! Paths can currently only be built up and followed by animations, they
+//! can't be drawn.
+
+use super::cg_affine_transform::CGAffineTransform;
+use super::{CGFloat, CGPoint};
+use crate::dyld::FunctionExports;
+use crate::export_c_func;
+use crate::frameworks::core_foundation::{CFRelease, CFRetain, CFTypeRef};
+use crate::mem::ConstPtr;
+use crate::objc::{objc_classes, ClassExports, HostObject, ObjC};
+use crate::Environment;
+
+pub type CGPathRef = CFTypeRef;
+pub type CGMutablePathRef = CFTypeRef;
+
+#[derive(Copy, Clone, Debug)]
+enum PathElement {
+    MoveToPoint(CGPoint),
+    AddLineToPoint(CGPoint),
+    AddQuadCurveToPoint(CGPoint, CGPoint),
+    AddCurveToPoint(CGPoint, CGPoint, CGPoint),
+    CloseSubpath,
+}
+
+struct CGPathHostObject {
+    elements: Vec<PathElement>,
+}
+impl HostObject for CGPathHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// CGPath is a CFType-based type, but in our implementation those are just
+// Objective-C types, so we need a class for it, but its name is not visible
+// anywhere.
+@implementation _touchHLE_CGPath: NSObject
+@end
+
+};
+
+/// A part of a path between two points, as followed by an animation.
+#[derive(Copy, Clone, Debug)]
+pub enum PathSegment {
+    Line(CGPoint, CGPoint),
+    QuadCurve(CGPoint, CGPoint, CGPoint),
+    Curve(CGPoint, CGPoint, CGPoint, CGPoint),
+}
+impl PathSegment {
+    /// Get the point a fraction `t` (0 to 1) of the way along the segment.
+    pub fn point_at(self, t: CGFloat) -> CGPoint {
+        let u = 1.0 - t;
+        let weighted_sum = |terms: &[(CGPoint, CGFloat)]| {
+            let mut point = CGPoint { x: 0.0, y: 0.0 };
+            for &(p, weight) in terms {
+                point.x += p.x * weight;
+                point.y += p.y * weight;
+            }
+            point
+        };
+        match self {
+            PathSegment::Line(a, b) => weighted_sum(&[(a, u), (b, t)]),
+            PathSegment::QuadCurve(a, b, c) => {
+                weighted_sum(&[(a, u * u), (b, 2.0 * u * t), (c, t * t)])
+            }
+            PathSegment::Curve(a, b, c, d) => weighted_sum(&[
+                (a, u * u * u),
+                (b, 3.0 * u * u * t),
+                (c, 3.0 * u * t * t),
+                (d, t * t * t),
+            ]),
+        }
+    }
+}
+
+/// Get the segments of a path, in order. Moving to a new point doesn't
+/// create a segment.
+pub fn segments(objc: &ObjC, path: CGPathRef) -> Vec<PathSegment> {
+    let mut segments = Vec::new();
+    let mut current = CGPoint { x: 0.0, y: 0.0 };
+    let mut subpath_start = current;
+    for &element in &objc.borrow::<CGPathHostObject>(path).elements {
+        let segment = match element {
+            PathElement::MoveToPoint(p) => {
+                current = p;
+                subpath_start = p;
+                continue;
+            }
+            PathElement::AddLineToPoint(p) => PathSegment::Line(current, p),
+            PathElement::AddQuadCurveToPoint(cp, p) => PathSegment::QuadCurve(current, cp, p),
+            PathElement::AddCurveToPoint(cp1, cp2, p) => PathSegment::Curve(current, cp1, cp2, p),
+            PathElement::CloseSubpath => PathSegment::Line(current, subpath_start),
+        };
+        current = match segment {
+            PathSegment::Line(_, p) | PathSegment::QuadCurve(_, _, p) => p,
+            PathSegment::Curve(_, _, _, p) => p,
+        };
+        segments.push(segment);
+    }
+    segments
+}
+
+fn CGPathCreateMutable(env: &mut Environment) -> CGMutablePathRef {
+    let class = env.objc.get_known_class("_touchHLE_CGPath", &mut env.mem);
+    env.objc.alloc_object(
+        class,
+        Box::new(CGPathHostObject {
+            elements: Vec::new(),
+        }),
+        &mut env.mem,
+    )
+}
+
+pub fn CGPathRetain(env: &mut Environment, path: CGPathRef) -> CGPathRef {
+    if !path.is_null() {
+        CFRetain(env, path)
+    } else {
+        path
+    }
+}
+pub fn CGPathRelease(env: &mut Environment, path: CGPathRef) {
+    if !path.is_null() {
+        CFRelease(env, path);
+    }
+}
+
+fn CGPathIsEmpty(env: &mut Environment, path: CGPathRef) -> bool {
+    path.is_null()
+        || env
+            .objc
+            .borrow::<CGPathHostObject>(path)
+            .elements
+            .is_empty()
+}
+
+/// Apply the transform the functions for adding to a path take, which may be
+/// `NULL`.
+fn transform_point(
+    env: &Environment,
+    m: ConstPtr<CGAffineTransform>,
+    x: CGFloat,
+    y: CGFloat,
+) -> CGPoint {
+    let point = CGPoint { x, y };
+    if m.is_null() {
+        point
+    } else {
+        env.mem.read(m).apply_to_point(point)
+    }
+}
+
+fn add_element(env: &mut Environment, path: CGMutablePathRef, element: PathElement) {
+    env.objc
+        .borrow_mut::<CGPathHostObject>(path)
+        .elements
+        .push(element);
+}
+
+fn CGPathMoveToPoint(
+    env: &mut Environment,
+    path: CGMutablePathRef,
+    m: ConstPtr<CGAffineTransform>,
+    x: CGFloat,
+    y: CGFloat,
+) {
+    let p = transform_point(env, m, x, y);
+    add_element(env, path, PathElement::MoveToPoint(p));
+}
+
+fn CGPathAddLineToPoint(
+    env: &mut Environment,
+    path: CGMutablePathRef,
+    m: ConstPtr<CGAffineTransform>,
+    x: CGFloat,
+    y: CGFloat,
+) {
+    let p = transform_point(env, m, x, y);
+    add_element(env, path, PathElement::AddLineToPoint(p));
+}
+
+fn CGPathAddQuadCurveToPoint(
+    env: &mut Environment,
+    path: CGMutablePathRef,
+    m: ConstPtr<CGAffineTransform>,
+    cpx: CGFloat,
+    cpy: CGFloat,
+    x: CGFloat,
+    y: CGFloat,
+) {
+    let cp = transform_point(env, m, cpx, cpy);
+    let p = transform_point(env, m, x, y);
+    add_element(env, path, PathElement::AddQuadCurveToPoint(cp, p));
+}
+
+fn CGPathAddCurveToPoint(
+    env: &mut Environment,
+    path: CGMutablePathRef,
+    m: ConstPtr<CGAffineTransform>,
+    cp1x: CGFloat,
+    cp1y: CGFloat,
+    cp2x: CGFloat,
+    cp2y: CGFloat,
+    x: CGFloat,
+    y: CGFloat,
+) {
+    let cp1 = transform_point(env, m, cp1x, cp1y);
+    let cp2 = transform_point(env, m, cp2x, cp2y);
+    let p = transform_point(env, m, x, y);
+    add_element(env, path, PathElement::AddCurveToPoint(cp1, cp2, p));
+}
+
+fn CGPathCloseSubpath(env: &mut Environment, path: CGMutablePathRef) {
+    add_element(env, path, PathElement::CloseSubpath);
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CGPathCreateMutable()),
+    export_c_func!(CGPathRetain(_)),
+    export_c_func!(CGPathRelease(_)),
+    export_c_func!(CGPathIsEmpty(_)),
+    export_c_func!(CGPathMoveToPoint(_, _, _, _)),
+    export_c_func!(CGPathAddLineToPoint(_, _, _, _)),
+    export_c_func!(CGPathAddQuadCurveToPoint(_, _, _, _, _, _)),
+    export_c_func!(CGPathAddCurveToPoint(_, _, _, _, _, _, _, _)),
+    export_c_func!(CGPathCloseSubpath(_)),
+];
@@ -24,10 +24,13 @@ pub mod ns_enumerator;
 pub mod ns_error;
 pub mod ns_exception;
 pub mod ns_file_manager;
+pub mod ns_invocation;
+pub mod ns_keyed_archiver;
 pub mod ns_keyed_unarchiver;
 pub mod ns_locale;
 pub mod ns_lock;
 pub mod ns_log;
+pub mod ns_method_signature;
 pub mod ns_notification;
 pub mod ns_notification_center;
 pub mod ns_null;
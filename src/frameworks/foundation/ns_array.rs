@@ -6,13 +6,13 @@
 //! The `NSArray` class cluster, including `NSMutableArray`.
 
 use super::ns_enumerator::{fast_enumeration_helper, NSFastEnumerationState};
-use super::ns_property_list_serialization::deserialize_plist_from_file;
-use super::{ns_keyed_unarchiver, ns_string, ns_url, NSUInteger};
+use super::ns_property_list_serialization::{deserialize_plist_from_file, serialize_plist_to_file};
+use super::{ns_keyed_archiver, ns_keyed_unarchiver, ns_string, ns_url, NSUInteger};
 use crate::fs::GuestPath;
 use crate::mem::MutPtr;
 use crate::objc::{
-    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
-    NSZonePtr,
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports,
+    HostObject, NSZonePtr,
 };
 use crate::Environment;
 
@@ -27,6 +27,13 @@ struct ArrayHostObject {
 }
 impl HostObject for ArrayHostObject {}
 
+/// Whether `class` is `NSMutableArray` or a subclass of it, for the plist-reading
+/// methods it inherits.
+fn is_mutable_class(env: &mut Environment, class: Class) -> bool {
+    let mutable_class = env.objc.get_known_class("NSMutableArray", &mut env.mem);
+    env.objc.class_is_subclass_of(class, mutable_class)
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -47,17 +54,20 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // These probably comes from some category related to plists.
 + (id)arrayWithContentsOfFile:(id)path { // NSString*
+    let mutable = is_mutable_class(env, this);
     let path = ns_string::to_rust_string(env, path);
     let res = deserialize_plist_from_file(
         env,
         GuestPath::new(&path),
         /* array_expected: */ true,
+        mutable,
     );
     autorelease(env, res)
 }
 + (id)arrayWithContentsOfURL:(id)url { // NSURL*
+    let mutable = is_mutable_class(env, this);
     let path = ns_url::to_rust_path(env, url);
-    let res = deserialize_plist_from_file(env, &path, /* array_expected: */ true);
+    let res = deserialize_plist_from_file(env, &path, /* array_expected: */ true, mutable);
     autorelease(env, res)
 }
 + (id)arrayWithObjects:(id)firstObj, ...args {
@@ -78,18 +88,34 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // These probably comes from some category related to plists.
 - (id)initWithContentsOfFile:(id)path { // NSString*
+    let class: Class = msg![env; this class];
+    let mutable = is_mutable_class(env, class);
     release(env, this);
     let path = ns_string::to_rust_string(env, path);
     deserialize_plist_from_file(
         env,
         GuestPath::new(&path),
         /* array_expected: */ true,
+        mutable,
     )
 }
 - (id)initWithContentsOfURL:(id)url { // NSURL*
+    let class: Class = msg![env; this class];
+    let mutable = is_mutable_class(env, class);
     release(env, this);
     let path = ns_url::to_rust_path(env, url);
-    deserialize_plist_from_file(env, &path, /* array_expected: */ true)
+    deserialize_plist_from_file(env, &path, /* array_expected: */ true, mutable)
+}
+
+- (bool)writeToFile:(id)path // NSString*
+         atomically:(bool)_use_aux_file {
+    let path = ns_string::to_rust_string(env, path);
+    serialize_plist_to_file(env, this, GuestPath::new(&path))
+}
+- (bool)writeToURL:(id)url // NSURL*
+        atomically:(bool)_use_aux_file {
+    let path = ns_url::to_rust_path(env, url);
+    serialize_plist_to_file(env, this, &path)
 }
 
 // NSCopying implementation
@@ -98,6 +124,34 @@ pub const CLASSES: ClassExports = objc_classes! {
     retain(env, this)
 }
 
+// NSMutableCopying implementation
+- (id)mutableCopyWithZone:(NSZonePtr)_zone {
+    let count: NSUInteger = msg![env; this count];
+    let new: id = msg_class![env; NSMutableArray alloc];
+    let new: id = msg![env; new initWithCapacity:count];
+    for i in 0..count {
+        let object: id = msg![env; this objectAtIndex:i];
+        () = msg![env; new addObject:object];
+    }
+    new
+}
+
+// NSCoding implementation
+- (Class)classForCoder {
+    env.objc.get_known_class("NSArray", &mut env.mem)
+}
+- (())encodeWithCoder:(id)coder {
+    // See initWithCoder: on _touchHLE_NSArray for the format.
+    // FIXME: What if it's not an NSKeyedArchiver?
+    let count: NSUInteger = msg![env; this count];
+    let mut objects = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let object: id = msg![env; this objectAtIndex:i];
+        objects.push(object);
+    }
+    ns_keyed_archiver::encode_current_array(env, coder, "NS.objects", &objects);
+}
+
 - (id)lastObject {
     let size: NSUInteger = msg![env; this count];
     if size == 0 {
@@ -131,6 +185,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     todo!(); // TODO: this should produce an immutable copy
 }
 
+// NSCoding implementation
+- (Class)classForCoder {
+    env.objc.get_known_class("NSMutableArray", &mut env.mem)
+}
+
 @end
 
 // Our private subclass that is the single implementation of NSArray for the
@@ -159,7 +218,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     // allocating an NSString here would be inconvenient, so let's just take a
     // shortcut.
     // FIXME: What if it's not an NSKeyedUnarchiver?
-    let objects = ns_keyed_unarchiver::decode_current_array(env, coder);
+    let objects = ns_keyed_unarchiver::decode_current_array(env, coder, "NS.objects");
     let host_object: &mut ArrayHostObject = env.objc.borrow_mut(this);
     assert!(host_object.array.is_empty());
     host_object.array = objects; // objects are already retained
@@ -230,7 +289,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // NSCoding implementation
 - (id)initWithCoder:(id)coder {
-    let objects = ns_keyed_unarchiver::decode_current_array(env, coder);
+    let objects = ns_keyed_unarchiver::decode_current_array(env, coder, "NS.objects");
     let host_object: &mut ArrayHostObject = env.objc.borrow_mut(this);
     assert!(host_object.array.is_empty());
     host_object.array = objects; // objects are already retained
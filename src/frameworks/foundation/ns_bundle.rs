@@ -5,6 +5,9 @@
  */
 //! `NSBundle`.
 
+mod strings_file;
+
+use super::ns_dictionary::dict_from_keys_and_objects;
 use super::{ns_string, NSUInteger};
 use crate::bundle::Bundle;
 use crate::frameworks::core_foundation::cf_bundle::{
@@ -13,12 +16,12 @@ use crate::frameworks::core_foundation::cf_bundle::{
 use crate::frameworks::foundation::ns_string::get_static_str;
 use crate::frameworks::foundation::ns_string::to_rust_string;
 use crate::frameworks::uikit::ui_nib::load_nib_file;
-use crate::fs::GuestPathBuf;
+use crate::fs::{GuestPath, GuestPathBuf};
 use crate::objc::{
     autorelease, id, msg, msg_class, nil, objc_classes, release, ClassExports, HostObject,
 };
 use crate::Environment;
-use std::collections::HashSet;
+use std::collections::HashMap;
 
 // Should be ISO 639-1 (or ISO 639-2) compliant
 // TODO: complete this list or use some crate for mapping
@@ -51,6 +54,10 @@ pub struct NSBundleHostObject {
     bundle_url: Option<id>,
     /// `NSDictionary*` for the `Info.plist` content. [None] if not created yet.
     info_dictionary: Option<id>,
+    /// Cache of `NSDictionary*` for each localized strings table that has
+    /// been looked up, by table name. The value is `nil` if the table
+    /// doesn't exist.
+    strings_tables: HashMap<String, id>,
 }
 impl HostObject for NSBundleHostObject {}
 
@@ -71,6 +78,7 @@ pub const CLASSES: ClassExports = objc_classes! {
             bundle_path,
             bundle_url: None,
             info_dictionary: None,
+            strings_tables: HashMap::new(),
         };
         let new = env.objc.alloc_object(
             this,
@@ -93,6 +101,7 @@ pub const CLASSES: ClassExports = objc_classes! {
         bundle_path: _, // FIXME?
         bundle_url,
         info_dictionary,
+        strings_tables: _,
     } = env.objc.borrow(this);
     if let Some(bundle_url) = bundle_url {
         release(env, bundle_url);
@@ -100,6 +109,12 @@ pub const CLASSES: ClassExports = objc_classes! {
     if let Some(info_dictionary) = info_dictionary {
         release(env, info_dictionary);
     }
+    let strings_tables = std::mem::take(
+        &mut env.objc.borrow_mut::<NSBundleHostObject>(this).strings_tables
+    );
+    for (_, table) in strings_tables {
+        release(env, table);
+    }
     env.objc.dealloc_object(this, &mut env.mem)
 }
 
@@ -158,32 +173,16 @@ pub const CLASSES: ClassExports = objc_classes! {
         return path
     }
 
-    // Try preferred languages in order of preference
-    let langs: id = msg_class![env; NSLocale preferredLanguages];
-    let lang_count: NSUInteger = msg![env; langs count];
-    let mut unknown_codes = HashSet::new();
-    for i in 0..lang_count {
-        let lang_code: id = msg![env; langs objectAtIndex:i];
-        let lang_code = ns_string::to_rust_string(env, lang_code); // TODO: avoid copy
-        if let Some(&(_, lproj)) = LANG_ID_TO_LANG_PROJ.iter().find(|&&(code, _)| code == lang_code) {
-            let lproj: id = ns_string::get_static_str(env, lproj);
-            let localized_path = path_for_resource_helper(env, this, name, lproj, directory, extension);
-            if localized_path != nil {
-                return localized_path;
-            }
-        } else {
-            unknown_codes.insert(lang_code);
+    // Try localizations in order of preference
+    for lproj in lproj_names(env, this) {
+        let lproj = ns_string::from_rust_string(env, lproj);
+        let localized_path = path_for_resource_helper(env, this, name, lproj, directory, extension);
+        release(env, lproj);
+        if localized_path != nil {
+            return localized_path;
         }
     }
-
-    // As a last resort, fallback to English
-    // TODO: fallback to a development language (CFBundleDevelopmentRegion from
-    // Info.plist)
-    if !unknown_codes.is_empty() {
-        log!("TODO: language codes {:?} aren't mapped to a language name, falling back to English", unknown_codes);
-    }
-    let lproj: id = ns_string::get_static_str(env, "English.lproj");
-    path_for_resource_helper(env, this, name, lproj, directory, extension)
+    nil
 }
 - (id)pathForResource:(id)name // NSString*
                ofType:(id)extension { // NSString*
@@ -227,6 +226,47 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, localizations)
 }
 
+- (id)localizedStringForKey:(id)key // NSString*
+                      value:(id)value // NSString*
+                      table:(id)table_name { // NSString*
+    let table_name = if table_name == nil {
+        String::new()
+    } else {
+        to_rust_string(env, table_name).into_owned()
+    };
+    let table_name = if table_name.is_empty() {
+        "Localizable"
+    } else {
+        table_name.as_str()
+    };
+
+    let table = strings_table(env, this, table_name);
+    if key != nil && table != nil {
+        let localized: id = msg![env; table objectForKey:key];
+        if localized != nil {
+            return localized;
+        }
+    }
+
+    let value_length: NSUInteger = if value == nil {
+        0
+    } else {
+        msg![env; value length]
+    };
+    if value_length != 0 {
+        value
+    } else if key != nil {
+        log_dbg!(
+            "No localization for {:?} in table {:?}",
+            to_rust_string(env, key),
+            table_name
+        );
+        key
+    } else {
+        get_static_str(env, "")
+    }
+}
+
 // TODO: constructors, more accessors
 
 @end
@@ -259,3 +299,126 @@ fn path_for_resource_helper(
     }
     nil
 }
+
+fn host_bundle(env: &Environment, bundle: id) -> &Bundle {
+    env.objc
+        .borrow::<NSBundleHostObject>(bundle)
+        .bundle
+        .as_ref()
+        .unwrap_or(&env.bundle)
+}
+
+/// Get the names of the `.lproj` directories to search for localized
+/// resources, in order of preference: the user's preferred languages (which
+/// can be overridden with `--preferred-languages=`), then the bundle's
+/// development region and declared localizations, and finally English.
+fn lproj_names(env: &mut Environment, bundle: id) -> Vec<String> {
+    let mut languages = Vec::new();
+    let langs: id = msg_class![env; NSLocale preferredLanguages];
+    let lang_count: NSUInteger = msg![env; langs count];
+    for i in 0..lang_count {
+        let lang_code: id = msg![env; langs objectAtIndex:i];
+        languages.push(to_rust_string(env, lang_code).into_owned());
+    }
+    let host_bundle = host_bundle(env, bundle);
+    languages.extend(host_bundle.development_region().map(str::to_owned));
+    languages.extend(
+        host_bundle
+            .bundle_localizations()
+            .iter()
+            .filter_map(|value| value.as_string())
+            .map(str::to_owned),
+    );
+    languages.push("en".to_string());
+
+    let mut names = Vec::new();
+    let mut add_name = |name: String| {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    };
+    for lang in languages {
+        // A region-specific language (e.g. "en-GB" or "en_GB") falls back to
+        // the general one.
+        let base_lang = lang
+            .split_once(|c: char| c == '-' || c == '_')
+            .map(|(base, _)| base.to_string());
+        for lang in std::iter::once(lang).chain(base_lang) {
+            // Older apps use English language names rather than ISO codes.
+            let legacy_name = LANG_ID_TO_LANG_PROJ
+                .iter()
+                .find(|&&(code, _)| code == lang)
+                .map(|&(_, lproj)| lproj.to_string());
+            let code_name = LANG_ID_TO_LANG_PROJ
+                .iter()
+                .find(|&&(_, lproj)| lproj.strip_suffix(".lproj") == Some(lang.as_str()))
+                .map(|&(code, _)| format!("{}.lproj", code));
+            add_name(format!("{}.lproj", lang));
+            legacy_name
+                .into_iter()
+                .chain(code_name)
+                .for_each(&mut add_name);
+        }
+    }
+    names
+}
+
+/// Get the `NSDictionary*` for a localized strings table (i.e. the content of
+/// `[table_name].strings` in the most preferred `.lproj` directory that has
+/// it), or `nil` if there isn't one. The result is cached.
+fn strings_table(env: &mut Environment, bundle: id, table_name: &str) -> id {
+    let host_object = env.objc.borrow::<NSBundleHostObject>(bundle);
+    if let Some(&table) = host_object.strings_tables.get(table_name) {
+        return table;
+    }
+    let bundle_path = host_object.bundle_path;
+    let bundle_path = to_rust_string(env, bundle_path).into_owned();
+
+    let file_name = format!("{}.strings", table_name);
+    let mut paths: Vec<GuestPathBuf> = lproj_names(env, bundle)
+        .into_iter()
+        .map(|lproj| GuestPath::new(&bundle_path).join(lproj).join(&file_name))
+        .collect();
+    paths.push(GuestPath::new(&bundle_path).join(&file_name));
+
+    let mut table = nil;
+    if let Some(path) = paths.into_iter().find(|path| env.fs.is_file(path)) {
+        let pairs = env
+            .fs
+            .read(&path)
+            .map_err(|()| "couldn't read file".to_string())
+            .and_then(|bytes| strings_file::parse_strings_file(&bytes));
+        match pairs {
+            Ok(pairs) => {
+                log_dbg!(
+                    "Loaded strings table {:?} with {} entries",
+                    path,
+                    pairs.len()
+                );
+                let pairs: Vec<(id, id)> = pairs
+                    .into_iter()
+                    .map(|(key, value)| {
+                        (
+                            ns_string::from_rust_string(env, key),
+                            ns_string::from_rust_string(env, value),
+                        )
+                    })
+                    .collect();
+                table = dict_from_keys_and_objects(env, &pairs);
+                for (key, value) in pairs {
+                    release(env, key);
+                    release(env, value);
+                }
+            }
+            Err(e) => log!("Warning: couldn't parse strings file {:?}: {}", path, e),
+        }
+    } else {
+        log_dbg!("No strings table named {:?}", table_name);
+    }
+
+    env.objc
+        .borrow_mut::<NSBundleHostObject>(bundle)
+        .strings_tables
+        .insert(table_name.to_string(), table);
+    table
+}
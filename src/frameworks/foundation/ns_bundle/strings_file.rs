@@ -0,0 +1,305 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Parsing of `.strings` files, used for localized strings tables.
+//!
+//! These are usually in the old-style "OpenStep" property list format, limited
+//! to a dictionary with string keys and values, and with the outer braces
+//! omitted. Xcode writes them as UTF-16 with a byte order mark, but UTF-8 is
+//! accepted too. Xcode can also be configured to compile them to binary
+//! property lists, and XML property lists work as well.
+//!
+//! Resources:
+//! - [String Resources](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/LoadingResources/Strings/Strings.html)
+//! - [Old-Style ASCII Property Lists](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/PropertyLists/OldStylePlists/OldStylePLists.html)
+
+use plist::Value;
+use std::io::Cursor;
+
+/// Parse the contents of a `.strings` file into a list of key-value pairs, in
+/// file order. Later duplicates should override earlier ones.
+pub fn parse_strings_file(bytes: &[u8]) -> Result<Vec<(String, String)>, String> {
+    if bytes.starts_with(b"bplist") || bytes.starts_with(b"<?xml") {
+        return parse_plist_strings_file(bytes);
+    }
+    let text = decode_text(bytes)?;
+    if text.trim_start().starts_with("<?xml") {
+        return parse_plist_strings_file(text.as_bytes());
+    }
+    Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+    }
+    .parse()
+}
+
+fn parse_plist_strings_file(bytes: &[u8]) -> Result<Vec<(String, String)>, String> {
+    let value = Value::from_reader(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let Value::Dictionary(dict) = value else {
+        return Err("root is not a dictionary".to_string());
+    };
+    dict.into_iter()
+        .map(|(key, value)| match value {
+            Value::String(value) => Ok((key, value)),
+            _ => Err(format!("value for key {:?} is not a string", key)),
+        })
+        .collect()
+}
+
+fn decode_text(bytes: &[u8]) -> Result<String, String> {
+    fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Result<String, String> {
+        if bytes.len() & 1 != 0 {
+            return Err("odd number of bytes in UTF-16 text".to_string());
+        }
+        let units = bytes
+            .chunks_exact(2)
+            .map(|pair| from_bytes([pair[0], pair[1]]));
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map_err(|e| e.to_string())
+    }
+
+    if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        decode_utf16(rest, u16::from_le_bytes)
+    } else if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        decode_utf16(rest, u16::from_be_bytes)
+    } else {
+        let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+        String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("{} at character {}", message, self.pos))
+    }
+
+    /// Skip whitespace and comments.
+    fn skip_ignored(&mut self) -> Result<(), String> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => self.pos += 1,
+                Some('/') if self.chars.get(self.pos + 1) == Some(&'/') => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.pos += 1;
+                    }
+                }
+                Some('/') if self.chars.get(self.pos + 1) == Some(&'*') => {
+                    self.pos += 2;
+                    loop {
+                        match self.peek() {
+                            None => return self.error("unterminated comment"),
+                            Some('*') if self.chars.get(self.pos + 1) == Some(&'/') => {
+                                self.pos += 2;
+                                break;
+                            }
+                            Some(_) => self.pos += 1,
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn parse(mut self) -> Result<Vec<(String, String)>, String> {
+        let mut pairs = Vec::new();
+        self.skip_ignored()?;
+        // The braces are optional, but some files have them anyway.
+        let braced = self.peek() == Some('{');
+        if braced {
+            self.pos += 1;
+        }
+        loop {
+            self.skip_ignored()?;
+            match self.peek() {
+                None if braced => return self.error("missing '}'"),
+                None => break,
+                Some('}') if braced => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => (),
+            }
+            let key = self.parse_string()?;
+            self.skip_ignored()?;
+            // A key on its own is its own value.
+            let value = if self.peek() == Some('=') {
+                self.pos += 1;
+                self.skip_ignored()?;
+                self.parse_string()?
+            } else {
+                key.clone()
+            };
+            self.skip_ignored()?;
+            if self.peek() != Some(';') {
+                return self.error("expected ';'");
+            }
+            self.pos += 1;
+            pairs.push((key, value));
+        }
+        Ok(pairs)
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some('"') => {
+                self.pos += 1;
+                self.parse_quoted_string()
+            }
+            Some(c) if is_unquoted_char(c) => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if is_unquoted_char(c)) {
+                    self.pos += 1;
+                }
+                Ok(self.chars[start..self.pos].iter().collect())
+            }
+            _ => self.error("expected a string"),
+        }
+    }
+
+    fn parse_quoted_string(&mut self) -> Result<String, String> {
+        let mut string = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return self.error("unterminated string");
+            };
+            self.pos += 1;
+            match c {
+                '"' => return Ok(string),
+                '\\' => string.push(self.parse_escape()?),
+                _ => string.push(c),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, String> {
+        let Some(c) = self.peek() else {
+            return self.error("unterminated escape sequence");
+        };
+        self.pos += 1;
+        Ok(match c {
+            'a' => '\x07',
+            'b' => '\x08',
+            'f' => '\x0C',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => '\x0B',
+            'U' | 'u' => {
+                let mut code = 0u32;
+                for _ in 0..4 {
+                    let Some(digit) = self.peek().and_then(|c| c.to_digit(16)) else {
+                        return self.error("bad \\U escape");
+                    };
+                    self.pos += 1;
+                    code = code * 16 + digit;
+                }
+                // Surrogates can't be represented as a char, and are very
+                // unlikely to appear, so they are replaced.
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            '0'..='7' => {
+                let mut code = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    let Some(digit) = self.peek().and_then(|c| c.to_digit(8)) else {
+                        break;
+                    };
+                    self.pos += 1;
+                    code = code * 8 + digit;
+                }
+                char::from_u32(code).unwrap()
+            }
+            // Includes \\, \" and \'.
+            _ => c,
+        })
+    }
+}
+
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '+' | '/' | ':' | '.' | '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_strings_file;
+
+    fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_text_strings_file() {
+        let text = r#"
+/* Title of the main menu */
+"MENU_TITLE" = "Main menu";
+// Line comment
+"GREETING" = "Hello, \"%@\"!\n";
+PLAIN_KEY = plain.value;
+"KEY_ONLY";
+"UNICODE" = "caf\U00e9";
+"#;
+        let expected = pairs(&[
+            ("MENU_TITLE", "Main menu"),
+            ("GREETING", "Hello, \"%@\"!\n"),
+            ("PLAIN_KEY", "plain.value"),
+            ("KEY_ONLY", "KEY_ONLY"),
+            ("UNICODE", "café"),
+        ]);
+        assert_eq!(parse_strings_file(text.as_bytes()).unwrap(), expected);
+
+        let mut utf16 = vec![0xFF, 0xFE];
+        for unit in text.encode_utf16() {
+            utf16.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(parse_strings_file(&utf16).unwrap(), expected);
+
+        let mut utf16_be = vec![0xFE, 0xFF];
+        for unit in text.encode_utf16() {
+            utf16_be.extend_from_slice(&unit.to_be_bytes());
+        }
+        assert_eq!(parse_strings_file(&utf16_be).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_braced_strings_file() {
+        assert_eq!(
+            parse_strings_file(b"{ a = b; \"c\" = \"d\"; }").unwrap(),
+            pairs(&[("a", "b"), ("c", "d")])
+        );
+    }
+
+    #[test]
+    fn test_bad_strings_file() {
+        assert!(parse_strings_file(b"\"a\" = \"b\"").is_err());
+        assert!(parse_strings_file(b"\"a\" = \"b;").is_err());
+        assert!(parse_strings_file(b"/* \"a\" = \"b\";").is_err());
+    }
+
+    #[test]
+    fn test_binary_strings_file() {
+        let mut dict = plist::Dictionary::new();
+        dict.insert("key".to_string(), plist::Value::String("value".to_string()));
+        let mut bytes = Vec::new();
+        plist::Value::Dictionary(dict)
+            .to_writer_binary(&mut bytes)
+            .unwrap();
+        assert_eq!(
+            parse_strings_file(&bytes).unwrap(),
+            pairs(&[("key", "value")])
+        );
+    }
+}
@@ -5,14 +5,78 @@
  */
 //! `NSCoder`.
 
-use crate::objc::{objc_classes, ClassExports};
+use super::ns_exception;
+use super::{NSInteger, NSUInteger};
+use crate::mem::ConstPtr;
+use crate::objc::{id, msg, objc_classes, Class, ClassExports, SEL};
+use crate::Environment;
+
+/// The encoding methods are only implemented by subclasses. Like on the real
+/// iPhone OS, sending one to a subclass that doesn't implement it is an error.
+fn abstract_method(env: &mut Environment, this: id, cmd: SEL) {
+    let class: Class = msg![env; this class];
+    let reason = format!(
+        "-[NSCoder {}]: cannot be sent to an abstract object of class {}: Create a concrete instance!",
+        cmd.as_str(&env.mem),
+        env.objc.get_class_name(class),
+    );
+    ns_exception::raise(env, "NSInvalidArgumentException", reason);
+}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
 
 @implementation NSCoder: NSObject
-// This is an abstract class
+// This is an abstract class. A subclass that supports keyed coding (currently
+// only NSKeyedArchiver, for encoding) must provide the methods below.
+
+- (bool)allowsKeyedCoding {
+    false
+}
+
+- (())encodeObject:(id)_object
+            forKey:(id)_key { // NSString*
+    abstract_method(env, this, _cmd)
+}
+- (())encodeConditionalObject:(id)_object
+                       forKey:(id)_key { // NSString*
+    abstract_method(env, this, _cmd)
+}
+- (())encodeBool:(bool)_value
+          forKey:(id)_key { // NSString*
+    abstract_method(env, this, _cmd)
+}
+- (())encodeInt:(i32)_value
+         forKey:(id)_key { // NSString*
+    abstract_method(env, this, _cmd)
+}
+- (())encodeInt32:(i32)_value
+           forKey:(id)_key { // NSString*
+    abstract_method(env, this, _cmd)
+}
+- (())encodeInt64:(i64)_value
+           forKey:(id)_key { // NSString*
+    abstract_method(env, this, _cmd)
+}
+- (())encodeInteger:(NSInteger)_value
+             forKey:(id)_key { // NSString*
+    abstract_method(env, this, _cmd)
+}
+- (())encodeFloat:(f32)_value
+           forKey:(id)_key { // NSString*
+    abstract_method(env, this, _cmd)
+}
+- (())encodeDouble:(f64)_value
+            forKey:(id)_key { // NSString*
+    abstract_method(env, this, _cmd)
+}
+- (())encodeBytes:(ConstPtr<u8>)_bytes
+           length:(NSUInteger)_length
+           forKey:(id)_key { // NSString*
+    abstract_method(env, this, _cmd)
+}
+
 @end
 
 };
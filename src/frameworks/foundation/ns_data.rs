@@ -189,10 +189,30 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; new initWithBytes:bytes length:length]
 }
 
+- (())appendBytes:(ConstVoidPtr)new_bytes
+            length:(NSUInteger)add_len {
+    if add_len == 0 {
+        return;
+    }
+    let &NSDataHostObject { length, .. } = env.objc.borrow(this);
+    () = msg![env; this increaseLengthBy:add_len];
+    let &NSDataHostObject { bytes, .. } = env.objc.borrow(this);
+    env.mem.memmove(bytes + length, new_bytes, add_len);
+}
+- (())appendData:(id)other { // NSData*
+    let other_bytes: ConstVoidPtr = msg![env; other bytes];
+    let other_length: NSUInteger = msg![env; other length];
+    msg![env; this appendBytes:other_bytes length:other_length]
+}
+
 - (())increaseLengthBy:(NSUInteger)add_len {
     let &NSDataHostObject { bytes, length, .. } = env.objc.borrow(this);
     let new_len = length + add_len;
-    let new_bytes = env.mem.realloc(bytes, new_len);
+    let new_bytes = if bytes.is_null() {
+        env.mem.alloc(new_len)
+    } else {
+        env.mem.realloc(bytes, new_len)
+    };
     let host = env.objc.borrow_mut::<NSDataHostObject>(this);
     host.length = new_len;
     host.bytes = new_bytes;
@@ -202,6 +222,30 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+/// Shortcut for host code: create a new `NSData*` (+1) with a copy of some
+/// bytes.
+pub fn from_rust_slice(env: &mut Environment, bytes: &[u8]) -> id {
+    let length: NSUInteger = bytes.len().try_into().unwrap();
+    let alloc: MutPtr<u8> = env.mem.alloc(length).cast();
+    env.mem.bytes_at_mut(alloc, length).copy_from_slice(bytes);
+    let data: id = msg_class![env; NSData alloc];
+    msg![env; data initWithBytesNoCopy:(alloc.cast_void()) length:length]
+}
+
+/// Shortcut for host code: append bytes to an `NSMutableData*`.
+pub fn append_rust_slice(env: &mut Environment, data: id, new_bytes: &[u8]) {
+    let add_len: NSUInteger = new_bytes.len().try_into().unwrap();
+    if add_len == 0 {
+        return;
+    }
+    let &NSDataHostObject { length, .. } = env.objc.borrow(data);
+    () = msg![env; data increaseLengthBy:add_len];
+    let &NSDataHostObject { bytes, .. } = env.objc.borrow(data);
+    env.mem
+        .bytes_at_mut((bytes + length).cast(), add_len)
+        .copy_from_slice(new_bytes);
+}
+
 pub fn to_rust_slice(env: &mut Environment, data: id) -> &[u8] {
     let borrowed_data = env.objc.borrow::<NSDataHostObject>(data);
     assert!(!borrowed_data.bytes.is_null() && borrowed_data.length != 0);
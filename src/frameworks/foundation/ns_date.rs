@@ -5,9 +5,10 @@
  */
 //! `NSDate`.
 
+use super::ns_string::get_static_str;
 use super::NSTimeInterval;
 use crate::frameworks::core_foundation::time::apple_epoch;
-use crate::objc::{autorelease, id, objc_classes, ClassExports, HostObject};
+use crate::objc::{autorelease, id, msg, objc_classes, ClassExports, HostObject, NSZonePtr};
 
 use std::time::SystemTime;
 
@@ -22,6 +23,13 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @implementation NSDate: NSObject
 
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSDateHostObject {
+        time_interval: 0.0,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
 + (id)date {
     // "Date objects are immutable, representing an invariant time interval
     // relative to an absolute reference date (00:00:00 UTC on 1 January 2001)."
@@ -37,6 +45,27 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, new)
 }
 
++ (id)dateWithTimeIntervalSinceReferenceDate:(NSTimeInterval)time_interval {
+    let host_object = Box::new(NSDateHostObject {
+        time_interval
+    });
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, new)
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let key = get_static_str(env, "NS.time");
+    let time_interval: NSTimeInterval = msg![env; coder decodeDoubleForKey:key];
+    env.objc.borrow_mut::<NSDateHostObject>(this).time_interval = time_interval;
+    this
+}
+- (())encodeWithCoder:(id)coder {
+    let time_interval = env.objc.borrow::<NSDateHostObject>(this).time_interval;
+    let key = get_static_str(env, "NS.time");
+    msg![env; coder encodeDouble:time_interval forKey:key]
+}
+
 - (NSTimeInterval)timeIntervalSinceDate:(id)anotherDate {
     assert!(!anotherDate.is_null());
     let host_object = env.objc.borrow::<NSDateHostObject>(this);
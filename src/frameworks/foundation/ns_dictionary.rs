@@ -5,13 +5,15 @@
  */
 //! The `NSDictionary` class cluster, including `NSMutableDictionary`.
 
-use super::ns_property_list_serialization::deserialize_plist_from_file;
-use super::{ns_string, ns_url, NSUInteger};
+use super::ns_property_list_serialization::{deserialize_plist_from_file, serialize_plist_to_file};
+use super::{
+    ns_array, ns_exception, ns_keyed_archiver, ns_keyed_unarchiver, ns_string, ns_url, NSUInteger,
+};
 use crate::abi::VaList;
 use crate::fs::GuestPath;
 use crate::objc::{
-    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
-    NSZonePtr,
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports,
+    HostObject, NSZonePtr,
 };
 use crate::Environment;
 use std::collections::HashMap;
@@ -70,6 +72,29 @@ impl DictionaryHostObject {
         collisions.push((key, value));
         self.count += 1;
     }
+    pub(super) fn remove(&mut self, env: &mut Environment, key: id) {
+        let hash: Hash = msg![env; key hash];
+        let Some(collisions) = self.map.get_mut(&hash) else {
+            return;
+        };
+        let mut found = None;
+        for (idx, &(candidate_key, _value)) in collisions.iter().enumerate() {
+            if candidate_key == key || msg![env; candidate_key isEqualTo:key] {
+                found = Some(idx);
+                break;
+            }
+        }
+        let Some(idx) = found else {
+            return;
+        };
+        let (existing_key, existing_value) = collisions.swap_remove(idx);
+        if collisions.is_empty() {
+            self.map.remove(&hash);
+        }
+        self.count -= 1;
+        release(env, existing_key);
+        release(env, existing_value);
+    }
     pub(super) fn release(&mut self, env: &mut Environment) {
         for collisions in self.map.values() {
             for &(key, value) in collisions {
@@ -81,6 +106,21 @@ impl DictionaryHostObject {
     pub(super) fn iter_keys(&self) -> impl Iterator<Item = id> + '_ {
         self.map.values().flatten().map(|&(key, _value)| key)
     }
+    pub(super) fn iter(&self) -> impl Iterator<Item = (id, id)> + '_ {
+        self.map.values().flatten().copied()
+    }
+    /// Make a new host object with the same contents, retaining the keys and
+    /// values again.
+    fn duplicate(&self, env: &mut Environment) -> Self {
+        for (key, value) in self.iter() {
+            retain(env, key);
+            retain(env, value);
+        }
+        DictionaryHostObject {
+            map: self.map.clone(),
+            count: self.count,
+        }
+    }
 }
 
 /// Helper to enable sharing `dictionaryWithObjectsAndKeys:` and
@@ -112,6 +152,31 @@ pub fn init_with_objects_and_keys(
     this
 }
 
+/// Shared implementation of `initWithCoder:` for the mutable and immutable
+/// dictionary classes.
+fn init_with_coder(env: &mut Environment, this: id, coder: id) -> id {
+    // FIXME: What if it's not an NSKeyedUnarchiver?
+    let keys_and_objects = ns_keyed_unarchiver::decode_current_dictionary(env, coder);
+    let mut host_object = <DictionaryHostObject as Default>::default();
+    for (key, object) in keys_and_objects {
+        host_object.insert(env, key, object, /* copy_key: */ true);
+        // insert() retains, but they're already retained by the Vec
+        release(env, key);
+        release(env, object);
+    }
+    *env.objc.borrow_mut(this) = host_object;
+    this
+}
+
+/// Whether `class` is `NSMutableDictionary` or a subclass of it, for the plist-reading
+/// methods it inherits.
+fn is_mutable_class(env: &mut Environment, class: Class) -> bool {
+    let mutable_class = env
+        .objc
+        .get_known_class("NSMutableDictionary", &mut env.mem);
+    env.objc.class_is_subclass_of(class, mutable_class)
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -146,17 +211,20 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // These probably comes from some category related to plists.
 + (id)dictionaryWithContentsOfFile:(id)path { // NSString*
+    let mutable = is_mutable_class(env, this);
     let path = ns_string::to_rust_string(env, path);
     let res = deserialize_plist_from_file(
         env,
         GuestPath::new(&path),
         /* array_expected: */ false,
+        mutable,
     );
     autorelease(env, res)
 }
 + (id)dictionaryWithContentsOfURL:(id)url { // NSURL*
+    let mutable = is_mutable_class(env, this);
     let path = ns_url::to_rust_path(env, url);
-    let res = deserialize_plist_from_file(env, &path, /* array_expected: */ false);
+    let res = deserialize_plist_from_file(env, &path, /* array_expected: */ false, mutable);
     autorelease(env, res)
 }
 
@@ -166,30 +234,114 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // These probably comes from some category related to plists.
 - (id)initWithContentsOfFile:(id)path { // NSString*
+    let class: Class = msg![env; this class];
+    let mutable = is_mutable_class(env, class);
     release(env, this);
     let path = ns_string::to_rust_string(env, path);
     deserialize_plist_from_file(
         env,
         GuestPath::new(&path),
         /* array_expected: */ false,
+        mutable,
     )
 }
 - (id)initWithContentsOfURL:(id)url { // NSURL*
+    let class: Class = msg![env; this class];
+    let mutable = is_mutable_class(env, class);
     release(env, this);
     let path = ns_url::to_rust_path(env, url);
-    deserialize_plist_from_file(env, &path, /* array_expected: */ false)
+    deserialize_plist_from_file(env, &path, /* array_expected: */ false, mutable)
+}
+
+- (bool)writeToFile:(id)path // NSString*
+         atomically:(bool)_use_aux_file {
+    let path = ns_string::to_rust_string(env, path);
+    serialize_plist_to_file(env, this, GuestPath::new(&path))
+}
+- (bool)writeToURL:(id)url // NSURL*
+        atomically:(bool)_use_aux_file {
+    let path = ns_url::to_rust_path(env, url);
+    serialize_plist_to_file(env, this, &path)
 }
 
 // NSCopying implementation
 - (id)copyWithZone:(NSZonePtr)_zone {
-    // TODO: override this once we have NSMutableString!
     retain(env, this)
 }
 
+// NSMutableCopying implementation
+- (id)mutableCopyWithZone:(NSZonePtr)_zone {
+    let host_object: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(this));
+    let new_host_object = host_object.duplicate(env);
+    *env.objc.borrow_mut(this) = host_object;
+    let new: id = msg_class![env; NSMutableDictionary alloc];
+    *env.objc.borrow_mut(new) = new_host_object;
+    new
+}
+
+// NSCoding implementation
+- (Class)classForCoder {
+    env.objc.get_known_class("NSDictionary", &mut env.mem)
+}
+- (())encodeWithCoder:(id)coder {
+    // FIXME: What if it's not an NSKeyedArchiver?
+    let keys: id = msg![env; this allKeys];
+    let count: NSUInteger = msg![env; keys count];
+    let mut key_vec = Vec::with_capacity(count as usize);
+    let mut object_vec = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let key: id = msg![env; keys objectAtIndex:i];
+        let object: id = msg![env; this objectForKey:key];
+        key_vec.push(key);
+        object_vec.push(object);
+    }
+    ns_keyed_archiver::encode_current_array(env, coder, "NS.keys", &key_vec);
+    ns_keyed_archiver::encode_current_array(env, coder, "NS.objects", &object_vec);
+}
+
 // TODO
 
 @end
 
+// NSMutableDictionary is an abstract class. A subclass must provide everything
+// NSDictionary provides, plus:
+// - (void)setObject:(id)object forKey:(id)key;
+// - (void)removeObjectForKey:(id)key;
+// Note that it inherits from NSDictionary, so we must ensure we override any
+// default methods that would be inappropriate for mutability.
+@implementation NSMutableDictionary: NSDictionary
+
++ (id)allocWithZone:(NSZonePtr)zone {
+    // NSMutableDictionary might be subclassed by something which needs
+    // allocWithZone: to have the normal behaviour. Unimplemented: call
+    // superclass alloc then.
+    assert!(this == env.objc.get_known_class("NSMutableDictionary", &mut env.mem));
+    msg_class![env; _touchHLE_NSMutableDictionary allocWithZone:zone]
+}
+
++ (id)dictionaryWithCapacity:(NSUInteger)capacity {
+    let new_dict: id = msg![env; this alloc];
+    let new_dict: id = msg![env; new_dict initWithCapacity:capacity];
+    autorelease(env, new_dict)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    let host_object: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(this));
+    let new_host_object = host_object.duplicate(env);
+    *env.objc.borrow_mut(this) = host_object;
+    let new: id = msg_class![env; NSDictionary alloc];
+    *env.objc.borrow_mut(new) = new_host_object;
+    new
+}
+
+// NSCoding implementation
+- (Class)classForCoder {
+    env.objc.get_known_class("NSMutableDictionary", &mut env.mem)
+}
+
+@end
+
 // Our private subclass that is the single implementation of NSDictionary for
 // the time being.
 @implementation _touchHLE_NSDictionary: NSDictionary
@@ -214,6 +366,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     this
 }
 
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    init_with_coder(env, this, coder)
+}
+
 // TODO: enumeration, more init methods, etc
 
 - (NSUInteger)count {
@@ -225,6 +382,94 @@ pub const CLASSES: ClassExports = objc_classes! {
     *env.objc.borrow_mut(this) = host_obj;
     res
 }
+- (id)allKeys {
+    let keys: Vec<id> = env.objc.borrow::<DictionaryHostObject>(this).iter_keys().collect();
+    let keys = keys.into_iter().map(|key| retain(env, key)).collect();
+    let keys = ns_array::from_vec(env, keys);
+    autorelease(env, keys)
+}
+
+@end
+
+// Our private subclass that is the single implementation of
+// NSMutableDictionary for the time being.
+@implementation _touchHLE_NSMutableDictionary: NSMutableDictionary
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<DictionaryHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    std::mem::take(env.objc.borrow_mut::<DictionaryHostObject>(this)).release(env);
+
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)init {
+    *env.objc.borrow_mut(this) = <DictionaryHostObject as Default>::default();
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    init_with_coder(env, this, coder)
+}
+- (id)initWithCapacity:(NSUInteger)_capacity {
+    msg![env; this init]
+}
+- (id)initWithObjectsAndKeys:(id)first_object, ...dots {
+    init_with_objects_and_keys(env, this, first_object, dots.start())
+}
+
+- (NSUInteger)count {
+    env.objc.borrow::<DictionaryHostObject>(this).count
+}
+- (id)objectForKey:(id)key {
+    let host_obj: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(this));
+    let res = host_obj.lookup(env, key);
+    *env.objc.borrow_mut(this) = host_obj;
+    res
+}
+- (id)allKeys {
+    let keys: Vec<id> = env.objc.borrow::<DictionaryHostObject>(this).iter_keys().collect();
+    let keys = keys.into_iter().map(|key| retain(env, key)).collect();
+    let keys = ns_array::from_vec(env, keys);
+    autorelease(env, keys)
+}
+
+- (())setObject:(id)object
+         forKey:(id)key {
+    if object == nil || key == nil {
+        let what = if key == nil { "key" } else { "object" };
+        ns_exception::raise(
+            env,
+            "NSInvalidArgumentException",
+            format!("-[NSMutableDictionary setObject:forKey:]: {} cannot be nil", what),
+        );
+        return;
+    }
+    let mut host_obj: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(this));
+    host_obj.insert(env, key, object, /* copy_key: */ true);
+    *env.objc.borrow_mut(this) = host_obj;
+}
+- (())removeObjectForKey:(id)key {
+    let mut host_obj: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(this));
+    host_obj.remove(env, key);
+    *env.objc.borrow_mut(this) = host_obj;
+}
+- (())removeAllObjects {
+    std::mem::take(env.objc.borrow_mut::<DictionaryHostObject>(this)).release(env);
+}
+- (())addEntriesFromDictionary:(id)other { // NSDictionary*
+    let keys: id = msg![env; other allKeys];
+    let count: NSUInteger = msg![env; keys count];
+    for i in 0..count {
+        let key: id = msg![env; keys objectAtIndex:i];
+        let object: id = msg![env; other objectForKey:key];
+        () = msg![env; this setObject:object forKey:key];
+    }
+}
 
 @end
 
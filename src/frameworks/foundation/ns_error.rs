@@ -3,11 +3,31 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+//! `NSError`.
 
+use super::ns_dictionary::dict_from_keys_and_objects;
+use super::ns_string::{from_rust_string, get_static_str, to_rust_string};
 use crate::dyld::{ConstantExports, HostConstant};
 use crate::frameworks::foundation::NSInteger;
-use crate::objc::{id, nil, release, retain, ClassExports, HostObject, NSZonePtr};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, release, retain, ClassExports, HostObject, NSZonePtr,
+};
 use crate::objc_classes;
+use crate::Environment;
+
+pub const NSCocoaErrorDomain: &str = "NSCocoaErrorDomain";
+pub const NSLocalizedDescriptionKey: &str = "NSLocalizedDescriptionKey";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_NSCocoaErrorDomain",
+        HostConstant::NSString(NSCocoaErrorDomain),
+    ),
+    (
+        "_NSLocalizedDescriptionKey",
+        HostConstant::NSString(NSLocalizedDescriptionKey),
+    ),
+];
 
 struct ErrorHostObject {
     domain: id,
@@ -32,6 +52,14 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
 
++ (id)errorWithDomain:(id)domain // NSString*
+                 code:(NSInteger)code
+             userInfo:(id)user_info { // NSDictionary*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithDomain:domain code:code userInfo:user_info];
+    autorelease(env, new)
+}
+
 - (id)initWithDomain:(id)domain
                 code:(NSInteger)code
             userInfo:(id)user_info {
@@ -52,12 +80,67 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.dealloc_object(this, &mut env.mem);
 }
 
+- (id)domain {
+    env.objc.borrow::<ErrorHostObject>(this).domain
+}
+- (NSInteger)code {
+    env.objc.borrow::<ErrorHostObject>(this).code
+}
+- (id)userInfo {
+    env.objc.borrow::<ErrorHostObject>(this).user_info
+}
+
+- (id)localizedDescription {
+    let &ErrorHostObject { domain, code, user_info } = env.objc.borrow(this);
+    if user_info != nil {
+        let key = get_static_str(env, NSLocalizedDescriptionKey);
+        let description: id = msg![env; user_info objectForKey:key];
+        if description != nil {
+            return description;
+        }
+    }
+    let domain = to_rust_string(env, domain);
+    let description = format!(
+        "The operation couldn’t be completed. ({} error {}.)",
+        domain, code
+    );
+    let description = from_rust_string(env, description);
+    autorelease(env, description)
+}
+
+- (id)description {
+    let description: id = msg![env; this localizedDescription];
+    let &ErrorHostObject { domain, code, .. } = env.objc.borrow(this);
+    let domain = to_rust_string(env, domain);
+    let description = to_rust_string(env, description);
+    let description = format!(
+        "Error Domain={} Code={} \"{}\"",
+        domain, code, description
+    );
+    let description = from_rust_string(env, description);
+    autorelease(env, description)
+}
 
 @end
 
 };
 
-pub const CONSTANTS: ConstantExports = &[(
-    "_NSLocalizedDescriptionKey",
-    HostConstant::NSString("NSLocalizedDescriptionKey"),
-)];
+/// Shortcut for host code: create an autoreleased `NSError*` whose `userInfo`
+/// has a description of the problem.
+pub fn error_with_description(
+    env: &mut Environment,
+    domain: &'static str,
+    code: NSInteger,
+    description: &str,
+) -> id {
+    let domain = get_static_str(env, domain);
+    let key = get_static_str(env, NSLocalizedDescriptionKey);
+    let description = from_rust_string(env, description.to_string());
+    let user_info = dict_from_keys_and_objects(env, &[(key, description)]);
+    release(env, description);
+    let error: id = msg_class![env; NSError errorWithDomain:domain
+                                                      code:code
+                                                  userInfo:user_info];
+    release(env, user_info);
+    error
+}
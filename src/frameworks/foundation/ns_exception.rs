@@ -3,8 +3,15 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+//! `NSException`.
 
+use super::ns_string::{from_rust_string, get_static_str, with_format};
 use crate::dyld::{ConstantExports, HostConstant};
+use crate::objc::{
+    autorelease, id, msg, nil, objc_classes, objc_exception_throw, release, retain, ClassExports,
+    HostObject, NSZonePtr,
+};
+use crate::Environment;
 
 // All constants are NSExceptionName
 pub const CONSTANTS: ConstantExports = &[
@@ -273,3 +280,101 @@ pub const CONSTANTS: ConstantExports = &[
         HostConstant::NSString("UIApplicationInvalidInterfaceOrientationException"),
     ),
 ];
+
+struct NSExceptionHostObject {
+    name: id,
+    reason: id,
+    user_info: id,
+}
+impl HostObject for NSExceptionHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSException: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSExceptionHostObject {
+        name: nil,
+        reason: nil,
+        user_info: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)exceptionWithName:(id)name // NSString*
+                 reason:(id)reason // NSString*
+               userInfo:(id)user_info { // NSDictionary*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithName:name reason:reason userInfo:user_info];
+    autorelease(env, new)
+}
+
++ (())raise:(id)name // NSString*
+     format:(id)format, // NSString*
+     ...args {
+    let reason = with_format(env, format, args.start());
+    let reason = from_rust_string(env, reason);
+    let exception: id = msg![env; this exceptionWithName:name
+                                                  reason:reason
+                                                userInfo:nil];
+    release(env, reason);
+    () = msg![env; exception raise];
+}
+
+- (id)initWithName:(id)name
+            reason:(id)reason
+          userInfo:(id)user_info {
+    retain(env, name);
+    retain(env, reason);
+    retain(env, user_info);
+    let host_object = env.objc.borrow_mut::<NSExceptionHostObject>(this);
+    host_object.name = name;
+    host_object.reason = reason;
+    host_object.user_info = user_info;
+    this
+}
+
+- (())dealloc {
+    let &NSExceptionHostObject { name, reason, user_info } = env.objc.borrow(this);
+    release(env, name);
+    release(env, reason);
+    release(env, user_info);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)name {
+    env.objc.borrow::<NSExceptionHostObject>(this).name
+}
+- (id)reason {
+    env.objc.borrow::<NSExceptionHostObject>(this).reason
+}
+- (id)userInfo {
+    env.objc.borrow::<NSExceptionHostObject>(this).user_info
+}
+
+- (id)description {
+    msg![env; this reason]
+}
+
+- (())raise {
+    objc_exception_throw(env, this);
+}
+
+@end
+
+};
+
+/// Shortcut for host code: raise an `NSException` with one of the names in
+/// [CONSTANTS] (without the leading underscore) and a reason.
+pub fn raise(env: &mut Environment, name: &'static str, reason: String) {
+    let name = get_static_str(env, name);
+    let reason = from_rust_string(env, reason);
+    let class = env.objc.get_known_class("NSException", &mut env.mem);
+    let exception: id = msg![env; class exceptionWithName:name
+                                                   reason:reason
+                                                 userInfo:nil];
+    release(env, reason);
+    () = msg![env; exception raise];
+}
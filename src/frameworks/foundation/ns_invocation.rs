@@ -0,0 +1,332 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSInvocation`, and Foundation's part of message forwarding.
+//!
+//! Resources:
+//! - Apple's [Objective-C Runtime Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjCRuntimeGuide/Articles/ocrtForwarding.html)
+//!   explains message forwarding.
+
+use super::ns_method_signature::{EncodedType, NSMethodSignatureHostObject};
+use super::NSInteger;
+use crate::abi::{read_arg_words, GuestArg};
+use crate::cpu::Cpu;
+use crate::mem::{ConstVoidPtr, MutPtr, MutVoidPtr, Ptr};
+use crate::objc::{
+    autorelease, id, msg, msg_send_with_arg_words, nil, objc_classes, release, retain,
+    ClassExports, HostObject, ObjC, SEL,
+};
+use crate::Environment;
+
+struct NSInvocationHostObject {
+    /// `NSMethodSignature*`
+    signature: id,
+    /// The arguments, including the target and selector, laid out as words
+    /// like they would be passed according to the calling convention.
+    arg_words: Vec<u32>,
+    return_value: Vec<u8>,
+    arguments_retained: bool,
+}
+impl HostObject for NSInvocationHostObject {}
+
+/// Create a new (not autoreleased) invocation.
+fn new_invocation(env: &mut Environment, signature: id, arg_words: Vec<u32>) -> id {
+    let signature_host_object = env.objc.borrow::<NSMethodSignatureHostObject>(signature);
+    assert!(arg_words.len() == signature_host_object.frame_words());
+    let return_size = signature_host_object.return_type().size;
+
+    retain(env, signature);
+    let host_object = Box::new(NSInvocationHostObject {
+        signature,
+        arg_words,
+        return_value: vec![0; return_size.try_into().unwrap()],
+        arguments_retained: false,
+    });
+    let class = env.objc.get_known_class("NSInvocation", &mut env.mem);
+    env.objc.alloc_object(class, host_object, &mut env.mem)
+}
+
+/// Get the offset in words and the type of an argument.
+fn argument_layout(env: &Environment, invocation: id, index: NSInteger) -> (usize, EncodedType) {
+    let signature = env
+        .objc
+        .borrow::<NSInvocationHostObject>(invocation)
+        .signature;
+    let signature = env.objc.borrow::<NSMethodSignatureHostObject>(signature);
+    let (offset, ty) = usize::try_from(index)
+        .ok()
+        .and_then(|index| signature.argument_layout(index))
+        .expect("NSInvocation argument index out of range"); // TODO: raise exception
+    (offset, ty.clone())
+}
+
+/// Words to bytes, for argument and return values.
+fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// Replace the value of an argument that's an object, retaining it and
+/// releasing the old value if necessary.
+fn set_object_argument(env: &mut Environment, invocation: id, word_offset: usize, value: id) {
+    let host_object = env.objc.borrow_mut::<NSInvocationHostObject>(invocation);
+    let old_value: id = Ptr::from_bits(host_object.arg_words[word_offset]);
+    host_object.arg_words[word_offset] = value.to_bits();
+    if host_object.arguments_retained {
+        retain(env, value);
+        release(env, old_value);
+    }
+}
+
+/// Foundation's part of message forwarding, called by the Objective-C runtime
+/// when `receiver` has no method for `selector`, even after dynamic method
+/// resolution. This tries `forwardingTargetForSelector:`, then
+/// `methodSignatureForSelector:` and `forwardInvocation:`, and finally
+/// `doesNotRecognizeSelector:`.
+///
+/// The arguments of the message must still be in the registers and on the
+/// stack, as for `objc_msgSend` (or `objc_msgSend_stret` if `stret` is
+/// [true]). The return value is written to the registers or to memory
+/// likewise, unless a new receiver is returned, in which case the runtime must
+/// send the message to it instead.
+pub fn forward_message(
+    env: &mut Environment,
+    receiver: id,
+    selector: SEL,
+    stret: bool,
+) -> Option<id> {
+    let responds =
+        |env: &Environment, name: &str| env.objc.object_has_method_named(&env.mem, receiver, name);
+
+    // Sending other messages will overwrite the arguments of this one.
+    let saved_regs: [u32; 4] = env.cpu.regs()[0..4].try_into().unwrap();
+
+    if responds(env, "forwardingTargetForSelector:") {
+        let target: id = msg![env; receiver forwardingTargetForSelector:selector];
+        env.cpu.regs_mut()[0..4].copy_from_slice(&saved_regs);
+        if target != nil && target != receiver {
+            return Some(target);
+        }
+    }
+
+    if responds(env, "methodSignatureForSelector:") && responds(env, "forwardInvocation:") {
+        let signature: id = msg![env; receiver methodSignatureForSelector:selector];
+        env.cpu.regs_mut()[0..4].copy_from_slice(&saved_regs);
+        if signature != nil {
+            let signature_host_object = env.objc.borrow::<NSMethodSignatureHostObject>(signature);
+            let frame_words = signature_host_object.frame_words();
+            if signature_host_object.returns_via_pointer() != stret {
+                log!(
+                    "Warning: method signature for forwarded message {} doesn't match how it was sent",
+                    selector.as_str(&env.mem)
+                );
+            }
+
+            let stret_words = if stret { 1 } else { 0 };
+            let mut arg_words = read_arg_words(env.cpu.regs(), &env.mem, stret_words + frame_words);
+            let arg_words = arg_words.split_off(stret_words);
+            let invocation = new_invocation(env, signature, arg_words);
+
+            () = msg![env; receiver forwardInvocation:invocation];
+
+            let return_value = std::mem::take(
+                &mut env
+                    .objc
+                    .borrow_mut::<NSInvocationHostObject>(invocation)
+                    .return_value,
+            );
+            release(env, invocation);
+            if stret {
+                let stret_ptr: MutPtr<u8> = Ptr::from_bits(saved_regs[0]);
+                let size = return_value.len().try_into().unwrap();
+                env.mem
+                    .bytes_at_mut(stret_ptr, size)
+                    .copy_from_slice(&return_value);
+            } else {
+                let mut bytes = [0u8; 8];
+                let size = return_value.len().min(8);
+                bytes[..size].copy_from_slice(&return_value[..size]);
+                let regs = env.cpu.regs_mut();
+                regs[0] = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+                regs[1] = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+            }
+            return None;
+        }
+    }
+
+    if responds(env, "doesNotRecognizeSelector:") {
+        () = msg![env; receiver doesNotRecognizeSelector:selector];
+        // This isn't supposed to return, but if it does, behave as if the
+        // message was sent to nil.
+        env.cpu.regs_mut()[0..2].fill(0);
+        return None;
+    }
+
+    // Only objects that don't inherit from NSObject can get here.
+    let class = ObjC::read_isa(receiver, &env.mem);
+    let is_metaclass = env.objc.is_metaclass(class);
+    panic!(
+        "{} {:?} ({}class \"{}\", {:?}) does not respond to selector \"{}\"!",
+        if is_metaclass { "Class" } else { "Object" },
+        receiver,
+        if is_metaclass { "meta" } else { "" },
+        env.objc.get_class_name(class),
+        class,
+        selector.as_str(&env.mem),
+    );
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSInvocation: NSObject
+
++ (id)invocationWithMethodSignature:(id)signature { // NSMethodSignature*
+    assert!(signature != nil); // TODO: raise exception
+    let frame_words = env.objc.borrow::<NSMethodSignatureHostObject>(signature).frame_words();
+    let new = new_invocation(env, signature, vec![0; frame_words]);
+    autorelease(env, new)
+}
+
+- (())dealloc {
+    let host_object = env.objc.borrow::<NSInvocationHostObject>(this);
+    let signature = host_object.signature;
+    if host_object.arguments_retained {
+        let argument_count = env
+            .objc
+            .borrow::<NSMethodSignatureHostObject>(signature)
+            .argument_types()
+            .len();
+        for index in 0..argument_count {
+            let (offset, ty) = argument_layout(env, this, index.try_into().unwrap());
+            if ty.is_object() {
+                let host_object = env.objc.borrow::<NSInvocationHostObject>(this);
+                let object: id = Ptr::from_bits(host_object.arg_words[offset]);
+                release(env, object);
+            }
+        }
+    }
+    release(env, signature);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)methodSignature {
+    env.objc.borrow::<NSInvocationHostObject>(this).signature
+}
+
+- (id)target {
+    Ptr::from_bits(env.objc.borrow::<NSInvocationHostObject>(this).arg_words[0])
+}
+- (())setTarget:(id)target {
+    set_object_argument(env, this, 0, target);
+}
+
+- (SEL)selector {
+    let word = env.objc.borrow::<NSInvocationHostObject>(this).arg_words[1];
+    SEL::from_regs(&[word])
+}
+- (())setSelector:(SEL)selector {
+    env.objc.borrow_mut::<NSInvocationHostObject>(this).arg_words[1] = selector.to_ptr().to_bits();
+}
+
+- (())getArgument:(MutVoidPtr)buffer
+          atIndex:(NSInteger)index {
+    let (offset, ty) = argument_layout(env, this, index);
+    let host_object = env.objc.borrow::<NSInvocationHostObject>(this);
+    let bytes = words_to_bytes(&host_object.arg_words[offset..offset + ty.words()]);
+    let size: usize = ty.size.try_into().unwrap();
+    env.mem
+        .bytes_at_mut(buffer.cast(), ty.size)
+        .copy_from_slice(&bytes[..size]);
+}
+- (())setArgument:(ConstVoidPtr)buffer
+          atIndex:(NSInteger)index {
+    let (offset, ty) = argument_layout(env, this, index);
+    let mut bytes = env.mem.bytes_at(buffer.cast(), ty.size).to_vec();
+    bytes.resize(ty.words() * 4, 0);
+    if ty.is_object() {
+        let value: id = Ptr::from_bits(u32::from_le_bytes(bytes[0..4].try_into().unwrap()));
+        set_object_argument(env, this, offset, value);
+        return;
+    }
+    let host_object = env.objc.borrow_mut::<NSInvocationHostObject>(this);
+    for (i, word) in bytes.chunks(4).enumerate() {
+        host_object.arg_words[offset + i] = u32::from_le_bytes(word.try_into().unwrap());
+    }
+}
+
+- (())getReturnValue:(MutVoidPtr)buffer {
+    let host_object = env.objc.borrow::<NSInvocationHostObject>(this);
+    let size = host_object.return_value.len().try_into().unwrap();
+    env.mem
+        .bytes_at_mut(buffer.cast(), size)
+        .copy_from_slice(&host_object.return_value);
+}
+- (())setReturnValue:(ConstVoidPtr)buffer {
+    let host_object = env.objc.borrow_mut::<NSInvocationHostObject>(this);
+    let size = host_object.return_value.len().try_into().unwrap();
+    host_object
+        .return_value
+        .copy_from_slice(env.mem.bytes_at(buffer.cast(), size));
+}
+
+- (())retainArguments {
+    // TODO: Also copy C string arguments.
+    let host_object = env.objc.borrow::<NSInvocationHostObject>(this);
+    if host_object.arguments_retained {
+        return;
+    }
+    let argument_count = env
+        .objc
+        .borrow::<NSMethodSignatureHostObject>(host_object.signature)
+        .argument_types()
+        .len();
+    for index in 0..argument_count {
+        let (offset, ty) = argument_layout(env, this, index.try_into().unwrap());
+        if ty.is_object() {
+            let host_object = env.objc.borrow::<NSInvocationHostObject>(this);
+            let object: id = Ptr::from_bits(host_object.arg_words[offset]);
+            retain(env, object);
+        }
+    }
+    env.objc.borrow_mut::<NSInvocationHostObject>(this).arguments_retained = true;
+}
+- (bool)argumentsRetained {
+    env.objc.borrow::<NSInvocationHostObject>(this).arguments_retained
+}
+
+- (())invoke {
+    let host_object = env.objc.borrow::<NSInvocationHostObject>(this);
+    let signature = env.objc.borrow::<NSMethodSignatureHostObject>(host_object.signature);
+    let returns_via_pointer = signature.returns_via_pointer();
+    let arg_words = host_object.arg_words.clone();
+    let return_size = host_object.return_value.len();
+
+    let return_value = if returns_via_pointer {
+        // Make space for the return value on the stack.
+        let regs = env.cpu.regs_mut();
+        let old_sp = regs[Cpu::SP];
+        let guest_return_size: u32 = return_size.try_into().unwrap();
+        regs[Cpu::SP] -= (guest_return_size + 3) / 4 * 4;
+        let stret_ptr: MutVoidPtr = Ptr::from_bits(regs[Cpu::SP]);
+        msg_send_with_arg_words(env, &arg_words, Some(stret_ptr));
+        let return_value = env.mem.bytes_at(stret_ptr.cast(), guest_return_size).to_vec();
+        env.cpu.regs_mut()[Cpu::SP] = old_sp;
+        return_value
+    } else {
+        let mut return_value = words_to_bytes(&msg_send_with_arg_words(env, &arg_words, None));
+        return_value.truncate(return_size);
+        return_value
+    };
+    env.objc.borrow_mut::<NSInvocationHostObject>(this).return_value = return_value;
+}
+- (())invokeWithTarget:(id)target {
+    () = msg![env; this setTarget:target];
+    () = msg![env; this invoke];
+}
+
+@end
+
+};
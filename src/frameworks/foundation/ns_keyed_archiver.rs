@@ -0,0 +1,370 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSKeyedArchiver` and serialization of its object graph format.
+//!
+//! This produces the same format that [super::ns_keyed_unarchiver] reads, see
+//! there for resources. In brief: every object gets an entry in the `$objects`
+//! array (the first entry is always the string `$null`, standing in for
+//! `nil`), objects refer to each other by their index in that array (as a
+//! [Uid]), and the top-level keys are in the `$top` dictionary.
+//!
+//! Strings, numbers and data are stored directly as plist values, everything
+//! else is a dictionary containing whatever the object's `encodeWithCoder:`
+//! put there, plus a `$class` reference to a dictionary describing its class.
+
+use super::ns_keyed_unarchiver::NSKeyedArchiveRootObjectKey;
+use super::ns_property_list_serialization::{
+    serialize_plist, NSPropertyListBinaryFormat_v1_0, NSPropertyListFormat,
+    NSPropertyListXMLFormat_v1_0,
+};
+use super::ns_string::{get_static_str, to_rust_string};
+use super::{ns_data, NSInteger, NSUInteger};
+use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
+use crate::frameworks::uikit::ui_geometry::{
+    NSStringFromCGPoint, NSStringFromCGRect, NSStringFromCGSize,
+};
+use crate::mem::ConstPtr;
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports,
+    HostObject, NSZonePtr,
+};
+use crate::Environment;
+use plist::{Dictionary, Uid, Value};
+use std::collections::HashMap;
+
+struct NSKeyedArchiverHostObject {
+    /// `NSMutableData*` that the archive will be written to by
+    /// `finishEncoding`.
+    data: id,
+    output_format: NSPropertyListFormat,
+    /// The `$objects` array.
+    objects: Vec<Value>,
+    /// The `$top` dictionary.
+    top: Dictionary,
+    /// Uids of the objects that have already been archived, so that objects
+    /// referenced more than once are only stored once. The objects are
+    /// retained, so that their addresses can't be reused while archiving.
+    archived_objects: HashMap<id, Uid>,
+    /// Uids of the class description dictionaries, by class name.
+    archived_classes: HashMap<String, Uid>,
+    /// The object whose `encodeWithCoder:` is currently being called, if any.
+    /// [None] means values are encoded into `$top`.
+    current_object: Option<Uid>,
+    /// Set if some object couldn't be archived, in which case no data is
+    /// produced.
+    failed: bool,
+    finished: bool,
+}
+impl HostObject for NSKeyedArchiverHostObject {}
+
+fn borrow_host_obj(env: &mut Environment, archiver: id) -> &mut NSKeyedArchiverHostObject {
+    env.objc.borrow_mut(archiver)
+}
+
+/// Store a value for a key in the scope currently being encoded.
+fn encode_value_for_key(env: &mut Environment, archiver: id, key: id, value: Value) {
+    let key = to_rust_string(env, key).into_owned();
+    encode_value_for_rust_key(env, archiver, key, value);
+}
+fn encode_value_for_rust_key(env: &mut Environment, archiver: id, key: String, value: Value) {
+    let host_obj = borrow_host_obj(env, archiver);
+    assert!(!host_obj.finished);
+    let scope = match host_obj.current_object {
+        Some(uid) => host_obj.objects[uid.get() as usize]
+            .as_dictionary_mut()
+            .unwrap(),
+        None => &mut host_obj.top,
+    };
+    scope.insert(key, value);
+}
+
+/// Get the uid of the dictionary describing a class (and its superclasses),
+/// archiving it if necessary.
+fn archive_class(env: &mut Environment, archiver: id, class: Class) -> Uid {
+    let class_name = env.objc.get_class_name(class).to_string();
+    if let Some(&uid) = borrow_host_obj(env, archiver)
+        .archived_classes
+        .get(&class_name)
+    {
+        return uid;
+    }
+
+    let mut class_names = Vec::new();
+    let mut next_class = class;
+    while next_class != nil {
+        let name = env.objc.get_class_name(next_class).to_string();
+        class_names.push(Value::String(name));
+        next_class = env.objc.get_superclass(next_class);
+    }
+
+    let mut class_dict = Dictionary::new();
+    class_dict.insert("$classname".to_string(), Value::String(class_name.clone()));
+    class_dict.insert("$classes".to_string(), Value::Array(class_names));
+
+    let host_obj = borrow_host_obj(env, archiver);
+    let uid = Uid::new(host_obj.objects.len() as u64);
+    host_obj.objects.push(Value::Dictionary(class_dict));
+    host_obj.archived_classes.insert(class_name, uid);
+    uid
+}
+
+/// The core of the implementation: archive an object (if it wasn't already)
+/// and get its uid.
+///
+/// This is recursive in practice: the `encodeWithCoder:` messages sent by this
+/// function will be received by objects which will then send
+/// `encodeXXX:forKey:` messages back to the archiver, which will then call this
+/// function (and so on).
+fn archive_object(env: &mut Environment, archiver: id, object: id) -> Uid {
+    if object == nil {
+        return Uid::new(0);
+    }
+    if let Some(&uid) = borrow_host_obj(env, archiver).archived_objects.get(&object) {
+        return uid;
+    }
+
+    // Some basic types are stored as plain values.
+    let string_class: Class = msg_class![env; NSString class];
+    let number_class: Class = msg_class![env; NSNumber class];
+    let data_class: Class = msg_class![env; NSData class];
+    let plain_value = if msg![env; object isKindOfClass:string_class]
+        || msg![env; object isKindOfClass:number_class]
+        || msg![env; object isKindOfClass:data_class]
+    {
+        // TODO: NSMutableString and NSMutableData should be archived as
+        // objects, so that they are still mutable when unarchived.
+        match serialize_plist(env, object) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log!("Warning: couldn't archive {:?}: {}", object, e);
+                borrow_host_obj(env, archiver).failed = true;
+                return Uid::new(0);
+            }
+        }
+    } else {
+        None
+    };
+
+    retain(env, object);
+    let host_obj = borrow_host_obj(env, archiver);
+    let uid = Uid::new(host_obj.objects.len() as u64);
+    host_obj.archived_objects.insert(object, uid);
+
+    if let Some(value) = plain_value {
+        host_obj.objects.push(value);
+        return uid;
+    }
+
+    // The dictionary needs to be in place before encodeWithCoder: is called,
+    // so that references back to this object (cycles) get the right uid.
+    host_obj.objects.push(Value::Dictionary(Dictionary::new()));
+    let old_current_object = host_obj.current_object;
+    host_obj.current_object = Some(uid);
+
+    let class: Class = msg![env; object classForKeyedArchiver];
+    let class_uid = archive_class(env, archiver, class);
+    encode_value_for_rust_key(env, archiver, "$class".to_string(), Value::Uid(class_uid));
+
+    () = msg![env; object encodeWithCoder:archiver];
+
+    borrow_host_obj(env, archiver).current_object = old_current_object;
+    uid
+}
+
+/// Shortcut for use by `[NSArray encodeWithCoder:]` and similar, the inverse of
+/// [super::ns_keyed_unarchiver::decode_current_array].
+pub fn encode_current_array(env: &mut Environment, archiver: id, key: &str, objects: &[id]) {
+    let uids = objects
+        .iter()
+        .map(|&object| Value::Uid(archive_object(env, archiver, object)))
+        .collect();
+    encode_value_for_rust_key(env, archiver, key.to_string(), Value::Array(uids));
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSKeyedArchiver: NSCoder
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSKeyedArchiverHostObject {
+        data: nil,
+        output_format: NSPropertyListBinaryFormat_v1_0,
+        objects: vec![Value::String("$null".to_string())],
+        top: Dictionary::new(),
+        archived_objects: HashMap::new(),
+        archived_classes: HashMap::new(),
+        current_object: None,
+        failed: false,
+        finished: false,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)archivedDataWithRootObject:(id)root_object {
+    let data: id = msg_class![env; NSMutableData new];
+    let archiver: id = msg![env; this alloc];
+    let archiver: id = msg![env; archiver initForWritingWithMutableData:data];
+    let root_key = get_static_str(env, NSKeyedArchiveRootObjectKey);
+    () = msg![env; archiver encodeObject:root_object forKey:root_key];
+    () = msg![env; archiver finishEncoding];
+    let failed = borrow_host_obj(env, archiver).failed;
+    release(env, archiver);
+    if failed {
+        release(env, data);
+        return nil;
+    }
+    autorelease(env, data)
+}
+
++ (bool)archiveRootObject:(id)root_object
+                   toFile:(id)path { // NSString*
+    let data: id = msg![env; this archivedDataWithRootObject:root_object];
+    msg![env; data writeToFile:path atomically:true]
+}
+
+- (id)initForWritingWithMutableData:(id)data { // NSMutableData*
+    retain(env, data);
+    borrow_host_obj(env, this).data = data;
+    this
+}
+
+- (())dealloc {
+    let host_obj = borrow_host_obj(env, this);
+    let data = host_obj.data;
+    let archived_objects = std::mem::take(&mut host_obj.archived_objects);
+    for object in archived_objects.into_keys() {
+        release(env, object);
+    }
+    release(env, data);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (bool)allowsKeyedCoding {
+    true
+}
+
+- (NSPropertyListFormat)outputFormat {
+    borrow_host_obj(env, this).output_format
+}
+- (())setOutputFormat:(NSPropertyListFormat)format {
+    assert!(format == NSPropertyListBinaryFormat_v1_0 || format == NSPropertyListXMLFormat_v1_0);
+    borrow_host_obj(env, this).output_format = format;
+}
+
+- (())finishEncoding {
+    let host_obj = borrow_host_obj(env, this);
+    assert!(!host_obj.finished);
+    assert!(host_obj.current_object.is_none());
+    host_obj.finished = true;
+    if host_obj.failed {
+        log!("Warning: not writing NSKeyedArchiver data, some objects couldn't be archived");
+        return;
+    }
+
+    let mut plist = Dictionary::new();
+    plist.insert("$version".to_string(), Value::Integer(100000.into()));
+    plist.insert(
+        "$archiver".to_string(),
+        Value::String("NSKeyedArchiver".to_string()),
+    );
+    plist.insert("$top".to_string(), Value::Dictionary(std::mem::take(&mut host_obj.top)));
+    plist.insert(
+        "$objects".to_string(),
+        Value::Array(std::mem::take(&mut host_obj.objects)),
+    );
+    let plist = Value::Dictionary(plist);
+
+    let mut bytes = Vec::new();
+    if host_obj.output_format == NSPropertyListXMLFormat_v1_0 {
+        plist.to_writer_xml(&mut bytes).unwrap();
+    } else {
+        plist.to_writer_binary(&mut bytes).unwrap();
+    }
+    let data = host_obj.data;
+    ns_data::append_rust_slice(env, data, &bytes);
+}
+
+// These methods drive the encoding. They get called by the object currently
+// being archived, i.e. something that had `encodeWithCoder:` called on it, or
+// by the code that initiates the archival, for top-level objects.
+
+- (())encodeObject:(id)object
+            forKey:(id)key { // NSString*
+    let uid = archive_object(env, this, object);
+    encode_value_for_key(env, this, key, Value::Uid(uid));
+}
+- (())encodeConditionalObject:(id)object
+                       forKey:(id)key { // NSString*
+    // TODO: Only encode the object if it's also encoded unconditionally
+    // somewhere else. Encoding it anyway is wasteful, but harmless.
+    msg![env; this encodeObject:object forKey:key]
+}
+- (())encodeRootObject:(id)object {
+    let root_key = get_static_str(env, NSKeyedArchiveRootObjectKey);
+    msg![env; this encodeObject:object forKey:root_key]
+}
+
+- (())encodeBool:(bool)value
+          forKey:(id)key { // NSString*
+    encode_value_for_key(env, this, key, Value::Boolean(value));
+}
+- (())encodeInt:(i32)value
+         forKey:(id)key { // NSString*
+    encode_value_for_key(env, this, key, Value::Integer(value.into()));
+}
+- (())encodeInt32:(i32)value
+           forKey:(id)key { // NSString*
+    encode_value_for_key(env, this, key, Value::Integer(value.into()));
+}
+- (())encodeInt64:(i64)value
+           forKey:(id)key { // NSString*
+    encode_value_for_key(env, this, key, Value::Integer(value.into()));
+}
+- (())encodeInteger:(NSInteger)value
+             forKey:(id)key { // NSString*
+    encode_value_for_key(env, this, key, Value::Integer(value.into()));
+}
+- (())encodeFloat:(f32)value
+           forKey:(id)key { // NSString*
+    encode_value_for_key(env, this, key, Value::Real(value.into()));
+}
+- (())encodeDouble:(f64)value
+            forKey:(id)key { // NSString*
+    encode_value_for_key(env, this, key, Value::Real(value));
+}
+- (())encodeBytes:(ConstPtr<u8>)bytes
+           length:(NSUInteger)length
+           forKey:(id)key { // NSString*
+    let bytes = if length == 0 {
+        Vec::new()
+    } else {
+        env.mem.bytes_at(bytes, length).to_vec()
+    };
+    encode_value_for_key(env, this, key, Value::Data(bytes));
+}
+
+// These come from a category in UIKit's UIGeometry.h
+- (())encodeCGPoint:(CGPoint)point
+             forKey:(id)key { // NSString*
+    let string = NSStringFromCGPoint(env, point);
+    msg![env; this encodeObject:string forKey:key]
+}
+- (())encodeCGSize:(CGSize)size
+            forKey:(id)key { // NSString*
+    let string = NSStringFromCGSize(env, size);
+    msg![env; this encodeObject:string forKey:key]
+}
+- (())encodeCGRect:(CGRect)rect
+            forKey:(id)key { // NSString*
+    let string = NSStringFromCGRect(env, rect);
+    msg![env; this encodeObject:string forKey:key]
+}
+
+@end
+
+};
@@ -11,6 +11,7 @@
 //!   plists, e.g. `plutil -p` or `println!("{:#?}", plist::Value::...);`.
 //! - Apple's [Archives and Serializations Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/Archiving/Articles/archives.html)
 
+use super::ns_property_list_serialization::deserialize_plist;
 use super::ns_string::{from_rust_string, get_static_str, to_rust_string};
 use crate::dyld::{ConstantExports, HostConstant};
 use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
@@ -104,6 +105,10 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.dealloc_object(this, &mut env.mem)
 }
 
+- (bool)allowsKeyedCoding {
+    true
+}
+
 // These methods drive most of the decoding. They get called in two cases:
 // - By the code that initiates the unarchival, e.g. UINib, to retrieve
 //   top-level objects.
@@ -112,6 +117,10 @@ pub const CLASSES: ClassExports = objc_classes! {
 // They are all from the NSCoder abstract class and they return default values
 // if the key is unknown.
 
+- (bool)containsValueForKey:(id)key { // NSString *
+    get_value_to_decode_for_key(env, this, key).is_some()
+}
+
 - (bool)decodeBoolForKey:(id)key { // NSString *
     get_value_to_decode_for_key(env, this, key).map_or(
         false,
@@ -225,6 +234,11 @@ fn get_value_to_decode_for_key(env: &mut Environment, unarchiver: id, key: id) -
 /// The object returned is retained only by the archiver. Remember to retain and
 /// possibly autorelease it as appropriate.
 fn unarchive_key(env: &mut Environment, unarchiver: id, key: Uid) -> id {
+    // The first item is always the string "$null", which stands in for nil.
+    if key.get() == 0 {
+        return nil;
+    }
+
     let host_obj = borrow_host_obj(env, unarchiver);
     if let Some(existing) = host_obj.already_unarchived[key.get() as usize] {
         return existing;
@@ -275,6 +289,10 @@ fn unarchive_key(env: &mut Environment, unarchiver: id, key: Uid) -> id {
             let s = s.to_string();
             from_rust_string(env, s)
         }
+        Value::Integer(_) | Value::Real(_) | Value::Boolean(_) | Value::Data(_) => {
+            let item = item.clone();
+            deserialize_plist(env, &item)
+        }
         _ => unimplemented!("Unarchive: {:#?}", item),
     };
 
@@ -283,21 +301,22 @@ fn unarchive_key(env: &mut Environment, unarchiver: id, key: Uid) -> id {
     new_object
 }
 
+/// Get the uids in an array of uids for a key in the current object's scope.
+fn current_uid_array_for_key(env: &mut Environment, unarchiver: id, key: &str) -> Vec<Uid> {
+    let host_obj = borrow_host_obj(env, unarchiver);
+    let objects = host_obj.plist["$objects"].as_array().unwrap();
+    let item = &objects[host_obj.current_key.unwrap().get() as usize];
+    let keys = item.as_dictionary().unwrap()[key].as_array().unwrap();
+    keys.iter()
+        .map(|value| value.as_uid().copied().unwrap())
+        .collect()
+}
+
 /// Shortcut for use by `[_touchHLE_NSArray initWithCoder:]`.
 ///
 /// The objects are to be considered retained by the `Vec`.
-pub fn decode_current_array(env: &mut Environment, unarchiver: id) -> Vec<id> {
-    let keys: Vec<Uid> = {
-        let host_obj = borrow_host_obj(env, unarchiver);
-        let objects = host_obj.plist["$objects"].as_array().unwrap();
-        let item = &objects[host_obj.current_key.unwrap().get() as usize];
-        let keys = item.as_dictionary().unwrap()["NS.objects"]
-            .as_array()
-            .unwrap();
-        keys.iter()
-            .map(|value| value.as_uid().copied().unwrap())
-            .collect()
-    };
+pub fn decode_current_array(env: &mut Environment, unarchiver: id, key: &str) -> Vec<id> {
+    let keys = current_uid_array_for_key(env, unarchiver, key);
 
     keys.into_iter()
         .map(|key| {
@@ -307,3 +326,14 @@ pub fn decode_current_array(env: &mut Environment, unarchiver: id) -> Vec<id> {
         })
         .collect()
 }
+
+/// Shortcut for use by `[_touchHLE_NSDictionary initWithCoder:]`. The keys and
+/// objects are stored as two arrays, `NS.keys` and `NS.objects`.
+///
+/// The keys and objects are to be considered retained by the `Vec`.
+pub fn decode_current_dictionary(env: &mut Environment, unarchiver: id) -> Vec<(id, id)> {
+    let keys = decode_current_array(env, unarchiver, "NS.keys");
+    let objects = decode_current_array(env, unarchiver, "NS.objects");
+    assert!(keys.len() == objects.len());
+    keys.into_iter().zip(objects).collect()
+}
@@ -0,0 +1,331 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSMethodSignature`.
+//!
+//! Resources:
+//! - Apple's [Type Encodings](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjCRuntimeGuide/Articles/ocrtTypeEncodings.html)
+//!   documentation.
+
+use super::ns_exception;
+use super::NSUInteger;
+use crate::mem::{ConstPtr, GuestUSize, MutPtr};
+use crate::objc::{autorelease, id, msg, objc_classes, ClassExports, HostObject};
+use crate::Environment;
+
+/// One type from an Objective-C type encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct EncodedType {
+    /// The encoding of the type, without the offset that may follow it.
+    pub(super) encoding: String,
+    pub(super) size: GuestUSize,
+}
+impl EncodedType {
+    /// Returns the encoding without type qualifiers like `r` (`const`).
+    fn unqualified(&self) -> &str {
+        self.encoding
+            .trim_start_matches(|c| matches!(c, 'r' | 'n' | 'N' | 'o' | 'O' | 'R' | 'V' | 'A'))
+    }
+
+    /// Is this an object (`id`) or a class?
+    pub(super) fn is_object(&self) -> bool {
+        let unqualified = self.unqualified();
+        (unqualified.starts_with('@') && !unqualified.starts_with("@?"))
+            || unqualified.starts_with('#')
+    }
+
+    /// Is this a struct, union or array?
+    fn is_aggregate(&self) -> bool {
+        self.unqualified()
+            .starts_with(|c| matches!(c, '{' | '(' | '['))
+    }
+
+    /// Number of words this uses when passed as an argument.
+    pub(super) fn words(&self) -> usize {
+        ((self.size + 3) / 4).try_into().unwrap()
+    }
+}
+
+fn align_to(value: GuestUSize, alignment: GuestUSize) -> GuestUSize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+fn parse_number(encoding: &[u8]) -> Option<(GuestUSize, &[u8])> {
+    let digits = encoding.iter().take_while(|c| c.is_ascii_digit()).count();
+    let number = std::str::from_utf8(&encoding[..digits])
+        .ok()?
+        .parse()
+        .ok()?;
+    Some((number, &encoding[digits..]))
+}
+
+/// Skip a quoted name (as used for object classes and struct field names), if
+/// there is one.
+fn skip_quoted(encoding: &[u8]) -> Option<&[u8]> {
+    let Some(rest) = encoding.strip_prefix(b"\"") else {
+        return Some(encoding);
+    };
+    let end = rest.iter().position(|&c| c == b'"')?;
+    Some(&rest[end + 1..])
+}
+
+/// Parse the type at the start of an encoding. Returns its size, its
+/// alignment, and the rest of the encoding.
+fn parse_type(encoding: &[u8]) -> Option<(GuestUSize, GuestUSize, &[u8])> {
+    let (&c, rest) = encoding.split_first()?;
+    Some(match c {
+        // Type qualifiers, e.g. const
+        b'r' | b'n' | b'N' | b'o' | b'O' | b'R' | b'V' | b'A' => return parse_type(rest),
+        b'v' => (0, 1, rest),
+        b'c' | b'C' | b'B' => (1, 1, rest),
+        b's' | b'S' => (2, 2, rest),
+        b'i' | b'I' | b'l' | b'L' | b'f' | b'*' | b'#' | b':' | b'?' => (4, 4, rest),
+        // Apple's ARM ABI only requires 4-byte alignment for these.
+        b'q' | b'Q' | b'd' => (8, 4, rest),
+        b'@' => {
+            // Blocks are `@?`, objects may have a class name: `@"NSString"`.
+            let rest = rest.strip_prefix(b"?").unwrap_or(rest);
+            (4, 4, skip_quoted(rest)?)
+        }
+        b'^' => {
+            let (_, _, rest) = parse_type(rest)?;
+            (4, 4, rest)
+        }
+        // Bitfields aren't exact, but they should only appear in structs.
+        b'b' => {
+            let (bits, rest) = parse_number(rest)?;
+            ((bits + 7) / 8, 1, rest)
+        }
+        b'[' => {
+            let (count, rest) = parse_number(rest)?;
+            let (size, align, rest) = parse_type(rest)?;
+            (count * size, align, rest.strip_prefix(b"]")?)
+        }
+        b'{' | b'(' => {
+            let is_union = c == b'(';
+            let close = if is_union { b')' } else { b'}' };
+            // The name is optional and may be followed by the fields.
+            let name_len = rest.iter().position(|&c| c == b'=' || c == close)?;
+            let mut rest = &rest[name_len..];
+            let (mut size, mut align) = (0, 1);
+            if let Some(fields) = rest.strip_prefix(b"=") {
+                rest = fields;
+                while !rest.starts_with(&[close]) {
+                    let (field_size, field_align, after) = parse_type(skip_quoted(rest)?)?;
+                    rest = after;
+                    align = align.max(field_align);
+                    size = if is_union {
+                        size.max(field_size)
+                    } else {
+                        align_to(size, field_align) + field_size
+                    };
+                }
+            }
+            (align_to(size, align), align, &rest[1..])
+        }
+        _ => return None,
+    })
+}
+
+/// Parse a method's type encoding, e.g. `v12@0:4i8`. The first type is the
+/// return type, the rest are the argument types.
+pub(super) fn parse_method_types(types: &str) -> Option<Vec<EncodedType>> {
+    let mut rest = types.as_bytes();
+    let mut result = Vec::new();
+    while !rest.is_empty() {
+        let (size, _align, after) = parse_type(rest)?;
+        let encoding = &types[types.len() - rest.len()..types.len() - after.len()];
+        result.push(EncodedType {
+            encoding: encoding.to_string(),
+            size,
+        });
+        // Skip the offset, if any.
+        let after = after.strip_prefix(b"+").unwrap_or(after);
+        let after = after.strip_prefix(b"-").unwrap_or(after);
+        rest = &after[after.iter().take_while(|c| c.is_ascii_digit()).count()..];
+    }
+    // There must be at least a return type, self and _cmd.
+    if result.len() < 3 {
+        return None;
+    }
+    Some(result)
+}
+
+pub(super) struct NSMethodSignatureHostObject {
+    /// The return type, followed by the argument types.
+    types: Vec<EncodedType>,
+    /// C strings for `methodReturnType` and `getArgumentTypeAtIndex:`, indexed
+    /// like `types`.
+    type_cstrs: Vec<MutPtr<u8>>,
+}
+impl HostObject for NSMethodSignatureHostObject {}
+impl NSMethodSignatureHostObject {
+    pub(super) fn return_type(&self) -> &EncodedType {
+        &self.types[0]
+    }
+
+    pub(super) fn argument_types(&self) -> &[EncodedType] {
+        &self.types[1..]
+    }
+
+    fn is_oneway(&self) -> bool {
+        let return_type = self.return_type();
+        let qualifiers_len = return_type.encoding.len() - return_type.unqualified().len();
+        return_type.encoding[..qualifiers_len].contains('V')
+    }
+
+    /// Does the method return its value via a pointer passed as an implicit
+    /// first argument? (See `objc_msgSend_stret`.)
+    pub(super) fn returns_via_pointer(&self) -> bool {
+        let return_type = self.return_type();
+        return_type.is_aggregate() && return_type.size > 4
+    }
+
+    /// Get the offset in words and the type of an argument.
+    pub(super) fn argument_layout(&self, index: usize) -> Option<(usize, &EncodedType)> {
+        let types = self.argument_types();
+        let offset = types.get(..index)?.iter().map(|ty| ty.words()).sum();
+        Some((offset, types.get(index)?))
+    }
+
+    /// Total number of words used by the arguments.
+    pub(super) fn frame_words(&self) -> usize {
+        self.argument_types().iter().map(|ty| ty.words()).sum()
+    }
+}
+
+/// Shortcut for host code: get an autoreleased `NSMethodSignature*` for a type
+/// encoding, or [None] if the encoding can't be parsed.
+pub fn signature_with_types(env: &mut Environment, types: &str) -> Option<id> {
+    let types = parse_method_types(types)?;
+    let type_cstrs = types
+        .iter()
+        .map(|ty| env.mem.alloc_and_write_cstr(ty.encoding.as_bytes()))
+        .collect();
+    let host_object = Box::new(NSMethodSignatureHostObject { types, type_cstrs });
+    let class = env.objc.get_known_class("NSMethodSignature", &mut env.mem);
+    let signature = env.objc.alloc_object(class, host_object, &mut env.mem);
+    Some(autorelease(env, signature))
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSMethodSignature: NSObject
+
++ (id)signatureWithObjCTypes:(ConstPtr<u8>)types {
+    let types = env.mem.cstr_at_utf8(types).unwrap().to_string();
+    match signature_with_types(env, &types) {
+        Some(signature) => signature,
+        None => {
+            ns_exception::raise(
+                env,
+                "NSInvalidArgumentException",
+                format!(
+                    "+[NSMethodSignature signatureWithObjCTypes:]: unsupported type encoding spec '{}'",
+                    types
+                ),
+            );
+            unreachable!()
+        }
+    }
+}
+
+- (())dealloc {
+    let type_cstrs = std::mem::take(
+        &mut env.objc.borrow_mut::<NSMethodSignatureHostObject>(this).type_cstrs
+    );
+    for type_cstr in type_cstrs {
+        env.mem.free(type_cstr.cast());
+    }
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (NSUInteger)numberOfArguments {
+    let host_object = env.objc.borrow::<NSMethodSignatureHostObject>(this);
+    host_object.argument_types().len().try_into().unwrap()
+}
+
+- (ConstPtr<u8>)getArgumentTypeAtIndex:(NSUInteger)index {
+    let host_object = env.objc.borrow::<NSMethodSignatureHostObject>(this);
+    let index: usize = index.try_into().unwrap();
+    assert!(index < host_object.argument_types().len()); // TODO: raise exception
+    host_object.type_cstrs[index + 1].cast_const()
+}
+
+- (ConstPtr<u8>)methodReturnType {
+    env.objc.borrow::<NSMethodSignatureHostObject>(this).type_cstrs[0].cast_const()
+}
+
+- (NSUInteger)methodReturnLength {
+    env.objc.borrow::<NSMethodSignatureHostObject>(this).return_type().size
+}
+
+- (NSUInteger)frameLength {
+    let host_object = env.objc.borrow::<NSMethodSignatureHostObject>(this);
+    (host_object.frame_words() * 4).try_into().unwrap()
+}
+
+- (bool)isOneway {
+    env.objc.borrow::<NSMethodSignatureHostObject>(this).is_oneway()
+}
+
+- (bool)isEqual:(id)other {
+    if this == other {
+        return true;
+    }
+    let class = env.objc.get_known_class("NSMethodSignature", &mut env.mem);
+    let is_signature: bool = msg![env; other isKindOfClass:class];
+    if !is_signature {
+        return false;
+    }
+    let a = &env.objc.borrow::<NSMethodSignatureHostObject>(this).types;
+    let b = &env.objc.borrow::<NSMethodSignatureHostObject>(other).types;
+    a == b
+}
+
+@end
+
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(types: &str) -> Vec<(&str, GuestUSize)> {
+        // Leak the result so the test can borrow from it easily.
+        let types: &'static [EncodedType] = parse_method_types(types).unwrap().leak();
+        types
+            .iter()
+            .map(|ty| (ty.encoding.as_str(), ty.size))
+            .collect()
+    }
+
+    #[test]
+    fn method_types() {
+        assert_eq!(parse("v@:"), [("v", 0), ("@", 4), (":", 4)]);
+        assert_eq!(parse("c16@0:4d8"), [("c", 1), ("@", 4), (":", 4), ("d", 8)]);
+        assert_eq!(
+            parse("{CGRect={CGPoint=ff}{CGSize=ff}}8@0:4"),
+            [("{CGRect={CGPoint=ff}{CGSize=ff}}", 16), ("@", 4), (":", 4)]
+        );
+        assert_eq!(
+            parse("r*@:@\"NSString\"^{_NSZone=}{?=cid}(?=cd)[3s]"),
+            [
+                ("r*", 4),
+                ("@", 4),
+                (":", 4),
+                ("@\"NSString\"", 4),
+                ("^{_NSZone=}", 4),
+                ("{?=cid}", 16),
+                ("(?=cd)", 8),
+                ("[3s]", 6),
+            ]
+        );
+        assert!(parse_method_types("v@").is_none());
+        assert!(parse_method_types("v@:{CGPoint=ff").is_none());
+    }
+}
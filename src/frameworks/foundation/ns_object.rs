@@ -14,13 +14,32 @@
 //!
 //! See also: [crate::objc], especially the `objects` module.
 
+use super::ns_exception;
+use super::ns_method_signature::signature_with_types;
 use super::ns_string::to_rust_string;
 use super::NSUInteger;
 use crate::mem::MutVoidPtr;
 use crate::objc::{
-    id, msg, msg_class, msg_send, objc_classes, Class, ClassExports, NSZonePtr, ObjC,
-    TrivialHostObject, SEL,
+    id, msg, msg_class, msg_send, nil, objc_classes, release_associated_objects, Class,
+    ClassExports, NSZonePtr, ObjC, TrivialHostObject, SEL,
 };
+use crate::Environment;
+
+/// Get an `NSMethodSignature*` for the method a class has for a selector, or
+/// [nil] if it has no such method.
+fn method_signature(env: &mut Environment, class: Class, selector: SEL) -> id {
+    let Some(types) = env.objc.method_type_encoding(class, selector, &env.mem) else {
+        return nil;
+    };
+    signature_with_types(env, &types).unwrap_or_else(|| {
+        log!(
+            "Warning: couldn't parse type encoding {:?} of method {}",
+            types,
+            selector.as_str(&env.mem)
+        );
+        nil
+    })
+}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -60,6 +79,18 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.class_has_method(this, selector)
 }
 
++ (id)instanceMethodSignatureForSelector:(SEL)selector {
+    method_signature(env, this, selector)
+}
+
+// Message forwarding. See the instance method section for the rest.
++ (bool)resolveClassMethod:(SEL)_selector {
+    false
+}
++ (bool)resolveInstanceMethod:(SEL)_selector {
+    false
+}
+
 - (id)init {
     this
 }
@@ -73,6 +104,9 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (())release {
     log_dbg!("[{:?} release]", this);
     if env.objc.decrement_refcount(this) {
+        // This is done here rather than in dealloc, because many host classes
+        // don't call NSObject's dealloc.
+        release_associated_objects(env, this);
         () = msg![env; this dealloc];
     }
 }
@@ -111,6 +145,15 @@ pub const CLASSES: ClassExports = objc_classes! {
 // to prevent weird behavior.
 // TODO: localized description methods also? (not sure if NSObject has them)
 
+// Archiving support. Classes that are private implementation details (e.g.
+// our _touchHLE_ classes) override classForCoder to hide themselves.
+- (Class)classForCoder {
+    msg![env; this class]
+}
+- (Class)classForKeyedArchiver {
+    msg![env; this classForCoder]
+}
+
 // Helper for NSCopying
 - (id)copy {
     msg![env; this copyWithZone:(MutVoidPtr::null())]
@@ -157,6 +200,33 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.class_has_method(class, selector)
 }
 
+// Message forwarding. Since NSObject's metaclass's superclass is NSObject,
+// these are also used for classes.
+- (id)forwardingTargetForSelector:(SEL)_selector {
+    nil
+}
+- (id)methodSignatureForSelector:(SEL)selector {
+    let class = ObjC::read_isa(this, &env.mem);
+    method_signature(env, class, selector)
+}
+- (())forwardInvocation:(id)invocation { // NSInvocation*
+    let selector: SEL = msg![env; invocation selector];
+    () = msg![env; this doesNotRecognizeSelector:selector];
+}
+- (())doesNotRecognizeSelector:(SEL)selector {
+    let class = ObjC::read_isa(this, &env.mem);
+    let is_class = env.objc.is_metaclass(class);
+    let reason = format!(
+        "{}[{} {}]: unrecognized selector sent to {} {:?}",
+        if is_class { '+' } else { '-' },
+        env.objc.get_class_name(class),
+        selector.as_str(&env.mem),
+        if is_class { "class" } else { "instance" },
+        this,
+    );
+    ns_exception::raise(env, "NSInvalidArgumentException", reason);
+}
+
 - (id)performSelector:(SEL)sel {
     assert!(!sel.is_null());
     msg_send(env, (this, sel))
@@ -1,22 +1,60 @@
-//! `NSPropertyListSerialization`.
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSPropertyListSerialization` and conversion between property list objects
+//! and the [plist] crate's [Value].
+//!
+//! Other classes use this for reading and writing property list files, e.g.
+//! `[NSDictionary writeToFile:atomically:]` and
+//! `[NSArray arrayWithContentsOfFile:]`.
+//!
+//! Only the XML and binary formats are supported. The OpenStep format is
+//! deprecated, and can't be written by Apple's implementation either.
 
-use super::{ns_array, ns_dictionary, ns_string, NSUInteger};
+use super::ns_error::{error_with_description, NSCocoaErrorDomain};
+use super::ns_value::NSNumberHostObject;
+use super::{ns_array, ns_data, ns_dictionary, ns_string, NSInteger, NSTimeInterval, NSUInteger};
+use crate::frameworks::core_foundation::time::apple_epoch;
 use crate::fs::GuestPath;
-use crate::mem::MutPtr;
-use crate::objc::{id, msg, msg_class, nil, release};
+use crate::mem::{ConstPtr, MutPtr};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports,
+};
 use crate::Environment;
 use plist::Value;
 use std::io::Cursor;
+use std::time::{Duration, SystemTime};
 
-// TODO: Implement reading of property lists other than Info.plist.
-// [NSDictionary contentsOfFile:] and [NSArray contentsOfFile:] in particular.
+pub type NSPropertyListFormat = NSUInteger;
+pub const NSPropertyListOpenStepFormat: NSPropertyListFormat = 1;
+pub const NSPropertyListXMLFormat_v1_0: NSPropertyListFormat = 100;
+pub const NSPropertyListBinaryFormat_v1_0: NSPropertyListFormat = 200;
+
+pub type NSPropertyListMutabilityOptions = NSUInteger;
+pub const NSPropertyListImmutable: NSPropertyListMutabilityOptions = 0;
+pub const NSPropertyListMutableContainers: NSPropertyListMutabilityOptions = 1;
+pub const NSPropertyListMutableContainersAndLeaves: NSPropertyListMutabilityOptions = 2;
+
+type NSPropertyListReadOptions = NSPropertyListMutabilityOptions;
+type NSPropertyListWriteOptions = NSUInteger;
+
+// Error codes in NSCocoaErrorDomain
+const NSPropertyListReadCorruptError: NSInteger = 3840;
+const NSPropertyListWriteInvalidError: NSInteger = 3852;
 
 /// Internals of `initWithContentsOfFile:` on `NSArray` and `NSDictionary`.
 /// Returns `nil` on failure.
+///
+/// If `mutable` is [true], the root container will be mutable (but not any
+/// nested containers), like when these methods are used on `NSMutableArray`
+/// and `NSMutableDictionary`.
 pub(super) fn deserialize_plist_from_file(
     env: &mut Environment,
     path: &GuestPath,
     array_expected: bool,
+    mutable: bool,
 ) -> id {
     log_dbg!("Reading plist from {:?}.", path);
     let Ok(bytes) = env.fs.read(path) else {
@@ -28,6 +66,10 @@ pub(super) fn deserialize_plist_from_file(
         log_dbg!("Couldn't parse plist, returning nil.");
         return nil;
     };
+    if contains_uid(&root) {
+        log_dbg!("Plist contains keyed archiver UIDs, returning nil.");
+        return nil;
+    }
 
     if array_expected && root.as_array().is_none() {
         log_dbg!("Plist root is not array, returning nil.");
@@ -38,17 +80,75 @@ pub(super) fn deserialize_plist_from_file(
         return nil;
     }
 
-    deserialize_plist(env, &root)
+    let res = deserialize_plist(env, &root);
+    if mutable {
+        let mutable_res: id = msg![env; res mutableCopy];
+        release(env, res);
+        mutable_res
+    } else {
+        res
+    }
+}
+
+/// Internals of `writeToFile:atomically:` on `NSArray` and `NSDictionary`.
+/// These always use the XML format.
+pub(super) fn serialize_plist_to_file(env: &mut Environment, object: id, path: &GuestPath) -> bool {
+    log_dbg!("Writing plist to {:?}.", path);
+    let bytes = match serialize_plist_to_bytes(env, object, NSPropertyListXMLFormat_v1_0) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            log!("Warning: couldn't write plist to {:?}: {}", path, e);
+            return false;
+        }
+    };
+    // TODO: atomic writes
+    env.fs.write(path, &bytes).is_ok()
+}
+
+/// Check whether a [Value] contains any [Value::Uid]s. These are only used by
+/// `NSKeyedArchiver` and have no property list object equivalent, so
+/// [deserialize_plist] can't handle them and callers must reject them first.
+pub(super) fn contains_uid(value: &Value) -> bool {
+    match value {
+        Value::Uid(_) => true,
+        Value::Array(array) => array.iter().any(contains_uid),
+        Value::Dictionary(dict) => dict.values().any(contains_uid),
+        _ => false,
+    }
+}
+
+/// Convert a [Value] to the equivalent Objective-C property list object.
+/// The result is retained (+1). Containers are immutable.
+///
+/// The value must not contain any [Value::Uid]s, see [contains_uid].
+pub(super) fn deserialize_plist(env: &mut Environment, value: &Value) -> id {
+    deserialize_plist_with_mutability(env, value, NSPropertyListImmutable)
 }
 
-fn deserialize_plist(env: &mut Environment, value: &Value) -> id {
+/// Like [deserialize_plist], but containers (and possibly leaves) can be made
+/// mutable.
+fn deserialize_plist_with_mutability(
+    env: &mut Environment,
+    value: &Value,
+    mutability: NSPropertyListMutabilityOptions,
+) -> id {
     match value {
         Value::Array(array) => {
-            let array = array
+            let array: Vec<id> = array
                 .iter()
-                .map(|value| deserialize_plist(env, value))
+                .map(|value| deserialize_plist_with_mutability(env, value, mutability))
                 .collect();
-            ns_array::from_vec(env, array)
+            if mutability == NSPropertyListImmutable {
+                return ns_array::from_vec(env, array);
+            }
+            let count: NSUInteger = array.len().try_into().unwrap();
+            let ns_array: id = msg_class![env; NSMutableArray alloc];
+            let ns_array: id = msg![env; ns_array initWithCapacity:count];
+            for object in array {
+                () = msg![env; ns_array addObject:object];
+                release(env, object);
+            }
+            ns_array
         }
         Value::Dictionary(dict) => {
             let pairs: Vec<_> = dict
@@ -56,13 +156,21 @@ fn deserialize_plist(env: &mut Environment, value: &Value) -> id {
                 .map(|(key, value)| {
                     (
                         ns_string::from_rust_string(env, key.clone()),
-                        deserialize_plist(env, value),
+                        deserialize_plist_with_mutability(env, value, mutability),
                     )
                 })
                 .collect();
-            // Unlike ns_array::from_vec and ns_string::from_rust_string,
-            // this will retain the keys and values!
-            let ns_dict = ns_dictionary::dict_from_keys_and_objects(env, &pairs);
+            let ns_dict = if mutability == NSPropertyListImmutable {
+                // Unlike ns_array::from_vec and ns_string::from_rust_string,
+                // this will retain the keys and values!
+                ns_dictionary::dict_from_keys_and_objects(env, &pairs)
+            } else {
+                let ns_dict: id = msg_class![env; NSMutableDictionary new];
+                for &(key, value) in &pairs {
+                    () = msg![env; ns_dict setObject:value forKey:key];
+                }
+                ns_dict
+            };
             // ...so they need to be released.
             for (key, value) in pairs {
                 release(env, key);
@@ -76,14 +184,24 @@ fn deserialize_plist(env: &mut Environment, value: &Value) -> id {
             msg![env; number initWithBool:b]
         }
         Value::Data(d) => {
-            let length: NSUInteger = d.len().try_into().unwrap();
-            let alloc: MutPtr<u8> = env.mem.alloc(length).cast();
-            env.mem.bytes_at_mut(alloc, length).copy_from_slice(d);
-            let data: id = msg_class![env; NSData alloc];
-            msg![env; data initWithBytesNoCopy:alloc length:length]
+            let data = ns_data::from_rust_slice(env, d);
+            if mutability == NSPropertyListMutableContainersAndLeaves {
+                let mutable_data: id = msg![env; data mutableCopy];
+                release(env, data);
+                mutable_data
+            } else {
+                data
+            }
         }
-        Value::Date(_) => {
-            todo!("deserialize plist value: {:?}", value); // TODO
+        Value::Date(date) => {
+            let date: SystemTime = (*date).into();
+            let time_interval: NSTimeInterval = match date.duration_since(apple_epoch()) {
+                Ok(after) => after.as_secs_f64(),
+                Err(before) => -before.duration().as_secs_f64(),
+            };
+            let date: id =
+                msg_class![env; NSDate dateWithTimeIntervalSinceReferenceDate:time_interval];
+            retain(env, date)
         }
         Value::Integer(int) => {
             let number: id = msg_class![env; NSNumber alloc];
@@ -103,14 +221,263 @@ fn deserialize_plist(env: &mut Environment, value: &Value) -> id {
             let double: f64 = *real;
             msg![env; number initWithDouble:double]
         }
+        // TODO: NSMutableString for NSPropertyListMutableContainersAndLeaves,
+        // once we have it.
         Value::String(s) => ns_string::from_rust_string(env, s.clone()),
         Value::Uid(_) => {
-            // These are probably only used by NSKeyedUnarchiver, which does not
-            // currently use this code in our implementation.
-            unimplemented!("deserialize plist value: {:?}", value);
+            // These are only used by NSKeyedArchiver, which handles them
+            // itself. Everything else rejects them before getting here.
+            unreachable!("deserialize plist value: {:?}", value);
         }
         _ => {
             unreachable!() // enum is marked inexhaustive, but shouldn't be
         }
     }
 }
+
+fn is_kind_of(env: &mut Environment, object: id, class_name: &str) -> bool {
+    let class: Class = env.objc.get_known_class(class_name, &mut env.mem);
+    msg![env; object isKindOfClass:class]
+}
+
+/// Convert an Objective-C property list object (`NSDictionary`, `NSArray`,
+/// `NSString`, `NSNumber`, `NSData` or `NSDate`, nested arbitrarily) to the
+/// equivalent [Value]. Returns a description of the problem if the object
+/// isn't a valid property list.
+pub(super) fn serialize_plist(env: &mut Environment, object: id) -> Result<Value, String> {
+    if object == nil {
+        return Err("nil is not a property list object".to_string());
+    }
+
+    if is_kind_of(env, object, "NSDictionary") {
+        let keys: id = msg![env; object allKeys];
+        let count: NSUInteger = msg![env; keys count];
+        let mut dict = plist::Dictionary::new();
+        for i in 0..count {
+            let key: id = msg![env; keys objectAtIndex:i];
+            if !is_kind_of(env, key, "NSString") {
+                return Err(format!("Dictionary key {:?} is not a string", key));
+            }
+            let value: id = msg![env; object objectForKey:key];
+            let key = ns_string::to_rust_string(env, key).into_owned();
+            let value = serialize_plist(env, value)?;
+            dict.insert(key, value);
+        }
+        Ok(Value::Dictionary(dict))
+    } else if is_kind_of(env, object, "NSArray") {
+        let count: NSUInteger = msg![env; object count];
+        let mut array = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let value: id = msg![env; object objectAtIndex:i];
+            array.push(serialize_plist(env, value)?);
+        }
+        Ok(Value::Array(array))
+    } else if is_kind_of(env, object, "NSString") {
+        Ok(Value::String(
+            ns_string::to_rust_string(env, object).into_owned(),
+        ))
+    } else if is_kind_of(env, object, "NSNumber") {
+        Ok(match *env.objc.borrow::<NSNumberHostObject>(object) {
+            NSNumberHostObject::Bool(b) => Value::Boolean(b),
+            NSNumberHostObject::UnsignedLongLong(u) => Value::Integer(u.into()),
+            NSNumberHostObject::LongLong(i) => Value::Integer(i.into()),
+            NSNumberHostObject::Float(f) => Value::Real(f.into()),
+            NSNumberHostObject::Double(d) => Value::Real(d),
+        })
+    } else if is_kind_of(env, object, "NSData") {
+        let bytes: ConstPtr<u8> = msg![env; object bytes];
+        let length: NSUInteger = msg![env; object length];
+        let bytes = if length == 0 {
+            Vec::new()
+        } else {
+            env.mem.bytes_at(bytes, length).to_vec()
+        };
+        Ok(Value::Data(bytes))
+    } else if is_kind_of(env, object, "NSDate") {
+        let time_interval: NSTimeInterval = msg![env; object timeIntervalSinceReferenceDate];
+        let date = if time_interval >= 0.0 {
+            apple_epoch() + Duration::from_secs_f64(time_interval)
+        } else {
+            apple_epoch() - Duration::from_secs_f64(-time_interval)
+        };
+        Ok(Value::Date(date.into()))
+    } else {
+        let class: Class = msg![env; object class];
+        Err(format!(
+            "Object {:?} of class {:?} is not a property list object",
+            object,
+            env.objc.get_class_name(class),
+        ))
+    }
+}
+
+/// Serialize a property list object in the requested format.
+fn serialize_plist_to_bytes(
+    env: &mut Environment,
+    object: id,
+    format: NSPropertyListFormat,
+) -> Result<Vec<u8>, String> {
+    if format != NSPropertyListXMLFormat_v1_0 && format != NSPropertyListBinaryFormat_v1_0 {
+        return Err(format!("Property list format {} is not supported", format));
+    }
+    let value = serialize_plist(env, object)?;
+    let mut bytes = Vec::new();
+    let res = if format == NSPropertyListXMLFormat_v1_0 {
+        value.to_writer_xml(&mut bytes)
+    } else {
+        value.to_writer_binary(&mut bytes)
+    };
+    res.map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Parse a property list in any supported format. Returns the format and the
+/// root value.
+fn parse_plist_bytes(bytes: &[u8]) -> Result<(NSPropertyListFormat, Value), String> {
+    let format = if bytes.starts_with(b"bplist") {
+        NSPropertyListBinaryFormat_v1_0
+    } else {
+        NSPropertyListXMLFormat_v1_0
+    };
+    let value = Value::from_reader(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    if contains_uid(&value) {
+        return Err("Property list contains keyed archiver UIDs".to_string());
+    }
+    Ok((format, value))
+}
+
+/// Shared implementation of `dataFromPropertyList:format:errorDescription:`
+/// and `dataWithPropertyList:format:options:error:`. Returns an autoreleased
+/// `NSData*`.
+fn data_from_plist(
+    env: &mut Environment,
+    plist: id,
+    format: NSPropertyListFormat,
+) -> Result<id, String> {
+    let bytes = serialize_plist_to_bytes(env, plist, format)?;
+    let data = ns_data::from_rust_slice(env, &bytes);
+    Ok(autorelease(env, data))
+}
+
+/// Shared implementation of
+/// `propertyListFromData:mutabilityOption:format:errorDescription:` and
+/// `propertyListWithData:options:format:error:`. Returns an autoreleased
+/// object.
+fn plist_from_data(
+    env: &mut Environment,
+    data: id,
+    mutability: NSPropertyListMutabilityOptions,
+    format_ptr: MutPtr<NSPropertyListFormat>,
+) -> Result<id, String> {
+    if data == nil {
+        return Err("Data is nil".to_string());
+    }
+    let length: NSUInteger = msg![env; data length];
+    if length == 0 {
+        return Err("Data is empty".to_string());
+    }
+    let bytes = ns_data::to_rust_slice(env, data).to_vec();
+    let (format, value) = parse_plist_bytes(&bytes)?;
+    if !format_ptr.is_null() {
+        env.mem.write(format_ptr, format);
+    }
+    let plist = deserialize_plist_with_mutability(env, &value, mutability);
+    Ok(autorelease(env, plist))
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSPropertyListSerialization: NSObject
+
++ (bool)propertyList:(id)plist
+    isValidForFormat:(NSPropertyListFormat)format {
+    if format != NSPropertyListXMLFormat_v1_0 && format != NSPropertyListBinaryFormat_v1_0 {
+        return false;
+    }
+    serialize_plist(env, plist).is_ok()
+}
+
+// The older API, which reports errors with strings
++ (id)dataFromPropertyList:(id)plist
+                    format:(NSPropertyListFormat)format
+          errorDescription:(MutPtr<id>)error_string { // NSString**
+    match data_from_plist(env, plist, format) {
+        Ok(data) => data,
+        Err(description) => {
+            log!("Warning: dataFromPropertyList: failed: {}", description);
+            if !error_string.is_null() {
+                // The caller is responsible for releasing this string.
+                let description = ns_string::from_rust_string(env, description);
+                env.mem.write(error_string, description);
+            }
+            nil
+        }
+    }
+}
++ (id)propertyListFromData:(id)data // NSData*
+          mutabilityOption:(NSPropertyListMutabilityOptions)mutability
+                    format:(MutPtr<NSPropertyListFormat>)format
+          errorDescription:(MutPtr<id>)error_string { // NSString**
+    match plist_from_data(env, data, mutability, format) {
+        Ok(plist) => plist,
+        Err(description) => {
+            log!("Warning: propertyListFromData: failed: {}", description);
+            if !error_string.is_null() {
+                // The caller is responsible for releasing this string.
+                let description = ns_string::from_rust_string(env, description);
+                env.mem.write(error_string, description);
+            }
+            nil
+        }
+    }
+}
+
+// The newer API (iPhone OS 4.0 and later), which reports errors with NSError
++ (id)dataWithPropertyList:(id)plist
+                    format:(NSPropertyListFormat)format
+                   options:(NSPropertyListWriteOptions)_options
+                     error:(MutPtr<id>)error { // NSError**
+    match data_from_plist(env, plist, format) {
+        Ok(data) => data,
+        Err(description) => {
+            log!("Warning: dataWithPropertyList: failed: {}", description);
+            if !error.is_null() {
+                let new_error = error_with_description(
+                    env,
+                    NSCocoaErrorDomain,
+                    NSPropertyListWriteInvalidError,
+                    &description,
+                );
+                env.mem.write(error, new_error);
+            }
+            nil
+        }
+    }
+}
++ (id)propertyListWithData:(id)data // NSData*
+                   options:(NSPropertyListReadOptions)options
+                    format:(MutPtr<NSPropertyListFormat>)format
+                     error:(MutPtr<id>)error { // NSError**
+    match plist_from_data(env, data, options, format) {
+        Ok(plist) => plist,
+        Err(description) => {
+            log!("Warning: propertyListWithData: failed: {}", description);
+            if !error.is_null() {
+                let new_error = error_with_description(
+                    env,
+                    NSCocoaErrorDomain,
+                    NSPropertyListReadCorruptError,
+                    &description,
+                );
+                env.mem.write(error, new_error);
+            }
+            nil
+        }
+    }
+}
+
+@end
+
+};
@@ -11,6 +11,7 @@
 use super::{ns_string, ns_timer};
 use crate::dyld::{ConstantExports, HostConstant};
 use crate::frameworks::audio_toolbox::audio_queue::{handle_audio_queue, AudioQueueRef};
+use crate::frameworks::core_animation::ca_display_link;
 use crate::frameworks::core_foundation::cf_run_loop::{
     kCFRunLoopCommonModes, kCFRunLoopDefaultMode, CFRunLoopRef,
 };
@@ -48,6 +49,9 @@ struct NSRunLoopHostObject {
     /// Strong references to `NSTimer*` in no particular order. Timers are owned
     /// by the run loop. The timer must remove itself when invalidated.
     timers: Vec<id>,
+    /// Strong references to `CADisplayLink*` in the order they were added.
+    /// The display link must remove itself when invalidated.
+    display_links: Vec<id>,
 }
 impl HostObject for NSRunLoopHostObject {}
 
@@ -64,6 +68,7 @@ pub const CLASSES: ClassExports = objc_classes! {
         let host_object = Box::new(NSRunLoopHostObject {
             audio_queues: Vec::new(),
             timers: Vec::new(),
+            display_links: Vec::new(),
         });
         let new = env.objc.alloc_static_object(this, host_object, &mut env.mem);
         env.framework_state.foundation.ns_run_loop.main_thread_run_loop = Some(new);
@@ -159,6 +164,33 @@ pub(super) fn remove_timer(env: &mut Environment, run_loop: id, timer: id) {
     }
 }
 
+/// For use by `CADisplayLink`.
+/// TODO: Handle run loop modes. Currently assumes the common modes.
+pub fn add_display_link(env: &mut Environment, run_loop: id, display_link: id) {
+    retain(env, display_link);
+    let display_links = &mut env
+        .objc
+        .borrow_mut::<NSRunLoopHostObject>(run_loop)
+        .display_links;
+    assert!(!display_links.contains(&display_link));
+    display_links.push(display_link);
+}
+
+/// For use by `CADisplayLink` so it can remove itself once it's invalidated or
+/// removed from the run loop.
+pub fn remove_display_link(env: &mut Environment, run_loop: id, display_link: id) {
+    let display_links = &mut env
+        .objc
+        .borrow_mut::<NSRunLoopHostObject>(run_loop)
+        .display_links;
+    let idx = display_links
+        .iter()
+        .position(|&item| item == display_link)
+        .unwrap();
+    display_links.remove(idx);
+    release(env, display_link);
+}
+
 /// Run the run loop for just a single iteration. This is a special mode just
 /// for the app picker, since we don't have `runMode:beforeDate:` or
 /// `runUntilDate:` yet. (TODO: implement those to replace this.)
@@ -176,6 +208,7 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
     // Temporary vectors used to track things without needing a reference to the
     // environment or to lock the object. Re-used each iteration for efficiency.
     let mut timers_tmp = Vec::new();
+    let mut display_links_tmp = Vec::new();
     let mut audio_queues_tmp = Vec::new();
 
     fn limit_sleep_time(current: &mut Option<Instant>, new: Option<Instant>) {
@@ -195,6 +228,28 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
         let next_due = uikit::handle_events(env);
         limit_sleep_time(&mut sleep_until, next_due);
 
+        // Display links are handled before compositing so that any changes
+        // they make to the UI are visible straight away.
+        assert!(display_links_tmp.is_empty());
+        display_links_tmp.extend_from_slice(
+            &env.objc
+                .borrow::<NSRunLoopHostObject>(run_loop)
+                .display_links,
+        );
+
+        // An earlier display link's callback might invalidate or release a
+        // later one, so they all need to be kept alive until the end.
+        for &display_link in &display_links_tmp {
+            retain(env, display_link);
+        }
+        for &display_link in &display_links_tmp {
+            let next_due = ca_display_link::handle_display_link(env, display_link);
+            limit_sleep_time(&mut sleep_until, next_due);
+        }
+        for display_link in display_links_tmp.drain(..) {
+            release(env, display_link);
+        }
+
         let next_due = core_animation::recomposite_if_necessary(env);
         limit_sleep_time(&mut sleep_until, next_due);
 
@@ -12,8 +12,8 @@ mod path_algorithms;
 
 use super::ns_array;
 use super::{
-    NSComparisonResult, NSNotFound, NSOrderedAscending, NSOrderedDescending, NSOrderedSame,
-    NSRange, NSUInteger,
+    NSComparisonResult, NSInteger, NSNotFound, NSOrderedAscending, NSOrderedDescending,
+    NSOrderedSame, NSRange, NSUInteger,
 };
 use crate::abi::VaList;
 use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
@@ -232,6 +232,42 @@ pub fn with_format(env: &mut Environment, format: id, args: VaList) -> String {
     String::from_utf8(res).unwrap()
 }
 
+/// Get the part of a string that `floatValue` and `doubleValue` parse: after
+/// any leading whitespace, an optional sign, digits with an optional decimal
+/// point, and an optional exponent, like `strtod()` (minus hex, infinity and
+/// NaN).
+fn float_prefix(st: &str) -> &str {
+    let st = st.trim_start();
+    let bytes = st.as_bytes();
+    let skip_digits = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut end = 0;
+    if matches!(bytes.first(), Some(b'+' | b'-')) {
+        end += 1;
+    }
+    end = skip_digits(end);
+    if bytes.get(end) == Some(&b'.') {
+        end = skip_digits(end + 1);
+    }
+    // The exponent only counts if it has at least one digit.
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let mut exponent_start = end + 1;
+        if matches!(bytes.get(exponent_start), Some(b'+' | b'-')) {
+            exponent_start += 1;
+        }
+        let exponent_end = skip_digits(exponent_start);
+        if exponent_end > exponent_start {
+            end = exponent_end;
+        }
+    }
+    &st[..end]
+}
+
 fn from_rust_ordering(ordering: std::cmp::Ordering) -> NSComparisonResult {
     match ordering {
         std::cmp::Ordering::Less => NSOrderedAscending,
@@ -887,16 +923,8 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (f32)floatValue {
     let st = to_rust_string(env, this);
-    let st = st.trim_start();
-    let mut cutoff = st.len();
-    for (i, c) in st.char_indices() {
-        if !c.is_ascii_digit() && c != '.' && c != '+' && c != '-' {
-            cutoff = i;
-            break;
-        }
-    }
     // TODO: handle over/underflow properly
-    st[..cutoff].parse().unwrap_or(0.0)
+    float_prefix(&st).parse().unwrap_or(0.0)
 }
 
 - (i32)intValue {
@@ -912,6 +940,15 @@ pub const CLASSES: ClassExports = objc_classes! {
     // TODO: handle over/underflow properly
     st[..cutoff].parse().unwrap_or(0)
 }
+- (NSInteger)integerValue {
+    msg![env; this intValue]
+}
+
+- (f64)doubleValue {
+    let st = to_rust_string(env, this);
+    // TODO: handle over/underflow properly
+    float_prefix(&st).parse().unwrap_or(0.0)
+}
 
 @end
 
@@ -5,17 +5,28 @@
  */
 //! `NSUserDefaults`.
 //!
+//! The app's defaults are stored in `Library/Preferences/<bundle id>.plist` in
+//! its sandbox, as a binary property list, like on iPhone OS. The file is read
+//! when the defaults are first used, and written by `synchronize` and when the
+//! app terminates.
+//!
 //! References:
 //! - Apple's [Preferences and Settings Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/UserDefaults/AboutPreferenceDomains/AboutPreferenceDomains.html).
 
 use super::ns_dictionary::dict_from_keys_and_objects;
-use super::ns_string;
-use crate::objc::{id, msg_class, objc_classes, ClassExports};
+use super::ns_property_list_serialization::{contains_uid, deserialize_plist, serialize_plist};
+use super::{ns_string, NSInteger, NSUInteger};
+use crate::fs::GuestPathBuf;
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, Class, ClassExports, HostObject,
+};
 use crate::Environment;
+use plist::Value;
+use std::io::Cursor;
 
 #[derive(Default)]
 pub struct State {
-    /// `NSDictionary*`
+    /// `NSUserDefaults*`
     standard_defaults: Option<id>,
 }
 impl State {
@@ -24,6 +35,128 @@ impl State {
     }
 }
 
+struct NSUserDefaultsHostObject {
+    /// `NSMutableDictionary*` for the app's own domain. This is the only
+    /// domain that is persisted.
+    app_domain: id,
+    /// `NSDictionary*` for the global domain (`AppleLanguages` etc).
+    global_domain: id,
+    /// `NSMutableDictionary*` for the registration domain, i.e. the values
+    /// passed to `registerDefaults:`.
+    registration_domain: id,
+    /// Whether the app domain has changed since it was last written.
+    dirty: bool,
+}
+impl HostObject for NSUserDefaultsHostObject {}
+
+fn preferences_path(env: &Environment) -> GuestPathBuf {
+    env.fs
+        .home_directory()
+        .join("Library/Preferences")
+        .join(format!("{}.plist", env.bundle.bundle_identifier()))
+}
+
+/// Read the app domain from the preferences file. Returns a new
+/// `NSMutableDictionary*`, which is empty if the file doesn't exist or can't
+/// be read.
+fn load_app_domain(env: &mut Environment) -> id {
+    let path = preferences_path(env);
+    let empty: id = msg_class![env; NSMutableDictionary new];
+    let Ok(bytes) = env.fs.read(&path) else {
+        log_dbg!("No user defaults at {:?}, starting afresh.", path);
+        return empty;
+    };
+    let root = match Value::from_reader(Cursor::new(bytes)) {
+        Ok(root @ Value::Dictionary(_)) if !contains_uid(&root) => root,
+        Ok(_) => {
+            log!(
+                "Warning: user defaults at {:?} are not a property list dictionary, ignoring them.",
+                path
+            );
+            return empty;
+        }
+        Err(e) => {
+            log!("Warning: couldn't parse user defaults at {:?}: {}", path, e);
+            return empty;
+        }
+    };
+    log_dbg!("Loaded user defaults from {:?}.", path);
+    release(env, empty);
+    let dict = deserialize_plist(env, &root);
+    let mutable_dict: id = msg![env; dict mutableCopy];
+    release(env, dict);
+    mutable_dict
+}
+
+/// Write the app domain to the preferences file, if it has changed.
+fn save_app_domain(env: &mut Environment, defaults: id) -> bool {
+    let &NSUserDefaultsHostObject {
+        app_domain, dirty, ..
+    } = env.objc.borrow(defaults);
+    if !dirty {
+        return true;
+    }
+
+    let path = preferences_path(env);
+    let value = match serialize_plist(env, app_domain) {
+        Ok(value) => value,
+        Err(e) => {
+            log!("Warning: couldn't serialize user defaults: {}", e);
+            return false;
+        }
+    };
+    let mut bytes = Vec::new();
+    value.to_writer_binary(&mut bytes).unwrap();
+    if env.fs.write(&path, &bytes).is_err() {
+        log!("Warning: couldn't write user defaults to {:?}", path);
+        return false;
+    }
+    log_dbg!("Wrote user defaults to {:?}.", path);
+    env.objc
+        .borrow_mut::<NSUserDefaultsHostObject>(defaults)
+        .dirty = false;
+    true
+}
+
+/// For use when the app is terminating: write the standard user defaults to
+/// disk, if they have been used.
+pub fn synchronize_standard_defaults(env: &mut Environment) {
+    if let Some(defaults) = State::get(env).standard_defaults {
+        save_app_domain(env, defaults);
+    }
+}
+
+/// Shared implementation of `stringForKey:` etc: get the object for a key,
+/// if it is an instance of the named class.
+fn object_of_class_for_key(env: &mut Environment, this: id, key: id, class_name: &str) -> id {
+    let object: id = msg![env; this objectForKey:key];
+    if object == nil {
+        return nil;
+    }
+    let class: Class = env.objc.get_known_class(class_name, &mut env.mem);
+    if msg![env; object isKindOfClass:class] {
+        object
+    } else {
+        nil
+    }
+}
+
+/// Shared implementation of `integerForKey:` etc: get the object for a key,
+/// if it is something that can provide a number (`NSNumber` or `NSString`).
+fn number_like_for_key(env: &mut Environment, this: id, key: id) -> id {
+    let object: id = msg![env; this objectForKey:key];
+    if object == nil {
+        return nil;
+    }
+    for class_name in ["NSNumber", "NSString"] {
+        let class: Class = env.objc.get_known_class(class_name, &mut env.mem);
+        if msg![env; object isKindOfClass:class] {
+            return object;
+        }
+    }
+    nil
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -37,13 +170,189 @@ pub const CLASSES: ClassExports = objc_classes! {
         // TODO: Are there other default keys we need to set?
         let langs_value: id = msg_class![env; NSLocale preferredLanguages];
         let langs_key: id = ns_string::get_static_str(env, "AppleLanguages");
-        let new = dict_from_keys_and_objects(env, &[(langs_key, langs_value)]);
+        let global_domain = dict_from_keys_and_objects(env, &[(langs_key, langs_value)]);
+        let registration_domain: id = msg_class![env; NSMutableDictionary new];
+        let app_domain = load_app_domain(env);
+
+        let host_object = Box::new(NSUserDefaultsHostObject {
+            app_domain,
+            global_domain,
+            registration_domain,
+            dirty: false,
+        });
+        let new = env.objc.alloc_static_object(this, host_object, &mut env.mem);
         State::get(env).standard_defaults = Some(new);
         new
     }
 }
 
-// TODO: plist methods etc
++ (())resetStandardUserDefaults {
+    // The real implementation discards the object, but ours lives forever, so
+    // all that matters is that nothing is lost.
+    synchronize_standard_defaults(env);
+}
+
+- (id)init {
+    // Only the standard user defaults are supported.
+    let standard: id = msg_class![env; NSUserDefaults standardUserDefaults];
+    if this != standard {
+        // release is a no-op for this class, so the object must be destroyed
+        // directly.
+        env.objc.dealloc_object(this, &mut env.mem);
+    }
+    standard
+}
+
+- (id)retain { this }
+- (())release {}
+- (id)autorelease { this }
+
+- (id)objectForKey:(id)key { // NSString*
+    let &NSUserDefaultsHostObject {
+        app_domain,
+        global_domain,
+        registration_domain,
+        ..
+    } = env.objc.borrow(this);
+    for domain in [app_domain, global_domain, registration_domain] {
+        let object: id = msg![env; domain objectForKey:key];
+        if object != nil {
+            return object;
+        }
+    }
+    nil
+}
+- (())setObject:(id)object
+         forKey:(id)key { // NSString*
+    if object == nil {
+        return msg![env; this removeObjectForKey:key];
+    }
+    let host_object = env.objc.borrow_mut::<NSUserDefaultsHostObject>(this);
+    host_object.dirty = true;
+    let app_domain = host_object.app_domain;
+    // Values must be immutable copies, so later changes to a mutable object
+    // don't affect the stored value.
+    let object: id = msg![env; object copy];
+    () = msg![env; app_domain setObject:object forKey:key];
+    release(env, object);
+}
+- (())removeObjectForKey:(id)key { // NSString*
+    let host_object = env.objc.borrow_mut::<NSUserDefaultsHostObject>(this);
+    host_object.dirty = true;
+    let app_domain = host_object.app_domain;
+    () = msg![env; app_domain removeObjectForKey:key];
+}
+
+- (id)stringForKey:(id)key { // NSString*
+    let object = number_like_for_key(env, this, key);
+    if object == nil {
+        return nil;
+    }
+    let string_class: Class = msg_class![env; NSString class];
+    if msg![env; object isKindOfClass:string_class] {
+        object
+    } else {
+        msg![env; object stringValue]
+    }
+}
+- (id)arrayForKey:(id)key { // NSString*
+    object_of_class_for_key(env, this, key, "NSArray")
+}
+- (id)stringArrayForKey:(id)key { // NSString*
+    let array = object_of_class_for_key(env, this, key, "NSArray");
+    if array == nil {
+        return nil;
+    }
+    let string_class: Class = msg_class![env; NSString class];
+    let count: NSUInteger = msg![env; array count];
+    for i in 0..count {
+        let object: id = msg![env; array objectAtIndex:i];
+        if !msg![env; object isKindOfClass:string_class] {
+            return nil;
+        }
+    }
+    array
+}
+- (id)dictionaryForKey:(id)key { // NSString*
+    object_of_class_for_key(env, this, key, "NSDictionary")
+}
+- (id)dataForKey:(id)key { // NSString*
+    object_of_class_for_key(env, this, key, "NSData")
+}
+
+- (NSInteger)integerForKey:(id)key { // NSString*
+    let object = number_like_for_key(env, this, key);
+    if object == nil {
+        return 0;
+    }
+    msg![env; object integerValue]
+}
+- (f32)floatForKey:(id)key { // NSString*
+    let object = number_like_for_key(env, this, key);
+    if object == nil {
+        return 0.0;
+    }
+    msg![env; object floatValue]
+}
+- (f64)doubleForKey:(id)key { // NSString*
+    let object = number_like_for_key(env, this, key);
+    if object == nil {
+        return 0.0;
+    }
+    msg![env; object doubleValue]
+}
+- (bool)boolForKey:(id)key { // NSString*
+    let object = number_like_for_key(env, this, key);
+    if object == nil {
+        return false;
+    }
+    msg![env; object boolValue]
+}
+
+- (())setInteger:(NSInteger)value
+          forKey:(id)key { // NSString*
+    let number: id = msg_class![env; NSNumber numberWithInteger:value];
+    msg![env; this setObject:number forKey:key]
+}
+- (())setFloat:(f32)value
+        forKey:(id)key { // NSString*
+    let number: id = msg_class![env; NSNumber numberWithFloat:value];
+    msg![env; this setObject:number forKey:key]
+}
+- (())setDouble:(f64)value
+         forKey:(id)key { // NSString*
+    let number: id = msg_class![env; NSNumber numberWithDouble:value];
+    msg![env; this setObject:number forKey:key]
+}
+- (())setBool:(bool)value
+       forKey:(id)key { // NSString*
+    let number: id = msg_class![env; NSNumber numberWithBool:value];
+    msg![env; this setObject:number forKey:key]
+}
+
+- (())registerDefaults:(id)defaults { // NSDictionary*
+    let registration_domain = env.objc.borrow::<NSUserDefaultsHostObject>(this).registration_domain;
+    () = msg![env; registration_domain addEntriesFromDictionary:defaults];
+}
+
+- (id)dictionaryRepresentation {
+    let &NSUserDefaultsHostObject {
+        app_domain,
+        global_domain,
+        registration_domain,
+        ..
+    } = env.objc.borrow(this);
+    let dict: id = msg_class![env; NSMutableDictionary new];
+    // Lowest priority first, so higher-priority domains overwrite it.
+    for domain in [registration_domain, global_domain, app_domain] {
+        () = msg![env; dict addEntriesFromDictionary:domain];
+    }
+    autorelease(env, dict)
+}
+
+- (bool)synchronize {
+    save_app_domain(env, this)
+}
 
 @end
 
@@ -5,14 +5,33 @@
  */
 //! The `NSValue` class cluster, including `NSNumber`.
 
-use super::NSUInteger;
+use super::{
+    NSComparisonResult, NSInteger, NSOrderedAscending, NSOrderedDescending, NSOrderedSame,
+    NSUInteger,
+};
+use crate::frameworks::core_animation::ca_transform_3d::CATransform3D;
+use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
+use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
 use crate::frameworks::foundation::ns_string::from_rust_string;
 use crate::objc::{
     autorelease, id, msg, msg_class, objc_classes, retain, Class, ClassExports, HostObject,
     NSZonePtr,
 };
 
-enum NSNumberHostObject {
+/// Host object for the geometry values that UIKit's and Core Animation's
+/// `NSValue` extensions (`UIGeometry.h`, `CATransform3D.h`) can box.
+#[derive(Copy, Clone, Debug)]
+pub enum NSValueHostObject {
+    CGPoint(CGPoint),
+    CGSize(CGSize),
+    CGRect(CGRect),
+    CGAffineTransform(CGAffineTransform),
+    CATransform3D(CATransform3D),
+}
+impl HostObject for NSValueHostObject {}
+
+#[derive(Copy, Clone)]
+pub(super) enum NSNumberHostObject {
     Bool(bool),
     UnsignedLongLong(u64),
     LongLong(i64),
@@ -20,15 +39,124 @@ enum NSNumberHostObject {
     Double(f64),
 }
 impl HostObject for NSNumberHostObject {}
+impl NSNumberHostObject {
+    fn as_i64(self) -> i64 {
+        match self {
+            NSNumberHostObject::Bool(value) => value as i64,
+            NSNumberHostObject::UnsignedLongLong(value) => value as i64,
+            NSNumberHostObject::LongLong(value) => value,
+            NSNumberHostObject::Float(value) => value as i64,
+            NSNumberHostObject::Double(value) => value as i64,
+        }
+    }
+    fn as_u64(self) -> u64 {
+        match self {
+            NSNumberHostObject::UnsignedLongLong(value) => value,
+            _ => self.as_i64() as u64,
+        }
+    }
+    fn as_f64(self) -> f64 {
+        match self {
+            NSNumberHostObject::Bool(value) => value as i64 as f64,
+            NSNumberHostObject::UnsignedLongLong(value) => value as f64,
+            NSNumberHostObject::LongLong(value) => value as f64,
+            NSNumberHostObject::Float(value) => value as f64,
+            NSNumberHostObject::Double(value) => value,
+        }
+    }
+    fn is_integer(self) -> bool {
+        !matches!(
+            self,
+            NSNumberHostObject::Float(_) | NSNumberHostObject::Double(_)
+        )
+    }
+    /// Numerical comparison, regardless of the types of the numbers.
+    fn compare(self, other: Self) -> std::cmp::Ordering {
+        if self.is_integer() && other.is_integer() {
+            let a = match self {
+                NSNumberHostObject::UnsignedLongLong(value) => value as i128,
+                _ => self.as_i64() as i128,
+            };
+            let b = match other {
+                NSNumberHostObject::UnsignedLongLong(value) => value as i128,
+                _ => other.as_i64() as i128,
+            };
+            a.cmp(&b)
+        } else {
+            // NaN is treated as equal to everything, which is at least
+            // consistent.
+            self.as_f64()
+                .partial_cmp(&other.as_f64())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        }
+    }
+}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
 
-// NSValue is an abstract class. None of the things it should provide are
-// implemented here yet (TODO).
+// NSValue is an abstract class. Only the UIKit geometry extensions are
+// implemented here so far (TODO: valueWithBytes:objCType: etc).
 @implementation NSValue: NSObject
 
++ (id)valueWithCGPoint:(CGPoint)point {
+    let host_object = Box::new(NSValueHostObject::CGPoint(point));
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, new)
+}
++ (id)valueWithCGSize:(CGSize)size {
+    let host_object = Box::new(NSValueHostObject::CGSize(size));
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, new)
+}
++ (id)valueWithCGRect:(CGRect)rect {
+    let host_object = Box::new(NSValueHostObject::CGRect(rect));
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, new)
+}
++ (id)valueWithCGAffineTransform:(CGAffineTransform)transform {
+    let host_object = Box::new(NSValueHostObject::CGAffineTransform(transform));
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, new)
+}
++ (id)valueWithCATransform3D:(CATransform3D)transform {
+    let host_object = Box::new(NSValueHostObject::CATransform3D(transform));
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, new)
+}
+
+- (CGPoint)CGPointValue {
+    let &NSValueHostObject::CGPoint(point) = env.objc.borrow(this) else {
+        panic!("{:?} is not a CGPoint value", this);
+    };
+    point
+}
+- (CGSize)CGSizeValue {
+    let &NSValueHostObject::CGSize(size) = env.objc.borrow(this) else {
+        panic!("{:?} is not a CGSize value", this);
+    };
+    size
+}
+- (CGRect)CGRectValue {
+    let &NSValueHostObject::CGRect(rect) = env.objc.borrow(this) else {
+        panic!("{:?} is not a CGRect value", this);
+    };
+    rect
+}
+- (CGAffineTransform)CGAffineTransformValue {
+    let &NSValueHostObject::CGAffineTransform(transform) = env.objc.borrow(this) else {
+        panic!("{:?} is not a CGAffineTransform value", this);
+    };
+    transform
+}
+- (CATransform3D)CATransform3DValue {
+    let &NSValueHostObject::CATransform3D(transform) = env.objc.borrow(this) else {
+        panic!("{:?} is not a CATransform3D value", this);
+    };
+    transform
+}
+
 // NSCopying implementation
 - (id)copyWithZone:(NSZonePtr)_zone {
     retain(env, this)
@@ -84,7 +212,31 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, new)
 }
 
-// TODO: types other than booleans and long longs
++ (id)numberWithInt:(i32)value {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithInt:value];
+    autorelease(env, new)
+}
+
++ (id)numberWithUnsignedInt:(u32)value {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithUnsignedInt:value];
+    autorelease(env, new)
+}
+
++ (id)numberWithInteger:(NSInteger)value {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithInteger:value];
+    autorelease(env, new)
+}
+
++ (id)numberWithUnsignedInteger:(NSUInteger)value {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithUnsignedInteger:value];
+    autorelease(env, new)
+}
+
+// TODO: types other than booleans, integers and floating-point numbers
 
 - (id)initWithBool:(bool)value {
     *env.objc.borrow_mut(this) = NSNumberHostObject::Bool(value);
@@ -111,6 +263,28 @@ pub const CLASSES: ClassExports = objc_classes! {
     this
 }
 
+// Smaller integer types are stored as long longs, which gives the same results
+// from the accessors.
+- (id)initWithInt:(i32)value {
+    *env.objc.borrow_mut(this) = NSNumberHostObject::LongLong(value.into());
+    this
+}
+
+- (id)initWithUnsignedInt:(u32)value {
+    *env.objc.borrow_mut(this) = NSNumberHostObject::UnsignedLongLong(value.into());
+    this
+}
+
+- (id)initWithInteger:(NSInteger)value {
+    *env.objc.borrow_mut(this) = NSNumberHostObject::LongLong(value.into());
+    this
+}
+
+- (id)initWithUnsignedInteger:(NSUInteger)value {
+    *env.objc.borrow_mut(this) = NSNumberHostObject::UnsignedLongLong(value.into());
+    this
+}
+
 - (id)description {
     match env.objc.borrow(this) {
         NSNumberHostObject::Bool(value) => from_rust_string(env, (*value as i32).to_string()),
@@ -120,11 +294,20 @@ pub const CLASSES: ClassExports = objc_classes! {
         NSNumberHostObject::Double(value) => from_rust_string(env, value.to_string())
     }
 }
+- (id)stringValue {
+    let description: id = msg![env; this description];
+    autorelease(env, description)
+}
+
 - (NSUInteger)hash {
-    let &NSNumberHostObject::Bool(value) = env.objc.borrow(this) else {
-        todo!();
-    };
-    super::hash_helper(&value)
+    let &value = env.objc.borrow::<NSNumberHostObject>(this);
+    // Numbers that compare equal must have the same hash, whatever their type.
+    let as_f64 = value.as_f64();
+    if value.is_integer() || as_f64.fract() == 0.0 {
+        super::hash_helper(&value.as_i64())
+    } else {
+        super::hash_helper(&as_f64.to_bits())
+    }
 }
 - (bool)isEqualTo:(id)other {
     if this == other {
@@ -134,13 +317,71 @@ pub const CLASSES: ClassExports = objc_classes! {
     if !msg![env; other isKindOfClass:class] {
         return false;
     }
-    let &NSNumberHostObject::Bool(a) = env.objc.borrow(this) else {
-        todo!();
-    };
-    let &NSNumberHostObject::Bool(b) = env.objc.borrow(other) else {
-        todo!();
-    };
-    a == b
+    msg![env; this isEqualToNumber:other]
+}
+- (bool)isEqualToNumber:(id)other { // NSNumber*
+    let &a = env.objc.borrow::<NSNumberHostObject>(this);
+    let &b = env.objc.borrow::<NSNumberHostObject>(other);
+    a.compare(b).is_eq()
+}
+- (NSComparisonResult)compare:(id)other { // NSNumber*
+    let &a = env.objc.borrow::<NSNumberHostObject>(this);
+    let &b = env.objc.borrow::<NSNumberHostObject>(other);
+    match a.compare(b) {
+        std::cmp::Ordering::Less => NSOrderedAscending,
+        std::cmp::Ordering::Equal => NSOrderedSame,
+        std::cmp::Ordering::Greater => NSOrderedDescending,
+    }
+}
+
+- (bool)boolValue {
+    let &value = env.objc.borrow::<NSNumberHostObject>(this);
+    match value {
+        NSNumberHostObject::Bool(value) => value,
+        _ => value.as_f64() != 0.0,
+    }
+}
+- (i8)charValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_i64() as i8
+}
+- (u8)unsignedCharValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_u64() as u8
+}
+- (i16)shortValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_i64() as i16
+}
+- (u16)unsignedShortValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_u64() as u16
+}
+- (i32)intValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_i64() as i32
+}
+- (u32)unsignedIntValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_u64() as u32
+}
+- (i32)longValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_i64() as i32
+}
+- (u32)unsignedLongValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_u64() as u32
+}
+- (NSInteger)integerValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_i64() as NSInteger
+}
+- (NSUInteger)unsignedIntegerValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_u64() as NSUInteger
+}
+- (i64)longLongValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_i64()
+}
+- (u64)unsignedLongLongValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_u64()
+}
+- (f32)floatValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_f64() as f32
+}
+- (f64)doubleValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_f64()
 }
 
 // TODO: accessors etc
@@ -0,0 +1,290 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The GameKit framework.
+//!
+//! There is no Game Center server for touchHLE to talk to, so this is a local
+//! stand-in: the local player always authenticates successfully, and scores
+//! and achievements are stored in a file in the app's `Library` directory,
+//! where they survive between runs. Only the local player ever appears on
+//! leaderboards.
+
+pub mod gk_achievement;
+pub mod gk_leaderboard;
+pub mod gk_leaderboard_view_controller;
+pub mod gk_local_player;
+pub mod gk_score;
+
+use crate::abi::CallFromHost;
+use crate::frameworks::foundation::ns_error::error_with_description;
+use crate::frameworks::foundation::NSInteger;
+use crate::fs::GuestPathBuf;
+use crate::libc::blocks::{_Block_copy, _Block_release, block_invoke_function};
+use crate::mem::MutVoidPtr;
+use crate::objc::{id, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject};
+use crate::Environment;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+#[derive(Default)]
+pub struct State {
+    gk_local_player: gk_local_player::State,
+    /// Loaded lazily, see [Storage::get].
+    storage: Option<Storage>,
+}
+
+/// Name of the file, inside the app's `Library` directory, that stores the
+/// GameKit data.
+const STORAGE_FILE_NAME: &str = "GameKit.plist";
+
+/// Domain of the `NSError`s GameKit passes to completion handlers.
+const GKErrorDomain: &str = "GKErrorDomain";
+/// From the `GKErrorCode` enum.
+const GKErrorUnknown: NSInteger = 1;
+/// From the `GKErrorCode` enum.
+const GKErrorInvalidParameter: NSInteger = 17;
+
+#[derive(Clone)]
+struct StoredScore {
+    value: i64,
+    /// Seconds since the Apple epoch, like `NSDate`.
+    date: f64,
+}
+
+#[derive(Clone)]
+struct StoredAchievement {
+    percent_complete: f64,
+    /// Seconds since the Apple epoch, like `NSDate`.
+    date: f64,
+}
+
+/// The persistent GameKit data for the current app.
+#[derive(Default)]
+struct Storage {
+    /// Keys are leaderboard categories.
+    scores: HashMap<String, Vec<StoredScore>>,
+    /// Keys are achievement identifiers.
+    achievements: HashMap<String, StoredAchievement>,
+}
+impl Storage {
+    fn path(env: &Environment) -> GuestPathBuf {
+        env.fs
+            .home_directory()
+            .join("Library")
+            .join(STORAGE_FILE_NAME)
+    }
+
+    fn get(env: &mut Environment) -> &mut Self {
+        if env.framework_state.game_kit.storage.is_none() {
+            let path = Self::path(env);
+            let storage = match env.fs.read(&path) {
+                Ok(bytes) => match plist::Value::from_reader(Cursor::new(bytes)) {
+                    Ok(value) => Self::from_plist(&value),
+                    Err(e) => {
+                        log!("Couldn't read GameKit data from {:?}: {}", path, e);
+                        Storage::default()
+                    }
+                },
+                Err(()) => Storage::default(),
+            };
+            env.framework_state.game_kit.storage = Some(storage);
+        }
+        env.framework_state.game_kit.storage.as_mut().unwrap()
+    }
+
+    fn save(env: &mut Environment) {
+        let path = Self::path(env);
+        let value = Self::get(env).to_plist();
+        let mut bytes = Vec::new();
+        value.to_writer_binary(&mut bytes).unwrap();
+        if env.fs.write(&path, &bytes).is_err() {
+            log!("Couldn't write GameKit data to {:?}", path);
+        }
+    }
+
+    fn from_plist(value: &plist::Value) -> Self {
+        let mut storage = Storage::default();
+        let Some(root) = value.as_dictionary() else {
+            log!("Warning: GameKit data is malformed, ignoring it.");
+            return storage;
+        };
+
+        if let Some(leaderboards) = root.get("Leaderboards").and_then(|v| v.as_dictionary()) {
+            for (category, scores) in leaderboards {
+                let scores = scores
+                    .as_array()
+                    .map(|a| a.as_slice())
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|score| {
+                        let score = score.as_dictionary()?;
+                        Some(StoredScore {
+                            value: score.get("value")?.as_signed_integer()?,
+                            date: score.get("date")?.as_real()?,
+                        })
+                    })
+                    .collect();
+                storage.scores.insert(category.clone(), scores);
+            }
+        }
+
+        if let Some(achievements) = root.get("Achievements").and_then(|v| v.as_dictionary()) {
+            for (identifier, achievement) in achievements {
+                let Some(achievement) = achievement.as_dictionary() else {
+                    continue;
+                };
+                let (Some(percent_complete), Some(date)) = (
+                    achievement.get("percentComplete").and_then(|v| v.as_real()),
+                    achievement.get("date").and_then(|v| v.as_real()),
+                ) else {
+                    continue;
+                };
+                storage.achievements.insert(
+                    identifier.clone(),
+                    StoredAchievement {
+                        percent_complete,
+                        date,
+                    },
+                );
+            }
+        }
+
+        storage
+    }
+
+    fn to_plist(&self) -> plist::Value {
+        let mut leaderboards = plist::Dictionary::new();
+        for (category, scores) in &self.scores {
+            let scores = scores
+                .iter()
+                .map(|score| {
+                    let mut dict = plist::Dictionary::new();
+                    dict.insert("value".to_string(), score.value.into());
+                    dict.insert("date".to_string(), score.date.into());
+                    plist::Value::Dictionary(dict)
+                })
+                .collect();
+            leaderboards.insert(category.clone(), plist::Value::Array(scores));
+        }
+
+        let mut achievements = plist::Dictionary::new();
+        for (identifier, achievement) in &self.achievements {
+            let mut dict = plist::Dictionary::new();
+            dict.insert(
+                "percentComplete".to_string(),
+                achievement.percent_complete.into(),
+            );
+            dict.insert("date".to_string(), achievement.date.into());
+            achievements.insert(identifier.clone(), plist::Value::Dictionary(dict));
+        }
+
+        let mut root = plist::Dictionary::new();
+        root.insert(
+            "Leaderboards".to_string(),
+            plist::Value::Dictionary(leaderboards),
+        );
+        root.insert(
+            "Achievements".to_string(),
+            plist::Value::Dictionary(achievements),
+        );
+        plist::Value::Dictionary(root)
+    }
+}
+
+/// Belongs to _touchHLE_GKCompletionHandler
+struct CompletionHandlerHostObject {
+    /// Copy of the block made with `_Block_copy()`.
+    block: MutVoidPtr,
+    /// Strong references. May contain [nil].
+    args: Vec<id>,
+}
+impl HostObject for CompletionHandlerHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// A timer target that calls a completion handler block.
+@implementation _touchHLE_GKCompletionHandler: NSObject
+
+- (())dealloc {
+    let CompletionHandlerHostObject { block, args } = std::mem::replace(
+        env.objc.borrow_mut(this),
+        CompletionHandlerHostObject {
+            block: MutVoidPtr::null(),
+            args: Vec::new(),
+        },
+    );
+    _Block_release(env, block.cast_const());
+    for arg in args {
+        release(env, arg);
+    }
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (())timerFireMethod:(id)_timer { // NSTimer*
+    let CompletionHandlerHostObject { block, ref args } = *env.objc.borrow(this);
+    let args = args.clone();
+    let invoke = block_invoke_function(env, block.cast());
+    match args[..] {
+        [a] => () = invoke.call_from_host(env, (block, a)),
+        [a, b] => () = invoke.call_from_host(env, (block, a, b)),
+        [a, b, c] => () = invoke.call_from_host(env, (block, a, b, c)),
+        _ => {
+            log!(
+                "Warning: GameKit completion handler {:?} can't be passed {} arguments, passing an error instead",
+                block,
+                args.len()
+            );
+            let error = error_with_description(
+                env,
+                GKErrorDomain,
+                GKErrorUnknown,
+                "touchHLE can't call this completion handler.",
+            );
+            () = invoke.call_from_host(env, (block, error));
+        }
+    }
+    // The block and arguments are released when the timer releases this.
+}
+
+@end
+
+};
+
+/// Call a completion handler block with some arguments, if it isn't `nil`.
+///
+/// Like on a real device, the handler is called later from the run loop,
+/// rather than before the method that was passed it returns, since apps may
+/// expect to finish setting things up first. The block is copied because the
+/// app might have passed a stack block.
+fn schedule_completion_handler(env: &mut Environment, block: id, args: &[id]) {
+    if block == nil {
+        return;
+    }
+    let block = _Block_copy(env, block.cast_const().cast());
+    for &arg in args {
+        retain(env, arg);
+    }
+    let class = env
+        .objc
+        .get_known_class("_touchHLE_GKCompletionHandler", &mut env.mem);
+    let handler = env.objc.alloc_object(
+        class,
+        Box::new(CompletionHandlerHostObject {
+            block,
+            args: args.to_vec(),
+        }),
+        &mut env.mem,
+    );
+    let selector = env.objc.lookup_selector("timerFireMethod:").unwrap();
+    let _: id = msg_class![env; NSTimer scheduledTimerWithTimeInterval:0.0
+                                                                target:handler
+                                                              selector:selector
+                                                              userInfo:nil
+                                                               repeats:false];
+    // The timer keeps the handler alive until it has fired.
+    release(env, handler);
+}
@@ -0,0 +1,178 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKAchievement`.
+
+use super::{
+    schedule_completion_handler, GKErrorDomain, GKErrorInvalidParameter, Storage, StoredAchievement,
+};
+use crate::frameworks::foundation::ns_error::error_with_description;
+use crate::frameworks::foundation::{ns_array, ns_string, NSTimeInterval};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+
+struct GKAchievementHostObject {
+    /// `NSString*`
+    identifier: id,
+    percent_complete: f64,
+    /// `NSDate*`
+    last_reported_date: id,
+}
+impl HostObject for GKAchievementHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation GKAchievement: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(GKAchievementHostObject {
+        identifier: nil,
+        percent_complete: 0.0,
+        last_reported_date: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (())loadAchievementsWithCompletionHandler:(id)handler { // void (^)(NSArray*, NSError*)
+    let mut stored: Vec<(String, StoredAchievement)> = Storage::get(env)
+        .achievements
+        .iter()
+        .map(|(identifier, achievement)| (identifier.clone(), achievement.clone()))
+        .collect();
+    stored.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut achievements = Vec::with_capacity(stored.len());
+    for (identifier, achievement) in stored {
+        let identifier = ns_string::from_rust_string(env, identifier);
+        let date: id = msg_class![env; NSDate dateWithTimeIntervalSinceReferenceDate:(achievement.date)];
+        let date = retain(env, date);
+        let new: id = msg![env; this alloc];
+        let new: id = msg![env; new initWithIdentifier:identifier];
+        release(env, identifier);
+        let host_object = env.objc.borrow_mut::<GKAchievementHostObject>(new);
+        host_object.percent_complete = achievement.percent_complete;
+        host_object.last_reported_date = date;
+        achievements.push(new);
+    }
+    let achievements = ns_array::from_vec(env, achievements);
+    let achievements = autorelease(env, achievements);
+    schedule_completion_handler(env, handler, &[achievements, nil]);
+}
+
++ (())resetAchievementsWithCompletionHandler:(id)handler { // void (^)(NSError*)
+    log!("Resetting GameKit achievements");
+    Storage::get(env).achievements.clear();
+    Storage::save(env);
+    schedule_completion_handler(env, handler, &[nil]);
+}
+
+- (id)init {
+    msg![env; this initWithIdentifier:nil]
+}
+
+- (id)initWithIdentifier:(id)identifier { // NSString*
+    let identifier: id = msg![env; identifier copy];
+    env.objc.borrow_mut::<GKAchievementHostObject>(this).identifier = identifier;
+    this
+}
+
+- (())dealloc {
+    let &GKAchievementHostObject {
+        identifier,
+        last_reported_date,
+        ..
+    } = env.objc.borrow(this);
+    release(env, identifier);
+    release(env, last_reported_date);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)identifier {
+    env.objc.borrow::<GKAchievementHostObject>(this).identifier
+}
+- (())setIdentifier:(id)identifier { // NSString*
+    let identifier: id = msg![env; identifier copy];
+    let host_object = env.objc.borrow_mut::<GKAchievementHostObject>(this);
+    let old_identifier = std::mem::replace(&mut host_object.identifier, identifier);
+    release(env, old_identifier);
+}
+
+- (f64)percentComplete {
+    env.objc.borrow::<GKAchievementHostObject>(this).percent_complete
+}
+- (())setPercentComplete:(f64)percent_complete {
+    env.objc.borrow_mut::<GKAchievementHostObject>(this).percent_complete = percent_complete;
+}
+
+- (bool)isCompleted {
+    env.objc.borrow::<GKAchievementHostObject>(this).percent_complete >= 100.0
+}
+- (bool)isHidden {
+    false
+}
+
+- (id)lastReportedDate {
+    env.objc.borrow::<GKAchievementHostObject>(this).last_reported_date
+}
+
+- (())reportAchievementWithCompletionHandler:(id)handler { // void (^)(NSError*)
+    let &GKAchievementHostObject {
+        identifier,
+        percent_complete,
+        ..
+    } = env.objc.borrow(this);
+    if identifier == nil {
+        log!("Warning: Can't report a GameKit achievement with no identifier");
+        let error = error_with_description(
+            env,
+            GKErrorDomain,
+            GKErrorInvalidParameter,
+            "The achievement has no identifier.",
+        );
+        schedule_completion_handler(env, handler, &[error]);
+        return;
+    }
+    let identifier = ns_string::to_rust_string(env, identifier).into_owned();
+    let percent_complete = percent_complete.clamp(0.0, 100.0);
+    log!(
+        "Reporting GameKit achievement {:?} as {}% complete",
+        identifier,
+        percent_complete,
+    );
+
+    let new_date: id = msg_class![env; NSDate date];
+    let date: NSTimeInterval = msg![env; new_date timeIntervalSinceReferenceDate];
+
+    // Game Center never lets an achievement's progress go backwards.
+    let achievements = &mut Storage::get(env).achievements;
+    let progressed = achievements
+        .get(&identifier)
+        .map_or(true, |old| old.percent_complete < percent_complete);
+    if progressed {
+        achievements.insert(
+            identifier,
+            StoredAchievement {
+                percent_complete,
+                date,
+            },
+        );
+        Storage::save(env);
+    }
+
+    let new_date = retain(env, new_date);
+    let host_object = env.objc.borrow_mut::<GKAchievementHostObject>(this);
+    let old_date = std::mem::replace(&mut host_object.last_reported_date, new_date);
+    release(env, old_date);
+
+    schedule_completion_handler(env, handler, &[nil]);
+}
+
+@end
+
+};
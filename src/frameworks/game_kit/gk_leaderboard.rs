@@ -0,0 +1,239 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKLeaderboard`.
+
+use super::gk_score::score_from_stored;
+use super::{schedule_completion_handler, Storage, StoredScore};
+use crate::frameworks::foundation::{ns_array, ns_string, NSInteger, NSRange, NSTimeInterval};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+
+pub type GKLeaderboardTimeScope = NSInteger;
+pub const GKLeaderboardTimeScopeToday: GKLeaderboardTimeScope = 0;
+pub const GKLeaderboardTimeScopeWeek: GKLeaderboardTimeScope = 1;
+pub const GKLeaderboardTimeScopeAllTime: GKLeaderboardTimeScope = 2;
+
+type GKLeaderboardPlayerScope = NSInteger;
+const GKLeaderboardPlayerScopeGlobal: GKLeaderboardPlayerScope = 0;
+
+struct GKLeaderboardHostObject {
+    /// `NSString*`
+    category: id,
+    time_scope: GKLeaderboardTimeScope,
+    player_scope: GKLeaderboardPlayerScope,
+    range: NSRange,
+    /// `NSArray*` of `GKScore*`, [nil] until scores are loaded
+    scores: id,
+    /// `GKScore*`, [nil] until scores are loaded
+    local_player_score: id,
+}
+impl HostObject for GKLeaderboardHostObject {}
+
+/// Get the stored scores for a leaderboard that fall within a time scope, best
+/// first.
+///
+/// Since the local player is the only player, every reported score gets its
+/// own entry, like an arcade high score table, rather than there being one
+/// entry per player as on the real Game Center.
+pub(super) fn ranked_scores(
+    env: &mut Environment,
+    category: &str,
+    time_scope: GKLeaderboardTimeScope,
+) -> Vec<StoredScore> {
+    let now: id = msg_class![env; NSDate date];
+    let now: NSTimeInterval = msg![env; now timeIntervalSinceReferenceDate];
+    let max_age = match time_scope {
+        GKLeaderboardTimeScopeToday => Some(24.0 * 60.0 * 60.0),
+        GKLeaderboardTimeScopeWeek => Some(7.0 * 24.0 * 60.0 * 60.0),
+        _ => None,
+    };
+
+    let mut scores: Vec<StoredScore> = Storage::get(env)
+        .scores
+        .get(category)
+        .map(|scores| scores.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter(|score| max_age.map_or(true, |max_age| now - score.date <= max_age))
+        .cloned()
+        .collect();
+    // TODO: Leaderboards can be configured to sort in ascending order, but that
+    // configuration lives on Apple's servers, so we always assume higher is
+    // better.
+    scores.sort_by(|a, b| b.value.cmp(&a.value));
+    scores
+}
+
+/// Convert a leaderboard category `NSString*` into the key used in storage.
+pub(super) fn category_key(env: &mut Environment, category: id) -> String {
+    if category == nil {
+        // TODO: this should be the app's default leaderboard
+        String::new()
+    } else {
+        ns_string::to_rust_string(env, category).into_owned()
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation GKLeaderboard: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(GKLeaderboardHostObject {
+        category: nil,
+        time_scope: GKLeaderboardTimeScopeAllTime,
+        player_scope: GKLeaderboardPlayerScopeGlobal,
+        range: NSRange {
+            location: 1,
+            length: 10,
+        },
+        scores: nil,
+        local_player_score: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (())loadCategoriesWithCompletionHandler:(id)handler { // void (^)(NSArray*, NSArray*, NSError*)
+    let mut names: Vec<String> = Storage::get(env).scores.keys().cloned().collect();
+    names.sort();
+
+    let mut categories = Vec::with_capacity(names.len());
+    let mut titles = Vec::with_capacity(names.len());
+    for name in names {
+        // The real titles live on Apple's servers.
+        let category = ns_string::from_rust_string(env, name);
+        categories.push(category);
+        titles.push(retain(env, category));
+    }
+    let categories = ns_array::from_vec(env, categories);
+    let categories = autorelease(env, categories);
+    let titles = ns_array::from_vec(env, titles);
+    let titles = autorelease(env, titles);
+
+    schedule_completion_handler(env, handler, &[categories, titles, nil]);
+}
+
+- (id)init {
+    this
+}
+- (id)initWithPlayerIDs:(id)_player_ids { // NSArray<NSString*>*
+    // There's only the local player, so this is the same as the global scope.
+    this
+}
+
+- (())dealloc {
+    let &GKLeaderboardHostObject {
+        category,
+        scores,
+        local_player_score,
+        ..
+    } = env.objc.borrow(this);
+    release(env, category);
+    release(env, scores);
+    release(env, local_player_score);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)category {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).category
+}
+- (())setCategory:(id)category { // NSString*
+    let category: id = msg![env; category copy];
+    let host_object = env.objc.borrow_mut::<GKLeaderboardHostObject>(this);
+    let old_category = std::mem::replace(&mut host_object.category, category);
+    release(env, old_category);
+}
+
+- (GKLeaderboardTimeScope)timeScope {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).time_scope
+}
+- (())setTimeScope:(GKLeaderboardTimeScope)time_scope {
+    env.objc.borrow_mut::<GKLeaderboardHostObject>(this).time_scope = time_scope;
+}
+
+- (GKLeaderboardPlayerScope)playerScope {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).player_scope
+}
+- (())setPlayerScope:(GKLeaderboardPlayerScope)player_scope {
+    env.objc.borrow_mut::<GKLeaderboardHostObject>(this).player_scope = player_scope;
+}
+
+- (NSRange)range {
+    let range = &env.objc.borrow::<GKLeaderboardHostObject>(this).range;
+    NSRange {
+        location: range.location,
+        length: range.length,
+    }
+}
+- (())setRange:(NSRange)range {
+    env.objc.borrow_mut::<GKLeaderboardHostObject>(this).range = range;
+}
+
+- (id)scores {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).scores
+}
+- (id)localPlayerScore {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).local_player_score
+}
+- (id)title {
+    msg![env; this category]
+}
+- (bool)isLoading {
+    // Loading always completes synchronously.
+    false
+}
+
+- (())loadScoresWithCompletionHandler:(id)handler { // void (^)(NSArray*, NSError*)
+    let &GKLeaderboardHostObject {
+        category,
+        time_scope,
+        ref range,
+        ..
+    } = env.objc.borrow(this);
+    // Ranks are 1-based.
+    let start = (range.location.max(1) - 1) as usize;
+    let end = start.saturating_add(range.length as usize);
+
+    let category_string = category_key(env, category);
+    let ranked = ranked_scores(env, &category_string, time_scope);
+
+    let mut scores = Vec::new();
+    for (idx, score) in ranked.iter().enumerate().take(end).skip(start) {
+        let score = score_from_stored(env, category, score, (idx + 1) as NSInteger);
+        scores.push(retain(env, score));
+    }
+    let local_player_score = ranked
+        .first()
+        .map(|best| score_from_stored(env, category, best, 1))
+        .unwrap_or(nil);
+
+    let scores = if scores.is_empty() {
+        // The real GameKit gives nil rather than an empty array.
+        nil
+    } else {
+        ns_array::from_vec(env, scores)
+    };
+    retain(env, local_player_score);
+    let host_object = env.objc.borrow_mut::<GKLeaderboardHostObject>(this);
+    let old_scores = std::mem::replace(&mut host_object.scores, scores);
+    let old_local_player_score = std::mem::replace(
+        &mut host_object.local_player_score,
+        local_player_score,
+    );
+    release(env, old_scores);
+    release(env, old_local_player_score);
+
+    schedule_completion_handler(env, handler, &[scores, nil]);
+}
+
+@end
+
+};
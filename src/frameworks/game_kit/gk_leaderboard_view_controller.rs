@@ -0,0 +1,207 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKLeaderboardViewController`.
+//!
+//! The real one is a full Game Center UI. This one is a plain list of the best
+//! scores with a "Done" button, drawn with ordinary UIKit views.
+
+use super::gk_leaderboard::{
+    category_key, ranked_scores, GKLeaderboardTimeScope, GKLeaderboardTimeScopeAllTime,
+    GKLeaderboardTimeScopeToday, GKLeaderboardTimeScopeWeek,
+};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string;
+use crate::frameworks::uikit::ui_font::{
+    UITextAlignment, UITextAlignmentCenter, UITextAlignmentLeft, UITextAlignmentRight,
+};
+use crate::frameworks::uikit::ui_view::ui_control::ui_button::UIButtonTypeRoundedRect;
+use crate::frameworks::uikit::ui_view::ui_control::{
+    UIControlEventTouchUpInside, UIControlStateNormal,
+};
+use crate::frameworks::uikit::ui_view_controller::{
+    alloc_view_controller, UIViewControllerHostObject,
+};
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes, release,
+    ClassExports, NSZonePtr,
+};
+use crate::Environment;
+
+/// How many scores are listed.
+const MAX_ROWS: usize = 10;
+
+struct GKLeaderboardViewControllerHostObject {
+    superclass: UIViewControllerHostObject,
+    /// Weak reference.
+    leaderboard_delegate: id,
+    /// `NSString*`
+    category: id,
+    time_scope: GKLeaderboardTimeScope,
+}
+impl_HostObject_with_superclass!(GKLeaderboardViewControllerHostObject);
+
+fn add_label(
+    env: &mut Environment,
+    super_view: id,
+    frame: CGRect,
+    text: String,
+    font_size: CGFloat,
+    alignment: UITextAlignment,
+) {
+    let label: id = msg_class![env; UILabel alloc];
+    let label: id = msg![env; label initWithFrame:frame];
+    let text = ns_string::from_rust_string(env, text);
+    () = msg![env; label setText:text];
+    release(env, text);
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:font_size];
+    () = msg![env; label setFont:font];
+    let color: id = msg_class![env; UIColor whiteColor];
+    () = msg![env; label setTextColor:color];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; label setBackgroundColor:bg_color];
+    () = msg![env; label setTextAlignment:alignment];
+    () = msg![env; super_view addSubview:label];
+    release(env, label);
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation GKLeaderboardViewController: UIViewController
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(GKLeaderboardViewControllerHostObject {
+        superclass: Default::default(),
+        leaderboard_delegate: nil,
+        category: nil,
+        time_scope: GKLeaderboardTimeScopeAllTime,
+    });
+    alloc_view_controller(env, this, host_object)
+}
+
+- (())dealloc {
+    let category = env.objc.borrow::<GKLeaderboardViewControllerHostObject>(this).category;
+    release(env, category);
+    msg_super![env; this dealloc]
+}
+
+- (id)leaderboardDelegate {
+    env.objc.borrow::<GKLeaderboardViewControllerHostObject>(this).leaderboard_delegate
+}
+- (())setLeaderboardDelegate:(id)delegate { // id<GKLeaderboardViewControllerDelegate>
+    env.objc.borrow_mut::<GKLeaderboardViewControllerHostObject>(this).leaderboard_delegate = delegate;
+}
+
+- (id)category {
+    env.objc.borrow::<GKLeaderboardViewControllerHostObject>(this).category
+}
+- (())setCategory:(id)category { // NSString*
+    let category: id = msg![env; category copy];
+    let host_object = env.objc.borrow_mut::<GKLeaderboardViewControllerHostObject>(this);
+    let old_category = std::mem::replace(&mut host_object.category, category);
+    release(env, old_category);
+}
+
+- (GKLeaderboardTimeScope)timeScope {
+    env.objc.borrow::<GKLeaderboardViewControllerHostObject>(this).time_scope
+}
+- (())setTimeScope:(GKLeaderboardTimeScope)time_scope {
+    env.objc.borrow_mut::<GKLeaderboardViewControllerHostObject>(this).time_scope = time_scope;
+}
+
+- (())loadView {
+    let &GKLeaderboardViewControllerHostObject {
+        category,
+        time_scope,
+        ..
+    } = env.objc.borrow(this);
+
+    let screen: id = msg_class![env; UIScreen mainScreen];
+    let bounds: CGRect = msg![env; screen bounds];
+    let width = bounds.size.width;
+
+    let view: id = msg_class![env; UIView alloc];
+    let view: id = msg![env; view initWithFrame:bounds];
+    let bg_color: id = msg_class![env; UIColor colorWithWhite:(0.15 as CGFloat)
+                                                        alpha:(1.0 as CGFloat)];
+    () = msg![env; view setBackgroundColor:bg_color];
+
+    let category_string = category_key(env, category);
+    let scope_name = match time_scope {
+        GKLeaderboardTimeScopeToday => "Today",
+        GKLeaderboardTimeScopeWeek => "This Week",
+        _ => "All Time",
+    };
+    let title = if category_string.is_empty() {
+        format!("Leaderboard ({})", scope_name)
+    } else {
+        format!("{} ({})", category_string, scope_name)
+    };
+    let title_frame = CGRect {
+        origin: CGPoint { x: 10.0, y: 10.0 },
+        size: CGSize { width: width - 20.0, height: 30.0 },
+    };
+    add_label(env, view, title_frame, title, 20.0, UITextAlignmentCenter);
+
+    let scores = ranked_scores(env, &category_string, time_scope);
+    let row_height = 30.0;
+    let mut y = 50.0;
+    if scores.is_empty() {
+        let frame = CGRect {
+            origin: CGPoint { x: 10.0, y },
+            size: CGSize { width: width - 20.0, height: row_height },
+        };
+        add_label(env, view, frame, "No scores yet".to_string(), 16.0, UITextAlignmentCenter);
+    }
+    for (idx, score) in scores.iter().take(MAX_ROWS).enumerate() {
+        let rank_frame = CGRect {
+            origin: CGPoint { x: 10.0, y },
+            size: CGSize { width: 40.0, height: row_height },
+        };
+        add_label(env, view, rank_frame, format!("{}.", idx + 1), 16.0, UITextAlignmentRight);
+        let value_frame = CGRect {
+            origin: CGPoint { x: 60.0, y },
+            size: CGSize { width: width - 70.0, height: row_height },
+        };
+        add_label(env, view, value_frame, score.value.to_string(), 16.0, UITextAlignmentLeft);
+        y += row_height;
+    }
+
+    let button: id = msg_class![env; UIButton buttonWithType:UIButtonTypeRoundedRect];
+    let text = ns_string::get_static_str(env, "Done");
+    () = msg![env; button setTitle:text forState:UIControlStateNormal];
+    let button_frame = CGRect {
+        origin: CGPoint { x: width / 2.0 - 50.0, y: bounds.size.height - 50.0 },
+        size: CGSize { width: 100.0, height: 30.0 },
+    };
+    () = msg![env; button setFrame:button_frame];
+    // FIXME: manually calling layoutSubviews shouldn't be needed?
+    () = msg![env; button layoutSubviews];
+    let selector = env.objc.lookup_selector("_touchHLE_doneButtonTapped").unwrap();
+    () = msg![env; button addTarget:this
+                             action:selector
+                   forControlEvents:UIControlEventTouchUpInside];
+    () = msg![env; view addSubview:button];
+
+    () = msg![env; this setView:view];
+    release(env, view);
+}
+
+// Internal method, target of the "Done" button.
+- (())_touchHLE_doneButtonTapped {
+    let delegate = env.objc.borrow::<GKLeaderboardViewControllerHostObject>(this).leaderboard_delegate;
+    if delegate != nil {
+        // The delegate is responsible for dismissing the view controller.
+        () = msg![env; delegate leaderboardViewControllerDidFinish:this];
+    } else {
+        () = msg![env; this dismissModalViewControllerAnimated:true];
+    }
+}
+
+@end
+
+};
@@ -0,0 +1,132 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKPlayer` and `GKLocalPlayer`.
+
+use super::schedule_completion_handler;
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::foundation::{ns_array, ns_string, NSUInteger};
+use crate::objc::{autorelease, id, msg, msg_class, nil, objc_classes, ClassExports, HostObject};
+
+#[derive(Default)]
+pub struct State {
+    local_player: Option<id>,
+}
+
+struct GKLocalPlayerHostObject {
+    authenticated: bool,
+}
+impl HostObject for GKLocalPlayerHostObject {}
+
+/// The player ID of the one and only player. Real IDs look like this.
+pub const LOCAL_PLAYER_ID: &str = "G:0000000001";
+const LOCAL_PLAYER_ALIAS: &str = "touchHLE";
+
+pub const GKPlayerAuthenticationDidChangeNotificationName: &str =
+    "GKPlayerAuthenticationDidChangeNotificationName";
+
+/// `NSNotificationName` values.
+pub const CONSTANTS: ConstantExports = &[(
+    "_GKPlayerAuthenticationDidChangeNotificationName",
+    HostConstant::NSString(GKPlayerAuthenticationDidChangeNotificationName),
+)];
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// Abstract class. Only the local player exists in touchHLE, so it is never
+// instantiated directly.
+@implementation GKPlayer: NSObject
+
++ (())loadPlayersForIdentifiers:(id)identifiers // NSArray<NSString*>*
+          withCompletionHandler:(id)handler { // void (^)(NSArray*, NSError*)
+    let count: NSUInteger = msg![env; identifiers count];
+    let mut players = Vec::new();
+    for i in 0..count {
+        let identifier: id = msg![env; identifiers objectAtIndex:i];
+        let identifier = ns_string::to_rust_string(env, identifier);
+        if identifier == LOCAL_PLAYER_ID {
+            let player: id = msg_class![env; GKLocalPlayer localPlayer];
+            players.push(player);
+        } else {
+            log!("Unknown player ID {:?}, ignoring", identifier);
+        }
+    }
+    let players = ns_array::from_vec(env, players);
+    let players = autorelease(env, players);
+    schedule_completion_handler(env, handler, &[players, nil]);
+}
+
+- (id)playerID {
+    ns_string::get_static_str(env, LOCAL_PLAYER_ID)
+}
+- (id)alias {
+    ns_string::get_static_str(env, LOCAL_PLAYER_ALIAS)
+}
+- (bool)isFriend {
+    false
+}
+
+@end
+
+@implementation GKLocalPlayer: GKPlayer
+
++ (id)localPlayer {
+    if let Some(player) = env.framework_state.game_kit.gk_local_player.local_player {
+        player
+    } else {
+        let host_object = Box::new(GKLocalPlayerHostObject {
+            authenticated: false,
+        });
+        let new = env.objc.alloc_static_object(this, host_object, &mut env.mem);
+        env.framework_state.game_kit.gk_local_player.local_player = Some(new);
+        new
+    }
+}
+
+- (id)retain { this }
+- (())release {}
+- (id)autorelease { this }
+
+- (bool)isAuthenticated {
+    env.objc.borrow::<GKLocalPlayerHostObject>(this).authenticated
+}
+- (bool)isUnderage {
+    false
+}
+
+- (())authenticateWithCompletionHandler:(id)handler { // void (^)(NSError*)
+    log!("Authenticating local GameKit player (always succeeds in touchHLE)");
+    let was_authenticated = std::mem::replace(
+        &mut env.objc.borrow_mut::<GKLocalPlayerHostObject>(this).authenticated,
+        true,
+    );
+
+    schedule_completion_handler(env, handler, &[nil]);
+
+    if !was_authenticated {
+        let name = ns_string::get_static_str(
+            env,
+            GKPlayerAuthenticationDidChangeNotificationName,
+        );
+        let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+        () = msg![env; center postNotificationName:name object:this];
+    }
+}
+
+- (id)friends {
+    // There's nobody else here.
+    let friends = ns_array::from_vec(env, Vec::new());
+    autorelease(env, friends)
+}
+- (())loadFriendsWithCompletionHandler:(id)handler { // void (^)(NSArray*, NSError*)
+    let friends: id = msg![env; this friends];
+    schedule_completion_handler(env, handler, &[friends, nil]);
+}
+
+@end
+
+};
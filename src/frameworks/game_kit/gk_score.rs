@@ -0,0 +1,144 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKScore`.
+
+use super::gk_local_player::LOCAL_PLAYER_ID;
+use super::{schedule_completion_handler, Storage, StoredScore};
+use crate::frameworks::foundation::{ns_string, NSInteger, NSTimeInterval};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+
+struct GKScoreHostObject {
+    /// `NSString*`
+    category: id,
+    value: i64,
+    /// `NSDate*`
+    date: id,
+    rank: NSInteger,
+}
+impl HostObject for GKScoreHostObject {}
+
+/// Create a `GKScore*` for a score loaded from storage. The result is
+/// autoreleased.
+pub(super) fn score_from_stored(
+    env: &mut Environment,
+    category: id,
+    score: &StoredScore,
+    rank: NSInteger,
+) -> id {
+    let date: id = msg_class![env; NSDate dateWithTimeIntervalSinceReferenceDate:(score.date)];
+    let new: id = msg_class![env; GKScore alloc];
+    let new: id = msg![env; new initWithCategory:category];
+    let date = retain(env, date);
+    let host_object = env.objc.borrow_mut::<GKScoreHostObject>(new);
+    host_object.value = score.value;
+    host_object.rank = rank;
+    let old_date = std::mem::replace(&mut host_object.date, date);
+    release(env, old_date);
+    autorelease(env, new)
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation GKScore: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(GKScoreHostObject {
+        category: nil,
+        value: 0,
+        date: nil,
+        rank: 0,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)init {
+    msg![env; this initWithCategory:nil]
+}
+
+- (id)initWithCategory:(id)category { // NSString*
+    let category: id = msg![env; category copy];
+    let date: id = msg_class![env; NSDate date];
+    let date = retain(env, date);
+    let host_object = env.objc.borrow_mut::<GKScoreHostObject>(this);
+    host_object.category = category;
+    host_object.date = date;
+    this
+}
+
+- (())dealloc {
+    let &GKScoreHostObject { category, date, .. } = env.objc.borrow(this);
+    release(env, category);
+    release(env, date);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)category {
+    env.objc.borrow::<GKScoreHostObject>(this).category
+}
+- (())setCategory:(id)category { // NSString*
+    let category: id = msg![env; category copy];
+    let host_object = env.objc.borrow_mut::<GKScoreHostObject>(this);
+    let old_category = std::mem::replace(&mut host_object.category, category);
+    release(env, old_category);
+}
+
+- (i64)value {
+    env.objc.borrow::<GKScoreHostObject>(this).value
+}
+- (())setValue:(i64)value {
+    env.objc.borrow_mut::<GKScoreHostObject>(this).value = value;
+}
+
+- (id)date {
+    env.objc.borrow::<GKScoreHostObject>(this).date
+}
+
+- (id)playerID {
+    ns_string::get_static_str(env, LOCAL_PLAYER_ID)
+}
+
+- (NSInteger)rank {
+    env.objc.borrow::<GKScoreHostObject>(this).rank
+}
+
+- (id)formattedValue {
+    // TODO: use the format from the app's Game Center configuration, which
+    // isn't available to us.
+    let value = env.objc.borrow::<GKScoreHostObject>(this).value;
+    let formatted = ns_string::from_rust_string(env, value.to_string());
+    autorelease(env, formatted)
+}
+
+- (())reportScoreWithCompletionHandler:(id)handler { // void (^)(NSError*)
+    let &GKScoreHostObject { category, value, date, .. } = env.objc.borrow(this);
+    let category = if category == nil {
+        // TODO: this should be the app's default leaderboard
+        String::new()
+    } else {
+        ns_string::to_rust_string(env, category).into_owned()
+    };
+    let date: NSTimeInterval = msg![env; date timeIntervalSinceReferenceDate];
+    log!("Reporting GameKit score {} for leaderboard {:?}", value, category);
+
+    Storage::get(env)
+        .scores
+        .entry(category)
+        .or_default()
+        .push(StoredScore { value, date });
+    Storage::save(env);
+
+    schedule_completion_handler(env, handler, &[nil]);
+}
+
+@end
+
+};
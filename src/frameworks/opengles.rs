@@ -12,9 +12,10 @@
 pub mod eagl;
 mod gles_guest;
 
-use crate::mem::ConstPtr;
+use crate::mem::{ConstPtr, GuestUSize};
+use crate::objc::id;
 pub use gles_guest::FUNCTIONS;
-use touchHLE_gl_bindings::gles11::types::GLenum;
+use touchHLE_gl_bindings::gles11::types::{GLenum, GLint, GLuint};
 
 #[derive(Default)]
 pub struct State {
@@ -23,8 +24,43 @@ pub struct State {
     /// Which thread's EAGLContext is currently active
     current_ctx_thread: Option<crate::ThreadId>,
     strings_cache: std::collections::HashMap<GLenum, ConstPtr<u8>>,
+    /// Estimated size of each texture image, keyed by context, texture name
+    /// and mipmap level. This counts towards the emulated device's memory
+    /// usage (see [crate::frameworks::uikit::ui_application]).
+    ///
+    /// touchHLE doesn't support `initWithAPI:sharegroup:`, so every context
+    /// has its own texture names and each texture is only counted once. If
+    /// sharegroups are added, this needs to be keyed by sharegroup instead.
+    texture_image_sizes: std::collections::HashMap<(id, GLuint, GLint), GuestUSize>,
+    /// Sum of `texture_image_sizes`.
+    texture_memory_usage: u64,
 }
 impl State {
+    fn set_texture_image_size(&mut self, ctx: id, texture: GLuint, level: GLint, size: GuestUSize) {
+        let old_size = self
+            .texture_image_sizes
+            .insert((ctx, texture, level), size)
+            .unwrap_or(0);
+        self.texture_memory_usage -= u64::from(old_size);
+        self.texture_memory_usage += u64::from(size);
+    }
+    /// Forget the sizes of the images of textures matching the predicate,
+    /// which is given the context and texture name.
+    fn forget_texture_images(&mut self, mut predicate: impl FnMut(id, GLuint) -> bool) {
+        let texture_memory_usage = &mut self.texture_memory_usage;
+        self.texture_image_sizes
+            .retain(|&(ctx, texture, _level), &mut size| {
+                let forget = predicate(ctx, texture);
+                if forget {
+                    *texture_memory_usage -= u64::from(size);
+                }
+                !forget
+            });
+    }
+    /// Estimated memory used by the images of all textures in all contexts.
+    pub fn texture_memory_usage(&self) -> u64 {
+        self.texture_memory_usage
+    }
     fn current_ctx_for_thread(&mut self, thread: crate::ThreadId) -> &mut Option<crate::objc::id> {
         self.current_ctxs.entry(thread).or_insert(None);
         self.current_ctxs.get_mut(&thread).unwrap()
@@ -9,11 +9,14 @@ use crate::dyld::{ConstantExports, HostConstant};
 use crate::frameworks::core_animation::ca_eagl_layer::{
     find_fullscreen_eagl_layer, get_pixels_vec_for_presenting, present_pixels,
 };
+use crate::frameworks::core_animation::{
+    draw_status_bar_overlay, status_bar_overlay, StatusBarOverlay,
+};
 use crate::frameworks::foundation::ns_string::get_static_str;
 use crate::frameworks::foundation::NSUInteger;
 use crate::gles::gles11_raw as gles11; // constants only
 use crate::gles::gles11_raw::types::*;
-use crate::gles::present::{present_frame, FpsCounter};
+use crate::gles::present::{present_frame, refresh_interval, FpsCounter};
 use crate::gles::{create_gles1_ctx, gles1_on_gl2, GLES};
 use crate::objc::{id, msg, nil, objc_classes, release, retain, ClassExports, HostObject};
 use crate::options::Options;
@@ -136,6 +139,9 @@ pub const CLASSES: ClassExports = objc_classes! {
     for (_renderbuffer, drawable) in bindings {
         release(env, drawable);
     }
+    env.framework_state
+        .opengles
+        .forget_texture_images(|ctx, _texture| ctx == this);
     env.objc.dealloc_object(this, &mut env.mem);
 }
 
@@ -236,10 +242,13 @@ pub const CLASSES: ClassExports = objc_classes! {
             drawable,
             renderbuffer,
         );
+        // Drawing the status bar sends messages, which might change the
+        // current context, so it has to happen before syncing it again.
+        let status_bar = status_bar_overlay(env);
         // re-borrow
         let gles = super::sync_context(&mut env.framework_state.opengles, &mut env.objc, env.window.as_mut().unwrap(), env.current_thread);
         unsafe {
-            present_renderbuffer(gles, env.window.as_mut().unwrap());
+            present_renderbuffer(gles, env.window.as_mut().unwrap(), status_bar.as_ref());
         }
     } else {
         if fullscreen_layer != nil {
@@ -308,11 +317,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 /// slop is not desirable, because if the game is running slowly for a long time
 /// and suddenly speeds back up, it will then run too fast for a long time.
 fn limit_framerate(next_frame_due: &mut Option<Instant>, options: &Options) -> Option<Duration> {
-    let interval = if let Some(fps) = options.fps_limit {
-        1.0 / fps
-    } else {
-        return None;
-    };
+    let interval = refresh_interval(options)?;
     let interval_rust = Duration::from_secs_f64(interval);
 
     let &mut Some(current_frame_due) = next_frame_due else {
@@ -471,10 +476,15 @@ unsafe fn read_renderbuffer(gles: &mut dyn GLES, mut pixel_buffer: Vec<u8>) -> (
 /// Copies the pixels in a renderbuffer bound to `GL_RENDERBUFFER_BINDING_OES`
 /// (which should be provided by the app) to a texture and presents it with
 /// [present_frame], trying to avoid noticeably modifying OpenGL ES state while
-/// doing so. The front and back buffers are then swapped.
+/// doing so. The status bar, if provided, is drawn on top. The front and back
+/// buffers are then swapped.
 ///
 /// The provided context must be current.
-unsafe fn present_renderbuffer(gles: &mut dyn GLES, window: &mut Window) {
+unsafe fn present_renderbuffer(
+    gles: &mut dyn GLES,
+    window: &mut Window,
+    status_bar: Option<&StatusBarOverlay>,
+) {
     // We can't directly copy the content of the renderbuffer to the default
     // framebuffer (the window), but if we attach it to a framebuffer object, we
     // can use glCopyTexImage2D() to copy it to a texture, which we can then
@@ -591,6 +601,10 @@ unsafe fn present_renderbuffer(gles: &mut dyn GLES, window: &mut Window) {
         tex_env_mode_arr.as_ptr().cast(),
     );
 
+    if let Some(status_bar) = status_bar {
+        draw_status_bar_overlay(gles, status_bar, texture, (width, height));
+    }
+
     // Draw the quad
     present_frame(
         gles,
@@ -16,7 +16,9 @@ use crate::dyld::{export_c_func, FunctionExports};
 use crate::gles::gles11_raw as gles11; // constants only
 use crate::gles::GLES;
 use crate::mem::{ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, Mem, MutPtr};
+use crate::objc::id;
 use crate::Environment;
+use std::collections::HashSet;
 
 // These types are the same size in guest code (32-bit) and host code (64-bit).
 use crate::gles::gles11_raw::types::{
@@ -691,11 +693,19 @@ fn glGenTextures(env: &mut Environment, n: GLsizei, textures: MutPtr<GLuint>) {
     })
 }
 fn glDeleteTextures(env: &mut Environment, n: GLsizei, textures: ConstPtr<GLuint>) {
-    with_ctx_and_mem(env, |gles, mem| {
+    let names = with_ctx_and_mem(env, |gles, mem| {
         let n_usize: GuestUSize = n.try_into().unwrap();
+        let names: HashSet<GLuint> = (0..n_usize).map(|i| mem.read(textures + i)).collect();
         let textures = mem.ptr_at(textures, n_usize);
-        unsafe { gles.DeleteTextures(n, textures) }
-    })
+        unsafe { gles.DeleteTextures(n, textures) };
+        names
+    });
+    let ctx = current_ctx(env);
+    env.framework_state
+        .opengles
+        .forget_texture_images(|texture_ctx, texture| {
+            texture_ctx == ctx && names.contains(&texture)
+        });
 }
 fn glActiveTexture(env: &mut Environment, texture: GLenum) {
     with_ctx_and_mem(env, |gles, _mem| unsafe { gles.ActiveTexture(texture) })
@@ -773,6 +783,37 @@ fn glTexParameterxv(
         gles.TexParameterxv(target, pname, params)
     })
 }
+fn current_ctx(env: &mut Environment) -> id {
+    let current_ctx = env
+        .framework_state
+        .opengles
+        .current_ctx_for_thread(env.current_thread);
+    current_ctx.unwrap()
+}
+
+/// Remember the estimated size of an image of the texture bound to `target`,
+/// so that it counts towards the emulated device's memory usage.
+fn record_texture_image_size(
+    env: &mut Environment,
+    target: GLenum,
+    level: GLint,
+    size: GuestUSize,
+) {
+    // Cube maps aren't supported by OpenGL ES 1.1.
+    if target != gles11::TEXTURE_2D {
+        return;
+    }
+    let texture = with_ctx_and_mem(env, |gles, _mem| {
+        let mut texture = 0;
+        unsafe { gles.GetIntegerv(gles11::TEXTURE_BINDING_2D, &mut texture) };
+        texture as GLuint
+    });
+    let ctx = current_ctx(env);
+    env.framework_state
+        .opengles
+        .set_texture_image_size(ctx, texture, level, size);
+}
+
 fn image_size_estimate(pixel_count: GuestUSize, format: GLenum, type_: GLenum) -> GuestUSize {
     let bytes_per_pixel: GuestUSize = match type_ {
         gles11::UNSIGNED_BYTE => match format {
@@ -803,12 +844,12 @@ fn glTexImage2D(
     type_: GLenum,
     pixels: ConstVoidPtr,
 ) {
+    let pixel_count: GuestUSize = width.checked_mul(height).unwrap().try_into().unwrap();
+    let size = image_size_estimate(pixel_count, format, type_);
     with_ctx_and_mem(env, |gles, mem| unsafe {
         let pixels = if pixels.is_null() {
             std::ptr::null()
         } else {
-            let pixel_count: GuestUSize = width.checked_mul(height).unwrap().try_into().unwrap();
-            let size = image_size_estimate(pixel_count, format, type_);
             mem.ptr_at(pixels.cast::<u8>(), size).cast::<GLvoid>()
         };
         gles.TexImage2D(
@@ -822,7 +863,8 @@ fn glTexImage2D(
             type_,
             pixels,
         )
-    })
+    });
+    record_texture_image_size(env, target, level, size);
 }
 fn glTexSubImage2D(
     env: &mut Environment,
@@ -870,7 +912,8 @@ fn glCompressedTexImage2D(
             image_size,
             data,
         )
-    })
+    });
+    record_texture_image_size(env, target, level, image_size.try_into().unwrap());
 }
 fn glCopyTexImage2D(
     env: &mut Environment,
@@ -885,7 +928,10 @@ fn glCopyTexImage2D(
 ) {
     with_ctx_and_mem(env, |gles, _mem| unsafe {
         gles.CopyTexImage2D(target, level, internalformat, x, y, width, height, border)
-    })
+    });
+    // The framebuffer format isn't known here, so assume 32-bit pixels.
+    let pixel_count: GuestUSize = width.checked_mul(height).unwrap().try_into().unwrap();
+    record_texture_image_size(env, target, level, pixel_count * 4);
 }
 fn glCopyTexSubImage2D(
     env: &mut Environment,
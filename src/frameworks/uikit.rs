@@ -15,6 +15,7 @@ use std::time::Instant;
 pub mod ui_accelerometer;
 pub mod ui_activity_indicator_view;
 pub mod ui_application;
+pub mod ui_bar_item;
 pub mod ui_color;
 pub mod ui_device;
 pub mod ui_event;
@@ -38,9 +39,11 @@ pub struct State {
     ui_device: ui_device::State,
     ui_font: ui_font::State,
     ui_graphics: ui_graphics::State,
+    ui_responder: ui_responder::State,
     ui_screen: ui_screen::State,
     ui_touch: ui_touch::State,
     pub ui_view: ui_view::State,
+    ui_view_controller: ui_view_controller::State,
 }
 
 /// For use by `NSRunLoop`: handles any events that have queued up.
@@ -64,6 +67,9 @@ pub fn handle_events(env: &mut Environment) -> Option<Instant> {
             Event::TouchesDown(..) | Event::TouchesMove(..) | Event::TouchesUp(..) => {
                 ui_touch::handle_event(env, event)
             }
+            Event::TextInput(event) => {
+                ui_view::ui_control::ui_text_field::handle_text_input(env, event)
+            }
             Event::AppWillResignActive => {
                 // Getting this event means touchHLE is becoming inactive, e.g.
                 // due to switching apps. The obvious way to handle this would
@@ -96,8 +102,14 @@ pub fn handle_events(env: &mut Environment) -> Option<Instant> {
                     log!("Ignoring EnterDebugger event: no debugger connected.");
                 }
             }
+            Event::MemoryWarning => {
+                log!("Handling MemoryWarning event: sending low-memory warning.");
+                ui_application::send_memory_warning(env);
+            }
         }
     }
 
+    ui_application::check_memory_usage(env);
+
     ui_accelerometer::handle_accelerometer(env)
 }
@@ -6,9 +6,12 @@
 //! `UIApplication` and `UIApplicationMain`.
 
 use super::ui_device::*;
-use crate::dyld::{export_c_func, FunctionExports};
-use crate::frameworks::foundation::{ns_array, ns_string};
+use super::ui_view::ui_status_bar::{animate_next_layout, set_status_bar_hidden, status_bar_frame};
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::frameworks::core_graphics::CGRect;
+use crate::frameworks::foundation::{ns_array, ns_string, ns_user_defaults, NSInteger};
 use crate::frameworks::uikit::ui_nib::load_main_nib_file;
+use crate::libc::mmap;
 use crate::mem::MutPtr;
 use crate::objc::{
     autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
@@ -22,8 +25,26 @@ pub struct State {
     /// [UIApplication sharedApplication]
     shared_application: Option<id>,
     pub(super) status_bar_hidden: bool,
+    pub(super) status_bar_style: UIStatusBarStyle,
+    /// How many of [MEMORY_WARNING_THRESHOLDS] memory usage was above when it
+    /// was last checked.
+    memory_warning_level: usize,
 }
 
+pub const UIApplicationDidReceiveMemoryWarningNotification: &str =
+    "UIApplicationDidReceiveMemoryWarningNotification";
+
+pub const CONSTANTS: ConstantExports = &[(
+    "_UIApplicationDidReceiveMemoryWarningNotification",
+    HostConstant::NSString(UIApplicationDidReceiveMemoryWarningNotification),
+)];
+
+/// Fractions of the device's RAM at which the app gets a low-memory warning.
+/// Much of the RAM is used by the OS and other processes, so apps typically
+/// got warnings long before using all of it. Like iPhone OS, there is a second,
+/// more urgent level.
+const MEMORY_WARNING_THRESHOLDS: [f64; 2] = [0.4, 0.5];
+
 struct UIApplicationHostObject {
     delegate: id,
     delegate_is_retained: bool,
@@ -32,6 +53,11 @@ impl HostObject for UIApplicationHostObject {}
 
 type UIInterfaceOrientation = UIDeviceOrientation;
 
+pub type UIStatusBarStyle = NSInteger;
+pub const UIStatusBarStyleDefault: UIStatusBarStyle = 0;
+pub const UIStatusBarStyleBlackTranslucent: UIStatusBarStyle = 1;
+pub const UIStatusBarStyleBlackOpaque: UIStatusBarStyle = 2;
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -55,6 +81,22 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (id)init {
     assert!(env.framework_state.uikit.ui_application.shared_application.is_none());
     env.framework_state.uikit.ui_application.shared_application = Some(this);
+
+    // The initial status bar state can be set in the Info.plist.
+    let status_bar_hidden = env.bundle.status_bar_hidden();
+    let status_bar_style = match env.bundle.status_bar_style() {
+        None | Some("UIStatusBarStyleDefault") => UIStatusBarStyleDefault,
+        Some("UIStatusBarStyleBlackTranslucent") => UIStatusBarStyleBlackTranslucent,
+        Some("UIStatusBarStyleBlackOpaque") => UIStatusBarStyleBlackOpaque,
+        Some(other) => {
+            log!("TODO: UIStatusBarStyle {:?}", other);
+            UIStatusBarStyleDefault
+        }
+    };
+    let state = &mut env.framework_state.uikit.ui_application;
+    state.status_bar_hidden = status_bar_hidden;
+    state.status_bar_style = status_bar_style;
+
     this
 }
 
@@ -76,14 +118,32 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
-// TODO: statusBarHidden getter
+- (bool)isStatusBarHidden {
+    env.framework_state.uikit.ui_application.status_bar_hidden
+}
 - (())setStatusBarHidden:(bool)hidden {
-    env.framework_state.uikit.ui_application.status_bar_hidden = hidden;
+    set_status_bar_hidden(env, hidden, false);
 }
 - (())setStatusBarHidden:(bool)hidden
-                animated:(bool)_animated {
-    // TODO: animation
-    msg![env; this setStatusBarHidden:hidden]
+                animated:(bool)animated {
+    set_status_bar_hidden(env, hidden, animated);
+}
+
+- (UIStatusBarStyle)statusBarStyle {
+    env.framework_state.uikit.ui_application.status_bar_style
+}
+- (())setStatusBarStyle:(UIStatusBarStyle)style {
+    env.framework_state.uikit.ui_application.status_bar_style = style;
+}
+- (())setStatusBarStyle:(UIStatusBarStyle)style
+               animated:(bool)_animated {
+    // The real status bar cross-fades to the new style. touchHLE just redraws
+    // it in the new style straight away.
+    msg![env; this setStatusBarStyle:style]
+}
+
+- (CGRect)statusBarFrame {
+    status_bar_frame(env)
 }
 
 - (UIInterfaceOrientation)statusBarOrientation {
@@ -102,9 +162,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     });
 }
 - (())setStatusBarOrientation:(UIInterfaceOrientation)orientation
-                     animated:(bool)_animated {
-    // TODO: animation
-    msg![env; this setStatusBarOrientation:orientation]
+                     animated:(bool)animated {
+    () = msg![env; this setStatusBarOrientation:orientation];
+    if animated {
+        animate_next_layout(env);
+    }
 }
 
 - (bool)idleTimerDisabled {
@@ -270,6 +332,85 @@ pub(super) fn UIApplicationMain(
     let _: () = msg![env; run_loop run];
 }
 
+/// For use by [super::handle_events]: send a low-memory warning if the app's
+/// memory usage has gone above another of the thresholds since the last check.
+pub(super) fn check_memory_usage(env: &mut Environment) {
+    let usage = u64::from(env.mem.heap_usage())
+        + u64::from(mmap::mapped_bytes(env))
+        + env.framework_state.opengles.texture_memory_usage();
+    let device_ram = u64::from(env.options.device_ram_mib.get()) * 1024 * 1024;
+
+    let state = &mut env.framework_state.uikit.ui_application;
+    if update_memory_warning_level(&mut state.memory_warning_level, usage, device_ram) {
+        log!(
+            "App is using {:.1}MiB of the device's {}MiB, sending low-memory warning.",
+            usage as f64 / (1024.0 * 1024.0),
+            env.options.device_ram_mib
+        );
+        send_memory_warning(env);
+    }
+}
+
+/// How many of [MEMORY_WARNING_THRESHOLDS] `usage` is above.
+fn memory_warning_level(usage: u64, device_ram: u64) -> usize {
+    MEMORY_WARNING_THRESHOLDS
+        .iter()
+        .filter(|&&threshold| usage as f64 > device_ram as f64 * threshold)
+        .count()
+}
+
+/// Store the new warning level for `usage` in `level` and return whether it
+/// went up, i.e. whether a warning should be sent. Staying above the same
+/// threshold doesn't cause repeated warnings.
+fn update_memory_warning_level(level: &mut usize, usage: u64, device_ram: u64) -> bool {
+    let new_level = memory_warning_level(usage, device_ram);
+    let old_level = std::mem::replace(level, new_level);
+    new_level > old_level
+}
+
+/// Send `applicationDidReceiveMemoryWarning:` to the app delegate, post
+/// `UIApplicationDidReceiveMemoryWarningNotification` and send
+/// `didReceiveMemoryWarning` to every view controller.
+pub(super) fn send_memory_warning(env: &mut Environment) {
+    log_dbg!("Sending low-memory warning");
+    let ui_application: id = msg_class![env; UIApplication sharedApplication];
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    let delegate: id = msg![env; ui_application delegate];
+    if env
+        .objc
+        .object_has_method_named(&env.mem, delegate, "applicationDidReceiveMemoryWarning:")
+    {
+        () = msg![env; delegate applicationDidReceiveMemoryWarning:ui_application];
+    }
+
+    let name = ns_string::get_static_str(env, UIApplicationDidReceiveMemoryWarningNotification);
+    let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+    () = msg![env; center postNotificationName:name object:ui_application];
+
+    let view_controllers = env
+        .framework_state
+        .uikit
+        .ui_view_controller
+        .view_controllers
+        .clone();
+    for view_controller in view_controllers {
+        // An earlier handler might have released this one.
+        if !env
+            .framework_state
+            .uikit
+            .ui_view_controller
+            .view_controllers
+            .contains(&view_controller)
+        {
+            continue;
+        }
+        () = msg![env; view_controller didReceiveMemoryWarning];
+    }
+
+    let _: () = msg![env; pool drain];
+}
+
 /// Tell the app it's about to quit and then exit.
 pub(super) fn exit(env: &mut Environment) {
     let ui_application: id = msg_class![env; UIApplication sharedApplication];
@@ -300,7 +441,44 @@ pub(super) fn exit(env: &mut Environment) {
         let _: () = msg![env; pool drain];
     };
 
+    ns_user_defaults::synchronize_standard_defaults(env);
+
+    env.print_allocator_leak_report();
+
     std::process::exit(0);
 }
 
 pub const FUNCTIONS: FunctionExports = &[export_c_func!(UIApplicationMain(_, _, _, _))];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn levels() {
+        let ram = 128 * MIB;
+        assert_eq!(memory_warning_level(0, ram), 0);
+        assert_eq!(memory_warning_level(ram * 2 / 5, ram), 0);
+        assert_eq!(memory_warning_level(ram * 2 / 5 + 1, ram), 1);
+        assert_eq!(memory_warning_level(ram / 2, ram), 1);
+        assert_eq!(memory_warning_level(ram / 2 + 1, ram), 2);
+        assert_eq!(memory_warning_level(ram * 2, ram), 2);
+    }
+
+    #[test]
+    fn warnings_are_not_repeated() {
+        let ram = 128 * MIB;
+        let mut level = 0;
+        assert!(!update_memory_warning_level(&mut level, 10 * MIB, ram));
+        assert!(update_memory_warning_level(&mut level, 60 * MIB, ram));
+        assert!(!update_memory_warning_level(&mut level, 61 * MIB, ram));
+        assert!(update_memory_warning_level(&mut level, 70 * MIB, ram));
+        assert!(!update_memory_warning_level(&mut level, 70 * MIB, ram));
+        // Dropping back down allows the warning to be sent again later.
+        assert!(!update_memory_warning_level(&mut level, 10 * MIB, ram));
+        assert!(update_memory_warning_level(&mut level, 100 * MIB, ram));
+        assert_eq!(level, 2);
+    }
+}
@@ -0,0 +1,102 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UIBarItem`.
+
+pub mod ui_bar_button_item;
+pub mod ui_tab_bar_item;
+
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::NSInteger;
+use crate::objc::{id, msg, nil, objc_classes, release, retain, ClassExports, HostObject};
+
+pub struct UIBarItemHostObject {
+    /// `NSString*`
+    title: id,
+    /// `UIImage*`
+    image: id,
+    tag: NSInteger,
+    enabled: bool,
+}
+impl HostObject for UIBarItemHostObject {}
+impl Default for UIBarItemHostObject {
+    fn default() -> Self {
+        UIBarItemHostObject {
+            title: nil,
+            image: nil,
+            tag: 0,
+            enabled: true,
+        }
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// Abstract class, the host object is always allocated by a subclass.
+@implementation UIBarItem: NSObject
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let key_ns_string = get_static_str(env, "UITitle");
+    let title: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    let key_ns_string = get_static_str(env, "UIImage");
+    let image: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    let key_ns_string = get_static_str(env, "UITag");
+    let tag: NSInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+    // TODO: decode other properties
+
+    () = msg![env; this setTitle:title];
+    () = msg![env; this setImage:image];
+    () = msg![env; this setTag:tag];
+
+    this
+}
+
+- (())dealloc {
+    let &UIBarItemHostObject { title, image, .. } = env.objc.borrow(this);
+    release(env, title);
+    release(env, image);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)title {
+    env.objc.borrow::<UIBarItemHostObject>(this).title
+}
+- (())setTitle:(id)title { // NSString*
+    let title: id = msg![env; title copy];
+    let host_obj = env.objc.borrow_mut::<UIBarItemHostObject>(this);
+    let old_title = std::mem::replace(&mut host_obj.title, title);
+    release(env, old_title);
+}
+
+- (id)image {
+    env.objc.borrow::<UIBarItemHostObject>(this).image
+}
+- (())setImage:(id)image { // UIImage*
+    retain(env, image);
+    let host_obj = env.objc.borrow_mut::<UIBarItemHostObject>(this);
+    let old_image = std::mem::replace(&mut host_obj.image, image);
+    release(env, old_image);
+}
+
+- (NSInteger)tag {
+    env.objc.borrow::<UIBarItemHostObject>(this).tag
+}
+- (())setTag:(NSInteger)tag {
+    env.objc.borrow_mut::<UIBarItemHostObject>(this).tag = tag;
+}
+
+- (bool)isEnabled {
+    env.objc.borrow::<UIBarItemHostObject>(this).enabled
+}
+- (())setEnabled:(bool)enabled {
+    env.objc.borrow_mut::<UIBarItemHostObject>(this).enabled = enabled;
+}
+
+@end
+
+};
@@ -0,0 +1,245 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UIBarButtonItem`.
+
+use crate::frameworks::core_graphics::CGFloat;
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::NSInteger;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_send, msg_super, nil, objc_classes, release,
+    retain, ClassExports, NSZonePtr, SEL,
+};
+use crate::Environment;
+
+pub type UIBarButtonItemStyle = NSInteger;
+pub const UIBarButtonItemStylePlain: UIBarButtonItemStyle = 0;
+pub const UIBarButtonItemStyleBordered: UIBarButtonItemStyle = 1;
+pub const UIBarButtonItemStyleDone: UIBarButtonItemStyle = 2;
+
+type UIBarButtonSystemItem = NSInteger;
+const UIBarButtonSystemItemDone: UIBarButtonSystemItem = 0;
+const UIBarButtonSystemItemCancel: UIBarButtonSystemItem = 1;
+const UIBarButtonSystemItemEdit: UIBarButtonSystemItem = 2;
+const UIBarButtonSystemItemSave: UIBarButtonSystemItem = 3;
+const UIBarButtonSystemItemAdd: UIBarButtonSystemItem = 4;
+const UIBarButtonSystemItemFlexibleSpace: UIBarButtonSystemItem = 5;
+const UIBarButtonSystemItemFixedSpace: UIBarButtonSystemItem = 6;
+
+pub struct UIBarButtonItemHostObject {
+    superclass: super::UIBarItemHostObject,
+    style: UIBarButtonItemStyle,
+    /// Weak reference.
+    target: id,
+    action: SEL,
+    width: CGFloat,
+    /// `UIView*`, strong reference.
+    custom_view: id,
+}
+impl_HostObject_with_superclass!(UIBarButtonItemHostObject);
+impl Default for UIBarButtonItemHostObject {
+    fn default() -> Self {
+        UIBarButtonItemHostObject {
+            superclass: Default::default(),
+            style: UIBarButtonItemStylePlain,
+            target: nil,
+            action: SEL::null(),
+            width: 0.0,
+            custom_view: nil,
+        }
+    }
+}
+
+/// Get the title and style a system item is displayed with.
+fn system_item_title(
+    env: &mut Environment,
+    system_item: UIBarButtonSystemItem,
+) -> (id, UIBarButtonItemStyle) {
+    let (title, style) = match system_item {
+        UIBarButtonSystemItemDone => ("Done", UIBarButtonItemStyleDone),
+        UIBarButtonSystemItemCancel => ("Cancel", UIBarButtonItemStyleBordered),
+        UIBarButtonSystemItemEdit => ("Edit", UIBarButtonItemStyleBordered),
+        UIBarButtonSystemItemSave => ("Save", UIBarButtonItemStyleDone),
+        UIBarButtonSystemItemAdd => ("+", UIBarButtonItemStyleBordered),
+        UIBarButtonSystemItemFlexibleSpace | UIBarButtonSystemItemFixedSpace => {
+            return (nil, UIBarButtonItemStylePlain)
+        }
+        _ => {
+            // The other system items are icons.
+            log!(
+                "TODO: UIBarButtonSystemItem {} (drawn as \"?\")",
+                system_item
+            );
+            ("?", UIBarButtonItemStyleBordered)
+        }
+    };
+    (get_static_str(env, title), style)
+}
+
+/// For use by bars: send the item's action message to its target, as if the
+/// user tapped it.
+pub fn send_action(env: &mut Environment, item: id) {
+    let &UIBarButtonItemHostObject { target, action, .. } = env.objc.borrow(item);
+    if action.is_null() {
+        return;
+    }
+    if target == nil {
+        // TODO: when the target is nil, the responder chain is searched for
+        // a suitable target
+        log!(
+            "TODO: bar button item {:?} with nil target, action {:?} (ignored)",
+            item,
+            action.as_str(&env.mem),
+        );
+        return;
+    }
+
+    let sel_str = action.as_str(&env.mem);
+    let colon_count = sel_str.bytes().filter(|&b| b == b':').count();
+    log_dbg!(
+        "Bar button item {:?} sending {:?} message to {:?}",
+        item,
+        sel_str,
+        target
+    );
+    match colon_count {
+        // - (IBAction)action;
+        0 => {
+            () = msg_send(env, (target, action));
+        }
+        // - (IBAction)action:(id)sender;
+        _ => {
+            () = msg_send(env, (target, action, item));
+        }
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UIBarButtonItem: UIBarItem
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UIBarButtonItemHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithTitle:(id)title // NSString*
+              style:(UIBarButtonItemStyle)style
+             target:(id)target
+             action:(SEL)action {
+    () = msg![env; this setTitle:title];
+    () = msg![env; this setStyle:style];
+    () = msg![env; this setTarget:target];
+    () = msg![env; this setAction:action];
+    this
+}
+
+- (id)initWithImage:(id)image // UIImage*
+              style:(UIBarButtonItemStyle)style
+             target:(id)target
+             action:(SEL)action {
+    () = msg![env; this setImage:image];
+    () = msg![env; this setStyle:style];
+    () = msg![env; this setTarget:target];
+    () = msg![env; this setAction:action];
+    this
+}
+
+- (id)initWithBarButtonSystemItem:(UIBarButtonSystemItem)system_item
+                           target:(id)target
+                           action:(SEL)action {
+    let (title, style) = system_item_title(env, system_item);
+    () = msg![env; this setTitle:title];
+    () = msg![env; this setStyle:style];
+    () = msg![env; this setTarget:target];
+    () = msg![env; this setAction:action];
+    this
+}
+
+- (id)initWithCustomView:(id)custom_view { // UIView*
+    () = msg![env; this setCustomView:custom_view];
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UIStyle");
+    let style: UIBarButtonItemStyle = msg![env; coder decodeIntegerForKey:key_ns_string];
+    () = msg![env; this setStyle:style];
+
+    let key_ns_string = get_static_str(env, "UIIsSystemItem");
+    if msg![env; coder decodeBoolForKey:key_ns_string] {
+        let key_ns_string = get_static_str(env, "UISystemItem");
+        let system_item: UIBarButtonSystemItem = msg![env; coder decodeIntegerForKey:key_ns_string];
+        let (title, style) = system_item_title(env, system_item);
+        () = msg![env; this setTitle:title];
+        () = msg![env; this setStyle:style];
+    }
+
+    let key_ns_string = get_static_str(env, "UIWidth");
+    let width: CGFloat = msg![env; coder decodeFloatForKey:key_ns_string];
+    () = msg![env; this setWidth:width];
+
+    let key_ns_string = get_static_str(env, "UICustomView");
+    let custom_view: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setCustomView:custom_view];
+
+    // The target and action are connected by a UIRuntimeEventConnection.
+
+    this
+}
+
+- (())dealloc {
+    let custom_view = env.objc.borrow::<UIBarButtonItemHostObject>(this).custom_view;
+    release(env, custom_view);
+    msg_super![env; this dealloc]
+}
+
+- (UIBarButtonItemStyle)style {
+    env.objc.borrow::<UIBarButtonItemHostObject>(this).style
+}
+- (())setStyle:(UIBarButtonItemStyle)style {
+    env.objc.borrow_mut::<UIBarButtonItemHostObject>(this).style = style;
+}
+
+- (id)target {
+    env.objc.borrow::<UIBarButtonItemHostObject>(this).target
+}
+- (())setTarget:(id)target {
+    // The target is a *weak* reference!
+    env.objc.borrow_mut::<UIBarButtonItemHostObject>(this).target = target;
+}
+
+- (SEL)action {
+    env.objc.borrow::<UIBarButtonItemHostObject>(this).action
+}
+- (())setAction:(SEL)action {
+    env.objc.borrow_mut::<UIBarButtonItemHostObject>(this).action = action;
+}
+
+- (CGFloat)width {
+    env.objc.borrow::<UIBarButtonItemHostObject>(this).width
+}
+- (())setWidth:(CGFloat)width {
+    env.objc.borrow_mut::<UIBarButtonItemHostObject>(this).width = width;
+}
+
+- (id)customView {
+    env.objc.borrow::<UIBarButtonItemHostObject>(this).custom_view
+}
+- (())setCustomView:(id)custom_view { // UIView*
+    retain(env, custom_view);
+    let host_obj = env.objc.borrow_mut::<UIBarButtonItemHostObject>(this);
+    let old_view = std::mem::replace(&mut host_obj.custom_view, custom_view);
+    release(env, old_view);
+}
+
+@end
+
+};
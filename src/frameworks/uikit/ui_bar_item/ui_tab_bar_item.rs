@@ -0,0 +1,109 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UITabBarItem`.
+
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::NSInteger;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_super, nil, objc_classes, release, ClassExports,
+    NSZonePtr,
+};
+
+type UITabBarSystemItem = NSInteger;
+
+pub struct UITabBarItemHostObject {
+    superclass: super::UIBarItemHostObject,
+    /// `NSString*`
+    badge_value: id,
+}
+impl_HostObject_with_superclass!(UITabBarItemHostObject);
+impl Default for UITabBarItemHostObject {
+    fn default() -> Self {
+        UITabBarItemHostObject {
+            superclass: Default::default(),
+            badge_value: nil,
+        }
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UITabBarItem: UIBarItem
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UITabBarItemHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithTitle:(id)title // NSString*
+              image:(id)image // UIImage*
+                tag:(NSInteger)tag {
+    () = msg![env; this setTitle:title];
+    () = msg![env; this setImage:image];
+    () = msg![env; this setTag:tag];
+    this
+}
+
+- (id)initWithTabBarSystemItem:(UITabBarSystemItem)system_item
+                           tag:(NSInteger)tag {
+    // TODO: draw the system item icons
+    let title = match system_item {
+        0 => "More",
+        1 => "Favorites",
+        2 => "Featured",
+        3 => "Top Rated",
+        4 => "Recents",
+        5 => "Contacts",
+        6 => "History",
+        7 => "Bookmarks",
+        8 => "Search",
+        9 => "Downloads",
+        10 => "Most Recent",
+        11 => "Most Viewed",
+        _ => {
+            log!("Warning: unknown UITabBarSystemItem {}", system_item);
+            ""
+        }
+    };
+    let title = get_static_str(env, title);
+    () = msg![env; this setTitle:title];
+    () = msg![env; this setTag:tag];
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UIBadgeValue");
+    let badge_value: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setBadgeValue:badge_value];
+
+    this
+}
+
+- (())dealloc {
+    let badge_value = env.objc.borrow::<UITabBarItemHostObject>(this).badge_value;
+    release(env, badge_value);
+    msg_super![env; this dealloc]
+}
+
+- (id)badgeValue {
+    env.objc.borrow::<UITabBarItemHostObject>(this).badge_value
+}
+- (())setBadgeValue:(id)badge_value { // NSString*
+    // TODO: draw badges
+    let badge_value: id = msg![env; badge_value copy];
+    let host_obj = env.objc.borrow_mut::<UITabBarItemHostObject>(this);
+    let old_value = std::mem::replace(&mut host_obj.badge_value, badge_value);
+    release(env, old_value);
+}
+
+@end
+
+};
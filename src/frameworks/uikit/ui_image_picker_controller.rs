@@ -14,9 +14,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
 
-// TODO: should extend UINavigationController, which extends
-//       UIViewController.
-@implementation UIImagePickerController: UIViewController
+@implementation UIImagePickerController: UINavigationController
 
 + (bool)isSourceTypeAvailable:(UIImagePickerControllerSourceType)_type {
     // For now, simply claim no sources are available.
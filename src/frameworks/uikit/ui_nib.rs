@@ -189,7 +189,30 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())connect {
-    log!("TODO: [(UIRuntimeEventConnection*) {:?} connect]", this);
+    let &UIRuntimeEventConnectionHostObject {
+        superclass: UIRuntimeConnectionHostObject {
+            destination,
+            label,
+            source,
+        },
+        eventMask: event_mask,
+    } = env.objc.borrow(this);
+
+    let action = to_rust_string(env, label).to_string();
+    let action = env.objc.register_host_selector(action, &mut env.mem);
+
+    // Bar button items aren't controls, but their actions are connected the
+    // same way.
+    let bar_button_item_class = env.objc.get_known_class("UIBarButtonItem", &mut env.mem);
+    if msg![env; source isKindOfClass:bar_button_item_class] {
+        () = msg![env; source setTarget:destination];
+        () = msg![env; source setAction:action];
+    } else {
+        let events = event_mask as NSUInteger;
+        () = msg![env; source addTarget:destination
+                                 action:action
+                       forControlEvents:events];
+    }
 }
 
 // NSCoding implementation
@@ -5,7 +5,23 @@
  */
 //! `UIResponder`.
 
-use crate::objc::{id, objc_classes, ClassExports};
+use crate::objc::{id, msg, nil, objc_classes, ClassExports};
+use crate::Environment;
+
+#[derive(Default)]
+pub(super) struct State {
+    /// The current first responder, if any. This is a weak reference.
+    first_responder: Option<id>,
+}
+
+/// Get the current first responder, or [nil] if there is none.
+pub fn first_responder(env: &Environment) -> id {
+    env.framework_state
+        .uikit
+        .ui_responder
+        .first_responder
+        .unwrap_or(nil)
+}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -49,12 +65,41 @@ pub const CLASSES: ClassExports = objc_classes! {
     );
 }
 
-- (bool)becomeFirstResponder {
-    // TODO
+// TODO: nextResponder and the rest of the responder chain
+
+- (bool)canBecomeFirstResponder {
     false
 }
+- (bool)canResignFirstResponder {
+    true
+}
+- (bool)isFirstResponder {
+    first_responder(env) == this
+}
+
+- (bool)becomeFirstResponder {
+    let current = first_responder(env);
+    if current == this {
+        return true;
+    }
+    if !msg![env; this canBecomeFirstResponder] {
+        return false;
+    }
+    if current != nil && !msg![env; current resignFirstResponder] {
+        return false;
+    }
+    log_dbg!("New first responder: {:?}", this);
+    env.framework_state.uikit.ui_responder.first_responder = Some(this);
+    true
+}
 - (bool)resignFirstResponder {
-    // TODO
+    if first_responder(env) == this {
+        if !msg![env; this canResignFirstResponder] {
+            return false;
+        }
+        log_dbg!("{:?} is no longer first responder", this);
+        env.framework_state.uikit.ui_responder.first_responder = None;
+    }
     true
 }
 
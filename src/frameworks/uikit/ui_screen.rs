@@ -5,6 +5,7 @@
  */
 //! `UIScreen`.
 
+use super::ui_view::ui_status_bar::STATUS_BAR_HEIGHT;
 use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
 use crate::objc::{id, msg, objc_classes, ClassExports, TrivialHostObject};
 
@@ -50,7 +51,6 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (CGRect)applicationFrame {
     let mut bounds: CGRect = msg![env; this bounds];
-    const STATUS_BAR_HEIGHT: f32 = 20.0;
     if !env.framework_state.uikit.ui_application.status_bar_hidden {
         bounds.origin.y += STATUS_BAR_HEIGHT;
         bounds.size.height -= STATUS_BAR_HEIGHT;
@@ -8,29 +8,41 @@
 //! Useful resources:
 //! - Apple's [View Programming Guide for iOS](https://developer.apple.com/library/archive/documentation/WindowsViews/Conceptual/ViewPG_iPhoneOS/Introduction/Introduction.html)
 
+pub mod animation;
+pub mod drawing;
+pub mod ui_action_sheet;
 pub mod ui_alert_view;
 pub mod ui_control;
 pub mod ui_image_view;
 pub mod ui_label;
+pub mod ui_navigation_bar;
+pub mod ui_progress_view;
+pub mod ui_status_bar;
+pub mod ui_tab_bar;
 pub mod ui_window;
 
 use super::ui_graphics::{UIGraphicsPopContext, UIGraphicsPushContext};
+use crate::frameworks::core_animation::ca_layer::frame_from_geometry;
 use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
 use crate::frameworks::core_graphics::cg_context::{CGContextClearRect, CGContextRef};
 use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect};
 use crate::frameworks::foundation::ns_string::get_static_str;
-use crate::frameworks::foundation::{ns_array, NSInteger, NSUInteger};
+use crate::frameworks::foundation::{ns_array, NSInteger, NSTimeInterval, NSUInteger};
+use crate::mem::MutVoidPtr;
 use crate::objc::{
-    autorelease, id, msg, nil, objc_classes, release, retain, Class, ClassExports, HostObject,
-    NSZonePtr,
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports,
+    HostObject, NSZonePtr, SEL,
 };
 use crate::Environment;
+use animation::{UIViewAnimationCurve, Value};
 
 #[derive(Default)]
 pub struct State {
     /// List of views for internal purposes. Non-retaining!
     pub(super) views: Vec<id>,
     pub ui_window: ui_window::State,
+    animation: animation::State,
+    ui_status_bar: ui_status_bar::State,
 }
 
 pub(super) struct UIViewHostObject {
@@ -96,6 +108,55 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.get_known_class("CALayer", &mut env.mem)
 }
 
+// Animations (see animation.rs)
+
++ (())beginAnimations:(id)animation_id // NSString*
+              context:(MutVoidPtr)context {
+    animation::begin_animations(env, animation_id, context);
+}
++ (())commitAnimations {
+    animation::commit_animations(env);
+}
++ (())setAnimationDuration:(NSTimeInterval)duration {
+    animation::set_duration(env, duration);
+}
++ (())setAnimationDelay:(NSTimeInterval)delay {
+    animation::set_delay(env, delay);
+}
++ (())setAnimationCurve:(UIViewAnimationCurve)curve {
+    animation::set_curve(env, curve);
+}
++ (())setAnimationRepeatCount:(f32)repeat_count {
+    animation::set_repeat_count(env, repeat_count);
+}
++ (())setAnimationRepeatAutoreverses:(bool)repeat_autoreverses {
+    animation::set_repeat_autoreverses(env, repeat_autoreverses);
+}
++ (())setAnimationBeginsFromCurrentState:(bool)_from_current_state {
+    // Animations always begin from the current presentation value, which is
+    // the behavior this asks for.
+}
++ (())setAnimationDelegate:(id)delegate {
+    animation::set_delegate(env, delegate);
+}
++ (())setAnimationWillStartSelector:(SEL)selector {
+    animation::set_will_start_selector(env, selector);
+}
++ (())setAnimationDidStopSelector:(SEL)selector {
+    animation::set_did_stop_selector(env, selector);
+}
++ (())setAnimationTransition:(NSInteger)transition // UIViewAnimationTransition
+                     forView:(id)view
+                       cache:(bool)_cache {
+    log!("TODO: [UIView setAnimationTransition:{} forView:{:?} cache:]", transition, view);
+}
++ (bool)areAnimationsEnabled {
+    animation::animations_enabled(env)
+}
++ (())setAnimationsEnabled:(bool)enabled {
+    animation::set_animations_enabled(env, enabled);
+}
+
 // TODO: accessors etc
 
 // initWithCoder: and initWithFrame: are basically UIView's designated
@@ -313,12 +374,10 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (CGFloat)alpha {
-    let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
-    msg![env; layer opacity]
+    animation::get_alpha(env, this)
 }
 - (())setAlpha:(CGFloat)alpha {
-    let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
-    msg![env; layer setOpacity:alpha]
+    animation::set_value(env, this, Value::Alpha(alpha));
 }
 
 // FIXME: CALayer's backgroundColor should be a CGColorRef, which is supposedly
@@ -340,34 +399,50 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; layer setNeedsDisplay]
 }
 
+// The geometry accessors go through animation.rs so that they can be
+// animated, rather than directly to the layer.
 - (CGRect)bounds {
-    let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
-    msg![env; layer bounds]
+    animation::get_bounds(env, this)
 }
 - (())setBounds:(CGRect)bounds {
-    let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
-    msg![env; layer setBounds:bounds]
+    animation::set_value(env, this, Value::Bounds(bounds));
 }
 - (CGPoint)center {
     // FIXME: what happens if [layer anchorPoint] isn't (0.5, 0.5)?
-    let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
-    msg![env; layer position]
+    animation::get_center(env, this)
 }
 - (())setCenter:(CGPoint)center {
-    let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
-    msg![env; layer setPosition:center]
+    animation::set_value(env, this, Value::Center(center));
 }
+// These match CALayer's frame accessors, but use the view's model values.
 - (CGRect)frame {
     let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
-    msg![env; layer frame]
+    let anchor_point: CGPoint = msg![env; layer anchorPoint];
+    let bounds: CGRect = msg![env; this bounds];
+    let center: CGPoint = msg![env; this center];
+    let transform: CGAffineTransform = msg![env; this transform];
+    frame_from_geometry(bounds, center, anchor_point, transform)
 }
 - (())setFrame:(CGRect)frame {
     let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
-    msg![env; layer setFrame:frame]
+    let anchor_point: CGPoint = msg![env; layer anchorPoint];
+    let center = CGPoint {
+        x: frame.origin.x + frame.size.width * anchor_point.x,
+        y: frame.origin.y + frame.size.height * anchor_point.y,
+    };
+    let bounds = CGRect {
+        origin: CGPoint { x: 0.0, y: 0.0 },
+        size: frame.size,
+    };
+    animation::set_value(env, this, Value::Center(center));
+    animation::set_value(env, this, Value::Bounds(bounds));
+}
+
+- (CGAffineTransform)transform {
+    animation::get_transform(env, this)
 }
-
 - (())setTransform:(CGAffineTransform)transform {
-    log!("TODO: [{:?} setTransform:{:?}]", this, transform);
+    animation::set_value(env, this, Value::Transform(transform));
 }
 
 - (())setContentMode:(NSInteger)content_mode { // should be UIViewContentMode
@@ -399,6 +474,13 @@ pub const CLASSES: ClassExports = objc_classes! {
     UIGraphicsPopContext(env);
 }
 
+- (id)actionForLayer:(id)_layer // CALayer*
+              forKey:(id)_key { // NSString*
+    // Views don't use Core Animation's implicit animations, they have their own
+    // animation system (see animation.rs).
+    msg_class![env; NSNull null]
+}
+
 // Event handling
 
 - (bool)pointInside:(CGPoint)point
@@ -421,12 +503,8 @@ pub const CLASSES: ClassExports = objc_classes! {
         if hidden || alpha < 0.01 || !interactible {
            continue;
         }
-        let frame: CGRect = msg![env; subview frame];
-        let bounds: CGRect = msg![env; subview bounds];
-        let point = CGPoint {
-            x: point.x - frame.origin.x + bounds.origin.x,
-            y: point.y - frame.origin.y + bounds.origin.y,
-        };
+        // This takes the subview's transform into account.
+        let point: CGPoint = msg![env; subview convertPoint:point fromView:this];
         let subview: id = msg![env; subview hitTest:point withEvent:event];
         if subview != nil {
             return subview;
@@ -0,0 +1,530 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UIView` animations, i.e. `+beginAnimations:context:` and
+//! `+commitAnimations`.
+//!
+//! Property changes made on a view between those two calls are not applied
+//! immediately, but recorded. Once committed, they are interpolated by
+//! [advance_animations], which the Core Animation compositor calls each frame.
+//! While an animation is in flight, the view's layer holds the interpolated
+//! ("presentation") value, and the view's getters return the final ("model")
+//! value, like on a real device.
+//!
+//! Useful resources:
+//! - Apple's [View Programming Guide for iOS](https://developer.apple.com/library/archive/documentation/WindowsViews/Conceptual/ViewPG_iPhoneOS/AnimatingViews/AnimatingViews.html)
+
+use super::UIViewHostObject;
+use crate::frameworks::core_animation::ca_media_timing_function::{
+    control_points_for_name, cubic_bezier, kCAMediaTimingFunctionEaseIn,
+    kCAMediaTimingFunctionEaseInEaseOut, kCAMediaTimingFunctionEaseOut,
+    kCAMediaTimingFunctionLinear,
+};
+use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::{NSInteger, NSTimeInterval};
+use crate::mem::MutVoidPtr;
+use crate::objc::{id, msg, msg_class, msg_send, nil, release, retain, SEL};
+use crate::Environment;
+use std::time::{Duration, Instant};
+
+pub type UIViewAnimationCurve = NSInteger;
+pub const UIViewAnimationCurveEaseInOut: UIViewAnimationCurve = 0;
+pub const UIViewAnimationCurveEaseIn: UIViewAnimationCurve = 1;
+pub const UIViewAnimationCurveEaseOut: UIViewAnimationCurve = 2;
+pub const UIViewAnimationCurveLinear: UIViewAnimationCurve = 3;
+
+#[derive(Default)]
+pub struct State {
+    /// Animation blocks opened with `beginAnimations:context:` that haven't
+    /// been committed yet, innermost last.
+    pending: Vec<Animation>,
+    /// Committed animations, oldest first.
+    running: Vec<Animation>,
+    /// See `setAnimationsEnabled:`.
+    disabled: bool,
+}
+
+/// An animatable view property and its value.
+#[derive(Copy, Clone, Debug)]
+pub enum Value {
+    Bounds(CGRect),
+    Center(CGPoint),
+    Alpha(CGFloat),
+    Transform(CGAffineTransform),
+}
+impl Value {
+    fn same_property(&self, other: &Value) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    fn interpolate(from: Value, to: Value, t: CGFloat) -> Value {
+        fn lerp(a: CGFloat, b: CGFloat, t: CGFloat) -> CGFloat {
+            a + (b - a) * t
+        }
+        fn lerp_point(a: CGPoint, b: CGPoint, t: CGFloat) -> CGPoint {
+            CGPoint {
+                x: lerp(a.x, b.x, t),
+                y: lerp(a.y, b.y, t),
+            }
+        }
+
+        match (from, to) {
+            (Value::Bounds(a), Value::Bounds(b)) => Value::Bounds(CGRect {
+                origin: lerp_point(a.origin, b.origin, t),
+                size: CGSize {
+                    width: lerp(a.size.width, b.size.width, t),
+                    height: lerp(a.size.height, b.size.height, t),
+                },
+            }),
+            (Value::Center(a), Value::Center(b)) => Value::Center(lerp_point(a, b, t)),
+            (Value::Alpha(a), Value::Alpha(b)) => Value::Alpha(lerp(a, b, t)),
+            // Component-wise interpolation isn't quite what iPhone OS does for
+            // rotations, but it's close enough for small angles.
+            (Value::Transform(a), Value::Transform(b)) => Value::Transform(CGAffineTransform {
+                a: lerp(a.a, b.a, t),
+                b: lerp(a.b, b.b, t),
+                c: lerp(a.c, b.c, t),
+                d: lerp(a.d, b.d, t),
+                tx: lerp(a.tx, b.tx, t),
+                ty: lerp(a.ty, b.ty, t),
+            }),
+            // Values of different properties can't be interpolated, so jump
+            // straight to the end.
+            _ => to,
+        }
+    }
+}
+
+struct Change {
+    /// `UIView*`, strong reference.
+    view: id,
+    from: Value,
+    to: Value,
+}
+
+/// An animation block, either pending or running.
+struct Animation {
+    /// `NSString*`, strong reference. May be [nil].
+    animation_id: id,
+    context: MutVoidPtr,
+    duration: NSTimeInterval,
+    delay: NSTimeInterval,
+    curve: UIViewAnimationCurve,
+    repeat_count: f32,
+    repeat_autoreverses: bool,
+    /// Strong reference. May be [nil].
+    delegate: id,
+    will_start_selector: Option<SEL>,
+    did_stop_selector: Option<SEL>,
+    changes: Vec<Change>,
+    /// Set once committed.
+    start_time: Option<Instant>,
+    /// Whether `will_start_selector` has been sent.
+    started: bool,
+    /// Whether a newer animation or change replaced some of this one's
+    /// changes before they finished.
+    interrupted: bool,
+}
+
+fn state(env: &mut Environment) -> &mut State {
+    &mut env.framework_state.uikit.ui_view.animation
+}
+
+/// Get the innermost animation block that is still being set up, if any.
+/// Calling the `setAnimation...` methods outside an animation block does
+/// nothing, but it's useful to know about.
+fn current_pending(env: &mut Environment) -> Option<&mut Animation> {
+    let pending = state(env).pending.last_mut();
+    if pending.is_none() {
+        log!("Warning: UIView animation parameter set outside an animation block, ignoring");
+    }
+    pending
+}
+
+pub fn begin_animations(env: &mut Environment, animation_id: id, context: MutVoidPtr) {
+    retain(env, animation_id);
+    state(env).pending.push(Animation {
+        animation_id,
+        context,
+        duration: 0.2,
+        delay: 0.0,
+        curve: UIViewAnimationCurveEaseInOut,
+        repeat_count: 0.0,
+        repeat_autoreverses: false,
+        delegate: nil,
+        will_start_selector: None,
+        did_stop_selector: None,
+        changes: Vec::new(),
+        start_time: None,
+        started: false,
+        interrupted: false,
+    });
+}
+
+pub fn commit_animations(env: &mut Environment) {
+    let Some(mut animation) = state(env).pending.pop() else {
+        log!("Warning: commitAnimations called without beginAnimations:context:, ignoring");
+        return;
+    };
+    log_dbg!(
+        "Committing UIView animation {:?} with {} change(s), duration {}s, delay {}s",
+        animation.animation_id,
+        animation.changes.len(),
+        animation.duration,
+        animation.delay,
+    );
+
+    // A newer animation of a property replaces any older one.
+    for change in &animation.changes {
+        cancel_running(env, change.view, &change.to);
+    }
+
+    animation.start_time = Some(Instant::now());
+    state(env).running.push(animation);
+}
+
+pub fn set_duration(env: &mut Environment, duration: NSTimeInterval) {
+    if let Some(animation) = current_pending(env) {
+        animation.duration = duration;
+    }
+}
+pub fn set_delay(env: &mut Environment, delay: NSTimeInterval) {
+    if let Some(animation) = current_pending(env) {
+        animation.delay = delay;
+    }
+}
+pub fn set_curve(env: &mut Environment, curve: UIViewAnimationCurve) {
+    if let Some(animation) = current_pending(env) {
+        animation.curve = curve;
+    }
+}
+pub fn set_repeat_count(env: &mut Environment, repeat_count: f32) {
+    if let Some(animation) = current_pending(env) {
+        animation.repeat_count = repeat_count;
+    }
+}
+pub fn set_repeat_autoreverses(env: &mut Environment, repeat_autoreverses: bool) {
+    if let Some(animation) = current_pending(env) {
+        animation.repeat_autoreverses = repeat_autoreverses;
+    }
+}
+pub fn set_delegate(env: &mut Environment, delegate: id) {
+    let Some(animation) = current_pending(env) else {
+        return;
+    };
+    let old_delegate = std::mem::replace(&mut animation.delegate, delegate);
+    retain(env, delegate);
+    release(env, old_delegate);
+}
+pub fn set_will_start_selector(env: &mut Environment, selector: SEL) {
+    if let Some(animation) = current_pending(env) {
+        animation.will_start_selector = (!selector.is_null()).then_some(selector);
+    }
+}
+pub fn set_did_stop_selector(env: &mut Environment, selector: SEL) {
+    if let Some(animation) = current_pending(env) {
+        animation.did_stop_selector = (!selector.is_null()).then_some(selector);
+    }
+}
+
+pub fn animations_enabled(env: &mut Environment) -> bool {
+    !state(env).disabled
+}
+pub fn set_animations_enabled(env: &mut Environment, enabled: bool) {
+    state(env).disabled = !enabled;
+}
+
+/// Get the presentation value of a property, i.e. what is currently visible.
+fn get_presentation_value(env: &mut Environment, view: id, property: &Value) -> Value {
+    let layer = env.objc.borrow::<UIViewHostObject>(view).layer;
+    match property {
+        Value::Bounds(_) => Value::Bounds(msg![env; layer bounds]),
+        Value::Center(_) => Value::Center(msg![env; layer position]),
+        Value::Alpha(_) => {
+            let opacity: f32 = msg![env; layer opacity];
+            Value::Alpha(opacity)
+        }
+        Value::Transform(_) => Value::Transform(msg![env; layer affineTransform]),
+    }
+}
+
+/// Set the presentation value of a property, i.e. what is currently visible.
+fn set_presentation_value(env: &mut Environment, view: id, value: Value) {
+    let layer = env.objc.borrow::<UIViewHostObject>(view).layer;
+    match value {
+        Value::Bounds(bounds) => msg![env; layer setBounds:bounds],
+        Value::Center(center) => msg![env; layer setPosition:center],
+        Value::Alpha(alpha) => msg![env; layer setOpacity:alpha],
+        Value::Transform(transform) => msg![env; layer setAffineTransform:transform],
+    }
+}
+
+/// Find the model value of a property, i.e. the value it will have once all
+/// animations have finished, if it is being animated. `extract` picks out the
+/// property's value.
+fn animated_model_value<T>(
+    env: &mut Environment,
+    view: id,
+    extract: impl Fn(&Value) -> Option<T>,
+) -> Option<T> {
+    let state = state(env);
+    state
+        .pending
+        .iter()
+        .chain(state.running.iter())
+        .rev()
+        .flat_map(|animation| animation.changes.iter())
+        .filter(|change| change.view == view)
+        .find_map(|change| extract(&change.to))
+}
+
+// Getters for the model values of each property. If the property isn't being
+// animated, the model value is the same as the layer's value.
+
+pub fn get_bounds(env: &mut Environment, view: id) -> CGRect {
+    let bounds = animated_model_value(env, view, |value| match *value {
+        Value::Bounds(bounds) => Some(bounds),
+        _ => None,
+    });
+    bounds.unwrap_or_else(|| {
+        let layer = env.objc.borrow::<UIViewHostObject>(view).layer;
+        msg![env; layer bounds]
+    })
+}
+pub fn get_center(env: &mut Environment, view: id) -> CGPoint {
+    let center = animated_model_value(env, view, |value| match *value {
+        Value::Center(center) => Some(center),
+        _ => None,
+    });
+    center.unwrap_or_else(|| {
+        let layer = env.objc.borrow::<UIViewHostObject>(view).layer;
+        msg![env; layer position]
+    })
+}
+pub fn get_alpha(env: &mut Environment, view: id) -> CGFloat {
+    let alpha = animated_model_value(env, view, |value| match *value {
+        Value::Alpha(alpha) => Some(alpha),
+        _ => None,
+    });
+    alpha.unwrap_or_else(|| {
+        let layer = env.objc.borrow::<UIViewHostObject>(view).layer;
+        msg![env; layer opacity]
+    })
+}
+pub fn get_transform(env: &mut Environment, view: id) -> CGAffineTransform {
+    let transform = animated_model_value(env, view, |value| match *value {
+        Value::Transform(transform) => Some(transform),
+        _ => None,
+    });
+    transform.unwrap_or_else(|| {
+        let layer = env.objc.borrow::<UIViewHostObject>(view).layer;
+        msg![env; layer affineTransform]
+    })
+}
+
+/// Set the value of a property. Inside an animation block, this records a
+/// change to be animated, otherwise it takes effect immediately.
+pub fn set_value(env: &mut Environment, view: id, value: Value) {
+    if state(env).disabled || state(env).pending.is_empty() {
+        cancel_running(env, view, &value);
+        set_presentation_value(env, view, value);
+        return;
+    }
+
+    let from = get_presentation_value(env, view, &value);
+    let Some(animation) = state(env).pending.last_mut() else {
+        return;
+    };
+    if let Some(change) = animation
+        .changes
+        .iter_mut()
+        .find(|change| change.view == view && change.to.same_property(&value))
+    {
+        change.to = value;
+    } else {
+        animation.changes.push(Change {
+            view,
+            from,
+            to: value,
+        });
+        retain(env, view);
+    }
+}
+
+/// Animate a property of a view straight away, separately from any animation
+/// block the app has open. This is for views UIKit animates itself, like the
+/// status bar. A `duration` of zero sets the value immediately.
+pub fn animate_value_now(env: &mut Environment, view: id, to: Value, duration: NSTimeInterval) {
+    cancel_running(env, view, &to);
+    if duration <= 0.0 {
+        set_presentation_value(env, view, to);
+        return;
+    }
+    let from = get_presentation_value(env, view, &to);
+    retain(env, view);
+    state(env).running.push(Animation {
+        animation_id: nil,
+        context: MutVoidPtr::null(),
+        duration,
+        delay: 0.0,
+        curve: UIViewAnimationCurveEaseInOut,
+        repeat_count: 0.0,
+        repeat_autoreverses: false,
+        delegate: nil,
+        will_start_selector: None,
+        did_stop_selector: None,
+        changes: vec![Change { view, from, to }],
+        start_time: Some(Instant::now()),
+        started: false,
+        interrupted: false,
+    });
+}
+
+/// Stop animating a property of a view, leaving its presentation value as-is.
+fn cancel_running(env: &mut Environment, view: id, property: &Value) {
+    let mut cancelled = Vec::new();
+    for animation in state(env).running.iter_mut() {
+        let count_before = cancelled.len();
+        animation.changes.retain(|change| {
+            let matches = change.view == view && change.to.same_property(property);
+            if matches {
+                cancelled.push(change.view);
+            }
+            !matches
+        });
+        if cancelled.len() != count_before {
+            animation.interrupted = true;
+        }
+    }
+    for view in cancelled {
+        release(env, view);
+    }
+}
+
+fn apply_curve(curve: UIViewAnimationCurve, t: f32) -> f32 {
+    let name = match curve {
+        UIViewAnimationCurveEaseInOut => kCAMediaTimingFunctionEaseInEaseOut,
+        UIViewAnimationCurveEaseIn => kCAMediaTimingFunctionEaseIn,
+        UIViewAnimationCurveEaseOut => kCAMediaTimingFunctionEaseOut,
+        UIViewAnimationCurveLinear => kCAMediaTimingFunctionLinear,
+        _ => {
+            log!("Unknown UIViewAnimationCurve {}, using linear", curve);
+            kCAMediaTimingFunctionLinear
+        }
+    };
+    cubic_bezier(control_points_for_name(name).unwrap(), t)
+}
+
+/// For use by the Core Animation compositor: update the presentation values
+/// of all animated views, and notify delegates of animations that started or
+/// finished.
+///
+/// Returns the time the next update is due, if there are animations running.
+pub fn advance_animations(env: &mut Environment) -> Option<Instant> {
+    if state(env).running.is_empty() {
+        return None;
+    }
+
+    let now = Instant::now();
+    let mut updates = Vec::new();
+    let mut newly_started = Vec::new();
+    let mut finished = Vec::new();
+
+    let running = std::mem::take(&mut state(env).running);
+    for mut animation in running {
+        if animation.interrupted && animation.changes.is_empty() {
+            // Nothing left to animate.
+            finished.push(animation);
+            continue;
+        }
+        let start_time = animation.start_time.unwrap();
+        let delay = Duration::from_secs_f64(animation.delay.max(0.0));
+        let Some(elapsed) = now.checked_duration_since(start_time + delay) else {
+            // Still waiting for the delay to pass.
+            state(env).running.push(animation);
+            continue;
+        };
+        let elapsed = elapsed.as_secs_f64();
+
+        if !animation.started {
+            animation.started = true;
+            if let Some(selector) = animation.will_start_selector {
+                newly_started.push((
+                    animation.delegate,
+                    selector,
+                    animation.animation_id,
+                    animation.context,
+                ));
+            }
+        }
+
+        let cycles = (animation.repeat_count as f64).max(1.0);
+        if animation.duration <= 0.0 || elapsed >= animation.duration * cycles {
+            for change in &animation.changes {
+                updates.push((change.view, change.to));
+            }
+            finished.push(animation);
+            continue;
+        }
+
+        let cycle = elapsed / animation.duration;
+        let mut t = cycle.fract() as f32;
+        if animation.repeat_autoreverses && (cycle as u64) & 1 != 0 {
+            t = 1.0 - t;
+        }
+        let t = apply_curve(animation.curve, t);
+        for change in &animation.changes {
+            updates.push((change.view, Value::interpolate(change.from, change.to, t)));
+        }
+        state(env).running.push(animation);
+    }
+
+    for (view, value) in updates {
+        set_presentation_value(env, view, value);
+    }
+
+    for (delegate, selector, animation_id, context) in newly_started {
+        if delegate != nil {
+            () = msg_send(env, (delegate, selector, animation_id, context));
+        }
+    }
+
+    for animation in finished {
+        let Animation {
+            animation_id,
+            context,
+            delegate,
+            did_stop_selector,
+            changes,
+            interrupted,
+            ..
+        } = animation;
+        log_dbg!(
+            "UIView animation {:?} {}",
+            animation_id,
+            if interrupted {
+                "interrupted"
+            } else {
+                "finished"
+            }
+        );
+        if let (true, Some(selector)) = (delegate != nil, did_stop_selector) {
+            let finished: id = msg_class![env; NSNumber numberWithBool:(!interrupted)];
+            () = msg_send(env, (delegate, selector, animation_id, finished, context));
+        }
+        for change in changes {
+            release(env, change.view);
+        }
+        release(env, delegate);
+        release(env, animation_id);
+    }
+
+    if state(env).running.is_empty() {
+        None
+    } else {
+        Some(now + Duration::from_secs_f64(1.0 / 60.0))
+    }
+}
@@ -0,0 +1,51 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Drawing helpers shared by the views that touchHLE draws itself (bars,
+//! controls, alerts and so on), since it doesn't have UIKit's artwork.
+
+use crate::frameworks::core_graphics::cg_context::{
+    CGContextFillRect, CGContextRef, CGContextSetRGBFillColor,
+};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::Environment;
+
+/// Fill a rectangle with a vertical gradient, one row at a time.
+pub fn fill_vertical_gradient(
+    env: &mut Environment,
+    context: CGContextRef,
+    rect: CGRect,
+    top: (CGFloat, CGFloat, CGFloat),
+    bottom: (CGFloat, CGFloat, CGFloat),
+) {
+    let rows = rect.size.height.ceil().max(1.0) as u32;
+    for row in 0..rows {
+        let t = if rows > 1 {
+            row as CGFloat / (rows - 1) as CGFloat
+        } else {
+            0.0
+        };
+        let lerp = |a: CGFloat, b: CGFloat| a + (b - a) * t;
+        CGContextSetRGBFillColor(
+            env,
+            context,
+            lerp(top.0, bottom.0),
+            lerp(top.1, bottom.1),
+            lerp(top.2, bottom.2),
+            1.0,
+        );
+        let row_rect = CGRect {
+            origin: CGPoint {
+                x: rect.origin.x,
+                y: rect.origin.y + row as CGFloat,
+            },
+            size: CGSize {
+                width: rect.size.width,
+                height: 1.0,
+            },
+        };
+        CGContextFillRect(env, context, row_rect);
+    }
+}
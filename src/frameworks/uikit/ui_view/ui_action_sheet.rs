@@ -0,0 +1,362 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UIActionSheet`.
+//!
+//! Action sheets are shown at the bottom of the screen the same way alerts
+//! are, see [super::ui_alert_view].
+
+use super::drawing::fill_vertical_gradient;
+use super::ui_alert_view::{
+    collect_button_titles, dismiss_modal, make_button, notify_delegate, present_modal,
+};
+use super::ui_control::UIControlStateNormal;
+use crate::frameworks::core_graphics::cg_context::{CGContextFillRect, CGContextSetRGBFillColor};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::NSInteger;
+use crate::frameworks::uikit::ui_font::{UILineBreakModeWordWrap, UITextAlignmentCenter};
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::objc::{
+    autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes,
+    release, retain, ClassExports, NSZonePtr,
+};
+use crate::Environment;
+
+type UIActionSheetStyle = NSInteger;
+const UIActionSheetStyleAutomatic: UIActionSheetStyle = -1;
+
+const PADDING: CGFloat = 20.0;
+const BUTTON_HEIGHT: CGFloat = 46.0;
+const BUTTON_SPACING: CGFloat = 8.0;
+/// Extra space above the cancel button.
+const CANCEL_SPACING: CGFloat = 8.0;
+
+pub struct UIActionSheetHostObject {
+    superclass: super::UIViewHostObject,
+    /// `NSString*`
+    title: id,
+    /// Weak reference.
+    delegate: id,
+    /// `NSString*`s
+    button_titles: Vec<id>,
+    cancel_button_index: NSInteger,
+    destructive_button_index: NSInteger,
+    first_other_button_index: NSInteger,
+    style: UIActionSheetStyle,
+    /// `UIButton*`s for each title, while the sheet is visible. Weak
+    /// references (the sheet owns them by virtue of being their superview).
+    buttons: Vec<id>,
+    visible: bool,
+}
+impl_HostObject_with_superclass!(UIActionSheetHostObject);
+impl Default for UIActionSheetHostObject {
+    fn default() -> Self {
+        UIActionSheetHostObject {
+            superclass: Default::default(),
+            title: nil,
+            delegate: nil,
+            button_titles: Vec::new(),
+            cancel_button_index: -1,
+            destructive_button_index: -1,
+            first_other_button_index: -1,
+            style: UIActionSheetStyleAutomatic,
+            buttons: Vec::new(),
+            visible: false,
+        }
+    }
+}
+
+fn title_font(env: &mut Environment) -> id {
+    msg_class![env; UIFont systemFontOfSize:(13.0 as CGFloat)]
+}
+
+/// Work out where the title goes, given the sheet's width. Returns its
+/// rectangle and the y co-ordinate where the buttons start.
+fn title_layout(env: &mut Environment, this: id, width: CGFloat) -> (CGRect, CGFloat) {
+    let title = env.objc.borrow::<UIActionSheetHostObject>(this).title;
+    if title == nil {
+        return (CGRect::default(), PADDING);
+    }
+    let font = title_font(env);
+    let text_width = width - PADDING * 2.0;
+    let max_size = CGSize {
+        width: text_width,
+        height: 1000.0,
+    };
+    let text_size: CGSize = msg![env; title sizeWithFont:font constrainedToSize:max_size];
+    let rect = CGRect {
+        origin: CGPoint {
+            x: PADDING,
+            y: PADDING / 2.0,
+        },
+        size: CGSize {
+            width: text_width,
+            height: text_size.height,
+        },
+    };
+    (rect, rect.origin.y + text_size.height + PADDING / 2.0)
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UIActionSheet: UIView
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UIActionSheetHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    () = msg![env; this setOpaque:false];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; this setBackgroundColor:bg_color];
+    this
+}
+
+- (id)initWithTitle:(id)title // NSString*
+           delegate:(id)delegate
+  cancelButtonTitle:(id)cancel_button_title // NSString*
+destructiveButtonTitle:(id)destructive_button_title // NSString*
+  otherButtonTitles:(id)first_other_button_title, // NSString*
+                    ...other_button_titles {
+    let this: id = msg![env; this initWithFrame:(<CGRect as Default>::default())];
+
+    () = msg![env; this setTitle:title];
+    () = msg![env; this setDelegate:delegate];
+    // The destructive button comes first and the cancel button last.
+    if destructive_button_title != nil {
+        let index: NSInteger = msg![env; this addButtonWithTitle:destructive_button_title];
+        () = msg![env; this setDestructiveButtonIndex:index];
+    }
+    let other_titles = collect_button_titles(env, first_other_button_title, other_button_titles);
+    for (i, title) in other_titles.into_iter().enumerate() {
+        let index: NSInteger = msg![env; this addButtonWithTitle:title];
+        if i == 0 {
+            env.objc.borrow_mut::<UIActionSheetHostObject>(this).first_other_button_index = index;
+        }
+    }
+    if cancel_button_title != nil {
+        let index: NSInteger = msg![env; this addButtonWithTitle:cancel_button_title];
+        () = msg![env; this setCancelButtonIndex:index];
+    }
+
+    this
+}
+
+- (())dealloc {
+    let host_obj = env.objc.borrow_mut::<UIActionSheetHostObject>(this);
+    let title = host_obj.title;
+    let button_titles = std::mem::take(&mut host_obj.button_titles);
+    release(env, title);
+    for button_title in button_titles {
+        release(env, button_title);
+    }
+    msg_super![env; this dealloc]
+}
+
+- (id)title {
+    env.objc.borrow::<UIActionSheetHostObject>(this).title
+}
+- (())setTitle:(id)title { // NSString*
+    let title: id = msg![env; title copy];
+    let host_obj = env.objc.borrow_mut::<UIActionSheetHostObject>(this);
+    let old_title = std::mem::replace(&mut host_obj.title, title);
+    release(env, old_title);
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (id)delegate {
+    env.objc.borrow::<UIActionSheetHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate { // id<UIActionSheetDelegate>
+    // The delegate is a *weak* reference!
+    env.objc.borrow_mut::<UIActionSheetHostObject>(this).delegate = delegate;
+}
+
+- (UIActionSheetStyle)actionSheetStyle {
+    env.objc.borrow::<UIActionSheetHostObject>(this).style
+}
+- (())setActionSheetStyle:(UIActionSheetStyle)style {
+    // TODO: draw the black styles differently
+    env.objc.borrow_mut::<UIActionSheetHostObject>(this).style = style;
+}
+
+- (NSInteger)addButtonWithTitle:(id)title { // NSString*
+    let title: id = msg![env; title copy];
+    let button_titles = &mut env.objc.borrow_mut::<UIActionSheetHostObject>(this).button_titles;
+    button_titles.push(title);
+    (button_titles.len() - 1) as NSInteger
+}
+- (NSInteger)numberOfButtons {
+    env.objc.borrow::<UIActionSheetHostObject>(this).button_titles.len() as NSInteger
+}
+- (id)buttonTitleAtIndex:(NSInteger)index {
+    let button_titles = &env.objc.borrow::<UIActionSheetHostObject>(this).button_titles;
+    usize::try_from(index).ok().and_then(|i| button_titles.get(i)).copied().unwrap_or(nil)
+}
+
+- (NSInteger)cancelButtonIndex {
+    env.objc.borrow::<UIActionSheetHostObject>(this).cancel_button_index
+}
+- (())setCancelButtonIndex:(NSInteger)index {
+    env.objc.borrow_mut::<UIActionSheetHostObject>(this).cancel_button_index = index;
+}
+- (NSInteger)destructiveButtonIndex {
+    env.objc.borrow::<UIActionSheetHostObject>(this).destructive_button_index
+}
+- (())setDestructiveButtonIndex:(NSInteger)index {
+    env.objc.borrow_mut::<UIActionSheetHostObject>(this).destructive_button_index = index;
+}
+- (NSInteger)firstOtherButtonIndex {
+    env.objc.borrow::<UIActionSheetHostObject>(this).first_other_button_index
+}
+
+- (bool)isVisible {
+    env.objc.borrow::<UIActionSheetHostObject>(this).visible
+}
+
+// The sheet is always shown at the bottom of the screen, so these are all the
+// same.
+- (())showInView:(id)_view { // UIView*
+    () = msg![env; this _touchHLE_show];
+}
+- (())showFromTabBar:(id)_tab_bar { // UITabBar*
+    () = msg![env; this _touchHLE_show];
+}
+- (())showFromToolbar:(id)_toolbar { // UIToolbar*
+    () = msg![env; this _touchHLE_show];
+}
+
+- (())_touchHLE_show {
+    if env.objc.borrow::<UIActionSheetHostObject>(this).visible {
+        return;
+    }
+
+    let delegate = env.objc.borrow::<UIActionSheetHostObject>(this).delegate;
+    notify_delegate(env, delegate, "willPresentActionSheet:", this, None);
+
+    let host_obj = env.objc.borrow_mut::<UIActionSheetHostObject>(this);
+    let old_buttons = std::mem::take(&mut host_obj.buttons);
+    let button_titles = host_obj.button_titles.clone();
+    let cancel_button_index = host_obj.cancel_button_index;
+    let destructive_button_index = host_obj.destructive_button_index;
+    for button in old_buttons {
+        () = msg![env; button removeFromSuperview];
+    }
+
+    // The sheet is added first so the width is known.
+    let area = present_modal(env, this);
+    let (_, buttons_top) = title_layout(env, this, area.width);
+
+    let mut y = buttons_top;
+    let mut buttons = Vec::with_capacity(button_titles.len());
+    for (i, &title) in button_titles.iter().enumerate() {
+        let i = i as NSInteger;
+        if i == cancel_button_index && i != 0 {
+            y += CANCEL_SPACING;
+        }
+        let frame = CGRect {
+            origin: CGPoint { x: PADDING, y },
+            size: CGSize {
+                width: area.width - PADDING * 2.0,
+                height: BUTTON_HEIGHT,
+            },
+        };
+        y += BUTTON_HEIGHT + BUTTON_SPACING;
+        let color = if i == destructive_button_index {
+            (0.85, 0.15, 0.15)
+        } else if i == cancel_button_index {
+            (0.2, 0.2, 0.2)
+        } else {
+            (0.92, 0.92, 0.92)
+        };
+        let button = make_button(env, this, title, frame, color);
+        if i != destructive_button_index && i != cancel_button_index {
+            let text_color: id = msg_class![env; UIColor blackColor];
+            () = msg![env; button setTitleColor:text_color forState:UIControlStateNormal];
+        }
+        buttons.push(button);
+    }
+    env.objc.borrow_mut::<UIActionSheetHostObject>(this).buttons = buttons;
+
+    let height = y - BUTTON_SPACING + PADDING;
+    let frame = CGRect {
+        origin: CGPoint {
+            x: 0.0,
+            y: area.height - height,
+        },
+        size: CGSize {
+            width: area.width,
+            height,
+        },
+    };
+    () = msg![env; this setFrame:frame];
+    () = msg![env; this setNeedsDisplay];
+    env.objc.borrow_mut::<UIActionSheetHostObject>(this).visible = true;
+
+    notify_delegate(env, delegate, "didPresentActionSheet:", this, None);
+}
+
+- (())dismissWithClickedButtonIndex:(NSInteger)index
+                           animated:(bool)_animated {
+    // TODO: animation
+    if !env.objc.borrow::<UIActionSheetHostObject>(this).visible {
+        return;
+    }
+    let delegate = env.objc.borrow::<UIActionSheetHostObject>(this).delegate;
+    notify_delegate(env, delegate, "actionSheet:willDismissWithButtonIndex:", this, Some(index));
+    dismiss_modal(env, this);
+    env.objc.borrow_mut::<UIActionSheetHostObject>(this).visible = false;
+    notify_delegate(env, delegate, "actionSheet:didDismissWithButtonIndex:", this, Some(index));
+}
+
+- (())drawRect:(CGRect)_rect {
+    let bounds: CGRect = msg![env; this bounds];
+    let context = UIGraphicsGetCurrentContext(env);
+
+    CGContextSetRGBFillColor(env, context, 0.55, 0.6, 0.65, 1.0);
+    CGContextFillRect(env, context, bounds);
+    let mut inner = bounds;
+    inner.origin.y += 1.0;
+    inner.size.height -= 1.0;
+    fill_vertical_gradient(env, context, inner, (0.35, 0.4, 0.45), (0.15, 0.18, 0.22));
+
+    let title = env.objc.borrow::<UIActionSheetHostObject>(this).title;
+    if title != nil {
+        let (title_rect, _) = title_layout(env, this, bounds.size.width);
+        let font = title_font(env);
+        CGContextSetRGBFillColor(env, context, 0.9, 0.9, 0.9, 1.0);
+        let _: CGSize = msg![env; title drawInRect:title_rect
+                                          withFont:font
+                                     lineBreakMode:UILineBreakModeWordWrap
+                                         alignment:UITextAlignmentCenter];
+    }
+}
+
+// Internal method, target of the buttons.
+- (())_touchHLE_buttonTapped:(id)button { // UIButton*
+    let host_obj = env.objc.borrow::<UIActionSheetHostObject>(this);
+    let Some(index) = host_obj.buttons.iter().position(|&b| b == button) else {
+        return;
+    };
+    let index = index as NSInteger;
+    let delegate = host_obj.delegate;
+    // The delegate may release the sheet, and the button is still in use by
+    // the caller.
+    retain(env, this);
+    autorelease(env, this);
+    retain(env, button);
+    autorelease(env, button);
+
+    notify_delegate(env, delegate, "actionSheet:clickedButtonAtIndex:", this, Some(index));
+    () = msg![env; this dismissWithClickedButtonIndex:index animated:true];
+}
+
+@end
+
+};
@@ -4,32 +4,516 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `UIAlertView`.
+//!
+//! Alerts (and action sheets, see [super::ui_action_sheet]) are shown on top
+//! of the app inside a dimming view that swallows touches, which is added to
+//! the top window rather than to a separate window, because only the top
+//! window is composited. The dimming view is rotated to match the status bar
+//! orientation.
 
-use crate::frameworks::foundation::ns_string;
-use crate::objc::{id, msg_super, objc_classes, ClassExports};
+use super::drawing::fill_vertical_gradient;
+use super::ui_control::ui_button::UIButtonTypeCustom;
+use super::ui_control::{UIControlEventTouchUpInside, UIControlStateNormal};
+use crate::abi::DotDotDot;
+use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
+use crate::frameworks::core_graphics::cg_context::{CGContextFillRect, CGContextSetRGBFillColor};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::NSInteger;
+use crate::frameworks::uikit::ui_device::{
+    UIDeviceOrientation, UIDeviceOrientationLandscapeLeft, UIDeviceOrientationLandscapeRight,
+};
+use crate::frameworks::uikit::ui_font::{UILineBreakModeWordWrap, UITextAlignmentCenter};
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::objc::{
+    autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_send, msg_super, nil,
+    objc_classes, release, retain, ClassExports, NSZonePtr,
+};
+use crate::Environment;
+use std::f32::consts::FRAC_PI_2;
+
+const ALERT_WIDTH: CGFloat = 284.0;
+const PADDING: CGFloat = 12.0;
+const BUTTON_HEIGHT: CGFloat = 43.0;
+const BUTTON_SPACING: CGFloat = 8.0;
+
+/// Add a view to the top window, inside a new dimming view, and return the
+/// size of the area it can occupy (the screen size, accounting for rotation).
+/// The view should position itself within that area.
+pub(super) fn present_modal(env: &mut Environment, view: id) -> CGSize {
+    let Some(&window) = env
+        .framework_state
+        .uikit
+        .ui_view
+        .ui_window
+        .visible_windows
+        .last()
+    else {
+        log!("Warning: no visible window to show {:?} in", view);
+        return CGSize::default();
+    };
+
+    let screen: id = msg_class![env; UIScreen mainScreen];
+    let screen_bounds: CGRect = msg![env; screen bounds];
+    let app: id = msg_class![env; UIApplication sharedApplication];
+    let orientation: UIDeviceOrientation = msg![env; app statusBarOrientation];
+    let CGSize { width, height } = screen_bounds.size;
+    let (size, angle) = match orientation {
+        UIDeviceOrientationLandscapeLeft => (
+            CGSize {
+                width: height,
+                height: width,
+            },
+            FRAC_PI_2,
+        ),
+        UIDeviceOrientationLandscapeRight => (
+            CGSize {
+                width: height,
+                height: width,
+            },
+            -FRAC_PI_2,
+        ),
+        _ => (screen_bounds.size, 0.0),
+    };
+
+    let dimming_view: id = msg_class![env; UIView alloc];
+    let dimming_view: id = msg![env; dimming_view init];
+    () = msg![env; dimming_view setBounds:(CGRect {
+        origin: CGPoint::default(),
+        size,
+    })];
+    () = msg![env; dimming_view setCenter:(CGPoint {
+        x: screen_bounds.origin.x + width / 2.0,
+        y: screen_bounds.origin.y + height / 2.0,
+    })];
+    () = msg![env; dimming_view setTransform:(CGAffineTransform::make_rotation(angle))];
+    () = msg![env; dimming_view setOpaque:false];
+    let color: id = msg_class![env; UIColor colorWithWhite:(0.0 as CGFloat)
+                                                     alpha:(0.4 as CGFloat)];
+    () = msg![env; dimming_view setBackgroundColor:color];
+
+    () = msg![env; dimming_view addSubview:view];
+    () = msg![env; window addSubview:dimming_view];
+    release(env, dimming_view);
+
+    size
+}
+
+/// Remove a view shown with [present_modal].
+pub(super) fn dismiss_modal(env: &mut Environment, view: id) {
+    let dimming_view: id = msg![env; view superview];
+    // The caller may still be using the view.
+    retain(env, view);
+    autorelease(env, view);
+    () = msg![env; view removeFromSuperview];
+    () = msg![env; dimming_view removeFromSuperview];
+}
+
+/// Collect the titles passed to a method with a `otherButtonTitles:` variadic
+/// parameter (which is [nil]-terminated).
+pub(super) fn collect_button_titles(env: &mut Environment, first: id, rest: DotDotDot) -> Vec<id> {
+    let mut titles = Vec::new();
+    if first == nil {
+        return titles;
+    }
+    titles.push(first);
+    let mut varargs = rest.start();
+    loop {
+        let title: id = varargs.next(env);
+        if title == nil {
+            break;
+        }
+        titles.push(title);
+    }
+    titles
+}
+
+/// Send a message like `alertView:clickedButtonAtIndex:` or
+/// `willPresentAlertView:` to a delegate, if it implements it.
+pub(super) fn notify_delegate(
+    env: &mut Environment,
+    delegate: id,
+    selector: &str,
+    sender: id,
+    button_index: Option<NSInteger>,
+) {
+    if delegate == nil
+        || !env
+            .objc
+            .object_has_method_named(&env.mem, delegate, selector)
+    {
+        return;
+    }
+    let sel = env.objc.lookup_selector(selector).unwrap();
+    match button_index {
+        Some(button_index) => msg_send(env, (delegate, sel, sender, button_index)),
+        None => msg_send(env, (delegate, sel, sender)),
+    }
+}
+
+/// Create a button for an alert or action sheet.
+pub(super) fn make_button(
+    env: &mut Environment,
+    owner: id,
+    title: id,
+    frame: CGRect,
+    color: (CGFloat, CGFloat, CGFloat),
+) -> id {
+    let button: id = msg_class![env; UIButton buttonWithType:UIButtonTypeCustom];
+    () = msg![env; button setTitle:title forState:UIControlStateNormal];
+    let (r, g, b) = color;
+    let color: id = msg_class![env; UIColor colorWithRed:r green:g blue:b alpha:(1.0 as CGFloat)];
+    () = msg![env; button setBackgroundColor:color];
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:(16.0 as CGFloat)];
+    let label: id = msg![env; button titleLabel];
+    () = msg![env; label setFont:font];
+    () = msg![env; button setFrame:frame];
+    () = msg![env; button layoutSubviews];
+    let selector = env.objc.lookup_selector("_touchHLE_buttonTapped:").unwrap();
+    () = msg![env; button addTarget:owner
+                             action:selector
+                   forControlEvents:UIControlEventTouchUpInside];
+    () = msg![env; owner addSubview:button];
+    button
+}
+
+pub struct UIAlertViewHostObject {
+    superclass: super::UIViewHostObject,
+    /// `NSString*`
+    title: id,
+    /// `NSString*`
+    message: id,
+    /// Weak reference.
+    delegate: id,
+    /// `NSString*`s
+    button_titles: Vec<id>,
+    cancel_button_index: NSInteger,
+    first_other_button_index: NSInteger,
+    /// `UIButton*`s for each title, while the alert is visible. Weak
+    /// references (the alert owns them by virtue of being their superview).
+    buttons: Vec<id>,
+    visible: bool,
+}
+impl_HostObject_with_superclass!(UIAlertViewHostObject);
+impl Default for UIAlertViewHostObject {
+    fn default() -> Self {
+        UIAlertViewHostObject {
+            superclass: Default::default(),
+            title: nil,
+            message: nil,
+            delegate: nil,
+            button_titles: Vec::new(),
+            cancel_button_index: -1,
+            first_other_button_index: -1,
+            buttons: Vec::new(),
+            visible: false,
+        }
+    }
+}
+
+/// Work out where the title and message go. Returns their rectangles and the
+/// y co-ordinate where the buttons start.
+fn text_layout(env: &mut Environment, this: id) -> (CGRect, CGRect, CGFloat) {
+    let &UIAlertViewHostObject { title, message, .. } = env.objc.borrow(this);
+    let text_width = ALERT_WIDTH - PADDING * 2.0;
+    let constraint = CGSize {
+        width: text_width,
+        height: 1000.0,
+    };
+
+    let mut y = PADDING;
+    let mut rects = [CGRect::default(); 2];
+    let texts: [(id, bool, CGFloat); 2] = [(title, true, 18.0), (message, false, 16.0)];
+    for (i, (text, bold, size)) in texts.into_iter().enumerate() {
+        if text == nil {
+            continue;
+        }
+        let font: id = if bold {
+            msg_class![env; UIFont boldSystemFontOfSize:size]
+        } else {
+            msg_class![env; UIFont systemFontOfSize:size]
+        };
+        let text_size: CGSize = msg![env; text sizeWithFont:font constrainedToSize:constraint];
+        rects[i] = CGRect {
+            origin: CGPoint { x: PADDING, y },
+            size: CGSize {
+                width: text_width,
+                height: text_size.height,
+            },
+        };
+        y += text_size.height + BUTTON_SPACING;
+    }
+    (rects[0], rects[1], y + PADDING - BUTTON_SPACING)
+}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
 
 @implementation UIAlertView: UIView
-- (id)initWithTitle:(id)title
-                      message:(id)message
-                     delegate:(id)delegate
-            cancelButtonTitle:(id)cancelButtonTitle
-            otherButtonTitles:(id)otherButtonTitles {
 
-    log!("TODO: [(UIAlertView*){:?} initWithTitle:{:?} message:{:?} delegate:{:?} cancelButtonTitle:{:?} otherButtonTitles:{:?}]", this, title, message, delegate, cancelButtonTitle, otherButtonTitles);
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UIAlertViewHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    () = msg![env; this setOpaque:false];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; this setBackgroundColor:bg_color];
+    this
+}
+
+- (id)initWithTitle:(id)title // NSString*
+            message:(id)message // NSString*
+           delegate:(id)delegate
+  cancelButtonTitle:(id)cancel_button_title // NSString*
+  otherButtonTitles:(id)first_other_button_title, // NSString*
+                    ...other_button_titles {
+    let this: id = msg![env; this initWithFrame:(<CGRect as Default>::default())];
+
+    () = msg![env; this setTitle:title];
+    () = msg![env; this setMessage:message];
+    () = msg![env; this setDelegate:delegate];
+    if cancel_button_title != nil {
+        let index: NSInteger = msg![env; this addButtonWithTitle:cancel_button_title];
+        () = msg![env; this setCancelButtonIndex:index];
+    }
+    let other_titles = collect_button_titles(env, first_other_button_title, other_button_titles);
+    for (i, title) in other_titles.into_iter().enumerate() {
+        let index: NSInteger = msg![env; this addButtonWithTitle:title];
+        if i == 0 {
+            env.objc.borrow_mut::<UIAlertViewHostObject>(this).first_other_button_index = index;
+        }
+    }
+
+    this
+}
+
+- (())dealloc {
+    let host_obj = env.objc.borrow_mut::<UIAlertViewHostObject>(this);
+    let title = host_obj.title;
+    let message = host_obj.message;
+    let button_titles = std::mem::take(&mut host_obj.button_titles);
+    release(env, title);
+    release(env, message);
+    for button_title in button_titles {
+        release(env, button_title);
+    }
+    msg_super![env; this dealloc]
+}
+
+- (id)title {
+    env.objc.borrow::<UIAlertViewHostObject>(this).title
+}
+- (())setTitle:(id)title { // NSString*
+    let title: id = msg![env; title copy];
+    let host_obj = env.objc.borrow_mut::<UIAlertViewHostObject>(this);
+    let old_title = std::mem::replace(&mut host_obj.title, title);
+    release(env, old_title);
+    () = msg![env; this setNeedsDisplay];
+}
 
-    let msg = ns_string::to_rust_string(env, message);
-    let title = ns_string::to_rust_string(env, title);
-    log!("UIAlertView: title: {:?}, message: {:?}", title, msg);
+- (id)message {
+    env.objc.borrow::<UIAlertViewHostObject>(this).message
+}
+- (())setMessage:(id)message { // NSString*
+    let message: id = msg![env; message copy];
+    let host_obj = env.objc.borrow_mut::<UIAlertViewHostObject>(this);
+    let old_message = std::mem::replace(&mut host_obj.message, message);
+    release(env, old_message);
+    () = msg![env; this setNeedsDisplay];
+}
 
-    msg_super![env; this init]
+- (id)delegate {
+    env.objc.borrow::<UIAlertViewHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate { // id<UIAlertViewDelegate>
+    // The delegate is a *weak* reference!
+    env.objc.borrow_mut::<UIAlertViewHostObject>(this).delegate = delegate;
+}
+
+- (NSInteger)addButtonWithTitle:(id)title { // NSString*
+    let title: id = msg![env; title copy];
+    let button_titles = &mut env.objc.borrow_mut::<UIAlertViewHostObject>(this).button_titles;
+    button_titles.push(title);
+    (button_titles.len() - 1) as NSInteger
+}
+- (NSInteger)numberOfButtons {
+    env.objc.borrow::<UIAlertViewHostObject>(this).button_titles.len() as NSInteger
+}
+- (id)buttonTitleAtIndex:(NSInteger)index {
+    let button_titles = &env.objc.borrow::<UIAlertViewHostObject>(this).button_titles;
+    usize::try_from(index).ok().and_then(|i| button_titles.get(i)).copied().unwrap_or(nil)
 }
+
+- (NSInteger)cancelButtonIndex {
+    env.objc.borrow::<UIAlertViewHostObject>(this).cancel_button_index
+}
+- (())setCancelButtonIndex:(NSInteger)index {
+    env.objc.borrow_mut::<UIAlertViewHostObject>(this).cancel_button_index = index;
+}
+- (NSInteger)firstOtherButtonIndex {
+    env.objc.borrow::<UIAlertViewHostObject>(this).first_other_button_index
+}
+
+- (bool)isVisible {
+    env.objc.borrow::<UIAlertViewHostObject>(this).visible
+}
+
 - (())show {
-    log!("TODO: [(UIAlertView*){:?} show]", this);
+    if env.objc.borrow::<UIAlertViewHostObject>(this).visible {
+        return;
+    }
+
+    let delegate = env.objc.borrow::<UIAlertViewHostObject>(this).delegate;
+    notify_delegate(env, delegate, "willPresentAlertView:", this, None);
+
+    let (_, _, buttons_top) = text_layout(env, this);
+
+    let host_obj = env.objc.borrow_mut::<UIAlertViewHostObject>(this);
+    let old_buttons = std::mem::take(&mut host_obj.buttons);
+    let button_titles = host_obj.button_titles.clone();
+    let cancel_button_index = host_obj.cancel_button_index;
+    for button in old_buttons {
+        () = msg![env; button removeFromSuperview];
+    }
+
+    // Two buttons go side-by-side, with the cancel button on the left.
+    // Otherwise they are stacked, with the cancel button at the bottom.
+    let side_by_side = button_titles.len() == 2;
+    let mut order: Vec<usize> = (0..button_titles.len()).collect();
+    if !side_by_side {
+        if let Some(pos) = order.iter().position(|&i| i as NSInteger == cancel_button_index) {
+            let cancel = order.remove(pos);
+            order.push(cancel);
+        }
+    }
+    let full_width = ALERT_WIDTH - PADDING * 2.0;
+    let mut frames = vec![CGRect::default(); button_titles.len()];
+    let mut bottom = buttons_top;
+    for (slot, &i) in order.iter().enumerate() {
+        let frame = if side_by_side {
+            let width = (full_width - BUTTON_SPACING) / 2.0;
+            CGRect {
+                origin: CGPoint {
+                    x: PADDING + slot as CGFloat * (width + BUTTON_SPACING),
+                    y: buttons_top,
+                },
+                size: CGSize { width, height: BUTTON_HEIGHT },
+            }
+        } else {
+            CGRect {
+                origin: CGPoint {
+                    x: PADDING,
+                    y: buttons_top + slot as CGFloat * (BUTTON_HEIGHT + BUTTON_SPACING),
+                },
+                size: CGSize { width: full_width, height: BUTTON_HEIGHT },
+            }
+        };
+        bottom = frame.origin.y + BUTTON_HEIGHT + PADDING;
+        frames[i] = frame;
+    }
+
+    let mut buttons = Vec::with_capacity(button_titles.len());
+    for (i, (&title, frame)) in button_titles.iter().zip(frames).enumerate() {
+        let color = if i as NSInteger == cancel_button_index {
+            (0.2, 0.27, 0.45)
+        } else {
+            (0.4, 0.5, 0.7)
+        };
+        buttons.push(make_button(env, this, title, frame, color));
+    }
+    env.objc.borrow_mut::<UIAlertViewHostObject>(this).buttons = buttons;
+
+    let area = present_modal(env, this);
+    let frame = CGRect {
+        origin: CGPoint {
+            x: ((area.width - ALERT_WIDTH) / 2.0).round(),
+            y: ((area.height - bottom) / 2.0).round(),
+        },
+        size: CGSize {
+            width: ALERT_WIDTH,
+            height: bottom,
+        },
+    };
+    () = msg![env; this setFrame:frame];
+    () = msg![env; this setNeedsDisplay];
+    env.objc.borrow_mut::<UIAlertViewHostObject>(this).visible = true;
+
+    notify_delegate(env, delegate, "didPresentAlertView:", this, None);
+}
+
+- (())dismissWithClickedButtonIndex:(NSInteger)index
+                           animated:(bool)_animated {
+    // TODO: animation
+    if !env.objc.borrow::<UIAlertViewHostObject>(this).visible {
+        return;
+    }
+    let delegate = env.objc.borrow::<UIAlertViewHostObject>(this).delegate;
+    notify_delegate(env, delegate, "alertView:willDismissWithButtonIndex:", this, Some(index));
+    dismiss_modal(env, this);
+    env.objc.borrow_mut::<UIAlertViewHostObject>(this).visible = false;
+    notify_delegate(env, delegate, "alertView:didDismissWithButtonIndex:", this, Some(index));
 }
+
+- (())drawRect:(CGRect)_rect {
+    let bounds: CGRect = msg![env; this bounds];
+    let context = UIGraphicsGetCurrentContext(env);
+
+    // A light border around a dark blue box.
+    CGContextSetRGBFillColor(env, context, 0.9, 0.9, 0.95, 1.0);
+    CGContextFillRect(env, context, bounds);
+    let inner = CGRect {
+        origin: CGPoint {
+            x: bounds.origin.x + 2.0,
+            y: bounds.origin.y + 2.0,
+        },
+        size: CGSize {
+            width: bounds.size.width - 4.0,
+            height: bounds.size.height - 4.0,
+        },
+    };
+    fill_vertical_gradient(env, context, inner, (0.25, 0.32, 0.5), (0.08, 0.12, 0.28));
+
+    let (title_rect, message_rect, _) = text_layout(env, this);
+    let &UIAlertViewHostObject { title, message, .. } = env.objc.borrow(this);
+    CGContextSetRGBFillColor(env, context, 1.0, 1.0, 1.0, 1.0);
+    if title != nil {
+        let font: id = msg_class![env; UIFont boldSystemFontOfSize:(18.0 as CGFloat)];
+        let _: CGSize = msg![env; title drawInRect:title_rect
+                                          withFont:font
+                                     lineBreakMode:UILineBreakModeWordWrap
+                                         alignment:UITextAlignmentCenter];
+    }
+    if message != nil {
+        let font: id = msg_class![env; UIFont systemFontOfSize:(16.0 as CGFloat)];
+        let _: CGSize = msg![env; message drawInRect:message_rect
+                                            withFont:font
+                                       lineBreakMode:UILineBreakModeWordWrap
+                                           alignment:UITextAlignmentCenter];
+    }
+}
+
+// Internal method, target of the buttons.
+- (())_touchHLE_buttonTapped:(id)button { // UIButton*
+    let host_obj = env.objc.borrow::<UIAlertViewHostObject>(this);
+    let Some(index) = host_obj.buttons.iter().position(|&b| b == button) else {
+        return;
+    };
+    let index = index as NSInteger;
+    let delegate = host_obj.delegate;
+    // The delegate may release the alert, and the button is still in use by
+    // the caller.
+    retain(env, this);
+    autorelease(env, this);
+    retain(env, button);
+    autorelease(env, button);
+
+    notify_delegate(env, delegate, "alertView:clickedButtonAtIndex:", this, Some(index));
+    () = msg![env; this dismissWithClickedButtonIndex:index animated:true];
+}
+
 @end
 
 };
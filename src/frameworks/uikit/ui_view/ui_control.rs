@@ -9,9 +9,14 @@
 //! - The [Target-Action section](https://developer.apple.com/library/archive/documentation/General/Conceptual/CocoaEncyclopedia/Target-Action/Target-Action.html) of Apple's "Concepts in Objective-C Programming".
 
 pub mod ui_button;
+pub mod ui_page_control;
+pub mod ui_segmented_control;
+pub mod ui_slider;
+pub mod ui_switch;
 pub mod ui_text_field;
 
-use crate::frameworks::core_graphics::CGPoint;
+use crate::frameworks::core_graphics::cg_context::{CGContextFillRect, CGContextRef};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
 use crate::frameworks::foundation::NSUInteger;
 use crate::objc::{
     id, impl_HostObject_with_superclass, msg, msg_send, msg_super, nil, objc_classes, release,
@@ -28,6 +33,11 @@ const UIControlEventTouchDragEnter: UIControlEvents = 1 << 4;
 const UIControlEventTouchDragExit: UIControlEvents = 1 << 5;
 pub const UIControlEventTouchUpInside: UIControlEvents = 1 << 6;
 const UIControlEventTouchUpOutside: UIControlEvents = 1 << 7;
+const UIControlEventValueChanged: UIControlEvents = 1 << 12;
+const UIControlEventEditingDidBegin: UIControlEvents = 1 << 16;
+const UIControlEventEditingChanged: UIControlEvents = 1 << 17;
+const UIControlEventEditingDidEnd: UIControlEvents = 1 << 18;
+const UIControlEventEditingDidEndOnExit: UIControlEvents = 1 << 19;
 
 struct UIControlHostObject {
     superclass: super::UIViewHostObject,
@@ -86,6 +96,27 @@ fn send_actions(env: &mut Environment, this: id, event: id, control_event: UICon
     }
 }
 
+/// Fill a circle with the current fill color. There's no path support yet, so
+/// this approximates it with one rectangle per row of pixels.
+fn fill_circle(env: &mut Environment, context: CGContextRef, center: CGPoint, radius: CGFloat) {
+    let rows = (radius * 2.0).ceil() as u32;
+    for row in 0..rows {
+        let y = row as CGFloat + 0.5 - radius;
+        let half_width = (radius * radius - y * y).max(0.0).sqrt();
+        let row_rect = CGRect {
+            origin: CGPoint {
+                x: center.x - half_width,
+                y: center.y - radius + row as CGFloat,
+            },
+            size: CGSize {
+                width: half_width * 2.0,
+                height: 1.0,
+            },
+        };
+        CGContextFillRect(env, context, row_rect);
+    }
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -327,6 +358,10 @@ forControlEvents:(UIControlEvents)events {
     };
 }
 
+- (())sendActionsForControlEvents:(UIControlEvents)events {
+    send_actions(env, this, nil, events);
+}
+
 // TODO: more triggers/targets/actions stuff
 
 @end
@@ -0,0 +1,223 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UIPageControl`.
+//!
+//! The control is drawn in the iPhone OS 2/3 style: a row of dots, with the
+//! current page's dot white and the rest translucent.
+
+use super::{fill_circle, send_actions, UIControlEventValueChanged, UIControlHostObject};
+use crate::frameworks::core_graphics::cg_context::CGContextSetRGBFillColor;
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::NSInteger;
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_class, msg_super, objc_classes, ClassExports,
+    NSZonePtr,
+};
+use crate::Environment;
+
+const DOT_DIAMETER: CGFloat = 6.0;
+const DOT_SPACING: CGFloat = 10.0;
+const CONTROL_HEIGHT: CGFloat = 36.0;
+
+pub struct UIPageControlHostObject {
+    superclass: UIControlHostObject,
+    number_of_pages: NSInteger,
+    current_page: NSInteger,
+    /// The page shown by the dots, which can lag behind `current_page` if
+    /// `defers_current_page_display` is set.
+    displayed_page: NSInteger,
+    hides_for_single_page: bool,
+    defers_current_page_display: bool,
+}
+impl_HostObject_with_superclass!(UIPageControlHostObject);
+impl Default for UIPageControlHostObject {
+    fn default() -> Self {
+        UIPageControlHostObject {
+            superclass: Default::default(),
+            number_of_pages: 0,
+            current_page: 0,
+            displayed_page: 0,
+            hides_for_single_page: false,
+            defers_current_page_display: false,
+        }
+    }
+}
+
+fn size_for_number_of_pages(page_count: NSInteger) -> CGSize {
+    let page_count = page_count.max(0) as CGFloat;
+    CGSize {
+        width: (page_count * (DOT_DIAMETER + DOT_SPACING) - DOT_SPACING).max(0.0),
+        height: CONTROL_HEIGHT,
+    }
+}
+
+/// Set the current page, keeping it within range.
+fn set_current_page(env: &mut Environment, this: id, page: NSInteger) {
+    let host_obj = env.objc.borrow_mut::<UIPageControlHostObject>(this);
+    let page = page.min(host_obj.number_of_pages - 1).max(0);
+    host_obj.current_page = page;
+    host_obj.displayed_page = page;
+    () = msg![env; this setNeedsDisplay];
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UIPageControl: UIControl
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UIPageControlHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    () = msg![env; this setOpaque:false];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; this setBackgroundColor:bg_color];
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UINumberOfPages");
+    let number_of_pages: NSInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+    let key_ns_string = get_static_str(env, "UICurrentPage");
+    let current_page: NSInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+    let key_ns_string = get_static_str(env, "UIHidesForSinglePage");
+    let hides_for_single_page: bool = msg![env; coder decodeBoolForKey:key_ns_string];
+    let key_ns_string = get_static_str(env, "UIDefersCurrentPageDisplay");
+    let defers_current_page_display: bool = msg![env; coder decodeBoolForKey:key_ns_string];
+
+    let host_obj = env.objc.borrow_mut::<UIPageControlHostObject>(this);
+    host_obj.number_of_pages = number_of_pages.max(0);
+    host_obj.hides_for_single_page = hides_for_single_page;
+    host_obj.defers_current_page_display = defers_current_page_display;
+    set_current_page(env, this, current_page);
+
+    this
+}
+
+- (NSInteger)numberOfPages {
+    env.objc.borrow::<UIPageControlHostObject>(this).number_of_pages
+}
+- (())setNumberOfPages:(NSInteger)number_of_pages {
+    let host_obj = env.objc.borrow_mut::<UIPageControlHostObject>(this);
+    host_obj.number_of_pages = number_of_pages.max(0);
+    let current_page = host_obj.current_page;
+    set_current_page(env, this, current_page);
+}
+
+- (NSInteger)currentPage {
+    env.objc.borrow::<UIPageControlHostObject>(this).current_page
+}
+- (())setCurrentPage:(NSInteger)page {
+    set_current_page(env, this, page);
+}
+
+- (bool)hidesForSinglePage {
+    env.objc.borrow::<UIPageControlHostObject>(this).hides_for_single_page
+}
+- (())setHidesForSinglePage:(bool)hides {
+    env.objc.borrow_mut::<UIPageControlHostObject>(this).hides_for_single_page = hides;
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (bool)defersCurrentPageDisplay {
+    env.objc.borrow::<UIPageControlHostObject>(this).defers_current_page_display
+}
+- (())setDefersCurrentPageDisplay:(bool)defers {
+    env.objc.borrow_mut::<UIPageControlHostObject>(this).defers_current_page_display = defers;
+}
+
+- (())updateCurrentPageDisplay {
+    let host_obj = env.objc.borrow_mut::<UIPageControlHostObject>(this);
+    host_obj.displayed_page = host_obj.current_page;
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (CGSize)sizeForNumberOfPages:(NSInteger)page_count {
+    size_for_number_of_pages(page_count)
+}
+
+- (())drawRect:(CGRect)_rect {
+    let &UIPageControlHostObject {
+        number_of_pages,
+        displayed_page,
+        hides_for_single_page,
+        ..
+    } = env.objc.borrow(this);
+    if number_of_pages == 0 || (hides_for_single_page && number_of_pages == 1) {
+        return;
+    }
+
+    let bounds: CGRect = msg![env; this bounds];
+    let context = UIGraphicsGetCurrentContext(env);
+    let size = size_for_number_of_pages(number_of_pages);
+    let left = bounds.origin.x + ((bounds.size.width - size.width) / 2.0).round();
+    let y = bounds.origin.y + bounds.size.height / 2.0;
+    for page in 0..number_of_pages {
+        let alpha = if page == displayed_page { 1.0 } else { 0.35 };
+        CGContextSetRGBFillColor(env, context, 1.0, 1.0, 1.0, alpha);
+        let center = CGPoint {
+            x: left + page as CGFloat * (DOT_DIAMETER + DOT_SPACING) + DOT_DIAMETER / 2.0,
+            y,
+        };
+        fill_circle(env, context, center, DOT_DIAMETER / 2.0);
+    }
+}
+
+- (bool)beginTrackingWithTouch:(id)_touch // UITouch*
+                     withEvent:(id)_event { // UIEvent*
+    true
+}
+- (())endTrackingWithTouch:(id)touch // UITouch*
+                  withEvent:(id)event { // UIEvent*
+    () = msg_super![env; this endTrackingWithTouch:touch withEvent:event];
+
+    let location: CGPoint = msg![env; touch locationInView:this];
+    if !msg![env; this pointInside:location withEvent:event] {
+        return;
+    }
+
+    // Tapping either side of the current page's dot moves by one page.
+    let bounds: CGRect = msg![env; this bounds];
+    let &UIPageControlHostObject {
+        number_of_pages,
+        current_page,
+        displayed_page,
+        defers_current_page_display,
+        ..
+    } = env.objc.borrow(this);
+    let size = size_for_number_of_pages(number_of_pages);
+    let left = bounds.origin.x + ((bounds.size.width - size.width) / 2.0).round();
+    let current_dot_center =
+        left + current_page as CGFloat * (DOT_DIAMETER + DOT_SPACING) + DOT_DIAMETER / 2.0;
+    let new_page = if location.x < current_dot_center {
+        current_page - 1
+    } else {
+        current_page + 1
+    };
+    if new_page < 0 || new_page >= number_of_pages {
+        return;
+    }
+
+    set_current_page(env, this, new_page);
+    if defers_current_page_display {
+        // The app is expected to call updateCurrentPageDisplay.
+        env.objc.borrow_mut::<UIPageControlHostObject>(this).displayed_page = displayed_page;
+    }
+    send_actions(env, this, event, UIControlEventValueChanged);
+}
+
+@end
+
+};
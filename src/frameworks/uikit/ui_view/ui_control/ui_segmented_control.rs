@@ -0,0 +1,613 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UISegmentedControl`.
+//!
+//! The control is drawn in an approximation of the iPhone OS 2/3 styles: white
+//! segments with a blue selected segment for the plain and bordered styles, and
+//! segments in the tint color for the bar style. Rounded corners aren't
+//! supported yet.
+
+use super::{send_actions, UIControlEventValueChanged, UIControlHostObject};
+use crate::frameworks::core_graphics::cg_context::{CGContextFillRect, CGContextSetRGBFillColor};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::{NSInteger, NSUInteger};
+use crate::frameworks::uikit::ui_color;
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::frameworks::uikit::ui_view::drawing::fill_vertical_gradient;
+use crate::frameworks::uikit::ui_view::UIViewHostObject;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes, release,
+    retain, ClassExports, NSZonePtr,
+};
+use crate::Environment;
+
+type UISegmentedControlStyle = NSInteger;
+const UISegmentedControlStylePlain: UISegmentedControlStyle = 0;
+#[allow(dead_code)]
+const UISegmentedControlStyleBordered: UISegmentedControlStyle = 1;
+const UISegmentedControlStyleBar: UISegmentedControlStyle = 2;
+
+const UISegmentedControlNoSegment: NSInteger = -1;
+
+const PLAIN_HEIGHT: CGFloat = 44.0;
+const BAR_HEIGHT: CGFloat = 30.0;
+const SEGMENT_PADDING: CGFloat = 10.0;
+const SEGMENT_MIN_WIDTH: CGFloat = 40.0;
+
+struct Segment {
+    /// `NSString*`, strong reference.
+    title: id,
+    /// `UIImage*`, strong reference.
+    image: id,
+    enabled: bool,
+    /// 0 means the width is automatic.
+    width: CGFloat,
+}
+
+pub struct UISegmentedControlHostObject {
+    superclass: UIControlHostObject,
+    segments: Vec<Segment>,
+    selected_segment_index: NSInteger,
+    momentary: bool,
+    style: UISegmentedControlStyle,
+    /// `UIColor*`, strong reference.
+    tint_color: id,
+}
+impl_HostObject_with_superclass!(UISegmentedControlHostObject);
+impl Default for UISegmentedControlHostObject {
+    fn default() -> Self {
+        UISegmentedControlHostObject {
+            superclass: Default::default(),
+            segments: Vec::new(),
+            selected_segment_index: UISegmentedControlNoSegment,
+            momentary: false,
+            style: UISegmentedControlStylePlain,
+            tint_color: nil,
+        }
+    }
+}
+
+/// Host object for `UISegment`, the view class NIBs use for each segment. It
+/// only exists so segments can be decoded, the control draws them itself.
+pub struct UISegmentHostObject {
+    superclass: UIViewHostObject,
+    /// `NSString*` or `UIImage*`, strong reference.
+    info: id,
+}
+impl_HostObject_with_superclass!(UISegmentHostObject);
+impl Default for UISegmentHostObject {
+    fn default() -> Self {
+        UISegmentHostObject {
+            superclass: Default::default(),
+            info: nil,
+        }
+    }
+}
+
+/// Make a segment from an `NSString*` title or `UIImage*` image.
+fn segment_from_info(env: &mut Environment, info: id) -> Segment {
+    let string_class = env.objc.get_known_class("NSString", &mut env.mem);
+    let (title, image) = if info == nil {
+        (nil, nil)
+    } else if msg![env; info isKindOfClass:string_class] {
+        (msg![env; info copy], nil)
+    } else {
+        (nil, retain(env, info))
+    };
+    Segment {
+        title,
+        image,
+        enabled: true,
+        width: 0.0,
+    }
+}
+
+fn insert_segment(env: &mut Environment, this: id, segment: Segment, index: NSUInteger) {
+    let host_obj = env.objc.borrow_mut::<UISegmentedControlHostObject>(this);
+    // Out-of-range indices append the segment.
+    let index = (index as usize).min(host_obj.segments.len());
+    host_obj.segments.insert(index, segment);
+    if host_obj.selected_segment_index >= index as NSInteger {
+        host_obj.selected_segment_index += 1;
+    }
+    () = msg![env; this setNeedsDisplay];
+}
+
+fn release_segment(env: &mut Environment, segment: Segment) {
+    release(env, segment.title);
+    release(env, segment.image);
+}
+
+/// Run a closure on a segment, if the index is valid.
+fn with_segment<T>(
+    env: &mut Environment,
+    this: id,
+    index: NSUInteger,
+    f: impl FnOnce(&mut Segment) -> T,
+) -> Option<T> {
+    let host_obj = env.objc.borrow_mut::<UISegmentedControlHostObject>(this);
+    let segment = host_obj.segments.get_mut(index as usize);
+    if segment.is_none() {
+        log!(
+            "Warning: segment index {} out of range for UISegmentedControl {:?}",
+            index,
+            this
+        );
+    }
+    segment.map(f)
+}
+
+/// Select a segment, sending an event if this changes the selection.
+fn select_segment(env: &mut Environment, this: id, index: NSInteger, event: id) {
+    let host_obj = env.objc.borrow_mut::<UISegmentedControlHostObject>(this);
+    if index == host_obj.selected_segment_index {
+        return;
+    }
+    host_obj.selected_segment_index = index;
+    () = msg![env; this setNeedsDisplay];
+    send_actions(env, this, event, UIControlEventValueChanged);
+}
+
+/// The width a segment needs to fit its content.
+fn content_width(env: &mut Environment, segment: &Segment, font: id) -> CGFloat {
+    let &Segment { title, image, .. } = segment;
+    let content_size: CGSize = if title != nil {
+        msg![env; title sizeWithFont:font]
+    } else if image != nil {
+        msg![env; image size]
+    } else {
+        CGSize::default()
+    };
+    (content_size.width + SEGMENT_PADDING * 2.0).max(SEGMENT_MIN_WIDTH)
+}
+
+fn font_for_style(env: &mut Environment, style: UISegmentedControlStyle) -> id {
+    let size: CGFloat = if style == UISegmentedControlStyleBar {
+        12.0
+    } else {
+        13.0
+    };
+    msg_class![env; UIFont boldSystemFontOfSize:size]
+}
+
+/// Get the rectangle each segment occupies.
+fn segment_frames(env: &mut Environment, this: id) -> Vec<CGRect> {
+    let bounds: CGRect = msg![env; this bounds];
+    let segments = &env
+        .objc
+        .borrow::<UISegmentedControlHostObject>(this)
+        .segments;
+    let fixed_width: CGFloat = segments.iter().map(|s| s.width).sum();
+    let automatic_count = segments.iter().filter(|s| s.width == 0.0).count();
+    let automatic_width = if automatic_count > 0 {
+        ((bounds.size.width - fixed_width) / automatic_count as CGFloat).max(0.0)
+    } else {
+        0.0
+    };
+    let mut x = bounds.origin.x;
+    segments
+        .iter()
+        .map(|segment| {
+            let width = if segment.width == 0.0 {
+                automatic_width
+            } else {
+                segment.width
+            };
+            let frame = CGRect {
+                origin: CGPoint {
+                    x,
+                    y: bounds.origin.y,
+                },
+                size: CGSize {
+                    width,
+                    height: bounds.size.height,
+                },
+            };
+            x += width;
+            frame
+        })
+        .collect()
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UISegmentedControl: UIControl
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UISegmentedControlHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    () = msg![env; this setOpaque:false];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; this setBackgroundColor:bg_color];
+    this
+}
+
+- (id)initWithItems:(id)items { // NSArray* of NSString* or UIImage*
+    let this: id = msg![env; this initWithFrame:(<CGRect as Default>::default())];
+
+    // The control is sized to fit its segments.
+    let font = font_for_style(env, UISegmentedControlStylePlain);
+    let mut width = 0.0;
+    let count: NSUInteger = if items == nil { 0 } else { msg![env; items count] };
+    for i in 0..count {
+        let item: id = msg![env; items objectAtIndex:i];
+        let segment = segment_from_info(env, item);
+        width += content_width(env, &segment, font);
+        insert_segment(env, this, segment, i);
+    }
+    let frame = CGRect {
+        origin: CGPoint::default(),
+        size: CGSize {
+            width,
+            height: PLAIN_HEIGHT,
+        },
+    };
+    () = msg![env; this setFrame:frame];
+
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UISegments");
+    let segments: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    let count: NSUInteger = if segments == nil { 0 } else { msg![env; segments count] };
+    let segment_class = env.objc.get_known_class("UISegment", &mut env.mem);
+    for i in 0..count {
+        let mut info: id = msg![env; segments objectAtIndex:i];
+        if msg![env; info isKindOfClass:segment_class] {
+            // The segment views may also have been decoded as subviews, but
+            // the control draws the segments itself.
+            () = msg![env; info removeFromSuperview];
+            info = env.objc.borrow::<UISegmentHostObject>(info).info;
+        }
+        let segment = segment_from_info(env, info);
+        insert_segment(env, this, segment, i);
+    }
+
+    let key_ns_string = get_static_str(env, "UISegmentedControlStyle");
+    let style: UISegmentedControlStyle = msg![env; coder decodeIntegerForKey:key_ns_string];
+    let key_ns_string = get_static_str(env, "UIMomentary");
+    let momentary: bool = msg![env; coder decodeBoolForKey:key_ns_string];
+    let key_ns_string = get_static_str(env, "UISelectedSegmentIndex");
+    let selected_segment_index: NSInteger = if msg![env; coder containsValueForKey:key_ns_string] {
+        msg![env; coder decodeIntegerForKey:key_ns_string]
+    } else {
+        UISegmentedControlNoSegment
+    };
+    let key_ns_string = get_static_str(env, "UITintColor");
+    let tint_color: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    retain(env, tint_color);
+
+    let host_obj = env.objc.borrow_mut::<UISegmentedControlHostObject>(this);
+    host_obj.style = style;
+    host_obj.momentary = momentary;
+    host_obj.selected_segment_index = selected_segment_index;
+    host_obj.tint_color = tint_color;
+
+    this
+}
+
+- (())dealloc {
+    let host_obj = env.objc.borrow_mut::<UISegmentedControlHostObject>(this);
+    let segments = std::mem::take(&mut host_obj.segments);
+    let tint_color = host_obj.tint_color;
+    for segment in segments {
+        release_segment(env, segment);
+    }
+    release(env, tint_color);
+    msg_super![env; this dealloc]
+}
+
+- (NSUInteger)numberOfSegments {
+    env.objc.borrow::<UISegmentedControlHostObject>(this).segments.len() as NSUInteger
+}
+
+- (())insertSegmentWithTitle:(id)title // NSString*
+                     atIndex:(NSUInteger)index
+                    animated:(bool)_animated {
+    // TODO: animation
+    let segment = segment_from_info(env, title);
+    insert_segment(env, this, segment, index);
+}
+- (())insertSegmentWithImage:(id)image // UIImage*
+                     atIndex:(NSUInteger)index
+                    animated:(bool)_animated {
+    // TODO: animation
+    let segment = segment_from_info(env, image);
+    insert_segment(env, this, segment, index);
+}
+
+- (())removeSegmentAtIndex:(NSUInteger)index
+                  animated:(bool)_animated {
+    // TODO: animation
+    let host_obj = env.objc.borrow_mut::<UISegmentedControlHostObject>(this);
+    if index as usize >= host_obj.segments.len() {
+        return;
+    }
+    let segment = host_obj.segments.remove(index as usize);
+    let selected = host_obj.selected_segment_index;
+    if selected == index as NSInteger {
+        host_obj.selected_segment_index = UISegmentedControlNoSegment;
+    } else if selected > index as NSInteger {
+        host_obj.selected_segment_index -= 1;
+    }
+    release_segment(env, segment);
+    () = msg![env; this setNeedsDisplay];
+}
+- (())removeAllSegments {
+    let host_obj = env.objc.borrow_mut::<UISegmentedControlHostObject>(this);
+    let segments = std::mem::take(&mut host_obj.segments);
+    host_obj.selected_segment_index = UISegmentedControlNoSegment;
+    for segment in segments {
+        release_segment(env, segment);
+    }
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (id)titleForSegmentAtIndex:(NSUInteger)index {
+    with_segment(env, this, index, |segment| segment.title).unwrap_or(nil)
+}
+- (())setTitle:(id)title // NSString*
+forSegmentAtIndex:(NSUInteger)index {
+    let title: id = msg![env; title copy];
+    // Titles and images are mutually exclusive.
+    let old = with_segment(env, this, index, |segment| {
+        let old = (segment.title, segment.image);
+        segment.title = title;
+        segment.image = nil;
+        old
+    });
+    let Some((old_title, old_image)) = old else {
+        release(env, title);
+        return;
+    };
+    release(env, old_title);
+    release(env, old_image);
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (id)imageForSegmentAtIndex:(NSUInteger)index {
+    with_segment(env, this, index, |segment| segment.image).unwrap_or(nil)
+}
+- (())setImage:(id)image // UIImage*
+forSegmentAtIndex:(NSUInteger)index {
+    retain(env, image);
+    // Titles and images are mutually exclusive.
+    let old = with_segment(env, this, index, |segment| {
+        let old = (segment.title, segment.image);
+        segment.title = nil;
+        segment.image = image;
+        old
+    });
+    let Some((old_title, old_image)) = old else {
+        release(env, image);
+        return;
+    };
+    release(env, old_title);
+    release(env, old_image);
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (CGFloat)widthForSegmentAtIndex:(NSUInteger)index {
+    with_segment(env, this, index, |segment| segment.width).unwrap_or(0.0)
+}
+- (())setWidth:(CGFloat)width
+forSegmentAtIndex:(NSUInteger)index {
+    with_segment(env, this, index, |segment| segment.width = width.max(0.0));
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (bool)isEnabledForSegmentAtIndex:(NSUInteger)index {
+    with_segment(env, this, index, |segment| segment.enabled).unwrap_or(false)
+}
+- (())setEnabled:(bool)enabled
+forSegmentAtIndex:(NSUInteger)index {
+    with_segment(env, this, index, |segment| segment.enabled = enabled);
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (NSInteger)selectedSegmentIndex {
+    env.objc.borrow::<UISegmentedControlHostObject>(this).selected_segment_index
+}
+- (())setSelectedSegmentIndex:(NSInteger)index {
+    let count = env.objc.borrow::<UISegmentedControlHostObject>(this).segments.len();
+    let index = if index >= 0 && (index as usize) < count {
+        index
+    } else {
+        UISegmentedControlNoSegment
+    };
+    // Before iPhone OS 5, setting the selection programmatically also sends
+    // the value changed event, and some apps rely on this.
+    select_segment(env, this, index, nil);
+}
+
+- (bool)isMomentary {
+    env.objc.borrow::<UISegmentedControlHostObject>(this).momentary
+}
+- (())setMomentary:(bool)momentary {
+    env.objc.borrow_mut::<UISegmentedControlHostObject>(this).momentary = momentary;
+}
+
+- (UISegmentedControlStyle)segmentedControlStyle {
+    env.objc.borrow::<UISegmentedControlHostObject>(this).style
+}
+- (())setSegmentedControlStyle:(UISegmentedControlStyle)style {
+    env.objc.borrow_mut::<UISegmentedControlHostObject>(this).style = style;
+    // The bar style has a different height. This is what iPhone OS does if
+    // the control still has the height of the old style.
+    let mut frame: CGRect = msg![env; this frame];
+    let (old_height, new_height) = if style == UISegmentedControlStyleBar {
+        (PLAIN_HEIGHT, BAR_HEIGHT)
+    } else {
+        (BAR_HEIGHT, PLAIN_HEIGHT)
+    };
+    if frame.size.height == old_height {
+        frame.size.height = new_height;
+        () = msg![env; this setFrame:frame];
+    }
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (id)tintColor {
+    env.objc.borrow::<UISegmentedControlHostObject>(this).tint_color
+}
+- (())setTintColor:(id)color { // UIColor*
+    retain(env, color);
+    let host_obj = env.objc.borrow_mut::<UISegmentedControlHostObject>(this);
+    let old_color = std::mem::replace(&mut host_obj.tint_color, color);
+    release(env, old_color);
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (())drawRect:(CGRect)_rect {
+    let bounds: CGRect = msg![env; this bounds];
+    let frames = segment_frames(env, this);
+    let &UISegmentedControlHostObject {
+        selected_segment_index,
+        style,
+        tint_color,
+        ..
+    } = env.objc.borrow(this);
+    let control_enabled: bool = msg![env; this isEnabled];
+    let font = font_for_style(env, style);
+    let context = UIGraphicsGetCurrentContext(env);
+
+    let base = if style == UISegmentedControlStyleBar {
+        if tint_color != nil {
+            let (r, g, b, _a) = ui_color::get_rgba(&env.objc, tint_color);
+            (r, g, b)
+        } else {
+            (0.45, 0.55, 0.7)
+        }
+    } else {
+        (1.0, 1.0, 1.0)
+    };
+    let darken = |(r, g, b): (CGFloat, CGFloat, CGFloat), amount: CGFloat| {
+        (r * amount, g * amount, b * amount)
+    };
+
+    // The border and separators are whatever is left visible after drawing
+    // the segments on top of this.
+    CGContextSetRGBFillColor(env, context, 0.4, 0.4, 0.4, 1.0);
+    CGContextFillRect(env, context, bounds);
+
+    let segment_count = frames.len();
+    for (i, frame) in frames.into_iter().enumerate() {
+        let host_obj = env.objc.borrow::<UISegmentedControlHostObject>(this);
+        let segment = &host_obj.segments[i];
+        let (title, image, enabled) = (segment.title, segment.image, segment.enabled);
+        let selected = i as NSInteger == selected_segment_index;
+
+        let inner = CGRect {
+            origin: CGPoint {
+                x: frame.origin.x + 1.0,
+                y: frame.origin.y + 1.0,
+            },
+            size: CGSize {
+                width: frame.size.width - if i + 1 == segment_count { 2.0 } else { 1.0 },
+                height: frame.size.height - 2.0,
+            },
+        };
+        let (top, bottom) = match (selected, style == UISegmentedControlStyleBar) {
+            (true, true) => (darken(base, 0.8), darken(base, 0.6)),
+            (false, true) => (base, darken(base, 0.8)),
+            (true, false) => ((0.45, 0.65, 1.0), (0.15, 0.4, 0.9)),
+            (false, false) => (base, darken(base, 0.9)),
+        };
+        fill_vertical_gradient(env, context, inner, top, bottom);
+
+        if image != nil {
+            let size: CGSize = msg![env; image size];
+            let rect = CGRect {
+                origin: CGPoint {
+                    x: inner.origin.x + ((inner.size.width - size.width) / 2.0).round(),
+                    y: inner.origin.y + ((inner.size.height - size.height) / 2.0).round(),
+                },
+                size,
+            };
+            () = msg![env; image drawInRect:rect];
+        } else if title != nil {
+            let text_size: CGSize = msg![env; title sizeWithFont:font];
+            let origin = CGPoint {
+                x: inner.origin.x + ((inner.size.width - text_size.width) / 2.0).round(),
+                y: inner.origin.y + ((inner.size.height - text_size.height) / 2.0).round(),
+            };
+            if !enabled || !control_enabled {
+                CGContextSetRGBFillColor(env, context, 0.6, 0.6, 0.6, 1.0);
+            } else if selected || style == UISegmentedControlStyleBar {
+                CGContextSetRGBFillColor(env, context, 1.0, 1.0, 1.0, 1.0);
+            } else {
+                CGContextSetRGBFillColor(env, context, 0.3, 0.3, 0.3, 1.0);
+            }
+            let _: CGSize = msg![env; title drawAtPoint:origin withFont:font];
+        }
+    }
+}
+
+- (bool)beginTrackingWithTouch:(id)touch // UITouch*
+                     withEvent:(id)event { // UIEvent*
+    let location: CGPoint = msg![env; touch locationInView:this];
+    let frames = segment_frames(env, this);
+    let Some(index) = frames.iter().position(|frame| {
+        location.x >= frame.origin.x && location.x < frame.origin.x + frame.size.width
+    }) else {
+        return false;
+    };
+    if !env.objc.borrow::<UISegmentedControlHostObject>(this).segments[index].enabled {
+        return false;
+    }
+    // Segments are selected as soon as they're touched.
+    select_segment(env, this, index as NSInteger, event);
+    true
+}
+- (())endTrackingWithTouch:(id)touch // UITouch*
+                  withEvent:(id)event { // UIEvent*
+    () = msg_super![env; this endTrackingWithTouch:touch withEvent:event];
+    let host_obj = env.objc.borrow_mut::<UISegmentedControlHostObject>(this);
+    if host_obj.momentary {
+        host_obj.selected_segment_index = UISegmentedControlNoSegment;
+        () = msg![env; this setNeedsDisplay];
+    }
+}
+
+@end
+
+@implementation UISegment: UIView
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UISegmentHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+    let key_ns_string = get_static_str(env, "UISegmentInfo");
+    let info: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    retain(env, info);
+    env.objc.borrow_mut::<UISegmentHostObject>(this).info = info;
+    this
+}
+
+- (())dealloc {
+    let info = env.objc.borrow::<UISegmentHostObject>(this).info;
+    release(env, info);
+    msg_super![env; this dealloc]
+}
+
+@end
+
+};
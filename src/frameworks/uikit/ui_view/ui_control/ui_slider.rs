@@ -0,0 +1,429 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UISlider`.
+//!
+//! Parts without a custom image are drawn in an approximation of the default
+//! iPhone OS style: a blue track left of the thumb, a white track right of it,
+//! and a round white thumb. Custom track images are stretched rather than
+//! drawn with end caps.
+
+use super::{
+    fill_circle, send_actions, UIControlEventValueChanged, UIControlHostObject, UIControlState,
+    UIControlStateHighlighted, UIControlStateNormal,
+};
+use crate::frameworks::core_graphics::cg_context::{CGContextFillRect, CGContextSetRGBFillColor};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::frameworks::uikit::ui_view::drawing::fill_vertical_gradient;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes, release,
+    retain, ClassExports, NSZonePtr,
+};
+use crate::Environment;
+use std::collections::HashMap;
+
+const THUMB_SIZE: CGFloat = 23.0;
+const TRACK_HEIGHT: CGFloat = 9.0;
+/// How far outside the thumb a touch can be and still grab it.
+const THUMB_TOUCH_MARGIN: CGFloat = 10.0;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum SliderPart {
+    Thumb,
+    MinimumTrack,
+    MaximumTrack,
+}
+
+pub struct UISliderHostObject {
+    superclass: UIControlHostObject,
+    value: f32,
+    minimum_value: f32,
+    maximum_value: f32,
+    continuous: bool,
+    /// Values are `UIImage*`
+    images_for_states: HashMap<(SliderPart, UIControlState), id>,
+    /// The value when tracking began, so that a non-continuous slider knows
+    /// whether to send an event when tracking ends.
+    value_at_tracking_start: f32,
+    /// Horizontal distance from the center of the thumb to the touch.
+    tracking_offset: CGFloat,
+}
+impl_HostObject_with_superclass!(UISliderHostObject);
+impl Default for UISliderHostObject {
+    fn default() -> Self {
+        UISliderHostObject {
+            superclass: Default::default(),
+            value: 0.0,
+            minimum_value: 0.0,
+            maximum_value: 1.0,
+            continuous: true,
+            images_for_states: HashMap::new(),
+            value_at_tracking_start: 0.0,
+            tracking_offset: 0.0,
+        }
+    }
+}
+
+fn image_for_state(env: &mut Environment, this: id, part: SliderPart, state: UIControlState) -> id {
+    let images = &env
+        .objc
+        .borrow::<UISliderHostObject>(this)
+        .images_for_states;
+    images
+        .get(&(part, state))
+        .or_else(|| images.get(&(part, UIControlStateNormal)))
+        .copied()
+        .unwrap_or(nil)
+}
+
+fn set_image_for_state(
+    env: &mut Environment,
+    this: id,
+    part: SliderPart,
+    image: id,
+    state: UIControlState,
+) {
+    retain(env, image);
+    let host_obj = env.objc.borrow_mut::<UISliderHostObject>(this);
+    if let Some(old) = host_obj.images_for_states.insert((part, state), image) {
+        release(env, old);
+    }
+    () = msg![env; this setNeedsDisplay];
+}
+
+fn current_image(env: &mut Environment, this: id, part: SliderPart) -> id {
+    let state: UIControlState = msg![env; this state];
+    image_for_state(env, this, part, state)
+}
+
+/// Find the value for a thumb centered at a particular x co-ordinate.
+fn value_for_thumb_center(env: &mut Environment, this: id, x: CGFloat) -> f32 {
+    let bounds: CGRect = msg![env; this bounds];
+    let track: CGRect = msg![env; this trackRectForBounds:bounds];
+    let &UISliderHostObject {
+        minimum_value,
+        maximum_value,
+        ..
+    } = env.objc.borrow(this);
+    let thumb: CGRect = msg![env; this thumbRectForBounds:bounds
+                                               trackRect:track
+                                                   value:minimum_value];
+    let range = track.size.width - thumb.size.width;
+    let fraction = if range > 0.0 {
+        ((x - track.origin.x - thumb.size.width / 2.0) / range).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    minimum_value + fraction * (maximum_value - minimum_value)
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UISlider: UIControl
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UISliderHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    () = msg![env; this setOpaque:false];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; this setBackgroundColor:bg_color];
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UIMinValue");
+    let minimum_value: f32 = msg![env; coder decodeFloatForKey:key_ns_string];
+    let key_ns_string = get_static_str(env, "UIMaxValue");
+    let maximum_value: f32 = if msg![env; coder containsValueForKey:key_ns_string] {
+        msg![env; coder decodeFloatForKey:key_ns_string]
+    } else {
+        1.0
+    };
+    let key_ns_string = get_static_str(env, "UIValue");
+    let value: f32 = msg![env; coder decodeFloatForKey:key_ns_string];
+    let key_ns_string = get_static_str(env, "UIContinuous");
+    let continuous: bool = if msg![env; coder containsValueForKey:key_ns_string] {
+        msg![env; coder decodeBoolForKey:key_ns_string]
+    } else {
+        true
+    };
+
+    let host_obj = env.objc.borrow_mut::<UISliderHostObject>(this);
+    host_obj.minimum_value = minimum_value;
+    host_obj.maximum_value = maximum_value;
+    host_obj.continuous = continuous;
+    () = msg![env; this setValue:value];
+
+    this
+}
+
+- (())dealloc {
+    let images = std::mem::take(
+        &mut env.objc.borrow_mut::<UISliderHostObject>(this).images_for_states,
+    );
+    for (_key, image) in images {
+        release(env, image);
+    }
+    msg_super![env; this dealloc]
+}
+
+- (f32)value {
+    env.objc.borrow::<UISliderHostObject>(this).value
+}
+- (())setValue:(f32)value {
+    let host_obj = env.objc.borrow_mut::<UISliderHostObject>(this);
+    // Clamping with min()/max() rather than clamp() avoids a panic if the
+    // minimum is above the maximum.
+    host_obj.value = value.min(host_obj.maximum_value).max(host_obj.minimum_value);
+    () = msg![env; this setNeedsDisplay];
+}
+- (())setValue:(f32)value animated:(bool)_animated {
+    // TODO: animation
+    () = msg![env; this setValue:value];
+}
+
+- (f32)minimumValue {
+    env.objc.borrow::<UISliderHostObject>(this).minimum_value
+}
+- (())setMinimumValue:(f32)minimum_value {
+    let host_obj = env.objc.borrow_mut::<UISliderHostObject>(this);
+    host_obj.minimum_value = minimum_value;
+    let value = host_obj.value;
+    () = msg![env; this setValue:value];
+}
+
+- (f32)maximumValue {
+    env.objc.borrow::<UISliderHostObject>(this).maximum_value
+}
+- (())setMaximumValue:(f32)maximum_value {
+    let host_obj = env.objc.borrow_mut::<UISliderHostObject>(this);
+    host_obj.maximum_value = maximum_value;
+    let value = host_obj.value;
+    () = msg![env; this setValue:value];
+}
+
+- (bool)isContinuous {
+    env.objc.borrow::<UISliderHostObject>(this).continuous
+}
+- (())setContinuous:(bool)continuous {
+    env.objc.borrow_mut::<UISliderHostObject>(this).continuous = continuous;
+}
+
+- (id)thumbImageForState:(UIControlState)state {
+    image_for_state(env, this, SliderPart::Thumb, state)
+}
+- (())setThumbImage:(id)image // UIImage*
+           forState:(UIControlState)state {
+    set_image_for_state(env, this, SliderPart::Thumb, image, state);
+}
+- (id)currentThumbImage {
+    current_image(env, this, SliderPart::Thumb)
+}
+
+- (id)minimumTrackImageForState:(UIControlState)state {
+    image_for_state(env, this, SliderPart::MinimumTrack, state)
+}
+- (())setMinimumTrackImage:(id)image // UIImage*
+                  forState:(UIControlState)state {
+    set_image_for_state(env, this, SliderPart::MinimumTrack, image, state);
+}
+- (id)currentMinimumTrackImage {
+    current_image(env, this, SliderPart::MinimumTrack)
+}
+
+- (id)maximumTrackImageForState:(UIControlState)state {
+    image_for_state(env, this, SliderPart::MaximumTrack, state)
+}
+- (())setMaximumTrackImage:(id)image // UIImage*
+                  forState:(UIControlState)state {
+    set_image_for_state(env, this, SliderPart::MaximumTrack, image, state);
+}
+- (id)currentMaximumTrackImage {
+    current_image(env, this, SliderPart::MaximumTrack)
+}
+
+- (())setEnabled:(bool)enabled {
+    () = msg_super![env; this setEnabled:enabled];
+    () = msg![env; this setNeedsDisplay];
+}
+- (())setHighlighted:(bool)highlighted {
+    () = msg_super![env; this setHighlighted:highlighted];
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (CGRect)trackRectForBounds:(CGRect)bounds {
+    let image: id = msg![env; this currentMinimumTrackImage];
+    let height = if image != nil {
+        let size: CGSize = msg![env; image size];
+        size.height
+    } else {
+        TRACK_HEIGHT
+    };
+    CGRect {
+        origin: CGPoint {
+            x: bounds.origin.x,
+            y: bounds.origin.y + ((bounds.size.height - height) / 2.0).round(),
+        },
+        size: CGSize {
+            width: bounds.size.width,
+            height,
+        },
+    }
+}
+
+- (CGRect)thumbRectForBounds:(CGRect)bounds
+                   trackRect:(CGRect)track
+                       value:(f32)value {
+    let image: id = msg![env; this currentThumbImage];
+    let size = if image != nil {
+        msg![env; image size]
+    } else {
+        CGSize {
+            width: THUMB_SIZE,
+            height: THUMB_SIZE,
+        }
+    };
+    let &UISliderHostObject {
+        minimum_value,
+        maximum_value,
+        ..
+    } = env.objc.borrow(this);
+    let fraction = if maximum_value > minimum_value {
+        ((value - minimum_value) / (maximum_value - minimum_value)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    CGRect {
+        origin: CGPoint {
+            x: track.origin.x + ((track.size.width - size.width) * fraction).round(),
+            y: bounds.origin.y + ((bounds.size.height - size.height) / 2.0).round(),
+        },
+        size,
+    }
+}
+
+- (())drawRect:(CGRect)_rect {
+    let bounds: CGRect = msg![env; this bounds];
+    let track: CGRect = msg![env; this trackRectForBounds:bounds];
+    let value: f32 = msg![env; this value];
+    let thumb: CGRect = msg![env; this thumbRectForBounds:bounds trackRect:track value:value];
+    let state: UIControlState = msg![env; this state];
+    let context = UIGraphicsGetCurrentContext(env);
+
+    let split = thumb.origin.x + thumb.size.width / 2.0;
+    let mut minimum_track = track;
+    minimum_track.size.width = split - track.origin.x;
+    let mut maximum_track = track;
+    maximum_track.origin.x = split;
+    maximum_track.size.width = track.origin.x + track.size.width - split;
+
+    for (part, rect, top, bottom) in [
+        (SliderPart::MinimumTrack, minimum_track, (0.45, 0.65, 1.0), (0.15, 0.4, 0.9)),
+        (SliderPart::MaximumTrack, maximum_track, (0.85, 0.85, 0.85), (1.0, 1.0, 1.0)),
+    ] {
+        if rect.size.width <= 0.0 {
+            continue;
+        }
+        let image = image_for_state(env, this, part, state);
+        if image != nil {
+            () = msg![env; image drawInRect:rect];
+            continue;
+        }
+        CGContextSetRGBFillColor(env, context, 0.4, 0.4, 0.4, 1.0);
+        CGContextFillRect(env, context, rect);
+        let inner = CGRect {
+            origin: CGPoint {
+                x: rect.origin.x,
+                y: rect.origin.y + 1.0,
+            },
+            size: CGSize {
+                width: rect.size.width,
+                height: rect.size.height - 2.0,
+            },
+        };
+        fill_vertical_gradient(env, context, inner, top, bottom);
+    }
+
+    let image = image_for_state(env, this, SliderPart::Thumb, state);
+    if image != nil {
+        () = msg![env; image drawInRect:thumb];
+    } else {
+        let center = CGPoint {
+            x: thumb.origin.x + thumb.size.width / 2.0,
+            y: thumb.origin.y + thumb.size.height / 2.0,
+        };
+        let radius = thumb.size.width.min(thumb.size.height) / 2.0;
+        CGContextSetRGBFillColor(env, context, 0.5, 0.5, 0.5, 1.0);
+        fill_circle(env, context, center, radius);
+        let fill = if (state & UIControlStateHighlighted) != 0 { 0.8 } else { 0.95 };
+        CGContextSetRGBFillColor(env, context, fill, fill, fill, 1.0);
+        fill_circle(env, context, center, radius - 1.0);
+    }
+}
+
+- (bool)beginTrackingWithTouch:(id)touch // UITouch*
+                     withEvent:(id)_event { // UIEvent*
+    // Only touches on the thumb can move it.
+    let location: CGPoint = msg![env; touch locationInView:this];
+    let bounds: CGRect = msg![env; this bounds];
+    let track: CGRect = msg![env; this trackRectForBounds:bounds];
+    let value: f32 = msg![env; this value];
+    let thumb: CGRect = msg![env; this thumbRectForBounds:bounds trackRect:track value:value];
+    let on_thumb = location.x >= thumb.origin.x - THUMB_TOUCH_MARGIN
+        && location.x < thumb.origin.x + thumb.size.width + THUMB_TOUCH_MARGIN
+        && location.y >= thumb.origin.y - THUMB_TOUCH_MARGIN
+        && location.y < thumb.origin.y + thumb.size.height + THUMB_TOUCH_MARGIN;
+    if !on_thumb {
+        return false;
+    }
+
+    let host_obj = env.objc.borrow_mut::<UISliderHostObject>(this);
+    host_obj.value_at_tracking_start = value;
+    host_obj.tracking_offset = location.x - (thumb.origin.x + thumb.size.width / 2.0);
+    true
+}
+- (bool)continueTrackingWithTouch:(id)touch // UITouch*
+                        withEvent:(id)event { // UIEvent*
+    let location: CGPoint = msg![env; touch locationInView:this];
+    let tracking_offset = env.objc.borrow::<UISliderHostObject>(this).tracking_offset;
+    let new_value = value_for_thumb_center(env, this, location.x - tracking_offset);
+    let old_value: f32 = msg![env; this value];
+    if new_value == old_value {
+        return true;
+    }
+    () = msg![env; this setValue:new_value];
+    if env.objc.borrow::<UISliderHostObject>(this).continuous {
+        send_actions(env, this, event, UIControlEventValueChanged);
+    }
+    true
+}
+- (())endTrackingWithTouch:(id)touch // UITouch*
+                  withEvent:(id)event { // UIEvent*
+    () = msg_super![env; this endTrackingWithTouch:touch withEvent:event];
+    let &UISliderHostObject {
+        value,
+        value_at_tracking_start,
+        continuous,
+        ..
+    } = env.objc.borrow(this);
+    if !continuous && value != value_at_tracking_start {
+        send_actions(env, this, event, UIControlEventValueChanged);
+    }
+}
+
+@end
+
+};
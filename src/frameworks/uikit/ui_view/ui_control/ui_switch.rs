@@ -0,0 +1,224 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UISwitch`.
+//!
+//! The switch is drawn in an approximation of the iPhone OS 2/3 style: a blue
+//! "ON" side and a gray "OFF" side either side of a white thumb. Rounded
+//! corners aren't supported yet.
+
+use super::{
+    send_actions, UIControlEventValueChanged, UIControlHostObject, UIControlState,
+    UIControlStateHighlighted,
+};
+use crate::frameworks::core_graphics::cg_context::{CGContextFillRect, CGContextSetRGBFillColor};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::frameworks::uikit::ui_view::drawing::fill_vertical_gradient;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_class, msg_super, objc_classes, ClassExports,
+    NSZonePtr,
+};
+
+/// The size of a switch is fixed.
+const SWITCH_SIZE: CGSize = CGSize {
+    width: 94.0,
+    height: 27.0,
+};
+const THUMB_WIDTH: CGFloat = 40.0;
+/// How far a touch has to move to count as dragging the thumb rather than
+/// tapping.
+const DRAG_THRESHOLD: CGFloat = 5.0;
+
+pub struct UISwitchHostObject {
+    superclass: UIControlHostObject,
+    on: bool,
+    /// Where the touch being tracked began, in the switch's co-ordinates.
+    tracking_start: CGPoint,
+}
+impl_HostObject_with_superclass!(UISwitchHostObject);
+impl Default for UISwitchHostObject {
+    fn default() -> Self {
+        UISwitchHostObject {
+            superclass: Default::default(),
+            on: false,
+            tracking_start: Default::default(),
+        }
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UISwitch: UIControl
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UISwitchHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let frame = CGRect {
+        origin: frame.origin,
+        size: SWITCH_SIZE,
+    };
+    let this: id = msg_super![env; this initWithFrame:frame];
+    () = msg![env; this setOpaque:false];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; this setBackgroundColor:bg_color];
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UISwitchOn");
+    let on: bool = msg![env; coder decodeBoolForKey:key_ns_string];
+    env.objc.borrow_mut::<UISwitchHostObject>(this).on = on;
+
+    this
+}
+
+- (CGSize)sizeThatFits:(CGSize)_size {
+    SWITCH_SIZE
+}
+
+- (bool)isOn {
+    env.objc.borrow::<UISwitchHostObject>(this).on
+}
+- (())setOn:(bool)on {
+    env.objc.borrow_mut::<UISwitchHostObject>(this).on = on;
+    () = msg![env; this setNeedsDisplay];
+}
+- (())setOn:(bool)on animated:(bool)_animated {
+    // TODO: animation
+    () = msg![env; this setOn:on];
+}
+
+- (())setEnabled:(bool)enabled {
+    () = msg_super![env; this setEnabled:enabled];
+    () = msg![env; this setNeedsDisplay];
+}
+- (())setHighlighted:(bool)highlighted {
+    () = msg_super![env; this setHighlighted:highlighted];
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (())drawRect:(CGRect)_rect {
+    let bounds: CGRect = msg![env; this bounds];
+    let on: bool = msg![env; this isOn];
+    let state: UIControlState = msg![env; this state];
+    let context = UIGraphicsGetCurrentContext(env);
+
+    CGContextSetRGBFillColor(env, context, 0.4, 0.4, 0.4, 1.0);
+    CGContextFillRect(env, context, bounds);
+    let inner = CGRect {
+        origin: CGPoint {
+            x: bounds.origin.x + 1.0,
+            y: bounds.origin.y + 1.0,
+        },
+        size: CGSize {
+            width: bounds.size.width - 2.0,
+            height: bounds.size.height - 2.0,
+        },
+    };
+
+    // The thumb covers whichever label isn't currently applicable.
+    let thumb = CGRect {
+        origin: CGPoint {
+            x: if on {
+                inner.origin.x + inner.size.width - THUMB_WIDTH
+            } else {
+                inner.origin.x
+            },
+            y: inner.origin.y,
+        },
+        size: CGSize {
+            width: THUMB_WIDTH,
+            height: inner.size.height,
+        },
+    };
+    let mut label_area = inner;
+    if on {
+        label_area.size.width -= THUMB_WIDTH;
+        fill_vertical_gradient(env, context, label_area, (0.2, 0.45, 0.85), (0.35, 0.6, 1.0));
+    } else {
+        label_area.origin.x += THUMB_WIDTH;
+        label_area.size.width -= THUMB_WIDTH;
+        fill_vertical_gradient(env, context, label_area, (0.8, 0.8, 0.8), (0.98, 0.98, 0.98));
+    }
+
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:(16.0 as CGFloat)];
+    let text = get_static_str(env, if on { "ON" } else { "OFF" });
+    let text_size: CGSize = msg![env; text sizeWithFont:font];
+    let origin = CGPoint {
+        x: label_area.origin.x + ((label_area.size.width - text_size.width) / 2.0).round(),
+        y: label_area.origin.y + ((label_area.size.height - text_size.height) / 2.0).round(),
+    };
+    if on {
+        CGContextSetRGBFillColor(env, context, 1.0, 1.0, 1.0, 1.0);
+    } else {
+        CGContextSetRGBFillColor(env, context, 0.45, 0.45, 0.45, 1.0);
+    }
+    let _: CGSize = msg![env; text drawAtPoint:origin withFont:font];
+
+    CGContextSetRGBFillColor(env, context, 0.55, 0.55, 0.55, 1.0);
+    CGContextFillRect(env, context, thumb);
+    let thumb_inner = CGRect {
+        origin: CGPoint {
+            x: thumb.origin.x + 1.0,
+            y: thumb.origin.y,
+        },
+        size: CGSize {
+            width: thumb.size.width - 2.0,
+            height: thumb.size.height,
+        },
+    };
+    let (top, bottom) = if (state & UIControlStateHighlighted) != 0 {
+        ((0.85, 0.85, 0.85), (0.7, 0.7, 0.7))
+    } else {
+        ((1.0, 1.0, 1.0), (0.85, 0.85, 0.85))
+    };
+    fill_vertical_gradient(env, context, thumb_inner, top, bottom);
+}
+
+- (bool)beginTrackingWithTouch:(id)touch // UITouch*
+                     withEvent:(id)_event { // UIEvent*
+    let location: CGPoint = msg![env; touch locationInView:this];
+    env.objc.borrow_mut::<UISwitchHostObject>(this).tracking_start = location;
+    true
+}
+- (())endTrackingWithTouch:(id)touch // UITouch*
+                  withEvent:(id)event { // UIEvent*
+    () = msg_super![env; this endTrackingWithTouch:touch withEvent:event];
+
+    let location: CGPoint = msg![env; touch locationInView:this];
+    let &UISwitchHostObject {
+        on,
+        tracking_start,
+        ..
+    } = env.objc.borrow(this);
+    let distance = location.x - tracking_start.x;
+    // Dragging the thumb sets the switch to the side it was dragged to,
+    // tapping toggles it.
+    let new_on = if distance.abs() >= DRAG_THRESHOLD {
+        distance > 0.0
+    } else if msg![env; this pointInside:location withEvent:event] {
+        !on
+    } else {
+        on
+    };
+    if new_on != on {
+        () = msg![env; this setOn:new_on];
+        send_actions(env, this, event, UIControlEventValueChanged);
+    }
+}
+
+@end
+
+};
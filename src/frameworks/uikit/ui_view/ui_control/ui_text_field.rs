@@ -4,63 +4,727 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `UITextField`.
+//!
+//! Editing uses the host keyboard (see [crate::window::TextInputEvent]) rather
+//! than a simulated on-screen keyboard, so the insertion point is always at the
+//! end of the text, and the keyboard type is only used to filter the input.
 
-use crate::frameworks::foundation::{ns_string, NSInteger};
-use crate::objc::{id, objc_classes, ClassExports};
+use super::{
+    send_actions, UIControlEventEditingChanged, UIControlEventEditingDidBegin,
+    UIControlEventEditingDidEnd, UIControlEventEditingDidEndOnExit, UIControlHostObject,
+};
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::core_graphics::cg_context::{CGContextFillRect, CGContextSetRGBFillColor};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::{ns_string, NSInteger, NSRange, NSUInteger};
+use crate::frameworks::uikit::ui_color;
+use crate::frameworks::uikit::ui_font::{
+    UITextAlignment, UITextAlignmentCenter, UITextAlignmentLeft, UITextAlignmentRight,
+};
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::frameworks::uikit::ui_responder::first_responder;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes, release,
+    retain, Class, ClassExports, NSZonePtr,
+};
+use crate::window::TextInputEvent;
+use crate::Environment;
 
 type UIKeyboardAppearance = NSInteger;
-type UIKeyboardType = NSInteger;
-type UIReturnKeyType = NSInteger;
 type UITextAutocapitalizationType = NSInteger;
 type UITextAutocorrectionType = NSInteger;
 
+type UIKeyboardType = NSInteger;
+const UIKeyboardTypeDefault: UIKeyboardType = 0;
+const UIKeyboardTypeASCIICapable: UIKeyboardType = 1;
+const UIKeyboardTypeNumberPad: UIKeyboardType = 4;
+const UIKeyboardTypePhonePad: UIKeyboardType = 5;
+const UIKeyboardTypeDecimalPad: UIKeyboardType = 8;
+
+type UIReturnKeyType = NSInteger;
+const UIReturnKeyDefault: UIReturnKeyType = 0;
+
+type UITextBorderStyle = NSInteger;
+const UITextBorderStyleNone: UITextBorderStyle = 0;
+const UITextBorderStyleLine: UITextBorderStyle = 1;
+const UITextBorderStyleBezel: UITextBorderStyle = 2;
+const UITextBorderStyleRoundedRect: UITextBorderStyle = 3;
+
+pub const UITextFieldTextDidBeginEditingNotification: &str =
+    "UITextFieldTextDidBeginEditingNotification";
+pub const UITextFieldTextDidChangeNotification: &str = "UITextFieldTextDidChangeNotification";
+pub const UITextFieldTextDidEndEditingNotification: &str =
+    "UITextFieldTextDidEndEditingNotification";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_UITextFieldTextDidBeginEditingNotification",
+        HostConstant::NSString(UITextFieldTextDidBeginEditingNotification),
+    ),
+    (
+        "_UITextFieldTextDidChangeNotification",
+        HostConstant::NSString(UITextFieldTextDidChangeNotification),
+    ),
+    (
+        "_UITextFieldTextDidEndEditingNotification",
+        HostConstant::NSString(UITextFieldTextDidEndEditingNotification),
+    ),
+];
+
+pub struct UITextFieldHostObject {
+    superclass: UIControlHostObject,
+    /// `NSString*`
+    text: id,
+    /// `NSString*`
+    placeholder: id,
+    /// `UIFont*`
+    font: id,
+    /// `UIColor*`
+    text_color: id,
+    text_alignment: UITextAlignment,
+    border_style: UITextBorderStyle,
+    clears_on_begin_editing: bool,
+    /// Something implementing `UITextFieldDelegate`, weak reference
+    delegate: id,
+    editing: bool,
+    keyboard_type: UIKeyboardType,
+    return_key_type: UIReturnKeyType,
+    enables_return_key_automatically: bool,
+    secure_text_entry: bool,
+}
+impl_HostObject_with_superclass!(UITextFieldHostObject);
+impl Default for UITextFieldHostObject {
+    fn default() -> Self {
+        UITextFieldHostObject {
+            superclass: Default::default(),
+            text: nil,
+            placeholder: nil,
+            font: nil,
+            text_color: nil,
+            text_alignment: UITextAlignmentLeft,
+            border_style: UITextBorderStyleNone,
+            clears_on_begin_editing: false,
+            delegate: nil,
+            editing: false,
+            keyboard_type: UIKeyboardTypeDefault,
+            return_key_type: UIReturnKeyDefault,
+            enables_return_key_automatically: false,
+            secure_text_entry: false,
+        }
+    }
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
 
 @implementation UITextField: UIControl
 
-// TODO: rendering
-// TODO: more properties
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UITextFieldHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    // These aren't redundant, the setters fetch the real defaults.
+    () = msg![env; this setFont:nil];
+    () = msg![env; this setTextColor:nil];
+    this
+}
+
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+    // TODO: Decode the font and text color
+    () = msg![env; this setFont:nil];
+    () = msg![env; this setTextColor:nil];
+
+    let key_ns_string = ns_string::get_static_str(env, "UIText");
+    let text: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setText:text];
+
+    let key_ns_string = ns_string::get_static_str(env, "UIPlaceholder");
+    let placeholder: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setPlaceholder:placeholder];
+
+    let key_ns_string = ns_string::get_static_str(env, "UITextAlignment");
+    let text_alignment: NSInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+    () = msg![env; this setTextAlignment:text_alignment];
+
+    let key_ns_string = ns_string::get_static_str(env, "UIBorderStyle");
+    let border_style: NSInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+    () = msg![env; this setBorderStyle:border_style];
+
+    let key_ns_string = ns_string::get_static_str(env, "UIClearsOnBeginEditing");
+    let clear: bool = msg![env; coder decodeBoolForKey:key_ns_string];
+    () = msg![env; this setClearsOnBeginEditing:clear];
+
+    this
+}
+
+- (())dealloc {
+    if first_responder(env) == this {
+        // Don't bother the delegate, it's probably already gone.
+        () = msg_super![env; this resignFirstResponder];
+        stop_text_input(env);
+    }
+    let &UITextFieldHostObject {
+        text,
+        placeholder,
+        font,
+        text_color,
+        ..
+    } = env.objc.borrow(this);
+    release(env, text);
+    release(env, placeholder);
+    release(env, font);
+    release(env, text_color);
+    msg_super![env; this dealloc]
+}
 
 - (id)text {
-    // This should be `nil` by default, but Wolf3d crashes otherwise
-    ns_string::get_static_str(env, "")
+    let text = env.objc.borrow::<UITextFieldHostObject>(this).text;
+    if text == nil {
+        // This should be `nil` by default, but Wolf3d crashes otherwise
+        ns_string::get_static_str(env, "")
+    } else {
+        text
+    }
+}
+- (())setText:(id)new_text { // NSString*
+    let new_text: id = msg![env; new_text copy];
+    let old_text = std::mem::replace(
+        &mut env.objc.borrow_mut::<UITextFieldHostObject>(this).text,
+        new_text
+    );
+    release(env, old_text);
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (id)placeholder {
+    env.objc.borrow::<UITextFieldHostObject>(this).placeholder
+}
+- (())setPlaceholder:(id)new_placeholder { // NSString*
+    let new_placeholder: id = msg![env; new_placeholder copy];
+    let old_placeholder = std::mem::replace(
+        &mut env.objc.borrow_mut::<UITextFieldHostObject>(this).placeholder,
+        new_placeholder
+    );
+    release(env, old_placeholder);
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (id)font {
+    env.objc.borrow::<UITextFieldHostObject>(this).font
+}
+- (())setFont:(id)new_font { // UIFont*
+    let new_font: id = if new_font == nil {
+        // reset to default
+        let size: CGFloat = 12.0;
+        msg_class![env; UIFont systemFontOfSize:size]
+    } else {
+        new_font
+    };
+    retain(env, new_font);
+    let old_font = std::mem::replace(
+        &mut env.objc.borrow_mut::<UITextFieldHostObject>(this).font,
+        new_font
+    );
+    release(env, old_font);
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (id)textColor {
+    env.objc.borrow::<UITextFieldHostObject>(this).text_color
+}
+- (())setTextColor:(id)new_text_color { // UIColor*
+    let new_text_color: id = if new_text_color == nil {
+        msg_class![env; UIColor blackColor]
+    } else {
+        new_text_color
+    };
+    retain(env, new_text_color);
+    let old_text_color = std::mem::replace(
+        &mut env.objc.borrow_mut::<UITextFieldHostObject>(this).text_color,
+        new_text_color
+    );
+    release(env, old_text_color);
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (UITextAlignment)textAlignment {
+    env.objc.borrow::<UITextFieldHostObject>(this).text_alignment
+}
+- (())setTextAlignment:(UITextAlignment)text_alignment {
+    env.objc.borrow_mut::<UITextFieldHostObject>(this).text_alignment = text_alignment;
+    () = msg![env; this setNeedsDisplay];
 }
 
-- (())setText:(id)_text { // NSString*
-    // TODO
+- (UITextBorderStyle)borderStyle {
+    env.objc.borrow::<UITextFieldHostObject>(this).border_style
 }
-- (())setTextColor:(id)_color { // UIColor*
-    // TODO: implement this once views are actually rendered
+- (())setBorderStyle:(UITextBorderStyle)border_style {
+    env.objc.borrow_mut::<UITextFieldHostObject>(this).border_style = border_style;
+    () = msg![env; this setNeedsDisplay];
 }
 
-- (())setClearsOnBeginEditing:(bool)_clear {
-    // TODO
+- (bool)clearsOnBeginEditing {
+    env.objc.borrow::<UITextFieldHostObject>(this).clears_on_begin_editing
+}
+- (())setClearsOnBeginEditing:(bool)clear {
+    env.objc.borrow_mut::<UITextFieldHostObject>(this).clears_on_begin_editing = clear;
 }
 
+- (id)delegate {
+    env.objc.borrow::<UITextFieldHostObject>(this).delegate
+}
 // weak/non-retaining
-- (())setDelegate:(id)_delegate { // something implementing UITextFieldDelegate
-    // TODO
+- (())setDelegate:(id)delegate { // something implementing UITextFieldDelegate
+    env.objc.borrow_mut::<UITextFieldHostObject>(this).delegate = delegate;
+}
+
+- (bool)isEditing {
+    env.objc.borrow::<UITextFieldHostObject>(this).editing
+}
+
+// UIResponder overrides
+
+- (bool)canBecomeFirstResponder {
+    msg![env; this isEnabled]
+}
+
+- (bool)becomeFirstResponder {
+    if msg![env; this isFirstResponder] {
+        return true;
+    }
+    if !ask_delegate(env, this, "textFieldShouldBeginEditing:") {
+        return false;
+    }
+    let became: bool = msg_super![env; this becomeFirstResponder];
+    if !became {
+        return false;
+    }
+
+    env.objc.borrow_mut::<UITextFieldHostObject>(this).editing = true;
+    if env.objc.borrow::<UITextFieldHostObject>(this).clears_on_begin_editing {
+        let empty = ns_string::get_static_str(env, "");
+        () = msg![env; this setText:empty];
+    }
+    if let Some(window) = env.window.as_mut() {
+        window.start_text_input();
+    }
+
+    tell_delegate(env, this, "textFieldDidBeginEditing:");
+    send_actions(env, this, nil, UIControlEventEditingDidBegin);
+    post_notification(env, this, UITextFieldTextDidBeginEditingNotification);
+    () = msg![env; this setNeedsDisplay];
+    true
+}
+
+- (bool)resignFirstResponder {
+    if !msg![env; this isFirstResponder] {
+        return msg_super![env; this resignFirstResponder];
+    }
+    if !ask_delegate(env, this, "textFieldShouldEndEditing:") {
+        return false;
+    }
+    let resigned: bool = msg_super![env; this resignFirstResponder];
+    if !resigned {
+        return false;
+    }
+
+    env.objc.borrow_mut::<UITextFieldHostObject>(this).editing = false;
+    stop_text_input(env);
+
+    tell_delegate(env, this, "textFieldDidEndEditing:");
+    send_actions(env, this, nil, UIControlEventEditingDidEnd);
+    post_notification(env, this, UITextFieldTextDidEndEditingNotification);
+    () = msg![env; this setNeedsDisplay];
+    true
+}
+
+- (())touchesEnded:(id)touches // NSSet* of UITouch*
+         withEvent:(id)event { // UIEvent*
+    () = msg_super![env; this touchesEnded:touches withEvent:event];
+
+    // Tapping a text field starts editing.
+    let touch: id = msg![env; touches anyObject];
+    let location: CGPoint = msg![env; touch locationInView:this];
+    if msg![env; this pointInside:location withEvent:event] {
+        let _: bool = msg![env; this becomeFirstResponder];
+    }
 }
 
 // UITextInputTraits implementation
 - (())setAutocapitalizationType:(UITextAutocapitalizationType)_type {
-    // TODO
+    // The host keyboard takes care of this.
 }
 - (())setAutocorrectionType:(UITextAutocorrectionType)_type {
-    // TODO
-}
-- (())setReturnKeyType:(UIReturnKeyType)_type {
-    // TODO
+    // The host keyboard takes care of this.
 }
 - (())setKeyboardAppearance:(UIKeyboardAppearance)_appearance {
-    // TODO
+    // There's no on-screen keyboard to style.
+}
+- (UIKeyboardType)keyboardType {
+    env.objc.borrow::<UITextFieldHostObject>(this).keyboard_type
+}
+- (())setKeyboardType:(UIKeyboardType)type_ {
+    env.objc.borrow_mut::<UITextFieldHostObject>(this).keyboard_type = type_;
+}
+- (UIReturnKeyType)returnKeyType {
+    env.objc.borrow::<UITextFieldHostObject>(this).return_key_type
+}
+- (())setReturnKeyType:(UIReturnKeyType)type_ {
+    // This only changes the label of the return key, which the host keyboard
+    // doesn't have, but it's kept so the app can read it back.
+    env.objc.borrow_mut::<UITextFieldHostObject>(this).return_key_type = type_;
+}
+- (bool)enablesReturnKeyAutomatically {
+    env.objc.borrow::<UITextFieldHostObject>(this).enables_return_key_automatically
 }
-- (())setKeyboardType:(UIKeyboardType)_type {
-    // TODO
+- (())setEnablesReturnKeyAutomatically:(bool)enables {
+    env.objc.borrow_mut::<UITextFieldHostObject>(this).enables_return_key_automatically = enables;
+}
+- (bool)isSecureTextEntry {
+    env.objc.borrow::<UITextFieldHostObject>(this).secure_text_entry
+}
+- (())setSecureTextEntry:(bool)secure {
+    env.objc.borrow_mut::<UITextFieldHostObject>(this).secure_text_entry = secure;
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (())drawRect:(CGRect)_rect {
+    let bounds: CGRect = msg![env; this bounds];
+    let context = UIGraphicsGetCurrentContext(env);
+
+    let &UITextFieldHostObject {
+        text,
+        placeholder,
+        font,
+        text_color,
+        text_alignment,
+        border_style,
+        editing,
+        secure_text_entry,
+        ..
+    } = env.objc.borrow(this);
+
+    // Approximations of the real border styles.
+    let inset = match border_style {
+        UITextBorderStyleNone => 0.0,
+        UITextBorderStyleLine | UITextBorderStyleBezel | UITextBorderStyleRoundedRect => {
+            let (border, inset) = match border_style {
+                UITextBorderStyleLine => (0.0, 2.0),
+                UITextBorderStyleBezel => (0.4, 3.0),
+                _ => (0.7, 7.0),
+            };
+            CGContextSetRGBFillColor(env, context, border, border, border, 1.0);
+            CGContextFillRect(env, context, bounds);
+            let inner = CGRect {
+                origin: CGPoint {
+                    x: bounds.origin.x + 1.0,
+                    y: bounds.origin.y + 1.0,
+                },
+                size: CGSize {
+                    width: bounds.size.width - 2.0,
+                    height: bounds.size.height - 2.0,
+                },
+            };
+            CGContextSetRGBFillColor(env, context, 1.0, 1.0, 1.0, 1.0);
+            CGContextFillRect(env, context, inner);
+            inset
+        }
+        _ => {
+            log!("TODO: UITextBorderStyle {}", border_style);
+            0.0
+        }
+    };
+
+    let text_length: NSUInteger = if text == nil { 0 } else { msg![env; text length] };
+    let (display_text, is_placeholder) = if text_length == 0 {
+        (placeholder, true)
+    } else if secure_text_entry {
+        let bullets = "\u{2022}".repeat(text_length as usize);
+        (ns_string::from_rust_string(env, bullets), false)
+    } else {
+        (retain(env, text), false)
+    };
+
+    // Used for the caret height and when there's no text.
+    let reference_string = ns_string::get_static_str(env, "A");
+    let line_size: CGSize = msg![env; reference_string sizeWithFont:font];
+    let text_size: CGSize = if display_text == nil {
+        CGSize { width: 0.0, height: line_size.height }
+    } else {
+        msg![env; display_text sizeWithFont:font]
+    };
+
+    let available_width = bounds.size.width - inset * 2.0;
+    let x_offset = match text_alignment {
+        UITextAlignmentLeft => 0.0,
+        UITextAlignmentCenter => 0.5,
+        UITextAlignmentRight => 1.0,
+        _ => {
+            log!(
+                "TODO: Text alignment {} in UITextField, using left alignment",
+                text_alignment
+            );
+            0.0
+        }
+    };
+    // Text fields vertically center their text by default.
+    let origin = CGPoint {
+        x: bounds.origin.x + inset + x_offset * (available_width - text_size.width).max(0.0),
+        y: bounds.origin.y + (bounds.size.height - line_size.height) / 2.0,
+    };
+
+    if display_text != nil {
+        if is_placeholder {
+            CGContextSetRGBFillColor(env, context, 0.7, 0.7, 0.7, 1.0);
+        } else {
+            let (r, g, b, a) = ui_color::get_rgba(&env.objc, text_color);
+            CGContextSetRGBFillColor(env, context, r, g, b, a);
+        }
+        let _: CGSize = msg![env; display_text drawAtPoint:origin withFont:font];
+        if !is_placeholder {
+            release(env, display_text);
+        }
+    }
+
+    // The insertion point is always at the end of the text, since there's
+    // no way to move it.
+    if editing {
+        let caret_x = if is_placeholder {
+            origin.x
+        } else {
+            origin.x + text_size.width
+        };
+        let caret = CGRect {
+            origin: CGPoint { x: caret_x, y: origin.y },
+            size: CGSize { width: 2.0, height: line_size.height },
+        };
+        CGContextSetRGBFillColor(env, context, 0.26, 0.42, 0.95, 1.0);
+        CGContextFillRect(env, context, caret);
+    }
 }
 
 @end
 
 };
+
+fn delegate_responds_to(env: &mut Environment, this: id, sel_name: &str) -> Option<id> {
+    let delegate = env.objc.borrow::<UITextFieldHostObject>(this).delegate;
+    if delegate != nil
+        && env
+            .objc
+            .object_has_method_named(&env.mem, delegate, sel_name)
+    {
+        Some(delegate)
+    } else {
+        None
+    }
+}
+
+/// Send a `- (BOOL)textFieldShould...:(UITextField *)textField` message to the
+/// delegate, if it implements it. The default answer is yes.
+fn ask_delegate(env: &mut Environment, this: id, sel_name: &str) -> bool {
+    let Some(delegate) = delegate_responds_to(env, this, sel_name) else {
+        return true;
+    };
+    let sel = env.objc.lookup_selector(sel_name).unwrap();
+    crate::objc::msg_send(env, (delegate, sel, this))
+}
+
+/// Send a `- (void)textFieldDid...:(UITextField *)textField` message to the
+/// delegate, if it implements it.
+fn tell_delegate(env: &mut Environment, this: id, sel_name: &str) {
+    if let Some(delegate) = delegate_responds_to(env, this, sel_name) {
+        let sel = env.objc.lookup_selector(sel_name).unwrap();
+        () = crate::objc::msg_send(env, (delegate, sel, this));
+    }
+}
+
+fn post_notification(env: &mut Environment, this: id, name: &'static str) {
+    let name = ns_string::get_static_str(env, name);
+    let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+    () = msg![env; center postNotificationName:name object:this];
+}
+
+fn stop_text_input(env: &mut Environment) {
+    if let Some(window) = env.window.as_mut() {
+        window.stop_text_input();
+    }
+}
+
+/// Remove characters the text field's keyboard type wouldn't be able to type.
+fn filter_for_keyboard_type(keyboard_type: UIKeyboardType, text: &str) -> String {
+    text.chars()
+        .filter(|&c| match keyboard_type {
+            UIKeyboardTypeASCIICapable => c.is_ascii(),
+            UIKeyboardTypeNumberPad => c.is_ascii_digit(),
+            UIKeyboardTypePhonePad => c.is_ascii_digit() || "+*#".contains(c),
+            UIKeyboardTypeDecimalPad => c.is_ascii_digit() || c == '.',
+            _ => true,
+        })
+        .collect()
+}
+
+/// Replace the UTF-16 code units in `range` of `text` with `replacement`. The
+/// range is clamped to the length of `text`.
+fn splice_utf16(text: &str, range: NSRange, replacement: &str) -> String {
+    let mut units: Vec<u16> = text.encode_utf16().collect();
+    let start = (range.location as usize).min(units.len());
+    let end = start.saturating_add(range.length as usize).min(units.len());
+    units.splice(start..end, replacement.encode_utf16());
+    String::from_utf16_lossy(&units)
+}
+
+/// Replace the characters in `range` with `replacement`, if the delegate
+/// agrees, and send the appropriate notifications.
+fn replace_characters(env: &mut Environment, this: id, range: NSRange, replacement_str: &str) {
+    let replacement = ns_string::from_rust_string(env, replacement_str.to_string());
+
+    let should_change = match delegate_responds_to(
+        env,
+        this,
+        "textField:shouldChangeCharactersInRange:replacementString:",
+    ) {
+        Some(delegate) => msg![env; delegate textField:this
+                             shouldChangeCharactersInRange:range
+                                         replacementString:replacement],
+        None => true,
+    };
+    if should_change {
+        // The delegate might have changed the text, so it has to be fetched
+        // again.
+        let text: id = msg![env; this text];
+        let text = ns_string::to_rust_string(env, text);
+        let new_text = splice_utf16(&text, range, replacement_str);
+        let new_text = ns_string::from_rust_string(env, new_text);
+        () = msg![env; this setText:new_text];
+        release(env, new_text);
+        send_actions(env, this, nil, UIControlEventEditingChanged);
+        post_notification(env, this, UITextFieldTextDidChangeNotification);
+    }
+    release(env, replacement);
+}
+
+/// [crate::frameworks::uikit::handle_events] will forward text input events to
+/// this function.
+pub fn handle_text_input(env: &mut Environment, event: TextInputEvent) {
+    let this = first_responder(env);
+    let text_field_class: Class = msg_class![env; UITextField class];
+    if this == nil || !msg![env; this isKindOfClass:text_field_class] {
+        log_dbg!("No text field is being edited, ignoring {:?}", event);
+        return;
+    }
+
+    // UIKit creates and drains autorelease pools when handling events.
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    let text: id = msg![env; this text];
+    let length: NSUInteger = msg![env; text length];
+    match event {
+        TextInputEvent::Text(input) => {
+            let keyboard_type = env.objc.borrow::<UITextFieldHostObject>(this).keyboard_type;
+            let input = filter_for_keyboard_type(keyboard_type, &input);
+            if !input.is_empty() {
+                let range = NSRange {
+                    location: length,
+                    length: 0,
+                };
+                replace_characters(env, this, range, &input);
+            }
+        }
+        TextInputEvent::Backspace => {
+            if length > 0 {
+                // Delete a whole character, which may be a surrogate pair.
+                let last_char_len = ns_string::to_rust_string(env, text)
+                    .chars()
+                    .next_back()
+                    .unwrap()
+                    .len_utf16() as NSUInteger;
+                let range = NSRange {
+                    location: length - last_char_len,
+                    length: last_char_len,
+                };
+                replace_characters(env, this, range, "");
+            }
+        }
+        TextInputEvent::Return => {
+            let enables_automatically = env
+                .objc
+                .borrow::<UITextFieldHostObject>(this)
+                .enables_return_key_automatically;
+            if (length > 0 || !enables_automatically)
+                && ask_delegate(env, this, "textFieldShouldReturn:")
+            {
+                // Connecting an action to this event makes the keyboard go
+                // away automatically.
+                let has_exit_action = env
+                    .objc
+                    .borrow::<UIControlHostObject>(this)
+                    .action_targets
+                    .iter()
+                    .any(|&(_, _, events)| events & UIControlEventEditingDidEndOnExit != 0);
+                send_actions(env, this, nil, UIControlEventEditingDidEndOnExit);
+                if has_exit_action {
+                    let _: bool = msg![env; this resignFirstResponder];
+                }
+            }
+        }
+    }
+
+    release(env, pool);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(location: NSUInteger, length: NSUInteger) -> NSRange {
+        NSRange { location, length }
+    }
+
+    #[test]
+    fn splice() {
+        assert_eq!(splice_utf16("hello", range(5, 0), "!"), "hello!");
+        assert_eq!(splice_utf16("hello", range(1, 3), "ipp"), "hippo");
+        assert_eq!(splice_utf16("h\u{1F600}", range(1, 2), ""), "h");
+    }
+
+    #[test]
+    fn splice_out_of_bounds() {
+        // E.g. if the delegate shortened the text.
+        assert_eq!(splice_utf16("hi", range(5, 0), "!"), "hi!");
+        assert_eq!(splice_utf16("hello", range(3, 10), ""), "hel");
+        assert_eq!(
+            splice_utf16("hi", range(NSUInteger::MAX, NSUInteger::MAX), "!"),
+            "hi!"
+        );
+    }
+
+    #[test]
+    fn keyboard_type_filter() {
+        assert_eq!(
+            filter_for_keyboard_type(UIKeyboardTypeDefault, "a1é"),
+            "a1é"
+        );
+        assert_eq!(
+            filter_for_keyboard_type(UIKeyboardTypeASCIICapable, "a1é"),
+            "a1"
+        );
+        assert_eq!(
+            filter_for_keyboard_type(UIKeyboardTypeNumberPad, "a1.2"),
+            "12"
+        );
+        assert_eq!(
+            filter_for_keyboard_type(UIKeyboardTypeDecimalPad, "a1.2"),
+            "1.2"
+        );
+        assert_eq!(
+            filter_for_keyboard_type(UIKeyboardTypePhonePad, "+1 (2)#"),
+            "+12#"
+        );
+    }
+}
@@ -0,0 +1,726 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UINavigationBar` and `UINavigationItem`.
+//!
+//! The bar is drawn in the default iPhone OS style: a gradient background, the
+//! top item's title in the middle, its bar button items on either side, and a
+//! back button for the previous item if there's no left item. Buttons are
+//! plain rectangles, as rounded corners and the back arrow shape aren't
+//! supported yet.
+
+use super::drawing::fill_vertical_gradient;
+use super::ui_control::ui_button::UIButtonTypeCustom;
+use super::ui_control::{UIControlEventTouchUpInside, UIControlStateNormal};
+use crate::frameworks::core_graphics::cg_context::{
+    CGContextFillRect, CGContextRef, CGContextSetRGBFillColor,
+};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::{ns_array, NSInteger, NSUInteger};
+use crate::frameworks::uikit::ui_bar_item::ui_bar_button_item::{
+    self, UIBarButtonItemStyle, UIBarButtonItemStyleBordered, UIBarButtonItemStyleDone,
+};
+use crate::frameworks::uikit::ui_color;
+use crate::frameworks::uikit::ui_font::UITextAlignmentCenter;
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::objc::{
+    autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes,
+    release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+
+pub type UIBarStyle = NSInteger;
+pub const UIBarStyleDefault: UIBarStyle = 0;
+pub const UIBarStyleBlack: UIBarStyle = 1;
+
+/// The standard height of a navigation bar in portrait orientation.
+pub const NAVIGATION_BAR_HEIGHT: CGFloat = 44.0;
+
+const BUTTON_HEIGHT: CGFloat = 30.0;
+const BUTTON_MARGIN: CGFloat = 5.0;
+const BUTTON_PADDING: CGFloat = 10.0;
+const BUTTON_MIN_WIDTH: CGFloat = 40.0;
+
+pub struct UINavigationBarHostObject {
+    superclass: super::UIViewHostObject,
+    /// `UINavigationItem*`s, bottom to top. Strong references.
+    items: Vec<id>,
+    /// Weak reference.
+    delegate: id,
+    bar_style: UIBarStyle,
+    /// `UIColor*`, strong reference.
+    tint_color: id,
+    /// Subviews that display the top item. Weak references (the bar owns them
+    /// by virtue of being their superview).
+    item_views: Vec<id>,
+    /// Buttons created for bar button items, and which item each is for. [nil]
+    /// is used for the back button. Weak references.
+    buttons: Vec<(id, id)>,
+}
+impl_HostObject_with_superclass!(UINavigationBarHostObject);
+impl Default for UINavigationBarHostObject {
+    fn default() -> Self {
+        UINavigationBarHostObject {
+            superclass: Default::default(),
+            items: Vec::new(),
+            delegate: nil,
+            bar_style: UIBarStyleDefault,
+            tint_color: nil,
+            item_views: Vec::new(),
+            buttons: Vec::new(),
+        }
+    }
+}
+
+pub struct UINavigationItemHostObject {
+    /// `NSString*`
+    title: id,
+    /// `UIBarButtonItem*`
+    back_bar_button_item: id,
+    /// `UIBarButtonItem*`
+    left_bar_button_item: id,
+    /// `UIBarButtonItem*`
+    right_bar_button_item: id,
+    /// `UIView*`
+    title_view: id,
+    hides_back_button: bool,
+    /// The bar this item is on, if any. Weak reference.
+    navigation_bar: id,
+}
+impl HostObject for UINavigationItemHostObject {}
+impl Default for UINavigationItemHostObject {
+    fn default() -> Self {
+        UINavigationItemHostObject {
+            title: nil,
+            back_bar_button_item: nil,
+            left_bar_button_item: nil,
+            right_bar_button_item: nil,
+            title_view: nil,
+            hides_back_button: false,
+            navigation_bar: nil,
+        }
+    }
+}
+
+/// Get the base color of the bar, which the background gradient and buttons
+/// are derived from.
+fn base_color(env: &mut Environment, bar: id) -> (CGFloat, CGFloat, CGFloat) {
+    let &UINavigationBarHostObject {
+        bar_style,
+        tint_color,
+        ..
+    } = env.objc.borrow(bar);
+    if tint_color != nil {
+        let (r, g, b, _a) = ui_color::get_rgba(&env.objc, tint_color);
+        (r, g, b)
+    } else if bar_style == UIBarStyleDefault {
+        (0.43, 0.52, 0.64)
+    } else {
+        (0.05, 0.05, 0.05)
+    }
+}
+
+/// Create a button on the bar and return its width. The button is positioned
+/// `x` points from the left edge, or from the right edge if `from_right` is
+/// set.
+fn add_button(
+    env: &mut Environment,
+    bar: id,
+    item: id, // UIBarButtonItem* or nil for the back button
+    title: id,
+    style: UIBarButtonItemStyle,
+    x: CGFloat,
+    from_right: bool,
+) -> CGFloat {
+    let bounds: CGRect = msg![env; bar bounds];
+
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:(12.0 as CGFloat)];
+    let text_size: CGSize = if title != nil {
+        msg![env; title sizeWithFont:font]
+    } else {
+        CGSize::default()
+    };
+    let width = (text_size.width + BUTTON_PADDING * 2.0).max(BUTTON_MIN_WIDTH);
+    let x = if from_right {
+        bounds.size.width - x - width
+    } else {
+        x
+    };
+    let frame = CGRect {
+        origin: CGPoint {
+            x,
+            y: (NAVIGATION_BAR_HEIGHT - BUTTON_HEIGHT) / 2.0,
+        },
+        size: CGSize {
+            width,
+            height: BUTTON_HEIGHT,
+        },
+    };
+
+    let (r, g, b) = base_color(env, bar);
+    let (r, g, b) = if style == UIBarButtonItemStyleDone {
+        (0.2, 0.4, 0.8)
+    } else {
+        (r * 0.85, g * 0.85, b * 0.85)
+    };
+    let bg_color: id =
+        msg_class![env; UIColor colorWithRed:r green:g blue:b alpha:(1.0 as CGFloat)];
+
+    let button: id = msg_class![env; UIButton buttonWithType:UIButtonTypeCustom];
+    () = msg![env; button setTitle:title forState:UIControlStateNormal];
+    () = msg![env; button setBackgroundColor:bg_color];
+    let label: id = msg![env; button titleLabel];
+    () = msg![env; label setFont:font];
+    () = msg![env; button setFrame:frame];
+    // FIXME: manually calling layoutSubviews shouldn't be needed?
+    () = msg![env; button layoutSubviews];
+    let selector = env.objc.lookup_selector("_touchHLE_buttonTapped:").unwrap();
+    () = msg![env; button addTarget:bar
+                             action:selector
+                   forControlEvents:UIControlEventTouchUpInside];
+    () = msg![env; bar addSubview:button];
+
+    let host_obj = env.objc.borrow_mut::<UINavigationBarHostObject>(bar);
+    host_obj.item_views.push(button);
+    host_obj.buttons.push((button, item));
+
+    width
+}
+
+/// Add the view for a bar button item and return its width.
+fn add_bar_button_item(
+    env: &mut Environment,
+    bar: id,
+    item: id, // UIBarButtonItem*
+    x: CGFloat,
+    from_right: bool,
+) -> CGFloat {
+    let custom_view: id = msg![env; item customView];
+    if custom_view == nil {
+        let title: id = msg![env; item title];
+        let style: UIBarButtonItemStyle = msg![env; item style];
+        return add_button(env, bar, item, title, style, x, from_right);
+    }
+
+    // Custom views keep their size, but are centered vertically.
+    let bounds: CGRect = msg![env; bar bounds];
+    let mut frame: CGRect = msg![env; custom_view frame];
+    frame.origin.x = if from_right {
+        bounds.size.width - x - frame.size.width
+    } else {
+        x
+    };
+    frame.origin.y = (NAVIGATION_BAR_HEIGHT - frame.size.height) / 2.0;
+    () = msg![env; custom_view setFrame:frame];
+    () = msg![env; bar addSubview:custom_view];
+    env.objc
+        .borrow_mut::<UINavigationBarHostObject>(bar)
+        .item_views
+        .push(custom_view);
+    frame.size.width
+}
+
+/// Recreate the subviews that display the top item.
+fn update(env: &mut Environment, this: id) {
+    let host_obj = env.objc.borrow_mut::<UINavigationBarHostObject>(this);
+    let old_views = std::mem::take(&mut host_obj.item_views);
+    host_obj.buttons.clear();
+    let items = host_obj.items.clone();
+    for view in old_views {
+        () = msg![env; view removeFromSuperview];
+    }
+
+    () = msg![env; this setNeedsDisplay];
+
+    let Some(&top_item) = items.last() else {
+        return;
+    };
+    let &UINavigationItemHostObject {
+        title,
+        left_bar_button_item,
+        right_bar_button_item,
+        title_view,
+        hides_back_button,
+        ..
+    } = env.objc.borrow(top_item);
+
+    let left_width = if left_bar_button_item != nil {
+        add_bar_button_item(env, this, left_bar_button_item, BUTTON_MARGIN, false)
+    } else if items.len() >= 2 && !hides_back_button {
+        // The back button shows the title of the previous item, or of its
+        // back bar button item if it has one.
+        let back_item = items[items.len() - 2];
+        let &UINavigationItemHostObject {
+            title: back_title,
+            back_bar_button_item,
+            ..
+        } = env.objc.borrow(back_item);
+        let back_title = if back_bar_button_item != nil {
+            msg![env; back_bar_button_item title]
+        } else if back_title != nil {
+            back_title
+        } else {
+            get_static_str(env, "Back")
+        };
+        add_button(
+            env,
+            this,
+            nil,
+            back_title,
+            UIBarButtonItemStyleBordered,
+            BUTTON_MARGIN,
+            false,
+        )
+    } else {
+        0.0
+    };
+    let right_width = if right_bar_button_item != nil {
+        add_bar_button_item(env, this, right_bar_button_item, BUTTON_MARGIN, true)
+    } else {
+        0.0
+    };
+
+    // The title is centered on the bar, so it gets the same amount of space on
+    // both sides.
+    let bounds: CGRect = msg![env; this bounds];
+    let side_width = left_width.max(right_width) + BUTTON_MARGIN * 2.0;
+    if title_view != nil {
+        let mut frame: CGRect = msg![env; title_view frame];
+        frame.origin.x = (bounds.size.width - frame.size.width) / 2.0;
+        frame.origin.y = (NAVIGATION_BAR_HEIGHT - frame.size.height) / 2.0;
+        () = msg![env; title_view setFrame:frame];
+        () = msg![env; this addSubview:title_view];
+        env.objc
+            .borrow_mut::<UINavigationBarHostObject>(this)
+            .item_views
+            .push(title_view);
+    } else if title != nil {
+        let frame = CGRect {
+            origin: CGPoint {
+                x: side_width,
+                y: 0.0,
+            },
+            size: CGSize {
+                width: (bounds.size.width - side_width * 2.0).max(0.0),
+                height: NAVIGATION_BAR_HEIGHT,
+            },
+        };
+        let label: id = msg_class![env; UILabel alloc];
+        let label: id = msg![env; label initWithFrame:frame];
+        let font: id = msg_class![env; UIFont boldSystemFontOfSize:(20.0 as CGFloat)];
+        () = msg![env; label setFont:font];
+        let text_color: id = msg_class![env; UIColor whiteColor];
+        () = msg![env; label setTextColor:text_color];
+        () = msg![env; label setTextAlignment:UITextAlignmentCenter];
+        () = msg![env; label setText:title];
+        () = msg![env; this addSubview:label];
+        release(env, label);
+        env.objc
+            .borrow_mut::<UINavigationBarHostObject>(this)
+            .item_views
+            .push(label);
+    }
+}
+
+/// Set or clear the bar an item is on.
+fn set_item_bar(env: &mut Environment, item: id, bar: id) {
+    env.objc
+        .borrow_mut::<UINavigationItemHostObject>(item)
+        .navigation_bar = bar;
+}
+
+/// For use by `UINavigationItem` setters: redisplay the bar the item is on.
+fn update_item_bar(env: &mut Environment, item: id) {
+    let bar = env
+        .objc
+        .borrow::<UINavigationItemHostObject>(item)
+        .navigation_bar;
+    if bar != nil {
+        update(env, bar);
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UINavigationBar: UIView
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UINavigationBarHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    update(env, this);
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UIBarStyle");
+    let bar_style: UIBarStyle = msg![env; coder decodeIntegerForKey:key_ns_string];
+    () = msg![env; this setBarStyle:bar_style];
+
+    let key_ns_string = get_static_str(env, "UITintColor");
+    let tint_color: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setTintColor:tint_color];
+
+    let key_ns_string = get_static_str(env, "UIItems");
+    let items: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    if items != nil {
+        () = msg![env; this setItems:items];
+    }
+
+    this
+}
+
+- (())dealloc {
+    let host_obj = env.objc.borrow_mut::<UINavigationBarHostObject>(this);
+    let items = std::mem::take(&mut host_obj.items);
+    let tint_color = host_obj.tint_color;
+    for item in items {
+        set_item_bar(env, item, nil);
+        release(env, item);
+    }
+    release(env, tint_color);
+    msg_super![env; this dealloc]
+}
+
+- (())layoutSubviews {
+    update(env, this);
+}
+
+- (())drawRect:(CGRect)_rect {
+    let bounds: CGRect = msg![env; this bounds];
+    let context = UIGraphicsGetCurrentContext(env);
+
+    let (r, g, b) = base_color(env, this);
+    let lighten = |c: CGFloat| c + (1.0 - c) * 0.4;
+    fill_vertical_gradient(
+        env,
+        context,
+        bounds,
+        (lighten(r), lighten(g), lighten(b)),
+        (r, g, b),
+    );
+
+    // Dark line along the bottom edge
+    CGContextSetRGBFillColor(env, context, r * 0.4, g * 0.4, b * 0.4, 1.0);
+    let line = CGRect {
+        origin: CGPoint {
+            x: bounds.origin.x,
+            y: bounds.origin.y + bounds.size.height - 1.0,
+        },
+        size: CGSize {
+            width: bounds.size.width,
+            height: 1.0,
+        },
+    };
+    CGContextFillRect(env, context, line);
+}
+
+- (CGSize)sizeThatFits:(CGSize)size {
+    CGSize {
+        width: size.width,
+        height: NAVIGATION_BAR_HEIGHT,
+    }
+}
+
+- (id)delegate {
+    env.objc.borrow::<UINavigationBarHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate { // id<UINavigationBarDelegate>
+    // The delegate is a *weak* reference!
+    env.objc.borrow_mut::<UINavigationBarHostObject>(this).delegate = delegate;
+}
+
+- (UIBarStyle)barStyle {
+    env.objc.borrow::<UINavigationBarHostObject>(this).bar_style
+}
+- (())setBarStyle:(UIBarStyle)bar_style {
+    env.objc.borrow_mut::<UINavigationBarHostObject>(this).bar_style = bar_style;
+    update(env, this);
+}
+
+- (id)tintColor {
+    env.objc.borrow::<UINavigationBarHostObject>(this).tint_color
+}
+- (())setTintColor:(id)tint_color { // UIColor*
+    retain(env, tint_color);
+    let host_obj = env.objc.borrow_mut::<UINavigationBarHostObject>(this);
+    let old_color = std::mem::replace(&mut host_obj.tint_color, tint_color);
+    release(env, old_color);
+    update(env, this);
+}
+
+- (id)items {
+    let items = env.objc.borrow::<UINavigationBarHostObject>(this).items.clone();
+    for &item in &items {
+        retain(env, item);
+    }
+    let array = ns_array::from_vec(env, items);
+    autorelease(env, array)
+}
+- (())setItems:(id)items { // NSArray* of UINavigationItem*
+    () = msg![env; this setItems:items animated:false];
+}
+- (())setItems:(id)items // NSArray* of UINavigationItem*
+      animated:(bool)_animated {
+    // TODO: animation
+    let count: NSUInteger = msg![env; items count];
+    let mut new_items = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let item: id = msg![env; items objectAtIndex:i];
+        retain(env, item);
+        set_item_bar(env, item, this);
+        new_items.push(item);
+    }
+    let old_items = std::mem::replace(
+        &mut env.objc.borrow_mut::<UINavigationBarHostObject>(this).items,
+        new_items.clone(),
+    );
+    for item in old_items {
+        if !new_items.contains(&item) {
+            set_item_bar(env, item, nil);
+        }
+        release(env, item);
+    }
+    update(env, this);
+}
+
+- (id)topItem {
+    let items = &env.objc.borrow::<UINavigationBarHostObject>(this).items;
+    items.last().copied().unwrap_or(nil)
+}
+- (id)backItem {
+    let items = &env.objc.borrow::<UINavigationBarHostObject>(this).items;
+    if items.len() >= 2 {
+        items[items.len() - 2]
+    } else {
+        nil
+    }
+}
+
+- (())pushNavigationItem:(id)item // UINavigationItem*
+                animated:(bool)_animated {
+    // TODO: animation
+    retain(env, item);
+    set_item_bar(env, item, this);
+    env.objc.borrow_mut::<UINavigationBarHostObject>(this).items.push(item);
+    update(env, this);
+
+    let delegate = env.objc.borrow::<UINavigationBarHostObject>(this).delegate;
+    if delegate != nil && env.objc.object_has_method_named(
+        &env.mem,
+        delegate,
+        "navigationBar:didPushItem:",
+    ) {
+        () = msg![env; delegate navigationBar:this didPushItem:item];
+    }
+}
+
+- (id)popNavigationItemAnimated:(bool)_animated {
+    // TODO: animation
+    let Some(item) = env.objc.borrow_mut::<UINavigationBarHostObject>(this).items.pop() else {
+        return nil;
+    };
+    set_item_bar(env, item, nil);
+    update(env, this);
+
+    let delegate = env.objc.borrow::<UINavigationBarHostObject>(this).delegate;
+    if delegate != nil && env.objc.object_has_method_named(
+        &env.mem,
+        delegate,
+        "navigationBar:didPopItem:",
+    ) {
+        () = msg![env; delegate navigationBar:this didPopItem:item];
+    }
+
+    autorelease(env, item)
+}
+
+// Internal method, target of the bar's buttons.
+- (())_touchHLE_buttonTapped:(id)button { // UIButton*
+    let buttons = &env.objc.borrow::<UINavigationBarHostObject>(this).buttons;
+    let Some(&(_, item)) = buttons.iter().find(|&&(b, _)| b == button) else {
+        return;
+    };
+    // The button may be removed from the bar by the code below, but the
+    // caller is still using it.
+    retain(env, button);
+    autorelease(env, button);
+
+    if item != nil {
+        ui_bar_button_item::send_action(env, item);
+        return;
+    }
+
+    // This is the back button. The delegate (usually a navigation controller)
+    // gets a chance to handle the pop itself.
+    let &UINavigationBarHostObject { ref items, delegate, .. } = env.objc.borrow(this);
+    let top_item = items.last().copied().unwrap();
+    if delegate != nil && env.objc.object_has_method_named(
+        &env.mem,
+        delegate,
+        "navigationBar:shouldPopItem:",
+    ) {
+        let should_pop: bool = msg![env; delegate navigationBar:this shouldPopItem:top_item];
+        if !should_pop {
+            return;
+        }
+    }
+    let _: id = msg![env; this popNavigationItemAnimated:true];
+}
+
+@end
+
+@implementation UINavigationItem: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UINavigationItemHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithTitle:(id)title { // NSString*
+    () = msg![env; this setTitle:title];
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let key_ns_string = get_static_str(env, "UITitle");
+    let title: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setTitle:title];
+
+    let key_ns_string = get_static_str(env, "UIBackBarButtonItem");
+    let item: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setBackBarButtonItem:item];
+
+    let key_ns_string = get_static_str(env, "UILeftBarButtonItem");
+    let item: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setLeftBarButtonItem:item];
+
+    let key_ns_string = get_static_str(env, "UIRightBarButtonItem");
+    let item: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setRightBarButtonItem:item];
+
+    let key_ns_string = get_static_str(env, "UITitleView");
+    let title_view: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setTitleView:title_view];
+
+    let key_ns_string = get_static_str(env, "UIHidesBackButton");
+    let hides_back_button: bool = msg![env; coder decodeBoolForKey:key_ns_string];
+    () = msg![env; this setHidesBackButton:hides_back_button];
+
+    this
+}
+
+- (())dealloc {
+    let &UINavigationItemHostObject {
+        title,
+        back_bar_button_item,
+        left_bar_button_item,
+        right_bar_button_item,
+        title_view,
+        ..
+    } = env.objc.borrow(this);
+    release(env, title);
+    release(env, back_bar_button_item);
+    release(env, left_bar_button_item);
+    release(env, right_bar_button_item);
+    release(env, title_view);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)title {
+    env.objc.borrow::<UINavigationItemHostObject>(this).title
+}
+- (())setTitle:(id)title { // NSString*
+    let title: id = msg![env; title copy];
+    let host_obj = env.objc.borrow_mut::<UINavigationItemHostObject>(this);
+    let old_title = std::mem::replace(&mut host_obj.title, title);
+    release(env, old_title);
+    update_item_bar(env, this);
+}
+
+- (id)backBarButtonItem {
+    env.objc.borrow::<UINavigationItemHostObject>(this).back_bar_button_item
+}
+- (())setBackBarButtonItem:(id)item { // UIBarButtonItem*
+    retain(env, item);
+    let host_obj = env.objc.borrow_mut::<UINavigationItemHostObject>(this);
+    let old_item = std::mem::replace(&mut host_obj.back_bar_button_item, item);
+    release(env, old_item);
+    update_item_bar(env, this);
+}
+
+- (id)leftBarButtonItem {
+    env.objc.borrow::<UINavigationItemHostObject>(this).left_bar_button_item
+}
+- (())setLeftBarButtonItem:(id)item { // UIBarButtonItem*
+    retain(env, item);
+    let host_obj = env.objc.borrow_mut::<UINavigationItemHostObject>(this);
+    let old_item = std::mem::replace(&mut host_obj.left_bar_button_item, item);
+    release(env, old_item);
+    update_item_bar(env, this);
+}
+- (())setLeftBarButtonItem:(id)item // UIBarButtonItem*
+                  animated:(bool)_animated {
+    // TODO: animation
+    () = msg![env; this setLeftBarButtonItem:item];
+}
+
+- (id)rightBarButtonItem {
+    env.objc.borrow::<UINavigationItemHostObject>(this).right_bar_button_item
+}
+- (())setRightBarButtonItem:(id)item { // UIBarButtonItem*
+    retain(env, item);
+    let host_obj = env.objc.borrow_mut::<UINavigationItemHostObject>(this);
+    let old_item = std::mem::replace(&mut host_obj.right_bar_button_item, item);
+    release(env, old_item);
+    update_item_bar(env, this);
+}
+- (())setRightBarButtonItem:(id)item // UIBarButtonItem*
+                   animated:(bool)_animated {
+    // TODO: animation
+    () = msg![env; this setRightBarButtonItem:item];
+}
+
+- (id)titleView {
+    env.objc.borrow::<UINavigationItemHostObject>(this).title_view
+}
+- (())setTitleView:(id)title_view { // UIView*
+    retain(env, title_view);
+    let host_obj = env.objc.borrow_mut::<UINavigationItemHostObject>(this);
+    let old_view = std::mem::replace(&mut host_obj.title_view, title_view);
+    // The old view might still be displayed on the bar.
+    if old_view != nil {
+        () = msg![env; old_view removeFromSuperview];
+    }
+    release(env, old_view);
+    update_item_bar(env, this);
+}
+
+- (bool)hidesBackButton {
+    env.objc.borrow::<UINavigationItemHostObject>(this).hides_back_button
+}
+- (())setHidesBackButton:(bool)hides_back_button {
+    env.objc.borrow_mut::<UINavigationItemHostObject>(this).hides_back_button = hides_back_button;
+    update_item_bar(env, this);
+}
+- (())setHidesBackButton:(bool)hides_back_button
+                animated:(bool)_animated {
+    // TODO: animation
+    () = msg![env; this setHidesBackButton:hides_back_button];
+}
+
+@end
+
+};
@@ -0,0 +1,155 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UIProgressView`.
+//!
+//! The bar is drawn in an approximation of the iPhone OS 2/3 styles: blue
+//! progress on a white track for the default style, and a gray track for the
+//! bar style. Rounded corners aren't supported yet.
+
+use super::drawing::fill_vertical_gradient;
+use crate::frameworks::core_graphics::cg_context::{CGContextFillRect, CGContextSetRGBFillColor};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::NSInteger;
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_class, msg_super, objc_classes, ClassExports,
+    NSZonePtr,
+};
+
+type UIProgressViewStyle = NSInteger;
+const UIProgressViewStyleDefault: UIProgressViewStyle = 0;
+const UIProgressViewStyleBar: UIProgressViewStyle = 1;
+
+/// The height the bar is drawn at, regardless of the view's height.
+const BAR_HEIGHT: CGFloat = 9.0;
+
+pub struct UIProgressViewHostObject {
+    superclass: super::UIViewHostObject,
+    progress: f32,
+    style: UIProgressViewStyle,
+}
+impl_HostObject_with_superclass!(UIProgressViewHostObject);
+impl Default for UIProgressViewHostObject {
+    fn default() -> Self {
+        UIProgressViewHostObject {
+            superclass: Default::default(),
+            progress: 0.0,
+            style: UIProgressViewStyleDefault,
+        }
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UIProgressView: UIView
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UIProgressViewHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    () = msg![env; this setOpaque:false];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; this setBackgroundColor:bg_color];
+    this
+}
+
+- (id)initWithProgressViewStyle:(UIProgressViewStyle)style {
+    let this: id = msg![env; this initWithFrame:(<CGRect as Default>::default())];
+    () = msg![env; this setProgressViewStyle:style];
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UIProgressViewStyle");
+    let style: UIProgressViewStyle = msg![env; coder decodeIntegerForKey:key_ns_string];
+    () = msg![env; this setProgressViewStyle:style];
+    let key_ns_string = get_static_str(env, "UIProgress");
+    let progress: f32 = msg![env; coder decodeFloatForKey:key_ns_string];
+    () = msg![env; this setProgress:progress];
+
+    this
+}
+
+- (f32)progress {
+    env.objc.borrow::<UIProgressViewHostObject>(this).progress
+}
+- (())setProgress:(f32)progress {
+    env.objc.borrow_mut::<UIProgressViewHostObject>(this).progress = progress.clamp(0.0, 1.0);
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (UIProgressViewStyle)progressViewStyle {
+    env.objc.borrow::<UIProgressViewHostObject>(this).style
+}
+- (())setProgressViewStyle:(UIProgressViewStyle)style {
+    if style != UIProgressViewStyleDefault && style != UIProgressViewStyleBar {
+        log!("TODO: UIProgressViewStyle {}", style);
+    }
+    env.objc.borrow_mut::<UIProgressViewHostObject>(this).style = style;
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (CGSize)sizeThatFits:(CGSize)size {
+    CGSize {
+        width: size.width,
+        height: BAR_HEIGHT,
+    }
+}
+
+- (())drawRect:(CGRect)_rect {
+    let bounds: CGRect = msg![env; this bounds];
+    let &UIProgressViewHostObject { progress, style, .. } = env.objc.borrow(this);
+    let context = UIGraphicsGetCurrentContext(env);
+
+    let track = CGRect {
+        origin: CGPoint {
+            x: bounds.origin.x,
+            y: bounds.origin.y + ((bounds.size.height - BAR_HEIGHT) / 2.0).round(),
+        },
+        size: CGSize {
+            width: bounds.size.width,
+            height: BAR_HEIGHT,
+        },
+    };
+    CGContextSetRGBFillColor(env, context, 0.4, 0.4, 0.4, 1.0);
+    CGContextFillRect(env, context, track);
+
+    let inner = CGRect {
+        origin: CGPoint {
+            x: track.origin.x + 1.0,
+            y: track.origin.y + 1.0,
+        },
+        size: CGSize {
+            width: (track.size.width - 2.0).max(0.0),
+            height: track.size.height - 2.0,
+        },
+    };
+    let (track_top, track_bottom) = if style == UIProgressViewStyleBar {
+        ((0.3, 0.3, 0.3), (0.5, 0.5, 0.5))
+    } else {
+        ((0.85, 0.85, 0.85), (1.0, 1.0, 1.0))
+    };
+    fill_vertical_gradient(env, context, inner, track_top, track_bottom);
+
+    let mut filled = inner;
+    filled.size.width = (inner.size.width * progress).round();
+    if filled.size.width > 0.0 {
+        fill_vertical_gradient(env, context, filled, (0.45, 0.65, 1.0), (0.15, 0.4, 0.9));
+    }
+}
+
+@end
+
+};
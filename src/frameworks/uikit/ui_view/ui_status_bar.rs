@@ -0,0 +1,351 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The simulated status bar.
+//!
+//! On the real iPhone OS the status bar belongs to the system, not the app,
+//! but the app controls its visibility, style and orientation via
+//! `UIApplication`. touchHLE draws it using a view that isn't in any window,
+//! whose layer is composited on top of the app's top window (see
+//! [crate::frameworks::core_animation::composition]).
+//!
+//! The carrier name and battery are fake. The clock shows the host's time.
+
+use super::animation::{animate_value_now, Value};
+use super::drawing::fill_vertical_gradient;
+use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
+use crate::frameworks::core_graphics::cg_context::{
+    CGContextFillRect, CGContextRef, CGContextSetRGBFillColor,
+};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::{from_rust_string, get_static_str};
+use crate::frameworks::foundation::NSTimeInterval;
+use crate::frameworks::uikit::ui_application::{
+    UIStatusBarStyle, UIStatusBarStyleBlackOpaque, UIStatusBarStyleBlackTranslucent,
+};
+use crate::frameworks::uikit::ui_device::{
+    UIDeviceOrientation, UIDeviceOrientationLandscapeLeft, UIDeviceOrientationLandscapeRight,
+};
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::libc::time::host_utc_offset;
+use crate::objc::{id, msg, msg_class, objc_classes, release, ClassExports};
+use crate::Environment;
+use std::f32::consts::FRAC_PI_2;
+use std::time::SystemTime;
+
+pub const STATUS_BAR_HEIGHT: CGFloat = 20.0;
+
+/// How long the status bar takes to fade in or out, or to rotate, when the app
+/// asks for that to be animated.
+const ANIMATION_DURATION: NSTimeInterval = 0.3;
+
+#[derive(Default)]
+pub struct State {
+    /// The `UIStatusBar` instance, created when it's first needed.
+    view: Option<id>,
+    /// The style, orientation and clock text the status bar was last laid out
+    /// and drawn for.
+    drawn: Option<(UIStatusBarStyle, UIDeviceOrientation, String)>,
+    /// Whether the next change of layout should be animated, see
+    /// [animate_next_layout].
+    animate_next_layout: bool,
+}
+
+/// For use by `UIApplication`: show or hide the status bar. If `animated` is
+/// set, it fades in or out.
+pub fn set_status_bar_hidden(env: &mut Environment, hidden: bool, animated: bool) {
+    env.framework_state.uikit.ui_application.status_bar_hidden = hidden;
+    // If the status bar hasn't been drawn yet, there is nothing to fade.
+    let Some(view) = env.framework_state.uikit.ui_view.ui_status_bar.view else {
+        return;
+    };
+    let alpha = if hidden { 0.0 } else { 1.0 };
+    let duration = if animated { ANIMATION_DURATION } else { 0.0 };
+    animate_value_now(env, view, Value::Alpha(alpha), duration);
+}
+
+/// For use by `UIApplication`: animate the status bar into its new place the
+/// next time it is laid out, e.g. after its orientation changes.
+pub fn animate_next_layout(env: &mut Environment) {
+    env.framework_state
+        .uikit
+        .ui_view
+        .ui_status_bar
+        .animate_next_layout = true;
+}
+
+/// The size of the status bar, its center on the screen and its rotation, for
+/// an interface orientation.
+fn placement(orientation: UIDeviceOrientation, screen_size: CGSize) -> (CGSize, CGPoint, CGFloat) {
+    let CGSize { width, height } = screen_size;
+    // The status bar is along the top of the rotated interface, which is one
+    // of the long edges of the screen in landscape.
+    match orientation {
+        UIDeviceOrientationLandscapeLeft => (
+            CGSize {
+                width: height,
+                height: STATUS_BAR_HEIGHT,
+            },
+            CGPoint {
+                x: width - STATUS_BAR_HEIGHT / 2.0,
+                y: height / 2.0,
+            },
+            FRAC_PI_2,
+        ),
+        UIDeviceOrientationLandscapeRight => (
+            CGSize {
+                width: height,
+                height: STATUS_BAR_HEIGHT,
+            },
+            CGPoint {
+                x: STATUS_BAR_HEIGHT / 2.0,
+                y: height / 2.0,
+            },
+            -FRAC_PI_2,
+        ),
+        _ => (
+            CGSize {
+                width,
+                height: STATUS_BAR_HEIGHT,
+            },
+            CGPoint {
+                x: width / 2.0,
+                y: STATUS_BAR_HEIGHT / 2.0,
+            },
+            0.0,
+        ),
+    }
+}
+
+/// For use by `UIApplication`: the frame of the status bar in screen
+/// co-ordinates, or an empty rectangle if the app has hidden it.
+pub fn status_bar_frame(env: &mut Environment) -> CGRect {
+    if env.framework_state.uikit.ui_application.status_bar_hidden {
+        return CGRect::default();
+    }
+    let screen: id = msg_class![env; UIScreen mainScreen];
+    let screen_bounds: CGRect = msg![env; screen bounds];
+    let app: id = msg_class![env; UIApplication sharedApplication];
+    let orientation: UIDeviceOrientation = msg![env; app statusBarOrientation];
+    let (size, center, angle) = placement(orientation, screen_bounds.size);
+    let size = if angle == 0.0 {
+        size
+    } else {
+        CGSize {
+            width: size.height,
+            height: size.width,
+        }
+    };
+    CGRect {
+        origin: CGPoint {
+            x: center.x - size.width / 2.0,
+            y: center.y - size.height / 2.0,
+        },
+        size,
+    }
+}
+
+/// The current time in the format the status bar uses, e.g. "9:41 AM".
+fn clock_text() -> String {
+    let seconds: i64 = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        .try_into()
+        .unwrap();
+    let (offset, _) = host_utc_offset(seconds);
+    let minute_of_day = ((seconds + i64::from(offset)) / 60).rem_euclid(24 * 60);
+    let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+    let suffix = if hour < 12 { "AM" } else { "PM" };
+    let hour = match hour % 12 {
+        0 => 12,
+        hour => hour,
+    };
+    format!("{}:{:02} {}", hour, minute, suffix)
+}
+
+/// For use by the compositor: get the status bar's layer, laid out and marked
+/// for redrawing if anything it shows has changed, or [None] if the status bar
+/// shouldn't be drawn.
+pub fn status_bar_layer(env: &mut Environment) -> Option<id> {
+    let animate = std::mem::take(
+        &mut env
+            .framework_state
+            .uikit
+            .ui_view
+            .ui_status_bar
+            .animate_next_layout,
+    );
+    if env.window.is_none() || env.options.hide_status_bar {
+        return None;
+    }
+
+    let hidden = env.framework_state.uikit.ui_application.status_bar_hidden;
+    let view = match env.framework_state.uikit.ui_view.ui_status_bar.view {
+        Some(view) => view,
+        None if hidden => return None,
+        None => {
+            let view: id = msg_class![env; UIStatusBar alloc];
+            let view: id = msg![env; view init];
+            env.framework_state.uikit.ui_view.ui_status_bar.view = Some(view);
+            view
+        }
+    };
+
+    let app: id = msg_class![env; UIApplication sharedApplication];
+    let orientation: UIDeviceOrientation = msg![env; app statusBarOrientation];
+    let style: UIStatusBarStyle = msg![env; app statusBarStyle];
+    let drawn = Some((style, orientation, clock_text()));
+    if env.framework_state.uikit.ui_view.ui_status_bar.drawn != drawn {
+        // There's nothing to animate from the first time.
+        let old_drawn = env.framework_state.uikit.ui_view.ui_status_bar.drawn.take();
+        let animate = animate && old_drawn.is_some();
+        env.framework_state.uikit.ui_view.ui_status_bar.drawn = drawn;
+
+        let screen: id = msg_class![env; UIScreen mainScreen];
+        let screen_bounds: CGRect = msg![env; screen bounds];
+        let (size, center, angle) = placement(orientation, screen_bounds.size);
+        // The setters would get caught up in any animation block the app has
+        // open, so go around them.
+        let duration = if animate { ANIMATION_DURATION } else { 0.0 };
+        let bounds = CGRect {
+            origin: CGPoint::default(),
+            size,
+        };
+        animate_value_now(env, view, Value::Bounds(bounds), duration);
+        animate_value_now(env, view, Value::Center(center), duration);
+        let transform = CGAffineTransform::make_rotation(angle);
+        animate_value_now(env, view, Value::Transform(transform), duration);
+        () = msg![env; view setOpaque:(style != UIStatusBarStyleBlackTranslucent)];
+        () = msg![env; view setNeedsDisplay];
+    }
+
+    let layer: id = msg![env; view layer];
+    // A hidden status bar is still drawn while it fades out.
+    if hidden {
+        let opacity: f32 = msg![env; layer opacity];
+        if opacity <= 0.0 {
+            return None;
+        }
+    }
+    Some(layer)
+}
+
+/// Draw the signal strength bars, with their bottom-left corner at `origin`.
+/// Returns the x co-ordinate of their right edge.
+fn draw_signal_bars(env: &mut Environment, context: CGContextRef, origin: CGPoint) -> CGFloat {
+    let mut x = origin.x;
+    for bar in 0..5 {
+        let height = 3.0 + 2.0 * bar as CGFloat;
+        let rect = CGRect {
+            origin: CGPoint {
+                x,
+                y: origin.y - height,
+            },
+            size: CGSize { width: 3.0, height },
+        };
+        CGContextFillRect(env, context, rect);
+        x += 4.0;
+    }
+    x - 1.0
+}
+
+/// Draw a full battery, with its top-right corner at `origin`.
+fn draw_battery(env: &mut Environment, context: CGContextRef, origin: CGPoint) {
+    let (width, height) = (22.0, 11.0);
+    let body = CGRect {
+        origin: CGPoint {
+            x: origin.x - width - 2.0,
+            y: origin.y,
+        },
+        size: CGSize { width, height },
+    };
+    let CGRect {
+        origin: CGPoint { x, y },
+        ..
+    } = body;
+    let outline = [
+        (x, y, width, 1.0),
+        (x, y + height - 1.0, width, 1.0),
+        (x, y, 1.0, height),
+        (x + width - 1.0, y, 1.0, height),
+        // The nub on the end.
+        (x + width, y + 3.0, 2.0, height - 6.0),
+        // The charge.
+        (x + 2.0, y + 2.0, width - 4.0, height - 4.0),
+    ];
+    for (x, y, width, height) in outline {
+        let rect = CGRect {
+            origin: CGPoint { x, y },
+            size: CGSize { width, height },
+        };
+        CGContextFillRect(env, context, rect);
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// The real iPhone OS has a private class with this name too, but this one
+// doesn't attempt to match it.
+@implementation UIStatusBar: UIView
+
+- (())drawRect:(CGRect)_rect {
+    let bounds: CGRect = msg![env; this bounds];
+    let context = UIGraphicsGetCurrentContext(env);
+    let Some((style, _, clock)) = env.framework_state.uikit.ui_view.ui_status_bar.drawn.clone() else {
+        return;
+    };
+
+    let text_color = match style {
+        UIStatusBarStyleBlackOpaque | UIStatusBarStyleBlackTranslucent => {
+            let alpha = if style == UIStatusBarStyleBlackOpaque { 1.0 } else { 0.5 };
+            CGContextSetRGBFillColor(env, context, 0.0, 0.0, 0.0, alpha);
+            CGContextFillRect(env, context, bounds);
+            1.0
+        }
+        _ => {
+            fill_vertical_gradient(env, context, bounds, (0.9, 0.9, 0.9), (0.65, 0.65, 0.65));
+            CGContextSetRGBFillColor(env, context, 0.45, 0.45, 0.45, 1.0);
+            let mut bottom_line = bounds;
+            bottom_line.origin.y += bounds.size.height - 1.0;
+            bottom_line.size.height = 1.0;
+            CGContextFillRect(env, context, bottom_line);
+            0.0
+        }
+    };
+    CGContextSetRGBFillColor(env, context, text_color, text_color, text_color, 1.0);
+
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:(12.0 as CGFloat)];
+    let signal_end = draw_signal_bars(env, context, CGPoint {
+        x: bounds.origin.x + 4.0,
+        y: bounds.origin.y + 15.0,
+    });
+    let carrier = get_static_str(env, "Carrier");
+    let carrier_size: CGSize = msg![env; carrier sizeWithFont:font];
+    let _: CGSize = msg![env; carrier drawAtPoint:(CGPoint {
+        x: signal_end + 5.0,
+        y: bounds.origin.y + ((bounds.size.height - carrier_size.height) / 2.0).round(),
+    }) withFont:font];
+
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:(14.0 as CGFloat)];
+    let clock = from_rust_string(env, clock);
+    let clock_size: CGSize = msg![env; clock sizeWithFont:font];
+    let _: CGSize = msg![env; clock drawAtPoint:(CGPoint {
+        x: bounds.origin.x + ((bounds.size.width - clock_size.width) / 2.0).round(),
+        y: bounds.origin.y + ((bounds.size.height - clock_size.height) / 2.0).round(),
+    }) withFont:font];
+    release(env, clock);
+
+    draw_battery(env, context, CGPoint {
+        x: bounds.origin.x + bounds.size.width - 4.0,
+        y: bounds.origin.y + 4.0,
+    });
+}
+
+@end
+
+};
@@ -0,0 +1,329 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UITabBar`.
+//!
+//! The bar is drawn in the default iPhone OS style: black, with each item's
+//! image above its title, and the selected item highlighted.
+
+use super::drawing::fill_vertical_gradient;
+use super::ui_control::ui_button::UIButtonTypeCustom;
+use super::ui_control::UIControlEventTouchUpInside;
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::{ns_array, NSUInteger};
+use crate::frameworks::uikit::ui_font::UITextAlignmentCenter;
+use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
+use crate::objc::{
+    autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes,
+    release, retain, ClassExports, NSZonePtr,
+};
+use crate::Environment;
+
+/// The standard height of a tab bar.
+pub const TAB_BAR_HEIGHT: CGFloat = 49.0;
+
+const IMAGE_SIZE: CGFloat = 30.0;
+const TITLE_HEIGHT: CGFloat = 14.0;
+
+pub struct UITabBarHostObject {
+    superclass: super::UIViewHostObject,
+    /// `UITabBarItem*`s. Strong references.
+    items: Vec<id>,
+    /// `UITabBarItem*`, one of `items` or [nil].
+    selected_item: id,
+    /// Weak reference.
+    delegate: id,
+    /// Subviews that display the items. Weak references (the bar owns them by
+    /// virtue of being their superview).
+    item_views: Vec<id>,
+    /// Buttons covering each item, and which item each is for. Weak
+    /// references.
+    buttons: Vec<(id, id)>,
+}
+impl_HostObject_with_superclass!(UITabBarHostObject);
+impl Default for UITabBarHostObject {
+    fn default() -> Self {
+        UITabBarHostObject {
+            superclass: Default::default(),
+            items: Vec::new(),
+            selected_item: nil,
+            delegate: nil,
+            item_views: Vec::new(),
+            buttons: Vec::new(),
+        }
+    }
+}
+
+/// Add a subview that displays an item.
+fn add_item_view(env: &mut Environment, this: id, view: id) {
+    () = msg![env; this addSubview:view];
+    env.objc
+        .borrow_mut::<UITabBarHostObject>(this)
+        .item_views
+        .push(view);
+}
+
+/// Recreate the subviews that display the items.
+fn update(env: &mut Environment, this: id) {
+    let host_obj = env.objc.borrow_mut::<UITabBarHostObject>(this);
+    let old_views = std::mem::take(&mut host_obj.item_views);
+    host_obj.buttons.clear();
+    let items = host_obj.items.clone();
+    let selected_item = host_obj.selected_item;
+    for view in old_views {
+        () = msg![env; view removeFromSuperview];
+    }
+
+    () = msg![env; this setNeedsDisplay];
+
+    if items.is_empty() {
+        return;
+    }
+
+    let bounds: CGRect = msg![env; this bounds];
+    let item_width = bounds.size.width / items.len() as CGFloat;
+    let selector = env.objc.lookup_selector("_touchHLE_buttonTapped:").unwrap();
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:(10.0 as CGFloat)];
+
+    for (i, &item) in items.iter().enumerate() {
+        let slot = CGRect {
+            origin: CGPoint {
+                x: item_width * i as CGFloat,
+                y: 0.0,
+            },
+            size: CGSize {
+                width: item_width,
+                height: TAB_BAR_HEIGHT,
+            },
+        };
+        let selected = item == selected_item;
+
+        if selected {
+            let highlight: id = msg_class![env; UIView alloc];
+            let highlight: id = msg![env; highlight initWithFrame:slot];
+            let color: id = msg_class![env; UIColor colorWithWhite:(1.0 as CGFloat)
+                                                             alpha:(0.15 as CGFloat)];
+            () = msg![env; highlight setBackgroundColor:color];
+            add_item_view(env, this, highlight);
+            release(env, highlight);
+        }
+
+        let image: id = msg![env; item image];
+        if image != nil {
+            let frame = CGRect {
+                origin: CGPoint {
+                    x: slot.origin.x + (item_width - IMAGE_SIZE) / 2.0,
+                    y: 3.0,
+                },
+                size: CGSize {
+                    width: IMAGE_SIZE,
+                    height: IMAGE_SIZE,
+                },
+            };
+            // TODO: tint the image like iPhone OS does (gray, or blue when
+            // selected), rather than showing it as-is.
+            let image_view: id = msg_class![env; UIImageView alloc];
+            let image_view: id = msg![env; image_view initWithImage:image];
+            () = msg![env; image_view setFrame:frame];
+            add_item_view(env, this, image_view);
+            release(env, image_view);
+        }
+
+        let title: id = msg![env; item title];
+        if title != nil {
+            let frame = CGRect {
+                origin: CGPoint {
+                    x: slot.origin.x,
+                    y: TAB_BAR_HEIGHT - TITLE_HEIGHT - 2.0,
+                },
+                size: CGSize {
+                    width: item_width,
+                    height: TITLE_HEIGHT,
+                },
+            };
+            let label: id = msg_class![env; UILabel alloc];
+            let label: id = msg![env; label initWithFrame:frame];
+            () = msg![env; label setFont:font];
+            let text_color: id = if selected {
+                msg_class![env; UIColor colorWithRed:(0.4 as CGFloat)
+                                               green:(0.7 as CGFloat)
+                                                blue:(1.0 as CGFloat)
+                                               alpha:(1.0 as CGFloat)]
+            } else {
+                msg_class![env; UIColor colorWithWhite:(0.6 as CGFloat)
+                                                 alpha:(1.0 as CGFloat)]
+            };
+            () = msg![env; label setTextColor:text_color];
+            () = msg![env; label setTextAlignment:UITextAlignmentCenter];
+            () = msg![env; label setText:title];
+            add_item_view(env, this, label);
+            release(env, label);
+        }
+
+        // Transparent button on top of everything else to handle touches.
+        let button: id = msg_class![env; UIButton buttonWithType:UIButtonTypeCustom];
+        () = msg![env; button setFrame:slot];
+        () = msg![env; button addTarget:this
+                                 action:selector
+                       forControlEvents:UIControlEventTouchUpInside];
+        add_item_view(env, this, button);
+        env.objc
+            .borrow_mut::<UITabBarHostObject>(this)
+            .buttons
+            .push((button, item));
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UITabBar: UIView
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UITabBarHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    update(env, this);
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UIItems");
+    let items: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    if items != nil {
+        () = msg![env; this setItems:items];
+    }
+
+    let key_ns_string = get_static_str(env, "UISelectedItem");
+    let selected_item: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setSelectedItem:selected_item];
+
+    this
+}
+
+- (())dealloc {
+    let items = std::mem::take(&mut env.objc.borrow_mut::<UITabBarHostObject>(this).items);
+    for item in items {
+        release(env, item);
+    }
+    msg_super![env; this dealloc]
+}
+
+- (())layoutSubviews {
+    update(env, this);
+}
+
+- (())drawRect:(CGRect)_rect {
+    let bounds: CGRect = msg![env; this bounds];
+    let context = UIGraphicsGetCurrentContext(env);
+
+    // The top half is lighter than the bottom half.
+    let mut top_half = bounds;
+    top_half.size.height = (bounds.size.height / 2.0).floor();
+    let mut bottom_half = bounds;
+    bottom_half.origin.y += top_half.size.height;
+    bottom_half.size.height -= top_half.size.height;
+    fill_vertical_gradient(env, context, top_half, (0.35, 0.35, 0.35), (0.15, 0.15, 0.15));
+    fill_vertical_gradient(env, context, bottom_half, (0.07, 0.07, 0.07), (0.0, 0.0, 0.0));
+}
+
+- (CGSize)sizeThatFits:(CGSize)size {
+    CGSize {
+        width: size.width,
+        height: TAB_BAR_HEIGHT,
+    }
+}
+
+- (id)delegate {
+    env.objc.borrow::<UITabBarHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate { // id<UITabBarDelegate>
+    // The delegate is a *weak* reference!
+    env.objc.borrow_mut::<UITabBarHostObject>(this).delegate = delegate;
+}
+
+- (id)items {
+    let items = env.objc.borrow::<UITabBarHostObject>(this).items.clone();
+    for &item in &items {
+        retain(env, item);
+    }
+    let array = ns_array::from_vec(env, items);
+    autorelease(env, array)
+}
+- (())setItems:(id)items { // NSArray* of UITabBarItem*
+    () = msg![env; this setItems:items animated:false];
+}
+- (())setItems:(id)items // NSArray* of UITabBarItem*
+      animated:(bool)_animated {
+    // TODO: animation
+    let count: NSUInteger = msg![env; items count];
+    let mut new_items = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let item: id = msg![env; items objectAtIndex:i];
+        retain(env, item);
+        new_items.push(item);
+    }
+    let host_obj = env.objc.borrow_mut::<UITabBarHostObject>(this);
+    if !new_items.contains(&host_obj.selected_item) {
+        host_obj.selected_item = nil;
+    }
+    let old_items = std::mem::replace(&mut host_obj.items, new_items);
+    for item in old_items {
+        release(env, item);
+    }
+    update(env, this);
+}
+
+- (id)selectedItem {
+    env.objc.borrow::<UITabBarHostObject>(this).selected_item
+}
+- (())setSelectedItem:(id)item { // UITabBarItem*
+    let host_obj = env.objc.borrow_mut::<UITabBarHostObject>(this);
+    if item != nil && !host_obj.items.contains(&item) {
+        log!("Warning: [(UITabBar*){:?} setSelectedItem:{:?}] with item not in bar, ignoring", this, item);
+        return;
+    }
+    host_obj.selected_item = item;
+    update(env, this);
+}
+
+// Internal method, target of the item buttons.
+- (())_touchHLE_buttonTapped:(id)button { // UIButton*
+    let buttons = &env.objc.borrow::<UITabBarHostObject>(this).buttons;
+    let Some(&(_, item)) = buttons.iter().find(|&&(b, _)| b == button) else {
+        return;
+    };
+    // The button may be removed from the bar by the code below, but the
+    // caller is still using it.
+    retain(env, button);
+    autorelease(env, button);
+    let enabled: bool = msg![env; item isEnabled];
+    if !enabled {
+        return;
+    }
+
+    () = msg![env; this setSelectedItem:item];
+
+    let delegate = env.objc.borrow::<UITabBarHostObject>(this).delegate;
+    if delegate != nil && env.objc.object_has_method_named(
+        &env.mem,
+        delegate,
+        "tabBar:didSelectItem:",
+    ) {
+        () = msg![env; delegate tabBar:this didSelectItem:item];
+    }
+}
+
+@end
+
+};
@@ -6,6 +6,7 @@
 //! `UIWindow`.
 
 use crate::frameworks::core_graphics::CGRect;
+use crate::frameworks::uikit::ui_view_controller;
 use crate::objc::{id, msg, msg_super, objc_classes, ClassExports};
 
 #[derive(Default)]
@@ -90,6 +91,13 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
+- (())addSubview:(id)view {
+    () = msg_super![env; this addSubview:view];
+    if view != nil {
+        ui_view_controller::view_added_to_window(env, view);
+    }
+}
+
 - (())makeKeyAndVisible {
     // TODO: Set the "key" window once it's relevant. We don't currently have
     // send any non-touch events to windows, so there's no meaning in it yet.
@@ -5,17 +5,165 @@
  */
 //! `UIViewController`.
 
+pub mod ui_navigation_controller;
+pub mod ui_tab_bar_controller;
+
+use super::ui_view::UIViewHostObject;
+use crate::frameworks::core_graphics::CGRect;
 use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::NSInteger;
 use crate::objc::{
-    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+    id, impl_HostObject_with_superclass, msg, msg_class, nil, objc_classes, release, retain,
+    AnyHostObject, Class, ClassExports, HostObject, NSZonePtr,
 };
+use crate::Environment;
+
+#[derive(Default)]
+pub struct State {
+    /// List of view controllers for internal purposes. Non-retaining!
+    pub(super) view_controllers: Vec<id>,
+}
 
 #[derive(Default)]
-struct UIViewControllerHostObject {
+pub struct UIViewControllerHostObject {
     view: id,
+    /// The view controller presented by this one with
+    /// `presentModalViewController:animated:`, if any. Strong reference.
+    modal_view_controller: id,
+    /// The view controller that presented this one, if any. Weak reference.
+    parent_view_controller: id,
+    /// `NSString*`
+    title: id,
+    /// `UINavigationItem*`, created on demand.
+    navigation_item: id,
+    /// `UITabBarItem*`, created on demand.
+    tab_bar_item: id,
+    /// The navigation controller this one is on the stack of, if any. Weak
+    /// reference.
+    navigation_controller: id,
+    /// The tab bar controller this one is a tab of, if any. Weak reference.
+    tab_bar_controller: id,
+    /// Whether `viewDidAppear:` was the most recent appearance method sent by
+    /// [set_appeared].
+    appeared: bool,
 }
 impl HostObject for UIViewControllerHostObject {}
 
+/// For use by `allocWithZone:` of `UIViewController` and its subclasses:
+/// allocate the object and add it to the list of view controllers, so that
+/// it can be sent low-memory warnings.
+pub fn alloc_view_controller(
+    env: &mut Environment,
+    class: Class,
+    host_object: Box<dyn AnyHostObject>,
+) -> id {
+    let view_controller = env.objc.alloc_object(class, host_object, &mut env.mem);
+    env.framework_state
+        .uikit
+        .ui_view_controller
+        .view_controllers
+        .push(view_controller);
+    view_controller
+}
+
+/// `UILayoutContainerView` is the private class of the views of container
+/// view controllers like `UINavigationController`. It's used to find the view
+/// controller when the view is added to a window.
+#[derive(Default)]
+struct UILayoutContainerViewHostObject {
+    superclass: UIViewHostObject,
+    /// Weak reference.
+    view_controller: id,
+}
+impl_HostObject_with_superclass!(UILayoutContainerViewHostObject);
+
+/// Create the view for a container view controller. It fills the application
+/// frame, like the views of view controllers added directly to the window
+/// usually do.
+fn new_container_view(env: &mut Environment, view_controller: id) -> id {
+    let screen: id = msg_class![env; UIScreen mainScreen];
+    let frame: CGRect = msg![env; screen applicationFrame];
+    let view: id = msg_class![env; UILayoutContainerView alloc];
+    let view: id = msg![env; view initWithFrame:frame];
+    env.objc
+        .borrow_mut::<UILayoutContainerViewHostObject>(view)
+        .view_controller = view_controller;
+    view
+}
+
+/// Get the child view controller a container view controller is currently
+/// showing, if any.
+fn visible_child(env: &mut Environment, view_controller: id) -> id {
+    let nav_class = env
+        .objc
+        .get_known_class("UINavigationController", &mut env.mem);
+    let tab_class = env.objc.get_known_class("UITabBarController", &mut env.mem);
+    if msg![env; view_controller isKindOfClass:nav_class] {
+        msg![env; view_controller topViewController]
+    } else if msg![env; view_controller isKindOfClass:tab_class] {
+        msg![env; view_controller selectedViewController]
+    } else {
+        nil
+    }
+}
+
+/// Send `viewWillAppear:` and `viewDidAppear:` (or `viewWillDisappear:` and
+/// `viewDidDisappear:`) to a view controller, unless it's already in that
+/// state. This is forwarded to the child a container view controller is
+/// showing.
+pub fn set_appeared(env: &mut Environment, view_controller: id, appeared: bool, animated: bool) {
+    if view_controller == nil {
+        return;
+    }
+    let host_obj = env
+        .objc
+        .borrow_mut::<UIViewControllerHostObject>(view_controller);
+    if host_obj.appeared == appeared {
+        return;
+    }
+    host_obj.appeared = appeared;
+
+    log_dbg!(
+        "View controller {:?} {}",
+        view_controller,
+        if appeared {
+            "appearing"
+        } else {
+            "disappearing"
+        }
+    );
+
+    let child = visible_child(env, view_controller);
+    if appeared {
+        () = msg![env; view_controller viewWillAppear:animated];
+        set_appeared(env, child, true, animated);
+        () = msg![env; view_controller viewDidAppear:animated];
+    } else {
+        () = msg![env; view_controller viewWillDisappear:animated];
+        set_appeared(env, child, false, animated);
+        () = msg![env; view_controller viewDidDisappear:animated];
+    }
+}
+
+/// For use by `UIWindow`: a view was added to the window.
+///
+/// Like on iPhone OS, only container view controllers find out about this.
+/// Apps that add the view of a plain `UIViewController` to the window don't
+/// expect `viewWillAppear:` to be called.
+pub fn view_added_to_window(env: &mut Environment, view: id) {
+    let class = env
+        .objc
+        .get_known_class("UILayoutContainerView", &mut env.mem);
+    if !msg![env; view isKindOfClass:class] {
+        return;
+    }
+    let view_controller = env
+        .objc
+        .borrow::<UILayoutContainerViewHostObject>(view)
+        .view_controller;
+    set_appeared(env, view_controller, true, false);
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -24,7 +172,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 + (id)allocWithZone:(NSZonePtr)_zone {
     let host_object = Box::<UIViewControllerHostObject>::default();
-    env.objc.alloc_object(this, host_object, &mut env.mem)
+    alloc_view_controller(env, this, host_object)
 }
 
 - (id)initWithCoder:(id)coder {
@@ -33,13 +181,51 @@ pub const CLASSES: ClassExports = objc_classes! {
 
     () = msg![env; this setView:view];
 
+    let key_ns_string = get_static_str(env, "UITitle");
+    let title: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setTitle:title];
+
+    let key_ns_string = get_static_str(env, "UINavigationItem");
+    let navigation_item: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    retain(env, navigation_item);
+
+    let key_ns_string = get_static_str(env, "UITabBarItem");
+    let tab_bar_item: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    retain(env, tab_bar_item);
+
+    let host_obj = env.objc.borrow_mut::<UIViewControllerHostObject>(this);
+    host_obj.navigation_item = navigation_item;
+    host_obj.tab_bar_item = tab_bar_item;
+
     this
 }
 
 - (())dealloc {
-    let &UIViewControllerHostObject { view } = env.objc.borrow(this);
+    let &UIViewControllerHostObject {
+        view,
+        modal_view_controller,
+        title,
+        navigation_item,
+        tab_bar_item,
+        ..
+    } = env.objc.borrow(this);
+
+    // The view might outlive its view controller.
+    let container_class = env.objc.get_known_class("UILayoutContainerView", &mut env.mem);
+    if view != nil && msg![env; view isKindOfClass:container_class] {
+        env.objc.borrow_mut::<UILayoutContainerViewHostObject>(view).view_controller = nil;
+    }
 
     release(env, view);
+    release(env, modal_view_controller);
+    release(env, title);
+    release(env, navigation_item);
+    release(env, tab_bar_item);
+
+    let view_controllers = &mut env.framework_state.uikit.ui_view_controller.view_controllers;
+    if let Some(idx) = view_controllers.iter().position(|&v| v == this) {
+        view_controllers.swap_remove(idx);
+    }
 
     env.objc.dealloc_object(this, &mut env.mem);
 }
@@ -68,12 +254,180 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
+- (id)title {
+    env.objc.borrow::<UIViewControllerHostObject>(this).title
+}
+- (())setTitle:(id)title { // NSString*
+    let title: id = msg![env; title copy];
+    let host_obj = env.objc.borrow_mut::<UIViewControllerHostObject>(this);
+    let old_title = std::mem::replace(&mut host_obj.title, title);
+    let &mut UIViewControllerHostObject {
+        navigation_item,
+        tab_bar_item,
+        ..
+    } = host_obj;
+    release(env, old_title);
+
+    // The title is also shown by the navigation and tab bars.
+    if navigation_item != nil {
+        () = msg![env; navigation_item setTitle:title];
+    }
+    if tab_bar_item != nil {
+        () = msg![env; tab_bar_item setTitle:title];
+    }
+}
+
+- (id)navigationItem {
+    let navigation_item = env.objc.borrow::<UIViewControllerHostObject>(this).navigation_item;
+    if navigation_item != nil {
+        return navigation_item;
+    }
+    let title: id = msg![env; this title];
+    let navigation_item: id = msg_class![env; UINavigationItem alloc];
+    let navigation_item: id = msg![env; navigation_item initWithTitle:title];
+    env.objc.borrow_mut::<UIViewControllerHostObject>(this).navigation_item = navigation_item;
+    navigation_item
+}
+
+- (id)tabBarItem {
+    let tab_bar_item = env.objc.borrow::<UIViewControllerHostObject>(this).tab_bar_item;
+    if tab_bar_item != nil {
+        return tab_bar_item;
+    }
+    let title: id = msg![env; this title];
+    let tab_bar_item: id = msg_class![env; UITabBarItem alloc];
+    let tab_bar_item: id = msg![env; tab_bar_item initWithTitle:title image:nil tag:(0 as NSInteger)];
+    env.objc.borrow_mut::<UIViewControllerHostObject>(this).tab_bar_item = tab_bar_item;
+    tab_bar_item
+}
+- (())setTabBarItem:(id)tab_bar_item { // UITabBarItem*
+    retain(env, tab_bar_item);
+    let host_obj = env.objc.borrow_mut::<UIViewControllerHostObject>(this);
+    let old_item = std::mem::replace(&mut host_obj.tab_bar_item, tab_bar_item);
+    let tab_bar_controller = host_obj.tab_bar_controller;
+    release(env, old_item);
+    if tab_bar_controller != nil {
+        ui_tab_bar_controller::update_tab_bar(env, tab_bar_controller);
+    }
+}
+
+- (id)navigationController {
+    env.objc.borrow::<UIViewControllerHostObject>(this).navigation_controller
+}
+- (id)tabBarController {
+    let &UIViewControllerHostObject {
+        navigation_controller,
+        tab_bar_controller,
+        ..
+    } = env.objc.borrow(this);
+    if tab_bar_controller == nil && navigation_controller != nil {
+        // A view controller on the stack of a navigation controller that is
+        // a tab has the same tab bar controller.
+        msg![env; navigation_controller tabBarController]
+    } else {
+        tab_bar_controller
+    }
+}
+
+// Appearance methods. These are for subclasses to override.
+- (())viewWillAppear:(bool)_animated {}
+- (())viewDidAppear:(bool)_animated {}
+- (())viewWillDisappear:(bool)_animated {}
+- (())viewDidDisappear:(bool)_animated {}
+
+- (())didReceiveMemoryWarning {
+    // On iPhone OS, this releases the view if it has no superview, so it can
+    // be loaded again later. That's not safe here, because views from nib
+    // files can't be reloaded yet (see loadView).
+    log_dbg!("[(UIViewController*){:?} didReceiveMemoryWarning]", this);
+}
+// For subclasses to override. This is never sent, since the view is never
+// released by didReceiveMemoryWarning.
+- (())viewDidUnload {}
+
 - (())setEditing:(bool)editing {
     log!("TODO: [(UIViewController*){:?} setEditing:{}]", this, editing); // TODO
 }
 
+- (id)modalViewController {
+    env.objc.borrow::<UIViewControllerHostObject>(this).modal_view_controller
+}
+- (id)parentViewController {
+    env.objc.borrow::<UIViewControllerHostObject>(this).parent_view_controller
+}
+
+- (())presentModalViewController:(id)modal_view_controller // UIViewController*
+                        animated:(bool)animated {
+    // TODO: animation, more than one level of modal view controller
+    let current = env.objc.borrow::<UIViewControllerHostObject>(this).modal_view_controller;
+    if current != nil {
+        // Apps do this by accident sometimes. Better to carry on than crash.
+        log!(
+            "Warning: {:?} is already presenting {:?}, ignoring request to present {:?}",
+            this,
+            current,
+            modal_view_controller
+        );
+        return;
+    }
+    if modal_view_controller == nil {
+        log!("Warning: {:?} asked to present nil modal view controller, ignoring", this);
+        return;
+    }
+
+    retain(env, modal_view_controller);
+    env.objc.borrow_mut::<UIViewControllerHostObject>(this).modal_view_controller = modal_view_controller;
+    env.objc.borrow_mut::<UIViewControllerHostObject>(modal_view_controller).parent_view_controller = this;
+
+    // The modal view covers the whole window on top of everything else.
+    let Some(&window) = env.framework_state.uikit.ui_view.ui_window.visible_windows.last() else {
+        log!("Warning: no visible window to present {:?} in", modal_view_controller);
+        return;
+    };
+    let view: id = msg![env; modal_view_controller view];
+    let bounds: CGRect = msg![env; window bounds];
+    () = msg![env; view setFrame:bounds];
+    () = msg![env; window addSubview:view];
+    set_appeared(env, modal_view_controller, true, animated);
+}
+
 - (())dismissModalViewControllerAnimated:(bool)animated {
-    log!("TODO: [(UIViewController*){:?} dismissModalViewControllerAnimated:{}]", this, animated); // TODO
+    let &UIViewControllerHostObject {
+        modal_view_controller,
+        parent_view_controller,
+        ..
+    } = env.objc.borrow(this);
+    if modal_view_controller == nil {
+        // A modal view controller can ask to dismiss itself, in which case the
+        // request goes to the view controller that presented it.
+        if parent_view_controller != nil {
+            () = msg![env; parent_view_controller dismissModalViewControllerAnimated:animated];
+        }
+        return;
+    }
+
+    let view: id = msg![env; modal_view_controller view];
+    () = msg![env; view removeFromSuperview];
+    set_appeared(env, modal_view_controller, false, animated);
+    env.objc.borrow_mut::<UIViewControllerHostObject>(modal_view_controller).parent_view_controller = nil;
+    env.objc.borrow_mut::<UIViewControllerHostObject>(this).modal_view_controller = nil;
+    release(env, modal_view_controller);
+}
+
+@end
+
+@implementation UILayoutContainerView: UIView
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UILayoutContainerViewHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())layoutSubviews {
+    let view_controller = env.objc.borrow::<UILayoutContainerViewHostObject>(this).view_controller;
+    if view_controller != nil {
+        () = msg![env; view_controller _touchHLE_layoutContainerView];
+    }
 }
 
 @end
@@ -0,0 +1,432 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UINavigationController`.
+
+use super::{alloc_view_controller, new_container_view, set_appeared, UIViewControllerHostObject};
+use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::{ns_array, NSUInteger};
+use crate::frameworks::uikit::ui_view::ui_navigation_bar::NAVIGATION_BAR_HEIGHT;
+use crate::objc::{
+    autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes,
+    release, retain, ClassExports, NSZonePtr,
+};
+use crate::Environment;
+
+#[derive(Default)]
+struct UINavigationControllerHostObject {
+    superclass: UIViewControllerHostObject,
+    /// The stack of view controllers, bottom to top. Strong references.
+    view_controllers: Vec<id>,
+    /// `UINavigationBar*`, created on demand.
+    navigation_bar: id,
+    navigation_bar_hidden: bool,
+    /// Weak reference.
+    delegate: id,
+}
+impl_HostObject_with_superclass!(UINavigationControllerHostObject);
+
+/// Get the view of a view controller if it has been loaded, without loading
+/// it.
+fn loaded_view(env: &mut Environment, view_controller: id) -> id {
+    env.objc
+        .borrow::<UIViewControllerHostObject>(view_controller)
+        .view
+}
+
+/// Position the navigation bar and the top view controller's view within the
+/// navigation controller's view, if it has been loaded.
+fn layout(env: &mut Environment, this: id) {
+    let view = loaded_view(env, this);
+    if view == nil {
+        return;
+    }
+    let bounds: CGRect = msg![env; view bounds];
+
+    let navigation_bar: id = msg![env; this navigationBar];
+    let hidden = env
+        .objc
+        .borrow::<UINavigationControllerHostObject>(this)
+        .navigation_bar_hidden;
+    () = msg![env; navigation_bar setHidden:hidden];
+    let bar_height = if hidden { 0.0 } else { NAVIGATION_BAR_HEIGHT };
+    let bar_frame = CGRect {
+        origin: CGPoint { x: 0.0, y: 0.0 },
+        size: CGSize {
+            width: bounds.size.width,
+            height: NAVIGATION_BAR_HEIGHT,
+        },
+    };
+    () = msg![env; navigation_bar setFrame:bar_frame];
+    () = msg![env; navigation_bar layoutSubviews];
+
+    let top: id = msg![env; this topViewController];
+    if top != nil {
+        let top_view: id = msg![env; top view];
+        let frame = CGRect {
+            origin: CGPoint {
+                x: 0.0,
+                y: bar_height,
+            },
+            size: CGSize {
+                width: bounds.size.width,
+                height: bounds.size.height - bar_height,
+            },
+        };
+        () = msg![env; top_view setFrame:frame];
+        let superview: id = msg![env; top_view superview];
+        if superview != view {
+            () = msg![env; view addSubview:top_view];
+        }
+    }
+
+    // The bar is always above the content.
+    () = msg![env; view bringSubviewToFront:navigation_bar];
+}
+
+/// Replace the stack of view controllers. The new view controllers must
+/// already be retained by `new_stack`. This updates the bar and the displayed
+/// view, and sends appearance messages if the top view controller changes.
+///
+/// Returns the view controllers that were removed from the stack. They are
+/// still retained, and the caller is responsible for releasing them.
+fn set_stack(env: &mut Environment, this: id, new_stack: Vec<id>, animated: bool) -> Vec<id> {
+    // TODO: animation
+    for &view_controller in &new_stack {
+        env.objc
+            .borrow_mut::<UIViewControllerHostObject>(view_controller)
+            .navigation_controller = this;
+    }
+
+    let old_top: id = msg![env; this topViewController];
+    let new_top = new_stack.last().copied().unwrap_or(nil);
+    let old_stack = std::mem::replace(
+        &mut env
+            .objc
+            .borrow_mut::<UINavigationControllerHostObject>(this)
+            .view_controllers,
+        new_stack.clone(),
+    );
+    let mut removed = Vec::new();
+    for view_controller in old_stack {
+        if new_stack.contains(&view_controller) {
+            release(env, view_controller);
+        } else {
+            let host_obj = env
+                .objc
+                .borrow_mut::<UIViewControllerHostObject>(view_controller);
+            if host_obj.navigation_controller == this {
+                host_obj.navigation_controller = nil;
+            }
+            removed.push(view_controller);
+        }
+    }
+
+    // The navigation bar shows the items of every view controller in the
+    // stack.
+    let mut items = Vec::with_capacity(new_stack.len());
+    for &view_controller in &new_stack {
+        let item: id = msg![env; view_controller navigationItem];
+        retain(env, item);
+        items.push(item);
+    }
+    let items = ns_array::from_vec(env, items);
+    let navigation_bar: id = msg![env; this navigationBar];
+    () = msg![env; navigation_bar setItems:items animated:animated];
+    release(env, items);
+
+    if old_top == new_top {
+        layout(env, this);
+        return removed;
+    }
+
+    let &UINavigationControllerHostObject {
+        delegate,
+        superclass: UIViewControllerHostObject { appeared, .. },
+        ..
+    } = env.objc.borrow(this);
+
+    if new_top != nil
+        && delegate != nil
+        && env.objc.object_has_method_named(
+            &env.mem,
+            delegate,
+            "navigationController:willShowViewController:animated:",
+        )
+    {
+        () = msg![env; delegate navigationController:this
+                              willShowViewController:new_top
+                                            animated:animated];
+    }
+
+    if old_top != nil {
+        if appeared {
+            set_appeared(env, old_top, false, animated);
+        }
+        let view = loaded_view(env, this);
+        let old_top_view = loaded_view(env, old_top);
+        if view != nil && old_top_view != nil {
+            let superview: id = msg![env; old_top_view superview];
+            if superview == view {
+                () = msg![env; old_top_view removeFromSuperview];
+            }
+        }
+    }
+
+    layout(env, this);
+
+    if new_top != nil {
+        if appeared {
+            set_appeared(env, new_top, true, animated);
+        }
+
+        if delegate != nil
+            && env.objc.object_has_method_named(
+                &env.mem,
+                delegate,
+                "navigationController:didShowViewController:animated:",
+            )
+        {
+            () = msg![env; delegate navigationController:this
+                                   didShowViewController:new_top
+                                                animated:animated];
+        }
+    }
+
+    removed
+}
+
+/// Pop view controllers until there are `count` left, and return the popped
+/// ones as an autoreleased array.
+fn pop_to_count(env: &mut Environment, this: id, count: usize, animated: bool) -> id {
+    let mut new_stack = env
+        .objc
+        .borrow::<UINavigationControllerHostObject>(this)
+        .view_controllers
+        .clone();
+    new_stack.truncate(count);
+    for &view_controller in &new_stack {
+        retain(env, view_controller);
+    }
+    let popped = set_stack(env, this, new_stack, animated);
+    let popped = ns_array::from_vec(env, popped);
+    autorelease(env, popped)
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UINavigationController: UIViewController
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UINavigationControllerHostObject>::default();
+    alloc_view_controller(env, this, host_object)
+}
+
+- (id)initWithRootViewController:(id)root_view_controller { // UIViewController*
+    let this: id = msg![env; this init];
+    retain(env, root_view_controller);
+    let removed = set_stack(env, this, vec![root_view_controller], false);
+    assert!(removed.is_empty());
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UINavigationBar");
+    let navigation_bar: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    if navigation_bar != nil {
+        retain(env, navigation_bar);
+        () = msg![env; navigation_bar setDelegate:this];
+        env.objc.borrow_mut::<UINavigationControllerHostObject>(this).navigation_bar = navigation_bar;
+    }
+
+    let key_ns_string = get_static_str(env, "UINavigationBarHidden");
+    let hidden: bool = msg![env; coder decodeBoolForKey:key_ns_string];
+    env.objc.borrow_mut::<UINavigationControllerHostObject>(this).navigation_bar_hidden = hidden;
+
+    let key_ns_string = get_static_str(env, "UIViewControllers");
+    let view_controllers: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    if view_controllers != nil {
+        () = msg![env; this setViewControllers:view_controllers];
+    }
+
+    this
+}
+
+- (())dealloc {
+    let host_obj = env.objc.borrow_mut::<UINavigationControllerHostObject>(this);
+    let view_controllers = std::mem::take(&mut host_obj.view_controllers);
+    let navigation_bar = host_obj.navigation_bar;
+    for view_controller in view_controllers {
+        env.objc.borrow_mut::<UIViewControllerHostObject>(view_controller).navigation_controller = nil;
+        release(env, view_controller);
+    }
+    if navigation_bar != nil {
+        () = msg![env; navigation_bar setDelegate:nil];
+        release(env, navigation_bar);
+    }
+    msg_super![env; this dealloc]
+}
+
+- (())loadView {
+    let view = new_container_view(env, this);
+    let navigation_bar: id = msg![env; this navigationBar];
+    () = msg![env; view addSubview:navigation_bar];
+    () = msg![env; this setView:view];
+    release(env, view);
+    layout(env, this);
+}
+
+// Internal method, called by UILayoutContainerView.
+- (())_touchHLE_layoutContainerView {
+    layout(env, this);
+}
+
+- (id)navigationBar {
+    let navigation_bar = env.objc.borrow::<UINavigationControllerHostObject>(this).navigation_bar;
+    if navigation_bar != nil {
+        return navigation_bar;
+    }
+    let frame = CGRect {
+        origin: CGPoint { x: 0.0, y: 0.0 },
+        size: CGSize { width: 320.0, height: NAVIGATION_BAR_HEIGHT },
+    };
+    let navigation_bar: id = msg_class![env; UINavigationBar alloc];
+    let navigation_bar: id = msg![env; navigation_bar initWithFrame:frame];
+    () = msg![env; navigation_bar setDelegate:this];
+    env.objc.borrow_mut::<UINavigationControllerHostObject>(this).navigation_bar = navigation_bar;
+    navigation_bar
+}
+
+- (bool)isNavigationBarHidden {
+    env.objc.borrow::<UINavigationControllerHostObject>(this).navigation_bar_hidden
+}
+- (())setNavigationBarHidden:(bool)hidden {
+    env.objc.borrow_mut::<UINavigationControllerHostObject>(this).navigation_bar_hidden = hidden;
+    layout(env, this);
+}
+- (())setNavigationBarHidden:(bool)hidden
+                    animated:(bool)_animated {
+    // TODO: animation
+    () = msg![env; this setNavigationBarHidden:hidden];
+}
+
+- (id)delegate {
+    env.objc.borrow::<UINavigationControllerHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate { // id<UINavigationControllerDelegate>
+    // The delegate is a *weak* reference!
+    env.objc.borrow_mut::<UINavigationControllerHostObject>(this).delegate = delegate;
+}
+
+- (id)viewControllers {
+    let view_controllers = env.objc.borrow::<UINavigationControllerHostObject>(this).view_controllers.clone();
+    for &view_controller in &view_controllers {
+        retain(env, view_controller);
+    }
+    let array = ns_array::from_vec(env, view_controllers);
+    autorelease(env, array)
+}
+- (())setViewControllers:(id)view_controllers { // NSArray* of UIViewController*
+    () = msg![env; this setViewControllers:view_controllers animated:false];
+}
+- (())setViewControllers:(id)view_controllers // NSArray* of UIViewController*
+                animated:(bool)animated {
+    let count: NSUInteger = msg![env; view_controllers count];
+    let mut new_stack = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let view_controller: id = msg![env; view_controllers objectAtIndex:i];
+        retain(env, view_controller);
+        new_stack.push(view_controller);
+    }
+    let removed = set_stack(env, this, new_stack, animated);
+    for view_controller in removed {
+        release(env, view_controller);
+    }
+}
+
+- (id)topViewController {
+    let view_controllers = &env.objc.borrow::<UINavigationControllerHostObject>(this).view_controllers;
+    view_controllers.last().copied().unwrap_or(nil)
+}
+- (id)visibleViewController {
+    let top: id = msg![env; this topViewController];
+    if top == nil {
+        return nil;
+    }
+    let modal: id = msg![env; top modalViewController];
+    if modal != nil {
+        modal
+    } else {
+        top
+    }
+}
+
+- (())pushViewController:(id)view_controller // UIViewController*
+                animated:(bool)animated {
+    let mut new_stack = env.objc.borrow::<UINavigationControllerHostObject>(this).view_controllers.clone();
+    if new_stack.contains(&view_controller) {
+        log!(
+            "Warning: [(UINavigationController*){:?} pushViewController:{:?} animated:{}] with view controller already on the stack, ignoring",
+            this,
+            view_controller,
+            animated,
+        );
+        return;
+    }
+    new_stack.push(view_controller);
+    for &view_controller in &new_stack {
+        retain(env, view_controller);
+    }
+    let removed = set_stack(env, this, new_stack, animated);
+    assert!(removed.is_empty());
+}
+
+- (id)popViewControllerAnimated:(bool)animated {
+    let count = env.objc.borrow::<UINavigationControllerHostObject>(this).view_controllers.len();
+    // The root view controller can't be popped.
+    if count <= 1 {
+        return nil;
+    }
+    let popped = pop_to_count(env, this, count - 1, animated);
+    msg![env; popped lastObject]
+}
+
+- (id)popToRootViewControllerAnimated:(bool)animated {
+    pop_to_count(env, this, 1, animated)
+}
+
+- (id)popToViewController:(id)view_controller // UIViewController*
+                 animated:(bool)animated {
+    let view_controllers = &env.objc.borrow::<UINavigationControllerHostObject>(this).view_controllers;
+    let Some(idx) = view_controllers.iter().position(|&vc| vc == view_controller) else {
+        log!(
+            "Warning: [(UINavigationController*){:?} popToViewController:{:?} animated:{}] with view controller not on the stack, ignoring",
+            this,
+            view_controller,
+            animated,
+        );
+        return nil;
+    };
+    pop_to_count(env, this, idx + 1, animated)
+}
+
+// UINavigationBarDelegate implementation
+- (bool)navigationBar:(id)_navigation_bar // UINavigationBar*
+        shouldPopItem:(id)_item { // UINavigationItem*
+    // This is called when the user taps the back button. Popping the view
+    // controller also pops the item, so the bar doesn't need to.
+    let _: id = msg![env; this popViewControllerAnimated:true];
+    false
+}
+
+@end
+
+};
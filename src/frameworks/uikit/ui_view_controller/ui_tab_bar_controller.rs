@@ -0,0 +1,369 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UITabBarController`.
+
+use super::{alloc_view_controller, new_container_view, set_appeared, UIViewControllerHostObject};
+use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::{ns_array, NSNotFound, NSUInteger};
+use crate::frameworks::uikit::ui_view::ui_tab_bar::TAB_BAR_HEIGHT;
+use crate::objc::{
+    autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes,
+    release, retain, ClassExports, NSZonePtr,
+};
+use crate::Environment;
+
+#[derive(Default)]
+struct UITabBarControllerHostObject {
+    superclass: UIViewControllerHostObject,
+    /// The view controllers for each tab. Strong references.
+    view_controllers: Vec<id>,
+    /// One of `view_controllers`, or [nil] if there are none.
+    selected_view_controller: id,
+    /// `UITabBar*`, created on demand.
+    tab_bar: id,
+    /// Weak reference.
+    delegate: id,
+}
+impl_HostObject_with_superclass!(UITabBarControllerHostObject);
+
+/// Position the tab bar and the selected view controller's view within the
+/// tab bar controller's view, if it has been loaded.
+fn layout(env: &mut Environment, this: id) {
+    let view = env.objc.borrow::<UIViewControllerHostObject>(this).view;
+    if view == nil {
+        return;
+    }
+    let bounds: CGRect = msg![env; view bounds];
+
+    let tab_bar: id = msg![env; this tabBar];
+    let bar_frame = CGRect {
+        origin: CGPoint {
+            x: 0.0,
+            y: bounds.size.height - TAB_BAR_HEIGHT,
+        },
+        size: CGSize {
+            width: bounds.size.width,
+            height: TAB_BAR_HEIGHT,
+        },
+    };
+    () = msg![env; tab_bar setFrame:bar_frame];
+    () = msg![env; tab_bar layoutSubviews];
+
+    let selected: id = msg![env; this selectedViewController];
+    if selected != nil {
+        let selected_view: id = msg![env; selected view];
+        let frame = CGRect {
+            origin: CGPoint { x: 0.0, y: 0.0 },
+            size: CGSize {
+                width: bounds.size.width,
+                height: bounds.size.height - TAB_BAR_HEIGHT,
+            },
+        };
+        () = msg![env; selected_view setFrame:frame];
+        let superview: id = msg![env; selected_view superview];
+        if superview != view {
+            () = msg![env; view addSubview:selected_view];
+        }
+    }
+
+    () = msg![env; view bringSubviewToFront:tab_bar];
+}
+
+/// Make a different tab the selected one, updating the displayed view and
+/// sending appearance messages.
+fn select(env: &mut Environment, this: id, new_selected: id) {
+    let host_obj = env.objc.borrow_mut::<UITabBarControllerHostObject>(this);
+    let old_selected = std::mem::replace(&mut host_obj.selected_view_controller, new_selected);
+    let appeared = host_obj.superclass.appeared;
+    let view = host_obj.superclass.view;
+
+    let tab_bar: id = msg![env; this tabBar];
+    let item: id = if new_selected != nil {
+        msg![env; new_selected tabBarItem]
+    } else {
+        nil
+    };
+    () = msg![env; tab_bar setSelectedItem:item];
+
+    if old_selected == new_selected {
+        return;
+    }
+
+    if old_selected != nil {
+        if appeared {
+            set_appeared(env, old_selected, false, false);
+        }
+        let old_view = env
+            .objc
+            .borrow::<UIViewControllerHostObject>(old_selected)
+            .view;
+        if view != nil && old_view != nil {
+            let superview: id = msg![env; old_view superview];
+            if superview == view {
+                () = msg![env; old_view removeFromSuperview];
+            }
+        }
+    }
+
+    layout(env, this);
+
+    if new_selected != nil && appeared {
+        set_appeared(env, new_selected, true, false);
+    }
+}
+
+/// Give the tab bar the items of the current view controllers.
+fn set_tab_bar_items(env: &mut Environment, this: id) {
+    let view_controllers = env
+        .objc
+        .borrow::<UITabBarControllerHostObject>(this)
+        .view_controllers
+        .clone();
+    let mut items = Vec::with_capacity(view_controllers.len());
+    for view_controller in view_controllers {
+        let item: id = msg![env; view_controller tabBarItem];
+        retain(env, item);
+        items.push(item);
+    }
+    let items = ns_array::from_vec(env, items);
+    let tab_bar: id = msg![env; this tabBar];
+    () = msg![env; tab_bar setItems:items];
+    release(env, items);
+}
+
+/// For use by `UIViewController`: update the tab bar when the item of one of
+/// the tabs is replaced.
+pub fn update_tab_bar(env: &mut Environment, this: id) {
+    set_tab_bar_items(env, this);
+    let selected = env
+        .objc
+        .borrow::<UITabBarControllerHostObject>(this)
+        .selected_view_controller;
+    select(env, this, selected);
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UITabBarController: UIViewController
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UITabBarControllerHostObject>::default();
+    alloc_view_controller(env, this, host_object)
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+
+    let key_ns_string = get_static_str(env, "UITabBar");
+    let tab_bar: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    if tab_bar != nil {
+        retain(env, tab_bar);
+        () = msg![env; tab_bar setDelegate:this];
+        env.objc.borrow_mut::<UITabBarControllerHostObject>(this).tab_bar = tab_bar;
+    }
+
+    let key_ns_string = get_static_str(env, "UIViewControllers");
+    let view_controllers: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    if view_controllers != nil {
+        () = msg![env; this setViewControllers:view_controllers];
+    }
+
+    let key_ns_string = get_static_str(env, "UISelectedIndex");
+    let selected_index: NSUInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+    () = msg![env; this setSelectedIndex:selected_index];
+
+    this
+}
+
+- (())dealloc {
+    let host_obj = env.objc.borrow_mut::<UITabBarControllerHostObject>(this);
+    let view_controllers = std::mem::take(&mut host_obj.view_controllers);
+    let tab_bar = host_obj.tab_bar;
+    for view_controller in view_controllers {
+        env.objc.borrow_mut::<UIViewControllerHostObject>(view_controller).tab_bar_controller = nil;
+        release(env, view_controller);
+    }
+    if tab_bar != nil {
+        () = msg![env; tab_bar setDelegate:nil];
+        release(env, tab_bar);
+    }
+    msg_super![env; this dealloc]
+}
+
+- (())loadView {
+    let view = new_container_view(env, this);
+    let tab_bar: id = msg![env; this tabBar];
+    () = msg![env; view addSubview:tab_bar];
+    () = msg![env; this setView:view];
+    release(env, view);
+    layout(env, this);
+}
+
+// Internal method, called by UILayoutContainerView.
+- (())_touchHLE_layoutContainerView {
+    layout(env, this);
+}
+
+- (id)tabBar {
+    let tab_bar = env.objc.borrow::<UITabBarControllerHostObject>(this).tab_bar;
+    if tab_bar != nil {
+        return tab_bar;
+    }
+    let frame = CGRect {
+        origin: CGPoint { x: 0.0, y: 0.0 },
+        size: CGSize { width: 320.0, height: TAB_BAR_HEIGHT },
+    };
+    let tab_bar: id = msg_class![env; UITabBar alloc];
+    let tab_bar: id = msg![env; tab_bar initWithFrame:frame];
+    () = msg![env; tab_bar setDelegate:this];
+    env.objc.borrow_mut::<UITabBarControllerHostObject>(this).tab_bar = tab_bar;
+    tab_bar
+}
+
+- (id)delegate {
+    env.objc.borrow::<UITabBarControllerHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate { // id<UITabBarControllerDelegate>
+    // The delegate is a *weak* reference!
+    env.objc.borrow_mut::<UITabBarControllerHostObject>(this).delegate = delegate;
+}
+
+- (id)viewControllers {
+    let view_controllers = env.objc.borrow::<UITabBarControllerHostObject>(this).view_controllers.clone();
+    for &view_controller in &view_controllers {
+        retain(env, view_controller);
+    }
+    let array = ns_array::from_vec(env, view_controllers);
+    autorelease(env, array)
+}
+- (())setViewControllers:(id)view_controllers { // NSArray* of UIViewController*
+    () = msg![env; this setViewControllers:view_controllers animated:false];
+}
+- (())setViewControllers:(id)view_controllers // NSArray* of UIViewController*
+                animated:(bool)_animated {
+    // TODO: animation, "More" tab for more than five view controllers
+    let count: NSUInteger = msg![env; view_controllers count];
+    let mut new_view_controllers = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let view_controller: id = msg![env; view_controllers objectAtIndex:i];
+        retain(env, view_controller);
+        env.objc.borrow_mut::<UIViewControllerHostObject>(view_controller).tab_bar_controller = this;
+        new_view_controllers.push(view_controller);
+    }
+
+    let host_obj = env.objc.borrow_mut::<UITabBarControllerHostObject>(this);
+    let old_view_controllers = std::mem::replace(&mut host_obj.view_controllers, new_view_controllers.clone());
+    let old_selected = host_obj.selected_view_controller;
+    for view_controller in old_view_controllers {
+        if !new_view_controllers.contains(&view_controller) {
+            let host_obj = env.objc.borrow_mut::<UIViewControllerHostObject>(view_controller);
+            if host_obj.tab_bar_controller == this {
+                host_obj.tab_bar_controller = nil;
+            }
+        }
+        // Keep the old selected view controller alive until it's deselected.
+        if view_controller == old_selected {
+            autorelease(env, view_controller);
+        } else {
+            release(env, view_controller);
+        }
+    }
+
+    // The selection is kept if possible, otherwise the first tab is selected.
+    let new_selected = if new_view_controllers.contains(&old_selected) {
+        old_selected
+    } else {
+        new_view_controllers.first().copied().unwrap_or(nil)
+    };
+    set_tab_bar_items(env, this);
+    select(env, this, new_selected);
+}
+
+- (id)selectedViewController {
+    env.objc.borrow::<UITabBarControllerHostObject>(this).selected_view_controller
+}
+- (())setSelectedViewController:(id)view_controller { // UIViewController*
+    let view_controllers = &env.objc.borrow::<UITabBarControllerHostObject>(this).view_controllers;
+    if !view_controllers.contains(&view_controller) {
+        log!(
+            "Warning: [(UITabBarController*){:?} setSelectedViewController:{:?}] with view controller not in tab bar, ignoring",
+            this,
+            view_controller,
+        );
+        return;
+    }
+    select(env, this, view_controller);
+}
+
+- (NSUInteger)selectedIndex {
+    let &UITabBarControllerHostObject {
+        ref view_controllers,
+        selected_view_controller,
+        ..
+    } = env.objc.borrow(this);
+    view_controllers
+        .iter()
+        .position(|&vc| vc == selected_view_controller)
+        .map_or(NSNotFound as NSUInteger, |idx| idx as NSUInteger)
+}
+- (())setSelectedIndex:(NSUInteger)index {
+    let view_controllers = &env.objc.borrow::<UITabBarControllerHostObject>(this).view_controllers;
+    let Some(&view_controller) = view_controllers.get(index as usize) else {
+        return;
+    };
+    select(env, this, view_controller);
+}
+
+// UITabBarDelegate implementation
+- (())tabBar:(id)_tab_bar // UITabBar*
+didSelectItem:(id)item { // UITabBarItem*
+    let view_controllers = env.objc.borrow::<UITabBarControllerHostObject>(this).view_controllers.clone();
+    let mut selected = nil;
+    for view_controller in view_controllers {
+        let vc_item: id = msg![env; view_controller tabBarItem];
+        if vc_item == item {
+            selected = view_controller;
+            break;
+        }
+    }
+    if selected == nil {
+        return;
+    }
+
+    let delegate = env.objc.borrow::<UITabBarControllerHostObject>(this).delegate;
+    if delegate != nil && env.objc.object_has_method_named(
+        &env.mem,
+        delegate,
+        "tabBarController:shouldSelectViewController:",
+    ) {
+        let should_select: bool = msg![env; delegate tabBarController:this
+                                           shouldSelectViewController:selected];
+        if !should_select {
+            // Undo the selection on the bar.
+            let current: id = msg![env; this selectedViewController];
+            select(env, this, current);
+            return;
+        }
+    }
+
+    select(env, this, selected);
+
+    if delegate != nil && env.objc.object_has_method_named(
+        &env.mem,
+        delegate,
+        "tabBarController:didSelectViewController:",
+    ) {
+        () = msg![env; delegate tabBarController:this didSelectViewController:selected];
+    }
+}
+
+@end
+
+};
@@ -368,6 +368,16 @@ impl GuestFile {
             GuestFile::IpaBundleFile(_) | GuestFile::ResourceFile(_) => Ok(()),
         }
     }
+    /// Like [File::try_clone]. Only files that can be written to can be
+    /// cloned, since nothing else needs this.
+    pub fn try_clone(&self) -> std::io::Result<GuestFile> {
+        match self {
+            GuestFile::File(file) => file.try_clone().map(GuestFile::File),
+            GuestFile::IpaBundleFile(_) | GuestFile::ResourceFile(_) => {
+                Err(std::io::ErrorKind::Unsupported.into())
+            }
+        }
+    }
     pub fn set_len(&self, len: u64) -> std::io::Result<()> {
         match self {
             GuestFile::File(file) => file.set_len(len),
@@ -447,8 +457,8 @@ impl Fs {
     ///
     /// The `bundle_id` argument should be some value that uniquely identifies
     /// the app. This will be used to construct the host path for the app's
-    /// sandbox directory, where documents can be stored. A directory will be
-    /// created at that path if it does not already exist.
+    /// sandbox directory, where documents and preferences can be stored.
+    /// Directories will be created at that path if they do not already exist.
     ///
     /// `read_only_mode` can be used when the app won't actually be run, just
     /// just inspected (e.g. to retrieve display name and icon), so no user data
@@ -467,18 +477,22 @@ impl Fs {
 
         let bundle_guest_path = home_directory.join(&bundle_dir_name);
 
-        let documents_host_path = if !read_only_mode {
-            let path = paths::user_data_base_path()
+        let sandbox_host_paths = if !read_only_mode {
+            let sandbox_path = paths::user_data_base_path()
                 .join(paths::SANDBOX_DIR)
-                .join(bundle_id)
-                .join("Documents");
-            if let Err(e) = std::fs::create_dir_all(&path) {
-                panic!(
-                    "Could not create documents directory for app at {:?}: {:?}",
-                    path, e
-                );
+                .join(bundle_id);
+            let documents_path = sandbox_path.join("Documents");
+            let library_path = sandbox_path.join("Library");
+            // Library/Preferences is where NSUserDefaults stores its data.
+            for path in [&documents_path, &library_path.join("Preferences")] {
+                if let Err(e) = std::fs::create_dir_all(path) {
+                    panic!(
+                        "Could not create sandbox directory for app at {:?}: {:?}",
+                        path, e
+                    );
+                }
             }
-            Some(path)
+            Some((documents_path, library_path))
         } else {
             None
         };
@@ -502,11 +516,15 @@ impl Fs {
 
         let mut app_dir_children = HashMap::new();
         app_dir_children.insert(bundle_dir_name, app_bundle.into_fs_node());
-        if let Some(documents_host_path) = documents_host_path {
+        if let Some((documents_host_path, library_host_path)) = sandbox_host_paths {
             app_dir_children.insert(
                 "Documents".to_string(),
                 FsNode::from_host_dir(&documents_host_path, /* writeable: */ true),
             );
+            app_dir_children.insert(
+                "Library".to_string(),
+                FsNode::from_host_dir(&library_host_path, /* writeable: */ true),
+            );
         }
 
         let root = FsNode::dir()
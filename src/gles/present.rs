@@ -9,8 +9,19 @@
 use super::gles11_raw as gles11; // constants and types only
 use super::GLES;
 use crate::matrix::Matrix;
+use crate::options::Options;
 use std::time::{Duration, Instant};
 
+/// Get the interval in seconds between the simulated display's refreshes,
+/// which is what the framerate is limited to. This is based on the
+/// `--fps-limit=` option. [None] means there is no limit.
+///
+/// Everything that needs to match the display's timing (framerate limiting in
+/// `presentRenderbuffer:`, `CADisplayLink`) should use this.
+pub fn refresh_interval(options: &Options) -> Option<f64> {
+    options.fps_limit.map(|fps| 1.0 / fps)
+}
+
 pub struct FpsCounter {
     time: std::time::Instant,
     frames: u32,
@@ -42,6 +42,7 @@ mod gdb;
 mod gles;
 mod image;
 mod libc;
+mod libsqlite3;
 mod licenses;
 mod mach_o;
 mod matrix;
@@ -11,6 +11,7 @@
 
 mod generic_char;
 
+pub mod blocks;
 pub mod clocale;
 pub mod ctype;
 pub mod cxxabi;
@@ -42,11 +43,15 @@ pub mod wchar;
 /// Container for state of various child modules
 #[derive(Default)]
 pub struct State {
+    cxxabi: cxxabi::State,
     dirent: dirent::State,
+    dlfcn: dlfcn::State,
     keymgr: keymgr::State,
+    mmap: mmap::State,
     posix_io: posix_io::State,
     pthread: pthread::State,
     pub semaphore: semaphore::State,
+    signal: signal::State,
     stdlib: stdlib::State,
     string: string::State,
     time: time::State,
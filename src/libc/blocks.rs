@@ -0,0 +1,447 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `Block.h` (the blocks runtime).
+//!
+//! This is a minimal implementation of copying and releasing blocks, so that
+//! blocks the app passes to host code can be kept around for later. The
+//! layouts and flags are from Clang's "Block Implementation Specification"
+//! and Apple's libclosure. Copied blocks keep the `isa` of the original.
+
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::objc::{id, release, retain};
+use crate::Environment;
+
+const BLOCK_REFCOUNT_MASK: i32 = 0xfffe;
+const BLOCK_NEEDS_FREE: i32 = 1 << 24;
+const BLOCK_HAS_COPY_DISPOSE: i32 = 1 << 25;
+const BLOCK_IS_GLOBAL: i32 = 1 << 28;
+
+// Flags passed to _Block_object_assign() and _Block_object_dispose().
+const BLOCK_FIELD_IS_OBJECT: i32 = 3;
+const BLOCK_FIELD_IS_BLOCK: i32 = 7;
+const BLOCK_FIELD_IS_BYREF: i32 = 8;
+const BLOCK_FIELD_IS_WEAK: i32 = 16;
+const BLOCK_BYREF_CALLER: i32 = 128;
+
+/// The start of the in-memory layout of a block literal.
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct BlockLiteral {
+    isa: ConstVoidPtr,
+    flags: i32,
+    reserved: i32,
+    invoke: GuestFunction,
+    descriptor: ConstPtr<BlockDescriptor>,
+}
+unsafe impl SafeRead for BlockLiteral {}
+
+/// The start of a block descriptor. If the block has [BLOCK_HAS_COPY_DISPOSE]
+/// set, this is followed by the copy and dispose helpers.
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct BlockDescriptor {
+    reserved: GuestUSize,
+    size: GuestUSize,
+}
+unsafe impl SafeRead for BlockDescriptor {}
+
+/// The layout of a `__block` variable's storage, minus the variable itself.
+/// The helpers are only present if [BLOCK_HAS_COPY_DISPOSE] is set.
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct BlockByref {
+    isa: ConstVoidPtr,
+    forwarding: MutPtr<BlockByref>,
+    flags: i32,
+    size: i32,
+}
+unsafe impl SafeRead for BlockByref {}
+
+/// Get the copy and dispose helpers that follow a structure.
+fn helpers<T>(mem: &Mem, ptr: ConstPtr<T>) -> (GuestFunction, GuestFunction) {
+    let helpers: ConstPtr<GuestFunction> = (ptr + 1).cast();
+    (mem.read(helpers), mem.read(helpers + 1))
+}
+
+/// Get the function that implements a block, for calling it.
+pub fn block_invoke_function(env: &Environment, block: id) -> GuestFunction {
+    env.mem.read(block.cast::<BlockLiteral>()).invoke
+}
+
+/// The part of [_Block_copy] that doesn't call into the guest. If the block
+/// was moved to the heap and has a copy helper, that is also returned, and
+/// must be called with the new and old blocks.
+fn _Block_copy_inner(
+    mem: &mut Mem,
+    block: ConstPtr<BlockLiteral>,
+) -> (MutPtr<BlockLiteral>, Option<GuestFunction>) {
+    let literal = mem.read(block);
+    let flags = literal.flags;
+    if flags & BLOCK_NEEDS_FREE != 0 {
+        // Already on the heap, so just add a reference.
+        let flags_ptr: MutPtr<i32> = Ptr::from_bits(block.to_bits() + 4);
+        mem.write(flags_ptr, flags + 2);
+        return (block.cast_mut(), None);
+    }
+    if flags & BLOCK_IS_GLOBAL != 0 {
+        return (block.cast_mut(), None);
+    }
+
+    // The block is on the stack, so move it to the heap.
+    let descriptor = literal.descriptor;
+    let size = mem.read(descriptor).size;
+    let copy = mem.alloc(size);
+    mem.memmove(copy, block.cast(), size);
+    let copy: MutPtr<BlockLiteral> = copy.cast();
+    mem.write(
+        copy,
+        BlockLiteral {
+            flags: (flags & !BLOCK_REFCOUNT_MASK) | BLOCK_NEEDS_FREE | 2,
+            ..literal
+        },
+    );
+    let copy_helper = (flags & BLOCK_HAS_COPY_DISPOSE != 0).then(|| helpers(mem, descriptor).0);
+    (copy, copy_helper)
+}
+
+pub fn _Block_copy(env: &mut Environment, block: ConstVoidPtr) -> MutVoidPtr {
+    if block.is_null() {
+        return Ptr::null();
+    }
+    let (copy, copy_helper) = _Block_copy_inner(&mut env.mem, block.cast());
+    if let Some(copy_helper) = copy_helper {
+        () = copy_helper.call_from_host(env, (copy.cast_void(), block));
+    }
+    copy.cast()
+}
+
+/// The part of [_Block_release] that doesn't call into the guest. Returns
+/// `true` if the last reference was released, in which case the block must
+/// be disposed of and freed.
+fn _Block_release_inner(mem: &mut Mem, block: MutPtr<BlockLiteral>) -> bool {
+    let flags = mem.read(block).flags;
+    if flags & BLOCK_NEEDS_FREE == 0 || flags & BLOCK_REFCOUNT_MASK == 0 {
+        // Global and stack blocks aren't reference-counted.
+        return false;
+    }
+    let flags = flags - 2;
+    let flags_ptr: MutPtr<i32> = Ptr::from_bits(block.to_bits() + 4);
+    mem.write(flags_ptr, flags);
+    flags & BLOCK_REFCOUNT_MASK == 0
+}
+
+pub fn _Block_release(env: &mut Environment, block: ConstVoidPtr) {
+    if block.is_null() {
+        return;
+    }
+    let block: MutPtr<BlockLiteral> = block.cast_mut().cast();
+    if !_Block_release_inner(&mut env.mem, block) {
+        return;
+    }
+    let literal = env.mem.read(block);
+    if literal.flags & BLOCK_HAS_COPY_DISPOSE != 0 {
+        let (_, dispose_helper) = helpers(&env.mem, literal.descriptor);
+        () = dispose_helper.call_from_host(env, (block.cast_void(),));
+    }
+    env.mem.free(block.cast());
+}
+
+/// The part of [byref_copy] that doesn't call into the guest. Returns the
+/// variable's storage on the heap, and whether it was just moved there.
+fn byref_copy_inner(mem: &mut Mem, byref: MutPtr<BlockByref>) -> (MutPtr<BlockByref>, bool) {
+    let src = mem.read(byref);
+    let forwarding = src.forwarding;
+    let forwarding_flags = mem.read(forwarding).flags;
+    if forwarding_flags & BLOCK_NEEDS_FREE != 0 {
+        let flags_ptr: MutPtr<i32> = Ptr::from_bits(forwarding.to_bits() + 8);
+        mem.write(flags_ptr, forwarding_flags + 2);
+        return (forwarding, false);
+    }
+
+    let size = src.size as GuestUSize;
+    let copy: MutPtr<BlockByref> = mem.alloc(size).cast();
+    mem.memmove(copy.cast(), byref.cast_const().cast(), size);
+    // One reference for the original on the stack, and one for the caller.
+    mem.write(
+        copy,
+        BlockByref {
+            forwarding: copy,
+            flags: src.flags | BLOCK_NEEDS_FREE | 4,
+            ..src
+        },
+    );
+    mem.write(
+        byref,
+        BlockByref {
+            forwarding: copy,
+            ..src
+        },
+    );
+    (copy, true)
+}
+
+/// Move a `__block` variable to the heap if it isn't already there, and add a
+/// reference to it.
+fn byref_copy(env: &mut Environment, byref: MutPtr<BlockByref>) -> MutPtr<BlockByref> {
+    let (copy, moved) = byref_copy_inner(&mut env.mem, byref);
+    if moved && env.mem.read(byref).flags & BLOCK_HAS_COPY_DISPOSE != 0 {
+        let (keep_helper, _) = helpers(&env.mem, byref.cast_const());
+        () = keep_helper.call_from_host(env, (copy.cast_void(), byref.cast_void()));
+    }
+    copy
+}
+
+/// The part of [byref_release] that doesn't call into the guest. Returns the
+/// variable's storage on the heap if the last reference to it was released,
+/// in which case it must be destroyed and freed.
+fn byref_release_inner(mem: &mut Mem, byref: MutPtr<BlockByref>) -> Option<MutPtr<BlockByref>> {
+    let byref = mem.read(byref).forwarding;
+    let flags = mem.read(byref).flags;
+    if flags & BLOCK_NEEDS_FREE == 0 || flags & BLOCK_REFCOUNT_MASK == 0 {
+        return None;
+    }
+    let flags = flags - 2;
+    let flags_ptr: MutPtr<i32> = Ptr::from_bits(byref.to_bits() + 8);
+    mem.write(flags_ptr, flags);
+    (flags & BLOCK_REFCOUNT_MASK == 0).then_some(byref)
+}
+
+fn byref_release(env: &mut Environment, byref: MutPtr<BlockByref>) {
+    let Some(byref) = byref_release_inner(&mut env.mem, byref) else {
+        return;
+    };
+    if env.mem.read(byref).flags & BLOCK_HAS_COPY_DISPOSE != 0 {
+        let (_, destroy_helper) = helpers(&env.mem, byref.cast_const());
+        () = destroy_helper.call_from_host(env, (byref.cast_void(),));
+    }
+    env.mem.free(byref.cast());
+}
+
+/// What [_Block_object_assign] and [_Block_object_dispose] do with a field.
+#[derive(Debug, PartialEq, Eq)]
+enum FieldKind {
+    /// Copied as-is, without adding a reference.
+    Plain,
+    /// `__block` variable.
+    Byref,
+    Block,
+    /// Objective-C object, which is retained.
+    Object,
+}
+
+fn field_kind(flags: i32) -> Option<FieldKind> {
+    if flags & BLOCK_BYREF_CALLER != 0 {
+        // A __block variable holding an object or block: just copy it.
+        Some(FieldKind::Plain)
+    } else if flags & BLOCK_FIELD_IS_BYREF == BLOCK_FIELD_IS_BYREF {
+        Some(FieldKind::Byref)
+    } else if flags & BLOCK_FIELD_IS_BLOCK == BLOCK_FIELD_IS_BLOCK {
+        Some(FieldKind::Block)
+    } else if flags & BLOCK_FIELD_IS_OBJECT == BLOCK_FIELD_IS_OBJECT {
+        if flags & BLOCK_FIELD_IS_WEAK == 0 {
+            Some(FieldKind::Object)
+        } else {
+            Some(FieldKind::Plain)
+        }
+    } else {
+        None
+    }
+}
+
+/// Used by the copy helpers the compiler generates for blocks and `__block`
+/// variables.
+fn _Block_object_assign(env: &mut Environment, dest: MutPtr<u32>, object: u32, flags: i32) {
+    let new = match field_kind(flags) {
+        Some(FieldKind::Plain) => object,
+        Some(FieldKind::Byref) => byref_copy(env, Ptr::from_bits(object)).to_bits(),
+        Some(FieldKind::Block) => _Block_copy(env, Ptr::from_bits(object)).to_bits(),
+        Some(FieldKind::Object) => {
+            retain(env, Ptr::from_bits(object));
+            object
+        }
+        None => {
+            log!("TODO: _Block_object_assign() with flags {:#x}", flags);
+            object
+        }
+    };
+    env.mem.write(dest, new);
+}
+
+/// Used by the dispose helpers the compiler generates for blocks and
+/// `__block` variables.
+fn _Block_object_dispose(env: &mut Environment, object: u32, flags: i32) {
+    match field_kind(flags) {
+        // Counterpart of the plain copy in _Block_object_assign().
+        Some(FieldKind::Plain) => (),
+        Some(FieldKind::Byref) => byref_release(env, Ptr::from_bits(object)),
+        Some(FieldKind::Block) => _Block_release(env, Ptr::from_bits(object)),
+        Some(FieldKind::Object) => release(env, Ptr::from_bits(object)),
+        None => log!("TODO: _Block_object_dispose() with flags {:#x}", flags),
+    }
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(_Block_copy(_)),
+    export_c_func!(_Block_release(_)),
+    export_c_func!(_Block_object_assign(_, _, _)),
+    export_c_func!(_Block_object_dispose(_, _)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::guest_size_of;
+
+    const FLAGS_OFFSET: GuestUSize = 4;
+
+    /// Write a block literal with 4 bytes of captured data and the given
+    /// flags, and a descriptor with copy and dispose helpers, which aren't
+    /// called by the `_inner` functions.
+    fn write_block(mem: &mut Mem, flags: i32) -> MutPtr<BlockLiteral> {
+        let descriptor: MutPtr<GuestUSize> = mem.alloc(16).cast();
+        mem.write(descriptor, 0);
+        mem.write(descriptor + 1, guest_size_of::<BlockLiteral>() + 4);
+        mem.write(descriptor + 2, 0x1000); // copy helper
+        mem.write(descriptor + 3, 0x2000); // dispose helper
+        let block: MutPtr<BlockLiteral> = mem.alloc(guest_size_of::<BlockLiteral>() + 4).cast();
+        mem.write(
+            block,
+            BlockLiteral {
+                isa: Ptr::null(),
+                flags,
+                reserved: 0,
+                invoke: GuestFunction::from_addr_with_thumb_bit(0x3000),
+                descriptor: descriptor.cast().cast_const(),
+            },
+        );
+        mem.write((block + 1).cast::<u32>(), 0xcafe);
+        block
+    }
+
+    fn flags(mem: &Mem, ptr: MutPtr<BlockLiteral>) -> i32 {
+        let flags_ptr: ConstPtr<i32> = Ptr::from_bits(ptr.to_bits() + FLAGS_OFFSET);
+        mem.read(flags_ptr)
+    }
+
+    #[test]
+    fn copy_and_release_stack_block() {
+        let mut mem = Mem::new();
+        let block = write_block(&mut mem, 0);
+
+        let (copy, helper) = _Block_copy_inner(&mut mem, block.cast_const());
+        assert_ne!(copy, block);
+        assert!(helper.is_none());
+        assert_eq!(flags(&mem, copy), BLOCK_NEEDS_FREE | 2);
+        assert_eq!(mem.read((copy + 1).cast::<u32>()), 0xcafe);
+        let invoke = mem.read(copy).invoke;
+        assert_eq!(invoke.addr_with_thumb_bit(), 0x3000);
+        // The original isn't touched.
+        assert_eq!(flags(&mem, block), 0);
+
+        // Copying the copy adds a reference.
+        let (copy2, helper) = _Block_copy_inner(&mut mem, copy.cast_const());
+        assert_eq!(copy2, copy);
+        assert!(helper.is_none());
+        assert_eq!(flags(&mem, copy), BLOCK_NEEDS_FREE | 4);
+
+        assert!(!_Block_release_inner(&mut mem, copy));
+        assert_eq!(flags(&mem, copy), BLOCK_NEEDS_FREE | 2);
+        assert!(_Block_release_inner(&mut mem, copy));
+        assert_eq!(flags(&mem, copy), BLOCK_NEEDS_FREE);
+    }
+
+    #[test]
+    fn copy_stack_block_with_helpers() {
+        let mut mem = Mem::new();
+        let block = write_block(&mut mem, BLOCK_HAS_COPY_DISPOSE);
+        let (copy, helper) = _Block_copy_inner(&mut mem, block.cast_const());
+        assert_eq!(helper.unwrap().addr_with_thumb_bit(), 0x1000);
+        assert_eq!(
+            flags(&mem, copy),
+            BLOCK_HAS_COPY_DISPOSE | BLOCK_NEEDS_FREE | 2
+        );
+        // The helper is only needed when the block is moved.
+        let (_, helper) = _Block_copy_inner(&mut mem, copy.cast_const());
+        assert!(helper.is_none());
+    }
+
+    #[test]
+    fn global_and_stack_blocks_are_not_refcounted() {
+        let mut mem = Mem::new();
+        let global = write_block(&mut mem, BLOCK_IS_GLOBAL);
+        let (copy, helper) = _Block_copy_inner(&mut mem, global.cast_const());
+        assert_eq!(copy, global);
+        assert!(helper.is_none());
+        assert_eq!(flags(&mem, global), BLOCK_IS_GLOBAL);
+        assert!(!_Block_release_inner(&mut mem, global));
+        assert_eq!(flags(&mem, global), BLOCK_IS_GLOBAL);
+
+        let stack = write_block(&mut mem, 0);
+        assert!(!_Block_release_inner(&mut mem, stack));
+        assert_eq!(flags(&mem, stack), 0);
+    }
+
+    #[test]
+    fn copy_and_release_byref() {
+        let mut mem = Mem::new();
+        let size = guest_size_of::<BlockByref>() + 4;
+        let byref: MutPtr<BlockByref> = mem.alloc(size).cast();
+        mem.write(
+            byref,
+            BlockByref {
+                isa: Ptr::null(),
+                forwarding: byref,
+                flags: 0,
+                size: size as i32,
+            },
+        );
+        mem.write((byref + 1).cast::<u32>(), 0xbeef);
+
+        let (copy, moved) = byref_copy_inner(&mut mem, byref);
+        assert!(moved);
+        assert_ne!(copy, byref);
+        let (forwarding, copy_forwarding) = (mem.read(byref).forwarding, mem.read(copy).forwarding);
+        assert_eq!(forwarding, copy);
+        assert_eq!(copy_forwarding, copy);
+        assert_eq!(mem.read((copy + 1).cast::<u32>()), 0xbeef);
+        let copy_flags = mem.read(copy).flags;
+        assert_eq!(copy_flags, BLOCK_NEEDS_FREE | 4);
+
+        // Another block capturing the same variable, via the stack copy.
+        let (copy2, moved) = byref_copy_inner(&mut mem, byref);
+        assert!(!moved);
+        assert_eq!(copy2, copy);
+        let copy_flags = mem.read(copy).flags;
+        assert_eq!(copy_flags, BLOCK_NEEDS_FREE | 6);
+
+        assert_eq!(byref_release_inner(&mut mem, byref), None);
+        assert_eq!(byref_release_inner(&mut mem, copy), None);
+        assert_eq!(byref_release_inner(&mut mem, byref), Some(copy));
+    }
+
+    #[test]
+    fn object_field_flags() {
+        let object = BLOCK_FIELD_IS_OBJECT;
+        let block = BLOCK_FIELD_IS_BLOCK;
+        let byref = BLOCK_FIELD_IS_BYREF;
+        let weak = BLOCK_FIELD_IS_WEAK;
+        let caller = BLOCK_BYREF_CALLER;
+
+        assert_eq!(field_kind(object), Some(FieldKind::Object));
+        assert_eq!(field_kind(block), Some(FieldKind::Block));
+        assert_eq!(field_kind(byref), Some(FieldKind::Byref));
+        assert_eq!(field_kind(byref | weak), Some(FieldKind::Byref));
+        // Weak references aren't retained.
+        assert_eq!(field_kind(object | weak), Some(FieldKind::Plain));
+        // Copy helpers of __block variables just copy objects and blocks.
+        assert_eq!(field_kind(caller | object), Some(FieldKind::Plain));
+        assert_eq!(field_kind(caller | block), Some(FieldKind::Plain));
+        assert_eq!(field_kind(caller | object | weak), Some(FieldKind::Plain));
+        assert_eq!(field_kind(0), None);
+    }
+}
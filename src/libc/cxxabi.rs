@@ -5,13 +5,40 @@
  */
 //! `cxxabi.h`
 //!
+//! Most of the C++ runtime is provided by the real libstdc++ and libgcc_s
+//! bundled with touchHLE, including exception handling. The exception is
+//! unwinding through host functions: C++ exceptions on iPhone OS use
+//! `setjmp`/`longjmp`-based ("SJLJ") unwinding, where each function with a
+//! handler or cleanup registers a `SjLj_Function_Context` on a per-thread
+//! chain, and throwing an exception means jumping to the innermost context
+//! that wants to handle it. If there's a host function between the throw and
+//! that context, it must be unwound too, which the guest can't do.
+//!
+//! So `__cxa_throw` and `__cxa_rethrow` are hooked, and before an exception is
+//! thrown, a "sentinel" context is inserted into the chain after all the
+//! contexts belonging to the innermost host-to-guest call. Its personality
+//! routine claims to handle every exception. When the guest unwinder installs
+//! it, it lands in host code, which unwinds the host stack with
+//! [Environment::unwind_guest_to] and then re-raises the exception on the
+//! other side.
+//!
 //! Resources:
 //! - [Itanium C++ ABI specification](https://itanium-cxx-abi.github.io/cxx-abi/abi.html#dso-dtor-runtime-api)
+//! - [Itanium C++ ABI: Exception Handling](https://itanium-cxx-abi.github.io/cxx-abi/abi-eh.html)
+//! - GCC's `libgcc/unwind-sjlj.c`, for the layout of `SjLj_Function_Context`
 
-use crate::abi::GuestFunction;
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::cpu::Cpu;
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::mem::MutVoidPtr;
+use crate::mem::{GuestUSize, MutPtr, MutVoidPtr, Ptr};
 use crate::Environment;
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct State {
+    /// Guest functions created for [UNWIND_HELPERS], created on first use.
+    unwind_helpers: HashMap<&'static str, GuestFunction>,
+}
 
 fn __cxa_atexit(
     _env: &mut Environment,
@@ -38,3 +65,249 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(__cxa_atexit(_, _, _)),
     export_c_func!(__cxa_finalize(_)),
 ];
+
+fn __cxa_throw(
+    env: &mut Environment,
+    _thrown_exception: MutVoidPtr,
+    _tinfo: MutVoidPtr,
+    _dest: GuestFunction,
+) {
+    call_original_throw(env, "___cxa_throw");
+}
+
+fn __cxa_rethrow(env: &mut Environment) {
+    call_original_throw(env, "___cxa_rethrow");
+}
+
+/// Hooks for functions in the bundled libstdc++ (see
+/// [crate::dyld::Dyld::hooked_original]).
+pub const HOOKS: FunctionExports = &[
+    export_c_func!(__cxa_throw(_, _, _)),
+    export_c_func!(__cxa_rethrow()),
+];
+
+fn call_original_throw(env: &mut Environment, symbol: &str) {
+    preserving_regs(env, insert_unwind_sentinel);
+    // The registers are as they were when the guest called the hook, so this
+    // is a tail call with the same arguments.
+    let original = env.dyld.hooked_original(symbol).unwrap();
+    env.cpu.branch(original);
+}
+
+// Layout of `SjLj_Function_Context`, in words.
+const FC_SIZE: GuestUSize = 11 * 4;
+const FC_PREV: GuestUSize = 0;
+const FC_DATA: GuestUSize = 2;
+const FC_PERSONALITY: GuestUSize = 6;
+const FC_JBUF: GuestUSize = 8;
+
+// _Unwind_Action
+const _UA_SEARCH_PHASE: u32 = 1;
+const _UA_HANDLER_FRAME: u32 = 4;
+// _Unwind_Reason_Code
+const _URC_HANDLER_FOUND: u32 = 6;
+const _URC_INSTALL_CONTEXT: u32 = 7;
+const _URC_CONTINUE_UNWIND: u32 = 8;
+
+/// Look up a function exported by the bundled libgcc_s or libstdc++.
+fn guest_function(env: &Environment, symbol: &str) -> GuestFunction {
+    let addr = env
+        .bins
+        .iter()
+        .find_map(|bin| bin.exported_symbols.get(symbol).copied())
+        .unwrap_or_else(|| panic!("Couldn't find {} for C++ exception handling", symbol));
+    GuestFunction::from_addr_with_thumb_bit(addr)
+}
+
+fn unwind_helper(env: &mut Environment, symbol: &'static str) -> GuestFunction {
+    if let Some(&f) = env.libc_state.cxxabi.unwind_helpers.get(symbol) {
+        return f;
+    }
+    let &(symbol, f) = UNWIND_HELPERS.iter().find(|&&(s, _)| s == symbol).unwrap();
+    let f = env.dyld.create_guest_function(&mut env.mem, symbol, f);
+    env.cpu
+        .invalidate_cache_range(f.addr_without_thumb_bit(), 8);
+    env.libc_state.cxxabi.unwind_helpers.insert(symbol, f);
+    f
+}
+
+/// Run `f`, which calls guest functions, without clobbering the registers
+/// belonging to the guest code that called the current host function.
+fn preserving_regs<R>(env: &mut Environment, f: impl FnOnce(&mut Environment) -> R) -> R {
+    let regs = *env.cpu.regs();
+    let res = f(env);
+    // SP, LR and PC are restored by the calls themselves.
+    env.cpu.regs_mut()[..=12].copy_from_slice(&regs[..=12]);
+    res
+}
+
+fn register_fc(env: &mut Environment, fc: MutPtr<u32>) {
+    let register = guest_function(env, "__Unwind_SjLj_Register");
+    let () = register.call_from_host(env, (fc,));
+}
+fn unregister_fc(env: &mut Environment, fc: MutPtr<u32>) {
+    let unregister = guest_function(env, "__Unwind_SjLj_Unregister");
+    let () = unregister.call_from_host(env, (fc,));
+}
+fn get_prev(env: &Environment, fc: MutPtr<u32>) -> MutPtr<u32> {
+    env.mem.read((fc + FC_PREV).cast())
+}
+fn set_prev(env: &mut Environment, fc: MutPtr<u32>, prev: MutPtr<u32>) {
+    env.mem.write((fc + FC_PREV).cast(), prev)
+}
+
+/// Get the innermost registered `SjLj_Function_Context`. libgcc_s has no
+/// function for this, but registering a context reveals it.
+fn get_chain_head(env: &mut Environment) -> MutPtr<u32> {
+    let temp: MutPtr<u32> = env.mem.alloc(FC_SIZE).cast();
+    register_fc(env, temp);
+    let head = get_prev(env, temp);
+    unregister_fc(env, temp);
+    env.mem.free(temp.cast());
+    head
+}
+
+/// Insert a sentinel context (see module docs) for the innermost host-to-guest
+/// call on the current thread, if there is one and it doesn't have one yet.
+fn insert_unwind_sentinel(env: &mut Environment) {
+    let boundaries = env.guest_call_boundaries_mut();
+    let Some(boundary) = boundaries.last() else {
+        return;
+    };
+    if boundary.cxx_unwind_sentinel.is_some() {
+        return;
+    }
+    let boundary_sp = boundary.stack_pointer;
+    let other_sentinels: Vec<MutPtr<u32>> = boundaries
+        .iter()
+        .filter_map(|boundary| boundary.cxx_unwind_sentinel.map(|s| s.cast()))
+        .collect();
+
+    let personality = unwind_helper(env, "_sentinel_personality");
+    let landing = unwind_helper(env, "_sentinel_landing");
+    let sentinel: MutPtr<u32> = env.mem.alloc(FC_SIZE).cast();
+    env.mem
+        .write(sentinel + FC_PERSONALITY, personality.addr_with_thumb_bit());
+    // __builtin_setjmp buffer: frame pointer, label, stack pointer
+    env.mem
+        .write(sentinel + FC_JBUF + 1, landing.addr_with_thumb_bit());
+    env.mem.write(sentinel + FC_JBUF + 2, boundary_sp);
+
+    // Contexts registered inside the call are on the stack below the
+    // boundary, and the chain is ordered from innermost to outermost.
+    let mut last_inner = None;
+    let mut fc = get_chain_head(env);
+    while !fc.is_null() && fc.to_bits() < boundary_sp && !other_sentinels.contains(&fc) {
+        last_inner = Some(fc);
+        fc = get_prev(env, fc);
+    }
+    if let Some(last_inner) = last_inner {
+        set_prev(env, sentinel, fc);
+        set_prev(env, last_inner, sentinel);
+    } else {
+        register_fc(env, sentinel);
+    }
+    log_dbg!(
+        "Inserted C++ unwind sentinel {:?} for boundary at SP {:#x}",
+        sentinel,
+        boundary_sp
+    );
+
+    env.guest_call_boundaries_mut()
+        .last_mut()
+        .unwrap()
+        .cxx_unwind_sentinel = Some(sentinel.cast());
+}
+
+/// Remove a sentinel inserted by [insert_unwind_sentinel] from the current
+/// thread's chain and free it. This is called when the host-to-guest call it
+/// belongs to ends.
+pub fn remove_unwind_sentinel(env: &mut Environment, sentinel: MutVoidPtr) {
+    let sentinel: MutPtr<u32> = sentinel.cast();
+    preserving_regs(env, |env| {
+        let head = get_chain_head(env);
+        if head == sentinel {
+            unregister_fc(env, sentinel);
+            return;
+        }
+        let mut fc = head;
+        while !fc.is_null() {
+            let prev = get_prev(env, fc);
+            if prev == sentinel {
+                let sentinel_prev = get_prev(env, sentinel);
+                set_prev(env, fc, sentinel_prev);
+                break;
+            }
+            fc = prev;
+        }
+    });
+    log_dbg!("Removed C++ unwind sentinel {:?}", sentinel);
+    env.mem.free(sentinel.cast());
+}
+
+/// Personality routine of the sentinel context, which handles everything.
+fn sentinel_personality(
+    env: &mut Environment,
+    _version: i32,
+    actions: u32,
+    _exception_class_low: u32,
+    _exception_class_high: u32,
+    ue: MutVoidPtr,               // struct _Unwind_Exception *
+    context: MutPtr<MutPtr<u32>>, // struct _Unwind_Context *
+) -> u32 {
+    if actions & _UA_SEARCH_PHASE != 0 {
+        _URC_HANDLER_FOUND
+    } else if actions & _UA_HANDLER_FRAME != 0 {
+        // The context is just a pointer to the function context.
+        let fc = env.mem.read(context);
+        env.mem.write((fc + FC_DATA).cast(), ue);
+        _URC_INSTALL_CONTEXT
+    } else {
+        _URC_CONTINUE_UNWIND
+    }
+}
+
+/// Where the guest unwinder jumps to when it installs the sentinel context.
+fn sentinel_landing(env: &mut Environment) {
+    let boundary = env.guest_call_boundaries_mut().last().unwrap();
+    let boundary_sp = boundary.stack_pointer;
+    let sentinel: MutPtr<u32> = boundary.cxx_unwind_sentinel.unwrap().cast();
+    let ue: u32 = env.mem.read(sentinel + FC_DATA);
+    log_dbg!(
+        "C++ exception {:#x} reached host-to-guest call boundary at SP {:#x}, unwinding",
+        ue,
+        boundary_sp
+    );
+
+    env.unwind_guest_to(boundary_sp, move |env| {
+        env.cpu.regs_mut()[Cpu::SP] = boundary_sp;
+        insert_unwind_sentinel(env);
+        let raise = guest_function(env, "__Unwind_SjLj_RaiseException");
+        let uncaught = unwind_helper(env, "_sentinel_uncaught");
+        let regs = env.cpu.regs_mut();
+        regs[0] = ue;
+        // Callee-saved, so it's still there if _Unwind_SjLj_RaiseException
+        // returns.
+        regs[4] = ue;
+        regs[Cpu::LR] = uncaught.addr_with_thumb_bit();
+        env.cpu.branch(raise);
+    })
+}
+
+/// Where `_Unwind_SjLj_RaiseException` returns to if there's no handler for
+/// a re-raised exception. This does the same thing `__cxa_throw` would.
+fn sentinel_uncaught(env: &mut Environment) {
+    let ue: MutVoidPtr = Ptr::from_bits(env.cpu.regs()[4]);
+    let begin_catch = guest_function(env, "___cxa_begin_catch");
+    let _: MutVoidPtr = begin_catch.call_from_host(env, (ue,));
+    let terminate = guest_function(env, "__ZSt9terminatev");
+    env.cpu.branch(terminate);
+}
+
+/// Host functions that need to be callable by the guest unwinder. These aren't
+/// exported to the guest app.
+const UNWIND_HELPERS: FunctionExports = &[
+    export_c_func!(sentinel_personality(_, _, _, _, _, _)),
+    export_c_func!(sentinel_landing()),
+    export_c_func!(sentinel_uncaught()),
+];
@@ -5,46 +5,201 @@
  */
 //! `dlfcn.h` (`dlopen()` and friends)
 
-use crate::dyld::{export_c_func, FunctionExports};
-use crate::mem::{ConstPtr, MutVoidPtr, Ptr};
+use crate::dyld::{export_c_func, Dyld, FunctionExports};
+use crate::mem::{ConstPtr, MutPtr, MutVoidPtr, Ptr};
 use crate::Environment;
+use std::collections::HashMap;
 
-const ALLOWED_LIBRARIES: [Result<&str, &[u8]>; 2] = [
-    Ok("/usr/lib/libSystem.B.dylib"),
-    Ok("/System/Library/Frameworks/OpenAL.framework/OpenAL"),
-];
+const RTLD_NEXT: u32 = -1i32 as u32;
+const RTLD_DEFAULT: u32 = -2i32 as u32;
+const RTLD_SELF: u32 = -3i32 as u32;
+const RTLD_MAIN_ONLY: u32 = -5i32 as u32;
+
+/// What a handle returned by `dlopen()` refers to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Library {
+    /// A library with a host implementation, e.g. a system framework. All of
+    /// these share one handle.
+    Host,
+    /// A binary loaded from the guest filesystem (index into
+    /// [Environment::bins]).
+    Guest(usize),
+    /// `dlopen(NULL, ...)`: the global symbol namespace.
+    Global,
+}
+
+#[derive(Default)]
+pub struct State {
+    /// Handles currently open, with their reference counts. The handle is a
+    /// small allocation in guest memory, so that it's a unique pointer.
+    handles: HashMap<MutVoidPtr, (Library, u32)>,
+    /// Error message for the next call to `dlerror()`.
+    error: Option<String>,
+    /// String last returned by `dlerror()`, freed on the next call.
+    error_str: Option<MutPtr<u8>>,
+}
+
+fn set_error(env: &mut Environment, error: String) {
+    log_dbg!("dlfcn error: {}", error);
+    env.libc_state.dlfcn.error = Some(error);
+}
+
+fn get_handle(env: &mut Environment, library: Library) -> MutVoidPtr {
+    let existing = env
+        .libc_state
+        .dlfcn
+        .handles
+        .iter_mut()
+        .find(|(_, (other, _))| *other == library);
+    if let Some((&handle, (_, refcount))) = existing {
+        *refcount += 1;
+        return handle;
+    }
+    let handle = env.mem.alloc(1);
+    env.libc_state.dlfcn.handles.insert(handle, (library, 1));
+    handle
+}
 
 fn dlopen(env: &mut Environment, path: ConstPtr<u8>, _mode: i32) -> MutVoidPtr {
-    // TODO: dlopen() support for real dynamic libraries, and support for all
-    // libraries with host implementations.
-    assert!(ALLOWED_LIBRARIES.contains(&env.mem.cstr_at_utf8(path)));
-    // For convenience, use the path as the handle.
-    // TODO: Find out whether the handle is truly opaque on iPhone OS, and if
-    // not, where it points.
-    path.cast_mut().cast()
+    if path.is_null() {
+        return get_handle(env, Library::Global);
+    }
+
+    let path = env.mem.cstr_at_utf8(path).unwrap().to_string();
+    log_dbg!("dlopen({:?})", path);
+
+    if Dyld::is_host_library(&path) {
+        return get_handle(env, Library::Host);
+    }
+
+    let executable_path = env.bundle.executable_path();
+    let guest_path = env.dyld.expand_library_path(&path, &executable_path);
+    if env.fs.is_file(&guest_path) {
+        return match Dyld::load_library_at_runtime(env, &guest_path) {
+            Ok(idx) => get_handle(env, Library::Guest(idx)),
+            Err(e) => {
+                log!("Warning: dlopen() failed to load {:?}: {}", path, e);
+                set_error(env, format!("dlopen({}, 0): {}", path, e));
+                Ptr::null()
+            }
+        };
+    }
+
+    // System frameworks will have host implementations.
+    // TODO: check whether the framework is actually implemented?
+    if path.starts_with("/System/Library/Frameworks/") {
+        return get_handle(env, Library::Host);
+    }
+
+    set_error(env, format!("dlopen({}, 0): image not found", path));
+    Ptr::null()
+}
+
+/// Look up an exported symbol in a guest binary and its dependencies.
+fn find_guest_symbol(env: &Environment, idx: usize, symbol: &str) -> Option<u32> {
+    let mut to_search = vec![idx];
+    let mut searched = Vec::new();
+    while let Some(idx) = to_search.pop() {
+        if searched.contains(&idx) {
+            continue;
+        }
+        searched.push(idx);
+        if let Some(&addr) = env.bins[idx].exported_symbols.get(symbol) {
+            return Some(addr);
+        }
+        to_search.extend(env.dyld.bin_dependencies(idx).iter().rev());
+    }
+    None
 }
 
 fn dlsym(env: &mut Environment, handle: MutVoidPtr, symbol: ConstPtr<u8>) -> MutVoidPtr {
-    assert!(ALLOWED_LIBRARIES.contains(&env.mem.cstr_at_utf8(handle.cast())));
     // For some reason, the symbols passed to dlsym() don't have the leading _.
     let symbol = format!("_{}", env.mem.cstr_at_utf8(symbol).unwrap());
-    // TODO: error handling. dlsym() should just return NULL in this case, but
-    // currently it's probably more useful to have the emulator crash if there's
-    // no symbol found, since it most likely indicates a missing host function.
-    let addr = env
+
+    let library = match handle.to_bits() {
+        RTLD_DEFAULT => Library::Global,
+        RTLD_MAIN_ONLY => Library::Guest(0),
+        RTLD_NEXT | RTLD_SELF => {
+            // TODO: These depend on which binary the caller is in.
+            log!(
+                "TODO: dlsym() with RTLD_NEXT or RTLD_SELF, treating as RTLD_DEFAULT for {}",
+                symbol
+            );
+            Library::Global
+        }
+        _ => match env.libc_state.dlfcn.handles.get(&handle) {
+            Some(&(library, _)) => library,
+            None => {
+                set_error(
+                    env,
+                    format!("dlsym({:?}, {}): invalid handle", handle, symbol),
+                );
+                return Ptr::null();
+            }
+        },
+    };
+
+    let guest_addr = match library {
+        Library::Host => None,
+        Library::Guest(idx) => find_guest_symbol(env, idx, &symbol),
+        Library::Global => env
+            .bins
+            .iter()
+            .find_map(|bin| bin.exported_symbols.get(&symbol).copied()),
+    };
+    if let Some(addr) = guest_addr {
+        return Ptr::from_bits(addr);
+    }
+
+    // Guest libraries can depend on libraries with host implementations too,
+    // so those are always searched.
+    // TODO: Constants and other non-function symbols.
+    match env
         .dyld
         .create_proc_address(&mut env.mem, &mut env.cpu, &symbol)
-        .unwrap_or_else(|_| panic!("dlsym() for unimplemented function {}", symbol));
-    Ptr::from_bits(addr.addr_with_thumb_bit())
+    {
+        Ok(addr) => Ptr::from_bits(addr.addr_with_thumb_bit()),
+        Err(()) => {
+            log!("Warning: dlsym() for unknown symbol {}", symbol);
+            set_error(
+                env,
+                format!("dlsym({:?}, {}): symbol not found", handle, symbol),
+            );
+            Ptr::null()
+        }
+    }
 }
 
 fn dlclose(env: &mut Environment, handle: MutVoidPtr) -> i32 {
-    assert!(ALLOWED_LIBRARIES.contains(&env.mem.cstr_at_utf8(handle.cast())));
+    let Some((_, refcount)) = env.libc_state.dlfcn.handles.get_mut(&handle) else {
+        set_error(env, format!("dlclose({:?}): invalid handle", handle));
+        return -1;
+    };
+    *refcount -= 1;
+    if *refcount == 0 {
+        // Libraries are never actually unloaded, since they might still be in
+        // use via other handles or by other libraries.
+        env.libc_state.dlfcn.handles.remove(&handle);
+        env.mem.free(handle);
+    }
     0 // success
 }
 
+fn dlerror(env: &mut Environment) -> ConstPtr<u8> {
+    if let Some(old_str) = env.libc_state.dlfcn.error_str.take() {
+        env.mem.free(old_str.cast());
+    }
+    let Some(error) = env.libc_state.dlfcn.error.take() else {
+        return Ptr::null();
+    };
+    let error_str = env.mem.alloc_and_write_cstr(error.as_bytes());
+    env.libc_state.dlfcn.error_str = Some(error_str);
+    error_str.cast_const()
+}
+
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(dlopen(_, _)),
     export_c_func!(dlsym(_, _)),
     export_c_func!(dlclose(_)),
+    export_c_func!(dlerror()),
 ];
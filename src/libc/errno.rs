@@ -12,8 +12,12 @@ use crate::Environment;
 use std::io::Write;
 
 pub const EPERM: i32 = 1;
+pub const EBADF: i32 = 9;
 pub const EDEADLK: i32 = 11;
+pub const ENOMEM: i32 = 12;
+pub const EACCES: i32 = 13;
 pub const EBUSY: i32 = 16;
+pub const ENODEV: i32 = 19;
 pub const EINVAL: i32 = 22;
 
 #[derive(Default)]
@@ -28,7 +32,7 @@ impl State {
     ) -> MutPtr<i32> {
         *self.errnos.entry(thread).or_insert_with(|| {
             log!(
-                "TODO: errno accessed on thread {} (will always be 0)",
+                "TODO: errno accessed on thread {} (only set by a few functions)",
                 thread
             );
             mem.alloc_and_write(0i32)
@@ -36,6 +40,15 @@ impl State {
     }
 }
 
+/// Set `errno` for the current thread. Most functions don't do this yet.
+pub fn set_errno(env: &mut Environment, errno: i32) {
+    let ptr = env
+        .libc_state
+        .errno
+        .errno_for_thread(&mut env.mem, env.current_thread);
+    env.mem.write(ptr, errno);
+}
+
 fn __error(env: &mut Environment) -> MutPtr<i32> {
     env.libc_state
         .errno
@@ -3,38 +3,412 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+//! `sys/mman.h`
+//!
+//! Mappings are backed by regions of address space reserved with
+//! [crate::mem::Mem::reserve], so they never overlap memory from the
+//! allocator. File mappings read the whole range from the file up front. For
+//! shared mappings, changes are written back to the file on `msync()` and
+//! `munmap()`, but changes to the file are never reflected in the mapping.
 
 use crate::dyld::FunctionExports;
 use crate::environment::Environment;
 use crate::export_c_func;
+use crate::fs::GuestFile;
+use crate::libc::errno::{set_errno, EACCES, EBADF, EINVAL, ENODEV, ENOMEM};
 use crate::libc::posix_io;
-use crate::libc::posix_io::{off_t, FileDescriptor, SEEK_SET};
-use crate::mem::{GuestUSize, MutVoidPtr};
+use crate::libc::posix_io::{off_t, FileDescriptor};
+use crate::mem::{ConstPtr, GuestUSize, MutPtr, MutVoidPtr, Protection, Ptr, VAddr, PAGE_SIZE};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
 
-#[allow(dead_code)]
-const MAP_FILE: i32 = 0x0000;
+const PROT_WRITE: i32 = 0x2;
+
+const MAP_SHARED: i32 = 0x0001;
+const MAP_PRIVATE: i32 = 0x0002;
+const MAP_FIXED: i32 = 0x0010;
 const MAP_ANON: i32 = 0x1000;
 
-/// Our implementation of mmap is really simple: it's just load entirety of
-/// file in memory!
+fn map_failed() -> MutVoidPtr {
+    Ptr::from_bits(!0)
+}
+
+#[derive(Default)]
+pub struct State {
+    /// Mappings made by [mmap], keyed by base address.
+    mappings: BTreeMap<VAddr, Mapping>,
+}
+
+struct Mapping {
+    /// Always a multiple of the page size.
+    size: GuestUSize,
+    /// Only present for shared mappings of files.
+    shared_file: Option<SharedFile>,
+}
+
+#[derive(Clone)]
+struct SharedFile {
+    /// This is shared because [munmap] can split a mapping in two.
+    file: Rc<RefCell<GuestFile>>,
+    /// Offset in the file of the start of the mapping.
+    offset: u64,
+}
+
+/// Total size of the current mappings. This counts towards the emulated
+/// device's memory usage (see [crate::frameworks::uikit::ui_application]).
+pub fn mapped_bytes(env: &Environment) -> GuestUSize {
+    env.libc_state
+        .mmap
+        .mappings
+        .values()
+        .map(|mapping| mapping.size)
+        .sum()
+}
+
+/// Round a length up to a whole number of pages.
+fn page_round_up(len: GuestUSize) -> Option<GuestUSize> {
+    len.checked_next_multiple_of(PAGE_SIZE)
+}
+
+/// Read up to `len` bytes at `offset` without changing the file position,
+/// which `mmap()` must not do.
+fn read_at(file: &mut GuestFile, offset: u64, len: GuestUSize) -> std::io::Result<Vec<u8>> {
+    let old_position = file.stream_position()?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::new();
+    let res = file.by_ref().take(len.into()).read_to_end(&mut data);
+    file.seek(SeekFrom::Start(old_position))?;
+    res.map(|_| data)
+}
+
+/// Write `data` at `offset` without changing the file position. Like on a real
+/// system, the part of a mapping that is past the end of the file is not
+/// written.
+fn write_at(file: &mut GuestFile, offset: u64, data: &[u8]) -> std::io::Result<()> {
+    let old_position = file.stream_position()?;
+    let file_len = file.seek(SeekFrom::End(0))?;
+    let data = &data[..file_len.saturating_sub(offset).min(data.len() as u64) as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    let res = file.write_all(data);
+    file.seek(SeekFrom::Start(old_position))?;
+    res
+}
+
 fn mmap(
     env: &mut Environment,
     addr: MutVoidPtr,
     len: GuestUSize,
-    _prot: i32,
+    prot: i32,
     flags: i32,
     fd: FileDescriptor,
     offset: off_t,
 ) -> MutVoidPtr {
-    assert!(addr.is_null());
-    assert_eq!(offset, 0);
-    assert_eq!((flags & MAP_ANON), 0);
-    let new_offset = posix_io::lseek(env, fd, offset, SEEK_SET);
-    assert_eq!(new_offset, offset);
-    let ptr = env.mem.alloc(len);
-    let read = posix_io::read(env, fd, ptr, len);
-    assert_eq!(read as u32, len);
-    ptr
-}
-
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(mmap(_, _, _, _, _, _))];
+    match mmap_inner(env, addr, len, prot, flags, fd, offset) {
+        Ok(base) => {
+            let res = Ptr::from_bits(base);
+            log_dbg!(
+                "mmap({:?}, {:#x}, {:#x}, {:#x}, {}, {:#x}) => {:?}",
+                addr,
+                len,
+                prot,
+                flags,
+                fd,
+                offset,
+                res
+            );
+            res
+        }
+        Err(errno) => {
+            log!(
+                "Warning: mmap({:?}, {:#x}, {:#x}, {:#x}, {}, {:#x}) failed with errno {}",
+                addr,
+                len,
+                prot,
+                flags,
+                fd,
+                offset,
+                errno
+            );
+            set_errno(env, errno);
+            map_failed()
+        }
+    }
+}
+
+fn mmap_inner(
+    env: &mut Environment,
+    addr: MutVoidPtr,
+    len: GuestUSize,
+    prot: i32,
+    flags: i32,
+    fd: FileDescriptor,
+    offset: off_t,
+) -> Result<VAddr, i32> {
+    if len == 0 || offset < 0 || offset % off_t::from(PAGE_SIZE) != 0 {
+        return Err(EINVAL);
+    }
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return Err(EINVAL),
+    };
+    let size = page_round_up(len).ok_or(ENOMEM)?;
+
+    // The file is read before reserving anything, so there's nothing to clean
+    // up if it fails.
+    let (data, shared_file) = if (flags & MAP_ANON) != 0 {
+        (Vec::new(), None)
+    } else {
+        let (file, writable) = posix_io::guest_file_for_fd(env, fd).ok_or(EBADF)?;
+        let offset = offset as u64;
+        let data = read_at(file, offset, len).map_err(|_| ENODEV)?;
+        // Changes can only be written back if the file was opened for writing,
+        // but a read-only shared mapping of a read-only file is fine.
+        let shared_file = if !shared || !writable {
+            if shared && (prot & PROT_WRITE) != 0 {
+                return Err(EACCES);
+            }
+            None
+        } else {
+            let file = file.try_clone().map_err(|_| ENODEV)?;
+            Some(SharedFile {
+                file: Rc::new(RefCell::new(file)),
+                offset,
+            })
+        };
+        (data, shared_file)
+    };
+
+    let base = if (flags & MAP_FIXED) != 0 {
+        let base = addr.to_bits();
+        if base % PAGE_SIZE != 0 || base.checked_add(size).is_none() {
+            return Err(EINVAL);
+        }
+        // A fixed mapping replaces whatever was there before, but only if that
+        // was made by mmap(). Nothing is unmapped if this check fails.
+        if !can_replace(env, base, size) {
+            log!(
+                "Warning: mmap() can't replace memory at {:#x} ({:#x} bytes) that it doesn't own",
+                base,
+                size
+            );
+            return Err(ENOMEM);
+        }
+        unmap(env, base, size);
+        env.mem.reserve(base, size);
+        base
+    } else if !addr.is_null()
+        && addr.to_bits() % PAGE_SIZE == 0
+        && env.mem.can_reserve(addr.to_bits(), size)
+    {
+        env.mem.reserve(addr.to_bits(), size);
+        addr.to_bits()
+    } else {
+        env.mem.reserve_anywhere(size, PAGE_SIZE).ok_or(ENOMEM)?
+    };
+
+    // Unused memory is always zeroed, so only the file's data needs writing.
+    let data_len = data.len().try_into().unwrap();
+    env.mem
+        .bytes_at_mut(MutPtr::from_bits(base), data_len)
+        .copy_from_slice(&data);
+    env.mem
+        .set_protection_by_app(base, size, Protection::from_bits_truncate(prot as u32));
+
+    env.libc_state
+        .mmap
+        .mappings
+        .insert(base, Mapping { size, shared_file });
+    Ok(base)
+}
+
+/// Base addresses of mappings that overlap the range.
+fn overlapping_mappings(env: &Environment, base: VAddr, size: GuestUSize) -> Vec<VAddr> {
+    let end = base + size;
+    env.libc_state
+        .mmap
+        .mappings
+        .range(..end)
+        .filter(|&(&mapping_base, mapping)| mapping_base + mapping.size > base)
+        .map(|(&mapping_base, _)| mapping_base)
+        .collect()
+}
+
+/// Whether every part of the range is either unused or part of a mapping, i.e.
+/// whether it can be reserved once those mappings have been removed. The range
+/// must be page-aligned and not overflow.
+fn can_replace(env: &Environment, base: VAddr, size: GuestUSize) -> bool {
+    let end = base + size;
+    let mut gap_start = base;
+    for mapping_base in overlapping_mappings(env, base, size) {
+        let mapping_end = mapping_base + env.libc_state.mmap.mappings[&mapping_base].size;
+        if gap_start < mapping_base && !env.mem.can_reserve(gap_start, mapping_base - gap_start) {
+            return false;
+        }
+        gap_start = gap_start.max(mapping_end);
+    }
+    gap_start >= end || env.mem.can_reserve(gap_start, end - gap_start)
+}
+
+/// Write back the part of a shared file mapping that is within the range.
+fn write_back(env: &Environment, mapping_base: VAddr, base: VAddr, size: GuestUSize) {
+    let mapping = &env.libc_state.mmap.mappings[&mapping_base];
+    let Some(ref shared_file) = mapping.shared_file else {
+        return;
+    };
+    let start = base.max(mapping_base);
+    let end = (base + size).min(mapping_base + mapping.size);
+    let data = env.mem.bytes_at(ConstPtr::from_bits(start), end - start);
+    let offset = shared_file.offset + u64::from(start - mapping_base);
+    if let Err(e) = write_at(&mut shared_file.file.borrow_mut(), offset, data) {
+        log!(
+            "Warning: couldn't write back mapping at {:#x} ({:#x} bytes) to file: {}",
+            start,
+            end - start,
+            e
+        );
+    }
+}
+
+/// Write back and remove all parts of mappings within the range. The range
+/// must be page-aligned and not overflow.
+fn unmap(env: &mut Environment, base: VAddr, size: GuestUSize) {
+    let end = base + size;
+    for mapping_base in overlapping_mappings(env, base, size) {
+        write_back(env, mapping_base, base, size);
+
+        let mapping = env.libc_state.mmap.mappings.remove(&mapping_base).unwrap();
+        let mapping_end = mapping_base + mapping.size;
+        let start = base.max(mapping_base);
+        let end = end.min(mapping_end);
+        env.mem.unreserve(start, end - start);
+
+        // Keep whatever is left on either side.
+        let mappings = &mut env.libc_state.mmap.mappings;
+        if mapping_base < start {
+            mappings.insert(
+                mapping_base,
+                Mapping {
+                    size: start - mapping_base,
+                    shared_file: mapping.shared_file.clone(),
+                },
+            );
+        }
+        if end < mapping_end {
+            mappings.insert(
+                end,
+                Mapping {
+                    size: mapping_end - end,
+                    shared_file: mapping.shared_file.map(|shared_file| SharedFile {
+                        offset: shared_file.offset + u64::from(end - mapping_base),
+                        ..shared_file
+                    }),
+                },
+            );
+        }
+    }
+}
+
+fn munmap(env: &mut Environment, addr: MutVoidPtr, len: GuestUSize) -> i32 {
+    let base = addr.to_bits();
+    let Some(size) = page_round_up(len)
+        .filter(|&size| base % PAGE_SIZE == 0 && size != 0 && base.checked_add(size).is_some())
+    else {
+        log!(
+            "Warning: munmap({:?}, {:#x}) failed, returning -1",
+            addr,
+            len
+        );
+        set_errno(env, EINVAL);
+        return -1;
+    };
+    log_dbg!("munmap({:?}, {:#x})", addr, len);
+    // Unmapping pages that aren't mapped isn't an error.
+    unmap(env, base, size);
+    0
+}
+
+fn msync(env: &mut Environment, addr: MutVoidPtr, len: GuestUSize, flags: i32) -> i32 {
+    let base = addr.to_bits();
+    if base % PAGE_SIZE != 0 {
+        log!(
+            "Warning: msync({:?}, {:#x}, {:#x}) failed, returning -1",
+            addr,
+            len,
+            flags
+        );
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    let Some(size) = page_round_up(len).filter(|&size| base.checked_add(size).is_some()) else {
+        set_errno(env, ENOMEM);
+        return -1;
+    };
+
+    // Every page in the range must be mapped.
+    let mappings = overlapping_mappings(env, base, size);
+    let mut next = base;
+    for &mapping_base in &mappings {
+        if mapping_base > next {
+            break;
+        }
+        next = next.max(mapping_base + env.libc_state.mmap.mappings[&mapping_base].size);
+    }
+    if next < base + size {
+        log!(
+            "Warning: msync({:?}, {:#x}, {:#x}) on unmapped memory, returning -1",
+            addr,
+            len,
+            flags
+        );
+        set_errno(env, ENOMEM);
+        return -1;
+    }
+
+    // The flags don't matter, since writing back is always synchronous and
+    // the mapping never sees changes to the file anyway.
+    log_dbg!("msync({:?}, {:#x}, {:#x})", addr, len, flags);
+    for mapping_base in mappings {
+        write_back(env, mapping_base, base, size);
+    }
+    0
+}
+
+/// Change the protection of some pages. Note that only accesses by guest code
+/// are checked (see [crate::mem::Protection]).
+fn mprotect(env: &mut Environment, addr: MutVoidPtr, len: GuestUSize, prot: i32) -> i32 {
+    let protection = Protection::from_bits_truncate(prot as u32);
+    log_dbg!("mprotect({:?}, {:#x}, {:?})", addr, len, protection);
+    let errno = if addr.to_bits() % PAGE_SIZE != 0 {
+        EINVAL
+    } else if addr.to_bits().checked_add(len).is_none() {
+        ENOMEM
+    } else {
+        env.mem
+            .set_protection_by_app(addr.to_bits(), len, protection);
+        return 0;
+    };
+    log!(
+        "Warning: mprotect() with invalid range {:?} ({:#x} bytes)",
+        addr,
+        len
+    );
+    set_errno(env, errno);
+    -1
+}
+
+/// Advice is only advice, so this does nothing.
+fn madvise(_env: &mut Environment, addr: MutVoidPtr, len: GuestUSize, advice: i32) -> i32 {
+    log_dbg!("madvise({:?}, {:#x}, {})", addr, len, advice);
+    0
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(mmap(_, _, _, _, _, _)),
+    export_c_func!(munmap(_, _)),
+    export_c_func!(msync(_, _, _)),
+    export_c_func!(mprotect(_, _, _)),
+    export_c_func!(madvise(_, _, _)),
+];
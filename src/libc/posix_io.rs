@@ -30,6 +30,8 @@ impl State {
 struct PosixFileHostObject {
     file: GuestFile,
     reached_eof: bool,
+    /// Whether the file was opened with `O_WRONLY` or `O_RDWR`.
+    writable: bool,
 }
 
 // TODO: stdin/stdout/stderr handling somehow
@@ -146,6 +148,7 @@ pub fn open_direct(env: &mut Environment, path: ConstPtr<u8>, flags: i32) -> Fil
             let host_object = PosixFileHostObject {
                 file,
                 reached_eof: false,
+                writable: (flags & O_ACCMODE) != O_RDONLY,
             };
 
             let idx = if let Some(free_idx) = env
@@ -232,6 +235,18 @@ pub fn read(
     }
 }
 
+/// Helper for `mmap()`: get the [GuestFile] for a file descriptor, and
+/// whether it was opened for writing.
+pub(super) fn guest_file_for_fd(
+    env: &mut Environment,
+    fd: FileDescriptor,
+) -> Option<(&mut GuestFile, bool)> {
+    env.libc_state
+        .posix_io
+        .file_for_fd(fd)
+        .map(|file| (&mut file.file, file.writable))
+}
+
 /// Helper for C `feof()`.
 pub(super) fn eof(env: &mut Environment, fd: FileDescriptor) -> i32 {
     let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
@@ -5,26 +5,122 @@
  */
 //! `setjmp.h`.
 //!
-//! We don't have a real implementation for this right now. It could be quite
-//! tricky to write one, considering that we would need to unwind through host
-//! code, and somehow do so selectively since we have a mix of stack frames from
-//! different guest threads. For the moment, we simply pray the app never throws
-//! exceptions.
+//! Saving the state is easy, since the guest registers are exactly as they
+//! were in the caller when a host function is called. Jumping back is the hard
+//! part, because there might be host stack frames in between the `longjmp` and
+//! the `setjmp`, and because the host stack contains a mix of stack frames
+//! from different guest threads. That is handled by
+//! [Environment::unwind_guest_to].
 //!
 //! Note that `setjmp` and `longjmp` are defined as macros in the C standard,
 //! but it seems like the implementation of these on iPhone OS uses real
-//! functions, at least for the former.
+//! functions.
 
+use crate::abi::GuestFunction;
+use crate::cpu::Cpu;
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::signal;
+use crate::mem::MutPtr;
 use crate::Environment;
 
-/// The signature of this is incomplete because it's a stub (see module docs).
-fn setjmp(env: &mut Environment) -> i32 {
-    log_dbg!(
-        "TODO: setjmp() at {:#x}",
-        env.cpu.regs()[crate::cpu::Cpu::LR]
-    );
+/// `jmp_buf` is an array of `_JBLEN` (28 on ARM) `int`s, which decays to a
+/// pointer when passed to a function. We use only part of it:
+/// - words 0 to 7: R4 to R11
+/// - word 8: SP
+/// - word 9: LR
+/// - words 10 to 25: S16 to S31 (VFP)
+/// - word 26: whether the signal mask was saved
+#[allow(non_camel_case_types)]
+pub type jmp_buf = MutPtr<u32>;
+
+const JB_R4: u32 = 0;
+const JB_SP: u32 = 8;
+const JB_LR: u32 = 9;
+const JB_S16: u32 = 10;
+const JB_SAVEMASK: u32 = 26;
+
+fn save_context(env: &mut Environment, buf: jmp_buf) {
+    let regs = *env.cpu.regs();
+    for (i, &reg) in regs[4..=11].iter().enumerate() {
+        env.mem.write(buf + JB_R4 + i as u32, reg);
+    }
+    env.mem.write(buf + JB_SP, regs[Cpu::SP]);
+    env.mem.write(buf + JB_LR, regs[Cpu::LR]);
+    let vfp_regs = *env.cpu.extregs();
+    for (i, &reg) in vfp_regs[16..32].iter().enumerate() {
+        env.mem.write(buf + JB_S16 + i as u32, reg);
+    }
+}
+
+fn setjmp(env: &mut Environment, buf: jmp_buf) -> i32 {
+    log_dbg!("setjmp({:?})", buf);
+    save_context(env, buf);
+    // Signal masks are mostly not emulated, but see restore_signal_mask().
+    env.mem.write(buf + JB_SAVEMASK, 1);
+    0 // no longjmp() was performed
+}
+
+fn _setjmp(env: &mut Environment, buf: jmp_buf) -> i32 {
+    log_dbg!("_setjmp({:?})", buf);
+    save_context(env, buf);
+    env.mem.write(buf + JB_SAVEMASK, 0);
+    0 // no longjmp() was performed
+}
+
+fn sigsetjmp(env: &mut Environment, buf: jmp_buf, savemask: i32) -> i32 {
+    log_dbg!("sigsetjmp({:?}, {})", buf, savemask);
+    save_context(env, buf);
+    env.mem.write(buf + JB_SAVEMASK, (savemask != 0).into());
     0 // no longjmp() was performed
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(setjmp())];
+/// Restore the context saved by `setjmp` and make it return `val` (or 1 if
+/// `val` is 0). This never returns to the caller.
+pub fn do_longjmp(env: &mut Environment, buf: jmp_buf, val: i32) -> ! {
+    log_dbg!("longjmp({:?}, {})", buf, val);
+
+    let mut regs = [0u32; 10];
+    for (i, reg) in regs.iter_mut().enumerate() {
+        *reg = env.mem.read(buf + i as u32);
+    }
+    let mut vfp_regs = [0u32; 16];
+    for (i, reg) in vfp_regs.iter_mut().enumerate() {
+        *reg = env.mem.read(buf + JB_S16 + i as u32);
+    }
+    let sp = regs[JB_SP as usize];
+    let lr = regs[JB_LR as usize];
+
+    env.unwind_guest_to(sp, move |env| {
+        let cpu_regs = env.cpu.regs_mut();
+        cpu_regs[4..=11].copy_from_slice(&regs[..8]);
+        cpu_regs[Cpu::SP] = sp;
+        cpu_regs[Cpu::LR] = lr;
+        cpu_regs[0] = if val == 0 { 1 } else { val as u32 };
+        env.cpu.extregs_mut()[16..32].copy_from_slice(&vfp_regs);
+        env.cpu.branch(GuestFunction::from_addr_with_thumb_bit(lr));
+    })
+}
+
+fn longjmp(env: &mut Environment, buf: jmp_buf, val: i32) {
+    if env.mem.read(buf + JB_SAVEMASK) != 0 {
+        signal::restore_signal_mask(env);
+    }
+    do_longjmp(env, buf, val)
+}
+
+fn _longjmp(env: &mut Environment, buf: jmp_buf, val: i32) {
+    do_longjmp(env, buf, val)
+}
+
+fn siglongjmp(env: &mut Environment, buf: jmp_buf, val: i32) {
+    longjmp(env, buf, val)
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(setjmp(_)),
+    export_c_func!(_setjmp(_)),
+    export_c_func!(sigsetjmp(_, _)),
+    export_c_func!(longjmp(_, _)),
+    export_c_func!(_longjmp(_, _)),
+    export_c_func!(siglongjmp(_, _)),
+];
@@ -3,15 +3,182 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+//! `signal.h`
+//!
+//! Signals are mostly not emulated. The exception is that handlers for
+//! `SIGBUS` and `SIGSEGV` are called when the app makes a memory access that
+//! the protection it set with `mprotect()` or `mmap()` doesn't allow, which
+//! apps can use to recover from the fault with `siglongjmp()`. Other memory
+//! errors, like null pointer dereferences, are always reported by touchHLE
+//! itself.
 
+use crate::abi::{CallFromHost, GuestFunction};
 use crate::dyld::FunctionExports;
 use crate::environment::Environment;
 use crate::export_c_func;
-use crate::mem::{ConstVoidPtr, MutVoidPtr};
+use crate::mem::{guest_size_of, ConstPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use std::collections::HashMap;
 
-fn sigaction(_env: &mut Environment, signum: i32, act: ConstVoidPtr, oldact: MutVoidPtr) -> i32 {
-    log!("TODO: sigaction({:?}, {:?}, {:?})", signum, act, oldact);
-    0
+pub const SIGBUS: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+
+const SIG_DFL: u32 = 0;
+const SIG_IGN: u32 = 1;
+
+/// `si_code` for both [SIGBUS] (`BUS_ADRERR`) and [SIGSEGV] (`SEGV_ACCERR`).
+const SI_CODE_ACCESS_ERROR: i32 = 2;
+
+#[derive(Default)]
+pub struct State {
+    /// Handlers for [SIGBUS] and [SIGSEGV], keyed by signal number.
+    fault_handlers: HashMap<i32, GuestFunction>,
+    /// The fault that [call_memory_fault_handler] should deliver next.
+    pending_fault: Option<(i32, GuestFunction, GuestUSize)>,
+    /// Whether a fault handler is running. The signal is blocked meanwhile,
+    /// like on a real device, so a fault inside the handler isn't delivered.
+    /// Signal masks aren't otherwise emulated, so this applies to all threads.
+    in_fault_handler: bool,
+    /// `siginfo_t` passed to fault handlers, allocated on first use.
+    siginfo: Option<MutPtr<siginfo_t>>,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+struct struct_sigaction {
+    /// Either `sa_handler` or `sa_sigaction`, or [SIG_DFL] or [SIG_IGN].
+    handler: u32,
+    mask: u32,
+    flags: i32,
+}
+unsafe impl SafeRead for struct_sigaction {}
+
+#[allow(non_camel_case_types)]
+#[derive(Default)]
+#[repr(C, packed)]
+struct siginfo_t {
+    si_signo: i32,
+    si_errno: i32,
+    si_code: i32,
+    si_pid: i32,
+    si_uid: u32,
+    si_status: i32,
+    si_addr: u32,
+    si_value: u32,
+    si_band: i32,
+    __pad: [u32; 7],
+}
+unsafe impl SafeRead for siginfo_t {}
+fn sigaction(
+    env: &mut Environment,
+    signum: i32,
+    act: ConstPtr<struct_sigaction>,
+    oldact: MutPtr<struct_sigaction>,
+) -> i32 {
+    if signum != SIGBUS && signum != SIGSEGV {
+        log!("TODO: sigaction({:?}, {:?}, {:?})", signum, act, oldact);
+        return 0;
+    }
+
+    let handlers = &mut env.libc_state.signal.fault_handlers;
+    if !oldact.is_null() {
+        let handler = handlers
+            .get(&signum)
+            .map_or(SIG_DFL, |handler| handler.addr_with_thumb_bit());
+        env.mem.write(
+            oldact,
+            struct_sigaction {
+                handler,
+                mask: 0,
+                flags: 0,
+            },
+        );
+    }
+    if !act.is_null() {
+        let struct_sigaction { handler, .. } = env.mem.read(act);
+        log_dbg!("sigaction({}, handler {:#x})", signum, handler);
+        if handler == SIG_DFL || handler == SIG_IGN {
+            handlers.remove(&signum);
+        } else {
+            handlers.insert(signum, GuestFunction::from_addr_with_thumb_bit(handler));
+        }
+    }
+    0 // success
+}
+
+/// For use by [Environment] when the CPU reports a memory access the page
+/// protection doesn't allow. Returns [true] if the fault should be delivered
+/// to the app with [call_memory_fault_handler]: the faulting page must have
+/// been protected by the app itself, the app must have installed a handler,
+/// and no handler can already be running. `SIGBUS` is what iPhone OS sends for
+/// accesses to protected pages, so that takes priority.
+pub fn prepare_memory_fault(env: &mut Environment) -> bool {
+    let Some(fault_addr) = env.mem.take_app_protection_fault() else {
+        return false;
+    };
+    let state = &mut env.libc_state.signal;
+    if state.in_fault_handler {
+        log!(
+            "Memory fault at {:#x} inside a fault handler, not calling it again",
+            fault_addr
+        );
+        return false;
+    }
+    let Some((signum, handler)) = [SIGBUS, SIGSEGV].into_iter().find_map(|signum| {
+        state
+            .fault_handlers
+            .get(&signum)
+            .map(|&handler| (signum, handler))
+    }) else {
+        return false;
+    };
+    state.pending_fault = Some((signum, handler, fault_addr));
+    true
+}
+
+/// Call the handler for the fault prepared by [prepare_memory_fault], from
+/// within a host function so that it can leave with `siglongjmp()`. The
+/// faulting instruction can't be retried, so it's an error for the handler to
+/// return.
+pub fn call_memory_fault_handler(env: &mut Environment) {
+    let (signum, handler, fault_addr) = env.libc_state.signal.pending_fault.take().unwrap();
+    log!(
+        "Memory fault at {:#x}, calling signal handler {:?} for signal {}",
+        fault_addr,
+        handler,
+        signum
+    );
+
+    let siginfo = match env.libc_state.signal.siginfo {
+        Some(siginfo) => siginfo,
+        None => {
+            let siginfo = env.mem.alloc(guest_size_of::<siginfo_t>()).cast();
+            env.libc_state.signal.siginfo = Some(siginfo);
+            siginfo
+        }
+    };
+    env.mem.write(
+        siginfo,
+        siginfo_t {
+            si_signo: signum,
+            si_code: SI_CODE_ACCESS_ERROR,
+            si_addr: fault_addr,
+            ..Default::default()
+        },
+    );
+    // The ucontext isn't provided. Handlers that use it to resume execution
+    // couldn't work anyway, since the instruction can't be retried.
+    let ucontext: MutVoidPtr = Ptr::null();
+
+    // Handlers with and without SA_SIGINFO can both be called this way.
+    env.libc_state.signal.in_fault_handler = true;
+    () = handler.call_from_host(env, (signum, siginfo, ucontext));
+    panic!("Signal handler for memory fault returned, execution can't continue");
+}
+
+/// For use by `longjmp()` and `siglongjmp()`: leaving a fault handler by
+/// restoring a signal mask saved before it was called unblocks the signal.
+pub fn restore_signal_mask(env: &mut Environment) {
+    env.libc_state.signal.in_fault_handler = false;
 }
 
 pub const FUNCTIONS: FunctionExports = &[export_c_func!(sigaction(_, _, _))];
@@ -7,6 +7,7 @@
 
 use crate::abi::{CallFromHost, GuestFunction};
 use crate::dyld::{export_c_func, export_c_func_aliased, FunctionExports};
+use crate::frameworks::foundation::ns_user_defaults;
 use crate::libc::posix_io::getcwd;
 use crate::libc::string::{strcpy, strlen};
 use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr};
@@ -181,8 +182,10 @@ fn setenv(env: &mut Environment, name: ConstPtr<u8>, value: ConstPtr<u8>, overwr
     0 // success
 }
 
-fn exit(_env: &mut Environment, exit_code: i32) {
+fn exit(env: &mut Environment, exit_code: i32) {
     echo!("App called exit(), exiting.");
+    ns_user_defaults::synchronize_standard_defaults(env);
+    env.print_allocator_leak_report();
     std::process::exit(exit_code);
 }
 
@@ -247,24 +247,99 @@ fn gmtime_r(env: &mut Environment, timestamp: ConstPtr<time_t>, res: MutPtr<tm>)
     env.mem.write(res, calendar_date);
     res
 }
-fn gmtime(env: &mut Environment, timestamp: ConstPtr<time_t>) -> MutPtr<tm> {
-    let tmp = *env
-        .libc_state
+fn gmtime_tmp(env: &mut Environment) -> MutPtr<tm> {
+    *env.libc_state
         .time
         .gmtime_tmp
-        .get_or_insert_with(|| env.mem.alloc(guest_size_of::<tm>()).cast());
+        .get_or_insert_with(|| env.mem.alloc(guest_size_of::<tm>()).cast())
+}
+fn gmtime(env: &mut Environment, timestamp: ConstPtr<time_t>) -> MutPtr<tm> {
+    let tmp = gmtime_tmp(env);
     gmtime_r(env, timestamp, tmp)
 }
 
+/// Get the host's offset from UTC in seconds (positive east of Greenwich) at
+/// some point in time, and whether daylight saving time is in effect then.
+/// If the host can't tell us, this is UTC.
+pub fn host_utc_offset(timestamp: i64) -> (i32, bool) {
+    use std::ffi::c_int;
+
+    // Only the fields that every platform's `struct tm` starts with are used,
+    // but some platforms have more, so leave room for those.
+    #[allow(non_camel_case_types)]
+    #[repr(C)]
+    #[derive(Default)]
+    struct host_tm {
+        tm_sec: c_int,
+        tm_min: c_int,
+        tm_hour: c_int,
+        tm_mday: c_int,
+        tm_mon: c_int,
+        tm_year: c_int,
+        tm_wday: c_int,
+        tm_yday: c_int,
+        tm_isdst: c_int,
+        _extra: [u64; 4],
+    }
+
+    let mut local = host_tm::default();
+    let mut utc = host_tm::default();
+
+    #[cfg(unix)]
+    let success = unsafe {
+        extern "C" {
+            fn localtime_r(timep: *const std::ffi::c_long, result: *mut host_tm) -> *mut host_tm;
+            fn gmtime_r(timep: *const std::ffi::c_long, result: *mut host_tm) -> *mut host_tm;
+        }
+        // This truncates on hosts with a 32-bit time_t, but those have bigger
+        // problems.
+        let timestamp = timestamp as std::ffi::c_long;
+        !localtime_r(&timestamp, &mut local).is_null() && !gmtime_r(&timestamp, &mut utc).is_null()
+    };
+    #[cfg(windows)]
+    let success = unsafe {
+        extern "C" {
+            fn _localtime64_s(result: *mut host_tm, timep: *const i64) -> c_int;
+            fn _gmtime64_s(result: *mut host_tm, timep: *const i64) -> c_int;
+        }
+        _localtime64_s(&mut local, &timestamp) == 0 && _gmtime64_s(&mut utc, &timestamp) == 0
+    };
+    #[cfg(not(any(unix, windows)))]
+    let success = false;
+
+    if !success {
+        return (0, false);
+    }
+
+    // The two dates can be at most a day apart, possibly across a year
+    // boundary.
+    let days = match local.tm_year.cmp(&utc.tm_year) {
+        std::cmp::Ordering::Less => -1,
+        std::cmp::Ordering::Equal => local.tm_yday - utc.tm_yday,
+        std::cmp::Ordering::Greater => 1,
+    };
+    let hours = days * 24 + local.tm_hour - utc.tm_hour;
+    let minutes = hours * 60 + local.tm_min - utc.tm_min;
+    let seconds = minutes * 60 + local.tm_sec - utc.tm_sec;
+    (seconds, local.tm_isdst > 0)
+}
+
 fn localtime_r(env: &mut Environment, timestamp: ConstPtr<time_t>, res: MutPtr<tm>) -> MutPtr<tm> {
-    // TODO: don't assume local time is UTC?
-    gmtime_r(env, timestamp, res)
+    let timestamp = env.mem.read(timestamp);
+    let (offset, is_dst) = host_utc_offset(timestamp.into());
+    let calendar_date = tm {
+        tm_isdst: is_dst.into(),
+        tm_gmtoff: offset,
+        ..timestamp_to_calendar_date(timestamp.saturating_add(offset))
+    };
+    env.mem.write(res, calendar_date);
+    res
 }
 fn localtime(env: &mut Environment, timestamp: ConstPtr<time_t>) -> MutPtr<tm> {
-    // TODO: don't assume local time is UTC?
     // This doesn't have to be a unique temporary, gmtime and localtime are
     // allowed to share it.
-    gmtime(env, timestamp)
+    let tmp = gmtime_tmp(env);
+    localtime_r(env, timestamp, tmp)
 }
 
 // sys/time.h (POSIX)
@@ -0,0 +1,780 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Our implementation of `libsqlite3.dylib`, the system copy of SQLite.
+//!
+//! This wraps a host copy of SQLite (bundled via the `libsqlite3-sys` crate).
+//! Files are accessed through the guest filesystem using a custom VFS (see
+//! [vfs]), so apps can open databases in their bundle or sandbox as normal.
+//!
+//! Guest code never sees host pointers: `sqlite3*` and `sqlite3_stmt*` are
+//! opaque guest allocations that are mapped to the host objects, and strings
+//! and blobs returned by SQLite are copied into guest memory. Their lifetimes
+//! follow the rules in SQLite's documentation, i.e. column values are valid
+//! until the next step, reset or finalize of the statement.
+//!
+//! Only the commonly-used parts of the C API are implemented so far.
+//!
+//! Resources:
+//! - [C/C++ Interface For SQLite Version 3](https://www.sqlite.org/capi3ref.html)
+
+mod vfs;
+
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::{
+    guest_size_of, ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead,
+};
+use crate::Environment;
+use libsqlite3_sys as ffi;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+
+#[derive(Default)]
+pub struct State {
+    databases: HashMap<Sqlite3Ptr, DatabaseHostObject>,
+    statements: HashMap<Sqlite3StmtPtr, StatementHostObject>,
+    /// Guest copy of the last string returned by `sqlite3_errmsg` for a `NULL`
+    /// or invalid database handle.
+    invalid_db_errmsg: MutPtr<u8>,
+}
+
+struct DatabaseHostObject {
+    db: *mut ffi::sqlite3,
+    /// Guest copy of the last string returned by `sqlite3_errmsg`.
+    errmsg: MutPtr<u8>,
+}
+
+struct StatementHostObject {
+    stmt: *mut ffi::sqlite3_stmt,
+    /// Guest copies of column values for the current row, freed when the
+    /// statement is stepped, reset or finalized.
+    column_values: Vec<MutVoidPtr>,
+    /// Guest copies of column names, freed when the statement is finalized.
+    column_names: HashMap<i32, MutPtr<u8>>,
+}
+
+#[repr(C, packed)]
+pub struct OpaqueSqlite3 {
+    _filler: u8,
+}
+unsafe impl SafeRead for OpaqueSqlite3 {}
+
+pub type Sqlite3Ptr = MutPtr<OpaqueSqlite3>;
+
+#[repr(C, packed)]
+pub struct OpaqueSqlite3Stmt {
+    _filler: u8,
+}
+unsafe impl SafeRead for OpaqueSqlite3Stmt {}
+
+pub type Sqlite3StmtPtr = MutPtr<OpaqueSqlite3Stmt>;
+
+/// `sqlite3_destructor_type` values with special meanings.
+const SQLITE_STATIC: u32 = 0;
+const SQLITE_TRANSIENT: u32 = u32::MAX;
+
+/// Get the host database for a guest handle. Returns [None] for `NULL` and
+/// for handles that were never opened or have been closed, which the caller
+/// should report as `SQLITE_MISUSE` or similar, like SQLite does.
+fn host_db(env: &Environment, db: Sqlite3Ptr) -> Option<*mut ffi::sqlite3> {
+    let host_db = env.libsqlite3_state.databases.get(&db).map(|host| host.db);
+    if host_db.is_none() && !db.is_null() {
+        log!("Warning: invalid SQLite database handle {:?}", db);
+    }
+    host_db
+}
+
+/// Get the host statement for a guest handle. Like [host_db], returns [None]
+/// for `NULL` and for handles that aren't valid.
+fn host_stmt(env: &Environment, stmt: Sqlite3StmtPtr) -> Option<*mut ffi::sqlite3_stmt> {
+    let host_stmt = env
+        .libsqlite3_state
+        .statements
+        .get(&stmt)
+        .map(|host| host.stmt);
+    if host_stmt.is_none() && !stmt.is_null() {
+        log!("Warning: invalid SQLite statement handle {:?}", stmt);
+    }
+    host_stmt
+}
+
+fn free_column_values(env: &mut Environment, stmt: Sqlite3StmtPtr) {
+    let values = std::mem::take(
+        &mut env
+            .libsqlite3_state
+            .statements
+            .get_mut(&stmt)
+            .unwrap()
+            .column_values,
+    );
+    for value in values {
+        env.mem.free(value);
+    }
+}
+
+/// Copy a NUL-terminated host string into a new guest allocation.
+fn copy_cstr_to_guest(env: &mut Environment, s: *const std::os::raw::c_char) -> MutPtr<u8> {
+    if s.is_null() {
+        return Ptr::null();
+    }
+    let bytes = unsafe { CStr::from_ptr(s) }.to_bytes();
+    env.mem.alloc_and_write_cstr(bytes)
+}
+
+fn sqlite3_open(env: &mut Environment, filename: ConstPtr<u8>, pp_db: MutPtr<Sqlite3Ptr>) -> i32 {
+    sqlite3_open_v2(
+        env,
+        filename,
+        pp_db,
+        ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE,
+        Ptr::null(),
+    )
+}
+
+fn sqlite3_open_v2(
+    env: &mut Environment,
+    filename: ConstPtr<u8>,
+    pp_db: MutPtr<Sqlite3Ptr>,
+    flags: i32,
+    z_vfs: ConstPtr<u8>,
+) -> i32 {
+    if !z_vfs.is_null() {
+        log!(
+            "Warning: ignoring request for SQLite VFS {:?}",
+            env.mem.cstr_at_utf8(z_vfs)
+        );
+    }
+    let filename = CString::new(env.mem.cstr_at(filename)).unwrap();
+    let mut db = std::ptr::null_mut();
+    let res = vfs::with_fs(&mut env.fs, || unsafe {
+        ffi::sqlite3_open_v2(
+            filename.as_ptr(),
+            &mut db,
+            flags,
+            vfs::VFS_NAME.as_ptr().cast(),
+        )
+    });
+    log_dbg!("sqlite3_open_v2({:?}, {:#x}) => {}", filename, flags, res);
+
+    if db.is_null() {
+        // Only happens if SQLite runs out of memory.
+        env.mem.write(pp_db, Ptr::null());
+        return res;
+    }
+    // Like the real SQLite, a handle is returned even if opening failed, so
+    // the app can get the error message.
+    let db_ref = env.mem.alloc_and_write(OpaqueSqlite3 { _filler: 0 });
+    env.libsqlite3_state.databases.insert(
+        db_ref,
+        DatabaseHostObject {
+            db,
+            errmsg: Ptr::null(),
+        },
+    );
+    env.mem.write(pp_db, db_ref);
+    res
+}
+
+fn sqlite3_close(env: &mut Environment, db: Sqlite3Ptr) -> i32 {
+    if db.is_null() {
+        return ffi::SQLITE_OK;
+    }
+    let Some(host_db) = host_db(env, db) else {
+        return ffi::SQLITE_MISUSE;
+    };
+    let res = vfs::with_fs(&mut env.fs, || unsafe { ffi::sqlite3_close(host_db) });
+    if res != ffi::SQLITE_OK {
+        // Probably SQLITE_BUSY due to unfinalized statements. The handle is
+        // still valid in that case.
+        return res;
+    }
+    let host_object = env.libsqlite3_state.databases.remove(&db).unwrap();
+    if !host_object.errmsg.is_null() {
+        env.mem.free(host_object.errmsg.cast());
+    }
+    env.mem.free(db.cast());
+    res
+}
+
+fn sqlite3_exec(
+    env: &mut Environment,
+    db: Sqlite3Ptr,
+    sql: ConstPtr<u8>,
+    // (*int)(void *arg, int column_count, char **values, char **names)
+    callback: GuestFunction,
+    callback_arg: MutVoidPtr,
+    errmsg: MutPtr<MutPtr<u8>>,
+) -> i32 {
+    let Some(host_db) = host_db(env, db) else {
+        return ffi::SQLITE_MISUSE;
+    };
+    let sql_bytes = env.mem.cstr_at(sql).to_vec();
+    let mut remaining: &[u8] = &sql_bytes;
+
+    let mut res = ffi::SQLITE_OK;
+    while !remaining.is_empty() {
+        let mut stmt = std::ptr::null_mut();
+        let mut tail = std::ptr::null();
+        res = vfs::with_fs(&mut env.fs, || unsafe {
+            ffi::sqlite3_prepare_v2(
+                host_db,
+                remaining.as_ptr().cast(),
+                remaining.len().try_into().unwrap(),
+                &mut stmt,
+                &mut tail,
+            )
+        });
+        if res != ffi::SQLITE_OK {
+            break;
+        }
+        let consumed: usize = unsafe { tail.cast::<u8>().offset_from(remaining.as_ptr()) }
+            .try_into()
+            .unwrap();
+        remaining = &remaining[consumed..];
+        if stmt.is_null() {
+            // Whitespace or a comment.
+            continue;
+        }
+
+        loop {
+            res = vfs::with_fs(&mut env.fs, || unsafe { ffi::sqlite3_step(stmt) });
+            if res != ffi::SQLITE_ROW {
+                break;
+            }
+            if callback.to_ptr().is_null() {
+                continue;
+            }
+
+            let column_count = unsafe { ffi::sqlite3_column_count(stmt) };
+            let mut strings = Vec::new();
+            for i in 0..column_count {
+                let value = unsafe { ffi::sqlite3_column_text(stmt, i) };
+                strings.push(copy_cstr_to_guest(env, value.cast()));
+            }
+            for i in 0..column_count {
+                let name = unsafe { ffi::sqlite3_column_name(stmt, i) };
+                strings.push(copy_cstr_to_guest(env, name));
+            }
+            let count: GuestUSize = column_count.try_into().unwrap();
+            let array_size = count.max(1) * guest_size_of::<MutPtr<u8>>();
+            let values_array: MutPtr<MutPtr<u8>> = env.mem.alloc(array_size).cast();
+            let names_array: MutPtr<MutPtr<u8>> = env.mem.alloc(array_size).cast();
+            for i in 0..count {
+                env.mem.write(values_array + i, strings[i as usize]);
+                env.mem
+                    .write(names_array + i, strings[(count + i) as usize]);
+            }
+
+            let abort: i32 = callback
+                .call_from_host(env, (callback_arg, column_count, values_array, names_array));
+
+            env.mem.free(values_array.cast());
+            env.mem.free(names_array.cast());
+            for string in strings {
+                if !string.is_null() {
+                    env.mem.free(string.cast());
+                }
+            }
+
+            if abort != 0 {
+                res = ffi::SQLITE_ABORT;
+                break;
+            }
+        }
+        vfs::with_fs(&mut env.fs, || unsafe { ffi::sqlite3_finalize(stmt) });
+        if res == ffi::SQLITE_DONE {
+            res = ffi::SQLITE_OK;
+        } else {
+            break;
+        }
+    }
+
+    if !errmsg.is_null() {
+        let msg = if res == ffi::SQLITE_OK {
+            Ptr::null()
+        } else if res == ffi::SQLITE_ABORT {
+            env.mem.alloc_and_write_cstr(b"query aborted")
+        } else {
+            copy_cstr_to_guest(env, unsafe { ffi::sqlite3_errmsg(host_db) })
+        };
+        env.mem.write(errmsg, msg);
+    }
+    res
+}
+
+fn sqlite3_free(env: &mut Environment, ptr: MutVoidPtr) {
+    if !ptr.is_null() {
+        env.mem.free(ptr);
+    }
+}
+
+fn sqlite3_prepare(
+    env: &mut Environment,
+    db: Sqlite3Ptr,
+    sql: ConstPtr<u8>,
+    n_byte: i32,
+    pp_stmt: MutPtr<Sqlite3StmtPtr>,
+    pz_tail: MutPtr<ConstPtr<u8>>,
+) -> i32 {
+    // The legacy interface differs only in error reporting for schema
+    // changes, which shouldn't matter here.
+    sqlite3_prepare_v2(env, db, sql, n_byte, pp_stmt, pz_tail)
+}
+
+fn sqlite3_prepare_v2(
+    env: &mut Environment,
+    db: Sqlite3Ptr,
+    sql: ConstPtr<u8>,
+    n_byte: i32,
+    pp_stmt: MutPtr<Sqlite3StmtPtr>,
+    pz_tail: MutPtr<ConstPtr<u8>>,
+) -> i32 {
+    let Some(host_db) = host_db(env, db) else {
+        if !pp_stmt.is_null() {
+            env.mem.write(pp_stmt, Ptr::null());
+        }
+        return ffi::SQLITE_MISUSE;
+    };
+    let sql_bytes = if n_byte < 0 {
+        env.mem.cstr_at(sql).to_vec()
+    } else {
+        let bytes = env.mem.bytes_at(sql, n_byte.try_into().unwrap());
+        let len = bytes
+            .iter()
+            .position(|&c| c == b'\0')
+            .unwrap_or(bytes.len());
+        bytes[..len].to_vec()
+    };
+
+    let mut stmt = std::ptr::null_mut();
+    let mut tail = std::ptr::null();
+    let res = vfs::with_fs(&mut env.fs, || unsafe {
+        ffi::sqlite3_prepare_v2(
+            host_db,
+            sql_bytes.as_ptr().cast(),
+            sql_bytes.len().try_into().unwrap(),
+            &mut stmt,
+            &mut tail,
+        )
+    });
+    log_dbg!(
+        "sqlite3_prepare_v2({:?}, {:?}) => {}",
+        db,
+        String::from_utf8_lossy(&sql_bytes),
+        res
+    );
+
+    if !pz_tail.is_null() {
+        let consumed: GuestUSize = if tail.is_null() {
+            sql_bytes.len().try_into().unwrap()
+        } else {
+            let consumed = unsafe { tail.cast::<u8>().offset_from(sql_bytes.as_ptr()) };
+            consumed.try_into().unwrap()
+        };
+        env.mem.write(pz_tail, sql + consumed);
+    }
+
+    let stmt_ref = if stmt.is_null() {
+        Ptr::null()
+    } else {
+        let stmt_ref = env.mem.alloc_and_write(OpaqueSqlite3Stmt { _filler: 0 });
+        env.libsqlite3_state.statements.insert(
+            stmt_ref,
+            StatementHostObject {
+                stmt,
+                column_values: Vec::new(),
+                column_names: HashMap::new(),
+            },
+        );
+        stmt_ref
+    };
+    env.mem.write(pp_stmt, stmt_ref);
+    res
+}
+
+fn sqlite3_step(env: &mut Environment, stmt: Sqlite3StmtPtr) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return ffi::SQLITE_MISUSE;
+    };
+    free_column_values(env, stmt);
+    vfs::with_fs(&mut env.fs, || unsafe { ffi::sqlite3_step(host_stmt) })
+}
+
+fn sqlite3_reset(env: &mut Environment, stmt: Sqlite3StmtPtr) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return ffi::SQLITE_MISUSE;
+    };
+    free_column_values(env, stmt);
+    vfs::with_fs(&mut env.fs, || unsafe { ffi::sqlite3_reset(host_stmt) })
+}
+
+fn sqlite3_finalize(env: &mut Environment, stmt: Sqlite3StmtPtr) -> i32 {
+    if stmt.is_null() {
+        return ffi::SQLITE_OK;
+    }
+    if host_stmt(env, stmt).is_none() {
+        return ffi::SQLITE_MISUSE;
+    }
+    free_column_values(env, stmt);
+    let host_object = env.libsqlite3_state.statements.remove(&stmt).unwrap();
+    for (_, name) in host_object.column_names {
+        env.mem.free(name.cast());
+    }
+    env.mem.free(stmt.cast());
+    vfs::with_fs(&mut env.fs, || unsafe {
+        ffi::sqlite3_finalize(host_object.stmt)
+    })
+}
+
+fn sqlite3_clear_bindings(env: &mut Environment, stmt: Sqlite3StmtPtr) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return ffi::SQLITE_MISUSE;
+    };
+    unsafe { ffi::sqlite3_clear_bindings(host_stmt) }
+}
+
+fn sqlite3_bind_int(env: &mut Environment, stmt: Sqlite3StmtPtr, index: i32, value: i32) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return ffi::SQLITE_MISUSE;
+    };
+    unsafe { ffi::sqlite3_bind_int(host_stmt, index, value) }
+}
+
+fn sqlite3_bind_int64(env: &mut Environment, stmt: Sqlite3StmtPtr, index: i32, value: i64) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return ffi::SQLITE_MISUSE;
+    };
+    unsafe { ffi::sqlite3_bind_int64(host_stmt, index, value) }
+}
+
+fn sqlite3_bind_double(env: &mut Environment, stmt: Sqlite3StmtPtr, index: i32, value: f64) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return ffi::SQLITE_MISUSE;
+    };
+    unsafe { ffi::sqlite3_bind_double(host_stmt, index, value) }
+}
+
+fn sqlite3_bind_null(env: &mut Environment, stmt: Sqlite3StmtPtr, index: i32) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return ffi::SQLITE_MISUSE;
+    };
+    unsafe { ffi::sqlite3_bind_null(host_stmt, index) }
+}
+
+/// The host copy of SQLite always gets its own copy of bound data, so the
+/// guest's destructor (if any) can be called straight away.
+fn call_destructor(env: &mut Environment, data: ConstVoidPtr, destructor: GuestFunction) {
+    let addr = destructor.addr_with_thumb_bit();
+    if addr != SQLITE_STATIC && addr != SQLITE_TRANSIENT {
+        () = destructor.call_from_host(env, (data,));
+    }
+}
+
+fn sqlite3_bind_text(
+    env: &mut Environment,
+    stmt: Sqlite3StmtPtr,
+    index: i32,
+    text: ConstPtr<u8>,
+    n_byte: i32,
+    // (*void)(void *)
+    destructor: GuestFunction,
+) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        call_destructor(env, text.cast(), destructor);
+        return ffi::SQLITE_MISUSE;
+    };
+    let res = if text.is_null() {
+        unsafe { ffi::sqlite3_bind_null(host_stmt, index) }
+    } else {
+        let bytes = if n_byte < 0 {
+            env.mem.cstr_at(text)
+        } else {
+            env.mem.bytes_at(text, n_byte.try_into().unwrap())
+        };
+        unsafe {
+            ffi::sqlite3_bind_text(
+                host_stmt,
+                index,
+                bytes.as_ptr().cast(),
+                bytes.len().try_into().unwrap(),
+                ffi::SQLITE_TRANSIENT(),
+            )
+        }
+    };
+    call_destructor(env, text.cast(), destructor);
+    res
+}
+
+fn sqlite3_bind_blob(
+    env: &mut Environment,
+    stmt: Sqlite3StmtPtr,
+    index: i32,
+    blob: ConstVoidPtr,
+    n_byte: i32,
+    // (*void)(void *)
+    destructor: GuestFunction,
+) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        call_destructor(env, blob, destructor);
+        return ffi::SQLITE_MISUSE;
+    };
+    let res = if blob.is_null() {
+        unsafe { ffi::sqlite3_bind_null(host_stmt, index) }
+    } else if n_byte < 0 {
+        // Unlike with text, a negative length isn't meaningful here. SQLite
+        // itself only checks for this if built with SQLITE_ENABLE_API_ARMOR.
+        log!(
+            "Warning: sqlite3_bind_blob() called with negative length {}",
+            n_byte
+        );
+        ffi::SQLITE_MISUSE
+    } else {
+        let bytes = env.mem.bytes_at(blob.cast(), n_byte.try_into().unwrap());
+        unsafe {
+            ffi::sqlite3_bind_blob(
+                host_stmt,
+                index,
+                bytes.as_ptr().cast(),
+                n_byte,
+                ffi::SQLITE_TRANSIENT(),
+            )
+        }
+    };
+    call_destructor(env, blob, destructor);
+    res
+}
+
+fn sqlite3_bind_parameter_count(env: &mut Environment, stmt: Sqlite3StmtPtr) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return 0;
+    };
+    unsafe { ffi::sqlite3_bind_parameter_count(host_stmt) }
+}
+
+fn sqlite3_bind_parameter_index(
+    env: &mut Environment,
+    stmt: Sqlite3StmtPtr,
+    name: ConstPtr<u8>,
+) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return 0;
+    };
+    let name = CString::new(env.mem.cstr_at(name)).unwrap();
+    unsafe { ffi::sqlite3_bind_parameter_index(host_stmt, name.as_ptr()) }
+}
+
+fn sqlite3_column_count(env: &mut Environment, stmt: Sqlite3StmtPtr) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return 0;
+    };
+    unsafe { ffi::sqlite3_column_count(host_stmt) }
+}
+
+fn sqlite3_data_count(env: &mut Environment, stmt: Sqlite3StmtPtr) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return 0;
+    };
+    unsafe { ffi::sqlite3_data_count(host_stmt) }
+}
+
+fn sqlite3_column_type(env: &mut Environment, stmt: Sqlite3StmtPtr, column: i32) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return ffi::SQLITE_NULL;
+    };
+    unsafe { ffi::sqlite3_column_type(host_stmt, column) }
+}
+
+fn sqlite3_column_name(env: &mut Environment, stmt: Sqlite3StmtPtr, column: i32) -> ConstPtr<u8> {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return Ptr::null();
+    };
+    if let Some(&name) = env.libsqlite3_state.statements[&stmt]
+        .column_names
+        .get(&column)
+    {
+        return name.cast_const();
+    }
+    let name = unsafe { ffi::sqlite3_column_name(host_stmt, column) };
+    let name = copy_cstr_to_guest(env, name);
+    if !name.is_null() {
+        env.libsqlite3_state
+            .statements
+            .get_mut(&stmt)
+            .unwrap()
+            .column_names
+            .insert(column, name);
+    }
+    name.cast_const()
+}
+
+fn sqlite3_column_int(env: &mut Environment, stmt: Sqlite3StmtPtr, column: i32) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return 0;
+    };
+    unsafe { ffi::sqlite3_column_int(host_stmt, column) }
+}
+
+fn sqlite3_column_int64(env: &mut Environment, stmt: Sqlite3StmtPtr, column: i32) -> i64 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return 0;
+    };
+    unsafe { ffi::sqlite3_column_int64(host_stmt, column) }
+}
+
+fn sqlite3_column_double(env: &mut Environment, stmt: Sqlite3StmtPtr, column: i32) -> f64 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return 0.0;
+    };
+    unsafe { ffi::sqlite3_column_double(host_stmt, column) }
+}
+
+fn sqlite3_column_bytes(env: &mut Environment, stmt: Sqlite3StmtPtr, column: i32) -> i32 {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return 0;
+    };
+    unsafe { ffi::sqlite3_column_bytes(host_stmt, column) }
+}
+
+fn sqlite3_column_text(env: &mut Environment, stmt: Sqlite3StmtPtr, column: i32) -> ConstPtr<u8> {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return Ptr::null();
+    };
+    let text = unsafe { ffi::sqlite3_column_text(host_stmt, column) };
+    if text.is_null() {
+        return Ptr::null();
+    }
+    let len: usize = unsafe { ffi::sqlite3_column_bytes(host_stmt, column) }
+        .try_into()
+        .unwrap();
+    let bytes = unsafe { std::slice::from_raw_parts(text, len) };
+    let text = env.mem.alloc_and_write_cstr(bytes);
+    env.libsqlite3_state
+        .statements
+        .get_mut(&stmt)
+        .unwrap()
+        .column_values
+        .push(text.cast());
+    text.cast_const()
+}
+
+fn sqlite3_column_blob(env: &mut Environment, stmt: Sqlite3StmtPtr, column: i32) -> ConstVoidPtr {
+    let Some(host_stmt) = host_stmt(env, stmt) else {
+        return Ptr::null();
+    };
+    let blob = unsafe { ffi::sqlite3_column_blob(host_stmt, column) };
+    if blob.is_null() {
+        return Ptr::null();
+    }
+    let len: GuestUSize = unsafe { ffi::sqlite3_column_bytes(host_stmt, column) }
+        .try_into()
+        .unwrap();
+    let bytes = unsafe { std::slice::from_raw_parts(blob.cast::<u8>(), len as usize) };
+    let guest_blob: MutPtr<u8> = env.mem.alloc(len).cast();
+    env.mem.bytes_at_mut(guest_blob, len).copy_from_slice(bytes);
+    env.libsqlite3_state
+        .statements
+        .get_mut(&stmt)
+        .unwrap()
+        .column_values
+        .push(guest_blob.cast());
+    guest_blob.cast_const().cast()
+}
+
+fn sqlite3_errcode(env: &mut Environment, db: Sqlite3Ptr) -> i32 {
+    match host_db(env, db) {
+        Some(host_db) => unsafe { ffi::sqlite3_errcode(host_db) },
+        // Same as what SQLite returns for NULL.
+        None if db.is_null() => ffi::SQLITE_NOMEM,
+        None => ffi::SQLITE_MISUSE,
+    }
+}
+
+fn sqlite3_errmsg(env: &mut Environment, db: Sqlite3Ptr) -> ConstPtr<u8> {
+    let host_db = host_db(env, db);
+    let msg = match host_db {
+        Some(host_db) => unsafe { ffi::sqlite3_errmsg(host_db) },
+        // Same as what SQLite returns for NULL.
+        None if db.is_null() => unsafe { ffi::sqlite3_errstr(ffi::SQLITE_NOMEM) },
+        None => unsafe { ffi::sqlite3_errstr(ffi::SQLITE_MISUSE) },
+    };
+    let msg = copy_cstr_to_guest(env, msg);
+    let old_msg = match host_db {
+        Some(_) => {
+            let host_object = env.libsqlite3_state.databases.get_mut(&db).unwrap();
+            std::mem::replace(&mut host_object.errmsg, msg)
+        }
+        None => std::mem::replace(&mut env.libsqlite3_state.invalid_db_errmsg, msg),
+    };
+    if !old_msg.is_null() {
+        env.mem.free(old_msg.cast());
+    }
+    msg.cast_const()
+}
+
+fn sqlite3_changes(env: &mut Environment, db: Sqlite3Ptr) -> i32 {
+    let Some(host_db) = host_db(env, db) else {
+        return 0;
+    };
+    unsafe { ffi::sqlite3_changes(host_db) }
+}
+
+fn sqlite3_total_changes(env: &mut Environment, db: Sqlite3Ptr) -> i32 {
+    let Some(host_db) = host_db(env, db) else {
+        return 0;
+    };
+    unsafe { ffi::sqlite3_total_changes(host_db) }
+}
+
+fn sqlite3_last_insert_rowid(env: &mut Environment, db: Sqlite3Ptr) -> i64 {
+    let Some(host_db) = host_db(env, db) else {
+        return 0;
+    };
+    unsafe { ffi::sqlite3_last_insert_rowid(host_db) }
+}
+
+fn sqlite3_busy_timeout(_env: &mut Environment, db: Sqlite3Ptr, ms: i32) -> i32 {
+    // There's never any contention for the database, so this doesn't matter.
+    log_dbg!("Ignoring sqlite3_busy_timeout({:?}, {})", db, ms);
+    ffi::SQLITE_OK
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(sqlite3_open(_, _)),
+    export_c_func!(sqlite3_open_v2(_, _, _, _)),
+    export_c_func!(sqlite3_close(_)),
+    export_c_func!(sqlite3_exec(_, _, _, _, _)),
+    export_c_func!(sqlite3_free(_)),
+    export_c_func!(sqlite3_prepare(_, _, _, _, _)),
+    export_c_func!(sqlite3_prepare_v2(_, _, _, _, _)),
+    export_c_func!(sqlite3_step(_)),
+    export_c_func!(sqlite3_reset(_)),
+    export_c_func!(sqlite3_finalize(_)),
+    export_c_func!(sqlite3_clear_bindings(_)),
+    export_c_func!(sqlite3_bind_int(_, _, _)),
+    export_c_func!(sqlite3_bind_int64(_, _, _)),
+    export_c_func!(sqlite3_bind_double(_, _, _)),
+    export_c_func!(sqlite3_bind_null(_, _)),
+    export_c_func!(sqlite3_bind_text(_, _, _, _, _)),
+    export_c_func!(sqlite3_bind_blob(_, _, _, _, _)),
+    export_c_func!(sqlite3_bind_parameter_count(_)),
+    export_c_func!(sqlite3_bind_parameter_index(_, _)),
+    export_c_func!(sqlite3_column_count(_)),
+    export_c_func!(sqlite3_data_count(_)),
+    export_c_func!(sqlite3_column_type(_, _)),
+    export_c_func!(sqlite3_column_name(_, _)),
+    export_c_func!(sqlite3_column_int(_, _)),
+    export_c_func!(sqlite3_column_int64(_, _)),
+    export_c_func!(sqlite3_column_double(_, _)),
+    export_c_func!(sqlite3_column_bytes(_, _)),
+    export_c_func!(sqlite3_column_text(_, _)),
+    export_c_func!(sqlite3_column_blob(_, _)),
+    export_c_func!(sqlite3_errcode(_)),
+    export_c_func!(sqlite3_errmsg(_)),
+    export_c_func!(sqlite3_changes(_)),
+    export_c_func!(sqlite3_total_changes(_)),
+    export_c_func!(sqlite3_last_insert_rowid(_)),
+    export_c_func!(sqlite3_busy_timeout(_, _)),
+];
@@ -0,0 +1,416 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! SQLite "virtual file system" that routes file access through the guest
+//! filesystem ([crate::fs]), so that databases are looked up in the same place
+//! the app would expect, whether they are in the app bundle (possibly inside
+//! an IPA file), or in its sandbox.
+//!
+//! SQLite's callbacks can't be given a reference to the [Fs], so one is lent
+//! to them for the duration of each call into SQLite by [with_fs]. touchHLE's
+//! host code is single-threaded, so this is simple.
+//!
+//! Locking is a no-op, since there is only ever one process and connections
+//! aren't shared between threads.
+//!
+//! Resources:
+//! - [The SQLite OS Interface or "VFS"](https://www.sqlite.org/vfs.html)
+//! - `test_demovfs.c` in the SQLite source tree is a minimal example VFS.
+
+use crate::fs::{Fs, GuestFile, GuestOpenOptions, GuestPath, GuestPathBuf};
+use libsqlite3_sys as ffi;
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::Once;
+
+/// The name the VFS is registered with, to be passed to `sqlite3_open_v2()`.
+/// NUL-terminated.
+pub const VFS_NAME: &[u8] = b"touchHLE\0";
+
+thread_local! {
+    static CURRENT_FS: Cell<*mut Fs> = const { Cell::new(std::ptr::null_mut()) };
+}
+
+/// Call some SQLite functions, letting the VFS use the guest filesystem for
+/// the duration. Any SQLite function that might touch a file (opening,
+/// preparing and stepping statements, closing, etc) must be called this way.
+pub fn with_fs<R>(fs: &mut Fs, f: impl FnOnce() -> R) -> R {
+    register();
+    let old = CURRENT_FS.with(|current| current.replace(fs));
+    let res = f();
+    CURRENT_FS.with(|current| current.set(old));
+    res
+}
+
+fn current_fs<'a>() -> &'a mut Fs {
+    let fs = CURRENT_FS.with(|current| current.get());
+    assert!(
+        !fs.is_null(),
+        "SQLite tried to access a file outside of with_fs()"
+    );
+    // SAFETY: with_fs() holds a mutable borrow of the Fs for as long as the
+    // pointer is set, and the callbacks don't re-enter each other.
+    unsafe { &mut *fs }
+}
+
+/// Register the VFS with SQLite, if that hasn't been done already.
+fn register() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| unsafe {
+        // The OS-specific default VFS is used for the things that don't
+        // involve files (randomness, time, etc).
+        let default_vfs = ffi::sqlite3_vfs_find(std::ptr::null());
+        assert!(!default_vfs.is_null());
+        let vfs = Box::new(ffi::sqlite3_vfs {
+            iVersion: 1,
+            szOsFile: std::mem::size_of::<VfsFile>().try_into().unwrap(),
+            mxPathname: 1024,
+            pNext: std::ptr::null_mut(),
+            zName: VFS_NAME.as_ptr().cast(),
+            pAppData: std::ptr::null_mut(),
+            xOpen: Some(x_open),
+            xDelete: Some(x_delete),
+            xAccess: Some(x_access),
+            xFullPathname: Some(x_full_pathname),
+            xDlOpen: None,
+            xDlError: None,
+            xDlSym: None,
+            xDlClose: None,
+            xRandomness: (*default_vfs).xRandomness,
+            xSleep: (*default_vfs).xSleep,
+            xCurrentTime: (*default_vfs).xCurrentTime,
+            xGetLastError: None,
+            xCurrentTimeInt64: None,
+            xSetSystemCall: None,
+            xGetSystemCall: None,
+            xNextSystemCall: None,
+        });
+        // SQLite keeps the pointer forever, so the VFS is leaked.
+        let res = ffi::sqlite3_vfs_register(Box::into_raw(vfs), /* makeDflt: */ 0);
+        assert!(res == ffi::SQLITE_OK);
+    });
+}
+
+enum FileBacking {
+    Guest(GuestFile),
+    /// Temporary files (those without names) are kept in memory.
+    Memory(Cursor<Vec<u8>>),
+}
+
+/// Our "subclass" of `sqlite3_file`. SQLite allocates `szOsFile` bytes for it
+/// and `xOpen` initializes it.
+#[repr(C)]
+struct VfsFile {
+    base: ffi::sqlite3_file,
+    backing: *mut FileBacking,
+    read_only: bool,
+}
+
+static IO_METHODS: ffi::sqlite3_io_methods = ffi::sqlite3_io_methods {
+    iVersion: 1,
+    xClose: Some(x_close),
+    xRead: Some(x_read),
+    xWrite: Some(x_write),
+    xTruncate: Some(x_truncate),
+    xSync: Some(x_sync),
+    xFileSize: Some(x_file_size),
+    xLock: Some(x_lock),
+    xUnlock: Some(x_unlock),
+    xCheckReservedLock: Some(x_check_reserved_lock),
+    xFileControl: Some(x_file_control),
+    xSectorSize: Some(x_sector_size),
+    xDeviceCharacteristics: Some(x_device_characteristics),
+    xShmMap: None,
+    xShmLock: None,
+    xShmBarrier: None,
+    xShmUnmap: None,
+    xFetch: None,
+    xUnfetch: None,
+};
+
+unsafe fn guest_path<'a>(name: *const c_char) -> Option<&'a GuestPath> {
+    let name = CStr::from_ptr(name).to_str().ok()?;
+    Some(GuestPath::new(name))
+}
+
+unsafe fn backing<'a>(file: *mut ffi::sqlite3_file) -> &'a mut FileBacking {
+    &mut *(*file.cast::<VfsFile>()).backing
+}
+
+unsafe extern "C" fn x_open(
+    _vfs: *mut ffi::sqlite3_vfs,
+    name: ffi::sqlite3_filename,
+    file: *mut ffi::sqlite3_file,
+    flags: c_int,
+    out_flags: *mut c_int,
+) -> c_int {
+    let file = file.cast::<VfsFile>();
+    // Make sure xClose isn't called if opening fails.
+    (*file).base.pMethods = std::ptr::null();
+
+    let (backing, read_only) = if name.is_null() {
+        (FileBacking::Memory(Cursor::new(Vec::new())), false)
+    } else {
+        let Some(path) = guest_path(name) else {
+            return ffi::SQLITE_CANTOPEN;
+        };
+        let fs = current_fs();
+        let want_write = (flags & ffi::SQLITE_OPEN_READWRITE) != 0;
+        let create = (flags & ffi::SQLITE_OPEN_CREATE) != 0;
+        let (exists, _, writeable, _) = fs.access(path);
+
+        let mut options = GuestOpenOptions::new();
+        options.read();
+        // Fall back to read-only access if the file is in a read-only place
+        // like the app bundle, like the real VFS would.
+        let read_only = !want_write || (exists && !writeable);
+        if !read_only {
+            options.write();
+            if create {
+                options.create();
+            }
+        }
+        match fs.open_with_options(path, options) {
+            Ok(guest_file) => (FileBacking::Guest(guest_file), read_only),
+            Err(()) => {
+                log_dbg!("SQLite couldn't open {:?} (flags {:#x})", path, flags);
+                return ffi::SQLITE_CANTOPEN;
+            }
+        }
+    };
+
+    log_dbg!(
+        "SQLite opened {:?} (flags {:#x}, read-only: {})",
+        if name.is_null() {
+            None
+        } else {
+            guest_path(name)
+        },
+        flags,
+        read_only
+    );
+
+    if !out_flags.is_null() {
+        *out_flags = if read_only {
+            (flags & !(ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE))
+                | ffi::SQLITE_OPEN_READONLY
+        } else {
+            flags
+        };
+    }
+    (*file).backing = Box::into_raw(Box::new(backing));
+    (*file).read_only = read_only;
+    (*file).base.pMethods = &IO_METHODS;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_delete(
+    _vfs: *mut ffi::sqlite3_vfs,
+    name: *const c_char,
+    _sync_dir: c_int,
+) -> c_int {
+    let Some(path) = guest_path(name) else {
+        return ffi::SQLITE_IOERR_DELETE;
+    };
+    let fs = current_fs();
+    if !fs.exists(path) {
+        return ffi::SQLITE_IOERR_DELETE_NOENT;
+    }
+    match fs.remove(path) {
+        Ok(()) => ffi::SQLITE_OK,
+        Err(()) => ffi::SQLITE_IOERR_DELETE,
+    }
+}
+
+unsafe extern "C" fn x_access(
+    _vfs: *mut ffi::sqlite3_vfs,
+    name: *const c_char,
+    flags: c_int,
+    res_out: *mut c_int,
+) -> c_int {
+    let Some(path) = guest_path(name) else {
+        *res_out = 0;
+        return ffi::SQLITE_OK;
+    };
+    let (exists, readable, writeable, _) = current_fs().access(path);
+    *res_out = match flags {
+        ffi::SQLITE_ACCESS_READWRITE => readable && writeable,
+        ffi::SQLITE_ACCESS_READ => readable,
+        _ => exists,
+    }
+    .into();
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_full_pathname(
+    _vfs: *mut ffi::sqlite3_vfs,
+    name: *const c_char,
+    n_out: c_int,
+    z_out: *mut c_char,
+) -> c_int {
+    let Some(path) = guest_path(name) else {
+        return ffi::SQLITE_CANTOPEN;
+    };
+    let full_path: GuestPathBuf = if path.as_str().starts_with('/') {
+        path.to_owned()
+    } else {
+        current_fs().working_directory().join(path)
+    };
+    let bytes = full_path.as_str().as_bytes();
+    let n_out: usize = n_out.try_into().unwrap();
+    if bytes.len() + 1 > n_out {
+        return ffi::SQLITE_CANTOPEN;
+    }
+    let out = std::slice::from_raw_parts_mut(z_out.cast::<u8>(), n_out);
+    out[..bytes.len()].copy_from_slice(bytes);
+    out[bytes.len()] = b'\0';
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_close(file: *mut ffi::sqlite3_file) -> c_int {
+    let file = file.cast::<VfsFile>();
+    drop(Box::from_raw((*file).backing));
+    (*file).backing = std::ptr::null_mut();
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_read(
+    file: *mut ffi::sqlite3_file,
+    buf: *mut c_void,
+    amount: c_int,
+    offset: ffi::sqlite3_int64,
+) -> c_int {
+    let buf = std::slice::from_raw_parts_mut(buf.cast::<u8>(), amount.try_into().unwrap());
+    let offset: u64 = offset.try_into().unwrap();
+    let reader: &mut dyn ReadSeek = match backing(file) {
+        FileBacking::Guest(file) => file,
+        FileBacking::Memory(cursor) => cursor,
+    };
+    if reader.seek(SeekFrom::Start(offset)).is_err() {
+        return ffi::SQLITE_IOERR_READ;
+    }
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..]) {
+            Ok(0) => break,
+            Ok(count) => total += count,
+            Err(_) => return ffi::SQLITE_IOERR_READ,
+        }
+    }
+    if total < buf.len() {
+        // SQLite requires the rest of the buffer to be zero-filled.
+        buf[total..].fill(0);
+        return ffi::SQLITE_IOERR_SHORT_READ;
+    }
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_write(
+    file: *mut ffi::sqlite3_file,
+    buf: *const c_void,
+    amount: c_int,
+    offset: ffi::sqlite3_int64,
+) -> c_int {
+    if (*file.cast::<VfsFile>()).read_only {
+        return ffi::SQLITE_READONLY;
+    }
+    let buf = std::slice::from_raw_parts(buf.cast::<u8>(), amount.try_into().unwrap());
+    let offset: u64 = offset.try_into().unwrap();
+    let writer: &mut dyn WriteSeek = match backing(file) {
+        FileBacking::Guest(file) => file,
+        FileBacking::Memory(cursor) => cursor,
+    };
+    if writer.seek(SeekFrom::Start(offset)).is_err() || writer.write_all(buf).is_err() {
+        return ffi::SQLITE_IOERR_WRITE;
+    }
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_truncate(file: *mut ffi::sqlite3_file, size: ffi::sqlite3_int64) -> c_int {
+    if (*file.cast::<VfsFile>()).read_only {
+        return ffi::SQLITE_READONLY;
+    }
+    let size: u64 = size.try_into().unwrap();
+    let res = match backing(file) {
+        FileBacking::Guest(file) => file.set_len(size).is_ok(),
+        FileBacking::Memory(cursor) => {
+            cursor.get_mut().resize(size.try_into().unwrap(), 0);
+            true
+        }
+    };
+    if res {
+        ffi::SQLITE_OK
+    } else {
+        ffi::SQLITE_IOERR_TRUNCATE
+    }
+}
+
+unsafe extern "C" fn x_sync(file: *mut ffi::sqlite3_file, _flags: c_int) -> c_int {
+    if (*file.cast::<VfsFile>()).read_only {
+        return ffi::SQLITE_OK;
+    }
+    let res = match backing(file) {
+        FileBacking::Guest(file) => file.sync_all().is_ok(),
+        FileBacking::Memory(_) => true,
+    };
+    if res {
+        ffi::SQLITE_OK
+    } else {
+        ffi::SQLITE_IOERR_FSYNC
+    }
+}
+
+unsafe extern "C" fn x_file_size(
+    file: *mut ffi::sqlite3_file,
+    size_out: *mut ffi::sqlite3_int64,
+) -> c_int {
+    let size = match backing(file) {
+        FileBacking::Guest(file) => file.seek(SeekFrom::End(0)),
+        FileBacking::Memory(cursor) => Ok(cursor.get_ref().len() as u64),
+    };
+    match size {
+        Ok(size) => {
+            *size_out = size.try_into().unwrap();
+            ffi::SQLITE_OK
+        }
+        Err(_) => ffi::SQLITE_IOERR_FSTAT,
+    }
+}
+
+unsafe extern "C" fn x_lock(_file: *mut ffi::sqlite3_file, _level: c_int) -> c_int {
+    ffi::SQLITE_OK
+}
+unsafe extern "C" fn x_unlock(_file: *mut ffi::sqlite3_file, _level: c_int) -> c_int {
+    ffi::SQLITE_OK
+}
+unsafe extern "C" fn x_check_reserved_lock(
+    _file: *mut ffi::sqlite3_file,
+    res_out: *mut c_int,
+) -> c_int {
+    *res_out = 0;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_file_control(
+    _file: *mut ffi::sqlite3_file,
+    _op: c_int,
+    _arg: *mut c_void,
+) -> c_int {
+    ffi::SQLITE_NOTFOUND
+}
+
+unsafe extern "C" fn x_sector_size(_file: *mut ffi::sqlite3_file) -> c_int {
+    512
+}
+unsafe extern "C" fn x_device_characteristics(_file: *mut ffi::sqlite3_file) -> c_int {
+    0
+}
+
+trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+trait WriteSeek: Write + Seek {}
+impl<T: Write + Seek> WriteSeek for T {}
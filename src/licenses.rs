@@ -135,6 +135,11 @@ from the library minimp3, which is licensed under the Creative Commons CC0 1.0
 Universal Public Domain Dedication.
 ";
 
+const SQLITE: &str = "
+touchHLE, and therefore this executable, incorporates the library SQLite,
+which is in the public domain.
+";
+
 // When resource files are bundled with touchHLE in such a way that the user can
 // read their license files directly, use this caveat.
 
@@ -217,6 +222,8 @@ fn print(out: &mut String, resources_are_external_files: bool) -> Result<(), std
     writeln!(out, "{}", PVRTD_LICENSE.trim_end())?;
     divider(out)?;
     writeln!(out, "{}", DR_MP3)?;
+    divider(out)?;
+    writeln!(out, "{}", SQLITE)?;
     if !resources_are_external_files {
         divider(out)?;
         writeln!(out, "{}", INTERNAL_DYLIBS_DESCRIPTION)?;
@@ -16,11 +16,14 @@
 //! - The LLVM functions [`RuntimeDyldMachO::populateIndirectSymbolPointersSection`](https://github.com/llvm/llvm-project/blob/2e999b7dd1934a44d38c3a753460f1e5a217e9a5/llvm/lib/ExecutionEngine/RuntimeDyld/RuntimeDyldMachO.cpp#L179-L220) and [`MachOObjectFile::getIndirectSymbolTableEntry`](https://github.com/llvm/llvm-project/blob/3c09ed006ab35dd8faac03311b14f0857b01949c/llvm/lib/Object/MachOObjectFile.cpp#L4803-L4808) are references for how to read the indirect symbol table.
 //! - `/usr/include/mach-o/reloc.h` in the macOS SDK was the reference for the
 //!   format of relocation entries.
+//! - See [dyld_info] for binaries using `LC_DYLD_INFO`.
 //! - The [source code of the mach_object crate](https://docs.rs/mach_object/latest/src/mach_object/commands.rs.html) has useful comments that don't show up in the generated documentation, e.g. around `DySymTab`.
 
+mod dyld_info;
+
 use crate::abi::GuestFunction;
 use crate::fs::{Fs, GuestPath};
-use crate::mem::{Mem, Ptr};
+use crate::mem::{ConstPtr, Mem, MutPtr, Protection, Ptr};
 use mach_object::{
     cpu_subtype_t, vm_prot_t, DyLib, LoadCommand, MachCommand, OFile, Symbol, SymbolIter,
     ThreadState, N_ARM_THUMB_DEF, S_LAZY_SYMBOL_POINTERS, S_MOD_INIT_FUNC_POINTERS,
@@ -49,6 +52,14 @@ pub struct MachO {
     /// List of addresses and names of external relocations for the dynamic
     /// linker to resolve.
     pub external_relocations: Vec<(u32, String)>,
+    /// List of addresses, names and addends of pointers to weak definitions,
+    /// which should be re-pointed at the first definition of the symbol in
+    /// any loaded binary.
+    pub weak_bindings: Vec<(u32, String, i32)>,
+    /// Addresses and names of all named symbols (including non-exported ones)
+    /// in `__text` sections, sorted by address. The Thumb bit is not set. This
+    /// is only used for symbolicating stack traces.
+    pub function_symbols: Vec<(u32, String)>,
     /// Address/program counter value for the entry point.
     pub entry_point_pc: Option<u32>,
 }
@@ -296,16 +307,77 @@ impl MachO {
 
         let split_segs = (header.flags & mach_object::MH_SPLIT_SEGS) != 0;
 
+        // Binaries from newer linkers describe rebasing, binding and exports
+        // with LC_DYLD_INFO. If it's present, it's used instead of relocation
+        // entries, though the indirect symbol table is still used.
+        let has_dyld_info = commands
+            .iter()
+            .any(|MachCommand(command, _)| matches!(command, LoadCommand::DyldInfo { .. }));
+
+        // Work out where to load the binary. Executables must be loaded at the
+        // address they were linked for, but libraries can be loaded somewhere
+        // else if that address is taken, which means they must be rebased
+        // (see the handling of local relocations below).
+        let mut segments_range: Option<(u32, u32)> = None;
+        let mut segments_fit = true;
+        for MachCommand(command, _size) in &commands {
+            let LoadCommand::Segment {
+                segname,
+                vmaddr,
+                vmsize,
+                ..
+            } = command
+            else {
+                continue;
+            };
+            let vmaddr: u32 = (*vmaddr).try_into().unwrap();
+            let vmsize: u32 = (*vmsize).try_into().unwrap();
+            if segname == "__PAGEZERO" || vmsize == 0 {
+                continue;
+            }
+            if segname != "__LINKEDIT" && !into_mem.can_reserve(vmaddr, vmsize) {
+                segments_fit = false;
+            }
+            let end = vmaddr
+                .checked_add(vmsize)
+                .ok_or("Segment is out of range")?;
+            segments_range = Some(match segments_range {
+                Some((start, old_end)) => (start.min(vmaddr), old_end.max(end)),
+                None => (vmaddr, end),
+            });
+        }
+        let slide: u32 = if segments_fit {
+            0
+        } else {
+            if header.filetype == mach_object::MH_EXECUTE {
+                return Err("Executable can't be loaded at the address it was linked for");
+            }
+            let (start, end) = segments_range.unwrap();
+            let new_start = into_mem
+                .reserve_anywhere(end - start, 0x1000)
+                .ok_or("Not enough free memory to load binary")?;
+            log_dbg!(
+                "Loading {:?} at {:#x} rather than {:#x}",
+                name,
+                new_start,
+                start
+            );
+            new_start.wrapping_sub(start)
+        };
+
         // Info used while parsing file
         let mut first_segment_base: Option<u32> = None;
         let mut first_read_write_segment_base: Option<u32> = None;
         let mut text_segment_base: Option<u32> = None;
         let mut all_sections = Vec::new();
         let mut sym_tab_info: Option<(u32, u32, u32, u32)> = None;
+        let mut segment_bases = Vec::new();
+        let mut dyld_info_streams = None;
 
         // Info used for the result
         let mut dynamic_libraries = Vec::new();
         let mut exported_symbols = HashMap::new();
+        let mut function_symbols = Vec::new();
         let mut indirect_undef_symbols: Vec<Option<String>> = Vec::new();
         let mut external_relocations: Vec<(u32, String)> = Vec::new();
         let mut entry_point_pc: Option<u32> = None;
@@ -323,9 +395,11 @@ impl MachO {
                     ..
                 } => {
                     let vmaddr: u32 = vmaddr.try_into().unwrap();
+                    let vmaddr = vmaddr.wrapping_add(slide);
                     let vmsize: u32 = vmsize.try_into().unwrap();
                     let filesize: u32 = filesize.try_into().unwrap();
 
+                    segment_bases.push(vmaddr);
                     if first_segment_base.is_none() {
                         first_segment_base = Some(vmaddr);
                     }
@@ -359,7 +433,11 @@ impl MachO {
                     };
 
                     if load_me {
-                        into_mem.reserve(vmaddr, vmsize);
+                        // If the binary has been slid, the memory for all the
+                        // segments was already reserved.
+                        if slide == 0 {
+                            into_mem.reserve(vmaddr, vmsize);
+                        }
 
                         // If filesize is less than vmsize, the rest of the
                         // segment should be filled with zeroes. We are assuming
@@ -371,6 +449,9 @@ impl MachO {
                             let dst = into_mem.bytes_at_mut(Ptr::from_bits(vmaddr), filesize);
                             dst.copy_from_slice(src);
                         }
+
+                        let protection = Protection::from_bits_truncate(initprot as u32);
+                        into_mem.set_protection(vmaddr, vmsize, protection);
                     }
 
                     all_sections.extend_from_slice(&sections);
@@ -397,6 +478,16 @@ impl MachO {
                             if let Symbol::Debug { .. } = symbol {
                                 continue;
                             }
+                            if let Symbol::Defined {
+                                name: Some(name),
+                                entry,
+                                ..
+                            } = symbol
+                            {
+                                let entry: u32 = entry.try_into().unwrap();
+                                let entry = entry.wrapping_add(slide);
+                                function_symbols.push((entry, name.to_string()));
+                            }
                             if let Symbol::Defined {
                                 name: Some(name),
                                 external: true,
@@ -406,6 +497,7 @@ impl MachO {
                             } = symbol
                             {
                                 let entry: u32 = entry.try_into().unwrap();
+                                let entry = entry.wrapping_add(slide);
                                 let entry = if desc & N_ARM_THUMB_DEF != 0 {
                                     entry | GuestFunction::THUMB_BIT
                                 } else {
@@ -421,8 +513,17 @@ impl MachO {
                     nindirectsyms,
                     extreloff,
                     nextrel,
+                    locreloff,
+                    nlocrel,
                     ..
                 } => {
+                    // Relocation addresses are relative to this.
+                    let reloc_base = if split_segs {
+                        first_read_write_segment_base.unwrap()
+                    } else {
+                        first_segment_base.unwrap()
+                    };
+
                     let indirectsyms =
                         &bytes[indirectsymoff as usize..][..nindirectsyms as usize * 4];
                     for idx in indirectsyms.chunks(4) {
@@ -450,6 +551,7 @@ impl MachO {
                         })
                     }
 
+                    let nextrel = if has_dyld_info { 0 } else { nextrel };
                     let extrels = &bytes[extreloff as usize..][..nextrel as usize * 8];
                     for entry in extrels.chunks(8) {
                         let reloc = Reloc::parse(is_bigend, entry.try_into().unwrap());
@@ -463,11 +565,7 @@ impl MachO {
                         else {
                             panic!("Unhandled extrel: {:?}", reloc)
                         };
-                        let addr = if split_segs {
-                            addr + first_read_write_segment_base.unwrap()
-                        } else {
-                            addr + first_segment_base.unwrap()
-                        };
+                        let addr = reloc_base + addr;
 
                         let mut cursor = cursor.clone();
                         let sym = get_sym_by_idx(
@@ -489,7 +587,7 @@ impl MachO {
                                 // Resolve them immediately, there is no value
                                 // in passing these on to Dyld.
                                 let addr = Ptr::from_bits(addr);
-                                let entry = entry as u32;
+                                let entry = (entry as u32).wrapping_add(slide);
                                 let entry = if desc & N_ARM_THUMB_DEF != 0 {
                                     entry | GuestFunction::THUMB_BIT
                                 } else {
@@ -505,6 +603,37 @@ impl MachO {
                             _ => panic!("Unexpected symbol kind {:?}", sym),
                         };
                     }
+
+                    // Local relocations are pointers within the binary, which
+                    // need adjusting if it wasn't loaded where it was linked.
+                    if slide != 0 && !has_dyld_info {
+                        let locrels = &bytes[locreloff as usize..][..nlocrel as usize * 8];
+                        for entry in locrels.chunks(8) {
+                            let reloc = Reloc::parse(is_bigend, entry.try_into().unwrap());
+                            let addr = match reloc {
+                                Reloc::Local {
+                                    addr,
+                                    is_pc_relative: false,
+                                    size: 4,
+                                    type_: 0, // generic
+                                    ..
+                                } => addr,
+                                Reloc::Scattered {
+                                    offset,
+                                    is_pc_relative: false,
+                                    size: 4,
+                                    type_: 0 | 4, // generic, prebound lazy pointer
+                                    ..
+                                } => offset,
+                                // Other kinds (e.g. differences between two
+                                // addresses) aren't affected by the slide.
+                                _ => continue,
+                            };
+                            let ptr: MutPtr<u32> = Ptr::from_bits(reloc_base + addr);
+                            let value = into_mem.read(ptr);
+                            into_mem.write(ptr, value.wrapping_add(slide));
+                        }
+                    }
                 }
                 LoadCommand::EncryptionInfo { id, .. } => {
                     if id != 0 {
@@ -513,7 +642,8 @@ impl MachO {
                         );
                     }
                 }
-                LoadCommand::LoadDyLib(DyLib { name, .. }) => {
+                LoadCommand::LoadDyLib(DyLib { name, .. })
+                | LoadCommand::LoadWeakDyLib(DyLib { name, .. }) => {
                     dynamic_libraries.push(String::from(&*name));
                 }
                 // Old-style entry point PC command
@@ -530,7 +660,7 @@ impl MachO {
                     };
                     // There should only be a single initial thread state.
                     assert!(entry_point_pc.is_none());
-                    entry_point_pc = Some(pc);
+                    entry_point_pc = Some(pc.wrapping_add(slide));
                 }
                 // New-style entry point PC command
                 LoadCommand::EntryPoint {
@@ -546,22 +676,75 @@ impl MachO {
                     let entryoff: u32 = entryoff.try_into().unwrap();
                     entry_point_pc = Some(text_segment_base.unwrap() + entryoff);
                 }
-                // LoadCommand::DyldInfo is apparently a newer thing that 2008
-                // games don't have. Ignore for now? Unsure if/when iOS got it.
-                LoadCommand::DyldInfo { .. } => {
-                    log!("Warning! DyldInfo is not handled.");
+                // Newer thing that 2008 games don't have, see [dyld_info].
+                // Handled once all the segments are loaded.
+                LoadCommand::DyldInfo {
+                    rebase_off,
+                    rebase_size,
+                    bind_off,
+                    bind_size,
+                    weak_bind_off,
+                    weak_bind_size,
+                    lazy_bind_off,
+                    lazy_bind_size,
+                    export_off,
+                    export_size,
+                } => {
+                    let get = |off: u32, size: u32| -> Result<&[u8], &'static str> {
+                        bytes
+                            .get(off as usize..)
+                            .and_then(|rest| rest.get(..size as usize))
+                            .ok_or("Dyld info is out of range")
+                    };
+                    dyld_info_streams = Some((
+                        get(rebase_off, rebase_size)?,
+                        get(bind_off, bind_size)?,
+                        get(weak_bind_off, weak_bind_size)?,
+                        get(lazy_bind_off, lazy_bind_size)?,
+                        get(export_off, export_size)?,
+                    ));
                 }
                 _ => (),
             }
         }
 
-        let sections = all_sections
+        // Addresses and names of pointers bound by LC_DYLD_INFO.
+        let mut binds = Vec::new();
+        let mut lazy_binds = HashMap::new();
+        let mut weak_bindings = Vec::new();
+        if let Some((rebases, bind_ops, weak_bind_ops, lazy_bind_ops, exports)) = dyld_info_streams
+        {
+            if slide != 0 {
+                dyld_info::rebase(rebases, &segment_bases, slide, into_mem)?;
+            }
+            for bind in dyld_info::parse_binds(bind_ops, &segment_bases, false)? {
+                // Only used by the __stub_helper section, which touchHLE's
+                // lazy linking replaces.
+                if bind.symbol == "dyld_stub_binder" {
+                    continue;
+                }
+                binds.push(bind);
+            }
+            for bind in dyld_info::parse_binds(lazy_bind_ops, &segment_bases, true)? {
+                lazy_binds.insert(bind.addr, bind.symbol);
+            }
+            for bind in dyld_info::parse_binds(weak_bind_ops, &segment_bases, false)? {
+                weak_bindings.push((bind.addr, bind.symbol, bind.addend));
+            }
+            let header_addr = text_segment_base.ok_or("No __TEXT segment")?;
+            for (symbol, addr) in dyld_info::parse_export_trie(exports, header_addr)? {
+                exported_symbols.insert(symbol, addr);
+            }
+        }
+
+        let mut sections: Vec<Section> = all_sections
             .iter()
             .map(|section| {
                 let section = &**section;
 
                 let name = section.sectname.clone();
                 let addr: u32 = section.addr.try_into().unwrap();
+                let addr = addr.wrapping_add(slide);
                 let size: u32 = section.size.try_into().unwrap();
                 let type_ = section.flags.sect_type();
 
@@ -588,8 +771,15 @@ impl MachO {
                     let indirect_start = section.reserved1 as usize;
                     assert!(size % entry_size == 0);
                     let indirect_count = (size / entry_size) as usize;
-                    let indirects = &mut indirect_undef_symbols[indirect_start..][..indirect_count];
-                    let syms = indirects.iter_mut().map(|sym| sym.take()).collect();
+                    // Binaries using LC_DYLD_INFO might not have entries in
+                    // the indirect symbol table for every section.
+                    let syms = (0..indirect_count)
+                        .map(|i| {
+                            indirect_undef_symbols
+                                .get_mut(indirect_start + i)
+                                .and_then(|sym| sym.take())
+                        })
+                        .collect();
                     DyldIndirectSymbolInfo {
                         entry_size,
                         indirect_undef_symbols: syms,
@@ -606,12 +796,89 @@ impl MachO {
             })
             .collect();
 
+        if has_dyld_info {
+            // Fill in symbols missing from the indirect symbol table using the
+            // bind information. For stubs, the symbol is that of the
+            // __la_symbol_ptr they jump via.
+            let bound_symbols: HashMap<u32, &str> = binds
+                .iter()
+                .map(|bind| (bind.addr, bind.symbol.as_str()))
+                .chain(
+                    lazy_binds
+                        .iter()
+                        .map(|(&addr, symbol)| (addr, symbol.as_str())),
+                )
+                .collect();
+            for section in &mut sections {
+                let Section {
+                    addr,
+                    ref type_,
+                    dyld_indirect_symbol_info: Some(ref mut info),
+                    ..
+                } = *section
+                else {
+                    continue;
+                };
+                for (i, symbol) in info.indirect_undef_symbols.iter_mut().enumerate() {
+                    if symbol.is_some() {
+                        continue;
+                    }
+                    let entry_addr = addr + i as u32 * info.entry_size;
+                    // See also Dyld::do_lazy_link().
+                    let ptr_addr = match (type_, info.entry_size) {
+                        (SectionType::SymbolStubs, 12) => {
+                            let ptr: ConstPtr<u32> = Ptr::from_bits(entry_addr + 8);
+                            into_mem.read(ptr)
+                        }
+                        (SectionType::SymbolStubs, _) => {
+                            let ptr: ConstPtr<u32> = Ptr::from_bits(entry_addr + 12);
+                            entry_addr.wrapping_add(into_mem.read(ptr)).wrapping_add(12)
+                        }
+                        _ => entry_addr,
+                    };
+                    *symbol = bound_symbols.get(&ptr_addr).map(|&s| s.to_string());
+                }
+            }
+
+            // Pointers in the __nl_symbol_ptr section are handled by the
+            // dynamic linker using the section's symbols, which supports more
+            // kinds of symbols than external relocations do.
+            let non_lazy_ptrs: Vec<std::ops::Range<u32>> = sections
+                .iter()
+                .filter(|section| section.type_ == SectionType::NonLazySymbolPointers)
+                .map(|section| section.addr..(section.addr + section.size))
+                .collect();
+            for bind in binds {
+                if non_lazy_ptrs.iter().any(|range| range.contains(&bind.addr)) {
+                    continue;
+                }
+                // The existing value is treated as an offset by the dynamic
+                // linker, as with external relocations.
+                let ptr: MutPtr<u32> = Ptr::from_bits(bind.addr);
+                into_mem.write(ptr, bind.addend as u32);
+                external_relocations.push((bind.addr, bind.symbol));
+            }
+        }
+
+        // Symbols not in code sections (e.g. Objective-C metadata) would only
+        // make stack traces misleading.
+        function_symbols.retain(|&(addr, _)| {
+            sections.iter().any(|section| {
+                section.name == "__text"
+                    && (section.addr..section.addr.wrapping_add(section.size)).contains(&addr)
+            })
+        });
+        function_symbols.sort();
+        function_symbols.dedup_by_key(|&mut (addr, _)| addr);
+
         Ok(MachO {
             name,
             dynamic_libraries,
             sections,
             exported_symbols,
             external_relocations,
+            weak_bindings,
+            function_symbols,
             entry_point_pc,
         })
     }
@@ -633,6 +900,23 @@ impl MachO {
         )
     }
 
+    /// Find the function symbol containing `addr`, if any, and return its name
+    /// and the offset of `addr` from its start. This is a best guess, since the
+    /// ends of functions aren't recorded.
+    pub fn symbolicate(&self, addr: u32) -> Option<(&str, u32)> {
+        if !self.sections.iter().any(|section| {
+            section.name == "__text"
+                && (section.addr..section.addr.wrapping_add(section.size)).contains(&addr)
+        }) {
+            return None;
+        }
+        let idx = self
+            .function_symbols
+            .partition_point(|&(sym_addr, _)| sym_addr <= addr);
+        let (sym_addr, ref name) = self.function_symbols[idx.checked_sub(1)?];
+        Some((name.as_str(), addr - sym_addr))
+    }
+
     /// Get a section by its name (`&str`) or type ([SectionType]).
     pub fn get_section<P: SectionPredicate>(&self, by: P) -> Option<&Section> {
         self.sections.iter().find(|section| by.test(section))
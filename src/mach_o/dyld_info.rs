@@ -0,0 +1,450 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Parsing of the "compressed" dynamic linker information referenced by
+//! `LC_DYLD_INFO` and `LC_DYLD_INFO_ONLY`.
+//!
+//! Binaries built with newer linkers describe rebasing, binding and exports
+//! with byte-code streams and a trie rather than (or as well as) relocation
+//! entries and the symbol table. `/usr/include/mach-o/loader.h` in the macOS
+//! SDK was the reference for the format, along with `dyld`'s
+//! `ImageLoaderMachOCompressed.cpp`.
+
+use crate::mem::{Mem, MutPtr, Ptr};
+
+const REBASE_TYPE_POINTER: u8 = 1;
+const REBASE_TYPE_TEXT_ABSOLUTE32: u8 = 2;
+
+const REBASE_OPCODE_MASK: u8 = 0xF0;
+const REBASE_IMMEDIATE_MASK: u8 = 0x0F;
+const REBASE_OPCODE_DONE: u8 = 0x00;
+const REBASE_OPCODE_SET_TYPE_IMM: u8 = 0x10;
+const REBASE_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB: u8 = 0x20;
+const REBASE_OPCODE_ADD_ADDR_ULEB: u8 = 0x30;
+const REBASE_OPCODE_ADD_ADDR_IMM_SCALED: u8 = 0x40;
+const REBASE_OPCODE_DO_REBASE_IMM_TIMES: u8 = 0x50;
+const REBASE_OPCODE_DO_REBASE_ULEB_TIMES: u8 = 0x60;
+const REBASE_OPCODE_DO_REBASE_ADD_ADDR_ULEB: u8 = 0x70;
+const REBASE_OPCODE_DO_REBASE_ULEB_TIMES_SKIPPING_ULEB: u8 = 0x80;
+
+const BIND_TYPE_POINTER: u8 = 1;
+
+const BIND_SYMBOL_FLAGS_NON_WEAK_DEFINITION: u8 = 0x8;
+
+const BIND_OPCODE_MASK: u8 = 0xF0;
+const BIND_IMMEDIATE_MASK: u8 = 0x0F;
+const BIND_OPCODE_DONE: u8 = 0x00;
+const BIND_OPCODE_SET_DYLIB_ORDINAL_IMM: u8 = 0x10;
+const BIND_OPCODE_SET_DYLIB_ORDINAL_ULEB: u8 = 0x20;
+const BIND_OPCODE_SET_DYLIB_SPECIAL_IMM: u8 = 0x30;
+const BIND_OPCODE_SET_SYMBOL_TRAILING_FLAGS_IMM: u8 = 0x40;
+const BIND_OPCODE_SET_TYPE_IMM: u8 = 0x50;
+const BIND_OPCODE_SET_ADDEND_SLEB: u8 = 0x60;
+const BIND_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB: u8 = 0x70;
+const BIND_OPCODE_ADD_ADDR_ULEB: u8 = 0x80;
+const BIND_OPCODE_DO_BIND: u8 = 0x90;
+const BIND_OPCODE_DO_BIND_ADD_ADDR_ULEB: u8 = 0xA0;
+const BIND_OPCODE_DO_BIND_ADD_ADDR_IMM_SCALED: u8 = 0xB0;
+const BIND_OPCODE_DO_BIND_ULEB_TIMES_SKIPPING_ULEB: u8 = 0xC0;
+
+const EXPORT_SYMBOL_FLAGS_KIND_MASK: u64 = 0x03;
+const EXPORT_SYMBOL_FLAGS_KIND_REGULAR: u64 = 0x00;
+const EXPORT_SYMBOL_FLAGS_KIND_ABSOLUTE: u64 = 0x02;
+const EXPORT_SYMBOL_FLAGS_REEXPORT: u64 = 0x08;
+const EXPORT_SYMBOL_FLAGS_STUB_AND_RESOLVER: u64 = 0x10;
+
+const POINTER_SIZE: u32 = 4;
+
+/// A symbol reference from a bind, weak bind or lazy bind stream.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Bind {
+    /// Address of the pointer to be bound.
+    pub addr: u32,
+    /// Mangled name of the symbol.
+    pub symbol: String,
+    /// Offset to be added to the symbol's address.
+    pub addend: i32,
+}
+
+/// Cursor over a byte-code stream or the export trie.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, &'static str> {
+        let &byte = self.bytes.get(self.pos).ok_or("Truncated dyld info")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn uleb128(&mut self) -> Result<u64, &'static str> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 64 {
+                return Err("ULEB128 value is too large");
+            }
+            result |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> Result<i64, &'static str> {
+        let mut result = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 64 {
+                return Err("SLEB128 value is too large");
+            }
+            result |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && (byte & 0x40) != 0 {
+                    result |= -1i64 << shift;
+                }
+                return Ok(result);
+            }
+        }
+    }
+
+    fn cstr(&mut self) -> Result<&'a str, &'static str> {
+        let rest: &'a [u8] = &self.bytes[self.pos.min(self.bytes.len())..];
+        let len = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or("Unterminated string in dyld info")?;
+        self.pos += len + 1;
+        std::str::from_utf8(&rest[..len]).map_err(|_| "Invalid UTF-8 in dyld info")
+    }
+}
+
+/// Address of an offset within a segment. `segments` are the (slid) base
+/// addresses of the binary's segments, in load command order.
+fn segment_addr(segments: &[u32], segment: u8, offset: u64) -> Result<u32, &'static str> {
+    let &base = segments
+        .get(segment as usize)
+        .ok_or("Dyld info refers to non-existent segment")?;
+    let offset: u32 = offset.try_into().map_err(|_| "Segment offset too large")?;
+    Ok(base.wrapping_add(offset))
+}
+
+fn uleb_u32(value: u64) -> Result<u32, &'static str> {
+    value.try_into().map_err(|_| "Value in dyld info too large")
+}
+
+/// Rebase the pointer at `addr` and return the address after it.
+fn rebase_at(mem: &mut Mem, type_: u8, addr: Option<u32>, slide: u32) -> Result<u32, &'static str> {
+    let addr = addr.ok_or("Rebase before segment was set")?;
+    match type_ {
+        REBASE_TYPE_POINTER | REBASE_TYPE_TEXT_ABSOLUTE32 => {
+            let ptr: MutPtr<u32> = Ptr::from_bits(addr);
+            let value = mem.read(ptr);
+            mem.write(ptr, value.wrapping_add(slide));
+            Ok(addr.wrapping_add(POINTER_SIZE))
+        }
+        _ => Err("Unhandled rebase type"),
+    }
+}
+
+/// Run a rebase stream, adding `slide` to every pointer it lists.
+pub fn rebase(
+    opcodes: &[u8],
+    segments: &[u32],
+    slide: u32,
+    mem: &mut Mem,
+) -> Result<(), &'static str> {
+    let mut reader = Reader {
+        bytes: opcodes,
+        pos: 0,
+    };
+    let mut type_ = 0;
+    let mut addr: Option<u32> = None;
+
+    while !reader.at_end() {
+        let byte = reader.byte()?;
+        let immediate = byte & REBASE_IMMEDIATE_MASK;
+        match byte & REBASE_OPCODE_MASK {
+            REBASE_OPCODE_DONE => break,
+            REBASE_OPCODE_SET_TYPE_IMM => type_ = immediate,
+            REBASE_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB => {
+                let offset = reader.uleb128()?;
+                addr = Some(segment_addr(segments, immediate, offset)?);
+            }
+            REBASE_OPCODE_ADD_ADDR_ULEB => {
+                let delta = reader.uleb128()?;
+                addr = addr.map(|addr| addr.wrapping_add(delta as u32));
+            }
+            REBASE_OPCODE_ADD_ADDR_IMM_SCALED => {
+                let delta = u32::from(immediate) * POINTER_SIZE;
+                addr = addr.map(|addr| addr.wrapping_add(delta));
+            }
+            REBASE_OPCODE_DO_REBASE_IMM_TIMES => {
+                for _ in 0..immediate {
+                    addr = Some(rebase_at(mem, type_, addr, slide)?);
+                }
+            }
+            REBASE_OPCODE_DO_REBASE_ULEB_TIMES => {
+                let count = reader.uleb128()?;
+                for _ in 0..count {
+                    addr = Some(rebase_at(mem, type_, addr, slide)?);
+                }
+            }
+            REBASE_OPCODE_DO_REBASE_ADD_ADDR_ULEB => {
+                let next = rebase_at(mem, type_, addr, slide)?;
+                let delta = reader.uleb128()?;
+                addr = Some(next.wrapping_add(delta as u32));
+            }
+            REBASE_OPCODE_DO_REBASE_ULEB_TIMES_SKIPPING_ULEB => {
+                let count = reader.uleb128()?;
+                let skip = uleb_u32(reader.uleb128()?)?;
+                for _ in 0..count {
+                    let next = rebase_at(mem, type_, addr, slide)?;
+                    addr = Some(next.wrapping_add(skip));
+                }
+            }
+            _ => return Err("Unknown rebase opcode"),
+        }
+    }
+    Ok(())
+}
+
+/// Record a bind of the pointer at `addr` and return the address after it.
+///
+/// Weak imports aren't distinguished: if the symbol can't be found, the
+/// pointer is left as the addend (usually zero), which is what they need.
+fn bind_at(
+    binds: &mut Vec<Bind>,
+    symbol: &Option<String>,
+    type_: u8,
+    addend: i32,
+    addr: Option<u32>,
+) -> Result<u32, &'static str> {
+    let addr = addr.ok_or("Bind before segment was set")?;
+    let symbol = symbol.clone().ok_or("Bind before symbol was set")?;
+    if type_ != BIND_TYPE_POINTER {
+        return Err("Unhandled bind type");
+    }
+    binds.push(Bind {
+        addr,
+        symbol,
+        addend,
+    });
+    Ok(addr.wrapping_add(POINTER_SIZE))
+}
+
+/// Parse a bind, weak bind or lazy bind stream.
+///
+/// Lazy bind streams are a series of separate bind programs, each ending with
+/// `BIND_OPCODE_DONE`, so for these parsing continues after that opcode.
+///
+/// Weak bind entries that only declare a strong definition (in the binary
+/// itself) and don't bind anything are not returned.
+pub fn parse_binds(
+    opcodes: &[u8],
+    segments: &[u32],
+    lazy: bool,
+) -> Result<Vec<Bind>, &'static str> {
+    let mut reader = Reader {
+        bytes: opcodes,
+        pos: 0,
+    };
+    let mut binds = Vec::new();
+
+    let mut symbol: Option<String> = None;
+    let mut type_ = BIND_TYPE_POINTER;
+    let mut addend: i32 = 0;
+    let mut addr: Option<u32> = None;
+
+    while !reader.at_end() {
+        let byte = reader.byte()?;
+        let immediate = byte & BIND_IMMEDIATE_MASK;
+        match byte & BIND_OPCODE_MASK {
+            BIND_OPCODE_DONE => {
+                if !lazy {
+                    break;
+                }
+            }
+            // Library ordinals are ignored: like the rest of touchHLE's dynamic
+            // linker, symbols are looked up in a flat namespace.
+            BIND_OPCODE_SET_DYLIB_ORDINAL_IMM | BIND_OPCODE_SET_DYLIB_SPECIAL_IMM => (),
+            BIND_OPCODE_SET_DYLIB_ORDINAL_ULEB => {
+                reader.uleb128()?;
+            }
+            BIND_OPCODE_SET_SYMBOL_TRAILING_FLAGS_IMM => {
+                let name = reader.cstr()?.to_string();
+                if immediate & BIND_SYMBOL_FLAGS_NON_WEAK_DEFINITION != 0 {
+                    // Declares that this binary has a strong definition of
+                    // a weak symbol, which needs no action here.
+                    symbol = None;
+                } else {
+                    symbol = Some(name);
+                }
+            }
+            BIND_OPCODE_SET_TYPE_IMM => type_ = immediate,
+            BIND_OPCODE_SET_ADDEND_SLEB => {
+                addend = reader
+                    .sleb128()?
+                    .try_into()
+                    .map_err(|_| "Bind addend too large")?;
+            }
+            BIND_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB => {
+                let offset = reader.uleb128()?;
+                addr = Some(segment_addr(segments, immediate, offset)?);
+            }
+            BIND_OPCODE_ADD_ADDR_ULEB => {
+                let delta = reader.uleb128()?;
+                addr = addr.map(|addr| addr.wrapping_add(delta as u32));
+            }
+            BIND_OPCODE_DO_BIND => {
+                addr = Some(bind_at(&mut binds, &symbol, type_, addend, addr)?);
+            }
+            BIND_OPCODE_DO_BIND_ADD_ADDR_ULEB => {
+                let next = bind_at(&mut binds, &symbol, type_, addend, addr)?;
+                let delta = reader.uleb128()?;
+                addr = Some(next.wrapping_add(delta as u32));
+            }
+            BIND_OPCODE_DO_BIND_ADD_ADDR_IMM_SCALED => {
+                let next = bind_at(&mut binds, &symbol, type_, addend, addr)?;
+                addr = Some(next.wrapping_add(u32::from(immediate) * POINTER_SIZE));
+            }
+            BIND_OPCODE_DO_BIND_ULEB_TIMES_SKIPPING_ULEB => {
+                let count = reader.uleb128()?;
+                let skip = uleb_u32(reader.uleb128()?)?;
+                for _ in 0..count {
+                    let next = bind_at(&mut binds, &symbol, type_, addend, addr)?;
+                    addr = Some(next.wrapping_add(skip));
+                }
+            }
+            _ => return Err("Unknown bind opcode"),
+        }
+    }
+    Ok(binds)
+}
+
+/// Parse the export trie, returning the name and address of each symbol.
+/// `base` is the address of the Mach-O header, which addresses are relative
+/// to. Like the trie itself, Thumb function addresses have the Thumb bit set.
+pub fn parse_export_trie(trie: &[u8], base: u32) -> Result<Vec<(String, u32)>, &'static str> {
+    let mut exports = Vec::new();
+    if trie.is_empty() {
+        return Ok(exports);
+    }
+
+    // Depth-first traversal. Each entry is a node offset and the prefix of
+    // symbol names under that node.
+    let mut to_visit = vec![(0usize, String::new())];
+    let mut visited = Vec::new();
+    while let Some((offset, prefix)) = to_visit.pop() {
+        if visited.contains(&offset) {
+            return Err("Loop in export trie");
+        }
+        visited.push(offset);
+
+        let mut reader = Reader {
+            bytes: trie,
+            pos: offset,
+        };
+        let terminal_size = reader.uleb128()? as usize;
+        let children_pos = reader.pos + terminal_size;
+        if terminal_size != 0 {
+            let flags = reader.uleb128()?;
+            if flags & EXPORT_SYMBOL_FLAGS_REEXPORT != 0 {
+                // TODO: Re-exports of symbols from other libraries.
+                log_dbg!("Ignoring re-exported symbol {:?}", prefix);
+            } else {
+                // For stub-and-resolver symbols, this is the address of the
+                // stub, which is what non-lazy references should use.
+                let addr = uleb_u32(reader.uleb128()?)?;
+                if flags & EXPORT_SYMBOL_FLAGS_STUB_AND_RESOLVER != 0 {
+                    reader.uleb128()?;
+                }
+                let addr = match flags & EXPORT_SYMBOL_FLAGS_KIND_MASK {
+                    EXPORT_SYMBOL_FLAGS_KIND_REGULAR => base.wrapping_add(addr),
+                    EXPORT_SYMBOL_FLAGS_KIND_ABSOLUTE => addr,
+                    _ => return Err("Unhandled export symbol kind"),
+                };
+                exports.push((prefix.clone(), addr));
+            }
+        }
+
+        reader.pos = children_pos;
+        let child_count = reader.byte()?;
+        for _ in 0..child_count {
+            let label = reader.cstr()?;
+            let child_offset = reader.uleb128()? as usize;
+            to_visit.push((child_offset, format!("{}{}", prefix, label)));
+        }
+    }
+    Ok(exports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds() {
+        #[rustfmt::skip]
+        let opcodes = [
+            BIND_OPCODE_SET_DYLIB_ORDINAL_IMM | 1,
+            BIND_OPCODE_SET_SYMBOL_TRAILING_FLAGS_IMM, b'_', b'f', b'o', b'o', 0,
+            BIND_OPCODE_SET_TYPE_IMM | BIND_TYPE_POINTER,
+            BIND_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB | 1, 0x88, 0x01, // 0x88
+            BIND_OPCODE_DO_BIND_ULEB_TIMES_SKIPPING_ULEB, 2, 4,
+            BIND_OPCODE_SET_SYMBOL_TRAILING_FLAGS_IMM | 0x1, // weak import
+            b'_', b'b', b'a', b'r', 0,
+            BIND_OPCODE_SET_ADDEND_SLEB, 0x7c, // -4
+            BIND_OPCODE_DO_BIND,
+            BIND_OPCODE_DONE,
+        ];
+        let binds = parse_binds(&opcodes, &[0, 0x1000], false).unwrap();
+        let bind = |addr, symbol: &str, addend| Bind {
+            addr,
+            symbol: symbol.to_string(),
+            addend,
+        };
+        assert_eq!(
+            binds,
+            [
+                bind(0x1088, "_foo", 0),
+                bind(0x1090, "_foo", 0),
+                bind(0x1098, "_bar", -4),
+            ]
+        );
+    }
+
+    #[test]
+    fn export_trie() {
+        // Trie for "_foo" at 0x100 and "_foobar" (Thumb) at 0x201.
+        #[rustfmt::skip]
+        let trie = [
+            // 0: root, no terminal, one child
+            0, 1, b'_', b'f', b'o', b'o', 0, 8,
+            // 8: "_foo", terminal, one child
+            3, 0, 0x80, 0x02, 1, b'b', b'a', b'r', 0, 18,
+            // 18: "_foobar", terminal, no children
+            3, 0, 0x81, 0x04, 0,
+        ];
+        let mut exports = parse_export_trie(&trie, 0x1000).unwrap();
+        exports.sort();
+        assert_eq!(
+            exports,
+            [
+                ("_foo".to_string(), 0x1100),
+                ("_foobar".to_string(), 0x1201)
+            ]
+        );
+    }
+}
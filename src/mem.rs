@@ -17,6 +17,11 @@
 use crate::libc::wchar::wchar_t;
 
 mod allocator;
+mod allocator_debug;
+mod protection;
+
+pub use allocator_debug::{AllocatorReport, BacktraceContext, LeakSummary};
+pub use protection::Protection;
 
 /// Equivalent of `usize` for guest memory.
 pub type GuestUSize = u32;
@@ -33,6 +38,10 @@ pub const fn guest_size_of<T: Sized>() -> GuestUSize {
 /// Internal type for representing an untyped virtual address.
 type VAddr = GuestUSize;
 
+/// Size of a page of guest memory, which is the granularity of memory
+/// protection.
+pub const PAGE_SIZE: GuestUSize = 0x1000;
+
 /// Pointer type for guest memory, or the "guest pointer" type.
 ///
 /// The `MUT` type parameter determines whether this is mutable or not.
@@ -229,11 +238,21 @@ pub struct Mem {
     /// The size of the __PAGE_ZERO segment, where pointer accesses are trapped
     /// to prevent null pointer derefrences.
     ///
-    /// We don't have full memory protection, but we can check accesses in that
-    /// range.
+    /// This is also covered by `page_table`, but that only applies to the
+    /// guest CPU, whereas accesses by host code are checked against this.
     null_segment_size: VAddr,
 
+    /// Per-page protection for accesses by the guest CPU.
+    page_table: protection::PageTable,
+
     allocator: allocator::Allocator,
+
+    /// State for `--debug-allocator`, see [allocator_debug].
+    allocator_debug: Option<Box<allocator_debug::AllocatorDebugState>>,
+
+    /// Address of the most recent disallowed access by the guest CPU, if it was
+    /// to a page the app protected itself. See [Self::take_app_protection_fault].
+    app_protection_fault: std::cell::Cell<Option<VAddr>>,
 }
 
 impl Drop for Mem {
@@ -259,6 +278,10 @@ impl Mem {
     /// iPhone OS secondary thread stack size.
     pub const SECONDARY_THREAD_STACK_SIZE: GuestUSize = 512 * 1024;
 
+    /// Size of the inaccessible region below each thread's stack, so that
+    /// stack overflows are caught rather than corrupting other memory.
+    pub const STACK_GUARD_SIZE: GuestUSize = PAGE_SIZE;
+
     /// Create a fresh instance of guest memory.
     pub fn new() -> Mem {
         // This will hopefully get the host OS to lazily allocate the memory.
@@ -267,11 +290,24 @@ impl Mem {
 
         let allocator = allocator::Allocator::new();
 
-        Mem {
+        let mut mem = Mem {
             bytes,
             null_segment_size: 0,
+            page_table: protection::PageTable::new(),
             allocator,
-        }
+            allocator_debug: None,
+            app_protection_fault: Default::default(),
+        };
+        mem.protect_main_thread_stack_guard();
+        mem
+    }
+
+    fn protect_main_thread_stack_guard(&mut self) {
+        // The allocator reserves this along with the stack.
+        self.page_table.set_stack_guard(
+            Self::MAIN_THREAD_STACK_LOW_END - Self::STACK_GUARD_SIZE,
+            Self::STACK_GUARD_SIZE,
+        );
     }
 
     /// Take an existing instance of [Mem], but free and zero all the
@@ -283,13 +319,20 @@ impl Mem {
         let Mem {
             bytes: _,
             null_segment_size: _,
+            page_table: _,
             ref mut allocator,
+            allocator_debug: _,
+            app_protection_fault: _,
         } = mem;
         let used_chunks = allocator.reset_and_drain_used_chunks();
         for allocator::Chunk { base, size } in used_chunks {
             mem.bytes_mut()[base as usize..][..size.get() as usize].fill(0);
         }
         mem.null_segment_size = 0;
+        mem.page_table = protection::PageTable::new();
+        mem.protect_main_thread_stack_guard();
+        mem.allocator_debug = None;
+        mem.app_protection_fault.set(None);
         mem
     }
 
@@ -297,20 +340,121 @@ impl Mem {
     /// this outside of binary loading, and it won't be respected even if you
     /// do. The size must not have been set already, and must be page aligned.
     pub fn set_null_segment_size(&mut self, new_null_segment_size: VAddr) {
-        // TODO?: Maybe this should be replaced with a per-page rwx/callback
-        //        setting? Currently we don't properly follow segment
-        //        protections, which means that applications can write into
-        //        segments they shouldn't be able to. Adding that would fix
-        //        this, along with removing this special case.
         assert!(self.null_segment_size == 0);
-        assert!(new_null_segment_size % 0x1000 == 0);
+        assert!(new_null_segment_size % PAGE_SIZE == 0);
         self.allocator
             .reserve(allocator::Chunk::new(0, new_null_segment_size));
+        self.page_table
+            .set(0, new_null_segment_size, Protection::NONE);
         self.null_segment_size = new_null_segment_size;
     }
 
-    pub fn null_segment_size(&self) -> VAddr {
-        self.null_segment_size
+    /// Set the protection of the pages covering `size` bytes at `base`, which
+    /// must be page-aligned. This only affects the guest CPU.
+    pub fn set_protection(&mut self, base: VAddr, size: GuestUSize, protection: Protection) {
+        log_dbg!(
+            "Setting protection of {:#x}–{:#x} to {:?}",
+            base,
+            u64::from(base) + u64::from(size),
+            protection
+        );
+        self.page_table.set(base, size, protection);
+    }
+
+    /// Like [Self::set_protection], but for protection the app asked for with
+    /// `mprotect()` or `mmap()`. Disallowed accesses to these pages can be sent
+    /// to the app's signal handlers, see [Self::take_app_protection_fault].
+    pub fn set_protection_by_app(&mut self, base: VAddr, size: GuestUSize, protection: Protection) {
+        log_dbg!(
+            "App setting protection of {:#x}–{:#x} to {:?}",
+            base,
+            u64::from(base) + u64::from(size),
+            protection
+        );
+        self.page_table.set_by_app(base, size, protection);
+    }
+
+    /// If the guest CPU's most recent disallowed access was to a page the app
+    /// protected itself, return the faulting address. This can only be called
+    /// once per fault.
+    pub fn take_app_protection_fault(&self) -> Option<VAddr> {
+        self.app_protection_fault.take()
+    }
+
+    /// Make the pages covering `size` bytes at `base`, which must be
+    /// page-aligned, inaccessible as a guard region below a thread stack.
+    pub fn set_stack_guard(&mut self, base: VAddr, size: GuestUSize) {
+        self.page_table.set_stack_guard(base, size);
+    }
+
+    /// Check that the guest CPU is allowed to access `size` bytes at `addr` in
+    /// the ways given by `needed`, and panic if not. Only for use by
+    /// [crate::cpu].
+    pub fn check_guest_access(&self, addr: VAddr, size: GuestUSize, needed: Protection) {
+        if let Some(fault_addr) = self.page_table.find_disallowed(addr, size, needed) {
+            self.guest_access_fail(addr, size, needed, fault_addr)
+        }
+    }
+
+    #[cold]
+    fn guest_access_fail(
+        &self,
+        addr: VAddr,
+        size: GuestUSize,
+        needed: Protection,
+        fault_addr: VAddr,
+    ) -> ! {
+        self.app_protection_fault.set(
+            self.page_table
+                .is_app_protected(fault_addr)
+                .then_some(fault_addr),
+        );
+        if fault_addr < self.null_segment_size {
+            Self::null_check_fail(addr, size);
+        }
+        let kind = if needed.contains(Protection::EXECUTE) {
+            "execute"
+        } else if needed.contains(Protection::WRITE) {
+            "write"
+        } else {
+            "read"
+        };
+        if self.page_table.is_stack_guard(fault_addr) {
+            panic!(
+                "Attempted {} at {:#x} ({:#x} bytes) in a stack guard page, probably due to a stack overflow",
+                kind, addr, size
+            );
+        }
+        panic!(
+            "Attempted {} at {:#x} ({:#x} bytes) but page at {:#x} has protection {:?}",
+            kind,
+            addr,
+            size,
+            fault_addr & !(PAGE_SIZE - 1),
+            self.page_table.get(fault_addr)
+        );
+    }
+
+    /// Check whether the guest CPU can read from `addr` but can't write to it.
+    /// Only for use by [crate::cpu].
+    pub fn is_read_only_for_guest(&self, addr: VAddr) -> bool {
+        let protection = self.page_table.get(addr);
+        protection.contains(Protection::READ) && !protection.contains(Protection::WRITE)
+    }
+
+    /// Take the list of ranges of page numbers whose protection changed since
+    /// this was last called. Only for use by [crate::cpu].
+    pub fn take_protection_changes(&mut self) -> Vec<std::ops::Range<u32>> {
+        self.page_table.take_changes()
+    }
+
+    /// Iterate over runs of pages with the same protection within the range of
+    /// page numbers `pages`. Only for use by [crate::cpu].
+    pub fn protection_runs(
+        &self,
+        pages: std::ops::Range<u32>,
+    ) -> impl Iterator<Item = (std::ops::Range<u32>, Protection)> + '_ {
+        self.page_table.runs(pages)
     }
 
     /// Get a pointer to the full 4GiB of memory. This is only for use when
@@ -333,7 +477,7 @@ impl Mem {
     // the performance characteristics of this hasn't been profiled, but it
     // seems like a good idea to help the compiler optimise for the fast path
     #[cold]
-    fn null_check_fail(at: VAddr, size: GuestUSize) {
+    fn null_check_fail(at: VAddr, size: GuestUSize) -> ! {
         panic!(
             "Attempted null-page access at {:#x} ({:#x} bytes)",
             at, size
@@ -467,12 +611,32 @@ impl Mem {
 
     /// Allocate `size` bytes.
     pub fn alloc(&mut self, size: GuestUSize) -> MutVoidPtr {
-        let ptr = Ptr::from_bits(self.allocator.alloc(size));
+        let ptr = if let Some(mut debug) = self.allocator_debug.take() {
+            let ptr = debug.alloc(self, size);
+            self.allocator_debug = Some(debug);
+            Ptr::from_bits(ptr)
+        } else {
+            Ptr::from_bits(self.allocator.alloc(size))
+        };
         log_dbg!("Allocated {:?} ({:#x} bytes)", ptr, size);
         ptr
     }
 
     pub fn realloc(&mut self, old_ptr: MutVoidPtr, size: GuestUSize) -> MutVoidPtr {
+        if old_ptr.is_null() {
+            return self.alloc(size);
+        }
+
+        if let Some(ref debug) = self.allocator_debug {
+            // Always move the allocation, so that uses of the old pointer are
+            // caught as use-after-free.
+            let old_size = debug.allocation_size(old_ptr.to_bits()).unwrap_or(0);
+            let new_ptr = self.alloc(size);
+            self.memmove(new_ptr, old_ptr.cast_const(), old_size.min(size));
+            self.free(old_ptr);
+            return new_ptr;
+        }
+
         // TODO: for a moment we always assume that we do not have enough size
         //       to realloc inplace
         let old_size = self.allocator.find_allocated_size(old_ptr.to_bits());
@@ -487,11 +651,64 @@ impl Mem {
 
     /// Free an allocation made with one of the `alloc` methods on this type.
     pub fn free(&mut self, ptr: MutVoidPtr) {
+        if let Some(mut debug) = self.allocator_debug.take() {
+            debug.free(self, ptr.to_bits());
+            self.allocator_debug = Some(debug);
+            log_dbg!("Freed {:?} (quarantined)", ptr);
+            return;
+        }
         let size = self.allocator.free(ptr.to_bits());
         self.bytes_at_mut(ptr.cast(), size).fill(0);
+        // The app might have used mprotect() on the memory.
+        self.page_table.reset_within(ptr.to_bits(), size);
         log_dbg!("Freed {:?} ({:#x} bytes)", ptr, size);
     }
 
+    /// Total size of the allocations made with the `alloc` methods on this
+    /// type that haven't been freed, i.e. the size of the heap.
+    pub fn heap_usage(&self) -> GuestUSize {
+        self.allocator.allocated_bytes()
+    }
+
+    /// Turn on the allocator debugging mode (`--debug-allocator`). This must
+    /// be done before anything is allocated.
+    pub fn enable_allocator_debugging(&mut self) {
+        self.allocator_debug = Some(Default::default());
+    }
+
+    pub fn is_allocator_debugging_enabled(&self) -> bool {
+        self.allocator_debug.is_some()
+    }
+
+    /// Tell the allocator debugging mode where to find the guest backtrace for
+    /// allocations made from now on. Returns the previous context so it can be
+    /// restored. Does nothing if the mode is disabled.
+    pub fn set_backtrace_context(
+        &mut self,
+        context: Option<BacktraceContext>,
+    ) -> Option<BacktraceContext> {
+        self.allocator_debug
+            .as_mut()
+            .and_then(|debug| debug.set_context(context))
+    }
+
+    /// Take the problems found by the allocator debugging mode since the last
+    /// call to this.
+    pub fn take_allocator_reports(&mut self) -> Vec<AllocatorReport> {
+        self.allocator_debug
+            .as_mut()
+            .map(|debug| debug.take_reports())
+            .unwrap_or_default()
+    }
+
+    /// Summarize the allocations that haven't been freed, if the allocator
+    /// debugging mode is enabled.
+    pub fn allocator_leak_summary(&self) -> Option<LeakSummary> {
+        self.allocator_debug
+            .as_ref()
+            .map(|debug| debug.leak_summary())
+    }
+
     /// Allocate memory large enough for a value of type `T` and write the value
     /// to it. Equivalent to [Self::alloc] + [Self::write].
     pub fn alloc_and_write<T>(&mut self, value: T) -> MutPtr<T>
@@ -548,4 +765,30 @@ impl Mem {
     pub fn reserve(&mut self, base: VAddr, size: GuestUSize) {
         self.allocator.reserve(allocator::Chunk::new(base, size));
     }
+
+    /// Return a region of address space that was marked as unusable with
+    /// [Self::reserve] or [Self::reserve_anywhere] to the memory allocator.
+    /// The memory is zeroed and its protection is reset.
+    pub fn unreserve(&mut self, base: VAddr, size: GuestUSize) {
+        self.bytes_at_mut(Ptr::from_bits(base), size).fill(0);
+        self.page_table.set(base, size, Protection::ALL);
+        self.allocator.unreserve(allocator::Chunk::new(base, size));
+    }
+
+    /// Check whether a region of address space is unused, so that
+    /// [Self::reserve] would succeed.
+    pub fn can_reserve(&self, base: VAddr, size: GuestUSize) -> bool {
+        size != 0
+            && base.checked_add(size - 1).is_some()
+            && self
+                .allocator
+                .can_reserve(allocator::Chunk::new(base, size))
+    }
+
+    /// Like [Self::reserve], but for a region of address space anywhere that is
+    /// unused, with a base address that is a multiple of `align`. Returns the
+    /// base address, or [None] if there's no large enough region.
+    pub fn reserve_anywhere(&mut self, size: GuestUSize, align: GuestUSize) -> Option<VAddr> {
+        self.allocator.reserve_anywhere(size, align)
+    }
 }
@@ -255,13 +255,19 @@ use collections::{ChunkMap, SizeBucketedChunkMap};
 pub struct Allocator {
     used_chunks: ChunkMap,
     unused_chunks: SizeBucketedChunkMap,
+    /// Total size of chunks allocated with [Self::alloc] and not yet freed.
+    allocated_bytes: GuestUSize,
 }
 
 impl Allocator {
     pub fn new() -> Allocator {
-        let main_thread_stack =
-            Chunk::new(Mem::MAIN_THREAD_STACK_LOW_END, Mem::MAIN_THREAD_STACK_SIZE);
-        let rest = Chunk::new(0, Mem::MAIN_THREAD_STACK_LOW_END);
+        // The stack's guard page is reserved too (see Mem::new()).
+        let main_thread_stack_low_end = Mem::MAIN_THREAD_STACK_LOW_END - Mem::STACK_GUARD_SIZE;
+        let main_thread_stack = Chunk::new(
+            main_thread_stack_low_end,
+            Mem::MAIN_THREAD_STACK_SIZE + Mem::STACK_GUARD_SIZE,
+        );
+        let rest = Chunk::new(0, main_thread_stack_low_end);
 
         let mut used_chunks: ChunkMap = Default::default();
         used_chunks.insert(main_thread_stack);
@@ -272,6 +278,7 @@ impl Allocator {
         Allocator {
             used_chunks,
             unused_chunks,
+            allocated_bytes: 0,
         }
     }
 
@@ -299,6 +306,26 @@ impl Allocator {
         self.used_chunks.insert(chunk);
     }
 
+    /// Check whether a chunk could be reserved with [Self::reserve].
+    pub fn can_reserve(&self, chunk: Chunk) -> bool {
+        self.unused_chunks
+            .iter()
+            .any(|unused_chunk| unused_chunk.trisect_by(chunk).is_some())
+    }
+
+    /// Find an unused chunk of `size` bytes whose base is a multiple of `align`
+    /// and reserve it. Returns the base, or [None] if there is no space.
+    pub fn reserve_anywhere(&mut self, size: GuestUSize, align: GuestUSize) -> Option<VAddr> {
+        assert!(align.is_power_of_two());
+        let chunk = self.unused_chunks.iter().find_map(|unused_chunk| {
+            let base = unused_chunk.base.checked_add(align - 1)? & !(align - 1);
+            let last_byte = base.checked_add(size - 1)?;
+            (last_byte <= unused_chunk.last_byte()).then(|| Chunk::new(base, size))
+        })?;
+        self.reserve(chunk);
+        Some(chunk.base)
+    }
+
     pub fn alloc(&mut self, size: GuestUSize) -> VAddr {
         let size = size.max(MIN_CHUNK_SIZE);
         let size = if size % MIN_CHUNK_SIZE != 0 {
@@ -314,10 +341,15 @@ impl Allocator {
             );
         };
         self.used_chunks.insert(alloc);
+        self.allocated_bytes += alloc.size.get();
 
         alloc.base
     }
 
+    pub fn allocated_bytes(&self) -> GuestUSize {
+        self.allocated_bytes
+    }
+
     /// This is used for realloc
     pub fn find_allocated_size(&mut self, base: VAddr) -> GuestUSize {
         let Some(size) = self.used_chunks.get_size_with_base(base) else {
@@ -334,23 +366,34 @@ impl Allocator {
             return 0;
         };
 
-        if let Some(adjacent) = self
-            .unused_chunks
-            .remove_with_base(freed.last_byte() + 1)
-            .or_else(|| self.unused_chunks.remove_with_end(freed.base))
-        {
-            let combined = Chunk::new(
-                freed.base.min(adjacent.base),
-                freed.size.get() + adjacent.size.get(),
-            );
-            self.unused_chunks.insert(combined);
-        } else {
-            self.unused_chunks.insert(freed);
-        }
+        self.insert_unused(freed);
+        self.allocated_bytes -= freed.size.get();
 
         freed.size.get()
     }
 
+    /// Undo [Self::reserve]. The chunk must not overlap any used or unused
+    /// chunk.
+    pub fn unreserve(&mut self, chunk: Chunk) {
+        self.insert_unused(chunk);
+    }
+
+    /// Make a chunk available for allocation, merging it with any adjacent
+    /// unused chunks.
+    fn insert_unused(&mut self, mut chunk: Chunk) {
+        if let Some(after) = chunk
+            .last_byte()
+            .checked_add(1)
+            .and_then(|end| self.unused_chunks.remove_with_base(end))
+        {
+            chunk = Chunk::new(chunk.base, chunk.size.get() + after.size.get());
+        }
+        if let Some(before) = self.unused_chunks.remove_with_end(chunk.base) {
+            chunk = Chunk::new(before.base, before.size.get() + chunk.size.get());
+        }
+        self.unused_chunks.insert(chunk);
+    }
+
     pub(super) fn reset_and_drain_used_chunks(&mut self) -> impl Iterator<Item = Chunk> {
         let chunks = std::mem::take(&mut self.used_chunks);
         *self = Allocator::new();
@@ -0,0 +1,416 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Allocator debugging mode (`--debug-allocator`).
+//!
+//! When this is enabled, every allocation made with [Mem::alloc] is surrounded
+//! by "redzones" filled with a known byte value, which are checked when the
+//! allocation is freed, so that buffer overflows and underflows are noticed.
+//! Freed memory is filled with another byte value and kept in a quarantine for
+//! a while rather than being reused immediately. Writes to it can then be
+//! noticed when it leaves the quarantine, and double frees can be told apart
+//! from frees of pointers that were never allocated.
+//!
+//! Allocations and frees record the guest backtrace at the point the guest
+//! called into the host (see [BacktraceContext]), which is included in
+//! reports and used to group allocations that were never freed when the app
+//! exits. Reports are queued up rather than printed immediately, because
+//! symbolicating a backtrace needs the [crate::Environment].
+
+use super::{ConstPtr, GuestUSize, Mem, MutPtr, VAddr};
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+/// Size of each redzone. This is a multiple of the allocator's chunk size, so
+/// the alignment of allocations is unaffected.
+const REDZONE_SIZE: GuestUSize = 16;
+const REDZONE_BYTE: u8 = 0xFA;
+const FREED_BYTE: u8 = 0xDD;
+/// How many bytes of freed chunks to hold onto before actually freeing them.
+const QUARANTINE_LIMIT: GuestUSize = 16 * 1024 * 1024;
+const MAX_BACKTRACE_FRAMES: usize = 16;
+
+/// Guest return addresses, innermost first.
+pub type Backtrace = Rc<[VAddr]>;
+
+/// The state of the guest thread that is currently calling into the host, which
+/// is needed to find the guest backtrace.
+#[derive(Clone)]
+pub struct BacktraceContext {
+    pub lr: VAddr,
+    pub fp: VAddr,
+    /// Range of addresses occupied by the thread's stack.
+    pub stack: RangeInclusive<VAddr>,
+    /// Return addresses that mark the end of the guest part of the stack.
+    pub end_addrs: [VAddr; 2],
+}
+
+/// A problem found by the allocator debugging mode.
+pub struct AllocatorReport {
+    pub message: String,
+    /// Labelled backtraces, e.g. where the memory was allocated. These may be
+    /// missing if the host allocated or freed memory on its own behalf.
+    pub backtraces: Vec<(&'static str, Option<Backtrace>)>,
+}
+
+/// Allocations that are still live, grouped by where they were allocated.
+pub struct LeakSummary {
+    /// Backtrace, number of allocations and total bytes, sorted by descending
+    /// total bytes.
+    pub sites: Vec<(Backtrace, usize, u64)>,
+    /// Number of allocations and total bytes for allocations without a guest
+    /// backtrace.
+    pub untraced: (usize, u64),
+}
+
+struct Allocation {
+    size: GuestUSize,
+    backtrace: Option<Backtrace>,
+}
+
+struct FreedAllocation {
+    size: GuestUSize,
+    alloc_backtrace: Option<Backtrace>,
+    free_backtrace: Option<Backtrace>,
+}
+
+#[derive(Default)]
+pub(super) struct AllocatorDebugState {
+    context: Option<BacktraceContext>,
+    /// Keyed by the address returned to the user, not the chunk base.
+    live: HashMap<VAddr, Allocation>,
+    /// Allocations in the quarantine, keyed like `live`.
+    freed: HashMap<VAddr, FreedAllocation>,
+    /// Order in which allocations entered the quarantine.
+    quarantine: VecDeque<VAddr>,
+    quarantine_bytes: GuestUSize,
+    reports: Vec<AllocatorReport>,
+}
+
+impl AllocatorDebugState {
+    pub(super) fn set_context(
+        &mut self,
+        context: Option<BacktraceContext>,
+    ) -> Option<BacktraceContext> {
+        std::mem::replace(&mut self.context, context)
+    }
+
+    pub(super) fn take_reports(&mut self) -> Vec<AllocatorReport> {
+        std::mem::take(&mut self.reports)
+    }
+
+    pub(super) fn allocation_size(&self, ptr: VAddr) -> Option<GuestUSize> {
+        self.live.get(&ptr).map(|allocation| allocation.size)
+    }
+
+    /// Walk the frame pointer chain, like the Environment's stack traces do.
+    fn backtrace(&self, mem: &Mem) -> Option<Backtrace> {
+        let context = self.context.as_ref()?;
+        if context.end_addrs.contains(&context.lr) {
+            return Some(Vec::new().into());
+        }
+        let mut frames = vec![context.lr];
+        let mut fp = context.fp;
+        while frames.len() < MAX_BACKTRACE_FRAMES
+            && context.stack.contains(&fp)
+            && fp
+                .checked_add(7)
+                .is_some_and(|end| context.stack.contains(&end))
+        {
+            let lr: VAddr = mem.read(ConstPtr::from_bits(fp + 4));
+            if context.end_addrs.contains(&lr) {
+                break;
+            }
+            frames.push(lr);
+            fp = mem.read(ConstPtr::from_bits(fp));
+        }
+        Some(frames.into())
+    }
+
+    pub(super) fn alloc(&mut self, mem: &mut Mem, size: GuestUSize) -> VAddr {
+        let backtrace = self.backtrace(mem);
+
+        let base = mem
+            .allocator
+            .alloc(size.checked_add(REDZONE_SIZE * 2).unwrap());
+        let chunk_size = mem.allocator.find_allocated_size(base);
+        let ptr = base + REDZONE_SIZE;
+        // The memory in between is zero, like with any other allocation.
+        mem.bytes_at_mut(MutPtr::from_bits(base), REDZONE_SIZE)
+            .fill(REDZONE_BYTE);
+        mem.bytes_at_mut(
+            MutPtr::from_bits(ptr + size),
+            chunk_size - REDZONE_SIZE - size,
+        )
+        .fill(REDZONE_BYTE);
+
+        self.live.insert(ptr, Allocation { size, backtrace });
+        ptr
+    }
+
+    pub(super) fn free(&mut self, mem: &mut Mem, ptr: VAddr) {
+        let free_backtrace = self.backtrace(mem);
+
+        let Some(Allocation {
+            size,
+            backtrace: alloc_backtrace,
+        }) = self.live.remove(&ptr)
+        else {
+            let report = if let Some(freed) = self.freed.get(&ptr) {
+                AllocatorReport {
+                    message: format!("Double free of {:#x} ({:#x} bytes)", ptr, freed.size),
+                    backtraces: vec![
+                        ("Allocated at", freed.alloc_backtrace.clone()),
+                        ("First freed at", freed.free_backtrace.clone()),
+                        ("Freed again at", free_backtrace),
+                    ],
+                }
+            } else {
+                AllocatorReport {
+                    message: format!("Free of {:#x}, which is not an allocation", ptr),
+                    backtraces: vec![("Freed at", free_backtrace)],
+                }
+            };
+            self.reports.push(report);
+            return;
+        };
+
+        let base = ptr - REDZONE_SIZE;
+        let chunk_size = mem.allocator.find_allocated_size(base);
+        let before = mem.bytes_at(ConstPtr::from_bits(base), REDZONE_SIZE);
+        let after = mem.bytes_at(
+            ConstPtr::from_bits(ptr + size),
+            chunk_size - REDZONE_SIZE - size,
+        );
+        // Report the corrupted byte furthest from the allocation, since that
+        // says the most about how far out of bounds the app wrote.
+        let underflow = before.iter().position(|&b| b != REDZONE_BYTE);
+        let overflow = after.iter().rposition(|&b| b != REDZONE_BYTE);
+        let backtraces = || {
+            vec![
+                ("Allocated at", alloc_backtrace.clone()),
+                ("Freed at", free_backtrace.clone()),
+            ]
+        };
+        if let Some(offset) = underflow {
+            self.reports.push(AllocatorReport {
+                message: format!(
+                    "Buffer underflow: {:#x} bytes before {:#x} ({:#x} bytes) were overwritten",
+                    REDZONE_SIZE - offset as GuestUSize,
+                    ptr,
+                    size,
+                ),
+                backtraces: backtraces(),
+            });
+        }
+        if let Some(offset) = overflow {
+            self.reports.push(AllocatorReport {
+                message: format!(
+                    "Buffer overflow: {:#x} bytes after {:#x} ({:#x} bytes) were overwritten",
+                    offset + 1,
+                    ptr,
+                    size,
+                ),
+                backtraces: backtraces(),
+            });
+        }
+
+        mem.bytes_at_mut(MutPtr::from_bits(ptr), size)
+            .fill(FREED_BYTE);
+        self.freed.insert(
+            ptr,
+            FreedAllocation {
+                size,
+                alloc_backtrace,
+                free_backtrace,
+            },
+        );
+        self.quarantine.push_back(ptr);
+        self.quarantine_bytes += chunk_size;
+        while self.quarantine_bytes > QUARANTINE_LIMIT {
+            self.release_oldest(mem);
+        }
+    }
+
+    /// Take the oldest allocation out of the quarantine and really free it.
+    fn release_oldest(&mut self, mem: &mut Mem) {
+        let ptr = self.quarantine.pop_front().unwrap();
+        let freed = self.freed.remove(&ptr).unwrap();
+
+        let bytes = mem.bytes_at(ConstPtr::from_bits(ptr), freed.size);
+        if let Some(offset) = bytes.iter().position(|&b| b != FREED_BYTE) {
+            self.reports.push(AllocatorReport {
+                message: format!(
+                    "Use after free: {:#x} ({:#x} bytes) was written to at offset {:#x} after being freed",
+                    ptr,
+                    freed.size,
+                    offset,
+                ),
+                backtraces: vec![
+                    ("Allocated at", freed.alloc_backtrace),
+                    ("Freed at", freed.free_backtrace),
+                ],
+            });
+        }
+
+        let base = ptr - REDZONE_SIZE;
+        let chunk_size = mem.allocator.free(base);
+        mem.bytes_at_mut(MutPtr::from_bits(base), chunk_size)
+            .fill(0);
+        mem.page_table.reset_within(base, chunk_size);
+        self.quarantine_bytes -= chunk_size;
+    }
+
+    pub(super) fn leak_summary(&self) -> LeakSummary {
+        let mut sites: HashMap<&[VAddr], (Backtrace, usize, u64)> = HashMap::new();
+        let mut untraced = (0, 0);
+        for allocation in self.live.values() {
+            let (count, bytes) = match allocation.backtrace {
+                Some(ref backtrace) => {
+                    let (_, count, bytes) = sites
+                        .entry(&backtrace[..])
+                        .or_insert_with(|| (backtrace.clone(), 0, 0));
+                    (count, bytes)
+                }
+                None => (&mut untraced.0, &mut untraced.1),
+            };
+            *count += 1;
+            *bytes += u64::from(allocation.size);
+        }
+        let mut sites: Vec<_> = sites.into_values().collect();
+        sites.sort_by(|a, b| b.2.cmp(&a.2));
+        LeakSummary { sites, untraced }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debug_mem() -> Mem {
+        let mut mem = Mem::new();
+        mem.enable_allocator_debugging();
+        mem
+    }
+
+    /// A context whose backtrace is just `lr`, since `fp` is outside the stack.
+    fn context(lr: VAddr) -> BacktraceContext {
+        BacktraceContext {
+            lr,
+            fp: 0,
+            stack: 0x1000..=0x1fff,
+            end_addrs: [0, 0],
+        }
+    }
+
+    fn messages(mem: &mut Mem) -> Vec<String> {
+        mem.take_allocator_reports()
+            .into_iter()
+            .map(|report| report.message)
+            .collect()
+    }
+
+    #[test]
+    fn in_bounds_writes() {
+        let mut mem = debug_mem();
+        let ptr = mem.alloc(10).cast::<u8>();
+        mem.write(ptr, 1u8);
+        mem.write(ptr + 9, 1u8);
+        mem.free(ptr.cast());
+        assert!(messages(&mut mem).is_empty());
+    }
+
+    #[test]
+    fn overflow() {
+        let mut mem = debug_mem();
+        let ptr = mem.alloc(10).cast::<u8>();
+        mem.write(ptr + 10, 1u8);
+        mem.free(ptr.cast());
+        let messages = messages(&mut mem);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Buffer overflow: 0x1 bytes after"));
+    }
+
+    #[test]
+    fn underflow() {
+        let mut mem = debug_mem();
+        let ptr = mem.alloc(10).cast::<u8>();
+        mem.write(ptr - 2, 1u8);
+        mem.free(ptr.cast());
+        let messages = messages(&mut mem);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Buffer underflow: 0x2 bytes before"));
+    }
+
+    #[test]
+    fn use_after_free() {
+        let mut mem = debug_mem();
+        let ptr = mem.alloc(16).cast::<u8>();
+        mem.free(ptr.cast());
+        // Quarantined memory isn't reused straight away.
+        let other = mem.alloc(16);
+        assert_ne!(other.to_bits(), ptr.to_bits());
+        mem.free(other);
+
+        mem.write(ptr + 3, 1u8);
+        assert!(messages(&mut mem).is_empty());
+        // Push the first allocation out of the quarantine.
+        let big = mem.alloc(QUARANTINE_LIMIT);
+        mem.free(big);
+        let messages = messages(&mut mem);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Use after free"));
+        assert!(messages[0].ends_with("at offset 0x3 after being freed"));
+    }
+
+    #[test]
+    fn double_free() {
+        let mut mem = debug_mem();
+        mem.set_backtrace_context(Some(context(0x1000)));
+        let ptr = mem.alloc(8);
+        mem.free(ptr);
+        mem.free(ptr);
+        let reports = mem.take_allocator_reports();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].message.starts_with("Double free"));
+        let labels: Vec<_> = reports[0]
+            .backtraces
+            .iter()
+            .map(|&(label, ref backtrace)| {
+                assert_eq!(backtrace.as_deref(), Some(&[0x1000][..]));
+                label
+            })
+            .collect();
+        assert_eq!(labels, ["Allocated at", "First freed at", "Freed again at"]);
+
+        mem.free(ptr + 4);
+        let messages = messages(&mut mem);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].ends_with("which is not an allocation"));
+    }
+
+    #[test]
+    fn leak_summary() {
+        let mut mem = debug_mem();
+        mem.set_backtrace_context(Some(context(0x1000)));
+        mem.alloc(8);
+        mem.alloc(8);
+        let freed = mem.alloc(8);
+        mem.free(freed);
+        mem.set_backtrace_context(Some(context(0x2000)));
+        mem.alloc(100);
+        mem.set_backtrace_context(None);
+        mem.alloc(4);
+
+        let LeakSummary { sites, untraced } = mem.allocator_leak_summary().unwrap();
+        let sites: Vec<_> = sites
+            .iter()
+            .map(|(backtrace, count, bytes)| (&backtrace[..], *count, *bytes))
+            .collect();
+        assert_eq!(sites, [(&[0x2000][..], 1, 100), (&[0x1000][..], 2, 16)]);
+        assert_eq!(untraced, (1, 4));
+    }
+}
@@ -0,0 +1,207 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Per-page memory protection.
+//!
+//! Each page of the guest address space has read, write and execute
+//! permissions, like on a real device. These come from the `vm_prot_t` values
+//! of Mach-O segments, from thread stack guard pages and from `mprotect()`
+//! and `mmap()`.
+//! Pages that nothing has said anything about are readable, writable and
+//! executable, which is how touchHLE always behaved before this existed.
+//!
+//! Only accesses made by the emulated CPU are checked (see [crate::cpu]).
+//! Host code can still access any page other than the null segment, much like
+//! the kernel on a real device, which is needed for things like the dynamic
+//! linker rewriting stubs in `__TEXT`.
+
+use super::{GuestUSize, VAddr, PAGE_SIZE};
+use std::collections::HashSet;
+use std::ops::Range;
+
+const PAGE_COUNT: usize = 1 << (32 - PAGE_SIZE.trailing_zeros());
+
+/// Set of permissions for a page. The bit values are the same as those of
+/// `vm_prot_t` and the `PROT_*` constants used by `mprotect()`.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Protection(u8);
+
+impl Protection {
+    pub const NONE: Self = Protection(0);
+    pub const READ: Self = Protection(1);
+    pub const WRITE: Self = Protection(2);
+    pub const EXECUTE: Self = Protection(4);
+    pub const ALL: Self = Protection(7);
+
+    /// Convert from a `vm_prot_t` or `PROT_*` value, ignoring unknown bits.
+    pub fn from_bits_truncate(bits: u32) -> Self {
+        Protection((bits & Self::ALL.bits()) as u8)
+    }
+    pub fn bits(self) -> u32 {
+        self.0.into()
+    }
+    pub fn contains(self, other: Self) -> bool {
+        (self.0 & other.0) == other.0
+    }
+}
+
+impl std::ops::BitOr for Protection {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        Protection(self.0 | other.0)
+    }
+}
+
+impl std::fmt::Debug for Protection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Same style as vmmap: "rw-", "r-x" etc.
+        for (flag, c) in [(Self::READ, 'r'), (Self::WRITE, 'w'), (Self::EXECUTE, 'x')] {
+            write!(f, "{}", if self.contains(flag) { c } else { '-' })?;
+        }
+        Ok(())
+    }
+}
+
+/// Protection of every page in the address space.
+pub(super) struct PageTable {
+    pages: Box<[Protection]>,
+    /// Page numbers of guard pages below thread stacks, so a stack overflow can
+    /// be reported as such.
+    stack_guard_pages: HashSet<u32>,
+    /// Page numbers whose protection was set by the app itself (with
+    /// `mprotect()` or `mmap()`), so that only faults the app might expect are
+    /// sent to its signal handlers.
+    app_protected_pages: HashSet<u32>,
+    /// Ranges of page numbers whose protection has changed since the last call
+    /// to [Self::take_changes].
+    changes: Vec<Range<u32>>,
+}
+
+impl PageTable {
+    pub(super) fn new() -> PageTable {
+        PageTable {
+            pages: vec![Protection::ALL; PAGE_COUNT].into_boxed_slice(),
+            stack_guard_pages: HashSet::new(),
+            app_protected_pages: HashSet::new(),
+            changes: Vec::new(),
+        }
+    }
+
+    fn page_range(base: VAddr, size: GuestUSize) -> Range<u32> {
+        assert!(base % PAGE_SIZE == 0);
+        let end = u64::from(base) + u64::from(size);
+        let end_page = end.div_ceil(PAGE_SIZE.into());
+        assert!(end_page <= PAGE_COUNT as u64);
+        (base / PAGE_SIZE)..(end_page as u32)
+    }
+
+    pub(super) fn get(&self, addr: VAddr) -> Protection {
+        self.pages[(addr / PAGE_SIZE) as usize]
+    }
+
+    pub(super) fn set(&mut self, base: VAddr, size: GuestUSize, protection: Protection) {
+        let pages = Self::page_range(base, size);
+        if pages.is_empty() {
+            return;
+        }
+        self.pages[pages.start as usize..pages.end as usize].fill(protection);
+        for page in pages.clone() {
+            self.stack_guard_pages.remove(&page);
+            self.app_protected_pages.remove(&page);
+        }
+        self.changes.push(pages);
+    }
+
+    /// Like [Self::set], but for protection requested by the app.
+    pub(super) fn set_by_app(&mut self, base: VAddr, size: GuestUSize, protection: Protection) {
+        self.set(base, size, protection);
+        self.app_protected_pages
+            .extend(Self::page_range(base, size));
+    }
+
+    pub(super) fn is_app_protected(&self, addr: VAddr) -> bool {
+        self.app_protected_pages.contains(&(addr / PAGE_SIZE))
+    }
+
+    /// Make the pages that lie entirely within the `size` bytes at `base`
+    /// fully accessible again, e.g. when that memory has been freed and might
+    /// be reused. Unlike [Self::set], `base` needn't be page-aligned, and no
+    /// change is recorded if the pages already had that protection.
+    pub(super) fn reset_within(&mut self, base: VAddr, size: GuestUSize) {
+        let first_page = base.div_ceil(PAGE_SIZE);
+        let end_page = ((u64::from(base) + u64::from(size)) / u64::from(PAGE_SIZE)) as u32;
+        if first_page >= end_page {
+            return;
+        }
+        if self.pages[first_page as usize..end_page as usize]
+            .iter()
+            .all(|&protection| protection == Protection::ALL)
+        {
+            return;
+        }
+        self.set(
+            first_page * PAGE_SIZE,
+            (end_page - first_page) * PAGE_SIZE,
+            Protection::ALL,
+        );
+    }
+
+    pub(super) fn set_stack_guard(&mut self, base: VAddr, size: GuestUSize) {
+        self.set(base, size, Protection::NONE);
+        self.stack_guard_pages.extend(Self::page_range(base, size));
+    }
+
+    pub(super) fn is_stack_guard(&self, addr: VAddr) -> bool {
+        self.stack_guard_pages.contains(&(addr / PAGE_SIZE))
+    }
+
+    /// Find the first page overlapping the `size` bytes at `addr` that lacks
+    /// any of the permissions in `needed`, and return an address within it.
+    pub(super) fn find_disallowed(
+        &self,
+        addr: VAddr,
+        size: GuestUSize,
+        needed: Protection,
+    ) -> Option<VAddr> {
+        let last_byte = addr.wrapping_add(size.max(1) - 1);
+        let first_page = addr / PAGE_SIZE;
+        let last_page = last_byte / PAGE_SIZE;
+        if !self.pages[first_page as usize].contains(needed) {
+            Some(addr)
+        } else if last_page != first_page && !self.pages[last_page as usize].contains(needed) {
+            Some(last_page * PAGE_SIZE)
+        } else {
+            // Accesses by the CPU are never more than 8 bytes, so they can't
+            // span more than two pages.
+            None
+        }
+    }
+
+    /// Pages whose protection changed since the last call to this, so that
+    /// [crate::cpu::Cpu] can update its own state. Returns page numbers.
+    pub(super) fn take_changes(&mut self) -> Vec<Range<u32>> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Iterate over runs of consecutive pages within `pages` that have the
+    /// same protection.
+    pub(super) fn runs(
+        &self,
+        pages: Range<u32>,
+    ) -> impl Iterator<Item = (Range<u32>, Protection)> + '_ {
+        let mut next = pages.start;
+        std::iter::from_fn(move || {
+            if next >= pages.end {
+                return None;
+            }
+            let start = next;
+            let protection = self.pages[start as usize];
+            while next < pages.end && self.pages[next as usize] == protection {
+                next += 1;
+            }
+            Some((start..next, protection))
+        })
+    }
+}
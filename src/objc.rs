@@ -18,11 +18,15 @@
 //! classes that are both (considering Objective-C's support for inheritance,
 //! categories and dynamic class editing).
 
+use crate::abi::GuestFunction;
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::ConstPtr;
 use crate::MutexId;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+mod associated_objects;
 mod classes;
+mod exceptions;
 mod messages;
 mod methods;
 mod objects;
@@ -30,9 +34,12 @@ mod properties;
 mod selectors;
 mod synchronization;
 
+pub use associated_objects::release_associated_objects;
 pub use classes::{objc_classes, Class, ClassExports, ClassTemplate};
+pub use exceptions::objc_exception_throw;
 pub use messages::{
-    autorelease, msg, msg_class, msg_send, msg_send_super2, msg_super, objc_super, release, retain,
+    autorelease, msg, msg_class, msg_send, msg_send_super2, msg_send_with_arg_words, msg_super,
+    objc_super, release, retain,
 };
 pub use methods::{HostIMP, IMP};
 pub use objects::{
@@ -40,11 +47,28 @@ pub use objects::{
 };
 pub use selectors::{selector, SEL};
 
-use classes::{ClassHostObject, FakeClass, UnimplementedClass, CLASS_LISTS};
+use associated_objects::{
+    objc_getAssociatedObject, objc_removeAssociatedObjects, objc_setAssociatedObject,
+    AssociatedObjects,
+};
+use classes::{
+    class_getName, class_getSuperclass, class_isMetaClass, objc_getClass, objc_lookUpClass,
+    object_getClass, ClassHostObject, FakeClass, UnimplementedClass, CLASS_LISTS,
+};
+use exceptions::{
+    objc_exception_extract, objc_exception_match, objc_exception_try_enter,
+    objc_exception_try_exit, ExceptionHandlers,
+};
 use messages::{
-    objc_msgSend, objc_msgSendSuper2, objc_msgSend_stret, MsgSendSignature, MsgSendSuperSignature,
+    _objc_msgForward, _objc_msgForward_stret, objc_msgSend, objc_msgSendSuper2, objc_msgSend_stret,
+    MsgSendSignature, MsgSendSuperSignature,
+};
+use methods::{
+    class_addMethod, class_copyMethodList, class_getClassMethod, class_getInstanceMethod,
+    class_getMethodImplementation, class_replaceMethod, class_respondsToSelector,
+    method_exchangeImplementations, method_getImplementation, method_getName, method_list_t,
+    method_setImplementation, Method,
 };
-use methods::method_list_t;
 use objects::{objc_object, HostObjectEntry};
 use properties::{objc_copyStruct, objc_setProperty};
 use selectors::sel_registerName;
@@ -60,6 +84,9 @@ pub type NSZonePtr = crate::mem::MutVoidPtr;
 pub struct ObjC {
     /// Known selectors (interned method name strings).
     selectors: HashMap<String, SEL>,
+    /// The values of `selectors`, for quickly checking whether a [SEL] is the
+    /// canonical one for its name.
+    known_selectors: HashSet<SEL>,
 
     /// Mapping of known (guest) object pointers to their host objects.
     ///
@@ -74,20 +101,62 @@ pub struct ObjC {
     /// Mutexes used in @synchronized blocks (objc_sync_enter/exit).
     sync_mutexes: HashMap<id, MutexId>,
 
+    /// Registered `@try` blocks (objc_exception_try_enter/exit).
+    exception_handlers: ExceptionHandlers,
+
     /// Temporary storage for optional type information when sending a message.
     /// Type information isn't part of the `objc_msgSend` ABI, so an alternative
     /// channel is needed.
     message_type_info: Option<(std::any::TypeId, &'static str)>,
+
+    /// Guest function pointers created for host methods, for when the guest
+    /// asks for a method's implementation. The key is the address of the
+    /// [HostIMP].
+    host_imp_guest_functions: HashMap<usize, GuestFunction>,
+    /// Reverse of `host_imp_guest_functions`, keyed by the address (with Thumb
+    /// bit) of the guest function, so host methods can be moved around by the
+    /// guest without going through the guest function pointer.
+    guest_function_host_imps: HashMap<u32, &'static dyn HostIMP>,
+
+    /// `Method` handles given out by functions like `class_getInstanceMethod`,
+    /// keyed by the class and selector they refer to.
+    method_handles: HashMap<(Class, SEL), Method>,
+    /// Reverse of `method_handles`.
+    method_handle_targets: HashMap<Method, (Class, SEL)>,
+
+    /// Results of method lookups by `objc_msgSend`, keyed by the class the
+    /// lookup started at and the selector. This saves walking the superclass
+    /// chain on every message send. See [ObjC::flush_method_cache].
+    method_cache: HashMap<(Class, SEL), IMP>,
+    /// The classes that have an entry in `method_cache` for each selector, so
+    /// that flushing the cache doesn't need to look at every entry.
+    method_cache_classes: HashMap<SEL, Vec<Class>>,
+
+    /// C strings returned by `class_getName`, which must stay valid forever.
+    class_name_cstrs: HashMap<Class, ConstPtr<u8>>,
+
+    /// Values associated with objects via `objc_setAssociatedObject`.
+    associated_objects: HashMap<id, AssociatedObjects>,
 }
 
 impl ObjC {
     pub fn new() -> ObjC {
         ObjC {
             selectors: HashMap::new(),
+            known_selectors: HashSet::new(),
             objects: HashMap::new(),
             classes: HashMap::new(),
             sync_mutexes: HashMap::new(),
+            exception_handlers: Default::default(),
             message_type_info: None,
+            host_imp_guest_functions: HashMap::new(),
+            guest_function_host_imps: HashMap::new(),
+            method_handles: HashMap::new(),
+            method_handle_targets: HashMap::new(),
+            method_cache: HashMap::new(),
+            method_cache_classes: HashMap::new(),
+            class_name_cstrs: HashMap::new(),
+            associated_objects: HashMap::new(),
         }
     }
 }
@@ -96,9 +165,36 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(objc_msgSend(_, _)),
     export_c_func!(objc_msgSend_stret(_, _, _)),
     export_c_func!(objc_msgSendSuper2(_, _)),
+    export_c_func!(_objc_msgForward(_, _)),
+    export_c_func!(_objc_msgForward_stret(_, _, _)),
     export_c_func!(objc_setProperty(_, _, _, _, _, _)),
     export_c_func!(objc_copyStruct(_, _, _, _, _)),
     export_c_func!(objc_sync_enter(_)),
     export_c_func!(objc_sync_exit(_)),
+    export_c_func!(objc_exception_try_enter(_)),
+    export_c_func!(objc_exception_try_exit(_)),
+    export_c_func!(objc_exception_extract(_)),
+    export_c_func!(objc_exception_match(_, _)),
+    export_c_func!(objc_exception_throw(_)),
     export_c_func!(sel_registerName(_)),
+    export_c_func!(objc_getClass(_)),
+    export_c_func!(objc_lookUpClass(_)),
+    export_c_func!(object_getClass(_)),
+    export_c_func!(class_getName(_)),
+    export_c_func!(class_getSuperclass(_)),
+    export_c_func!(class_isMetaClass(_)),
+    export_c_func!(class_respondsToSelector(_, _)),
+    export_c_func!(class_getInstanceMethod(_, _)),
+    export_c_func!(class_getClassMethod(_, _)),
+    export_c_func!(class_getMethodImplementation(_, _)),
+    export_c_func!(class_addMethod(_, _, _, _)),
+    export_c_func!(class_replaceMethod(_, _, _, _)),
+    export_c_func!(class_copyMethodList(_, _)),
+    export_c_func!(method_getName(_)),
+    export_c_func!(method_getImplementation(_)),
+    export_c_func!(method_setImplementation(_, _)),
+    export_c_func!(method_exchangeImplementations(_, _)),
+    export_c_func!(objc_setAssociatedObject(_, _, _, _)),
+    export_c_func!(objc_getAssociatedObject(_, _)),
+    export_c_func!(objc_removeAssociatedObjects(_)),
 ];
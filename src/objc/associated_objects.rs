@@ -0,0 +1,124 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Associated objects (`objc_setAssociatedObject` and friends).
+//!
+//! These let an app attach extra values to any object, keyed by an arbitrary
+//! pointer, which is mostly used by categories that want to add "ivars" to
+//! existing classes.
+//!
+//! Resources:
+//! - Apple's documentation of [`objc_setAssociatedObject`](https://developer.apple.com/documentation/objectivec/1418509-objc_setassociatedobject?language=objc)
+//!   and [`objc_AssociationPolicy`](https://developer.apple.com/documentation/objectivec/objc_associationpolicy?language=objc)
+
+use super::{id, msg, nil, release, retain};
+use crate::mem::{ConstVoidPtr, MutVoidPtr, Ptr};
+use crate::Environment;
+use std::collections::HashMap;
+
+#[allow(non_camel_case_types)]
+type objc_AssociationPolicy = u32;
+const OBJC_ASSOCIATION_ASSIGN: objc_AssociationPolicy = 0;
+const OBJC_ASSOCIATION_RETAIN_NONATOMIC: objc_AssociationPolicy = 1;
+const OBJC_ASSOCIATION_COPY_NONATOMIC: objc_AssociationPolicy = 3;
+const OBJC_ASSOCIATION_RETAIN: objc_AssociationPolicy = 0o1401;
+const OBJC_ASSOCIATION_COPY: objc_AssociationPolicy = 0o1403;
+
+/// Values associated with an object, by key. The policy is kept so that it's
+/// known whether the value needs to be released.
+pub(super) type AssociatedObjects = HashMap<ConstVoidPtr, (id, objc_AssociationPolicy)>;
+
+fn policy_owns_value(policy: objc_AssociationPolicy) -> bool {
+    match policy {
+        OBJC_ASSOCIATION_RETAIN_NONATOMIC
+        | OBJC_ASSOCIATION_COPY_NONATOMIC
+        | OBJC_ASSOCIATION_RETAIN
+        | OBJC_ASSOCIATION_COPY => true,
+        // Unknown policies are treated as OBJC_ASSOCIATION_ASSIGN, see
+        // objc_setAssociatedObject.
+        _ => false,
+    }
+}
+
+pub(super) fn objc_setAssociatedObject(
+    env: &mut Environment,
+    object: id,
+    key: ConstVoidPtr,
+    value: id,
+    policy: objc_AssociationPolicy,
+) {
+    // Atomicity is irrelevant since we never switch threads here.
+    let value = if value == nil {
+        nil
+    } else {
+        match policy {
+            OBJC_ASSOCIATION_ASSIGN => value,
+            OBJC_ASSOCIATION_RETAIN_NONATOMIC | OBJC_ASSOCIATION_RETAIN => retain(env, value),
+            OBJC_ASSOCIATION_COPY_NONATOMIC | OBJC_ASSOCIATION_COPY => {
+                let zone: MutVoidPtr = Ptr::null();
+                msg![env; value copyWithZone:zone]
+            }
+            _ => {
+                log!(
+                    "Warning: Unknown association policy {:#o}, treating as OBJC_ASSOCIATION_ASSIGN",
+                    policy
+                );
+                value
+            }
+        }
+    };
+
+    let old = if value == nil {
+        let values = env.objc.associated_objects.get_mut(&object);
+        let old = values.and_then(|values| values.remove(&key));
+        if env
+            .objc
+            .associated_objects
+            .get(&object)
+            .is_some_and(|values| values.is_empty())
+        {
+            env.objc.associated_objects.remove(&object);
+        }
+        old
+    } else {
+        env.objc
+            .associated_objects
+            .entry(object)
+            .or_default()
+            .insert(key, (value, policy))
+    };
+
+    if let Some((old_value, old_policy)) = old {
+        if policy_owns_value(old_policy) {
+            release(env, old_value);
+        }
+    }
+}
+
+pub(super) fn objc_getAssociatedObject(env: &mut Environment, object: id, key: ConstVoidPtr) -> id {
+    env.objc
+        .associated_objects
+        .get(&object)
+        .and_then(|values| values.get(&key))
+        .map_or(nil, |&(value, _)| value)
+}
+
+pub(super) fn objc_removeAssociatedObjects(env: &mut Environment, object: id) {
+    release_associated_objects(env, object);
+}
+
+/// Remove all values associated with an object, releasing them if necessary.
+/// This must be called when an object is deallocated, which `NSObject`'s
+/// `release` does just before sending `dealloc`.
+pub fn release_associated_objects(env: &mut Environment, object: id) {
+    let Some(values) = env.objc.associated_objects.remove(&object) else {
+        return;
+    };
+    for (_key, (value, policy)) in values {
+        if policy_owns_value(policy) {
+            release(env, value);
+        }
+    }
+}
@@ -18,6 +18,7 @@ use super::{
 };
 use crate::mach_o::MachO;
 use crate::mem::{guest_size_of, ConstPtr, ConstVoidPtr, GuestUSize, Mem, Ptr, SafeRead};
+use crate::Environment;
 use std::collections::HashMap;
 
 /// Generic pointer to an Objective-C class or metaclass.
@@ -38,6 +39,9 @@ pub(super) struct ClassHostObject {
     pub(super) is_metaclass: bool,
     pub(super) superclass: Class,
     pub(super) methods: HashMap<SEL, IMP>,
+    /// Objective-C type encodings of guest methods, where known. Host methods
+    /// don't have these here, see [HostIMP::type_encoding].
+    pub(super) method_types: HashMap<SEL, ConstPtr<u8>>,
     /// Offset into the allocated memory for the object where the ivars of
     /// instances of this class or metaclass (respectively: normal objects or
     /// classes) should live. This is always >= the value in the superclass.
@@ -357,6 +361,7 @@ impl ClassHostObject {
                     (objc.selectors[name], IMP::Host(host_imp))
                 }),
             ),
+            method_types: HashMap::new(),
             // maybe this should be 0 for NSObject? does it matter?
             _instance_start: size,
             instance_size: size,
@@ -382,11 +387,13 @@ impl ClassHostObject {
             is_metaclass,
             superclass,
             methods: HashMap::new(),
+            method_types: HashMap::new(),
             _instance_start: instance_start,
             instance_size,
         };
 
         if !base_methods.is_null() {
+            // The class is new, so nothing can be in the method cache for it.
             host_object.add_methods_from_bin(base_methods, mem, objc);
         }
 
@@ -655,6 +662,7 @@ impl ObjC {
                         is_metaclass: Default::default(),
                         superclass: nil,
                         methods: Default::default(),
+                        method_types: Default::default(),
                         _instance_start: Default::default(),
                         instance_size: Default::default(),
                     },
@@ -676,8 +684,12 @@ impl ObjC {
                     host_obj.name,
                     class,
                 );
-                host_obj.add_methods_from_bin(methods, mem, self);
+                let sels = host_obj.add_methods_from_bin(methods, mem, self);
                 *self.borrow_mut::<ClassHostObject>(class) = host_obj;
+                // Categories can override existing methods.
+                for sel in sels {
+                    self.flush_method_cache(class, sel);
+                }
             }
         }
     }
@@ -702,6 +714,36 @@ impl ObjC {
         }
     }
 
+    /// Get the superclass of a class, or [nil] if it has none (or if it is a
+    /// placeholder for a class we don't implement).
+    pub fn get_superclass(&self, class: Class) -> Class {
+        let host_object = self.get_host_object(class).unwrap();
+        if let Some(&ClassHostObject { superclass, .. }) = host_object.as_any().downcast_ref() {
+            superclass
+        } else {
+            nil
+        }
+    }
+
+    pub fn is_metaclass(&self, class: Class) -> bool {
+        self.try_is_metaclass(class)
+            .unwrap_or_else(|| panic!("{:?} is not a class", class))
+    }
+
+    /// Like [Self::is_metaclass], but returns [None] if `class` isn't a class.
+    fn try_is_metaclass(&self, class: Class) -> Option<bool> {
+        let host_object = self.get_host_object(class)?.as_any();
+        if let Some(&ClassHostObject { is_metaclass, .. }) = host_object.downcast_ref() {
+            Some(is_metaclass)
+        } else if let Some(&UnimplementedClass { is_metaclass, .. }) = host_object.downcast_ref() {
+            Some(is_metaclass)
+        } else if let Some(&FakeClass { is_metaclass, .. }) = host_object.downcast_ref() {
+            Some(is_metaclass)
+        } else {
+            None
+        }
+    }
+
     pub fn get_class_name(&self, class: Class) -> &str {
         let host_object = self.get_host_object(class).unwrap();
         if let Some(ClassHostObject { name, .. }) = host_object.as_any().downcast_ref() {
@@ -715,3 +757,71 @@ impl ObjC {
         }
     }
 }
+
+pub(super) fn objc_getClass(env: &mut Environment, name: ConstPtr<u8>) -> Class {
+    let name = env.mem.cstr_at_utf8(name).unwrap().to_string();
+    if let Some(class) = env
+        .objc
+        .get_class(&name, /* is_metaclass: */ false, &env.mem)
+    {
+        let any = env.objc.get_host_object(class).unwrap().as_any();
+        if any.is::<UnimplementedClass>() {
+            log!(
+                "Warning: objc_getClass() for unimplemented class {:?}",
+                name
+            );
+            return nil;
+        }
+        return class;
+    }
+    if ObjC::find_template(&name).is_some() {
+        return env.objc.get_known_class(&name, &mut env.mem);
+    }
+    log!("Warning: objc_getClass() for unknown class {:?}", name);
+    nil
+}
+
+pub(super) fn objc_lookUpClass(env: &mut Environment, name: ConstPtr<u8>) -> Class {
+    objc_getClass(env, name)
+}
+
+pub(super) fn object_getClass(env: &mut Environment, object: id) -> Class {
+    if object == nil {
+        return nil;
+    }
+    ObjC::read_isa(object, &env.mem)
+}
+
+pub(super) fn class_getName(env: &mut Environment, class: Class) -> ConstPtr<u8> {
+    if let Some(&name) = env.objc.class_name_cstrs.get(&class) {
+        return name;
+    }
+    let name = if class == nil {
+        "nil".to_string()
+    } else {
+        env.objc.get_class_name(class).to_string()
+    };
+    let name = env.mem.alloc_and_write_cstr(name.as_bytes()).cast_const();
+    env.objc.class_name_cstrs.insert(class, name);
+    name
+}
+
+pub(super) fn class_getSuperclass(env: &mut Environment, class: Class) -> Class {
+    if class == nil {
+        return nil;
+    }
+    env.objc.get_superclass(class)
+}
+
+pub(super) fn class_isMetaClass(env: &mut Environment, class: Class) -> bool {
+    if class == nil {
+        return false;
+    }
+    env.objc.try_is_metaclass(class).unwrap_or_else(|| {
+        log!(
+            "Warning: class_isMetaClass() called on non-class {:?}",
+            class
+        );
+        false
+    })
+}
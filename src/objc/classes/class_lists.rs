@@ -7,19 +7,24 @@
 //! very long and frequently-updated list.
 
 use crate::frameworks::{
-    av_audio, core_animation, core_foundation, core_graphics, foundation, media_player, opengles,
-    store_kit, uikit,
+    av_audio, core_animation, core_foundation, core_graphics, foundation, game_kit, media_player,
+    opengles, store_kit, uikit,
 };
 
 /// All the lists of classes that the runtime should search through.
 pub const CLASS_LISTS: &[super::ClassExports] = &[
     crate::app_picker::CLASSES, // Not a framework! Special internal classes.
+    core_animation::ca_animation::CLASSES,
+    core_animation::ca_display_link::CLASSES,
     core_animation::ca_eagl_layer::CLASSES,
     core_animation::ca_layer::CLASSES,
+    core_animation::ca_media_timing_function::CLASSES,
+    core_animation::ca_transaction::CLASSES,
     core_graphics::cg_data_provider::CLASSES,
     core_graphics::cg_color_space::CLASSES,
     core_graphics::cg_context::CLASSES,
     core_graphics::cg_image::CLASSES,
+    core_graphics::cg_path::CLASSES,
     core_foundation::cf_run_loop_timer::CLASSES, // Special internal classes.
     foundation::ns_array::CLASSES,
     foundation::ns_autorelease_pool::CLASSES,
@@ -32,15 +37,20 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     foundation::ns_dictionary::CLASSES,
     foundation::ns_enumerator::CLASSES,
     foundation::ns_error::CLASSES,
+    foundation::ns_exception::CLASSES,
     foundation::ns_file_manager::CLASSES,
+    foundation::ns_invocation::CLASSES,
+    foundation::ns_keyed_archiver::CLASSES,
     foundation::ns_keyed_unarchiver::CLASSES,
     foundation::ns_locale::CLASSES,
     foundation::ns_lock::CLASSES,
+    foundation::ns_method_signature::CLASSES,
     foundation::ns_notification::CLASSES,
     foundation::ns_notification_center::CLASSES,
     foundation::ns_null::CLASSES,
     foundation::ns_object::CLASSES,
     foundation::ns_process_info::CLASSES,
+    foundation::ns_property_list_serialization::CLASSES,
     foundation::ns_run_loop::CLASSES,
     foundation::ns_set::CLASSES,
     foundation::ns_string::CLASSES,
@@ -50,6 +60,12 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     foundation::ns_user_defaults::CLASSES,
     foundation::ns_value::CLASSES,
     av_audio::av_audio_player::CLASSES,
+    game_kit::CLASSES,
+    game_kit::gk_achievement::CLASSES,
+    game_kit::gk_leaderboard::CLASSES,
+    game_kit::gk_leaderboard_view_controller::CLASSES,
+    game_kit::gk_local_player::CLASSES,
+    game_kit::gk_score::CLASSES,
     media_player::movie_player::CLASSES,
     media_player::music_player::CLASSES,
     opengles::eagl::CLASSES,
@@ -57,6 +73,9 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     uikit::ui_accelerometer::CLASSES,
     uikit::ui_activity_indicator_view::CLASSES,
     uikit::ui_application::CLASSES,
+    uikit::ui_bar_item::CLASSES,
+    uikit::ui_bar_item::ui_bar_button_item::CLASSES,
+    uikit::ui_bar_item::ui_tab_bar_item::CLASSES,
     uikit::ui_color::CLASSES,
     uikit::ui_device::CLASSES,
     uikit::ui_event::CLASSES,
@@ -68,12 +87,23 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     uikit::ui_screen::CLASSES,
     uikit::ui_touch::CLASSES,
     uikit::ui_view::CLASSES,
+    uikit::ui_view::ui_action_sheet::CLASSES,
     uikit::ui_view::ui_alert_view::CLASSES,
     uikit::ui_view::ui_control::CLASSES,
     uikit::ui_view::ui_control::ui_button::CLASSES,
+    uikit::ui_view::ui_control::ui_page_control::CLASSES,
+    uikit::ui_view::ui_control::ui_segmented_control::CLASSES,
+    uikit::ui_view::ui_control::ui_slider::CLASSES,
+    uikit::ui_view::ui_control::ui_switch::CLASSES,
     uikit::ui_view::ui_control::ui_text_field::CLASSES,
     uikit::ui_view::ui_image_view::CLASSES,
     uikit::ui_view::ui_label::CLASSES,
+    uikit::ui_view::ui_navigation_bar::CLASSES,
+    uikit::ui_view::ui_progress_view::CLASSES,
+    uikit::ui_view::ui_status_bar::CLASSES,
+    uikit::ui_view::ui_tab_bar::CLASSES,
     uikit::ui_view::ui_window::CLASSES,
     uikit::ui_view_controller::CLASSES,
+    uikit::ui_view_controller::ui_navigation_controller::CLASSES,
+    uikit::ui_view_controller::ui_tab_bar_controller::CLASSES,
 ];
@@ -0,0 +1,147 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Handling of `@try`/`@catch`/`@finally` and `@throw`
+//! (`objc_exception_try_enter` etc).
+//!
+//! The iPhone OS 2 and 3 ABI uses `setjmp`/`longjmp`-based exceptions: each
+//! `@try` block allocates an `objc_exception_data` structure on the stack,
+//! registers it with `objc_exception_try_enter`, and calls `_setjmp` on it.
+//! `objc_exception_throw` then `longjmp`s to the innermost registered handler,
+//! after which the compiler-generated code uses `objc_exception_extract` and
+//! `objc_exception_match` to pick a `@catch` block. Handlers are linked into a
+//! per-thread chain using the structure itself, as in Apple's implementation.
+//!
+//! Resources:
+//! - [Source code for the old exception ABI](https://opensource.apple.com/source/objc4/objc4-437.1/runtime/objc-exception.m.auto.html), otherwise undocumented.
+
+use super::{id, msg, nil, Class};
+use crate::frameworks::foundation::ns_string::to_rust_string;
+use crate::libc::setjmp::{do_longjmp, jmp_buf};
+use crate::mem::{GuestUSize, MutPtr, MutVoidPtr, Ptr};
+use crate::{Environment, ThreadId};
+use std::collections::HashMap;
+
+/// The `jmp_buf` at the start of the structure is followed by four pointers.
+/// The first is used for the exception that was thrown, the second for the
+/// previous handler in the chain. The others are unused.
+const POINTERS_OFFSET: GuestUSize = 28 * 4;
+
+fn exception_ptr(data: MutVoidPtr) -> MutPtr<id> {
+    (data.cast::<u8>() + POINTERS_OFFSET).cast()
+}
+fn previous_handler_ptr(data: MutVoidPtr) -> MutPtr<MutVoidPtr> {
+    (data.cast::<u8>() + POINTERS_OFFSET + 4).cast()
+}
+
+#[derive(Default)]
+pub(super) struct ExceptionHandlers {
+    /// Innermost handler for each thread.
+    top_handlers: HashMap<ThreadId, MutVoidPtr>,
+}
+
+pub(super) fn objc_exception_try_enter(env: &mut Environment, data: MutVoidPtr) {
+    let thread = env.current_thread;
+    let previous = env
+        .objc
+        .exception_handlers
+        .top_handlers
+        .get(&thread)
+        .copied()
+        .unwrap_or(Ptr::null());
+    log_dbg!(
+        "Thread {} entering @try with data {:?} (previous: {:?})",
+        thread,
+        data,
+        previous
+    );
+    env.mem.write(previous_handler_ptr(data), previous);
+    env.mem.write(exception_ptr(data), nil);
+    env.objc
+        .exception_handlers
+        .top_handlers
+        .insert(thread, data);
+}
+
+pub(super) fn objc_exception_try_exit(env: &mut Environment, data: MutVoidPtr) {
+    let thread = env.current_thread;
+    log_dbg!("Thread {} leaving @try with data {:?}", thread, data);
+    let previous = env.mem.read(previous_handler_ptr(data));
+    env.objc
+        .exception_handlers
+        .top_handlers
+        .insert(thread, previous);
+}
+
+pub(super) fn objc_exception_extract(env: &mut Environment, data: MutVoidPtr) -> id {
+    env.mem.read(exception_ptr(data))
+}
+
+pub(super) fn objc_exception_match(env: &mut Environment, class: Class, exception: id) -> bool {
+    msg![env; exception isKindOfClass:class]
+}
+
+/// `@throw`. Host code should usually use
+/// [crate::frameworks::foundation::ns_exception::raise] instead.
+pub fn objc_exception_throw(env: &mut Environment, exception: id) {
+    let thread = env.current_thread;
+    let sp = env.cpu.regs()[crate::cpu::Cpu::SP];
+
+    // Handlers should always be removed by objc_exception_try_exit or by a
+    // throw, but if a @try block's stack frame was skipped over by some other
+    // kind of unwinding (e.g. longjmp), its handler might still be there.
+    let mut handler = env
+        .objc
+        .exception_handlers
+        .top_handlers
+        .get(&thread)
+        .copied()
+        .unwrap_or(Ptr::null());
+    while !handler.is_null() {
+        let handler_sp: u32 = env.mem.read(handler.cast::<u32>() + 8);
+        if handler_sp >= sp {
+            break;
+        }
+        log_dbg!("Discarding stale @try handler {:?}", handler);
+        handler = env.mem.read(previous_handler_ptr(handler));
+    }
+
+    if handler.is_null() {
+        env.objc.exception_handlers.top_handlers.remove(&thread);
+        let exception_class = env.objc.get_known_class("NSException", &mut env.mem);
+        let description = if exception == nil {
+            "nil".to_string()
+        } else if msg![env; exception isKindOfClass:exception_class] {
+            let name: id = msg![env; exception name];
+            let reason: id = msg![env; exception reason];
+            let name = to_rust_string(env, name);
+            let reason = if reason == nil {
+                "(no reason)".into()
+            } else {
+                to_rust_string(env, reason)
+            };
+            format!("{}: {}", name, reason)
+        } else {
+            let description: id = msg![env; exception description];
+            to_rust_string(env, description).into_owned()
+        };
+        panic!("Uncaught Objective-C exception {}", description);
+    }
+
+    log_dbg!(
+        "Thread {} throwing exception {:?} to @try handler {:?}",
+        thread,
+        exception,
+        handler
+    );
+    let previous = env.mem.read(previous_handler_ptr(handler));
+    env.objc
+        .exception_handlers
+        .top_handlers
+        .insert(thread, previous);
+    env.mem.write(exception_ptr(handler), exception);
+    let buf: jmp_buf = handler.cast();
+    do_longjmp(env, buf, 1)
+}
@@ -10,10 +10,13 @@
 //! - [Apple's documentation of `objc_msgSend`](https://developer.apple.com/documentation/objectivec/1456712-objc_msgsend)
 //! - Mike Ash's [objc_msgSend's New Prototype](https://www.mikeash.com/pyblog/objc_msgsends-new-prototype.html)
 //! - Peter Steinberger's [Calling Super at Runtime in Swift](https://steipete.com/posts/calling-super-at-runtime/) explains `objc_msgSendSuper2`
+//! - Apple's [Objective-C Runtime Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjCRuntimeGuide/Articles/ocrtForwarding.html)
+//!   also explains message forwarding and [dynamic method resolution](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjCRuntimeGuide/Articles/ocrtDynamicResolution.html)
 
 use super::{id, nil, Class, ObjC, IMP, SEL};
-use crate::abi::{CallFromHost, GuestRet};
-use crate::mem::{ConstPtr, MutVoidPtr, SafeRead};
+use crate::abi::{extend_stack_for_args, write_arg_words, CallFromHost, GuestArg, GuestRet};
+use crate::cpu::Cpu;
+use crate::mem::{ConstPtr, MutVoidPtr, Ptr, SafeRead};
 use crate::Environment;
 use std::any::TypeId;
 
@@ -29,8 +32,17 @@ use std::any::TypeId;
 /// Similarly, the return value of `objc_msgSend` is whatever value is returned
 /// by the method implementation. We are relying on CallFromGuest not
 /// overwriting it.
+///
+/// `stret` says whether this is the `objc_msgSend_stret` variant, which only
+/// matters if the message has to be forwarded.
 #[allow(non_snake_case)]
-fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2: Option<Class>) {
+fn objc_msgSend_inner(
+    env: &mut Environment,
+    receiver: id,
+    selector: SEL,
+    super2: Option<Class>,
+    stret: bool,
+) {
     let message_type_info = env.objc.message_type_info.take();
 
     if receiver == nil {
@@ -43,34 +55,48 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
     let orig_class = super2.unwrap_or_else(|| ObjC::read_isa(receiver, &env.mem));
     assert!(orig_class != nil);
 
-    // Traverse the chain of superclasses to find the method implementation.
+    // Method tables and the method cache are keyed by the registered selector.
+    // Selectors in the app binary are registered when it's loaded, but nothing
+    // stops the app sending a message with some other string. Checking for
+    // that is left until the method cache misses, to keep the common case fast.
+    let mut selector = selector;
 
+    // Traverse the chain of superclasses to find the method implementation,
+    // unless the method cache already has the result.
+
+    // The class the lookup starts at, which is the method cache key.
+    let mut lookup_class = None;
     let mut class = orig_class;
     loop {
         if class == nil {
             assert!(class != orig_class);
+            let selector = env.objc.canonicalize_selector(selector, &mut env.mem);
 
-            let class_host_object = env.objc.get_host_object(orig_class).unwrap();
-            let &super::ClassHostObject {
-                ref name,
-                is_metaclass,
-                ..
-            } = class_host_object.as_any().downcast_ref().unwrap();
+            if resolve_method(env, receiver, selector, super2) {
+                objc_msgSend_inner(env, receiver, selector, super2, stret);
+                return;
+            }
 
-            panic!(
-                "{} {:?} ({}class \"{}\", {:?}){} does not respond to selector \"{}\"!",
-                if is_metaclass { "Class" } else { "Object" },
-                receiver,
-                if is_metaclass { "meta" } else { "" },
-                name,
-                orig_class,
-                if super2.is_some() {
-                    "'s superclass"
-                } else {
-                    ""
-                },
-                selector.as_str(&env.mem),
-            );
+            forward_message(env, receiver, selector, stret);
+            return;
+        }
+
+        // The super-call variant of objc_msgSend starts the lookup at the
+        // superclass (see below).
+        if lookup_class.is_none() && (super2.is_none() || class != orig_class) {
+            lookup_class = Some(class);
+            if let Some(&imp) = env.objc.method_cache.get(&(class, selector)) {
+                call_imp(env, imp, receiver, selector, message_type_info);
+                return;
+            }
+            let canonical = env.objc.canonicalize_selector(selector, &mut env.mem);
+            if canonical != selector {
+                selector = canonical;
+                if let Some(&imp) = env.objc.method_cache.get(&(class, selector)) {
+                    call_imp(env, imp, receiver, selector, message_type_info);
+                    return;
+                }
+            }
         }
 
         let host_object = env.objc.get_host_object(class).unwrap();
@@ -88,36 +114,9 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
                 continue;
             }
 
-            if let Some(imp) = methods.get(&selector) {
-                match imp {
-                    IMP::Host(host_imp) => {
-                        // TODO: do type checks when calling GuestIMPs too.
-                        // That requires using Objective-C type strings, rather
-                        // than Rust types, and should probably warn rather than
-                        // panicking, because apps might rely on type punning.
-                        if let Some((sent_type_id, sent_type_desc)) = message_type_info {
-                            let (expected_type_id, expected_type_desc) = host_imp.type_info();
-                            if sent_type_id != expected_type_id {
-                                panic!(
-                                    "\
-Type mismatch when sending message {} to {:?}!
-- Message has type: {:?} / {}
-- Method expects type: {:?} / {}",
-                                    selector.as_str(&env.mem),
-                                    receiver,
-                                    sent_type_id,
-                                    sent_type_desc,
-                                    expected_type_id,
-                                    expected_type_desc
-                                );
-                            }
-                        }
-                        host_imp.call_from_guest(env)
-                    }
-                    // We can't create a new stack frame, because that would
-                    // interfere with pass-through of stack arguments.
-                    IMP::Guest(guest_imp) => guest_imp.call_without_pushing_stack_frame(env),
-                }
+            if let Some(&imp) = methods.get(&selector) {
+                env.objc.cache_method(lookup_class.unwrap(), selector, imp);
+                call_imp(env, imp, receiver, selector, message_type_info);
                 return;
             } else {
                 class = superclass;
@@ -157,10 +156,51 @@ Type mismatch when sending message {} to {:?}!
     }
 }
 
+/// Call the method implementation found by [objc_msgSend_inner].
+fn call_imp(
+    env: &mut Environment,
+    imp: IMP,
+    receiver: id,
+    selector: SEL,
+    message_type_info: Option<(TypeId, &'static str)>,
+) {
+    match imp {
+        IMP::Host(host_imp) => {
+            // TODO: do type checks when calling GuestIMPs too. That requires
+            // using Objective-C type strings, rather than Rust types, and
+            // should probably warn rather than panicking, because apps might
+            // rely on type punning.
+            if let Some((sent_type_id, sent_type_desc)) = message_type_info {
+                let (expected_type_id, expected_type_desc) = host_imp.type_info();
+                if sent_type_id != expected_type_id {
+                    panic!(
+                        "\
+Type mismatch when sending message {} to {:?}!
+- Message has type: {:?} / {}
+- Method expects type: {:?} / {}",
+                        selector.as_str(&env.mem),
+                        receiver,
+                        sent_type_id,
+                        sent_type_desc,
+                        expected_type_id,
+                        expected_type_desc
+                    );
+                }
+            }
+            host_imp.call_from_guest(env)
+        }
+        // We can't create a new stack frame, because that would interfere
+        // with pass-through of stack arguments.
+        IMP::Guest(guest_imp) => guest_imp.call_without_pushing_stack_frame(env),
+    }
+}
+
 /// Standard variant of `objc_msgSend`. See [objc_msgSend_inner].
 #[allow(non_snake_case)]
 pub(super) fn objc_msgSend(env: &mut Environment, receiver: id, selector: SEL) {
-    objc_msgSend_inner(env, receiver, selector, /* super2: */ None)
+    objc_msgSend_inner(
+        env, receiver, selector, /* super2: */ None, /* stret: */ false,
+    )
 }
 
 /// Variant of `objc_msgSend` for methods that return a struct via a pointer.
@@ -178,7 +218,51 @@ pub(super) fn objc_msgSend_stret(
     receiver: id,
     selector: SEL,
 ) {
-    objc_msgSend_inner(env, receiver, selector, /* super2: */ None)
+    objc_msgSend_inner(
+        env, receiver, selector, /* super2: */ None, /* stret: */ true,
+    )
+}
+
+/// Forward a message that the receiver has no method for. Like in Apple's
+/// implementation, most of the forwarding process is Foundation's
+/// responsibility, but if that picks a new receiver, the message is sent to it
+/// here.
+fn forward_message(env: &mut Environment, receiver: id, selector: SEL, stret: bool) {
+    let new_receiver = crate::frameworks::foundation::ns_invocation::forward_message(
+        env, receiver, selector, stret,
+    );
+    if let Some(new_receiver) = new_receiver {
+        let receiver_reg = if stret { 1 } else { 0 };
+        env.cpu.regs_mut()[receiver_reg] = new_receiver.to_bits();
+        objc_msgSend_inner(env, new_receiver, selector, /* super2: */ None, stret);
+    }
+}
+
+/// The implementation that [super::class_getMethodImplementation] returns for
+/// selectors that have no method: calling it forwards the message, like
+/// sending it would. Apps can also use this to make a method be forwarded.
+#[allow(non_snake_case)]
+pub(super) fn _objc_msgForward(env: &mut Environment, receiver: id, selector: SEL) {
+    if receiver == nil {
+        env.cpu.regs_mut()[0..2].fill(0);
+        return;
+    }
+    forward_message(env, receiver, selector, /* stret: */ false)
+}
+
+/// Variant of [_objc_msgForward] for methods that return a struct via a
+/// pointer. See [objc_msgSend_stret].
+#[allow(non_snake_case)]
+pub(super) fn _objc_msgForward_stret(
+    env: &mut Environment,
+    _stret: MutVoidPtr,
+    receiver: id,
+    selector: SEL,
+) {
+    if receiver == nil {
+        return;
+    }
+    forward_message(env, receiver, selector, /* stret: */ true)
 }
 
 #[repr(C, packed)]
@@ -212,7 +296,95 @@ pub(super) fn objc_msgSendSuper2(
     // Rewrite first argument to match the normal ABI.
     crate::abi::write_next_arg(&mut 0, env.cpu.regs_mut(), &mut env.mem, receiver);
 
-    objc_msgSend_inner(env, receiver, selector, /* super2: */ Some(class))
+    objc_msgSend_inner(
+        env,
+        receiver,
+        selector,
+        /* super2: */ Some(class),
+        /* stret: */ false,
+    )
+}
+
+/// Dynamic method resolution: give the receiver's class a chance to add a
+/// method for a selector it doesn't have one for, using
+/// `+resolveInstanceMethod:` or `+resolveClassMethod:`. Returns [true] if a
+/// method was added, in which case the message can be sent again.
+fn resolve_method(
+    env: &mut Environment,
+    receiver: id,
+    selector: SEL,
+    super2: Option<Class>,
+) -> bool {
+    let class = ObjC::read_isa(receiver, &env.mem);
+    let is_metaclass = env.objc.is_metaclass(class);
+    // The resolve methods are class methods, so for an instance they are sent
+    // to its class, and for a class they are sent to the class itself.
+    let (resolve_receiver, resolve_sel) = if is_metaclass {
+        (receiver, "resolveClassMethod:")
+    } else {
+        (class, "resolveInstanceMethod:")
+    };
+    let Some(resolve_sel) = env.objc.lookup_selector(resolve_sel) else {
+        return false;
+    };
+    let resolve_receiver_class = ObjC::read_isa(resolve_receiver, &env.mem);
+    if env
+        .objc
+        .lookup_method(resolve_receiver_class, resolve_sel)
+        .is_none()
+    {
+        return false;
+    }
+
+    // Sending a message will overwrite the arguments of this one.
+    let saved_regs: [u32; 4] = env.cpu.regs()[0..4].try_into().unwrap();
+    let resolved: bool = msg_send(env, (resolve_receiver, resolve_sel, selector));
+    env.cpu.regs_mut()[0..4].copy_from_slice(&saved_regs);
+
+    // The class might claim to have resolved the method without actually
+    // adding it, which must not cause an infinite loop.
+    let search_class = match super2 {
+        Some(super2) => env.objc.get_superclass(super2),
+        None => class,
+    };
+    resolved && env.objc.lookup_method(search_class, selector).is_some()
+}
+
+/// Send a message whose argument types are only known at runtime, e.g. for
+/// `NSInvocation`. `args` are the arguments as words laid out according to the
+/// calling convention, starting with the receiver and selector.
+///
+/// If `stret` is provided, the method is expected to return a struct via that
+/// pointer (see [objc_msgSend_stret]). Otherwise, the values of r0 and r1 after
+/// the call are returned.
+pub fn msg_send_with_arg_words(
+    env: &mut Environment,
+    args: &[u32],
+    stret: Option<MutVoidPtr>,
+) -> [u32; 2] {
+    let receiver: id = Ptr::from_bits(args[0]);
+    let selector = <SEL as GuestArg>::from_regs(&args[1..2]);
+
+    let words: Vec<u32> = stret
+        .map(|stret| stret.to_bits())
+        .into_iter()
+        .chain(args.iter().copied())
+        .collect();
+    let regs = env.cpu.regs_mut();
+    let old_sp = extend_stack_for_args(words.len(), regs);
+    write_arg_words(regs, &mut env.mem, &words);
+
+    objc_msgSend_inner(
+        env,
+        receiver,
+        selector,
+        /* super2: */ None,
+        /* stret: */ stret.is_some(),
+    );
+
+    let regs = env.cpu.regs_mut();
+    regs[Cpu::SP] = old_sp;
+    [regs[0], regs[1]]
 }
 
 /// Trait that assists with type-checking of [msg_send]'s arguments.
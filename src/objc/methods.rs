@@ -7,12 +7,16 @@
 //!
 //! Resources:
 //! - [Apple's documentation of `class_addMethod`](https://developer.apple.com/documentation/objectivec/1418901-class_addmethod?language=objc)
+//! - Apple's [Objective-C Runtime reference](https://developer.apple.com/documentation/objectivec/objective-c_runtime?language=objc)
+//!   documents the C functions for working with methods (`class_getInstanceMethod`,
+//!   `method_exchangeImplementations` etc).
 
 use super::{
     id, nil, objc_super, Class, ClassHostObject, MsgSendSignature, MsgSendSuperSignature, ObjC, SEL,
 };
 use crate::abi::{CallFromGuest, DotDotDot, GuestArg, GuestFunction, GuestRet};
-use crate::mem::{guest_size_of, ConstPtr, GuestUSize, Mem, Ptr, SafeRead};
+use crate::dyld::HostFunction;
+use crate::mem::{guest_size_of, ConstPtr, GuestUSize, Mem, MutPtr, Ptr, SafeRead};
 use crate::Environment;
 use std::any::TypeId;
 
@@ -24,6 +28,7 @@ use std::any::TypeId;
 /// "guest methods" (functions in the guest app). Either way, the function needs
 /// to conform to the same ABI: [id] and [SEL] must be its first two parameters.
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone)]
 pub enum IMP {
     Host(&'static dyn HostIMP),
     Guest(GuestIMP),
@@ -33,6 +38,61 @@ pub enum IMP {
 pub trait HostIMP: CallFromGuest {
     /// See [MsgSendSignature::type_info].
     fn type_info(&self) -> (TypeId, &'static str);
+
+    /// Get this as a [HostFunction], so that a guest function pointer can be
+    /// created for it (see [crate::dyld::Dyld::create_guest_function]).
+    fn to_host_function(&'static self) -> HostFunction;
+
+    /// Get a best-effort Objective-C type encoding for this method, e.g.
+    /// `v@:i`. See [host_type_encoding].
+    fn type_encoding(&self) -> String;
+}
+
+/// Make a best-effort Objective-C type encoding for a type used by a host
+/// method, given its size in words. Host methods are written with Rust types,
+/// so the encoding can't always be exact, but it always has the right size,
+/// which is what matters for message forwarding.
+fn host_type_encoding<T: 'static>(words: usize) -> String {
+    let type_id = TypeId::of::<T>();
+    let known_types: [(TypeId, &str); 17] = [
+        (TypeId::of::<()>(), "v"),
+        (TypeId::of::<bool>(), "c"), // BOOL is a signed char
+        (TypeId::of::<i8>(), "c"),
+        (TypeId::of::<u8>(), "C"),
+        (TypeId::of::<i16>(), "s"),
+        (TypeId::of::<u16>(), "S"),
+        (TypeId::of::<i32>(), "i"),
+        (TypeId::of::<u32>(), "I"),
+        (TypeId::of::<i64>(), "q"),
+        (TypeId::of::<u64>(), "Q"),
+        (TypeId::of::<f32>(), "f"),
+        (TypeId::of::<f64>(), "d"),
+        (TypeId::of::<id>(), "@"),
+        (TypeId::of::<SEL>(), ":"),
+        (TypeId::of::<ConstPtr<u8>>(), "r*"),
+        (TypeId::of::<MutPtr<u8>>(), "*"),
+        (TypeId::of::<GuestFunction>(), "^?"),
+    ];
+    if let Some(&(_, encoding)) = known_types.iter().find(|&&(other, _)| other == type_id) {
+        return encoding.to_string();
+    }
+    match words {
+        0 => "v".to_string(),
+        // Other one-word types are most likely pointers.
+        1 => "^v".to_string(),
+        // Larger types are structs, e.g. CGRect.
+        _ => format!("{{?={}}}", "I".repeat(words)),
+    }
+}
+fn host_arg_type_encoding<P: GuestArg + 'static>() -> String {
+    host_type_encoding::<P>(P::REG_COUNT)
+}
+fn host_ret_type_encoding<R: GuestRet + 'static>() -> String {
+    let words = match R::SIZE_IN_MEM {
+        Some(size) => ((size + 3) / 4).try_into().unwrap(),
+        None => 1,
+    };
+    host_type_encoding::<R>(words)
 }
 
 macro_rules! impl_HostIMP {
@@ -45,6 +105,15 @@ macro_rules! impl_HostIMP {
             fn type_info(&self) -> (TypeId, &'static str) {
                 <(R, (id, SEL, $($P,)*)) as MsgSendSignature>::type_info()
             }
+            fn to_host_function(&'static self) -> HostFunction {
+                self
+            }
+            fn type_encoding(&self) -> String {
+                let mut encoding = host_ret_type_encoding::<R>();
+                encoding.push_str("@:");
+                $(encoding.push_str(&host_arg_type_encoding::<$P>());)*
+                encoding
+            }
         }
         impl<R, $($P,)*> HostIMP for fn(&mut Environment, id, SEL, $($P,)* DotDotDot) -> R
         where
@@ -54,6 +123,16 @@ macro_rules! impl_HostIMP {
             fn type_info(&self) -> (TypeId, &'static str) {
                 todo!("host-to-host message calls with var-args"); // TODO
             }
+            fn to_host_function(&'static self) -> HostFunction {
+                self
+            }
+            fn type_encoding(&self) -> String {
+                // Variadic arguments aren't part of the type encoding.
+                let mut encoding = host_ret_type_encoding::<R>();
+                encoding.push_str("@:");
+                $(encoding.push_str(&host_arg_type_encoding::<$P>());)*
+                encoding
+            }
         }
 
         // Currently there is a one-to-one mapping between valid host IMP
@@ -100,44 +179,56 @@ unsafe impl SafeRead for method_list_t {}
 /// The layout of a method in an app binary.
 ///
 /// The name, field names and field layout are based on what Ghidra outputs.
+///
+/// This is also the layout of the `Method` handles given out by functions like
+/// `class_getInstanceMethod` (see [Method]).
 #[repr(C, packed)]
-struct method_t {
+pub(super) struct method_t {
     name: ConstPtr<u8>,
     types: ConstPtr<u8>,
     imp: GuestIMP,
 }
 unsafe impl SafeRead for method_t {}
 
+/// Opaque handle for a method, used by the runtime's C API.
+///
+/// The name is standard Objective-C. Each one refers to the method for a
+/// particular selector in a particular class (not its superclasses). The
+/// runtime's own method tables are the source of truth, but the `method_t` in
+/// guest memory is kept up-to-date in case the app reads it directly.
+pub(super) type Method = MutPtr<method_t>;
+
 impl ClassHostObject {
     // See classes.rs for host method parsing
 
+    /// Returns the selectors of the methods, so the caller can flush the
+    /// method cache if the class is already in use.
     pub(super) fn add_methods_from_bin(
         &mut self,
         method_list_ptr: ConstPtr<method_list_t>,
         mem: &Mem,
         objc: &mut ObjC,
-    ) {
+    ) -> Vec<SEL> {
         let method_list_t { entsize, count } = mem.read(method_list_ptr);
         assert!(entsize >= guest_size_of::<method_t>());
 
         let methods_base_ptr: ConstPtr<method_t> = (method_list_ptr + 1).cast();
 
+        let mut sels = Vec::with_capacity(count as usize);
         for i in 0..count {
             let method_ptr: ConstPtr<method_t> =
                 Ptr::from_bits(methods_base_ptr.to_bits() + i * entsize);
 
-            // TODO: support type strings
-            let method_t {
-                name,
-                types: _,
-                imp,
-            } = mem.read(method_ptr);
+            let method_t { name, types, imp } = mem.read(method_ptr);
 
             // There is no guarantee this string is unique or known.
             // We must deduplicate it like any other.
             let sel = objc.register_bin_selector(name, mem);
             self.methods.insert(sel, IMP::Guest(imp));
+            self.method_types.insert(sel, types);
+            sels.push(sel);
         }
+        sels
     }
 }
 
@@ -220,3 +311,346 @@ impl ObjC {
         }
     }
 }
+
+impl ObjC {
+    /// Get a class's host object, if it is a real class (not a placeholder).
+    fn get_class_host_object(&self, class: Class) -> Option<&ClassHostObject> {
+        self.get_host_object(class)?.as_any().downcast_ref()
+    }
+
+    /// Mutable version of [Self::get_class_host_object].
+    fn get_class_host_object_mut(&mut self, class: Class) -> Option<&mut ClassHostObject> {
+        self.get_host_object_mut(class)?.as_any_mut().downcast_mut()
+    }
+
+    /// Add the result of a method lookup by `objc_msgSend` to the method cache.
+    pub(super) fn cache_method(&mut self, class: Class, sel: SEL, imp: IMP) {
+        if self.method_cache.insert((class, sel), imp).is_none() {
+            self.method_cache_classes
+                .entry(sel)
+                .or_default()
+                .push(class);
+        }
+    }
+
+    /// Discard the `objc_msgSend` method cache entries for a selector on a
+    /// class and its subclasses. This must be done whenever a method is added
+    /// to a class or has its implementation replaced, since that can change the
+    /// result of looking up the method on that class or any of its subclasses.
+    pub(super) fn flush_method_cache(&mut self, class: Class, sel: SEL) {
+        let Some(classes) = self.method_cache_classes.remove(&sel) else {
+            return;
+        };
+        let (affected, unaffected): (Vec<Class>, Vec<Class>) = classes
+            .into_iter()
+            .partition(|&cached_class| self.class_is_subclass_of(cached_class, class));
+        for cached_class in affected {
+            self.method_cache.remove(&(cached_class, sel));
+        }
+        if !unaffected.is_empty() {
+            self.method_cache_classes.insert(sel, unaffected);
+        }
+    }
+
+    /// Look up the implementation of a method in a class's superclass chain.
+    /// Returns the class which has the method, and the method. Unlike
+    /// [Self::class_has_method], this doesn't panic for placeholder classes.
+    pub(super) fn lookup_method(&self, class: Class, sel: SEL) -> Option<(Class, IMP)> {
+        let mut class = class;
+        while class != nil {
+            let &ClassHostObject {
+                superclass,
+                ref methods,
+                ..
+            } = self.get_class_host_object(class)?;
+            if let Some(&imp) = methods.get(&sel) {
+                return Some((class, imp));
+            }
+            class = superclass;
+        }
+        None
+    }
+
+    /// Get the Objective-C type encoding for the method a class (or one of its
+    /// superclasses) has for a selector, if it has one and it is known.
+    pub fn method_type_encoding(&self, class: Class, sel: SEL, mem: &Mem) -> Option<String> {
+        let (class, imp) = self.lookup_method(class, sel)?;
+        match imp {
+            IMP::Host(host_imp) => Some(host_imp.type_encoding()),
+            IMP::Guest(_) => {
+                let &types = self.get_class_host_object(class)?.method_types.get(&sel)?;
+                if types.is_null() {
+                    return None;
+                }
+                Some(mem.cstr_at_utf8(types).ok()?.to_string())
+            }
+        }
+    }
+
+    /// Convert a guest function pointer to an [IMP], undoing
+    /// [imp_to_guest_function] for host methods.
+    fn imp_from_guest_function(&self, function: GuestIMP) -> IMP {
+        match self
+            .guest_function_host_imps
+            .get(&function.addr_with_thumb_bit())
+        {
+            Some(&host_imp) => IMP::Host(host_imp),
+            None => IMP::Guest(function),
+        }
+    }
+}
+
+/// Get a guest function pointer for an [IMP] of a method of `class`. For host
+/// methods, a function is created the first time this is needed.
+fn imp_to_guest_function(env: &mut Environment, imp: IMP, class: Class, sel: SEL) -> GuestIMP {
+    let host_imp = match imp {
+        IMP::Guest(guest_imp) => return guest_imp,
+        IMP::Host(host_imp) => host_imp,
+    };
+
+    let key = host_imp as *const dyn HostIMP as *const () as usize;
+    if let Some(&function) = env.objc.host_imp_guest_functions.get(&key) {
+        return function;
+    }
+
+    // The name is only used for debugging. There is at most one of these per
+    // host method, so leaking it is fine.
+    let &ClassHostObject {
+        ref name,
+        is_metaclass,
+        ..
+    } = env.objc.borrow(class);
+    let name = format!(
+        "{}[{} {}]",
+        if is_metaclass { '+' } else { '-' },
+        name,
+        sel.as_str(&env.mem)
+    );
+    let name: &'static str = Box::leak(name.into_boxed_str());
+
+    let function = env
+        .dyld
+        .create_guest_function(&mut env.mem, name, host_imp.to_host_function());
+    env.cpu
+        .invalidate_cache_range(function.addr_without_thumb_bit(), 8);
+    env.objc.host_imp_guest_functions.insert(key, function);
+    env.objc
+        .guest_function_host_imps
+        .insert(function.addr_with_thumb_bit(), host_imp);
+    function
+}
+
+/// Get the [Method] handle for a method of a class (not its superclasses),
+/// creating it if necessary.
+fn get_method_handle(env: &mut Environment, class: Class, sel: SEL) -> Method {
+    if let Some(&method) = env.objc.method_handles.get(&(class, sel)) {
+        return method;
+    }
+
+    let host_object = env.objc.borrow::<ClassHostObject>(class);
+    let imp = host_object.methods[&sel];
+    let types = match imp {
+        IMP::Guest(_) => host_object
+            .method_types
+            .get(&sel)
+            .copied()
+            .unwrap_or(Ptr::null()),
+        // The method handle is only created once, so this string is too.
+        IMP::Host(host_imp) => {
+            let encoding = host_imp.type_encoding();
+            env.mem
+                .alloc_and_write_cstr(encoding.as_bytes())
+                .cast_const()
+        }
+    };
+    let imp = imp_to_guest_function(env, imp, class, sel);
+    let method = env.mem.alloc_and_write(method_t {
+        name: sel.to_ptr(),
+        types,
+        imp,
+    });
+    env.objc.method_handles.insert((class, sel), method);
+    env.objc.method_handle_targets.insert(method, (class, sel));
+    method
+}
+
+/// Get the class and selector a [Method] handle refers to.
+fn method_target(env: &Environment, method: Method) -> Option<(Class, SEL)> {
+    let target = env.objc.method_handle_targets.get(&method).copied();
+    if target.is_none() && !method.is_null() {
+        log!("Warning: unknown Method {:?}", method);
+    }
+    target
+}
+
+pub(super) fn class_respondsToSelector(env: &mut Environment, class: Class, sel: SEL) -> bool {
+    class != nil && !sel.is_null() && env.objc.lookup_method(class, sel).is_some()
+}
+
+pub(super) fn class_getInstanceMethod(env: &mut Environment, class: Class, sel: SEL) -> Method {
+    if class == nil || sel.is_null() {
+        return Ptr::null();
+    }
+    match env.objc.lookup_method(class, sel) {
+        Some((class, _)) => get_method_handle(env, class, sel),
+        None => Ptr::null(),
+    }
+}
+
+pub(super) fn class_getClassMethod(env: &mut Environment, class: Class, sel: SEL) -> Method {
+    if class == nil {
+        return Ptr::null();
+    }
+    let metaclass = ObjC::read_isa(class, &env.mem);
+    class_getInstanceMethod(env, metaclass, sel)
+}
+
+pub(super) fn class_getMethodImplementation(
+    env: &mut Environment,
+    class: Class,
+    sel: SEL,
+) -> GuestIMP {
+    if class == nil || sel.is_null() {
+        return GuestFunction::from_addr_with_thumb_bit(0);
+    }
+    match env.objc.lookup_method(class, sel) {
+        Some((class, imp)) => imp_to_guest_function(env, imp, class, sel),
+        None => env
+            .dyld
+            .create_proc_address(&mut env.mem, &mut env.cpu, "__objc_msgForward")
+            .unwrap(),
+    }
+}
+
+pub(super) fn method_getName(env: &mut Environment, method: Method) -> SEL {
+    match method_target(env, method) {
+        Some((_, sel)) => sel,
+        None => SEL::null(),
+    }
+}
+
+pub(super) fn method_getImplementation(env: &mut Environment, method: Method) -> GuestIMP {
+    let Some((class, sel)) = method_target(env, method) else {
+        return GuestFunction::from_addr_with_thumb_bit(0);
+    };
+    let imp = env.objc.borrow::<ClassHostObject>(class).methods[&sel];
+    imp_to_guest_function(env, imp, class, sel)
+}
+
+pub(super) fn method_setImplementation(
+    env: &mut Environment,
+    method: Method,
+    imp: GuestIMP,
+) -> GuestIMP {
+    let Some((class, sel)) = method_target(env, method) else {
+        return GuestFunction::from_addr_with_thumb_bit(0);
+    };
+    let new_imp = env.objc.imp_from_guest_function(imp);
+    let old_imp = env
+        .objc
+        .borrow_mut::<ClassHostObject>(class)
+        .methods
+        .insert(sel, new_imp)
+        .unwrap();
+    env.objc.flush_method_cache(class, sel);
+
+    let mut method_data = env.mem.read(method);
+    method_data.imp = imp;
+    env.mem.write(method, method_data);
+
+    imp_to_guest_function(env, old_imp, class, sel)
+}
+
+pub(super) fn method_exchangeImplementations(
+    env: &mut Environment,
+    method1: Method,
+    method2: Method,
+) {
+    if method_target(env, method1).is_none() || method_target(env, method2).is_none() {
+        return;
+    }
+    let imp1 = method_getImplementation(env, method1);
+    let imp2 = method_getImplementation(env, method2);
+    method_setImplementation(env, method1, imp2);
+    method_setImplementation(env, method2, imp1);
+}
+
+pub(super) fn class_addMethod(
+    env: &mut Environment,
+    class: Class,
+    sel: SEL,
+    imp: GuestIMP,
+    types: ConstPtr<u8>,
+) -> bool {
+    let sel = env.objc.canonicalize_selector(sel, &mut env.mem);
+    let imp = env.objc.imp_from_guest_function(imp);
+    let Some(host_object) = env.objc.get_class_host_object_mut(class) else {
+        log!(
+            "Warning: class_addMethod() on unsupported class {:?}",
+            class
+        );
+        return false;
+    };
+    // This only fails if the class itself has the method, overriding a
+    // superclass's method is allowed.
+    if host_object.methods.contains_key(&sel) {
+        return false;
+    }
+    host_object.methods.insert(sel, imp);
+    host_object.method_types.insert(sel, types);
+    env.objc.flush_method_cache(class, sel);
+    true
+}
+
+pub(super) fn class_replaceMethod(
+    env: &mut Environment,
+    class: Class,
+    sel: SEL,
+    imp: GuestIMP,
+    types: ConstPtr<u8>,
+) -> GuestIMP {
+    let has_method = env
+        .objc
+        .get_class_host_object(class)
+        .is_some_and(|host_object| host_object.methods.contains_key(&sel));
+    if has_method {
+        let method = get_method_handle(env, class, sel);
+        method_setImplementation(env, method, imp)
+    } else {
+        class_addMethod(env, class, sel, imp, types);
+        GuestFunction::from_addr_with_thumb_bit(0)
+    }
+}
+
+pub(super) fn class_copyMethodList(
+    env: &mut Environment,
+    class: Class,
+    out_count: MutPtr<GuestUSize>,
+) -> MutPtr<Method> {
+    let mut sels: Vec<SEL> = match env.objc.get_class_host_object(class) {
+        Some(host_object) => host_object.methods.keys().copied().collect(),
+        None => Vec::new(),
+    };
+    // The order isn't meaningful, but it should at least be consistent.
+    sels.sort_by(|a, b| a.as_str(&env.mem).cmp(b.as_str(&env.mem)));
+
+    let count: GuestUSize = sels.len().try_into().unwrap();
+    if !out_count.is_null() {
+        env.mem.write(out_count, count);
+    }
+    if count == 0 {
+        return Ptr::null();
+    }
+
+    // The list is NULL-terminated and must be freed by the caller with free().
+    let list: MutPtr<Method> = env
+        .mem
+        .alloc((count + 1) * guest_size_of::<Method>())
+        .cast();
+    for (i, sel) in (0..count).zip(sels) {
+        let method = get_method_handle(env, class, sel);
+        env.mem.write(list + i, method);
+    }
+    env.mem.write(list + count, Ptr::null());
+    list
+}
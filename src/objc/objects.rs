@@ -237,6 +237,13 @@ impl super::ObjC {
         self.objects.get(&object).map(|entry| &*entry.host_object)
     }
 
+    /// Get a mutable reference to a host object, if the object exists.
+    pub(super) fn get_host_object_mut(&mut self, object: id) -> Option<&mut dyn AnyHostObject> {
+        self.objects
+            .get_mut(&object)
+            .map(|entry| &mut *entry.host_object)
+    }
+
     /// Get a reference to a host object and downcast it. Panics if there is
     /// no such object, or if downcasting fails.
     pub fn borrow<T: AnyHostObject + 'static>(&self, object: id) -> &T {
@@ -253,6 +260,19 @@ impl super::ObjC {
         }
     }
 
+    /// Like [Self::borrow], but returns [None] if there is no such object or
+    /// if downcasting fails.
+    pub fn try_borrow<T: AnyHostObject + 'static>(&self, object: id) -> Option<&T> {
+        let mut host_object: &(dyn AnyHostObject + 'static) =
+            &*self.objects.get(&object)?.host_object;
+        loop {
+            if let Some(res) = host_object.as_any().downcast_ref() {
+                return Some(res);
+            }
+            host_object = host_object.as_superclass()?;
+        }
+    }
+
     /// Get a reference to a host object and downcast it. Panics if there is
     /// no such object, or if downcasting fails.
     pub fn borrow_mut<T: AnyHostObject + 'static>(&mut self, object: id) -> &mut T {
@@ -346,6 +366,17 @@ impl super::ObjC {
 
         std::mem::drop(host_object);
 
+        // NSObject's release releases associated objects before deallocating,
+        // so this only happens if dealloc is called some other way. The values
+        // can't be released without an environment and are leaked, but they
+        // mustn't end up attached to a new object at the same address.
+        if self.associated_objects.remove(&object).is_some() {
+            log!(
+                "Warning: {:?} was deallocated with associated objects, leaking them",
+                object
+            );
+        }
+
         mem.free(object.cast());
     }
 }
@@ -7,9 +7,11 @@
 //!
 //! These are the names used to look up method implementations in Objective-C.
 //! In Apple's implementation, they are always null-terminated C strings, but
-//! they are meant to be treated as opaque values. Selector strings should be
-//! (TODO) interned so pointer comparison can be used instead of string
-//! comparison.
+//! they are meant to be treated as opaque values. Selector strings are interned
+//! so that pointer comparison can be used instead of string comparison: there
+//! is exactly one registered [SEL] for each name. Selectors the app didn't
+//! register (e.g. a C string passed straight to `objc_msgSend`) are mapped to
+//! the registered one by [ObjC::canonicalize_selector].
 //!
 //! Resources:
 //! - Apple's [The Objective-C Programming Language](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjectiveC/Chapters/ocSelectors.html)
@@ -58,6 +60,9 @@ impl GuestRet for SEL {
 }
 
 impl SEL {
+    pub fn null() -> Self {
+        SEL(Ptr::null())
+    }
     pub fn as_str(self, mem: &Mem) -> &str {
         // selectors are probably always UTF-8 but this hasn't been verified
         mem.cstr_at_utf8(self.0).unwrap()
@@ -65,6 +70,10 @@ impl SEL {
     pub fn is_null(self) -> bool {
         self.0.is_null()
     }
+    /// Get the pointer to the selector's C string.
+    pub fn to_ptr(self) -> ConstPtr<u8> {
+        self.0
+    }
 }
 
 impl ObjC {
@@ -72,6 +81,27 @@ impl ObjC {
         self.selectors.get(name).copied()
     }
 
+    fn insert_selector(&mut self, name: String, sel: SEL) {
+        self.selectors.insert(name, sel);
+        self.known_selectors.insert(sel);
+    }
+
+    /// Get the registered selector with the same name as `sel`, registering it
+    /// if there isn't one. This is cheap if `sel` is already the registered
+    /// one, which is almost always the case.
+    pub(super) fn canonicalize_selector(&mut self, sel: SEL, mem: &mut Mem) -> SEL {
+        if sel.is_null() || self.known_selectors.contains(&sel) {
+            return sel;
+        }
+        let name = sel.as_str(mem).to_string();
+        log_dbg!(
+            "Canonicalizing unregistered selector {:?} \"{}\"",
+            sel,
+            name
+        );
+        self.register_host_selector(name, mem)
+    }
+
     /// Register a selector using a Rust [String]. Despite the name there is no
     /// inherent "host" quality of the resulting selector, but because this
     /// function will allocate a new C string, this function is not the most
@@ -82,7 +112,7 @@ impl ObjC {
         }
 
         let sel = SEL(mem.alloc_and_write_cstr(name.as_bytes()).cast_const());
-        self.selectors.insert(name, sel);
+        self.insert_selector(name, sel);
         sel
     }
 
@@ -100,7 +130,7 @@ impl ObjC {
                             continue;
                         }
                         let sel = SEL(mem.alloc_and_write_cstr(name.as_bytes()).cast_const());
-                        self.selectors.insert(name.to_string(), sel);
+                        self.insert_selector(name.to_string(), sel);
                     }
                 }
             }
@@ -116,7 +146,7 @@ impl ObjC {
             existing_sel
         } else {
             let sel = SEL(sel_cstr);
-            self.selectors.insert(sel_str.to_string(), sel);
+            self.insert_selector(sel_str.to_string(), sel);
             sel
         }
     }
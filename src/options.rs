@@ -35,6 +35,7 @@ pub struct Options {
     pub fullscreen: bool,
     pub initial_orientation: DeviceOrientation,
     pub scale_hack: NonZeroU32,
+    pub hide_status_bar: bool,
     pub deadzone: f32,
     pub x_tilt_range: f32,
     pub y_tilt_range: f32,
@@ -44,11 +45,14 @@ pub struct Options {
     pub stabilize_virtual_cursor: Option<(f32, f32)>,
     pub gles1_implementation: Option<GLESImplementation>,
     pub direct_memory_access: bool,
+    pub direct_read_only_memory_access: bool,
+    pub debug_allocator: bool,
     pub gdb_listen_addrs: Option<Vec<SocketAddr>>,
     pub preferred_languages: Option<Vec<String>>,
     pub headless: bool,
     pub print_fps: bool,
     pub fps_limit: Option<f64>,
+    pub device_ram_mib: NonZeroU32,
 }
 
 impl Default for Options {
@@ -57,6 +61,7 @@ impl Default for Options {
             fullscreen: false,
             initial_orientation: DeviceOrientation::Portrait,
             scale_hack: NonZeroU32::new(1).unwrap(),
+            hide_status_bar: false,
             deadzone: 0.1,
             x_tilt_range: 60.0,
             y_tilt_range: 60.0,
@@ -66,11 +71,15 @@ impl Default for Options {
             stabilize_virtual_cursor: None,
             gles1_implementation: None,
             direct_memory_access: true,
+            direct_read_only_memory_access: false,
+            debug_allocator: false,
             gdb_listen_addrs: None,
             preferred_languages: None,
             headless: false,
             print_fps: false,
             fps_limit: Some(60.0), // Original iPhone is 60Hz and uses v-sync
+            // Original iPhone, iPhone 3G and first two iPod touch models
+            device_ram_mib: NonZeroU32::new(128).unwrap(),
         }
     }
 }
@@ -100,6 +109,8 @@ impl Options {
             self.scale_hack = value
                 .parse()
                 .map_err(|_| "Invalid scale hack factor".to_string())?;
+        } else if arg == "--hide-status-bar" {
+            self.hide_status_bar = true;
         } else if let Some(value) = arg.strip_prefix("--deadzone=") {
             self.deadzone = parse_degrees(value, "deadzone")?;
         } else if let Some(value) = arg.strip_prefix("--x-tilt-range=") {
@@ -163,6 +174,10 @@ impl Options {
             );
         } else if arg == "--disable-direct-memory-access" {
             self.direct_memory_access = false;
+        } else if arg == "--direct-read-only-memory-access" {
+            self.direct_read_only_memory_access = true;
+        } else if arg == "--debug-allocator" {
+            self.debug_allocator = true;
         } else if let Some(address) = arg.strip_prefix("--gdb=") {
             let addrs = address
                 .to_socket_addrs()
@@ -186,6 +201,10 @@ impl Options {
                     .ok_or_else(|| "Invalid value for --fps-limit=".to_string())?;
                 self.fps_limit = Some(limit);
             }
+        } else if let Some(value) = arg.strip_prefix("--device-ram=") {
+            self.device_ram_mib = value
+                .parse()
+                .map_err(|_| "Invalid value for --device-ram=".to_string())?;
         } else {
             return Ok(false);
         };
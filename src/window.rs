@@ -90,9 +90,23 @@ pub enum Event {
     TouchesDown(HashMap<FingerId, Coords>),
     TouchesMove(HashMap<FingerId, Coords>),
     TouchesUp(HashMap<FingerId, Coords>),
+    /// Keyboard input while text input is active (see
+    /// [Window::start_text_input]).
+    TextInput(TextInputEvent),
     /// User pressed F12, requesting that execution be paused and the debugger
     /// take over.
     EnterDebugger,
+    /// User pressed F10, requesting that the app be sent a low-memory warning.
+    MemoryWarning,
+}
+
+/// Text input from the host keyboard. Composition with an input method (IME)
+/// is handled by the host OS, so only committed text is received.
+#[derive(Debug)]
+pub enum TextInputEvent {
+    Text(String),
+    Backspace,
+    Return,
 }
 
 pub enum GLVersion {
@@ -171,6 +185,10 @@ impl Window {
     ) -> Window {
         let sdl_ctx = sdl2::init().unwrap();
         let video_ctx = sdl_ctx.video().unwrap();
+        // SDL2 enables text input by default on desktop platforms, which can
+        // make IME windows pop up. It's only wanted while a text field is
+        // being edited.
+        video_ctx.text_input().stop();
 
         // The "hidapi" feature of rust-sdl2 is enabled so that sdl2::sensor
         // is available, but we don't want to enable SDL's HIDAPI controller
@@ -566,6 +584,19 @@ impl Window {
                         _ => unreachable!(),
                     }
                 }
+                E::TextInput { text, .. } => Event::TextInput(TextInputEvent::Text(text)),
+                E::KeyDown {
+                    keycode:
+                        Some(
+                            keycode @ (sdl2::keyboard::Keycode::Backspace
+                            | sdl2::keyboard::Keycode::Return
+                            | sdl2::keyboard::Keycode::KpEnter),
+                        ),
+                    ..
+                } if self.video_ctx.text_input().is_active() => Event::TextInput(match keycode {
+                    sdl2::keyboard::Keycode::Backspace => TextInputEvent::Backspace,
+                    _ => TextInputEvent::Return,
+                }),
                 E::KeyDown {
                     keycode: Some(sdl2::keyboard::Keycode::F12),
                     ..
@@ -575,6 +606,10 @@ impl Window {
                     echo!("F12 pressed, EnterDebugger event queued.");
                     Event::EnterDebugger
                 }
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F10),
+                    ..
+                } if !self.video_ctx.text_input().is_active() => Event::MemoryWarning,
                 _ => continue,
             })
         }
@@ -1104,6 +1139,17 @@ impl Window {
         }
     }
 
+    /// Start receiving [Event::TextInput] events. On Android this shows the
+    /// on-screen keyboard.
+    pub fn start_text_input(&mut self) {
+        log_dbg!("Starting text input");
+        self.video_ctx.text_input().start();
+    }
+    pub fn stop_text_input(&mut self) {
+        log_dbg!("Stopping text input");
+        self.video_ctx.text_input().stop();
+    }
+
     pub fn is_screen_saver_enabled(&self) -> bool {
         self.video_ctx.is_screen_saver_enabled()
     }
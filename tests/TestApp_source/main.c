@@ -72,12 +72,17 @@ int strncmp(const char *, const char *, size_t);
 // <unistd.h>
 typedef unsigned int __uint32_t;
 typedef __uint32_t useconds_t;
+typedef long ssize_t;
+int close(int);
+ssize_t read(int, void *, size_t);
 int chdir(const char *);
 char *getcwd(char *, size_t);
 int usleep(useconds_t);
 
 // <fcntl.h>
+#define O_RDONLY 0x00000000
 #define O_CREAT 0x00000200
+int open(const char *, int, ...);
 
 // <pthread.h>
 typedef struct opaque_pthread_t opaque_pthread_t;
@@ -121,6 +126,126 @@ DIR *opendir(const char *);
 struct dirent *readdir(DIR *);
 int closedir(DIR *);
 
+// <dlfcn.h>
+#define RTLD_LAZY 0x1
+#define RTLD_DEFAULT ((void *)-2)
+void *dlopen(const char *, int);
+void *dlsym(void *, const char *);
+int dlclose(void *);
+char *dlerror(void);
+
+// <sys/mman.h>
+#define PROT_READ 0x01
+#define PROT_WRITE 0x02
+#define MAP_SHARED 0x0001
+#define MAP_PRIVATE 0x0002
+#define MAP_ANON 0x1000
+#define MAP_FAILED ((void *)-1)
+void *mmap(void *, size_t, int, int, int, long long);
+int munmap(void *, size_t);
+int mprotect(void *, size_t, int);
+
+// <sqlite3.h>
+typedef struct sqlite3 sqlite3;
+typedef struct sqlite3_stmt sqlite3_stmt;
+#define SQLITE_OK 0
+#define SQLITE_MISUSE 21
+#define SQLITE_ROW 100
+#define SQLITE_DONE 101
+#define SQLITE_TRANSIENT ((void (*)(void *))-1)
+int sqlite3_open(const char *, sqlite3 **);
+int sqlite3_close(sqlite3 *);
+int sqlite3_exec(sqlite3 *, const char *,
+                 int (*)(void *, int, char **, char **), void *, char **);
+int sqlite3_prepare_v2(sqlite3 *, const char *, int, sqlite3_stmt **,
+                       const char **);
+int sqlite3_bind_int(sqlite3_stmt *, int, int);
+int sqlite3_bind_text(sqlite3_stmt *, int, const char *, int,
+                      void (*)(void *));
+int sqlite3_bind_blob(sqlite3_stmt *, int, const void *, int,
+                      void (*)(void *));
+int sqlite3_step(sqlite3_stmt *);
+int sqlite3_reset(sqlite3_stmt *);
+int sqlite3_finalize(sqlite3_stmt *);
+int sqlite3_column_int(sqlite3_stmt *, int);
+const unsigned char *sqlite3_column_text(sqlite3_stmt *, int);
+const void *sqlite3_column_blob(sqlite3_stmt *, int);
+int sqlite3_column_bytes(sqlite3_stmt *, int);
+const char *sqlite3_errmsg(sqlite3 *);
+
+// <stdio.h> (continued)
+int remove(const char *);
+
+// <setjmp.h>
+typedef int jmp_buf[28];
+int setjmp(jmp_buf) __attribute__((returns_twice));
+int _setjmp(jmp_buf) __attribute__((returns_twice));
+void longjmp(jmp_buf, int) __attribute__((noreturn));
+typedef int sigjmp_buf[29];
+int sigsetjmp(sigjmp_buf, int) __attribute__((returns_twice));
+void siglongjmp(sigjmp_buf, int) __attribute__((noreturn));
+
+// <sys/time.h>
+struct timeval {
+  long tv_sec;
+  int tv_usec;
+};
+int gettimeofday(struct timeval *, void *);
+
+// <signal.h>
+#define SIGBUS 10
+struct sigaction {
+  void (*sa_handler)(int);
+  unsigned int sa_mask;
+  int sa_flags;
+};
+int sigaction(int, const struct sigaction *, struct sigaction *);
+
+// <objc/objc.h> and <objc/message.h>
+typedef struct objc_object *id;
+typedef struct objc_selector *SEL;
+id objc_msgSend(id, SEL, ...);
+SEL sel_registerName(const char *);
+
+// <objc/runtime.h>
+typedef struct objc_object *Class;
+typedef struct objc_method *Method;
+typedef id (*IMP)(id, SEL, ...);
+#define OBJC_ASSOCIATION_RETAIN_NONATOMIC 1
+Class objc_getClass(const char *);
+Class object_getClass(id);
+const char *class_getName(Class);
+Class class_getSuperclass(Class);
+int class_respondsToSelector(Class, SEL);
+Method class_getInstanceMethod(Class, SEL);
+IMP method_getImplementation(Method);
+void method_exchangeImplementations(Method, Method);
+int class_addMethod(Class, SEL, IMP, const char *);
+Method *class_copyMethodList(Class, unsigned int *);
+void objc_setAssociatedObject(id, const void *, id, unsigned int);
+id objc_getAssociatedObject(id, const void *);
+
+// <objc/objc-exception.h>
+struct objc_exception_data {
+  int buf[28];
+  void *pointers[4];
+};
+void objc_exception_try_enter(void *);
+void objc_exception_try_exit(void *);
+id objc_exception_extract(void *);
+int objc_exception_match(Class, id);
+void objc_exception_throw(id);
+
+// <CoreFoundation/CFString.h>
+typedef const struct __CFString *CFStringRef;
+typedef unsigned int CFStringEncoding;
+#define kCFStringEncodingUTF8 0x08000100
+CFStringRef CFStringCreateWithCString(const void *, const char *,
+                                      CFStringEncoding);
+
+// <Foundation/NSObjCRuntime.h>
+id NSClassFromString(CFStringRef);
+
 // === Main code ===
 
 int int_compar(const void *a, const void *b) { return *(int *)a - *(int *)b; }
@@ -778,6 +903,641 @@ int test_realpath() {
   return 0;
 }
 
+// Objective-C can't be compiled (see README.md), so these helpers make the
+// objc_msgSend calls a little less verbose.
+id make_string(const char *str) {
+  return (id)CFStringCreateWithCString(NULL, str, kCFStringEncodingUTF8);
+}
+id get_class(const char *name) {
+  return NSClassFromString((CFStringRef)make_string(name));
+}
+#define MSG(receiver, selector, ...)                                           \
+  objc_msgSend((receiver), sel_registerName(selector), ##__VA_ARGS__)
+
+int test_NSKeyedArchiver() {
+  id pool = MSG(get_class("NSAutoreleasePool"), "new");
+
+  id scores = MSG(get_class("NSMutableArray"), "new");
+  MSG(scores, "addObject:", MSG(get_class("NSNumber"), "numberWithInt:", 42));
+  MSG(scores, "addObject:", MSG(get_class("NSNumber"), "numberWithInt:", -7));
+  id name_key = make_string("name");
+  id scores_key = make_string("scores");
+  id root = MSG(get_class("NSMutableDictionary"), "new");
+  MSG(root, "setObject:forKey:", make_string("touchHLE"), name_key);
+  MSG(root, "setObject:forKey:", scores, scores_key);
+
+  id data = MSG(get_class("NSKeyedArchiver"), "archivedDataWithRootObject:",
+                root);
+  id copy = MSG(get_class("NSKeyedUnarchiver"), "unarchiveObjectWithData:",
+                data);
+
+  int res = 0;
+  id copy_name = MSG(copy, "objectForKey:", name_key);
+  id copy_scores = MSG(copy, "objectForKey:", scores_key);
+  if (copy == NULL || (size_t)MSG(copy, "count") != 2) {
+    res = -1;
+  } else if (copy_name == NULL ||
+             !(size_t)MSG(copy_name, "isEqualToString:",
+                          make_string("touchHLE"))) {
+    res = -2;
+  } else if (copy_scores == NULL ||
+             (size_t)MSG(copy_scores, "count") != 2) {
+    res = -3;
+  } else if ((int)(size_t)MSG(MSG(copy_scores, "objectAtIndex:", 0),
+                              "intValue") != 42 ||
+             (int)(size_t)MSG(MSG(copy_scores, "objectAtIndex:", 1),
+                              "intValue") != -7) {
+    res = -4;
+  } else if (MSG(copy, "objectForKey:", make_string("missing")) != NULL) {
+    res = -5;
+  }
+
+  MSG(root, "release");
+  MSG(scores, "release");
+  MSG(pool, "drain");
+  return res;
+}
+
+int test_NSPropertyListSerialization() {
+  id pool = MSG(get_class("NSAutoreleasePool"), "new");
+  id serialization = get_class("NSPropertyListSerialization");
+
+  id key = make_string("level");
+  id root = MSG(get_class("NSMutableDictionary"), "new");
+  id level = MSG(get_class("NSNumber"), "numberWithInt:", 3);
+  MSG(root, "setObject:forKey:", level, key);
+
+  int res = 0;
+  unsigned int format = 0;
+  id error = NULL;
+  // XML and binary formats
+  unsigned int formats[] = {100, 200};
+  for (int i = 0; i < 2 && res == 0; i++) {
+    id data = MSG(serialization, "dataWithPropertyList:format:options:error:",
+                  root, formats[i], 0, &error);
+    // NSPropertyListMutableContainers
+    id copy = MSG(serialization, "propertyListWithData:options:format:error:",
+                  data, 1, &format, &error);
+    if (data == NULL || copy == NULL || error != NULL) {
+      res = -1;
+    } else if (format != formats[i]) {
+      res = -2;
+    } else if ((int)(size_t)MSG(MSG(copy, "objectForKey:", key), "intValue") !=
+               3) {
+      res = -3;
+    } else if (!(size_t)MSG(copy, "isKindOfClass:",
+                            get_class("NSMutableDictionary"))) {
+      res = -4;
+    }
+  }
+
+  if (res == 0) {
+    char garbage[] = "not a plist";
+    id data = MSG(get_class("NSData"), "dataWithBytes:length:", garbage,
+                  sizeof(garbage));
+    id copy = MSG(serialization, "propertyListWithData:options:format:error:",
+                  data, 0, NULL, &error);
+    // NSPropertyListReadCorruptError
+    if (copy != NULL || error == NULL ||
+        (int)(size_t)MSG(error, "code") != 3840) {
+      res = -5;
+    }
+  }
+
+  MSG(root, "release");
+  MSG(pool, "drain");
+  return res;
+}
+
+int test_NSBundle_localizedString() {
+  id pool = MSG(get_class("NSAutoreleasePool"), "new");
+  id bundle = MSG(get_class("NSBundle"), "mainBundle");
+  SEL sel = sel_registerName("localizedStringForKey:value:table:");
+
+  int res = 0;
+  id str = objc_msgSend(bundle, sel, make_string("GREETING"), NULL, NULL);
+  if (!(size_t)MSG(str, "isEqualToString:", make_string("Hello, world!"))) {
+    res = -1;
+  }
+  str = objc_msgSend(bundle, sel, make_string("QUOTED"), NULL,
+                     make_string("Localizable"));
+  if (res == 0 &&
+      !(size_t)MSG(str, "isEqualToString:", make_string("\"quoted\""))) {
+    res = -2;
+  }
+  // Missing keys fall back to the value, or the key if there is no value.
+  str = objc_msgSend(bundle, sel, make_string("MISSING"),
+                     make_string("fallback"), NULL);
+  if (res == 0 &&
+      !(size_t)MSG(str, "isEqualToString:", make_string("fallback"))) {
+    res = -3;
+  }
+  str = objc_msgSend(bundle, sel, make_string("GREETING"), NULL,
+                     make_string("MissingTable"));
+  if (res == 0 &&
+      !(size_t)MSG(str, "isEqualToString:", make_string("GREETING"))) {
+    res = -4;
+  }
+
+  MSG(pool, "drain");
+  return res;
+}
+
+int test_dlfcn() {
+  // Libraries with host implementations.
+  void *handle = dlopen("/usr/lib/libSystem.B.dylib", RTLD_LAZY);
+  if (handle == NULL) {
+    return -1;
+  }
+  size_t (*strlen_ptr)(const char *) = dlsym(handle, "strlen");
+  if (strlen_ptr == NULL || strlen_ptr("hello") != 5) {
+    return -2;
+  }
+  if (dlsym(RTLD_DEFAULT, "strlen") == NULL) {
+    return -3;
+  }
+  if (dlclose(handle) != 0) {
+    return -4;
+  }
+
+  // Errors return NULL rather than crashing, and are reported once.
+  if (dlerror() != NULL) {
+    return -5;
+  }
+  if (dlopen("/usr/lib/libDoesNotExist.dylib", RTLD_LAZY) != NULL) {
+    return -6;
+  }
+  if (dlerror() == NULL || dlerror() != NULL) {
+    return -7;
+  }
+  if (dlsym(RTLD_DEFAULT, "this_function_does_not_exist") != NULL) {
+    return -8;
+  }
+  if (dlerror() == NULL) {
+    return -9;
+  }
+  return 0;
+}
+
+#define SQLITE_TEST_PATH                                                       \
+  "/var/mobile/Applications/00000000-0000-0000-0000-000000000000/Documents/"   \
+  "test.sqlite"
+
+int sqlite_test_callback(void *arg, int count, char **values, char **names) {
+  int *sum = arg;
+  if (count != 1 || strcmp(names[0], "number") != 0) {
+    return 1;
+  }
+  *sum += (int)strtoul(values[0], NULL, 10);
+  return 0;
+}
+
+int sqlite_test_insert(sqlite3 *db) {
+  sqlite3_stmt *stmt;
+  if (sqlite3_prepare_v2(db, "INSERT INTO test VALUES (?, ?, ?);", -1, &stmt,
+                         NULL) != SQLITE_OK) {
+    return -1;
+  }
+  int i;
+  for (i = 1; i <= 3; i++) {
+    char name[2] = {'a' + i - 1, '\0'};
+    if (sqlite3_bind_int(stmt, 1, i) != SQLITE_OK ||
+        sqlite3_bind_text(stmt, 2, name, -1, SQLITE_TRANSIENT) != SQLITE_OK ||
+        sqlite3_bind_blob(stmt, 3, "\x01\x00\x02", 3, SQLITE_TRANSIENT) !=
+            SQLITE_OK ||
+        sqlite3_step(stmt) != SQLITE_DONE || sqlite3_reset(stmt) != SQLITE_OK) {
+      sqlite3_finalize(stmt);
+      return -2;
+    }
+  }
+  // Negative lengths are only meaningful for text.
+  int res = sqlite3_bind_blob(stmt, 3, "", -1, SQLITE_TRANSIENT);
+  sqlite3_finalize(stmt);
+  return res == SQLITE_MISUSE ? 0 : -3;
+}
+
+int sqlite_test_select(sqlite3 *db) {
+  sqlite3_stmt *stmt;
+  if (sqlite3_prepare_v2(db, "SELECT name, data FROM test WHERE number = ?;",
+                         -1, &stmt, NULL) != SQLITE_OK) {
+    return -1;
+  }
+  int res = 0;
+  if (sqlite3_bind_int(stmt, 1, 2) != SQLITE_OK ||
+      sqlite3_step(stmt) != SQLITE_ROW ||
+      strcmp((const char *)sqlite3_column_text(stmt, 0), "b") != 0 ||
+      sqlite3_column_bytes(stmt, 1) != 3 ||
+      memcmp(sqlite3_column_blob(stmt, 1), "\x01\x00\x02", 3) != 0 ||
+      sqlite3_step(stmt) != SQLITE_DONE) {
+    res = -2;
+  }
+  sqlite3_finalize(stmt);
+  return res;
+}
+
+int test_sqlite() {
+  sqlite3 *db;
+  if (sqlite3_open(SQLITE_TEST_PATH, &db) != SQLITE_OK) {
+    return -1;
+  }
+  // The table might be left over from an earlier run that failed part-way.
+  if (sqlite3_exec(db,
+                   "DROP TABLE IF EXISTS test;"
+                   "CREATE TABLE test (number INTEGER, name TEXT, data BLOB);",
+                   NULL, NULL, NULL) != SQLITE_OK) {
+    sqlite3_close(db);
+    return -2;
+  }
+  int res = sqlite_test_insert(db);
+  if (res != 0) {
+    sqlite3_close(db);
+    return -10 + res;
+  }
+  res = sqlite_test_select(db);
+  if (res != 0) {
+    sqlite3_close(db);
+    return -20 + res;
+  }
+  int sum = 0;
+  if (sqlite3_exec(db, "SELECT number FROM test;", sqlite_test_callback, &sum,
+                   NULL) != SQLITE_OK ||
+      sum != 6) {
+    sqlite3_close(db);
+    return -3;
+  }
+  if (sqlite3_close(db) != SQLITE_OK) {
+    return -4;
+  }
+
+  // The data was written to the file and can be read back.
+  if (sqlite3_open(SQLITE_TEST_PATH, &db) != SQLITE_OK) {
+    return -5;
+  }
+  res = sqlite_test_select(db);
+  sqlite3_close(db);
+  remove(SQLITE_TEST_PATH);
+  if (res != 0) {
+    return -30 + res;
+  }
+
+  // NULL handles are allowed by the API.
+  if (sqlite3_finalize(NULL) != SQLITE_OK || sqlite3_errmsg(NULL) == NULL ||
+      sqlite3_step(NULL) != SQLITE_MISUSE) {
+    return -6;
+  }
+  return 0;
+}
+
+id runtime_test_imp_a(id self, SEL _cmd) { return (id)1; }
+id runtime_test_imp_b(id self, SEL _cmd) { return (id)2; }
+
+int test_objc_runtime() {
+  Class ns_object = objc_getClass("NSObject");
+  Class ns_array = objc_getClass("NSArray");
+  if (ns_object == NULL || ns_array == NULL ||
+      objc_getClass("NoSuchClass") != NULL) {
+    return -1;
+  }
+  if (strcmp(class_getName(ns_array), "NSArray") != 0 ||
+      class_getSuperclass(ns_array) != ns_object ||
+      class_getSuperclass(ns_object) != NULL) {
+    return -2;
+  }
+  SEL count = sel_registerName("count");
+  if (!class_respondsToSelector(ns_array, count) ||
+      class_respondsToSelector(ns_object, count)) {
+    return -3;
+  }
+
+  // Host methods can be called through their IMP.
+  id array = MSG(get_class("NSMutableArray"), "new");
+  MSG(array, "addObject:", make_string("foo"));
+  Method count_method = class_getInstanceMethod(object_getClass(array), count);
+  IMP count_imp = method_getImplementation(count_method);
+  if (count_imp == NULL || (size_t)count_imp(array, count) != 1) {
+    return -4;
+  }
+
+  // Guest methods can be added and swizzled.
+  SEL sel_a = sel_registerName("touchHLE_runtimeTestA");
+  SEL sel_b = sel_registerName("touchHLE_runtimeTestB");
+  if (!class_addMethod(ns_object, sel_a, (IMP)runtime_test_imp_a, "@@:") ||
+      !class_addMethod(ns_object, sel_b, (IMP)runtime_test_imp_b, "@@:") ||
+      class_addMethod(ns_object, sel_a, (IMP)runtime_test_imp_b, "@@:")) {
+    return -5;
+  }
+  method_exchangeImplementations(class_getInstanceMethod(ns_object, sel_a),
+                                 class_getInstanceMethod(ns_object, sel_b));
+  if (MSG(array, "touchHLE_runtimeTestA") != (id)2 ||
+      MSG(array, "touchHLE_runtimeTestB") != (id)1) {
+    return -6;
+  }
+  unsigned int method_count = 0;
+  Method *methods = class_copyMethodList(ns_object, &method_count);
+  if (methods == NULL || method_count == 0 || methods[method_count] != NULL) {
+    return -7;
+  }
+  free(methods);
+
+  // Associated objects are retained until they are replaced.
+  static char key;
+  id value = MSG(get_class("NSMutableArray"), "new");
+  MSG(value, "addObject:", make_string("bar"));
+  objc_setAssociatedObject(array, &key, value,
+                           OBJC_ASSOCIATION_RETAIN_NONATOMIC);
+  MSG(value, "release");
+  if (objc_getAssociatedObject(array, &key) != value ||
+      (size_t)MSG(value, "count") != 1) {
+    return -8;
+  }
+  objc_setAssociatedObject(array, &key, NULL,
+                           OBJC_ASSOCIATION_RETAIN_NONATOMIC);
+  if (objc_getAssociatedObject(array, &key) != NULL) {
+    return -9;
+  }
+
+  MSG(array, "release");
+  return 0;
+}
+
+id forwarding_test_imp(id self, SEL _cmd) { return (id)3; }
+int forwarding_test_resolve(Class self, SEL _cmd, SEL sel) {
+  if (sel != sel_registerName("touchHLE_forwardingTest")) {
+    return 0;
+  }
+  return class_addMethod(self, sel, (IMP)forwarding_test_imp, "@@:");
+}
+
+int test_message_forwarding() {
+  id array = MSG(get_class("NSMutableArray"), "new");
+  MSG(array, "addObject:", make_string("foo"));
+  MSG(array, "addObject:", make_string("bar"));
+
+  // Invocations can be constructed from a method signature and invoked.
+  SEL object_at_index = sel_registerName("objectAtIndex:");
+  id signature = MSG(array, "methodSignatureForSelector:", object_at_index);
+  if (signature == NULL || (size_t)MSG(signature, "numberOfArguments") != 3 ||
+      strcmp((const char *)MSG(signature, "methodReturnType"), "@") != 0) {
+    return -1;
+  }
+  id invocation = MSG(get_class("NSInvocation"),
+                      "invocationWithMethodSignature:", signature);
+  MSG(invocation, "setTarget:", array);
+  MSG(invocation, "setSelector:", object_at_index);
+  size_t index = 1;
+  MSG(invocation, "setArgument:atIndex:", &index, 2);
+  MSG(invocation, "invoke");
+  id result = NULL;
+  MSG(invocation, "getReturnValue:", &result);
+  if (result != MSG(array, "objectAtIndex:", 1)) {
+    return -2;
+  }
+
+  // Methods can be added on demand by +resolveInstanceMethod:.
+  Class class = object_getClass(array);
+  if (!class_addMethod(object_getClass(class),
+                       sel_registerName("resolveInstanceMethod:"),
+                       (IMP)forwarding_test_resolve, "c@::")) {
+    return -3;
+  }
+  if (MSG(array, "touchHLE_forwardingTest") != (id)3 ||
+      !class_respondsToSelector(class,
+                                sel_registerName("touchHLE_forwardingTest"))) {
+    return -4;
+  }
+
+  MSG(array, "release");
+  return 0;
+}
+
+char mprotect_test_pages[0x2000] __attribute__((aligned(0x1000)));
+sigjmp_buf mprotect_test_buf;
+
+void mprotect_test_handler(int signum) {
+  siglongjmp(mprotect_test_buf, signum);
+}
+
+// Write to `p` and return the signal number if the write faulted, or 0.
+int mprotect_test_write(volatile char *p, char value) {
+  struct sigaction action = {0}, old_action;
+  action.sa_handler = mprotect_test_handler;
+  sigaction(SIGBUS, &action, &old_action);
+  int signum = sigsetjmp(mprotect_test_buf, 1);
+  if (signum == 0) {
+    *p = value;
+  }
+  sigaction(SIGBUS, &old_action, NULL);
+  return signum;
+}
+
+int test_mprotect() {
+  char *page = mprotect_test_pages;
+  page[0] = 1;
+  if (mprotect(page, 0x1000, PROT_READ) != 0 || page[0] != 1) {
+    return -1;
+  }
+  // Writes to a read-only page are caught and have no effect.
+  if (mprotect_test_write(page, 2) != SIGBUS || page[0] != 1) {
+    return -2;
+  }
+  // Neighbouring pages aren't affected.
+  if (mprotect_test_write(page + 0x1000, 2) != 0 || page[0x1000] != 2) {
+    return -3;
+  }
+  if (mprotect(page, 0x1000, PROT_READ | PROT_WRITE) != 0) {
+    return -4;
+  }
+  if (mprotect_test_write(page, 3) != 0 || page[0] != 3) {
+    return -5;
+  }
+  // The address must be page-aligned.
+  if (mprotect(page + 1, 0x1000, PROT_READ) != -1) {
+    return -6;
+  }
+
+  // Freed memory is writable again when it's reused.
+  char *buffer = malloc(0x3000);
+  char *aligned = (char *)(((size_t)buffer + 0xfff) & ~(size_t)0xfff);
+  if (mprotect(aligned, 0x1000, PROT_READ) != 0) {
+    return -7;
+  }
+  free(buffer);
+  buffer = malloc(0x3000);
+  int i;
+  for (i = 0; i < 0x3000; i += 0x100) {
+    if (mprotect_test_write(buffer + i, 1) != 0) {
+      return -8;
+    }
+  }
+  free(buffer);
+  return 0;
+}
+
+int test_mmap() {
+  // Anonymous mappings are zeroed and can be partially unmapped.
+  char *anon = mmap(NULL, 0x3000, PROT_READ | PROT_WRITE,
+                    MAP_ANON | MAP_PRIVATE, -1, 0);
+  if (anon == MAP_FAILED || anon[0] != 0 || anon[0x2fff] != 0) {
+    return -1;
+  }
+  anon[0] = 1;
+  anon[0x2000] = 2;
+  if (munmap(anon + 0x1000, 0x1000) != 0 || anon[0] != 1 ||
+      anon[0x2000] != 2) {
+    return -2;
+  }
+  if (munmap(anon, 0x3000) != 0) {
+    return -3;
+  }
+
+  // Errors are reported via errno.
+  if (mmap(NULL, 0, PROT_READ, MAP_ANON | MAP_PRIVATE, -1, 0) != MAP_FAILED ||
+      errno != 22 /* EINVAL */) {
+    return -4;
+  }
+
+  // File mappings have the file's contents and don't move the file position.
+  int fd = open("/var/mobile/Applications/00000000-0000-0000-0000-000000000000/"
+                "TestApp.app/TestApp",
+                O_RDONLY);
+  if (fd == -1) {
+    return -5;
+  }
+  char *file = mmap(NULL, 0x1000, PROT_READ, MAP_PRIVATE, fd, 0);
+  char buf[32];
+  if (file == MAP_FAILED || read(fd, buf, sizeof buf) != sizeof buf ||
+      memcmp(file, buf, sizeof buf) != 0) {
+    close(fd);
+    return -6;
+  }
+  // Read-only files can't have writable shared mappings.
+  if (mmap(NULL, 0x1000, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0) !=
+      MAP_FAILED) {
+    close(fd);
+    return -7;
+  }
+  close(fd);
+  // The mapping outlives the file descriptor.
+  if (file[0] != buf[0] || munmap(file, 0x1000) != 0) {
+    return -8;
+  }
+  return 0;
+}
+
+id method_cache_test_imp_a(id self, SEL _cmd) { return (id)4; }
+id method_cache_test_imp_b(id self, SEL _cmd) { return (id)5; }
+
+int test_objc_method_cache() {
+  Class ns_object = objc_getClass("NSObject");
+  id array = MSG(get_class("NSMutableArray"), "new");
+  MSG(array, "addObject:", make_string("foo"));
+
+  // Selectors that weren't registered still find the right method.
+  if ((size_t)objc_msgSend(array, (SEL) "count") != 1) {
+    return -1;
+  }
+
+  // Adding a method to a subclass overrides a cached superclass method.
+  SEL sel = sel_registerName("touchHLE_methodCacheTest");
+  if (!class_addMethod(ns_object, sel, (IMP)method_cache_test_imp_a, "@@:") ||
+      objc_msgSend(array, sel) != (id)4) {
+    return -2;
+  }
+  if (!class_addMethod(object_getClass(array), sel,
+                       (IMP)method_cache_test_imp_b, "@@:") ||
+      objc_msgSend(array, sel) != (id)5) {
+    return -3;
+  }
+  // Swizzling replaces cached methods.
+  method_exchangeImplementations(
+      class_getInstanceMethod(object_getClass(array), sel),
+      class_getInstanceMethod(ns_object, sel));
+  if (objc_msgSend(array, sel) != (id)4) {
+    return -4;
+  }
+
+  MSG(array, "release");
+  return 0;
+}
+
+jmp_buf setjmp_test_buf;
+
+void setjmp_test_jump(int value) { longjmp(setjmp_test_buf, value); }
+int setjmp_test_compar(const void *a, const void *b) {
+  longjmp(setjmp_test_buf, 7);
+}
+
+int test_setjmp() {
+  volatile int count = 0;
+  int value = setjmp(setjmp_test_buf);
+  count++;
+  if (value == 0) {
+    setjmp_test_jump(42);
+    return -1;
+  }
+  if (value != 42 || count != 2) {
+    return -2;
+  }
+
+  // longjmp() with 0 makes setjmp() return 1.
+  value = _setjmp(setjmp_test_buf);
+  if (value == 0) {
+    setjmp_test_jump(0);
+    return -3;
+  } else if (value != 1) {
+    return -4;
+  }
+
+  // Jumping out of a callback called by a host function (qsort).
+  value = setjmp(setjmp_test_buf);
+  if (value == 0) {
+    int array[2] = {2, 1};
+    qsort(array, 2, sizeof(int), setjmp_test_compar);
+    return -5;
+  } else if (value != 7) {
+    return -6;
+  }
+  return 0;
+}
+
+// Objective-C can't be compiled, so this does what the compiler would do for
+// @try/@catch.
+int test_objc_exceptions() {
+  // Raised by host code, so host frames must be unwound.
+  struct objc_exception_data data;
+  objc_exception_try_enter(&data);
+  if (_setjmp(data.buf) == 0) {
+    MSG(get_class("NSException"), "raise:format:",
+        make_string("TestException"), make_string("reason %d"), 1);
+    objc_exception_try_exit(&data);
+    return -1;
+  }
+  id exception = objc_exception_extract(&data);
+  if (exception == NULL ||
+      !objc_exception_match(get_class("NSException"), exception) ||
+      !(size_t)MSG(MSG(exception, "name"), "isEqualToString:",
+                   make_string("TestException"))) {
+    return -2;
+  }
+
+  // Nested handlers, with a rethrow from the inner @catch.
+  struct objc_exception_data outer, inner;
+  objc_exception_try_enter(&outer);
+  if (_setjmp(outer.buf) == 0) {
+    objc_exception_try_enter(&inner);
+    if (_setjmp(inner.buf) == 0) {
+      objc_exception_throw(exception);
+      return -3;
+    }
+    objc_exception_throw(objc_exception_extract(&inner));
+    return -4;
+  }
+  if (objc_exception_extract(&outer) != exception) {
+    return -5;
+  }
+  return 0;
+}
+
 #define FUNC_DEF(func)                                                         \
   { &func, #func }
 struct {
@@ -793,7 +1553,18 @@ struct {
     FUNC_DEF(test_strlcpy),  FUNC_DEF(test_setlocale),
     FUNC_DEF(test_strtoul),  FUNC_DEF(test_dirent),
     FUNC_DEF(test_strchr),   FUNC_DEF(test_swprintf),
-    FUNC_DEF(test_realpath),
+    FUNC_DEF(test_realpath), FUNC_DEF(test_NSKeyedArchiver),
+    FUNC_DEF(test_NSPropertyListSerialization),
+    FUNC_DEF(test_NSBundle_localizedString),
+    FUNC_DEF(test_dlfcn),
+    FUNC_DEF(test_objc_runtime),
+    FUNC_DEF(test_message_forwarding),
+    FUNC_DEF(test_objc_method_cache),
+    FUNC_DEF(test_setjmp),
+    FUNC_DEF(test_objc_exceptions),
+    FUNC_DEF(test_mprotect),
+    FUNC_DEF(test_mmap),
+    FUNC_DEF(test_sqlite),
 };
 
 // Because no libc is linked into this executable, there is no libc entry point
@@ -801,7 +1572,52 @@ struct {
 // as the entry point. (It has to be _main because a C compiler will throw
 // away stuff not called by main().) Since this is the true entry point, there's
 // no argc or argv and we must call exit() ourselves.
+#ifdef BENCHMARKS
+// Benchmarks are only built and run by the ignored `benchmark_test_app` test
+// in integration.rs, since timings vary too much to be pass/fail tests.
+
+long benchmark_elapsed_usecs(struct timeval *start) {
+  struct timeval end;
+  gettimeofday(&end, NULL);
+  return (end.tv_sec - start->tv_sec) * 1000000 +
+         (end.tv_usec - start->tv_usec);
+}
+
+// Measure how fast messages to host and guest methods can be sent, which
+// mostly depends on the objc_msgSend method cache.
+void benchmark_objc_msgSend() {
+  id array = MSG(get_class("NSMutableArray"), "new");
+  SEL count = sel_registerName("count");
+  SEL sel = sel_registerName("touchHLE_methodCacheBenchmark");
+  class_addMethod(objc_getClass("NSObject"), sel,
+                  (IMP)method_cache_test_imp_a, "@@:");
+
+  struct timeval start;
+  int i;
+  gettimeofday(&start, NULL);
+  for (i = 0; i < 100000; i++) {
+    objc_msgSend(array, count);
+  }
+  printf("objc_msgSend to host method: 100000 messages in %ld us\n",
+         benchmark_elapsed_usecs(&start));
+
+  gettimeofday(&start, NULL);
+  for (i = 0; i < 100000; i++) {
+    objc_msgSend(array, sel);
+  }
+  printf("objc_msgSend to guest method: 100000 messages in %ld us\n",
+         benchmark_elapsed_usecs(&start));
+
+  MSG(array, "release");
+}
+#endif
+
 int main() {
+#ifdef BENCHMARKS
+  benchmark_objc_msgSend();
+  exit(0);
+#endif
+
   int tests_run = 0;
   int tests_passed = 0;
 
@@ -26,7 +26,11 @@ fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
         .position(|window| window == needle)
 }
 
-fn build_test_app(tests_dir: &Path, test_app_path: &Path) -> Result<(), Box<dyn Error>> {
+fn build_test_app(
+    tests_dir: &Path,
+    test_app_path: &Path,
+    defines: &[&str],
+) -> Result<(), Box<dyn Error>> {
     let clang_path = tests_dir
         .join("llvm")
         .join("bin")
@@ -69,6 +73,7 @@ fn build_test_app(tests_dir: &Path, test_app_path: &Path) -> Result<(), Box<dyn
         // references, falling back to dynamic linking instead. This is needed
         // because we have no system libraries/frameworks for it to link to.
         .arg("-Wl,-e,_main,-undefined,dynamic_lookup")
+        .args(defines.iter().map(|define| format!("-D{}", define)))
         // Input
         .arg(tests_dir.join("TestApp_source").join("main.c"))
         // Write the output to the bundle.
@@ -93,8 +98,58 @@ fn run_test_app() -> Result<(), Box<dyn Error>> {
 
     let test_app_path = tests_dir.join("TestApp.app");
 
-    build_test_app(&tests_dir, &test_app_path)?;
+    build_test_app(&tests_dir, &test_app_path, &[])?;
 
+    let output = run_touchhle(&test_app_path);
+
+    assert!(output.status.success());
+    // sanity check: check that emulation actually happened
+    assert_ne!(
+        find_subsequence(output.stderr.as_slice(), b"CPU emulation begins now."),
+        None
+    );
+
+    Ok(())
+}
+
+/// Build TestApp with its benchmarks instead of its tests and run it. This is
+/// ignored by default because it's slow and only prints the timings. Run it
+/// with `cargo test --release --test integration -- --ignored`.
+#[test]
+#[ignore]
+fn benchmark_test_app() -> Result<(), Box<dyn Error>> {
+    let tests_dir = current_dir()?.join("tests");
+
+    // A separate copy of the bundle, so this can run at the same time as
+    // run_test_app.
+    let test_app_path = target_dir().join("benchmark").join("TestApp.app");
+    copy_dir(&tests_dir.join("TestApp.app"), &test_app_path)?;
+
+    build_test_app(&tests_dir, &test_app_path, &["BENCHMARKS"])?;
+
+    let output = run_touchhle(&test_app_path);
+
+    assert!(output.status.success());
+
+    Ok(())
+}
+
+/// Copy a directory's contents recursively, overwriting existing files.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let to = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to)?;
+        } else {
+            std::fs::copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+fn run_touchhle(test_app_path: &Path) -> std::process::Output {
     let binary_name = "touchHLE";
     let binary_path = target_dir().join(format!("{}{}", binary_name, env::consts::EXE_SUFFIX));
 
@@ -111,12 +166,5 @@ fn run_test_app() -> Result<(), Box<dyn Error>> {
     std::io::stdout().write_all(&output.stdout).unwrap();
     std::io::stderr().write_all(&output.stderr).unwrap();
 
-    assert!(output.status.success());
-    // sanity check: check that emulation actually happened
-    assert_ne!(
-        find_subsequence(output.stderr.as_slice(), b"CPU emulation begins now."),
-        None
-    );
-
-    Ok(())
+    output
 }